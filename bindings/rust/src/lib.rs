@@ -82,6 +82,7 @@ pub type Result<T> = std::result::Result<T, Error>;
 pub struct Builder {
     path: String,
     enable_mvcc: bool,
+    enable_mvcc_serializable: bool,
     vfs: Option<String>,
 }
 
@@ -91,6 +92,7 @@ impl Builder {
         Self {
             path: path.to_string(),
             enable_mvcc: false,
+            enable_mvcc_serializable: false,
             vfs: None,
         }
     }
@@ -100,6 +102,13 @@ impl Builder {
         self
     }
 
+    /// Run MVCC transactions under serializable snapshot isolation instead of snapshot
+    /// isolation. Only takes effect together with [Builder::with_mvcc].
+    pub fn with_mvcc_serializable(mut self, serializable: bool) -> Self {
+        self.enable_mvcc_serializable = serializable;
+        self
+    }
+
     pub fn with_io(mut self, vfs: String) -> Self {
         self.vfs = Some(vfs);
         self
@@ -109,7 +118,15 @@ impl Builder {
     #[allow(unused_variables, clippy::arc_with_non_send_sync)]
     pub async fn build(self) -> Result<Database> {
         let io = self.get_io()?;
-        let db = turso_core::Database::open_file(io, self.path.as_str(), self.enable_mvcc, true)?;
+        let opts = turso_core::DatabaseOpts::new()
+            .with_mvcc(self.enable_mvcc)
+            .with_mvcc_serializable(self.enable_mvcc_serializable);
+        let db = turso_core::Database::open_file_with_flags(
+            io,
+            self.path.as_str(),
+            turso_core::OpenFlags::default(),
+            opts,
+        )?;
         Ok(Database { inner: db })
     }
 
@@ -339,3 +339,61 @@ async fn test_index() {
     assert!(row.get::<String>(1).unwrap() == "b@d.e");
     assert!(rows.next().await.unwrap().is_none());
 }
+
+#[tokio::test]
+async fn test_mvcc_serializable_write_skew() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("test.db");
+    let db = Builder::new_local(path.to_str().unwrap())
+        .with_mvcc(true)
+        .with_mvcc_serializable(true)
+        .build()
+        .await
+        .unwrap();
+    let conn1 = db.connect().unwrap();
+    let conn2 = db.connect().unwrap();
+    conn1
+        .execute_batch(
+            "CREATE TABLE doctors (id INTEGER PRIMARY KEY, on_call INTEGER);
+             INSERT INTO doctors VALUES (1, 1), (2, 1);",
+        )
+        .await
+        .unwrap();
+
+    // Each transaction checks that the other doctor is on call before going off call.
+    conn1.execute("BEGIN CONCURRENT", ()).await.unwrap();
+    conn2.execute("BEGIN CONCURRENT", ()).await.unwrap();
+    for conn in [&conn1, &conn2] {
+        let mut rows = conn
+            .query("SELECT on_call FROM doctors WHERE id = 1", ())
+            .await
+            .unwrap();
+        assert_eq!(
+            rows.next().await.unwrap().unwrap().get::<i64>(0).unwrap(),
+            1
+        );
+        let mut rows = conn
+            .query("SELECT on_call FROM doctors WHERE id = 2", ())
+            .await
+            .unwrap();
+        assert_eq!(
+            rows.next().await.unwrap().unwrap().get::<i64>(0).unwrap(),
+            1
+        );
+    }
+    conn1
+        .execute("UPDATE doctors SET on_call = 0 WHERE id = 1", ())
+        .await
+        .unwrap();
+    conn2
+        .execute("UPDATE doctors SET on_call = 0 WHERE id = 2", ())
+        .await
+        .unwrap();
+
+    conn1.execute("COMMIT", ()).await.unwrap();
+    let result = conn2.execute("COMMIT", ()).await;
+    assert!(
+        matches!(&result, Err(Error::SqlExecutionFailure(msg)) if msg.contains("Serialization failure")),
+        "{result:?}"
+    );
+}
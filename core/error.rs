@@ -45,6 +45,10 @@ pub enum LimboError {
     InvalidFormatter(String),
     #[error("Runtime error: {0}")]
     Constraint(String),
+    /// RAISE(ABORT) or RAISE(FAIL) ended the statement, whose changes were already dealt with,
+    /// so the transaction is left open.
+    #[error("Runtime error: {0}")]
+    StatementAborted(String),
     #[error("Extension error: {0}")]
     ExtensionError(String),
     #[error("Runtime error: integer overflow")]
@@ -69,6 +73,8 @@ pub enum LimboError {
     TxTerminated,
     #[error("Write-write conflict")]
     WriteWriteConflict,
+    #[error("Serialization failure: transaction conflicts with a concurrent transaction")]
+    SerializationFailure,
     #[error("No such transaction ID: {0}")]
     NoSuchTransactionID(String),
     #[error("Null value")]
@@ -164,3 +170,4 @@ impl From<turso_ext::ResultCode> for LimboError {
 pub const SQLITE_CONSTRAINT: usize = 19;
 pub const SQLITE_CONSTRAINT_PRIMARYKEY: usize = SQLITE_CONSTRAINT | (6 << 8);
 pub const SQLITE_CONSTRAINT_NOTNULL: usize = SQLITE_CONSTRAINT | (5 << 8);
+pub const SQLITE_CONSTRAINT_FOREIGNKEY: usize = SQLITE_CONSTRAINT | (3 << 8);
@@ -272,6 +272,84 @@ impl AggFunc {
     }
 }
 
+/// Built-in window functions, i.e. functions that can only be used with an OVER clause.
+/// Aggregate functions used with an OVER clause are represented by [AggFunc].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WindowFunc {
+    RowNumber,
+    Rank,
+    DenseRank,
+    PercentRank,
+    CumeDist,
+    Ntile,
+    Lag,
+    Lead,
+    FirstValue,
+    LastValue,
+    NthValue,
+}
+
+impl WindowFunc {
+    /// Resolves a built-in window function by name.
+    /// Returns `Ok(None)` if `name` is not a built-in window function.
+    pub fn resolve_function(name: &str, arg_count: usize) -> Result<Option<Self>, LimboError> {
+        let func = match name.to_lowercase().as_str() {
+            "row_number" => Self::RowNumber,
+            "rank" => Self::Rank,
+            "dense_rank" => Self::DenseRank,
+            "percent_rank" => Self::PercentRank,
+            "cume_dist" => Self::CumeDist,
+            "ntile" => Self::Ntile,
+            "lag" => Self::Lag,
+            "lead" => Self::Lead,
+            "first_value" => Self::FirstValue,
+            "last_value" => Self::LastValue,
+            "nth_value" => Self::NthValue,
+            _ => return Ok(None),
+        };
+        if !func.accepts_arg_count(arg_count) {
+            crate::bail_parse_error!("wrong number of arguments to function {}()", func);
+        }
+        Ok(Some(func))
+    }
+
+    fn accepts_arg_count(&self, arg_count: usize) -> bool {
+        match self {
+            Self::RowNumber | Self::Rank | Self::DenseRank | Self::PercentRank | Self::CumeDist => {
+                arg_count == 0
+            }
+            Self::Ntile | Self::FirstValue | Self::LastValue => arg_count == 1,
+            Self::Lag | Self::Lead => (1..=3).contains(&arg_count),
+            Self::NthValue => arg_count == 2,
+        }
+    }
+
+    /// Whether the value of the function depends on the frame of the current row.
+    /// Ranking and offset functions always look at the whole partition.
+    pub fn uses_frame(&self) -> bool {
+        matches!(self, Self::FirstValue | Self::LastValue | Self::NthValue)
+    }
+}
+
+impl Display for WindowFunc {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let str = match self {
+            Self::RowNumber => "row_number",
+            Self::Rank => "rank",
+            Self::DenseRank => "dense_rank",
+            Self::PercentRank => "percent_rank",
+            Self::CumeDist => "cume_dist",
+            Self::Ntile => "ntile",
+            Self::Lag => "lag",
+            Self::Lead => "lead",
+            Self::FirstValue => "first_value",
+            Self::LastValue => "last_value",
+            Self::NthValue => "nth_value",
+        };
+        write!(f, "{str}")
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum ScalarFunc {
     Cast,
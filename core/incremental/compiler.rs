@@ -1181,6 +1181,7 @@ mod tests {
                         unique: false,
                         collation: None,
                         hidden: false,
+                        generated: None,
                     },
                     SchemaColumn {
                         name: Some("name".to_string()),
@@ -1193,6 +1194,7 @@ mod tests {
                         unique: false,
                         collation: None,
                         hidden: false,
+                        generated: None,
                     },
                     SchemaColumn {
                         name: Some("age".to_string()),
@@ -1205,11 +1207,13 @@ mod tests {
                         unique: false,
                         collation: None,
                         hidden: false,
+                        generated: None,
                     },
                 ],
                 has_rowid: true,
                 is_strict: false,
                 unique_sets: vec![],
+                foreign_keys: vec![],
             };
             schema.add_btree_table(Arc::new(users_table));
             let sales_table = BTreeTable {
@@ -1228,6 +1232,7 @@ mod tests {
                         unique: false,
                         collation: None,
                         hidden: false,
+                        generated: None,
                     },
                     SchemaColumn {
                         name: Some("amount".to_string()),
@@ -1240,11 +1245,13 @@ mod tests {
                         unique: false,
                         collation: None,
                         hidden: false,
+                        generated: None,
                     },
                 ],
                 has_rowid: true,
                 is_strict: false,
                 unique_sets: vec![],
+                foreign_keys: vec![],
             };
             schema.add_btree_table(Arc::new(sales_table));
 
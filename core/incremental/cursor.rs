@@ -315,6 +315,7 @@ mod tests {
             OpenFlags::default(),
             crate::DatabaseOpts {
                 enable_mvcc: false,
+                enable_mvcc_serializable: false,
                 enable_indexes: false,
                 enable_views: true,
                 enable_strict: false,
@@ -147,6 +147,27 @@ impl AllViewsTxState {
     pub fn get_view_names(&self) -> Vec<String> {
         self.states.borrow().keys().cloned().collect()
     }
+
+    /// Copy the deltas of every view, so they can be restored when rolling back to a savepoint
+    pub fn snapshot(&self) -> HashMap<String, HashMap<String, Delta>> {
+        self.states
+            .borrow()
+            .iter()
+            .map(|(view_name, state)| (view_name.clone(), state.get_table_deltas()))
+            .collect()
+    }
+
+    /// Replace all transaction states with the deltas captured by [AllViewsTxState::snapshot]
+    pub fn restore(&self, snapshot: &HashMap<String, HashMap<String, Delta>>) {
+        let mut states = self.states.borrow_mut();
+        states.clear();
+        for (view_name, table_deltas) in snapshot {
+            let state = ViewTransactionState {
+                table_deltas: RefCell::new(table_deltas.clone()),
+            };
+            states.insert(view_name.clone(), Rc::new(state));
+        }
+    }
 }
 
 /// Incremental view that maintains its state through a DBSP circuit
@@ -865,6 +886,7 @@ mod tests {
                     unique: false,
                     collation: None,
                     hidden: false,
+                    generated: None,
                 },
                 SchemaColumn {
                     name: Some("name".to_string()),
@@ -877,11 +899,13 @@ mod tests {
                     unique: false,
                     collation: None,
                     hidden: false,
+                    generated: None,
                 },
             ],
             has_rowid: true,
             is_strict: false,
             unique_sets: vec![],
+            foreign_keys: vec![],
         };
 
         // Create orders table
@@ -901,6 +925,7 @@ mod tests {
                     unique: false,
                     collation: None,
                     hidden: false,
+                    generated: None,
                 },
                 SchemaColumn {
                     name: Some("customer_id".to_string()),
@@ -913,6 +938,7 @@ mod tests {
                     unique: false,
                     collation: None,
                     hidden: false,
+                    generated: None,
                 },
                 SchemaColumn {
                     name: Some("total".to_string()),
@@ -925,11 +951,13 @@ mod tests {
                     unique: false,
                     collation: None,
                     hidden: false,
+                    generated: None,
                 },
             ],
             has_rowid: true,
             is_strict: false,
             unique_sets: vec![],
+            foreign_keys: vec![],
         };
 
         // Create products table
@@ -949,6 +977,7 @@ mod tests {
                     unique: false,
                     collation: None,
                     hidden: false,
+                    generated: None,
                 },
                 SchemaColumn {
                     name: Some("name".to_string()),
@@ -961,11 +990,13 @@ mod tests {
                     unique: false,
                     collation: None,
                     hidden: false,
+                    generated: None,
                 },
             ],
             has_rowid: true,
             is_strict: false,
             unique_sets: vec![],
+            foreign_keys: vec![],
         };
 
         schema.add_btree_table(Arc::new(customers_table));
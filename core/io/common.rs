@@ -1,5 +1,90 @@
 pub const ENV_DISABLE_FILE_LOCK: &str = "LIMBO_DISABLE_FILE_LOCK";
 
+/// The kind of byte-range lock taken by [fcntl_lock_range].
+#[cfg(target_family = "unix")]
+#[derive(Debug, Clone, Copy)]
+pub enum RangeLock {
+    Shared,
+    Exclusive,
+    Unlock,
+}
+
+/// Takes or releases a POSIX advisory lock on the `len` bytes of the file starting at
+/// `offset`, without waiting. Returns false if another process holds a conflicting lock.
+///
+/// These locks belong to the process, not to the file descriptor: closing any descriptor of
+/// the file releases every lock the process holds on it, including the ones taken through
+/// other descriptors. Files locked this way must be opened once per process.
+#[cfg(target_family = "unix")]
+pub fn fcntl_lock_range(
+    fd: std::os::fd::RawFd,
+    offset: u64,
+    len: u64,
+    lock: RangeLock,
+) -> crate::Result<bool> {
+    // SAFETY: `flock` is a plain C struct for which all zeroes is a valid value.
+    let mut flock: libc::flock = unsafe { std::mem::zeroed() };
+    flock.l_type = match lock {
+        RangeLock::Shared => libc::F_RDLCK,
+        RangeLock::Exclusive => libc::F_WRLCK,
+        RangeLock::Unlock => libc::F_UNLCK,
+    } as _;
+    flock.l_whence = libc::SEEK_SET as _;
+    flock.l_start = offset as libc::off_t;
+    flock.l_len = len as libc::off_t;
+    // SAFETY: `fd` is an open file descriptor and `flock` outlives the call.
+    if unsafe { libc::fcntl(fd, libc::F_SETLK, &flock) } == 0 {
+        return Ok(true);
+    }
+    let error = std::io::Error::last_os_error();
+    match error.raw_os_error() {
+        Some(libc::EACCES) | Some(libc::EAGAIN) => Ok(false),
+        _ => Err(crate::LimboError::LockingError(format!(
+            "Failed locking file range, {error}"
+        ))),
+    }
+}
+
+/// Maps the `len` bytes of the file starting at `offset` into memory shared with the other
+/// processes mapping them. The file is grown first if it is shorter, since the pages of a
+/// mapping past the end of the file cannot be accessed.
+#[cfg(target_family = "unix")]
+pub fn mmap_shared(
+    fd: std::os::fd::RawFd,
+    offset: u64,
+    len: usize,
+) -> crate::Result<super::SharedMemory> {
+    // SAFETY: `stat` is a plain C struct for which all zeroes is a valid value.
+    let mut stat: libc::stat = unsafe { std::mem::zeroed() };
+    // SAFETY: `fd` is an open file descriptor and `stat` outlives the call.
+    if unsafe { libc::fstat(fd, &mut stat) } != 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    let end = offset + len as u64;
+    // SAFETY: `fd` is an open file descriptor.
+    if (stat.st_size as u64) < end && unsafe { libc::ftruncate(fd, end as libc::off_t) } != 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    // SAFETY: `fd` is an open file descriptor spanning the mapped range.
+    let ptr = unsafe {
+        libc::mmap(
+            std::ptr::null_mut(),
+            len,
+            libc::PROT_READ | libc::PROT_WRITE,
+            libc::MAP_SHARED,
+            fd,
+            offset as libc::off_t,
+        )
+    };
+    if ptr == libc::MAP_FAILED {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    Ok(super::SharedMemory {
+        ptr: std::ptr::NonNull::new(ptr as *mut u8).expect("mmap never returns null"),
+        len,
+    })
+}
+
 #[cfg(test)]
 pub mod tests {
     use crate::{Result, IO};
@@ -1,6 +1,6 @@
 #![allow(clippy::arc_with_non_send_sync)]
 
-use super::{common, Completion, CompletionInner, File, OpenFlags, IO};
+use super::{common, Completion, CompletionInner, File, OpenFlags, SharedMemory, IO};
 use crate::io::clock::{Clock, Instant};
 use crate::storage::wal::CKPT_BATCH_PAGES;
 use crate::{turso_assert, CompletionError, LimboError, Result};
@@ -558,7 +558,9 @@ impl IO for UringIO {
             file,
             id,
         });
-        if std::env::var(common::ENV_DISABLE_FILE_LOCK).is_err() {
+        if !flags.contains(OpenFlags::NoLock)
+            && std::env::var(common::ENV_DISABLE_FILE_LOCK).is_err()
+        {
             uring_file.lock_file(!flags.contains(OpenFlags::ReadOnly))?;
         }
         Ok(uring_file)
@@ -778,6 +780,29 @@ impl File for UringFile {
         Ok(())
     }
 
+    fn lock_range(&self, offset: u64, len: u64, exclusive: bool) -> Result<bool> {
+        let lock = if exclusive {
+            common::RangeLock::Exclusive
+        } else {
+            common::RangeLock::Shared
+        };
+        common::fcntl_lock_range(self.file.as_raw_fd(), offset, len, lock)
+    }
+
+    fn unlock_range(&self, offset: u64, len: u64) -> Result<()> {
+        common::fcntl_lock_range(
+            self.file.as_raw_fd(),
+            offset,
+            len,
+            common::RangeLock::Unlock,
+        )?;
+        Ok(())
+    }
+
+    fn map_shared(&self, offset: u64, len: usize) -> Result<Option<SharedMemory>> {
+        common::mmap_shared(self.file.as_raw_fd(), offset, len).map(Some)
+    }
+
     fn pread(&self, pos: u64, c: Completion) -> Result<Completion> {
         let r = c.as_read();
         let read_e = {
@@ -12,6 +12,26 @@ use std::{fmt::Debug, pin::Pin};
 pub trait File: Send + Sync {
     fn lock_file(&self, exclusive: bool) -> Result<()>;
     fn unlock_file(&self) -> Result<()>;
+    /// Tries to lock the `len` bytes starting at `offset` without waiting, and returns whether
+    /// the lock was taken. The lock is held by the process, so it only conflicts with the locks
+    /// of other processes. Taking a lock on a range this process already holds changes its kind.
+    ///
+    /// Byte-range locks coordinate the processes sharing a WAL database; backends whose files
+    /// are never shared between processes do not need to implement them.
+    fn lock_range(&self, _offset: u64, _len: u64, _exclusive: bool) -> Result<bool> {
+        Ok(true)
+    }
+    /// Releases the lock this process holds on the `len` bytes starting at `offset`.
+    fn unlock_range(&self, _offset: u64, _len: u64) -> Result<()> {
+        Ok(())
+    }
+    /// Maps the `len` bytes starting at `offset` into memory shared with the other processes
+    /// mapping them, growing the file first if it is shorter. Returns None on backends whose
+    /// files are never shared between processes, which are read and written with `pread` and
+    /// `pwrite` instead.
+    fn map_shared(&self, _offset: u64, _len: usize) -> Result<Option<SharedMemory>> {
+        Ok(None)
+    }
     fn pread(&self, pos: u64, c: Completion) -> Result<Completion>;
     fn pwrite(&self, pos: u64, buffer: Arc<Buffer>, c: Completion) -> Result<Completion>;
     fn sync(&self, c: Completion) -> Result<Completion>;
@@ -61,6 +81,52 @@ pub trait File: Send + Sync {
     fn truncate(&self, len: u64, c: Completion) -> Result<Completion>;
 }
 
+/// Bytes of a file mapped into memory shared between processes, see [File::map_shared]. They
+/// are unmapped when this is dropped.
+pub struct SharedMemory {
+    ptr: NonNull<u8>,
+    len: usize,
+}
+
+// SAFETY: the mapping stays valid until it is dropped, and is only accessed through copies.
+unsafe impl Send for SharedMemory {}
+unsafe impl Sync for SharedMemory {}
+
+impl SharedMemory {
+    /// Copies the bytes at `offset` into `buf`. Other processes may write them at the same
+    /// time, so the callers must be able to tell a torn read from a complete one.
+    pub fn read(&self, offset: usize, buf: &mut [u8]) {
+        assert!(offset + buf.len() <= self.len);
+        // SAFETY: the range is within the mapping, which cannot overlap `buf`.
+        unsafe {
+            std::ptr::copy_nonoverlapping(
+                self.ptr.as_ptr().add(offset),
+                buf.as_mut_ptr(),
+                buf.len(),
+            )
+        }
+    }
+
+    /// Copies `buf` to the bytes at `offset`.
+    pub fn write(&self, offset: usize, buf: &[u8]) {
+        assert!(offset + buf.len() <= self.len);
+        // SAFETY: the range is within the mapping, which cannot overlap `buf`.
+        unsafe {
+            std::ptr::copy_nonoverlapping(buf.as_ptr(), self.ptr.as_ptr().add(offset), buf.len())
+        }
+    }
+}
+
+#[cfg(target_family = "unix")]
+impl Drop for SharedMemory {
+    fn drop(&mut self) {
+        // SAFETY: the mapping was made by `common::mmap_shared` and is not used anymore.
+        unsafe {
+            libc::munmap(self.ptr.as_ptr() as *mut libc::c_void, self.len);
+        }
+    }
+}
+
 #[derive(Debug, Copy, Clone, PartialEq)]
 pub struct OpenFlags(i32);
 
@@ -69,6 +135,9 @@ bitflags! {
         const None = 0b00000000;
         const Create = 0b0000001;
         const ReadOnly = 0b0000010;
+        /// Do not lock the whole file when opening it. Files shared between processes are
+        /// coordinated with byte-range locks instead.
+        const NoLock = 0b0000100;
     }
 }
 
@@ -1,4 +1,4 @@
-use super::{Completion, File, OpenFlags, IO};
+use super::{Completion, File, OpenFlags, SharedMemory, IO};
 use crate::error::LimboError;
 use crate::io::clock::{Clock, Instant};
 use crate::io::common;
@@ -108,7 +108,9 @@ impl IO for UnixIO {
         let unix_file = Arc::new(UnixFile {
             file: Arc::new(Mutex::new(file)),
         });
-        if std::env::var(common::ENV_DISABLE_FILE_LOCK).is_err() {
+        if !flags.contains(OpenFlags::NoLock)
+            && std::env::var(common::ENV_DISABLE_FILE_LOCK).is_err()
+        {
             unix_file.lock_file(!flags.contains(OpenFlags::ReadOnly))?;
         }
         Ok(unix_file)
@@ -171,6 +173,29 @@ impl File for UnixFile {
         Ok(())
     }
 
+    fn lock_range(&self, offset: u64, len: u64, exclusive: bool) -> Result<bool> {
+        let lock = if exclusive {
+            common::RangeLock::Exclusive
+        } else {
+            common::RangeLock::Shared
+        };
+        common::fcntl_lock_range(self.file.lock().as_raw_fd(), offset, len, lock)
+    }
+
+    fn unlock_range(&self, offset: u64, len: u64) -> Result<()> {
+        common::fcntl_lock_range(
+            self.file.lock().as_raw_fd(),
+            offset,
+            len,
+            common::RangeLock::Unlock,
+        )?;
+        Ok(())
+    }
+
+    fn map_shared(&self, offset: u64, len: usize) -> Result<Option<SharedMemory>> {
+        common::mmap_shared(self.file.lock().as_raw_fd(), offset, len).map(Some)
+    }
+
     #[instrument(err, skip_all, level = Level::TRACE)]
     fn pread(&self, pos: u64, c: Completion) -> Result<Completion> {
         let file = self.file.lock();
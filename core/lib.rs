@@ -30,6 +30,7 @@ pub mod types;
 mod util;
 #[cfg(feature = "uuid")]
 mod uuid;
+mod vacuum;
 mod vdbe;
 mod vector;
 mod vtab;
@@ -40,6 +41,8 @@ pub mod numeric;
 #[cfg(not(feature = "fuzz"))]
 mod numeric;
 
+use crate::incremental::dbsp::Delta;
+use crate::mvcc::database::MvccSavepoint;
 use crate::storage::checksum::CHECKSUM_REQUIRED_RESERVED_BYTES;
 use crate::storage::encryption::CipherMode;
 use crate::translate::pragma::TURSO_CDC_DEFAULT_TABLE_NAME;
@@ -58,14 +61,14 @@ pub use io::UnixIO;
 #[cfg(all(feature = "fs", target_os = "linux", feature = "io_uring"))]
 pub use io::UringIO;
 pub use io::{
-    Buffer, Completion, CompletionType, File, MemoryIO, OpenFlags, PlatformIO, SyscallIO,
-    WriteCompletion, IO,
+    Buffer, Completion, CompletionType, File, MemoryIO, OpenFlags, PlatformIO, SharedMemory,
+    SyscallIO, WriteCompletion, IO,
 };
 use parking_lot::RwLock;
-use schema::Schema;
+use schema::{Schema, STAT1_TABLE_NAME};
 use std::{
     borrow::Cow,
-    cell::{Cell, RefCell},
+    cell::{Cell, Ref, RefCell},
     collections::HashMap,
     fmt::{self, Display},
     num::NonZero,
@@ -82,8 +85,9 @@ use storage::database::DatabaseFile;
 pub use storage::database::IOContext;
 pub use storage::encryption::{EncryptionContext, EncryptionKey};
 use storage::page_cache::PageCache;
-use storage::pager::{AtomicDbState, DbState};
+use storage::pager::{AtomicDbState, DbState, PagerSavepoint};
 use storage::sqlite3_ondisk::PageSize;
+use storage::super_journal::{SuperJournal, SuperJournalEntry};
 pub use storage::{
     buffer_pool::BufferPool,
     database::DatabaseStorage,
@@ -94,17 +98,20 @@ pub use storage::{
 use tracing::{instrument, Level};
 use turso_macros::match_ignore_ascii_case;
 use turso_parser::{ast, ast::Cmd, parser::Parser};
-use types::IOResult;
 pub use types::RefValue;
 pub use types::Value;
-use util::parse_schema_rows;
+use types::{IOCompletions, IOResult};
 pub use util::IOExt;
+use util::{parse_schema_rows, parse_stat1_rows};
 pub use vdbe::{builder::QueryMode, explain::EXPLAIN_COLUMNS, explain::EXPLAIN_QUERY_PLAN_COLUMNS};
 
 /// Configuration for database features
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct DatabaseOpts {
     pub enable_mvcc: bool,
+    /// Whether MVCC transactions run under serializable snapshot isolation instead of
+    /// snapshot isolation.
+    pub enable_mvcc_serializable: bool,
     pub enable_indexes: bool,
     pub enable_views: bool,
     pub enable_strict: bool,
@@ -114,6 +121,7 @@ impl Default for DatabaseOpts {
     fn default() -> Self {
         Self {
             enable_mvcc: false,
+            enable_mvcc_serializable: false,
             enable_indexes: true,
             enable_views: false,
             enable_strict: false,
@@ -131,6 +139,11 @@ impl DatabaseOpts {
         self
     }
 
+    pub fn with_mvcc_serializable(mut self, enable: bool) -> Self {
+        self.enable_mvcc_serializable = enable;
+        self
+    }
+
     pub fn with_indexes(mut self, enable: bool) -> Self {
         self.enable_indexes = enable;
         self
@@ -157,6 +170,163 @@ enum TransactionState {
     None,
 }
 
+/// A savepoint opened with `SAVEPOINT name`, holding the state needed to roll the
+/// transaction back to the point where it was opened.
+struct Savepoint {
+    name: String,
+    /// Whether the savepoint was opened outside of a transaction, in which case releasing it
+    /// commits the transaction it started.
+    starts_transaction: bool,
+    pager: PagerSavepoint,
+    mvcc: Option<MvccSavepoint>,
+    schema: Arc<Schema>,
+    view_deltas: HashMap<String, HashMap<String, Delta>>,
+    deferred_fk_violations: i64,
+    /// The state of the temp and attached databases that had a write transaction open on them,
+    /// by database index.
+    attached: HashMap<usize, AttachedSavepoint>,
+}
+
+/// The state of a temp or attached database at the time a savepoint was opened.
+struct AttachedSavepoint {
+    pager: PagerSavepoint,
+    schema: Arc<Schema>,
+}
+
+/// A database used by a connection besides its main database: the temp database, holding the
+/// objects created with `CREATE TEMP`, or a database attached with `ATTACH`. Its transaction
+/// is tracked here, apart from the one of the main database.
+struct AttachedDatabase {
+    db: Arc<Database>,
+    pager: Rc<Pager>,
+    /// The schema as seen by the current transaction, `db.schema` holds the committed one.
+    schema: RefCell<Arc<Schema>>,
+    transaction_state: Cell<TransactionState>,
+    /// Set when the transaction is committed while a super-journal is in place, to whether the
+    /// schema changed. Until the super-journal is deleted, the commit can still be rolled back:
+    /// the locks are kept, and the schema change is not made visible to other connections.
+    super_journal_commit: Cell<Option<bool>>,
+    /// The file backing the temp database, deleted when it is closed. It is None for attached
+    /// databases and temp databases stored in memory.
+    path: Option<String>,
+}
+
+impl AttachedDatabase {
+    fn new(db: Arc<Database>, path: Option<String>) -> Result<Self> {
+        let pager = Rc::new(db.init_pager(None)?);
+        let schema = db.clone_schema()?;
+        Ok(Self {
+            db,
+            pager,
+            schema: RefCell::new(schema),
+            transaction_state: Cell::new(TransactionState::None),
+            super_journal_commit: Cell::new(None),
+            path,
+        })
+    }
+
+    fn in_write_transaction(&self) -> bool {
+        matches!(self.transaction_state.get(), TransactionState::Write { .. })
+    }
+
+    /// Begin a transaction, or upgrade the current one to a write transaction
+    fn begin_transaction(&self, write: bool) -> Result<()> {
+        let pager = &self.pager;
+        let current_state = self.transaction_state.get();
+        match current_state {
+            TransactionState::Write { .. } => return Ok(()),
+            TransactionState::Read if !write => return Ok(()),
+            TransactionState::None => {
+                if let result::LimboResult::Busy = pager.begin_read_tx()? {
+                    return Err(LimboError::Busy);
+                }
+                // Pick up the schema changes committed by other connections
+                let committed_schema = self.db.clone_schema()?;
+                if committed_schema.schema_version > self.schema.borrow().schema_version {
+                    self.schema.replace(committed_schema);
+                }
+            }
+            TransactionState::Read | TransactionState::PendingUpgrade => {}
+        }
+        if !write {
+            self.transaction_state.set(TransactionState::Read);
+            return Ok(());
+        }
+        if let result::LimboResult::Busy = pager.io.block(|| pager.begin_write_tx())? {
+            if current_state == TransactionState::None {
+                pager.end_read_tx()?;
+            }
+            return Err(LimboError::Busy);
+        }
+        self.transaction_state.set(TransactionState::Write {
+            schema_did_change: false,
+        });
+        Ok(())
+    }
+
+    /// End the transaction, if any
+    fn end_transaction(
+        &self,
+        rollback: bool,
+        sync_mode: SyncMode,
+        connection: &Connection,
+    ) -> Result<()> {
+        let (is_write, schema_did_change) = match self.transaction_state.get() {
+            TransactionState::None => return Ok(()),
+            TransactionState::Write { schema_did_change } => (true, schema_did_change),
+            TransactionState::Read | TransactionState::PendingUpgrade => (false, false),
+        };
+        self.transaction_state.set(TransactionState::None);
+        // Set before committing, so that the locks kept by a commit that fails midway are
+        // released when it is rolled back
+        let super_journal_commit = is_write && !rollback && connection.has_super_journal();
+        if super_journal_commit {
+            self.super_journal_commit.set(Some(schema_did_change));
+        }
+        self.pager.io.block(|| {
+            self.pager
+                .end_attached_tx(rollback, is_write, sync_mode, connection)
+        })?;
+        if schema_did_change && !super_journal_commit {
+            if rollback {
+                self.schema.replace(self.db.clone_schema()?);
+            } else {
+                self.db
+                    .update_schema_if_newer(self.schema.borrow().clone())?;
+            }
+        }
+        Ok(())
+    }
+
+    /// End the transaction committed while a super-journal was in place, if any, once the
+    /// super-journal is deleted. With `rollback`, the commit was rolled back from it instead.
+    fn end_super_journal_commit(&self, rollback: bool, connection: &Connection) -> Result<()> {
+        let Some(schema_did_change) = self.super_journal_commit.take() else {
+            return Ok(());
+        };
+        self.pager.end_super_journal_tx(rollback, connection)?;
+        if schema_did_change {
+            if rollback {
+                self.schema.replace(self.db.clone_schema()?);
+            } else {
+                self.db
+                    .update_schema_if_newer(self.schema.borrow().clone())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Where the temp database of a connection is stored, set with `PRAGMA temp_store`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum TempStore {
+    /// A temporary file, the default like in SQLite builds with `SQLITE_TEMP_STORE=1`.
+    #[default]
+    Default = 0,
+    File = 1,
+    Memory = 2,
+}
+
 #[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub enum SyncMode {
     Off = 0,
@@ -166,6 +336,7 @@ pub enum SyncMode {
 pub(crate) type MvStore = mvcc::MvStore<mvcc::LocalClock>;
 
 pub(crate) type MvCursor = mvcc::cursor::MvccLazyCursor<mvcc::LocalClock>;
+pub(crate) type MvIndexCursor = mvcc::cursor::MvccIndexCursor<mvcc::LocalClock>;
 
 /// The database manager ensures that there is a single, shared
 /// `Database` object per a database file. We need because it is not safe
@@ -277,9 +448,26 @@ impl Database {
         flags: OpenFlags,
         opts: DatabaseOpts,
     ) -> Result<Arc<Database>> {
-        let file = io.open_file(path, flags, true)?;
+        // Opening the file again would release the locks held on it by this process. The
+        // registry stays locked until the database is registered, so that another thread cannot
+        // open the file in the meantime.
+        let mut registry = DATABASE_MANAGER.lock().unwrap();
+        if let Some(db) = registry
+            .get(&Self::canonical_path(path))
+            .and_then(Weak::upgrade)
+        {
+            return Ok(db);
+        }
+        let file = if opts.enable_mvcc {
+            io.open_file(path, flags, true)?
+        } else {
+            // Other processes may use the database through the wal-index
+            let file = io.open_file(path, flags | OpenFlags::NoLock, true)?;
+            storage::wal_index::lock_database_file(&file)?;
+            file
+        };
         let db_file = Arc::new(DatabaseFile::new(file));
-        Self::open_with_flags(io, path, db_file, flags, opts)
+        Self::open_with_registry(&mut registry, io, path, db_file, flags, opts)
     }
 
     #[allow(clippy::arc_with_non_send_sync)]
@@ -308,6 +496,21 @@ impl Database {
         db_file: Arc<dyn DatabaseStorage>,
         flags: OpenFlags,
         opts: DatabaseOpts,
+    ) -> Result<Arc<Database>> {
+        let mut registry = DATABASE_MANAGER.lock().unwrap();
+        Self::open_with_registry(&mut registry, io, path, db_file, flags, opts)
+    }
+
+    /// Opens the database at `path` and adds it to `registry`, or returns the database already
+    /// registered for that path.
+    #[allow(clippy::arc_with_non_send_sync)]
+    fn open_with_registry(
+        registry: &mut HashMap<String, Weak<Database>>,
+        io: Arc<dyn IO>,
+        path: &str,
+        db_file: Arc<dyn DatabaseStorage>,
+        flags: OpenFlags,
+        opts: DatabaseOpts,
     ) -> Result<Arc<Database>> {
         // turso-sync-engine create 2 databases with different names in the same IO if MemoryIO is used
         // in this case we need to bypass registry (as this is MemoryIO DB) but also preserve original distinction in names (e.g. :memory:-draft and :memory:-synced)
@@ -316,26 +519,30 @@ impl Database {
                 io,
                 path,
                 &format!("{path}-wal"),
+                None,
                 db_file,
                 flags,
                 opts,
             );
         }
 
-        let mut registry = DATABASE_MANAGER.lock().unwrap();
-
-        let canonical_path = std::fs::canonicalize(path)
-            .ok()
-            .and_then(|p| p.to_str().map(|s| s.to_string()))
-            .unwrap_or_else(|| path.to_string());
+        let canonical_path = Self::canonical_path(path);
 
         if let Some(db) = registry.get(&canonical_path).and_then(Weak::upgrade) {
             return Ok(db);
         }
+        // Roll back the commits spanning several database files that did not complete
+        #[cfg(feature = "fs")]
+        storage::super_journal::recover(&io, path)?;
+        // The WAL is shared with other processes through the wal-index, except with MVCC whose
+        // log is not
+        let shm_path = (!opts.enable_mvcc && !flags.contains(OpenFlags::ReadOnly))
+            .then(|| format!("{path}-shm"));
         let db = Self::open_with_flags_bypass_registry_internal(
             io,
             path,
             &format!("{path}-wal"),
+            shm_path.as_deref(),
             db_file,
             flags,
             opts,
@@ -344,6 +551,16 @@ impl Database {
         Ok(db)
     }
 
+    fn canonical_path(path: &str) -> String {
+        std::fs::canonicalize(path)
+            .ok()
+            .and_then(|p| p.to_str().map(|s| s.to_string()))
+            .unwrap_or_else(|| path.to_string())
+    }
+
+    /// Opens the database without sharing it through the registry of open databases. The
+    /// wal-index is not used, and the caller must not open the same file elsewhere in the
+    /// process: closing it would release the locks other processes rely on.
     #[allow(clippy::arc_with_non_send_sync)]
     #[cfg(all(feature = "fs", feature = "conn_raw_api"))]
     pub fn open_with_flags_bypass_registry(
@@ -354,7 +571,9 @@ impl Database {
         flags: OpenFlags,
         opts: DatabaseOpts,
     ) -> Result<Arc<Database>> {
-        Self::open_with_flags_bypass_registry_internal(io, path, wal_path, db_file, flags, opts)
+        Self::open_with_flags_bypass_registry_internal(
+            io, path, wal_path, None, db_file, flags, opts,
+        )
     }
 
     #[allow(clippy::arc_with_non_send_sync)]
@@ -362,17 +581,26 @@ impl Database {
         io: Arc<dyn IO>,
         path: &str,
         wal_path: &str,
+        shm_path: Option<&str>,
         db_file: Arc<dyn DatabaseStorage>,
         flags: OpenFlags,
         opts: DatabaseOpts,
     ) -> Result<Arc<Database>> {
-        let shared_wal = WalFileShared::open_shared_if_exists(&io, wal_path)?;
+        let shared_wal = WalFileShared::open_shared_if_exists(&io, wal_path, shm_path)?;
 
         let mv_store = if opts.enable_mvcc {
-            Some(Arc::new(MvStore::new(
-                mvcc::LocalClock::new(),
-                mvcc::persistent_storage::Storage::new_noop(),
-            )))
+            let storage = if path.starts_with(":memory:") {
+                mvcc::persistent_storage::Storage::new_noop()
+            } else {
+                mvcc::persistent_storage::Storage::new_logical_log(
+                    io.clone(),
+                    &format!("{path}-log"),
+                )?
+            };
+            Some(Arc::new(
+                MvStore::new(mvcc::LocalClock::new(), storage)
+                    .with_serializable(opts.enable_mvcc_serializable),
+            ))
         } else {
             None
         };
@@ -438,6 +666,21 @@ impl Database {
                 }
                 Ok(())
             })?;
+
+            if let Some(mv_store) = &db.mv_store {
+                // Transactions logged before a crash may not have reached the b-tree
+                mv_store.recover(&conn)?;
+            }
+
+            // The statistics stored by ANALYZE are read with a statement, which needs the
+            // schema that was just parsed
+            conn.schema.replace(db.clone_schema()?);
+            conn.load_analysis(None)?;
+            let table_stats = conn.schema.borrow().table_stats.clone();
+            db.with_schema_mut(|schema| {
+                schema.table_stats = table_stats;
+                Ok(())
+            })?;
         }
         Ok(db)
     }
@@ -463,7 +706,6 @@ impl Database {
                     .map_err(|_| LimboError::SchemaLocked)?
                     .clone(),
             ),
-            database_schemas: RefCell::new(std::collections::HashMap::new()),
             auto_commit: Cell::new(true),
             transaction_state: Cell::new(TransactionState::None),
             last_insert_rowid: Cell::new(0),
@@ -478,7 +720,12 @@ impl Database {
             closed: Cell::new(false),
             attached_databases: RefCell::new(DatabaseCatalog::new()),
             query_only: Cell::new(false),
+            analysis_limit: Cell::new(0),
+            foreign_keys: Cell::new(false),
+            defer_foreign_keys: Cell::new(false),
+            deferred_fk_violations: Cell::new(0),
             mv_tx: Cell::new(None),
+            savepoints: RefCell::new(Vec::new()),
             view_transaction_states: AllViewsTxState::new(),
             metrics: RefCell::new(ConnectionMetrics::new()),
             is_nested_stmt: Cell::new(false),
@@ -487,6 +734,9 @@ impl Database {
             sync_mode: Cell::new(SyncMode::Full),
             data_sync_retry: Cell::new(false),
             busy_timeout: Cell::new(None),
+            temp_store: Cell::new(TempStore::Default),
+            temp_database: RefCell::new(None),
+            super_journal: RefCell::new(None),
         });
         self.n_connections
             .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
@@ -652,9 +902,12 @@ impl Database {
         if disable_checksums {
             pager.reset_checksum_context();
         }
-        let file = self
-            .io
-            .open_file(&self.wal_path, OpenFlags::Create, false)?;
+        let wal_flags = if self.shared_wal.read().wal_index.is_some() {
+            OpenFlags::Create | OpenFlags::NoLock
+        } else {
+            OpenFlags::Create
+        };
+        let file = self.io.open_file(&self.wal_path, wal_flags, false)?;
 
         // Enable WAL in the existing shared instance
         {
@@ -833,7 +1086,7 @@ impl CaptureDataChangesMode {
 struct DatabaseCatalog {
     name_to_index: HashMap<String, usize>,
     allocated: Vec<u64>,
-    index_to_data: HashMap<usize, (Arc<Database>, Rc<Pager>)>,
+    index_to_data: HashMap<usize, AttachedDatabase>,
 }
 
 #[allow(unused)]
@@ -849,7 +1102,7 @@ impl DatabaseCatalog {
     fn get_database_by_index(&self, index: usize) -> Option<Arc<Database>> {
         self.index_to_data
             .get(&index)
-            .map(|(db, _pager)| db.clone())
+            .map(|attached| attached.db.clone())
     }
 
     fn get_database_by_name(&self, s: &str) -> Option<(usize, Arc<Database>)> {
@@ -858,16 +1111,16 @@ impl DatabaseCatalog {
             Some(idx) => self
                 .index_to_data
                 .get(idx)
-                .map(|(db, _pager)| (*idx, db.clone())),
+                .map(|attached| (*idx, attached.db.clone())),
         }
     }
 
     fn get_pager_by_index(&self, idx: &usize) -> Rc<Pager> {
-        let (_db, pager) = self
-            .index_to_data
+        self.index_to_data
             .get(idx)
-            .expect("If we are looking up a database by index, it must exist.");
-        pager.clone()
+            .expect("If we are looking up a database by index, it must exist.")
+            .pager
+            .clone()
     }
 
     fn add(&mut self, s: &str) -> usize {
@@ -878,7 +1131,7 @@ impl DatabaseCatalog {
         index
     }
 
-    fn insert(&mut self, s: &str, data: (Arc<Database>, Rc<Pager>)) -> usize {
+    fn insert(&mut self, s: &str, data: AttachedDatabase) -> usize {
         let idx = self.add(s);
         self.index_to_data.insert(idx, data);
         idx
@@ -940,9 +1193,6 @@ pub struct Connection {
     _db: Arc<Database>,
     pager: RefCell<Rc<Pager>>,
     schema: RefCell<Arc<Schema>>,
-    /// Per-database schema cache (database_index -> schema)
-    /// Loaded lazily to avoid copying all schemas on connection open
-    database_schemas: RefCell<std::collections::HashMap<usize, Arc<Schema>>>,
     /// Whether to automatically commit transaction
     auto_commit: Cell<bool>,
     transaction_state: Cell<TransactionState>,
@@ -963,7 +1213,18 @@ pub struct Connection {
     /// Attached databases
     attached_databases: RefCell<DatabaseCatalog>,
     query_only: Cell<bool>,
+    /// Approximate maximum number of index entries ANALYZE scans per index, or 0 for no limit.
+    analysis_limit: Cell<u64>,
+    /// Whether foreign key constraints are enforced, set with `PRAGMA foreign_keys`.
+    foreign_keys: Cell<bool>,
+    /// Whether all foreign key constraints are checked at commit time until the transaction
+    /// ends, set with `PRAGMA defer_foreign_keys`.
+    defer_foreign_keys: Cell<bool>,
+    /// The number of deferred foreign key constraint violations in the current transaction.
+    pub(crate) deferred_fk_violations: Cell<i64>,
     pub(crate) mv_tx: Cell<Option<(crate::mvcc::database::TxID, TransactionMode)>>,
+    /// Savepoints of the current transaction, innermost last.
+    savepoints: RefCell<Vec<Savepoint>>,
 
     /// Per-connection view transaction states for uncommitted changes. This represents
     /// one entry per view that was touched in the transaction.
@@ -979,6 +1240,12 @@ pub struct Connection {
     data_sync_retry: Cell<bool>,
     /// User defined max accumulated Busy timeout duration
     busy_timeout: Cell<Option<std::time::Duration>>,
+    /// Where the temp database is stored, set with `PRAGMA temp_store`.
+    temp_store: Cell<TempStore>,
+    /// The temp database, opened when a statement first uses it.
+    temp_database: RefCell<Option<AttachedDatabase>>,
+    /// The super-journal of the commit in progress, if it writes to several database files.
+    super_journal: RefCell<Option<SuperJournal>>,
 }
 
 impl Drop for Connection {
@@ -988,6 +1255,8 @@ impl Drop for Connection {
             self._db
                 .n_connections
                 .fetch_sub(1, std::sync::atomic::Ordering::Relaxed);
+            // the temp database must not outlive the connection
+            let _ = self.close_temp_database();
         }
     }
 }
@@ -1142,6 +1411,7 @@ impl Connection {
         self.with_schema_mut(|schema| {
             *schema = fresh;
         });
+        self.load_analysis(None)?;
         Result::Ok(())
     }
 
@@ -1323,9 +1593,7 @@ impl Connection {
         db_opts: DatabaseOpts,
         io: Arc<dyn IO>,
     ) -> Result<Arc<Database>> {
-        let mut opts = OpenOptions::parse(uri)?;
-        // FIXME: for now, only support read only attach
-        opts.mode = OpenMode::ReadOnly;
+        let opts = OpenOptions::parse(uri)?;
         let flags = opts.get_flags()?;
         let io = opts.vfs.map(Database::io_for_vfs).unwrap_or(Ok(io))?;
         let db = Database::open_file_with_flags(io.clone(), &opts.path, flags, db_opts)?;
@@ -1577,6 +1845,7 @@ impl Connection {
                 self.transaction_state.set(TransactionState::None);
             }
         }
+        self.close_temp_database()?;
 
         if self
             ._db
@@ -1651,6 +1920,8 @@ impl Connection {
     pub fn is_readonly(&self, index: usize) -> bool {
         if index == 0 {
             self._db.is_readonly()
+        } else if index == 1 {
+            false
         } else {
             let db = self
                 .attached_databases
@@ -1738,6 +2009,25 @@ impl Connection {
         Ok(())
     }
 
+    /// Loads the statistics that ANALYZE stored in sqlite_stat1 into the schema, where the
+    /// query optimizer uses them.
+    pub(crate) fn load_analysis(
+        self: &Arc<Connection>,
+        mv_tx: Option<(u64, TransactionMode)>,
+    ) -> Result<()> {
+        if self
+            .schema
+            .borrow()
+            .get_btree_table(STAT1_TABLE_NAME)
+            .is_none()
+        {
+            self.with_schema_mut(|schema| schema.table_stats.clear());
+            return Ok(());
+        }
+        let stmt = self.prepare(format!("SELECT tbl, idx, stat FROM {STAT1_TABLE_NAME}"))?;
+        self.with_schema_mut(|schema| parse_stat1_rows(stmt, schema, mv_tx))
+    }
+
     // Clearly there is something to improve here, Vec<Vec<Value>> isn't a couple of tea
     /// Query the current rows/values of `pragma_name`.
     pub fn pragma_query(self: &Arc<Connection>, pragma_name: &str) -> Result<Vec<Vec<Value>>> {
@@ -1819,10 +2109,16 @@ impl Connection {
     }
 
     fn get_pager_from_database_index(&self, index: &usize) -> Rc<Pager> {
-        if *index < 2 {
-            self.pager.borrow().clone()
-        } else {
-            self.attached_databases.borrow().get_pager_by_index(index)
+        match *index {
+            0 => self.pager.borrow().clone(),
+            1 => self
+                .temp_database
+                .borrow()
+                .as_ref()
+                .expect("temp database should have been opened by the Transaction instruction")
+                .pager
+                .clone(),
+            _ => self.attached_databases.borrow().get_pager_by_index(index),
         }
     }
 
@@ -1878,11 +2174,9 @@ impl Connection {
             .with_views(use_views)
             .with_strict(use_strict);
         let db = Self::from_uri_attached(path, db_opts, self._db.io.clone())?;
-        let pager = Rc::new(db.init_pager(None)?);
+        let attached = AttachedDatabase::new(db, None)?;
 
-        self.attached_databases
-            .borrow_mut()
-            .insert(alias, (db, pager));
+        self.attached_databases.borrow_mut().insert(alias, attached);
 
         Ok(())
     }
@@ -1901,6 +2195,16 @@ impl Connection {
 
         // Remove from attached databases
         let mut attached_dbs = self.attached_databases.borrow_mut();
+        let in_transaction = attached_dbs
+            .name_to_index
+            .get(alias)
+            .and_then(|index| attached_dbs.index_to_data.get(index))
+            .is_some_and(|attached| attached.transaction_state.get() != TransactionState::None);
+        if in_transaction {
+            return Err(LimboError::InvalidArgument(format!(
+                "database {alias} is locked"
+            )));
+        }
         if attached_dbs.remove(alias).is_none() {
             return Err(LimboError::InvalidArgument(format!(
                 "no such database: {alias}"
@@ -1925,33 +2229,475 @@ impl Connection {
             .collect()
     }
 
-    /// Resolve database ID from a qualified name
+    /// Open the temp database, stored according to `PRAGMA temp_store`
+    #[cfg(not(feature = "fs"))]
+    fn open_temp_database(&self) -> Result<AttachedDatabase> {
+        Err(LimboError::InvalidArgument(
+            "temporary databases are not available in this build (no-fs)".to_string(),
+        ))
+    }
+
+    /// Open the temp database, stored according to `PRAGMA temp_store`
+    #[cfg(feature = "fs")]
+    fn open_temp_database(&self) -> Result<AttachedDatabase> {
+        if self._db.mv_store.is_some() {
+            return Err(LimboError::ParseError(
+                "TEMP objects are not supported with MVCC yet".to_string(),
+            ));
+        }
+        let use_indexes = self
+            ._db
+            .schema
+            .lock()
+            .map_err(|_| LimboError::SchemaLocked)?
+            .indexes_enabled();
+        let db_opts = DatabaseOpts::new()
+            .with_indexes(use_indexes)
+            .with_views(self._db.experimental_views_enabled())
+            .with_strict(self._db.experimental_strict_enabled());
+        let (io, path): (Arc<dyn IO>, Option<String>) = match self.temp_store.get() {
+            TempStore::Memory => (Arc::new(MemoryIO::new()), None),
+            TempStore::Default | TempStore::File => {
+                let name = format!("turso-temp-{:016x}", self._db.io.generate_random_number());
+                let path = std::env::temp_dir().join(name);
+                (
+                    self._db.io.clone(),
+                    Some(path.to_string_lossy().to_string()),
+                )
+            }
+        };
+        let db = Database::open_file_with_flags(
+            io,
+            path.as_deref().unwrap_or(util::MEMORY_PATH),
+            OpenFlags::Create,
+            db_opts,
+        )?;
+        AttachedDatabase::new(db, path)
+    }
+
+    /// Get the temp database, opening it if it is not in use yet
+    fn temp_database(&self) -> Result<Ref<'_, AttachedDatabase>> {
+        if self.temp_database.borrow().is_none() {
+            let temp_database = self.open_temp_database()?;
+            self.temp_database.replace(Some(temp_database));
+        }
+        Ok(Ref::map(self.temp_database.borrow(), |temp_database| {
+            temp_database
+                .as_ref()
+                .expect("temp database should be open")
+        }))
+    }
+
+    /// Get the temp database or an attached database by index, opening the temp database if it
+    /// is not in use yet
+    fn attached_database(&self, database_id: usize) -> Result<Ref<'_, AttachedDatabase>> {
+        if database_id == 1 {
+            return self.temp_database();
+        }
+        Ref::filter_map(self.attached_databases.borrow(), |attached_dbs| {
+            attached_dbs.index_to_data.get(&database_id)
+        })
+        .map_err(|_| LimboError::InternalError(format!("no database with index {database_id}")))
+    }
+
+    /// Call `f` on the temp database, if it is in use, and on every attached database
+    fn for_each_attached_database(
+        &self,
+        mut f: impl FnMut(usize, &AttachedDatabase) -> Result<()>,
+    ) -> Result<()> {
+        if let Some(temp) = self.temp_database.borrow().as_ref() {
+            f(1, temp)?;
+        }
+        for (index, attached) in self.attached_databases.borrow().index_to_data.iter() {
+            f(*index, attached)?;
+        }
+        Ok(())
+    }
+
+    /// Close the temp database, dropping all of its contents
+    fn close_temp_database(&self) -> Result<()> {
+        let Some(temp) = self.temp_database.take() else {
+            return Ok(());
+        };
+        temp.end_transaction(true, SyncMode::Off, self)?;
+        if let Some(path) = &temp.path {
+            temp.db.io.remove_file(&temp.db.wal_path)?;
+            temp.db.io.remove_file(path)?;
+        }
+        Ok(())
+    }
+
+    /// The schema of the temp database, as seen by the current transaction
+    pub(crate) fn temp_schema(&self) -> Result<Arc<Schema>> {
+        Ok(self.temp_database()?.schema.borrow().clone())
+    }
+
+    /// The schema that statements on the objects of `database_id` are translated against. The
+    /// statements of triggers on temp tables may refer to main tables and vice versa, so this
+    /// does not depend on the schema the enclosing statement is translated against.
+    pub(crate) fn database_schema(&self, database_id: usize) -> Result<Option<Arc<Schema>>> {
+        match database_id {
+            0 => Ok(Some(self.schema.borrow().clone())),
+            _ => Ok(Some(
+                self.attached_database(database_id)?.schema.borrow().clone(),
+            )),
+        }
+    }
+
+    /// The schema cookies of the temp database and of the attached databases, as seen by the
+    /// current transaction
+    pub(crate) fn attached_schema_cookies(&self) -> HashMap<usize, u32> {
+        let mut schema_cookies = HashMap::new();
+        let _ = self.for_each_attached_database(|index, attached| {
+            schema_cookies.insert(index, attached.schema.borrow().schema_version);
+            Ok(())
+        });
+        schema_cookies
+    }
+
+    pub(crate) fn with_attached_schema_mut<T>(
+        &self,
+        database_id: usize,
+        f: impl FnOnce(&mut Schema) -> T,
+    ) -> Result<T> {
+        let attached = self.attached_database(database_id)?;
+        let mut schema_ref = attached.schema.borrow_mut();
+        let schema = Arc::make_mut(&mut *schema_ref);
+        Ok(f(schema))
+    }
+
+    /// Begin a transaction on the temp database or on an attached database, or upgrade the
+    /// current one to a write transaction
+    pub(crate) fn begin_attached_transaction(&self, database_id: usize, write: bool) -> Result<()> {
+        if self.is_nested_stmt.get() {
+            // Parent statement has already begun the transaction.
+            return Ok(());
+        }
+        let attached = self.attached_database(database_id)?;
+        if write && attached.db.is_readonly() {
+            return Err(LimboError::ReadOnly);
+        }
+        attached.begin_transaction(write)
+    }
+
+    /// End the transactions on the temp database and on the attached databases, if any. The
+    /// temp database is not synced since it does not survive the connection.
+    pub(crate) fn end_attached_transactions(&self, rollback: bool) -> Result<()> {
+        let sync_mode = self.get_sync_mode();
+        let mut result = Ok(());
+        // Every transaction is ended even if one of them fails, so that no lock is left behind
+        let _ = self.for_each_attached_database(|index, attached| {
+            let sync_mode = if index == 1 { SyncMode::Off } else { sync_mode };
+            if let Err(err) = attached.end_transaction(rollback, sync_mode, self) {
+                if result.is_ok() {
+                    result = Err(err);
+                }
+            }
+            Ok(())
+        });
+        if rollback {
+            // The commit, if any, did not go through
+            let rolled_back = self.rollback_super_journal();
+            if result.is_ok() {
+                result = rolled_back;
+            }
+        }
+        result
+    }
+
+    /// Record a change of the schema of the temp database or of an attached database made by
+    /// the current write transaction
+    pub(crate) fn set_attached_schema_version(
+        &self,
+        database_id: usize,
+        version: u32,
+    ) -> Result<()> {
+        let attached = self.attached_database(database_id)?;
+        attached.transaction_state.set(TransactionState::Write {
+            schema_did_change: true,
+        });
+        Arc::make_mut(&mut *attached.schema.borrow_mut()).schema_version = version;
+        Ok(())
+    }
+
+    /// Capture the state the transaction can later be rolled back to with
+    /// [Connection::rollback_to_savepoint]
+    fn savepoint(
+        &self,
+        name: String,
+        starts_transaction: bool,
+        pager: &Pager,
+        mv_store: Option<&Arc<MvStore>>,
+    ) -> Result<Savepoint> {
+        let mvcc = match (mv_store, self.mv_tx.get()) {
+            (Some(mv_store), Some((tx_id, _))) => Some(mv_store.savepoint(tx_id)?),
+            _ => None,
+        };
+        Ok(Savepoint {
+            name,
+            starts_transaction,
+            pager: pager.savepoint(),
+            mvcc,
+            schema: self.schema.borrow().clone(),
+            view_deltas: self.view_transaction_states.snapshot(),
+            deferred_fk_violations: self.deferred_fk_violations.get(),
+            attached: self.attached_savepoints(),
+        })
+    }
+
+    /// Roll the transaction back to the state captured by a savepoint
+    fn rollback_to_savepoint(
+        &self,
+        savepoint: &Savepoint,
+        pager: &Pager,
+        mv_store: Option<&Arc<MvStore>>,
+    ) -> Result<()> {
+        pager.rollback_to_savepoint(&savepoint.pager)?;
+        if let (Some(mv_store), Some((tx_id, _))) = (mv_store, self.mv_tx.get()) {
+            mv_store.rollback_to_savepoint(tx_id, savepoint.mvcc.as_ref())?;
+        }
+        self.schema.replace(savepoint.schema.clone());
+        self.rollback_attached_to_savepoint(&savepoint.attached)?;
+        self.view_transaction_states.restore(&savepoint.view_deltas);
+        self.deferred_fk_violations
+            .set(savepoint.deferred_fk_violations);
+        Ok(())
+    }
+
+    /// Capture the state of the temp and attached databases that have a write transaction open
+    /// for a savepoint
+    fn attached_savepoints(&self) -> HashMap<usize, AttachedSavepoint> {
+        let mut savepoints = HashMap::new();
+        let _ = self.for_each_attached_database(|index, attached| {
+            if attached.in_write_transaction() {
+                let savepoint = AttachedSavepoint {
+                    pager: attached.pager.savepoint(),
+                    schema: attached.schema.borrow().clone(),
+                };
+                savepoints.insert(index, savepoint);
+            }
+            Ok(())
+        });
+        savepoints
+    }
+
+    /// Roll the temp and attached databases back to a savepoint, or roll back the whole
+    /// transaction of those on which it began after the savepoint was opened
+    fn rollback_attached_to_savepoint(
+        &self,
+        savepoints: &HashMap<usize, AttachedSavepoint>,
+    ) -> Result<()> {
+        let sync_mode = self.get_sync_mode();
+        self.for_each_attached_database(|index, attached| {
+            let Some(savepoint) = savepoints.get(&index) else {
+                return attached.end_transaction(true, sync_mode, self);
+            };
+            attached.pager.rollback_to_savepoint(&savepoint.pager)?;
+            attached.schema.replace(savepoint.schema.clone());
+            Ok(())
+        })
+    }
+
+    /// Write a super-journal if the transaction being committed writes to more than one
+    /// database file, so that the commit is atomic across all of them. The temp database is
+    /// left out since it does not survive the connection, and so are in-memory databases.
+    pub(crate) fn write_super_journal(&self) -> Result<()> {
+        if self.super_journal.borrow().is_some() || self._db.path.starts_with(util::MEMORY_PATH) {
+            return Ok(());
+        }
+        let entry =
+            |database_id: usize, db: &Database, pager: &Pager| -> Result<SuperJournalEntry> {
+                let wal_path = std::fs::canonicalize(&db.wal_path)
+                    .map(|path| path.to_string_lossy().to_string())
+                    .unwrap_or_else(|_| db.wal_path.clone());
+                Ok(SuperJournalEntry {
+                    database_id,
+                    db_path: (database_id != 0).then(|| db.path.clone()),
+                    wal_path,
+                    max_frame: pager.wal_state()?.max_frame,
+                })
+            };
+        let mut entries = Vec::new();
+        if matches!(self.transaction_state.get(), TransactionState::Write { .. }) {
+            entries.push(entry(0, &self._db, &self.pager.borrow())?);
+        }
+        for (&index, attached) in self.attached_databases.borrow().index_to_data.iter() {
+            if attached.in_write_transaction() && !attached.db.path.starts_with(util::MEMORY_PATH) {
+                entries.push(entry(index, &attached.db, &attached.pager)?);
+            }
+        }
+        if entries.len() < 2 {
+            return Ok(());
+        }
+        let super_journal = SuperJournal::write(&self._db.io, &self._db.path, entries)?;
+        self.super_journal.replace(Some(super_journal));
+        Ok(())
+    }
+
+    /// Delete the super-journal of the commit in progress, if any, which completes the commit.
+    /// The locks kept by the databases committed under it are released afterwards.
+    pub(crate) fn delete_super_journal(&self) -> Result<()> {
+        let Some(super_journal) = self.super_journal.take() else {
+            return Ok(());
+        };
+        let mut result = super_journal.delete(&self._db.io);
+        let _ = self.for_each_attached_database(|_, attached| {
+            if let Err(err) = attached.end_super_journal_commit(false, self) {
+                if result.is_ok() {
+                    result = Err(err);
+                }
+            }
+            Ok(())
+        });
+        result
+    }
+
+    /// Roll back the commit in progress under a super-journal, if any. Like
+    /// [storage::super_journal::recover] does after a crash, every WAL it lists is truncated back
+    /// to the frames it held before the commit, and only then is the super-journal deleted. The
+    /// WALs are still locked, so no other commit or checkpoint can have made use of the frames
+    /// dropped. The main database is rolled back by the caller afterwards.
+    fn rollback_super_journal(&self) -> Result<()> {
+        let Some(super_journal) = self.super_journal.take() else {
+            return Ok(());
+        };
+        let mut result = Ok(());
+        for entry in super_journal.entries() {
+            let rewound = match entry.database_id {
+                0 => self.pager.borrow().rewind_wal(entry.max_frame),
+                index => self
+                    .attached_database(index)
+                    .and_then(|attached| attached.pager.rewind_wal(entry.max_frame)),
+            };
+            if let Err(err) = rewound {
+                if result.is_ok() {
+                    result = Err(err);
+                }
+            }
+        }
+        let _ = self.for_each_attached_database(|_, attached| {
+            if let Err(err) = attached.end_super_journal_commit(true, self) {
+                if result.is_ok() {
+                    result = Err(err);
+                }
+            }
+            Ok(())
+        });
+        // If a WAL could not be truncated, the super-journal is left behind for the rollback to
+        // complete the next time the database is opened
+        result?;
+        super_journal.delete(&self._db.io)
+    }
+
+    pub(crate) fn has_super_journal(&self) -> bool {
+        self.super_journal.borrow().is_some()
+    }
+
+    pub fn get_temp_store(&self) -> TempStore {
+        self.temp_store.get()
+    }
+
+    /// Set where the temp database is stored. Like in SQLite, changing it deletes the temp
+    /// database along with all of its contents.
+    pub fn set_temp_store(&self, temp_store: TempStore) -> Result<()> {
+        if temp_store == self.temp_store.get() {
+            return Ok(());
+        }
+        if self.temp_database.borrow().is_some() && !self.auto_commit.get() {
+            return Err(LimboError::TxError(
+                "temporary storage cannot be changed from within a transaction".to_string(),
+            ));
+        }
+        self.close_temp_database()?;
+        self.temp_store.set(temp_store);
+        Ok(())
+    }
+
+    /// Resolve database ID from a qualified name. Like in SQLite, an unqualified name refers to
+    /// a table or view of the temp database first, then of the main database and then of the
+    /// attached databases.
     pub(crate) fn resolve_database_id(&self, qualified_name: &ast::QualifiedName) -> Result<usize> {
+        self.resolve_database_id_with(qualified_name, |schema, name| {
+            (schema.get_btree_table(name).is_some() || schema.get_view(name).is_some())
+                && !schema::is_system_table(name)
+        })
+    }
+
+    /// Resolve database ID from a qualified name, where an unqualified name refers to the first
+    /// of the temp, main and attached databases in whose schema `in_schema` finds the object,
+    /// or to the main database if none does.
+    pub(crate) fn resolve_database_id_with(
+        &self,
+        qualified_name: &ast::QualifiedName,
+        in_schema: impl Fn(&Schema, &str) -> bool,
+    ) -> Result<usize> {
         use crate::util::normalize_ident;
 
         // Check if this is a qualified name (database.table) or unqualified
         if let Some(db_name) = &qualified_name.db_name {
-            let db_name_normalized = normalize_ident(db_name.as_str());
-            let name_bytes = db_name_normalized.as_bytes();
-            match_ignore_ascii_case!(match name_bytes {
-                b"main" => Ok(0),
-                b"temp" => Ok(1),
-                _ => {
-                    // Look up attached database
-                    if let Some((idx, _attached_db)) =
-                        self.get_attached_database(&db_name_normalized)
-                    {
-                        Ok(idx)
-                    } else {
-                        Err(LimboError::InvalidArgument(format!(
-                            "no such database: {db_name_normalized}"
-                        )))
-                    }
+            return self.resolve_database_name(db_name.as_str());
+        }
+        let name = normalize_ident(qualified_name.name.as_str());
+        if schema::is_temp_schema_table(&name) {
+            return Ok(1);
+        }
+        let temp_database = self.temp_database.borrow();
+        let in_temp = temp_database
+            .as_ref()
+            .is_some_and(|temp| in_schema(&temp.schema.borrow(), &name));
+        if in_temp {
+            return Ok(1);
+        }
+        if in_schema(&self.schema.borrow(), &name) {
+            return Ok(0);
+        }
+        let attached_dbs = self.attached_databases.borrow();
+        let mut indexes = attached_dbs
+            .index_to_data
+            .keys()
+            .copied()
+            .collect::<Vec<_>>();
+        indexes.sort_unstable();
+        let in_attached = indexes
+            .into_iter()
+            .find(|index| in_schema(&attached_dbs.index_to_data[index].schema.borrow(), &name));
+        Ok(in_attached.unwrap_or(0))
+    }
+
+    /// Resolve database ID from a database name
+    pub(crate) fn resolve_database_name(&self, db_name: &str) -> Result<usize> {
+        use crate::util::normalize_ident;
+
+        let db_name_normalized = normalize_ident(db_name);
+        let name_bytes = db_name_normalized.as_bytes();
+        match_ignore_ascii_case!(match name_bytes {
+            b"main" => Ok(0),
+            b"temp" => Ok(1),
+            _ => {
+                // Look up attached database
+                if let Some((idx, _attached_db)) = self.get_attached_database(&db_name_normalized) {
+                    Ok(idx)
+                } else {
+                    Err(LimboError::InvalidArgument(format!(
+                        "no such database: {db_name_normalized}"
+                    )))
                 }
-            })
-        } else {
-            // Unqualified table name - use main database
-            Ok(0)
+            }
+        })
+    }
+
+    /// The name of the database with the given index, the one used to qualify names
+    pub(crate) fn database_name(&self, database_id: usize) -> Option<String> {
+        match database_id {
+            0 => Some("main".to_string()),
+            1 => Some("temp".to_string()),
+            _ => self
+                .attached_databases
+                .borrow()
+                .name_to_index
+                .iter()
+                .find(|(_, &index)| index == database_id)
+                .map(|(name, _)| name.clone()),
         }
     }
 
@@ -1962,33 +2708,20 @@ impl Connection {
             let schema = self.schema.borrow();
             f(&schema)
         } else if database_id == 1 {
-            // Temp database - uses same schema as main for now, but this will change later.
-            let schema = self.schema.borrow();
-            f(&schema)
-        } else {
-            // Attached database - check cache first, then load from database
-            let mut schemas = self.database_schemas.borrow_mut();
-
-            if let Some(cached_schema) = schemas.get(&database_id) {
-                return f(cached_schema);
+            // Temp database - opened lazily, until then its schema is empty.
+            let temp_database = self.temp_database.borrow();
+            match temp_database.as_ref() {
+                Some(temp) => f(&temp.schema.borrow()),
+                None => f(&Schema::new(self.schema.borrow().indexes_enabled())),
             }
-
-            // Schema not cached, load it lazily from the attached database
+        } else {
+            // Attached database - its schema as seen by the current transaction
             let attached_dbs = self.attached_databases.borrow();
-            let (db, _pager) = attached_dbs
+            let attached = attached_dbs
                 .index_to_data
                 .get(&database_id)
                 .expect("Database ID should be valid after resolve_database_id");
-
-            let schema = db
-                .schema
-                .lock()
-                .expect("Schema lock should not fail")
-                .clone();
-
-            // Cache the schema for future use
-            schemas.insert(database_id, schema.clone());
-
+            let schema = attached.schema.borrow();
             f(&schema)
         }
     }
@@ -2016,12 +2749,17 @@ impl Connection {
         let main_path = Self::get_canonical_path_for_database(&self._db);
         databases.push((0, "main".to_string(), main_path));
 
+        // Add temp database once it is in use (always seq=1, name="temp"). Like SQLite, it is
+        // listed without a file path.
+        if self.temp_database.borrow().is_some() {
+            databases.push((1, "temp".to_string(), String::new()));
+        }
+
         // Add attached databases
         let attached_dbs = self.attached_databases.borrow();
         for (alias, &seq_number) in attached_dbs.name_to_index.iter() {
-            let file_path = if let Some((db, _pager)) = attached_dbs.index_to_data.get(&seq_number)
-            {
-                Self::get_canonical_path_for_database(db)
+            let file_path = if let Some(attached) = attached_dbs.index_to_data.get(&seq_number) {
+                Self::get_canonical_path_for_database(&attached.db)
             } else {
                 String::new()
             };
@@ -2045,6 +2783,62 @@ impl Connection {
         self.query_only.set(value);
     }
 
+    pub fn get_analysis_limit(&self) -> u64 {
+        self.analysis_limit.get()
+    }
+
+    pub fn set_analysis_limit(&self, limit: u64) {
+        self.analysis_limit.set(limit);
+    }
+
+    /// Returns the memory limit of the MVCC row versions in bytes, zero if there is none or the
+    /// database does not use MVCC.
+    pub fn get_mvcc_memory_limit(&self) -> usize {
+        self._db
+            .mv_store
+            .as_ref()
+            .map_or(0, |mv_store| mv_store.memory_limit())
+    }
+
+    /// Sets the memory limit of the MVCC row versions of the database, which all of its
+    /// connections share. Does nothing if the database does not use MVCC.
+    pub fn set_mvcc_memory_limit(&self, limit: usize) {
+        if let Some(mv_store) = &self._db.mv_store {
+            mv_store.set_memory_limit(limit);
+        }
+    }
+
+    /// Returns the counters of the garbage collection of the MVCC row versions of the database,
+    /// or None if the database does not use MVCC.
+    pub fn mvcc_gc_metrics(&self) -> Option<mvcc::GcMetrics> {
+        self._db
+            .mv_store
+            .as_ref()
+            .map(|mv_store| mv_store.gc_metrics())
+    }
+
+    pub fn foreign_keys_enabled(&self) -> bool {
+        self.foreign_keys.get()
+    }
+
+    pub fn set_foreign_keys_enabled(&self, enabled: bool) {
+        self.foreign_keys.set(enabled);
+    }
+
+    pub fn get_defer_foreign_keys(&self) -> bool {
+        self.defer_foreign_keys.get()
+    }
+
+    pub fn set_defer_foreign_keys(&self, defer: bool) {
+        self.defer_foreign_keys.set(defer);
+    }
+
+    /// Resets the foreign key state that only lasts until the end of a transaction.
+    pub(crate) fn end_foreign_key_transaction(&self) {
+        self.deferred_fk_violations.set(0);
+        self.defer_foreign_keys.set(false);
+    }
+
     pub fn get_sync_mode(&self) -> SyncMode {
         self.sync_mode.get()
     }
@@ -2373,6 +3167,16 @@ impl Statement {
         Ok(())
     }
 
+    /// The IO that the statement waits for after [Statement::step] returned [StepResult::IO].
+    pub(crate) fn pending_io(&self) -> IOCompletions {
+        match &self.state.io_completions {
+            Some(IOCompletions::Single(c)) => IOCompletions::Single(c.clone()),
+            Some(IOCompletions::Many(completions)) => IOCompletions::Many(completions.clone()),
+            // A statement waiting for its busy timeout has no IO in flight
+            None => IOCompletions::Single(Completion::new_dummy()),
+        }
+    }
+
     pub fn run_once(&self) -> Result<()> {
         let res = self.pager.io.step();
         if self.program.connection.is_nested_stmt.get() {
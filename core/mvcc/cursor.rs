@@ -1,8 +1,10 @@
 use crate::mvcc::clock::LogicalClock;
-use crate::mvcc::database::{MvStore, Row, RowID};
-use crate::types::{IOResult, SeekKey, SeekOp, SeekResult};
+use crate::mvcc::database::{IndexKey, MvStore, Row, RowID};
+use crate::schema::Index;
+use crate::types::{IOResult, ImmutableRecord, IndexInfo, SeekKey, SeekOp, SeekResult};
 use crate::Result;
 use crate::{Pager, Value};
+use std::cmp::Ordering;
 use std::fmt::Debug;
 use std::ops::Bound;
 use std::rc::Rc;
@@ -32,7 +34,7 @@ impl<Clock: LogicalClock> MvccLazyCursor<Clock> {
         table_id: u64,
         pager: Rc<Pager>,
     ) -> Result<MvccLazyCursor<Clock>> {
-        db.maybe_initialize_table(table_id, pager)?;
+        db.maybe_initialize_table(table_id, tx_id, pager)?;
         let cursor = Self {
             db,
             tx_id,
@@ -108,6 +110,7 @@ impl<Clock: LogicalClock> MvccLazyCursor<Clock> {
 
     /// Move the cursor to the next row. Returns true if the cursor moved to the next row, false if the cursor is at the end of the table.
     pub fn forward(&mut self) -> bool {
+        self.db.record_scan(self.tx_id, self.table_id);
         let before_first = matches!(self.current_pos, CursorPosition::BeforeFirst);
         let min_id = match self.current_pos {
             CursorPosition::Loaded(id) => id.row_id + 1,
@@ -153,6 +156,11 @@ impl<Clock: LogicalClock> MvccLazyCursor<Clock> {
     }
 
     pub fn last(&mut self) {
+        self.db.record_scan(self.tx_id, self.table_id);
+        self.move_to_last();
+    }
+
+    fn move_to_last(&mut self) {
         let last_rowid = self.db.get_last_rowid(self.table_id);
         if let Some(last_rowid) = last_rowid {
             self.current_pos = CursorPosition::Loaded(RowID {
@@ -164,8 +172,15 @@ impl<Clock: LogicalClock> MvccLazyCursor<Clock> {
         }
     }
 
+    /// Counts the rows of the table that are visible to the transaction.
+    pub fn count(&mut self) -> usize {
+        self.db.record_scan(self.tx_id, self.table_id);
+        self.db.count_rows(self.table_id, self.tx_id)
+    }
+
     pub fn get_next_rowid(&mut self) -> i64 {
-        self.last();
+        // Allocating a rowid does not read the table
+        self.move_to_last();
         match self.current_pos {
             CursorPosition::Loaded(id) => id.row_id + 1,
             CursorPosition::BeforeFirst => 1,
@@ -194,6 +209,11 @@ impl<Clock: LogicalClock> MvccLazyCursor<Clock> {
             SeekOp::LT => (Bound::Excluded(&rowid), false),
             SeekOp::LE { eq_only: _ } => (Bound::Included(&rowid), false),
         };
+        if op.eq_only() {
+            self.db.record_read(self.tx_id, rowid);
+        } else {
+            self.db.record_scan(self.tx_id, self.table_id);
+        }
         let rowid = self.db.seek_rowid(bound, lower_bound, self.tx_id);
         if let Some(rowid) = rowid {
             self.current_pos = CursorPosition::Loaded(rowid);
@@ -209,7 +229,7 @@ impl<Clock: LogicalClock> MvccLazyCursor<Clock> {
         } else {
             let forwards = matches!(op, SeekOp::GE { eq_only: _ } | SeekOp::GT);
             if forwards {
-                self.last();
+                self.move_to_last();
             } else {
                 self.rewind();
             }
@@ -222,6 +242,13 @@ impl<Clock: LogicalClock> MvccLazyCursor<Clock> {
             Value::Integer(i) => i,
             _ => unreachable!("btree tables are indexed by integers!"),
         };
+        self.db.record_read(
+            self.tx_id,
+            RowID {
+                table_id: self.table_id,
+                row_id: *int_key,
+            },
+        );
         let exists = self
             .db
             .seek_rowid(
@@ -242,3 +269,214 @@ impl<Clock: LogicalClock> MvccLazyCursor<Clock> {
         Ok(IOResult::Done(exists))
     }
 }
+
+#[derive(Debug, Clone)]
+enum IndexCursorPosition {
+    /// We haven't loaded any entry yet.
+    BeforeFirst,
+    /// We have loaded an entry, and the version of it that is visible to the transaction.
+    Loaded(IndexKey, Row),
+    /// We have reached the end of the index.
+    End,
+}
+
+/// A cursor over the entries of an index that are visible to a transaction.
+#[derive(Debug)]
+pub struct MvccIndexCursor<Clock: LogicalClock> {
+    pub db: Arc<MvStore<Clock>>,
+    current_pos: IndexCursorPosition,
+    index_id: u64,
+    /// A key that precedes all the entries of the index, which the other keys are made from.
+    first_key: IndexKey,
+    /// For a unique index, the number of leading columns of the entries that must be unique.
+    unique_columns: Option<usize>,
+    tx_id: u64,
+}
+
+impl<Clock: LogicalClock> MvccIndexCursor<Clock> {
+    pub fn new(
+        db: Arc<MvStore<Clock>>,
+        tx_id: u64,
+        index: &Index,
+        pager: Rc<Pager>,
+    ) -> Result<MvccIndexCursor<Clock>> {
+        let index_id = index.root_page as u64;
+        let index_info = Arc::new(IndexInfo::new_from_index(index));
+        // The entries of a WITHOUT ROWID table are keyed by its primary key
+        let key_columns = index.num_key_columns.unwrap_or(index_info.num_cols);
+        db.maybe_initialize_index(index_id, &index_info, key_columns, tx_id, pager)?;
+        // The rowid or primary key that ends the entries is not part of the unique key
+        let unique_columns = index.unique.then(|| index.key_columns().len());
+        let first_key = IndexKey::new(index_id, empty_record(), index_info, key_columns)
+            .seek_key(empty_record(), Ordering::Less);
+        Ok(Self {
+            db,
+            current_pos: IndexCursorPosition::BeforeFirst,
+            index_id,
+            first_key,
+            unique_columns,
+            tx_id,
+        })
+    }
+
+    /// Insert an entry into the index.
+    /// Sets the cursor to the inserted entry.
+    pub fn insert(&mut self, record: &ImmutableRecord) -> Result<()> {
+        let key = self.key(record.clone());
+        let row = Row::new(
+            RowID::new(self.index_id, key.rowid()),
+            record.get_payload().to_vec(),
+            record.column_count(),
+        );
+        self.db
+            .insert_index_entry(self.tx_id, key.clone(), row.clone(), self.unique_columns)
+            .inspect_err(|_| {
+                self.current_pos = IndexCursorPosition::BeforeFirst;
+            })?;
+        self.current_pos = IndexCursorPosition::Loaded(key, row);
+        Ok(())
+    }
+
+    /// Deletes the entry the cursor points at.
+    pub fn delete(&mut self) -> Result<()> {
+        if let IndexCursorPosition::Loaded(key, _) = &self.current_pos {
+            self.db.delete_index_entry(self.tx_id, key)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the record of the entry the cursor points at.
+    pub fn current_record(&self) -> Option<ImmutableRecord> {
+        match &self.current_pos {
+            IndexCursorPosition::Loaded(_, row) => {
+                Some(ImmutableRecord::from_bin_record(row.data.clone()))
+            }
+            IndexCursorPosition::BeforeFirst | IndexCursorPosition::End => None,
+        }
+    }
+
+    /// Move the cursor to the next entry. Returns true if the cursor moved to the next entry, false if the cursor is at the end of the index.
+    pub fn forward(&mut self) -> bool {
+        self.db.record_scan(self.tx_id, self.index_id);
+        let entry = match &self.current_pos {
+            IndexCursorPosition::BeforeFirst => self.db.seek_index_entry(
+                self.index_id,
+                Bound::Included(&self.first_key),
+                true,
+                self.tx_id,
+            ),
+            IndexCursorPosition::Loaded(key, _) => {
+                self.db
+                    .seek_index_entry(self.index_id, Bound::Excluded(key), true, self.tx_id)
+            }
+            IndexCursorPosition::End => return false,
+        };
+        self.current_pos = match entry {
+            Some((key, row)) => IndexCursorPosition::Loaded(key, row),
+            // An empty index stays before its first entry
+            None if matches!(self.current_pos, IndexCursorPosition::BeforeFirst) => {
+                IndexCursorPosition::BeforeFirst
+            }
+            None => IndexCursorPosition::End,
+        };
+        matches!(self.current_pos, IndexCursorPosition::Loaded(..))
+    }
+
+    /// Move the cursor to the previous entry. Returns true if the cursor moved to the previous entry, false if the cursor is at the start of the index.
+    pub fn backward(&mut self) -> bool {
+        self.db.record_scan(self.tx_id, self.index_id);
+        let entry = match &self.current_pos {
+            IndexCursorPosition::BeforeFirst => return false,
+            IndexCursorPosition::Loaded(key, _) => {
+                self.db
+                    .seek_index_entry(self.index_id, Bound::Excluded(key), false, self.tx_id)
+            }
+            IndexCursorPosition::End => self.last_entry(),
+        };
+        self.current_pos = match entry {
+            Some((key, row)) => IndexCursorPosition::Loaded(key, row),
+            None => IndexCursorPosition::BeforeFirst,
+        };
+        matches!(self.current_pos, IndexCursorPosition::Loaded(..))
+    }
+
+    /// Returns true if the cursor is not pointing to any entry.
+    pub fn is_empty(&self) -> bool {
+        !matches!(self.current_pos, IndexCursorPosition::Loaded(..))
+    }
+
+    pub fn rewind(&mut self) {
+        self.current_pos = IndexCursorPosition::BeforeFirst;
+    }
+
+    pub fn last(&mut self) {
+        self.db.record_scan(self.tx_id, self.index_id);
+        self.current_pos = match self.last_entry() {
+            Some((key, row)) => IndexCursorPosition::Loaded(key, row),
+            None => IndexCursorPosition::BeforeFirst,
+        };
+    }
+
+    fn last_entry(&self) -> Option<(IndexKey, Row)> {
+        let last_key = self.first_key.seek_key(empty_record(), Ordering::Greater);
+        self.db
+            .seek_index_entry(self.index_id, Bound::Included(&last_key), false, self.tx_id)
+    }
+
+    /// Counts the entries of the index that are visible to the transaction.
+    pub fn count(&mut self) -> usize {
+        self.db.record_scan(self.tx_id, self.index_id);
+        self.db
+            .count_index_entries(self.index_id, &self.first_key, self.tx_id)
+    }
+
+    pub fn seek(&mut self, seek_key: SeekKey<'_>, op: SeekOp) -> Result<IOResult<SeekResult>> {
+        let record = match seek_key {
+            SeekKey::IndexKey(record) => record,
+            SeekKey::TableRowId(_) => unreachable!("index cursors are not seeked by rowid"),
+        };
+        // Index reads are tracked per index, as a seek may read a range of entries
+        self.db.record_scan(self.tx_id, self.index_id);
+        // The seek key may hold only the first columns of the entries. It sorts before the
+        // entries it is a prefix of when they match the seek, and after them otherwise.
+        // gt -> first entry whose prefix is greater than the key
+        // ge -> first entry whose prefix is equal to or greater than the key
+        // lt -> last entry whose prefix is less than the key
+        // le -> last entry whose prefix is equal to or less than the key
+        let (tie_breaker, forwards) = match op {
+            SeekOp::GT => (Ordering::Greater, true),
+            SeekOp::GE { eq_only: _ } => (Ordering::Less, true),
+            SeekOp::LT => (Ordering::Less, false),
+            SeekOp::LE { eq_only: _ } => (Ordering::Greater, false),
+        };
+        let key = self.first_key.seek_key(record.clone(), tie_breaker);
+        let entry =
+            self.db
+                .seek_index_entry(self.index_id, Bound::Included(&key), forwards, self.tx_id);
+        let Some((found_key, row)) = entry else {
+            self.current_pos = if forwards {
+                IndexCursorPosition::End
+            } else {
+                IndexCursorPosition::BeforeFirst
+            };
+            return Ok(IOResult::Done(SeekResult::NotFound));
+        };
+        let found =
+            !op.eq_only() || found_key == self.first_key.seek_key(record.clone(), Ordering::Equal);
+        self.current_pos = IndexCursorPosition::Loaded(found_key, row);
+        if found {
+            Ok(IOResult::Done(SeekResult::Found))
+        } else {
+            Ok(IOResult::Done(SeekResult::NotFound))
+        }
+    }
+
+    /// Makes the key of an entry of the index.
+    fn key(&self, record: ImmutableRecord) -> IndexKey {
+        self.first_key.seek_key(record, Ordering::Equal)
+    }
+}
+
+fn empty_record() -> ImmutableRecord {
+    ImmutableRecord::from_values(&[], 0)
+}
@@ -1,4 +1,5 @@
 use crate::mvcc::clock::LogicalClock;
+use crate::mvcc::persistent_storage::LogWrite;
 use crate::mvcc::persistent_storage::Storage;
 use crate::result::LimboResult;
 use crate::return_if_io;
@@ -10,22 +11,25 @@ use crate::storage::btree::BTreeKey;
 use crate::storage::btree::CursorValidState;
 use crate::storage::sqlite3_ondisk::DatabaseHeader;
 use crate::storage::wal::TursoRwLock;
+use crate::types::compare_immutable;
 use crate::types::IOResult;
 use crate::types::ImmutableRecord;
+use crate::types::IndexInfo;
 use crate::Completion;
 use crate::IOExt;
 use crate::LimboError;
 use crate::Result;
-use crate::{Connection, Pager};
+use crate::{Connection, Pager, SyncMode};
 use crossbeam_skiplist::{SkipMap, SkipSet};
-use parking_lot::RwLock;
+use parking_lot::{Mutex, RwLock};
+use std::collections::BTreeMap;
 use std::collections::HashMap;
 use std::collections::HashSet;
 use std::fmt::Debug;
 use std::marker::PhantomData;
 use std::ops::Bound;
 use std::rc::Rc;
-use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
 use std::sync::Arc;
 use tracing::instrument;
 use tracing::Level;
@@ -46,6 +50,101 @@ impl RowID {
     }
 }
 
+/// The key of an index entry.
+///
+/// The record of an entry holds the indexed columns followed by the rowid of the row it points
+/// at, and the entries of an index are ordered like in its b-tree. The versions of an entry all
+/// have the same key, even when the record of a WITHOUT ROWID table changes in the columns
+/// that follow its primary key.
+#[derive(Clone, Debug)]
+pub struct IndexKey {
+    /// The index ID. Analogous to index's root page number.
+    pub index_id: u64,
+    pub record: ImmutableRecord,
+    index_info: Arc<IndexInfo>,
+    /// Number of leading columns of the record that make up the key.
+    key_columns: usize,
+    /// How the key compares to the keys it is a prefix of, which only seek keys are.
+    tie_breaker: std::cmp::Ordering,
+}
+
+impl IndexKey {
+    pub fn new(
+        index_id: u64,
+        record: ImmutableRecord,
+        index_info: Arc<IndexInfo>,
+        key_columns: usize,
+    ) -> Self {
+        Self {
+            index_id,
+            record,
+            index_info,
+            key_columns,
+            tie_breaker: std::cmp::Ordering::Equal,
+        }
+    }
+
+    /// A key to seek with, that compares with the keys it is a prefix of as `tie_breaker`.
+    pub(crate) fn seek_key(
+        &self,
+        record: ImmutableRecord,
+        tie_breaker: std::cmp::Ordering,
+    ) -> Self {
+        Self {
+            index_id: self.index_id,
+            record,
+            index_info: self.index_info.clone(),
+            key_columns: self.key_columns,
+            tie_breaker,
+        }
+    }
+
+    /// The rowid of the row the entry points at, or 0 for an entry of a WITHOUT ROWID table.
+    pub fn rowid(&self) -> i64 {
+        if !self.index_info.has_rowid {
+            return 0;
+        }
+        match self.record.get_values().last() {
+            Some(crate::types::RefValue::Integer(rowid)) => *rowid,
+            _ => unreachable!("index entries should end with an integer rowid"),
+        }
+    }
+}
+
+impl PartialEq for IndexKey {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other).is_eq()
+    }
+}
+
+impl Eq for IndexKey {}
+
+impl PartialOrd for IndexKey {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for IndexKey {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        use std::cmp::Ordering;
+        self.index_id.cmp(&other.index_id).then_with(|| {
+            let left = self.record.get_values();
+            let right = other.record.get_values();
+            let left_len = left.len().min(self.key_columns);
+            let right_len = right.len().min(other.key_columns);
+            let len = left_len.min(right_len);
+            compare_immutable(&left[..len], &right[..len], &self.index_info.key_info).then(
+                match left_len.cmp(&right_len) {
+                    Ordering::Equal => Ordering::Equal,
+                    Ordering::Less => self.tie_breaker,
+                    Ordering::Greater => other.tie_breaker.reverse(),
+                },
+            )
+        })
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, PartialOrd)]
 
 pub struct Row {
@@ -67,9 +166,9 @@ impl Row {
 /// A row version.
 #[derive(Clone, Debug, PartialEq)]
 pub struct RowVersion {
-    begin: TxTimestampOrID,
-    end: Option<TxTimestampOrID>,
-    row: Row,
+    pub(crate) begin: TxTimestampOrID,
+    pub(crate) end: Option<TxTimestampOrID>,
+    pub(crate) row: Row,
 }
 
 pub type TxID = u64;
@@ -78,11 +177,11 @@ pub type TxID = u64;
 #[derive(Clone, Debug)]
 pub struct LogRecord {
     pub(crate) tx_timestamp: TxID,
-    row_versions: Vec<RowVersion>,
+    pub(crate) row_versions: Vec<RowVersion>,
 }
 
 impl LogRecord {
-    fn new(tx_timestamp: TxID) -> Self {
+    pub(crate) fn new(tx_timestamp: TxID) -> Self {
         Self {
             tx_timestamp,
             row_versions: Vec::new(),
@@ -90,6 +189,29 @@ impl LogRecord {
     }
 }
 
+/// The row versions a transaction had inserted or deleted when a savepoint was opened.
+#[derive(Debug)]
+pub struct MvccSavepoint {
+    tx_id: TxID,
+    row_versions: HashMap<RowID, Vec<RowVersion>>,
+    index_versions: BTreeMap<IndexKey, Vec<RowVersion>>,
+}
+
+/// Counters of the garbage collection of row versions.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct GcMetrics {
+    /// Number of garbage collections that ran.
+    pub collections: u64,
+    /// Number of versions dropped because no transaction could see them anymore.
+    pub versions_reclaimed: u64,
+    /// Number of versions dropped from memory because the store exceeded its memory limit,
+    /// which are read from the b-tree again instead.
+    pub versions_checkpointed: u64,
+}
+
+/// Number of commits between two garbage collections of row versions.
+const GC_COMMIT_INTERVAL: u64 = 64;
+
 /// A transaction timestamp or ID.
 ///
 /// Versions either track a timestamp or a transaction ID, depending on the
@@ -97,7 +219,7 @@ impl LogRecord {
 /// transaction ID in the `begin` and `end` fields. After a transaction commits,
 /// versions switch to tracking timestamps.
 #[derive(Clone, Debug, PartialEq, PartialOrd)]
-enum TxTimestampOrID {
+pub(crate) enum TxTimestampOrID {
     /// A committed transaction's timestamp.
     Timestamp(u64),
     /// The ID of a non-committed transaction.
@@ -115,8 +237,19 @@ pub struct Transaction {
     begin_ts: u64,
     /// The transaction write set.
     write_set: SkipSet<RowID>,
+    /// The index entries the transaction inserted or deleted.
+    index_write_set: SkipSet<IndexKey>,
     /// The transaction read set.
     read_set: SkipSet<RowID>,
+    /// The tables and indexes the transaction scanned, only tracked under serializable isolation.
+    scan_set: SkipSet<u64>,
+    /// The tables and indexes the transaction opened cursors on, which are kept in memory while
+    /// it runs, see [MvStore::checkpoint_versions].
+    used_tables: SkipSet<u64>,
+    /// Whether a concurrent transaction read something this transaction wrote.
+    in_conflict: AtomicBool,
+    /// Whether this transaction read something a concurrent transaction wrote.
+    out_conflict: AtomicBool,
 }
 
 impl Transaction {
@@ -126,7 +259,12 @@ impl Transaction {
             tx_id,
             begin_ts,
             write_set: SkipSet::new(),
+            index_write_set: SkipSet::new(),
             read_set: SkipSet::new(),
+            scan_set: SkipSet::new(),
+            used_tables: SkipSet::new(),
+            in_conflict: AtomicBool::new(false),
+            out_conflict: AtomicBool::new(false),
         }
     }
 
@@ -134,9 +272,37 @@ impl Transaction {
         self.read_set.insert(id);
     }
 
+    fn insert_to_scan_set(&self, id: u64) {
+        self.scan_set.insert(id);
+    }
+
+    /// Whether the transaction read a row that `write_set` holds, or scanned a table or index
+    /// that `written` holds.
+    fn reads_any(&self, write_set: &HashSet<RowID>, written: &HashSet<u64>) -> bool {
+        self.read_set
+            .iter()
+            .any(|id| write_set.contains(id.value()))
+            || self.scan_set.iter().any(|id| written.contains(id.value()))
+    }
+
+    /// Returns the rows the transaction wrote, and the tables and indexes it wrote to.
+    fn writes(&self) -> (HashSet<RowID>, HashSet<u64>) {
+        let write_set: HashSet<RowID> = self.write_set.iter().map(|id| *id.value()).collect();
+        let written = write_set
+            .iter()
+            .map(|id| id.table_id)
+            .chain(self.index_write_set.iter().map(|key| key.value().index_id))
+            .collect();
+        (write_set, written)
+    }
+
     fn insert_to_write_set(&self, id: RowID) {
         self.write_set.insert(id);
     }
+
+    fn insert_to_index_write_set(&self, key: IndexKey) {
+        self.index_write_set.insert(key);
+    }
 }
 
 impl std::fmt::Display for Transaction {
@@ -168,6 +334,30 @@ impl std::fmt::Display for Transaction {
     }
 }
 
+/// What a transaction validated under serializable isolation read and wrote, kept after it
+/// commits for as long as a transaction that runs concurrently with it is active.
+#[derive(Debug)]
+struct CommittedTx {
+    tx_id: TxID,
+    end_ts: u64,
+    read_set: HashSet<RowID>,
+    scan_set: HashSet<u64>,
+    write_set: HashSet<RowID>,
+    /// The tables and indexes the transaction wrote to.
+    written: HashSet<u64>,
+    in_conflict: bool,
+    out_conflict: bool,
+}
+
+impl CommittedTx {
+    /// Whether the transaction read a row that `write_set` holds, or scanned a table or index
+    /// that `written` holds.
+    fn reads_any(&self, write_set: &HashSet<RowID>, written: &HashSet<u64>) -> bool {
+        self.read_set.iter().any(|id| write_set.contains(id))
+            || self.scan_set.iter().any(|id| written.contains(id))
+    }
+}
+
 /// Transaction state.
 #[derive(Debug, Clone, PartialEq)]
 enum TransactionState {
@@ -276,9 +466,34 @@ pub enum CommitState {
         end_ts: u64,
         write_set_index: usize,
     },
+    WriteIndexEntry {
+        end_ts: u64,
+        index_write_index: usize,
+    },
+    IndexEntryStateMachine {
+        end_ts: u64,
+        index_write_index: usize,
+    },
+    LogTx {
+        end_ts: u64,
+    },
+    WaitLog {
+        end_ts: u64,
+        write: LogWrite,
+    },
     CommitPagerTxn {
         end_ts: u64,
     },
+    TruncateLog {
+        end_ts: u64,
+    },
+    WaitTruncatedLog {
+        end_ts: u64,
+        write: LogWrite,
+    },
+    ReleaseCommitLock {
+        end_ts: u64,
+    },
     Commit {
         end_ts: u64,
     },
@@ -307,6 +522,10 @@ pub struct CommitStateMachine<Clock: LogicalClock> {
     connection: Arc<Connection>,
     /// Write set sorted by table id and row id
     write_set: Vec<RowID>,
+    /// Index entries written by the transaction, in index order
+    index_write_set: Vec<IndexKey>,
+    /// Changes to the index b-trees, computed once the rows are written
+    index_writes: Vec<IndexWrite>,
     write_row_state_machine: Option<StateMachine<WriteRowStateMachine>>,
     delete_row_state_machine: Option<StateMachine<DeleteRowStateMachine>>,
     commit_coordinator: Arc<CommitCoordinator>,
@@ -324,6 +543,13 @@ impl<Clock: LogicalClock> Debug for CommitStateMachine<Clock> {
     }
 }
 
+/// A change of a committing transaction to an index b-tree.
+struct IndexWrite {
+    key: IndexKey,
+    row: Row,
+    delete: bool,
+}
+
 pub struct WriteRowStateMachine {
     state: WriteRowState,
     is_finalized: bool,
@@ -331,6 +557,8 @@ pub struct WriteRowStateMachine {
     record: Option<ImmutableRecord>,
     cursor: Arc<RwLock<BTreeCursor>>,
     requires_seek: bool,
+    /// Whether the row is an entry of an index b-tree.
+    is_index: bool,
 }
 
 #[derive(Debug)]
@@ -344,6 +572,8 @@ pub struct DeleteRowStateMachine {
     state: DeleteRowState,
     is_finalized: bool,
     rowid: RowID,
+    /// The record of the entry to delete from an index b-tree.
+    record: Option<ImmutableRecord>,
     cursor: Arc<RwLock<BTreeCursor>>,
 }
 
@@ -363,6 +593,8 @@ impl<Clock: LogicalClock> CommitStateMachine<Clock> {
             tx_id,
             connection,
             write_set: Vec::new(),
+            index_write_set: Vec::new(),
+            index_writes: Vec::new(),
             write_row_state_machine: None,
             delete_row_state_machine: None,
             commit_coordinator,
@@ -371,10 +603,47 @@ impl<Clock: LogicalClock> CommitStateMachine<Clock> {
             _phantom: PhantomData,
         }
     }
+
+    /// Computes the changes of the transaction to the index b-trees. An entry is deleted if the
+    /// transaction deleted the version it could see, and then written if the transaction
+    /// inserted a version it did not delete. Both happen when the record of a WITHOUT ROWID
+    /// table changed.
+    fn collect_index_writes(&self, mvcc_store: &MvStore<Clock>) -> Vec<IndexWrite> {
+        let tx_id = TxTimestampOrID::TxID(self.tx_id);
+        let mut index_writes = Vec::new();
+        for key in &self.index_write_set {
+            let Some(row_versions) = mvcc_store.index_rows.get(key) else {
+                continue;
+            };
+            let row_versions = row_versions.value().read();
+            if let Some(deleted) = row_versions
+                .iter()
+                .find(|rv| rv.begin != tx_id && rv.end.as_ref() == Some(&tx_id))
+            {
+                index_writes.push(IndexWrite {
+                    key: key.clone(),
+                    row: deleted.row.clone(),
+                    delete: true,
+                });
+            }
+            if let Some(inserted) = row_versions
+                .iter()
+                .find(|rv| rv.begin == tx_id && rv.end.is_none())
+            {
+                index_writes.push(IndexWrite {
+                    key: key.clone(),
+                    row: inserted.row.clone(),
+                    delete: false,
+                });
+            }
+        }
+        index_writes
+    }
 }
 
 impl WriteRowStateMachine {
     fn new(row: Row, cursor: Arc<RwLock<BTreeCursor>>, requires_seek: bool) -> Self {
+        let is_index = cursor.read().index_info.is_some();
         Self {
             state: WriteRowState::Initial,
             is_finalized: false,
@@ -382,6 +651,7 @@ impl WriteRowStateMachine {
             record: None,
             cursor,
             requires_seek,
+            is_index,
         }
     }
 }
@@ -491,7 +761,13 @@ impl<Clock: LogicalClock> StateTransition for CommitStateMachine<Clock> {
                     .extend(tx.write_set.iter().map(|v| *v.value()));
                 self.write_set
                     .sort_by(|a, b| a.table_id.cmp(&b.table_id).then(a.row_id.cmp(&b.row_id)));
-                if self.write_set.is_empty() {
+                self.index_write_set
+                    .extend(tx.index_write_set.iter().map(|v| v.value().clone()));
+                if mvcc_store.is_serializable() {
+                    // Read-only transactions are validated too, as their reads may complete a cycle
+                    mvcc_store.validate_serializable(tx, end_ts)?;
+                }
+                if self.write_set.is_empty() && self.index_write_set.is_empty() {
                     tx.state.store(TransactionState::Committed(end_ts));
                     if mvcc_store.is_exclusive_tx(&self.tx_id) {
                         mvcc_store.release_exclusive_tx(&self.tx_id);
@@ -506,7 +782,11 @@ impl<Clock: LogicalClock> StateTransition for CommitStateMachine<Clock> {
                     self.finalize(mvcc_store)?;
                     return Ok(TransitionResult::Done(()));
                 }
-                self.state = CommitState::BeginPagerTxn { end_ts };
+                if !mvcc_store.is_exclusive_tx(&self.tx_id) && mvcc_store.has_exclusive_tx() {
+                    // There is an exclusive transaction holding the write lock. We must abort.
+                    return Err(LimboError::WriteWriteConflict);
+                }
+                self.state = CommitState::LogTx { end_ts };
                 Ok(TransitionResult::Continue)
             }
             CommitState::BeginPagerTxn { end_ts } => {
@@ -525,9 +805,6 @@ impl<Clock: LogicalClock> StateTransition for CommitStateMachine<Clock> {
                         requires_seek: true,
                     };
                     return Ok(TransitionResult::Continue);
-                } else if mvcc_store.has_exclusive_tx() {
-                    // There is an exclusive transaction holding the write lock. We must abort.
-                    return Err(LimboError::WriteWriteConflict);
                 }
                 // Currently txns are queued without any heuristics whasoever. This is important because
                 // we need to ensure writes to disk happen sequentially.
@@ -536,6 +813,8 @@ impl<Clock: LogicalClock> StateTransition for CommitStateMachine<Clock> {
                 // If any of these were to happen, we would find ourselves in a bad corruption situation.
 
                 // NOTE: since we are blocking for `begin_write_tx` we do not care about re-entrancy right now.
+                // The transaction is logged already, so it waits for an exclusive transaction
+                // to release the lock rather than aborting.
                 let locked = self.commit_coordinator.pager_commit_lock.write();
                 if !locked {
                     self.commit_coordinator
@@ -564,9 +843,11 @@ impl<Clock: LogicalClock> StateTransition for CommitStateMachine<Clock> {
                 }
                 let result = self.pager.io.block(|| self.pager.begin_write_tx())?;
                 if let crate::result::LimboResult::Busy = result {
-                    // There is a non-CONCURRENT transaction holding the write lock. We must abort.
+                    // Another process holds the write lock. Wait for it like for the commit lock.
                     self.commit_coordinator.pager_commit_lock.unlock();
-                    return Err(LimboError::WriteWriteConflict);
+                    return Ok(TransitionResult::Io(crate::types::IOCompletions::Single(
+                        Completion::new_dummy(),
+                    )));
                 }
                 self.state = CommitState::WriteRow {
                     end_ts,
@@ -581,71 +862,84 @@ impl<Clock: LogicalClock> StateTransition for CommitStateMachine<Clock> {
                 requires_seek,
             } => {
                 if write_set_index == self.write_set.len() {
-                    self.state = CommitState::CommitPagerTxn { end_ts };
+                    self.index_writes = self.collect_index_writes(mvcc_store);
+                    self.state = CommitState::WriteIndexEntry {
+                        end_ts,
+                        index_write_index: 0,
+                    };
                     return Ok(TransitionResult::Continue);
                 }
                 let id = &self.write_set[write_set_index];
                 if let Some(row_versions) = mvcc_store.rows.get(id) {
                     let row_versions = row_versions.value().read();
-                    // Find rows that were written by this transaction
-                    for row_version in row_versions.iter() {
-                        if let TxTimestampOrID::TxID(row_tx_id) = row_version.begin {
-                            if row_tx_id == self.tx_id {
-                                let cursor = if let Some(cursor) = self.cursors.get(&id.table_id) {
-                                    cursor.clone()
-                                } else {
-                                    let cursor = BTreeCursor::new_table(
-                                        None, // Write directly to B-tree
-                                        self.pager.clone(),
-                                        id.table_id as usize,
-                                        row_version.row.column_count,
-                                    );
-                                    let cursor = Arc::new(RwLock::new(cursor));
-                                    self.cursors.insert(id.table_id, cursor.clone());
-                                    cursor
-                                };
-                                let state_machine = mvcc_store.write_row_to_pager(
-                                    &row_version.row,
-                                    cursor,
-                                    requires_seek,
-                                )?;
-                                self.write_row_state_machine = Some(state_machine);
-
-                                self.state = CommitState::WriteRowStateMachine {
-                                    end_ts,
-                                    write_set_index,
-                                };
-                                break;
-                            }
-                        }
-                        if let Some(TxTimestampOrID::TxID(row_tx_id)) = row_version.end {
-                            if row_tx_id == self.tx_id {
-                                let column_count = row_version.row.column_count;
-                                let cursor = if let Some(cursor) = self.cursors.get(&id.table_id) {
-                                    cursor.clone()
-                                } else {
-                                    let cursor = BTreeCursor::new_table(
-                                        None, // Write directly to B-tree
-                                        self.pager.clone(),
-                                        id.table_id as usize,
-                                        column_count,
-                                    );
-                                    let cursor = Arc::new(RwLock::new(cursor));
-                                    self.cursors.insert(id.table_id, cursor.clone());
-                                    cursor
-                                };
-                                let state_machine =
-                                    mvcc_store.delete_row_from_pager(row_version.row.id, cursor)?;
-                                self.delete_row_state_machine = Some(state_machine);
-                                self.state = CommitState::DeleteRowStateMachine {
-                                    end_ts,
-                                    write_set_index,
-                                };
-                                break;
-                            }
-                        }
+                    let tx_id = TxTimestampOrID::TxID(self.tx_id);
+                    // The row is written with the version the transaction inserted, unless it
+                    // deleted that version as well, or deleted if the transaction deleted the
+                    // version it could see.
+                    let inserted = row_versions
+                        .iter()
+                        .find(|rv| rv.begin == tx_id && rv.end.is_none());
+                    let deleted = row_versions
+                        .iter()
+                        .find(|rv| rv.end.as_ref() == Some(&tx_id));
+                    if let Some(row_version) = inserted {
+                        let cursor = if let Some(cursor) = self.cursors.get(&id.table_id) {
+                            cursor.clone()
+                        } else {
+                            let cursor = BTreeCursor::new_table(
+                                None, // Write directly to B-tree
+                                self.pager.clone(),
+                                id.table_id as usize,
+                                row_version.row.column_count,
+                            );
+                            let cursor = Arc::new(RwLock::new(cursor));
+                            self.cursors.insert(id.table_id, cursor.clone());
+                            cursor
+                        };
+                        let state_machine = mvcc_store.write_row_to_pager(
+                            &row_version.row,
+                            cursor,
+                            requires_seek,
+                        )?;
+                        self.write_row_state_machine = Some(state_machine);
+
+                        self.state = CommitState::WriteRowStateMachine {
+                            end_ts,
+                            write_set_index,
+                        };
+                        return Ok(TransitionResult::Continue);
+                    }
+                    if let Some(row_version) = deleted {
+                        let column_count = row_version.row.column_count;
+                        let cursor = if let Some(cursor) = self.cursors.get(&id.table_id) {
+                            cursor.clone()
+                        } else {
+                            let cursor = BTreeCursor::new_table(
+                                None, // Write directly to B-tree
+                                self.pager.clone(),
+                                id.table_id as usize,
+                                column_count,
+                            );
+                            let cursor = Arc::new(RwLock::new(cursor));
+                            self.cursors.insert(id.table_id, cursor.clone());
+                            cursor
+                        };
+                        let state_machine =
+                            mvcc_store.delete_row_from_pager(row_version.row.id, cursor)?;
+                        self.delete_row_state_machine = Some(state_machine);
+                        self.state = CommitState::DeleteRowStateMachine {
+                            end_ts,
+                            write_set_index,
+                        };
+                        return Ok(TransitionResult::Continue);
                     }
                 }
+                // The transaction did not change the row in the end
+                self.state = CommitState::WriteRow {
+                    end_ts,
+                    write_set_index: write_set_index + 1,
+                    requires_seek: true,
+                };
                 Ok(TransitionResult::Continue)
             }
 
@@ -705,6 +999,118 @@ impl<Clock: LogicalClock> StateTransition for CommitStateMachine<Clock> {
                     }
                 }
             }
+            CommitState::WriteIndexEntry {
+                end_ts,
+                index_write_index,
+            } => {
+                let Some(write) = self.index_writes.get(index_write_index) else {
+                    self.state = CommitState::CommitPagerTxn { end_ts };
+                    return Ok(TransitionResult::Continue);
+                };
+                let index_id = write.key.index_id;
+                let cursor = if let Some(cursor) = self.cursors.get(&index_id) {
+                    cursor.clone()
+                } else {
+                    let cursor =
+                        index_btree_cursor(self.pager.clone(), index_id, &write.key.index_info);
+                    let cursor = Arc::new(RwLock::new(cursor));
+                    self.cursors.insert(index_id, cursor.clone());
+                    cursor
+                };
+                if write.delete {
+                    self.delete_row_state_machine = Some(StateMachine::new(
+                        DeleteRowStateMachine::new_index(&write.row, cursor),
+                    ));
+                } else {
+                    self.write_row_state_machine =
+                        Some(mvcc_store.write_row_to_pager(&write.row, cursor, true)?);
+                }
+                self.state = CommitState::IndexEntryStateMachine {
+                    end_ts,
+                    index_write_index,
+                };
+                Ok(TransitionResult::Continue)
+            }
+            CommitState::IndexEntryStateMachine {
+                end_ts,
+                index_write_index,
+            } => {
+                let result = if self.index_writes[index_write_index].delete {
+                    self.delete_row_state_machine.as_mut().unwrap().step(&())?
+                } else {
+                    self.write_row_state_machine.as_mut().unwrap().step(&())?
+                };
+                match result {
+                    TransitionResult::Io(io) => Ok(TransitionResult::Io(io)),
+                    TransitionResult::Continue => Ok(TransitionResult::Continue),
+                    TransitionResult::Done(_) => {
+                        self.state = CommitState::WriteIndexEntry {
+                            end_ts,
+                            index_write_index: index_write_index + 1,
+                        };
+                        Ok(TransitionResult::Continue)
+                    }
+                }
+            }
+            CommitState::LogTx { end_ts } => {
+                // The transaction is logged before it reaches the b-tree, so that a crash while
+                // the pager commits is recovered from by replaying the log. It is logged before
+                // taking the commit lock, so that the transactions committing at the same time
+                // share a write and a sync of the log.
+                let mut log_record = LogRecord::new(end_ts);
+                let committed = |ts_or_id: &TxTimestampOrID| match ts_or_id {
+                    TxTimestampOrID::TxID(id) if *id == self.tx_id => {
+                        Some(TxTimestampOrID::Timestamp(end_ts))
+                    }
+                    _ => None,
+                };
+                let mut log_versions = |row_versions: &RwLock<Vec<RowVersion>>| {
+                    for row_version in row_versions.read().iter() {
+                        let begin = committed(&row_version.begin);
+                        let end = row_version.end.as_ref().and_then(committed);
+                        if begin.is_none() && end.is_none() {
+                            continue;
+                        }
+                        log_record.row_versions.push(RowVersion {
+                            begin: begin.unwrap_or_else(|| row_version.begin.clone()),
+                            end: end.or_else(|| row_version.end.clone()),
+                            row: row_version.row.clone(),
+                        });
+                    }
+                };
+                for id in &self.write_set {
+                    if let Some(row_versions) = mvcc_store.rows.get(id) {
+                        log_versions(row_versions.value());
+                    }
+                }
+                // Index entries are logged like rows, with the index ID as their table ID
+                for key in &self.index_write_set {
+                    if let Some(row_versions) = mvcc_store.index_rows.get(key) {
+                        log_versions(row_versions.value());
+                    }
+                }
+                tracing::trace!("logged(tx_id={})", self.tx_id);
+                if log_record.row_versions.is_empty() {
+                    self.state = CommitState::BeginPagerTxn { end_ts };
+                    return Ok(TransitionResult::Continue);
+                }
+                // Like the WAL, the log is not synced when syncing is off
+                let sync = self.connection.get_sync_mode() != SyncMode::Off;
+                self.state = match mvcc_store.storage.log_tx(log_record, sync) {
+                    Some(write) => CommitState::WaitLog { end_ts, write },
+                    None => CommitState::BeginPagerTxn { end_ts },
+                };
+                Ok(TransitionResult::Continue)
+            }
+            CommitState::WaitLog { end_ts, ref write } => {
+                match mvcc_store.storage.poll_tx_log(write)? {
+                    Some(c) => Ok(TransitionResult::Io(crate::types::IOCompletions::Single(c))),
+                    None => {
+                        self.state = CommitState::BeginPagerTxn { end_ts };
+                        Ok(TransitionResult::Continue)
+                    }
+                }
+            }
             CommitState::CommitPagerTxn { end_ts } => {
                 // Write committed data to pager for persistence
                 // Flush dirty pages to WAL - this is critical for data persistence
@@ -726,9 +1132,11 @@ impl<Clock: LogicalClock> StateTransition for CommitStateMachine<Clock> {
                                 self.header.write().replace(*header);
                             })
                         })?;
-                        self.commit_coordinator.pager_commit_lock.unlock();
-                        // TODO: here mark we are ready for a batch
-                        self.state = CommitState::Commit { end_ts };
+                        self.state = if mvcc_store.storage.needs_truncation() {
+                            CommitState::TruncateLog { end_ts }
+                        } else {
+                            CommitState::ReleaseCommitLock { end_ts }
+                        };
                         return Ok(TransitionResult::Continue);
                     }
                     IOResult::IO(io) => {
@@ -736,38 +1144,47 @@ impl<Clock: LogicalClock> StateTransition for CommitStateMachine<Clock> {
                     }
                 }
             }
+            CommitState::TruncateLog { end_ts } => {
+                // The truncation is durable before the next batch is written, so that a crash
+                // cannot leave the records of the log before it behind the new ones
+                let sync = self.connection.get_sync_mode() != SyncMode::Off;
+                self.state = match mvcc_store.storage.truncate_tx_log(sync) {
+                    Some(write) => CommitState::WaitTruncatedLog { end_ts, write },
+                    None => CommitState::ReleaseCommitLock { end_ts },
+                };
+                Ok(TransitionResult::Continue)
+            }
+            CommitState::WaitTruncatedLog { end_ts, ref write } => {
+                match mvcc_store.storage.poll_tx_log(write) {
+                    Ok(Some(c)) => {
+                        return Ok(TransitionResult::Io(crate::types::IOCompletions::Single(c)))
+                    }
+                    Ok(None) => {}
+                    // The transaction is in the b-tree already. The log is rewound before the
+                    // next batch, and truncated again once it grows.
+                    Err(e) => tracing::warn!("truncating the logical log failed: {e}"),
+                }
+                self.state = CommitState::ReleaseCommitLock { end_ts };
+                Ok(TransitionResult::Continue)
+            }
+            CommitState::ReleaseCommitLock { end_ts } => {
+                self.commit_coordinator.pager_commit_lock.unlock();
+                // TODO: here mark we are ready for a batch
+                self.state = CommitState::Commit { end_ts };
+                Ok(TransitionResult::Continue)
+            }
             CommitState::Commit { end_ts } => {
-                let mut log_record = LogRecord::new(end_ts);
                 let tx = mvcc_store.txs.get(&self.tx_id).unwrap();
                 let tx_unlocked = tx.value();
                 tx_unlocked.state.store(TransactionState::Committed(end_ts));
                 for id in &self.write_set {
                     if let Some(row_versions) = mvcc_store.rows.get(id) {
-                        let mut row_versions = row_versions.value().write();
-                        for row_version in row_versions.iter_mut() {
-                            if let TxTimestampOrID::TxID(id) = row_version.begin {
-                                if id == self.tx_id {
-                                    // New version is valid STARTING FROM committing transaction's end timestamp
-                                    // See diagram on page 299: https://www.cs.cmu.edu/~15721-f24/papers/Hekaton.pdf
-                                    row_version.begin = TxTimestampOrID::Timestamp(end_ts);
-                                    mvcc_store.insert_version_raw(
-                                        &mut log_record.row_versions,
-                                        row_version.clone(),
-                                    ); // FIXME: optimize cloning out
-                                }
-                            }
-                            if let Some(TxTimestampOrID::TxID(id)) = row_version.end {
-                                if id == self.tx_id {
-                                    // Old version is valid UNTIL committing transaction's end timestamp
-                                    // See diagram on page 299: https://www.cs.cmu.edu/~15721-f24/papers/Hekaton.pdf
-                                    row_version.end = Some(TxTimestampOrID::Timestamp(end_ts));
-                                    mvcc_store.insert_version_raw(
-                                        &mut log_record.row_versions,
-                                        row_version.clone(),
-                                    ); // FIXME: optimize cloning out
-                                }
-                            }
-                        }
+                        commit_versions(&mut row_versions.value().write(), self.tx_id, end_ts);
+                    }
+                }
+                for key in &self.index_write_set {
+                    if let Some(row_versions) = mvcc_store.index_rows.get(key) {
+                        commit_versions(&mut row_versions.value().write(), self.tx_id, end_ts);
                     }
                 }
                 tracing::trace!("updated(tx_id={})", self.tx_id);
@@ -785,11 +1202,8 @@ impl<Clock: LogicalClock> StateTransition for CommitStateMachine<Clock> {
                 if mvcc_store.is_exclusive_tx(&self.tx_id) {
                     mvcc_store.release_exclusive_tx(&self.tx_id);
                 }
+                mvcc_store.maybe_collect_garbage();
 
-                if !log_record.row_versions.is_empty() {
-                    mvcc_store.storage.log_tx(log_record)?;
-                }
-                tracing::trace!("logged(tx_id={})", self.tx_id);
                 self.finalize(mvcc_store)?;
                 Ok(TransitionResult::Done(()))
             }
@@ -830,7 +1244,11 @@ impl StateTransition for WriteRowStateMachine {
             }
             WriteRowState::Seek => {
                 // Position the cursor by seeking to the row position
-                let seek_key = SeekKey::TableRowId(self.row.id.row_id);
+                let seek_key = if self.is_index {
+                    SeekKey::IndexKey(self.record.as_ref().unwrap())
+                } else {
+                    SeekKey::TableRowId(self.row.id.row_id)
+                };
 
                 match self
                     .cursor
@@ -848,7 +1266,11 @@ impl StateTransition for WriteRowStateMachine {
             }
             WriteRowState::Insert => {
                 // Insert the record into the B-tree
-                let key = BTreeKey::new_table_rowid(self.row.id.row_id, self.record.as_ref());
+                let key = if self.is_index {
+                    BTreeKey::new_index_key(self.record.as_ref().unwrap())
+                } else {
+                    BTreeKey::new_table_rowid(self.row.id.row_id, self.record.as_ref())
+                };
 
                 match self
                     .cursor
@@ -861,6 +1283,11 @@ impl StateTransition for WriteRowStateMachine {
                         return Ok(TransitionResult::Io(io));
                     }
                 }
+                if self.is_index {
+                    // Index entries are always written after a seek
+                    self.finalize(&())?;
+                    return Ok(TransitionResult::Done(()));
+                }
                 self.state = WriteRowState::Next;
                 Ok(TransitionResult::Continue)
             }
@@ -898,7 +1325,7 @@ impl StateTransition for DeleteRowStateMachine {
 
     #[tracing::instrument(fields(state = ?self.state), skip(self, _context))]
     fn step(&mut self, _context: &Self::Context) -> Result<TransitionResult<Self::SMResult>> {
-        use crate::types::{IOResult, SeekKey, SeekOp};
+        use crate::types::{IOResult, SeekKey, SeekOp, SeekResult};
 
         match self.state {
             DeleteRowState::Initial => {
@@ -906,17 +1333,25 @@ impl StateTransition for DeleteRowStateMachine {
                 Ok(TransitionResult::Continue)
             }
             DeleteRowState::Seek => {
-                let seek_key = SeekKey::TableRowId(self.rowid.row_id);
+                let seek_key = match &self.record {
+                    Some(record) => SeekKey::IndexKey(record),
+                    None => SeekKey::TableRowId(self.rowid.row_id),
+                };
 
                 match self
                     .cursor
                     .write()
                     .seek(seek_key, SeekOp::GE { eq_only: true })?
                 {
-                    IOResult::Done(_) => {
+                    IOResult::Done(SeekResult::Found) => {
                         self.state = DeleteRowState::Delete;
                         Ok(TransitionResult::Continue)
                     }
+                    IOResult::Done(_) => {
+                        // The row is not in the b-tree, e.g. a deletion replayed from the log
+                        self.finalize(&())?;
+                        Ok(TransitionResult::Done(()))
+                    }
                     IOResult::IO(io) => {
                         return Ok(TransitionResult::Io(io));
                     }
@@ -963,6 +1398,18 @@ impl DeleteRowStateMachine {
             state: DeleteRowState::Initial,
             is_finalized: false,
             rowid,
+            record: None,
+            cursor,
+        }
+    }
+
+    /// Deletes the index entry `row` from the index b-tree of `cursor`.
+    fn new_index(row: &Row, cursor: Arc<RwLock<BTreeCursor>>) -> Self {
+        Self {
+            state: DeleteRowState::Initial,
+            is_finalized: false,
+            rowid: row.id,
+            record: Some(ImmutableRecord::from_bin_record(row.data.clone())),
             cursor,
         }
     }
@@ -972,6 +1419,7 @@ impl DeleteRowStateMachine {
 #[derive(Debug)]
 pub struct MvStore<Clock: LogicalClock> {
     rows: SkipMap<RowID, RwLock<Vec<RowVersion>>>,
+    index_rows: SkipMap<IndexKey, RwLock<Vec<RowVersion>>>,
     txs: SkipMap<TxID, Transaction>,
     tx_ids: AtomicU64,
     next_rowid: AtomicU64,
@@ -987,6 +1435,20 @@ pub struct MvStore<Clock: LogicalClock> {
     exclusive_tx: RwLock<Option<TxID>>,
     commit_coordinator: Arc<CommitCoordinator>,
     header: Arc<RwLock<Option<DatabaseHeader>>>,
+    /// The memory the versions may take before they are checkpointed out of memory, in bytes.
+    /// Zero means there is no limit.
+    memory_limit: AtomicUsize,
+    /// Number of commits since the last garbage collection.
+    commits_since_gc: AtomicU64,
+    gc_metrics: RwLock<GcMetrics>,
+    /// Whether transactions run under serializable snapshot isolation.
+    serializable: bool,
+    /// The transactions validated under serializable isolation that may still conflict with an
+    /// active transaction. The lock also serializes the validations.
+    committed_txs: Mutex<Vec<CommittedTx>>,
+    /// Serializes the inserts into unique indexes, so that two transactions cannot both miss
+    /// the entry the other one is inserting.
+    unique_insert_lock: Mutex<()>,
 }
 
 impl<Clock: LogicalClock> MvStore<Clock> {
@@ -994,6 +1456,7 @@ impl<Clock: LogicalClock> MvStore<Clock> {
     pub fn new(clock: Clock, storage: Storage) -> Self {
         Self {
             rows: SkipMap::new(),
+            index_rows: SkipMap::new(),
             txs: SkipMap::new(),
             tx_ids: AtomicU64::new(1), // let's reserve transaction 0 for special purposes
             next_rowid: AtomicU64::new(0), // TODO: determine this from B-Tree
@@ -1006,9 +1469,26 @@ impl<Clock: LogicalClock> MvStore<Clock> {
                 commits_waiting: Arc::new(AtomicU64::new(0)),
             }),
             header: Arc::new(RwLock::new(None)),
+            memory_limit: AtomicUsize::new(0),
+            commits_since_gc: AtomicU64::new(0),
+            gc_metrics: RwLock::new(GcMetrics::default()),
+            serializable: false,
+            committed_txs: Mutex::new(Vec::new()),
+            unique_insert_lock: Mutex::new(()),
         }
     }
 
+    /// Makes transactions run under serializable snapshot isolation, which aborts the ones that
+    /// could otherwise commit a result no serial execution of the transactions would produce.
+    pub fn with_serializable(mut self, serializable: bool) -> Self {
+        self.serializable = serializable;
+        self
+    }
+
+    pub fn is_serializable(&self) -> bool {
+        self.serializable
+    }
+
     pub fn get_next_rowid(&self) -> i64 {
         self.next_rowid.fetch_add(1, Ordering::SeqCst) as i64
     }
@@ -1148,6 +1628,8 @@ impl<Clock: LogicalClock> MvStore<Clock> {
         let tx = self.txs.get(&tx_id).unwrap();
         let tx = tx.value();
         assert_eq!(tx.state, TransactionState::Active);
+        // A row that is not found is read too, as a concurrent insert of it would change the result
+        tx.insert_to_read_set(id);
         if let Some(row_versions) = self.rows.get(&id) {
             let row_versions = row_versions.value().read();
             if let Some(rv) = row_versions
@@ -1155,13 +1637,34 @@ impl<Clock: LogicalClock> MvStore<Clock> {
                 .rev()
                 .find(|rv| rv.is_visible_to(tx, &self.txs))
             {
-                tx.insert_to_read_set(id);
                 return Ok(Some(rv.row.clone()));
             }
         }
         Ok(None)
     }
 
+    /// Records that the transaction looked up the row `id`, whether or not it exists, so that
+    /// serializable isolation can tell when a concurrent transaction writes it.
+    pub fn record_read(&self, tx_id: TxID, id: RowID) {
+        if !self.serializable {
+            return;
+        }
+        if let Some(tx) = self.txs.get(&tx_id) {
+            tx.value().insert_to_read_set(id);
+        }
+    }
+
+    /// Records that the transaction scanned the table or index `id`, so that serializable
+    /// isolation can tell when a concurrent transaction writes to it.
+    pub fn record_scan(&self, tx_id: TxID, id: u64) {
+        if !self.serializable {
+            return;
+        }
+        if let Some(tx) = self.txs.get(&tx_id) {
+            tx.value().insert_to_scan_set(id);
+        }
+    }
+
     /// Gets all row ids in the database.
     pub fn scan_row_ids(&self) -> Result<Vec<RowID>> {
         tracing::trace!("scan_row_ids");
@@ -1275,6 +1778,196 @@ impl<Clock: LogicalClock> MvStore<Clock> {
         }
     }
 
+    /// Counts the rows of the table `table_id` that are visible to the transaction `tx_id`.
+    pub fn count_rows(&self, table_id: u64, tx_id: TxID) -> usize {
+        let tx = self.txs.get(&tx_id).unwrap();
+        let tx = tx.value();
+        self.rows
+            .range(RowID::new(table_id, i64::MIN)..=RowID::new(table_id, i64::MAX))
+            .filter_map(|row| self.find_last_visible_version(tx, row))
+            .count()
+    }
+
+    /// Inserts an index entry within the transaction `tx_id`. The version of the entry that is
+    /// visible to the transaction, if any, is replaced.
+    ///
+    /// For a unique index, `unique_columns` is the number of leading columns of the entries
+    /// that must be unique, see [Self::check_unique_index_entry].
+    pub fn insert_index_entry(
+        &self,
+        tx_id: TxID,
+        key: IndexKey,
+        row: Row,
+        unique_columns: Option<usize>,
+    ) -> Result<()> {
+        tracing::trace!("insert_index_entry(tx_id={}, key={:?})", tx_id, key);
+        self.delete_index_entry(tx_id, &key)?;
+        let tx = self
+            .txs
+            .get(&tx_id)
+            .ok_or(LimboError::NoSuchTransactionID(tx_id.to_string()))?;
+        let tx = tx.value();
+        assert_eq!(tx.state, TransactionState::Active);
+        let _unique_insert_guard = match unique_columns {
+            Some(unique_columns) => {
+                let guard = self.unique_insert_lock.lock();
+                self.check_unique_index_entry(tx, &key, unique_columns)?;
+                Some(guard)
+            }
+            None => None,
+        };
+        let row_version = RowVersion {
+            begin: TxTimestampOrID::TxID(tx.tx_id),
+            end: None,
+            row,
+        };
+        tx.insert_to_index_write_set(key.clone());
+        self.insert_index_version(key, row_version);
+        Ok(())
+    }
+
+    /// Fails with a write-write conflict if another transaction inserted an entry of the index
+    /// with the same `unique_columns` leading columns as `key` that `tx` cannot see: one that is
+    /// not committed yet, or that was committed after `tx` began. The statement already checked
+    /// the entries `tx` can see, like with a b-tree. Entries with a NULL in those columns are
+    /// never duplicates.
+    fn check_unique_index_entry(
+        &self,
+        tx: &Transaction,
+        key: &IndexKey,
+        unique_columns: usize,
+    ) -> Result<()> {
+        let values = key.record.get_values();
+        let prefix = &values[..unique_columns.min(values.len())];
+        if prefix
+            .iter()
+            .any(|value| matches!(value, crate::types::RefValue::Null))
+        {
+            return Ok(());
+        }
+        let prefix = prefix
+            .iter()
+            .map(|value| value.to_owned())
+            .collect::<Vec<_>>();
+        let record = ImmutableRecord::from_values(&prefix, prefix.len());
+        let first = key.seek_key(record.clone(), std::cmp::Ordering::Less);
+        let last = key.seek_key(record, std::cmp::Ordering::Greater);
+        for entry in self.index_rows.range(first..last) {
+            for rv in entry.value().read().iter() {
+                if rv.is_visible_to(tx, &self.txs) {
+                    continue;
+                }
+                let inserted_by_other = match rv.begin {
+                    TxTimestampOrID::TxID(id) => {
+                        id != tx.tx_id
+                            && self.txs.get(&id).is_some_and(|other| {
+                                !matches!(
+                                    other.value().state.load(),
+                                    TransactionState::Aborted | TransactionState::Terminated
+                                )
+                            })
+                    }
+                    TxTimestampOrID::Timestamp(ts) => ts > tx.begin_ts,
+                };
+                let deleted = matches!(rv.end, Some(TxTimestampOrID::Timestamp(_)));
+                if inserted_by_other && !deleted {
+                    return Err(LimboError::WriteWriteConflict);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Deletes the version of an index entry that is visible to the transaction `tx_id`.
+    ///
+    /// Returns `true` if the entry was deleted, and `false` if the transaction could not see it.
+    pub fn delete_index_entry(&self, tx_id: TxID, key: &IndexKey) -> Result<bool> {
+        tracing::trace!("delete_index_entry(tx_id={}, key={:?})", tx_id, key);
+        let Some(row_versions) = self.index_rows.get(key) else {
+            return Ok(false);
+        };
+        let tx = self
+            .txs
+            .get(&tx_id)
+            .ok_or(LimboError::NoSuchTransactionID(tx_id.to_string()))?;
+        let tx = tx.value();
+        assert_eq!(tx.state, TransactionState::Active);
+        let mut row_versions = row_versions.value().write();
+        let Some(rv) = row_versions
+            .iter_mut()
+            .rev()
+            .find(|rv| rv.is_visible_to(tx, &self.txs))
+        else {
+            return Ok(false);
+        };
+        if is_write_write_conflict(&self.txs, tx, rv) {
+            return Err(LimboError::WriteWriteConflict);
+        }
+        rv.end = Some(TxTimestampOrID::TxID(tx.tx_id));
+        drop(row_versions);
+        tx.insert_to_index_write_set(key.clone());
+        Ok(true)
+    }
+
+    /// Finds the first index entry that is visible to the transaction `tx_id`, starting from
+    /// `bound` and moving forwards or backwards through the entries of index `index_id`.
+    ///
+    /// Returns the key of the entry and the version the transaction can see.
+    pub fn seek_index_entry(
+        &self,
+        index_id: u64,
+        bound: Bound<&IndexKey>,
+        forwards: bool,
+        tx_id: TxID,
+    ) -> Option<(IndexKey, Row)> {
+        tracing::trace!(
+            "seek_index_entry(index_id={}, bound={:?}, forwards={})",
+            index_id,
+            bound,
+            forwards
+        );
+        let tx = self.txs.get(&tx_id).unwrap();
+        let tx = tx.value();
+        if forwards {
+            self.index_rows
+                .range((bound, Bound::Unbounded))
+                .take_while(|entry| entry.key().index_id == index_id)
+                .find_map(|entry| self.find_visible_index_version(tx, entry))
+        } else {
+            self.index_rows
+                .range((Bound::Unbounded, bound))
+                .rev()
+                .take_while(|entry| entry.key().index_id == index_id)
+                .find_map(|entry| self.find_visible_index_version(tx, entry))
+        }
+    }
+
+    /// Counts the entries of the index `index_id` that are visible to the transaction `tx_id`,
+    /// starting from `first`.
+    pub fn count_index_entries(&self, index_id: u64, first: &IndexKey, tx_id: TxID) -> usize {
+        let tx = self.txs.get(&tx_id).unwrap();
+        let tx = tx.value();
+        self.index_rows
+            .range((Bound::Included(first), Bound::Unbounded))
+            .take_while(|entry| entry.key().index_id == index_id)
+            .filter_map(|entry| self.find_visible_index_version(tx, entry))
+            .count()
+    }
+
+    fn find_visible_index_version(
+        &self,
+        tx: &Transaction,
+        entry: crossbeam_skiplist::map::Entry<'_, IndexKey, RwLock<Vec<RowVersion>>>,
+    ) -> Option<(IndexKey, Row)> {
+        entry
+            .value()
+            .read()
+            .iter()
+            .rev()
+            .find(|version| version.is_visible_to(tx, &self.txs))
+            .map(|version| (entry.key().clone(), version.row.clone()))
+    }
+
     /// Begins an exclusive write transaction that prevents concurrent writes.
     ///
     /// This is used for IMMEDIATE and EXCLUSIVE transaction types where we need
@@ -1426,6 +2119,11 @@ impl<Clock: LogicalClock> MvStore<Clock> {
         tx.state.store(TransactionState::Aborted);
         tracing::trace!("abort(tx_id={})", tx_id);
         let write_set: Vec<RowID> = tx.write_set.iter().map(|v| *v.value()).collect();
+        let index_write_set: Vec<IndexKey> = tx
+            .index_write_set
+            .iter()
+            .map(|v| v.value().clone())
+            .collect();
 
         let pager_rollback_done = if self.is_exclusive_tx(&tx_id) {
             self.commit_coordinator.pager_commit_lock.unlock();
@@ -1437,20 +2135,10 @@ impl<Clock: LogicalClock> MvStore<Clock> {
         };
 
         for ref id in write_set {
-            if let Some(row_versions) = self.rows.get(id) {
-                let mut row_versions = row_versions.value().write();
-                for rv in row_versions.iter_mut() {
-                    if rv.end == Some(TxTimestampOrID::TxID(tx_id)) {
-                        // undo deletions by this transaction
-                        rv.end = None;
-                    }
-                }
-                // remove insertions by this transaction
-                row_versions.retain(|rv| rv.begin != TxTimestampOrID::TxID(tx_id));
-                if row_versions.is_empty() {
-                    self.rows.remove(id);
-                }
-            }
+            rollback_versions(&self.rows, id, tx_id);
+        }
+        for ref key in index_write_set {
+            rollback_versions(&self.index_rows, key, tx_id);
         }
 
         let tx = tx_unlocked.value();
@@ -1462,10 +2150,130 @@ impl<Clock: LogicalClock> MvStore<Clock> {
         // FIXME: verify that we can already remove the transaction here!
         // Maybe it's fine for snapshot isolation, but too early for serializable?
         self.txs.remove(&tx_id);
+        if self.serializable {
+            // The transaction may have been validated before its commit failed
+            self.committed_txs
+                .lock()
+                .retain(|committed| committed.tx_id != tx_id);
+        }
 
         Ok(())
     }
 
+    /// Records the changes made so far by the transaction identified by `tx_id`, so that it can
+    /// later be rolled back to this point with [MvStore::rollback_to_savepoint].
+    pub fn savepoint(&self, tx_id: TxID) -> Result<MvccSavepoint> {
+        let tx = self
+            .txs
+            .get(&tx_id)
+            .ok_or(LimboError::NoSuchTransactionID(tx_id.to_string()))?;
+        let tx = tx.value();
+        let tx_id_ref = TxTimestampOrID::TxID(tx_id);
+        let own_versions = |versions: &RwLock<Vec<RowVersion>>| -> Vec<RowVersion> {
+            versions
+                .read()
+                .iter()
+                .filter(|rv| rv.begin == tx_id_ref || rv.end.as_ref() == Some(&tx_id_ref))
+                .cloned()
+                .collect()
+        };
+        let mut row_versions = HashMap::new();
+        for id in tx.write_set.iter() {
+            let id = *id.value();
+            let Some(versions) = self.rows.get(&id) else {
+                continue;
+            };
+            row_versions.insert(id, own_versions(versions.value()));
+        }
+        let mut index_versions = BTreeMap::new();
+        for key in tx.index_write_set.iter() {
+            let Some(versions) = self.index_rows.get(key.value()) else {
+                continue;
+            };
+            index_versions.insert(key.value().clone(), own_versions(versions.value()));
+        }
+        Ok(MvccSavepoint {
+            tx_id,
+            row_versions,
+            index_versions,
+        })
+    }
+
+    /// Undoes the changes made by the transaction identified by `tx_id` since `savepoint` was
+    /// taken, or all of its changes if the savepoint predates the transaction. The transaction
+    /// itself stays active.
+    pub fn rollback_to_savepoint(
+        &self,
+        tx_id: TxID,
+        savepoint: Option<&MvccSavepoint>,
+    ) -> Result<()> {
+        let tx = self
+            .txs
+            .get(&tx_id)
+            .ok_or(LimboError::NoSuchTransactionID(tx_id.to_string()))?;
+        let tx = tx.value();
+        assert_eq!(tx.state, TransactionState::Active);
+        tracing::trace!("rollback_to_savepoint(tx_id={})", tx_id);
+        let savepoint = savepoint.filter(|savepoint| savepoint.tx_id == tx_id);
+        let write_set: Vec<RowID> = tx.write_set.iter().map(|v| *v.value()).collect();
+        for id in write_set {
+            let saved = savepoint.and_then(|savepoint| savepoint.row_versions.get(&id));
+            self.restore_versions(&self.rows, &id, tx_id, saved);
+            if saved.is_none() {
+                tx.write_set.remove(&id);
+            }
+        }
+        let index_write_set: Vec<IndexKey> = tx
+            .index_write_set
+            .iter()
+            .map(|v| v.value().clone())
+            .collect();
+        for key in index_write_set {
+            let saved = savepoint.and_then(|savepoint| savepoint.index_versions.get(&key));
+            self.restore_versions(&self.index_rows, &key, tx_id, saved);
+            if saved.is_none() {
+                tx.index_write_set.remove(&key);
+            }
+        }
+        Ok(())
+    }
+
+    /// Undoes the changes of the transaction `tx_id` to the versions of `id`, and restores the
+    /// versions it had inserted or deleted when `saved` was recorded.
+    fn restore_versions<K: Ord + Send + 'static>(
+        &self,
+        versions: &SkipMap<K, RwLock<Vec<RowVersion>>>,
+        id: &K,
+        tx_id: TxID,
+        saved: Option<&Vec<RowVersion>>,
+    ) {
+        let tx_id_ref = TxTimestampOrID::TxID(tx_id);
+        let Some(row_versions) = versions.get(id) else {
+            return;
+        };
+        let mut row_versions = row_versions.value().write();
+        for rv in row_versions.iter_mut() {
+            if rv.end.as_ref() == Some(&tx_id_ref) {
+                rv.end = None;
+            }
+        }
+        row_versions.retain(|rv| rv.begin != tx_id_ref);
+        for saved_rv in saved.into_iter().flatten() {
+            if saved_rv.begin == tx_id_ref {
+                self.insert_version_raw(&mut row_versions, saved_rv.clone());
+            } else if let Some(rv) = row_versions
+                .iter_mut()
+                .find(|rv| rv.begin == saved_rv.begin && rv.row == saved_rv.row)
+            {
+                rv.end = saved_rv.end.clone();
+            }
+        }
+        if row_versions.is_empty() {
+            drop(row_versions);
+            versions.remove(id);
+        }
+    }
+
     /// Returns true if the given transaction is the exclusive transaction.
     fn is_exclusive_tx(&self, tx_id: &TxID) -> bool {
         self.exclusive_tx.read().as_ref() == Some(tx_id)
@@ -1505,8 +2313,7 @@ impl<Clock: LogicalClock> MvStore<Clock> {
         self.clock.get_timestamp()
     }
 
-    /// Removes unused row  versions with very loose heuristics,
-    /// which sometimes leaves versions intact for too long.
+    /// Removes the row versions that no transaction can see anymore.
     /// Returns the number of removed versions.
     pub fn drop_unused_row_versions(&self) -> usize {
         tracing::trace!(
@@ -1514,26 +2321,26 @@ impl<Clock: LogicalClock> MvStore<Clock> {
             self.txs.len(),
             self.rows.len()
         );
+        let oldest_active_ts = self.oldest_active_tx_timestamp();
+        self.drop_unused_versions(&self.rows, oldest_active_ts)
+            + self.drop_unused_versions(&self.index_rows, oldest_active_ts)
+    }
+
+    fn drop_unused_versions<K: Ord + Clone + Debug + Send + 'static>(
+        &self,
+        versions: &SkipMap<K, RwLock<Vec<RowVersion>>>,
+        oldest_active_ts: Option<u64>,
+    ) -> usize {
         let mut dropped = 0;
         let mut to_remove = Vec::new();
-        for entry in self.rows.iter() {
+        for entry in versions.iter() {
             let mut row_versions = entry.value().write();
             row_versions.retain(|rv| {
-                // FIXME: should take rv.begin into account as well
                 let should_stay = match rv.end {
+                    // A transaction that began before this row version ended can still see it.
+                    // If the oldest active transaction began after it ended, none can.
                     Some(TxTimestampOrID::Timestamp(version_end_ts)) => {
-                        // a transaction started before this row version ended, ergo row version is needed
-                        // NOTICE: O(row_versions x transactions), but also lock-free, so sounds acceptable
-                        self.txs.iter().any(|tx| {
-                            let tx = tx.value();
-                            // FIXME: verify!
-                            match tx.state.load() {
-                                TransactionState::Active | TransactionState::Preparing => {
-                                    version_end_ts > tx.begin_ts
-                                }
-                                _ => false,
-                            }
-                        })
+                        oldest_active_ts.is_some_and(|begin_ts| version_end_ts > begin_ts)
                     }
                     // Let's skip potentially complex logic if the transafction is still
                     // active/tracked. We will drop the row version when the transaction
@@ -1554,27 +2361,343 @@ impl<Clock: LogicalClock> MvStore<Clock> {
                 should_stay
             });
             if row_versions.is_empty() {
-                to_remove.push(*entry.key());
+                to_remove.push(entry.key().clone());
             }
         }
         for id in to_remove {
-            self.rows.remove(&id);
+            versions.remove(&id);
+        }
+        dropped
+    }
+
+    /// Validates a transaction that runs under serializable isolation before it commits.
+    ///
+    /// Following Serializable Snapshot Isolation, a transaction has an rw-antidependency on a
+    /// concurrent transaction when it read something the other one wrote. A cycle in the
+    /// serialization graph always contains a transaction with both an incoming and an outgoing
+    /// rw-antidependency, so the transaction is aborted with [LimboError::SerializationFailure]
+    /// when committing it would complete such a structure. Reads are tracked per row and scans
+    /// per table or index, so some aborts are false positives; the transaction can be retried.
+    fn validate_serializable(&self, tx: &Transaction, end_ts: u64) -> Result<()> {
+        let mut committed_txs = self.committed_txs.lock();
+        // The transactions that committed before every active transaction began cannot conflict anymore
+        if let Some(oldest_ts) = self.oldest_active_tx_timestamp() {
+            committed_txs.retain(|committed| committed.end_ts > oldest_ts);
+        }
+        let (write_set, written) = tx.writes();
+        let mut in_conflict = tx.in_conflict.load(Ordering::SeqCst);
+        let mut out_conflict = tx.out_conflict.load(Ordering::SeqCst);
+        // Whether a concurrent transaction that committed already has both rw-antidependencies
+        let mut committed_pivot = false;
+        let mut writers = Vec::new();
+        let mut readers = Vec::new();
+        for (i, committed) in committed_txs.iter().enumerate() {
+            if committed.end_ts <= tx.begin_ts {
+                continue;
+            }
+            if tx.reads_any(&committed.write_set, &committed.written) {
+                out_conflict = true;
+                committed_pivot |= committed.out_conflict;
+                writers.push(i);
+            }
+            if committed.reads_any(&write_set, &written) {
+                in_conflict = true;
+                committed_pivot |= committed.in_conflict;
+                readers.push(i);
+            }
+        }
+        // The writes of the active transactions are checked when they commit
+        let active_readers: Vec<_> = self
+            .txs
+            .iter()
+            .filter(|entry| {
+                let other = entry.value();
+                other.tx_id != tx.tx_id
+                    && other.state == TransactionState::Active
+                    && other.reads_any(&write_set, &written)
+            })
+            .collect();
+        in_conflict |= !active_readers.is_empty();
+        if committed_pivot || (in_conflict && out_conflict) {
+            tracing::debug!(
+                "validate_serializable(tx_id={}) failed: in_conflict={} out_conflict={}",
+                tx.tx_id,
+                in_conflict,
+                out_conflict
+            );
+            return Err(LimboError::SerializationFailure);
+        }
+        for i in writers {
+            committed_txs[i].in_conflict = true;
+        }
+        for i in readers {
+            committed_txs[i].out_conflict = true;
+        }
+        for entry in active_readers {
+            entry.value().out_conflict.store(true, Ordering::SeqCst);
+        }
+        committed_txs.push(CommittedTx {
+            tx_id: tx.tx_id,
+            end_ts,
+            read_set: tx.read_set.iter().map(|id| *id.value()).collect(),
+            scan_set: tx.scan_set.iter().map(|id| *id.value()).collect(),
+            write_set,
+            written,
+            in_conflict,
+            out_conflict,
+        });
+        Ok(())
+    }
+
+    /// Returns the begin timestamp of the oldest transaction that is still running, if any.
+    fn oldest_active_tx_timestamp(&self) -> Option<u64> {
+        self.txs
+            .iter()
+            .filter_map(|tx| {
+                let tx = tx.value();
+                match tx.state.load() {
+                    TransactionState::Active | TransactionState::Preparing => Some(tx.begin_ts),
+                    _ => None,
+                }
+            })
+            .min()
+    }
+
+    /// Collects garbage once every [GC_COMMIT_INTERVAL] commits, so that its cost is amortized
+    /// over the commits.
+    fn maybe_collect_garbage(&self) {
+        if self.commits_since_gc.fetch_add(1, Ordering::SeqCst) + 1 < GC_COMMIT_INTERVAL {
+            return;
+        }
+        self.commits_since_gc.store(0, Ordering::SeqCst);
+        self.collect_garbage();
+    }
+
+    /// Drops the row versions that no transaction can see anymore, and checkpoints the versions
+    /// out of memory if they still take more memory than the limit.
+    /// Returns the number of dropped versions.
+    pub fn collect_garbage(&self) -> usize {
+        let reclaimed = self.drop_unused_row_versions();
+        let limit = self.memory_limit();
+        let checkpointed = if limit > 0 && self.memory_usage() > limit {
+            self.checkpoint_versions()
+        } else {
+            0
+        };
+        tracing::debug!(
+            "collect_garbage() -> reclaimed: {}; checkpointed: {}",
+            reclaimed,
+            checkpointed
+        );
+        let mut metrics = self.gc_metrics.write();
+        metrics.collections += 1;
+        metrics.versions_reclaimed += reclaimed as u64;
+        metrics.versions_checkpointed += checkpointed as u64;
+        reclaimed + checkpointed
+    }
+
+    /// Drops from memory the versions of the tables and indexes that every active transaction
+    /// sees as they are in the b-tree: those that no active transaction opened, and whose last
+    /// change committed before the oldest active transaction began. The versions a transaction
+    /// commits are written to the b-tree before it completes, so the tables and indexes are
+    /// loaded from the b-tree again when they are next accessed.
+    /// Returns the number of dropped versions.
+    fn checkpoint_versions(&self) -> usize {
+        // Transactions that open a table meanwhile wait for the lock before looking it up
+        let mut loaded_tables = self.loaded_tables.write();
+        let oldest_active_ts = self.oldest_active_tx_timestamp();
+        let mut kept = HashSet::new();
+        for tx in self.txs.iter() {
+            let tx = tx.value();
+            if matches!(
+                tx.state.load(),
+                TransactionState::Active | TransactionState::Preparing
+            ) {
+                kept.extend(tx.used_tables.iter().map(|id| *id.value()));
+            }
         }
+        let committed_before_oldest = |ts: &TxTimestampOrID| match ts {
+            TxTimestampOrID::Timestamp(ts) => oldest_active_ts.is_none_or(|oldest| *ts <= oldest),
+            TxTimestampOrID::TxID(_) => false,
+        };
+        let in_btree = |rv: &RowVersion| {
+            committed_before_oldest(&rv.begin)
+                && rv.end.as_ref().is_none_or(committed_before_oldest)
+        };
+        for entry in self.rows.iter() {
+            if !entry.value().read().iter().all(in_btree) {
+                kept.insert(entry.key().table_id);
+            }
+        }
+        for entry in self.index_rows.iter() {
+            if !entry.value().read().iter().all(in_btree) {
+                kept.insert(entry.key().index_id);
+            }
+        }
+        let checkpointed: HashSet<u64> = loaded_tables
+            .iter()
+            .filter(|id| !kept.contains(id))
+            .copied()
+            .collect();
+        let dropped = remove_versions(&self.rows, |id| checkpointed.contains(&id.table_id))
+            + remove_versions(&self.index_rows, |key| checkpointed.contains(&key.index_id));
+        loaded_tables.retain(|id| !checkpointed.contains(id));
         dropped
     }
 
-    pub fn recover(&self) -> Result<()> {
-        let tx_log = self.storage.read_tx_log()?;
+    /// Returns an estimate of the memory the row versions take, in bytes.
+    pub fn memory_usage(&self) -> usize {
+        versions_memory_usage(&self.rows) + versions_memory_usage(&self.index_rows)
+    }
+
+    /// Returns the memory the row versions may take before they are checkpointed out of memory,
+    /// in bytes, or zero if there is no limit.
+    pub fn memory_limit(&self) -> usize {
+        self.memory_limit.load(Ordering::SeqCst)
+    }
+
+    pub fn set_memory_limit(&self, limit: usize) {
+        self.memory_limit.store(limit, Ordering::SeqCst);
+    }
+
+    pub fn gc_metrics(&self) -> GcMetrics {
+        *self.gc_metrics.read()
+    }
+
+    /// Replays the transactions of the log into the b-tree, which a crash may have kept them
+    /// from reaching, and then truncates the log.
+    ///
+    /// The rows are not loaded into the store, they are loaded from the b-tree like the others.
+    pub fn recover(&self, connection: &Arc<Connection>) -> Result<()> {
+        if let Storage::Noop = self.storage {
+            // Nothing outlives the database
+            return Ok(());
+        }
+        let mut tx_log = self.storage.read_tx_log()?;
+        // Transactions committing at the same time are logged in the order they reach the log,
+        // not in the order of their commit timestamps
+        tx_log.sort_by_key(|record| record.tx_timestamp);
+        let indexes = schema_indexes(connection)?;
+        // The state of each row after the last transaction that changed it. A transaction that
+        // updated a row logs both the deleted and the inserted version.
+        let mut rows: BTreeMap<RowID, Option<Row>> = BTreeMap::new();
+        // The state of each index entry, by index ID and record, likewise.
+        let mut index_entries: BTreeMap<(u64, Vec<u8>), Option<Row>> = BTreeMap::new();
+        let mut last_timestamp = None;
         for record in tx_log {
             tracing::debug!("recover() -> tx_timestamp={}", record.tx_timestamp);
+            let mut changes = HashMap::new();
+            let mut index_changes = HashMap::new();
             for version in record.row_versions {
-                self.insert_version(version.row.id, version);
+                if indexes.contains_key(&version.row.id.table_id) {
+                    let key = (version.row.id.table_id, version.row.data.clone());
+                    if version.end.is_none() {
+                        index_changes.insert(key, Some(version.row));
+                    } else {
+                        index_changes.entry(key).or_insert(None);
+                    }
+                } else if version.end.is_none() {
+                    changes.insert(version.row.id, Some(version.row));
+                } else {
+                    changes.entry(version.row.id).or_insert(None);
+                }
             }
-            self.clock.reset(record.tx_timestamp);
+            rows.extend(changes);
+            index_entries.extend(index_changes);
+            last_timestamp = Some(record.tx_timestamp);
+        }
+        if let Some(ts) = last_timestamp {
+            self.clock.reset(ts + 1);
+        }
+        if !rows.is_empty() || !index_entries.is_empty() {
+            self.replay_rows(connection, rows, index_entries, &indexes)?;
+        }
+        if let Some(write) = self.storage.truncate_tx_log(true) {
+            self.storage.wait_tx_log(&write)?;
         }
         Ok(())
     }
 
+    fn replay_rows(
+        &self,
+        connection: &Arc<Connection>,
+        rows: BTreeMap<RowID, Option<Row>>,
+        index_entries: BTreeMap<(u64, Vec<u8>), Option<Row>>,
+        indexes: &HashMap<u64, IndexInfo>,
+    ) -> Result<()> {
+        let pager = connection.pager.borrow().clone();
+        if let LimboResult::Busy = pager.begin_read_tx()? {
+            return Err(LimboError::Busy);
+        }
+        if let LimboResult::Busy = pager.io.block(|| pager.begin_write_tx())? {
+            pager.end_read_tx()?;
+            return Err(LimboError::Busy);
+        }
+        connection
+            .transaction_state
+            .set(crate::TransactionState::Write {
+                schema_did_change: false,
+            });
+        let result = (|| {
+            let mut cursors: HashMap<u64, Arc<RwLock<BTreeCursor>>> = HashMap::new();
+            for (id, row) in rows {
+                let cursor = cursors
+                    .entry(id.table_id)
+                    .or_insert_with(|| {
+                        Arc::new(RwLock::new(BTreeCursor::new_table(
+                            None,
+                            pager.clone(),
+                            id.table_id as usize,
+                            row.as_ref().map_or(1, |row| row.column_count),
+                        )))
+                    })
+                    .clone();
+                match row {
+                    Some(row) => {
+                        let state_machine = self.write_row_to_pager(&row, cursor, true)?;
+                        run_to_completion(state_machine, &pager)?;
+                    }
+                    None => {
+                        let state_machine = self.delete_row_from_pager(id, cursor)?;
+                        run_to_completion(state_machine, &pager)?;
+                    }
+                }
+            }
+            for ((index_id, data), row) in index_entries {
+                let cursor = cursors
+                    .entry(index_id)
+                    .or_insert_with(|| {
+                        Arc::new(RwLock::new(index_btree_cursor(
+                            pager.clone(),
+                            index_id,
+                            &indexes[&index_id],
+                        )))
+                    })
+                    .clone();
+                match row {
+                    Some(row) => {
+                        let state_machine = self.write_row_to_pager(&row, cursor, true)?;
+                        run_to_completion(state_machine, &pager)?;
+                    }
+                    None => {
+                        let row = Row::new(RowID::new(index_id, 0), data, 0);
+                        let state_machine =
+                            StateMachine::new(DeleteRowStateMachine::new_index(&row, cursor));
+                        run_to_completion(state_machine, &pager)?;
+                    }
+                }
+            }
+            pager.io.block(|| pager.end_tx(false, connection))
+        })();
+        if result.is_err() {
+            pager.io.block(|| pager.end_tx(true, connection))?;
+        }
+        connection
+            .transaction_state
+            .set(crate::TransactionState::None);
+        result.map(|_| ())
+    }
+
     // Extracts the begin timestamp from a transaction
     #[inline]
     fn get_begin_timestamp(&self, ts_or_id: &TxTimestampOrID) -> u64 {
@@ -1592,6 +2715,16 @@ impl<Clock: LogicalClock> MvStore<Clock> {
         self.insert_version_raw(&mut versions, row_version)
     }
 
+    /// Inserts a new version of an index entry into the database, while making sure that the
+    /// version is inserted in the correct order.
+    fn insert_index_version(&self, key: IndexKey, row_version: RowVersion) {
+        let versions = self
+            .index_rows
+            .get_or_insert_with(key, || RwLock::new(Vec::new()));
+        let mut versions = versions.value().write();
+        self.insert_version_raw(&mut versions, row_version)
+    }
+
     /// Inserts a new row version into the internal data structure for versions,
     /// while making sure that the row version is inserted in the correct order.
     pub fn insert_version_raw(&self, versions: &mut Vec<RowVersion>, row_version: RowVersion) {
@@ -1651,8 +2784,14 @@ impl<Clock: LogicalClock> MvStore<Clock> {
     ///
     /// # Arguments
     ///
-    pub fn maybe_initialize_table(&self, table_id: u64, pager: Rc<Pager>) -> Result<()> {
+    pub fn maybe_initialize_table(
+        &self,
+        table_id: u64,
+        tx_id: TxID,
+        pager: Rc<Pager>,
+    ) -> Result<()> {
         tracing::trace!("scan_row_ids_for_table(table_id={})", table_id);
+        self.mark_table_used(table_id, tx_id);
 
         // First, check if the table is already loaded.
         if self.loaded_tables.read().contains(&table_id) {
@@ -1667,6 +2806,15 @@ impl<Clock: LogicalClock> MvStore<Clock> {
         Ok(())
     }
 
+    /// Keeps the table or index in memory while the transaction runs. It is recorded before the
+    /// table is looked up, so that a concurrent checkpoint either sees it or completes before
+    /// the table is loaded again.
+    fn mark_table_used(&self, table_id: u64, tx_id: TxID) {
+        if let Some(tx) = self.txs.get(&tx_id) {
+            tx.value().used_tables.insert(table_id);
+        }
+    }
+
     /// Scans the table and inserts the rows into the database.
     ///
     /// This is initialization step for a table, where we still don't have any rows so we need to insert them if there are.
@@ -1743,6 +2891,72 @@ impl<Clock: LogicalClock> MvStore<Clock> {
         Ok(())
     }
 
+    /// Loads the entries of the index b-tree `index_id` into the database, if they were not
+    /// loaded yet. `key_columns` is the number of leading columns of the entries that make up
+    /// their key.
+    pub fn maybe_initialize_index(
+        &self,
+        index_id: u64,
+        index_info: &Arc<IndexInfo>,
+        key_columns: usize,
+        tx_id: TxID,
+        pager: Rc<Pager>,
+    ) -> Result<()> {
+        tracing::trace!("maybe_initialize_index(index_id={})", index_id);
+        self.mark_table_used(index_id, tx_id);
+        if self.loaded_tables.read().contains(&index_id) {
+            return Ok(());
+        }
+        self.scan_load_index(index_id, index_info, key_columns, pager)?;
+        self.loaded_tables.write().insert(index_id);
+        Ok(())
+    }
+
+    /// Scans the index b-tree and inserts its entries into the database.
+    fn scan_load_index(
+        &self,
+        index_id: u64,
+        index_info: &Arc<IndexInfo>,
+        key_columns: usize,
+        pager: Rc<Pager>,
+    ) -> Result<()> {
+        let mut cursor = index_btree_cursor(pager.clone(), index_id, index_info);
+        pager.io.block(|| cursor.rewind())?;
+        loop {
+            let payload = loop {
+                match cursor.record()? {
+                    IOResult::Done(record) => {
+                        break record.map(|record| record.get_payload().to_vec());
+                    }
+                    IOResult::IO(io) => io.wait(pager.io.as_ref())?,
+                }
+            };
+            let Some(payload) = payload else {
+                break;
+            };
+            let key = IndexKey::new(
+                index_id,
+                ImmutableRecord::from_bin_record(payload.clone()),
+                index_info.clone(),
+                key_columns,
+            );
+            let id = RowID::new(index_id, key.rowid());
+            // Like rows, the entries are loaded with the 0 timestamp.
+            self.insert_index_version(
+                key,
+                RowVersion {
+                    begin: TxTimestampOrID::Timestamp(0),
+                    end: None,
+                    row: Row::new(id, payload, index_info.num_cols),
+                },
+            );
+            if !pager.io.block(|| cursor.next())? {
+                break;
+            }
+        }
+        Ok(())
+    }
+
     pub fn get_last_rowid(&self, table_id: u64) -> Option<i64> {
         let last_rowid = self
             .rows
@@ -1756,6 +2970,126 @@ impl<Clock: LogicalClock> MvStore<Clock> {
     }
 }
 
+/// Replaces the ID of the committed transaction `tx_id` with its end timestamp in the versions
+/// it inserted and deleted.
+fn commit_versions(row_versions: &mut [RowVersion], tx_id: TxID, end_ts: u64) {
+    for row_version in row_versions.iter_mut() {
+        if let TxTimestampOrID::TxID(id) = row_version.begin {
+            if id == tx_id {
+                // New version is valid STARTING FROM committing transaction's end timestamp
+                // See diagram on page 299: https://www.cs.cmu.edu/~15721-f24/papers/Hekaton.pdf
+                row_version.begin = TxTimestampOrID::Timestamp(end_ts);
+            }
+        }
+        if let Some(TxTimestampOrID::TxID(id)) = row_version.end {
+            if id == tx_id {
+                // Old version is valid UNTIL committing transaction's end timestamp
+                // See diagram on page 299: https://www.cs.cmu.edu/~15721-f24/papers/Hekaton.pdf
+                row_version.end = Some(TxTimestampOrID::Timestamp(end_ts));
+            }
+        }
+    }
+}
+
+/// Removes the entries whose key `remove` selects, and returns the number of versions they held.
+fn remove_versions<K: Ord + Send + 'static>(
+    versions: &SkipMap<K, RwLock<Vec<RowVersion>>>,
+    remove: impl Fn(&K) -> bool,
+) -> usize {
+    let mut removed = 0;
+    for entry in versions.iter() {
+        if remove(entry.key()) {
+            removed += entry.value().read().len();
+            entry.remove();
+        }
+    }
+    removed
+}
+
+fn versions_memory_usage<K: Ord + Send + 'static>(
+    versions: &SkipMap<K, RwLock<Vec<RowVersion>>>,
+) -> usize {
+    versions
+        .iter()
+        .map(|entry| {
+            entry
+                .value()
+                .read()
+                .iter()
+                .map(|rv| std::mem::size_of::<RowVersion>() + rv.row.data.len())
+                .sum::<usize>()
+        })
+        .sum()
+}
+
+/// Undoes the insertions and deletions of the transaction `tx_id` to the versions of `id`.
+fn rollback_versions<K: Ord + Send + 'static>(
+    versions: &SkipMap<K, RwLock<Vec<RowVersion>>>,
+    id: &K,
+    tx_id: TxID,
+) {
+    if let Some(row_versions) = versions.get(id) {
+        let mut row_versions = row_versions.value().write();
+        for rv in row_versions.iter_mut() {
+            if rv.end == Some(TxTimestampOrID::TxID(tx_id)) {
+                // undo deletions by this transaction
+                rv.end = None;
+            }
+        }
+        // remove insertions by this transaction
+        row_versions.retain(|rv| rv.begin != TxTimestampOrID::TxID(tx_id));
+        if row_versions.is_empty() {
+            versions.remove(id);
+        }
+    }
+}
+
+/// Returns the indexes of the schema of `connection` by ID, including the primary keys of the
+/// WITHOUT ROWID tables, which hold their rows.
+fn schema_indexes(connection: &Arc<Connection>) -> Result<HashMap<u64, IndexInfo>> {
+    let schema = connection.schema.borrow();
+    let mut indexes = HashMap::new();
+    for index in schema.indexes.values().flatten() {
+        indexes.insert(index.root_page as u64, IndexInfo::new_from_index(index));
+    }
+    for table in schema.tables.values() {
+        let Some(table) = table.btree() else {
+            continue;
+        };
+        if table.has_rowid {
+            continue;
+        }
+        let index = crate::schema::Index::automatic_from_primary_key(
+            &table,
+            (table.name.clone(), table.root_page),
+            table.primary_key_columns.len(),
+        )?;
+        indexes.insert(index.root_page as u64, IndexInfo::new_from_index(&index));
+    }
+    Ok(indexes)
+}
+
+/// Creates a cursor that reads and writes the b-tree of the index `index_id` directly.
+fn index_btree_cursor(pager: Rc<Pager>, index_id: u64, index_info: &IndexInfo) -> BTreeCursor {
+    let mut cursor = BTreeCursor::new(None, pager, index_id as usize, index_info.num_cols);
+    cursor.index_info = Some(index_info.clone());
+    cursor
+}
+
+/// Steps `state_machine` until it is done, waiting for its IO.
+fn run_to_completion<T: StateTransition<Context = ()>>(
+    mut state_machine: StateMachine<T>,
+    pager: &Pager,
+) -> Result<T::SMResult> {
+    loop {
+        match state_machine.step(&())? {
+            TransitionResult::Io(io) => io.wait(pager.io.as_ref())?,
+            TransitionResult::Continue => continue,
+            TransitionResult::Done(result) => return Ok(result),
+        }
+    }
+}
+
 /// A write-write conflict happens when transaction T_current attempts to update a
 /// row version that is:
 /// a) currently being updated by an active transaction T_previous, or
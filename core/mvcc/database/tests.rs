@@ -26,6 +26,23 @@ impl MvccTestDb {
             conn,
         }
     }
+
+    /// Opens a database whose transactions run under serializable snapshot isolation
+    pub fn new_serializable() -> Self {
+        let io = Arc::new(MemoryIO::new());
+        let opts = crate::DatabaseOpts::new()
+            .with_mvcc(true)
+            .with_mvcc_serializable(true);
+        let db = Database::open_file_with_flags(io, ":memory:", crate::OpenFlags::default(), opts)
+            .unwrap();
+        let conn = db.connect().unwrap();
+        let mvcc_store = db.mv_store.as_ref().unwrap().clone();
+        Self {
+            mvcc_store,
+            db,
+            conn,
+        }
+    }
 }
 
 impl MvccTestDbNoConn {
@@ -287,6 +304,32 @@ fn test_commit() {
     db.mvcc_store.drop_unused_row_versions();
 }
 
+#[test]
+fn test_drop_versions_not_visible_to_active_txs() {
+    let db = MvccTestDb::new();
+    let pager = db.conn.pager.borrow().clone();
+    let id = RowID::new(1, 1);
+    let tx1 = db.mvcc_store.begin_tx(pager.clone()).unwrap();
+    let row = generate_simple_string_row(1, 1, "Hello");
+    db.mvcc_store.insert(tx1, row.clone()).unwrap();
+    commit_tx(db.mvcc_store.clone(), &db.conn, tx1).unwrap();
+
+    let tx2 = db.mvcc_store.begin_tx(pager.clone()).unwrap();
+    let tx3 = db.mvcc_store.begin_tx(pager.clone()).unwrap();
+    let updated_row = generate_simple_string_row(1, 1, "World");
+    db.mvcc_store.update(tx3, updated_row.clone()).unwrap();
+    commit_tx(db.mvcc_store.clone(), &db.conn, tx3).unwrap();
+
+    // tx2 began before the update committed, so it still needs the old version
+    assert_eq!(db.mvcc_store.drop_unused_row_versions(), 0);
+    assert_eq!(db.mvcc_store.read(tx2, id).unwrap().unwrap(), row);
+    commit_tx(db.mvcc_store.clone(), &db.conn, tx2).unwrap();
+
+    assert_eq!(db.mvcc_store.drop_unused_row_versions(), 1);
+    let tx4 = db.mvcc_store.begin_tx(pager).unwrap();
+    assert_eq!(db.mvcc_store.read(tx4, id).unwrap().unwrap(), updated_row);
+}
+
 #[test]
 fn test_rollback() {
     let db = MvccTestDb::new();
@@ -342,6 +385,44 @@ fn test_rollback() {
     assert_eq!(row5, None);
 }
 
+#[test]
+fn test_rollback_to_savepoint() {
+    let db = MvccTestDb::new();
+    let tx1 = db
+        .mvcc_store
+        .begin_tx(db.conn.pager.borrow().clone())
+        .unwrap();
+    let row1 = generate_simple_string_row(1, 1, "Hello");
+    db.mvcc_store.insert(tx1, row1.clone()).unwrap();
+    db.mvcc_store
+        .insert(tx1, generate_simple_string_row(1, 2, "Doomed"))
+        .unwrap();
+    db.mvcc_store.delete(tx1, RowID::new(1, 2)).unwrap();
+    let savepoint = db.mvcc_store.savepoint(tx1).unwrap();
+
+    db.mvcc_store
+        .update(tx1, generate_simple_string_row(1, 1, "World"))
+        .unwrap();
+    db.mvcc_store
+        .insert(tx1, generate_simple_string_row(1, 3, "Gone"))
+        .unwrap();
+    db.mvcc_store
+        .rollback_to_savepoint(tx1, Some(&savepoint))
+        .unwrap();
+
+    let row = db.mvcc_store.read(tx1, RowID::new(1, 1)).unwrap();
+    assert_eq!(row, Some(row1));
+    let row = db.mvcc_store.read(tx1, RowID::new(1, 2)).unwrap();
+    assert_eq!(row, None);
+    let row = db.mvcc_store.read(tx1, RowID::new(1, 3)).unwrap();
+    assert_eq!(row, None);
+
+    // A savepoint taken before the transaction started undoes everything.
+    db.mvcc_store.rollback_to_savepoint(tx1, None).unwrap();
+    let row = db.mvcc_store.read(tx1, RowID::new(1, 1)).unwrap();
+    assert_eq!(row, None);
+}
+
 #[test]
 fn test_dirty_write() {
     let db = MvccTestDb::new();
@@ -722,6 +803,110 @@ fn test_future_row() {
     assert_eq!(row, None);
 }
 
+// Write skew: T1 and T2 both read rows 1 and 2, then each updates a different one of them.
+// Snapshot isolation lets both commit, but serializable isolation must abort one of them.
+#[test]
+fn test_serializable_write_skew() {
+    let db = MvccTestDb::new_serializable();
+    let row1 = RowID::new(1, 1);
+    let row2 = RowID::new(1, 2);
+
+    let tx0 = db
+        .mvcc_store
+        .begin_tx(db.conn.pager.borrow().clone())
+        .unwrap();
+    db.mvcc_store
+        .insert(tx0, generate_simple_string_row(1, 1, "on call"))
+        .unwrap();
+    db.mvcc_store
+        .insert(tx0, generate_simple_string_row(1, 2, "on call"))
+        .unwrap();
+    commit_tx(db.mvcc_store.clone(), &db.conn, tx0).unwrap();
+
+    let conn1 = db.db.connect().unwrap();
+    let tx1 = db
+        .mvcc_store
+        .begin_tx(conn1.pager.borrow().clone())
+        .unwrap();
+    let conn2 = db.db.connect().unwrap();
+    let tx2 = db
+        .mvcc_store
+        .begin_tx(conn2.pager.borrow().clone())
+        .unwrap();
+    for tx in [tx1, tx2] {
+        assert!(db.mvcc_store.read(tx, row1).unwrap().is_some());
+        assert!(db.mvcc_store.read(tx, row2).unwrap().is_some());
+    }
+    assert!(db
+        .mvcc_store
+        .update(tx1, generate_simple_string_row(1, 1, "off call"))
+        .unwrap());
+    assert!(db
+        .mvcc_store
+        .update(tx2, generate_simple_string_row(1, 2, "off call"))
+        .unwrap());
+
+    commit_tx(db.mvcc_store.clone(), &conn1, tx1).unwrap();
+    assert!(matches!(
+        commit_tx(db.mvcc_store.clone(), &conn2, tx2),
+        Err(LimboError::SerializationFailure)
+    ));
+    // hack: in the actual tursodb database we rollback the mvcc tx ourselves, so manually roll it back here
+    db.mvcc_store
+        .rollback_tx(tx2, conn2.pager.borrow().clone(), &conn2)
+        .unwrap();
+
+    let conn3 = db.db.connect().unwrap();
+    let tx3 = db
+        .mvcc_store
+        .begin_tx(conn3.pager.borrow().clone())
+        .unwrap();
+    let row = db.mvcc_store.read(tx3, row2).unwrap().unwrap();
+    assert_eq!(row, generate_simple_string_row(1, 2, "on call"));
+}
+
+// Transactions that only read the rows they update do not conflict under serializable isolation.
+#[test]
+fn test_serializable_disjoint_updates() {
+    let db = MvccTestDb::new_serializable();
+
+    let tx0 = db
+        .mvcc_store
+        .begin_tx(db.conn.pager.borrow().clone())
+        .unwrap();
+    db.mvcc_store
+        .insert(tx0, generate_simple_string_row(1, 1, "Hello"))
+        .unwrap();
+    db.mvcc_store
+        .insert(tx0, generate_simple_string_row(1, 2, "Hello"))
+        .unwrap();
+    commit_tx(db.mvcc_store.clone(), &db.conn, tx0).unwrap();
+
+    let conn1 = db.db.connect().unwrap();
+    let tx1 = db
+        .mvcc_store
+        .begin_tx(conn1.pager.borrow().clone())
+        .unwrap();
+    let conn2 = db.db.connect().unwrap();
+    let tx2 = db
+        .mvcc_store
+        .begin_tx(conn2.pager.borrow().clone())
+        .unwrap();
+    assert!(db.mvcc_store.read(tx1, RowID::new(1, 1)).unwrap().is_some());
+    assert!(db.mvcc_store.read(tx2, RowID::new(1, 2)).unwrap().is_some());
+    assert!(db
+        .mvcc_store
+        .update(tx1, generate_simple_string_row(1, 1, "World"))
+        .unwrap());
+    assert!(db
+        .mvcc_store
+        .update(tx2, generate_simple_string_row(1, 2, "World"))
+        .unwrap());
+
+    commit_tx(db.mvcc_store.clone(), &conn1, tx1).unwrap();
+    commit_tx(db.mvcc_store.clone(), &conn2, tx2).unwrap();
+}
+
 use crate::mvcc::cursor::MvccLazyCursor;
 use crate::mvcc::database::{MvStore, Row, RowID};
 use crate::types::Text;
@@ -1076,7 +1261,12 @@ fn new_tx(tx_id: TxID, begin_ts: u64, state: TransactionState) -> Transaction {
         tx_id,
         begin_ts,
         write_set: SkipSet::new(),
+        index_write_set: SkipSet::new(),
         read_set: SkipSet::new(),
+        scan_set: SkipSet::new(),
+        used_tables: SkipSet::new(),
+        in_conflict: AtomicBool::new(false),
+        out_conflict: AtomicBool::new(false),
     }
 }
 
@@ -1301,6 +1491,288 @@ fn test_interactive_transaction() {
     assert_eq!(rows, vec![vec![Value::Integer(1)], vec![Value::Integer(2)]]);
 }
 
+#[test]
+fn test_recover_from_logical_log() {
+    let mut db = MvccTestDbNoConn::new_with_random_db();
+    let root_page = {
+        let conn = db.connect();
+        conn.execute("CREATE TABLE test (x)").unwrap();
+        conn.execute("INSERT INTO test (x) VALUES (1), (2)")
+            .unwrap();
+        let rows = get_rows(
+            &conn,
+            "SELECT rootpage FROM sqlite_schema WHERE name = 'test'",
+        );
+        conn.close().unwrap();
+        rows[0][0].as_int().unwrap() as u64
+    };
+    db.db.take();
+
+    // A transaction that was logged, but did not reach the b-tree before a crash
+    let path = db.path.clone().unwrap();
+    let io: Arc<dyn crate::IO> = Arc::new(PlatformIO::new().unwrap());
+    let log = crate::mvcc::persistent_storage::LogicalLog::open(io.clone(), &format!("{path}-log"))
+        .unwrap();
+    let mut record = LogRecord::new(100);
+    let record_3 = ImmutableRecord::from_values(&[Value::Integer(3)], 1);
+    record.row_versions.push(RowVersion {
+        begin: TxTimestampOrID::Timestamp(100),
+        end: None,
+        row: Row::new(RowID::new(root_page, 3), record_3.as_blob().to_vec(), 1),
+    });
+    let record_1 = ImmutableRecord::from_values(&[Value::Integer(1)], 1);
+    record.row_versions.push(RowVersion {
+        begin: TxTimestampOrID::Timestamp(0),
+        end: Some(TxTimestampOrID::Timestamp(100)),
+        row: Row::new(RowID::new(root_page, 1), record_1.as_blob().to_vec(), 1),
+    });
+    log.wait(&log.append(&record, true)).unwrap();
+    drop(log);
+
+    db.restart();
+    let conn = db.connect();
+    let rows = get_rows(&conn, "SELECT x FROM test");
+    assert_eq!(rows, vec![vec![Value::Integer(2)], vec![Value::Integer(3)]]);
+    // The log is truncated once replayed
+    assert_eq!(std::fs::metadata(format!("{path}-log")).unwrap().len(), 0);
+}
+
+#[test]
+fn test_logical_log_truncated_with_sync_off() {
+    let db = MvccTestDbNoConn::new_with_random_db();
+    let conn = db.connect();
+    conn.execute("PRAGMA synchronous = OFF").unwrap();
+    conn.execute("CREATE TABLE test (x)").unwrap();
+    for _ in 0..20 {
+        conn.execute("INSERT INTO test (x) VALUES (zeroblob(65536))")
+            .unwrap();
+    }
+    // The log is truncated once it grows past the threshold, even though it is not synced
+    let path = db.path.clone().unwrap();
+    let size = std::fs::metadata(format!("{path}-log")).unwrap().len();
+    assert!(size < crate::mvcc::persistent_storage::TRUNCATE_THRESHOLD);
+}
+
+#[test]
+fn test_index_scan_and_seek() {
+    let db = MvccTestDbNoConn::new_with_random_db();
+    let conn = db.connect();
+    conn.execute("CREATE TABLE test (id INTEGER PRIMARY KEY, x TEXT)")
+        .unwrap();
+    conn.execute("CREATE INDEX test_x ON test (x)").unwrap();
+    conn.execute("INSERT INTO test (id, x) VALUES (1, 'c'), (2, 'a'), (3, 'b')")
+        .unwrap();
+
+    let rows = get_rows(&conn, "SELECT x, id FROM test ORDER BY x");
+    assert_eq!(
+        rows,
+        vec![
+            vec![Value::build_text("a"), Value::Integer(2)],
+            vec![Value::build_text("b"), Value::Integer(3)],
+            vec![Value::build_text("c"), Value::Integer(1)],
+        ]
+    );
+    let rows = get_rows(&conn, "SELECT id FROM test WHERE x = 'b'");
+    assert_eq!(rows, vec![vec![Value::Integer(3)]]);
+    let rows = get_rows(&conn, "SELECT x FROM test WHERE x > 'a' ORDER BY x DESC");
+    assert_eq!(
+        rows,
+        vec![vec![Value::build_text("c")], vec![Value::build_text("b")]]
+    );
+
+    // Updating and deleting rows replaces their index entries
+    conn.execute("UPDATE test SET x = 'd' WHERE id = 2")
+        .unwrap();
+    conn.execute("DELETE FROM test WHERE id = 3").unwrap();
+    let rows = get_rows(&conn, "SELECT x, id FROM test ORDER BY x");
+    assert_eq!(
+        rows,
+        vec![
+            vec![Value::build_text("c"), Value::Integer(1)],
+            vec![Value::build_text("d"), Value::Integer(2)],
+        ]
+    );
+    let rows = get_rows(&conn, "SELECT id FROM test WHERE x = 'a'");
+    assert!(rows.is_empty());
+}
+
+#[test]
+fn test_index_entries_visibility() {
+    let db = MvccTestDbNoConn::new_with_random_db();
+    let conn0 = db.connect();
+    conn0
+        .execute("CREATE TABLE test (id INTEGER PRIMARY KEY, x INTEGER)")
+        .unwrap();
+    conn0.execute("CREATE INDEX test_x ON test (x)").unwrap();
+    conn0
+        .execute("INSERT INTO test (id, x) VALUES (1, 10)")
+        .unwrap();
+    let conn1 = db.connect();
+
+    conn0.execute("BEGIN").unwrap();
+    conn0
+        .execute("INSERT INTO test (id, x) VALUES (2, 20)")
+        .unwrap();
+    conn0
+        .execute("UPDATE test SET x = 15 WHERE id = 1")
+        .unwrap();
+    // The writer sees its own index entries
+    let rows = get_rows(&conn0, "SELECT x FROM test WHERE x >= 10");
+    assert_eq!(
+        rows,
+        vec![vec![Value::Integer(15)], vec![Value::Integer(20)]]
+    );
+    // Other transactions don't until the writer commits
+    let rows = get_rows(&conn1, "SELECT x FROM test WHERE x >= 10");
+    assert_eq!(rows, vec![vec![Value::Integer(10)]]);
+    conn0.execute("COMMIT").unwrap();
+
+    let rows = get_rows(&conn1, "SELECT x FROM test WHERE x >= 10");
+    assert_eq!(
+        rows,
+        vec![vec![Value::Integer(15)], vec![Value::Integer(20)]]
+    );
+}
+
+#[test]
+fn test_index_entries_rollback() {
+    let db = MvccTestDbNoConn::new_with_random_db();
+    let conn = db.connect();
+    conn.execute("CREATE TABLE test (id INTEGER PRIMARY KEY, x INTEGER)")
+        .unwrap();
+    conn.execute("CREATE UNIQUE INDEX test_x ON test (x)")
+        .unwrap();
+    conn.execute("INSERT INTO test (id, x) VALUES (1, 10)")
+        .unwrap();
+
+    conn.execute("BEGIN").unwrap();
+    conn.execute("INSERT INTO test (id, x) VALUES (2, 20)")
+        .unwrap();
+    conn.execute("DELETE FROM test WHERE id = 1").unwrap();
+    conn.execute("ROLLBACK").unwrap();
+
+    let rows = get_rows(&conn, "SELECT x FROM test WHERE x >= 10");
+    assert_eq!(rows, vec![vec![Value::Integer(10)]]);
+    // The unique index still holds the entry that the rolled back transaction deleted
+    assert!(conn
+        .execute("INSERT INTO test (id, x) VALUES (3, 10)")
+        .is_err());
+}
+
+#[test]
+fn test_count_with_index() {
+    let db = MvccTestDbNoConn::new_with_random_db();
+    let conn = db.connect();
+    conn.execute("CREATE TABLE test (id INTEGER PRIMARY KEY, x INTEGER)")
+        .unwrap();
+    conn.execute("CREATE INDEX test_x ON test (x)").unwrap();
+    conn.execute("INSERT INTO test (id, x) VALUES (1, 10), (2, 20), (3, 30)")
+        .unwrap();
+    conn.execute("DELETE FROM test WHERE id = 2").unwrap();
+
+    let rows = get_rows(&conn, "SELECT count(*) FROM test");
+    assert_eq!(rows, vec![vec![Value::Integer(2)]]);
+}
+
+#[test]
+fn test_recover_index_entries() {
+    let mut db = MvccTestDbNoConn::new_with_random_db();
+    {
+        let conn = db.connect();
+        conn.execute("CREATE TABLE test (id INTEGER PRIMARY KEY, x INTEGER)")
+            .unwrap();
+        conn.execute("CREATE INDEX test_x ON test (x)").unwrap();
+        conn.execute("INSERT INTO test (id, x) VALUES (1, 30), (2, 10), (3, 20)")
+            .unwrap();
+        conn.execute("UPDATE test SET x = 40 WHERE id = 2").unwrap();
+        conn.close().unwrap();
+    }
+    db.restart();
+
+    let conn = db.connect();
+    let rows = get_rows(&conn, "SELECT x FROM test WHERE x > 0");
+    assert_eq!(
+        rows,
+        vec![
+            vec![Value::Integer(20)],
+            vec![Value::Integer(30)],
+            vec![Value::Integer(40)],
+        ]
+    );
+}
+
+#[test]
+fn test_memory_limit_checkpoints_versions() {
+    let db = MvccTestDbNoConn::new_with_random_db();
+    let conn = db.connect();
+    let mvcc_store = db.get_mvcc_store();
+    conn.execute("CREATE TABLE test (id INTEGER PRIMARY KEY, x INTEGER)")
+        .unwrap();
+    conn.execute("CREATE INDEX test_x ON test (x)").unwrap();
+    conn.execute("INSERT INTO test (id, x) VALUES (1, 0)")
+        .unwrap();
+
+    let rows = get_rows(&conn, "PRAGMA mvcc_memory_limit = 1");
+    assert_eq!(rows, vec![vec![Value::Integer(1)]]);
+    assert_eq!(mvcc_store.memory_limit(), 1);
+
+    // Enough commits to collect garbage at least once
+    for i in 1..=GC_COMMIT_INTERVAL {
+        conn.execute(format!("UPDATE test SET x = {i} WHERE id = 1"))
+            .unwrap();
+    }
+    let metrics = mvcc_store.gc_metrics();
+    assert!(metrics.collections >= 1);
+    assert!(metrics.versions_reclaimed > 0);
+    assert!(metrics.versions_checkpointed > 0);
+
+    // The checkpointed rows and index entries are read from the b-tree again
+    let last = GC_COMMIT_INTERVAL as i64;
+    let rows = get_rows(&conn, "SELECT x FROM test");
+    assert_eq!(rows, vec![vec![Value::Integer(last)]]);
+    let rows = get_rows(&conn, &format!("SELECT id FROM test WHERE x = {last}"));
+    assert_eq!(rows, vec![vec![Value::Integer(1)]]);
+    let rows = get_rows(&conn, "SELECT id FROM test WHERE x = 0");
+    assert!(rows.is_empty());
+}
+
+#[test]
+fn test_memory_limit_checkpoints_versions_with_active_tx() {
+    let db = MvccTestDbNoConn::new_with_random_db();
+    let conn = db.connect();
+    conn.execute("CREATE TABLE test (id INTEGER PRIMARY KEY, x INTEGER)")
+        .unwrap();
+    conn.execute("CREATE TABLE idle (x INTEGER)").unwrap();
+    conn.execute("INSERT INTO test (id, x) VALUES (1, 0)")
+        .unwrap();
+    conn.execute("INSERT INTO idle (x) VALUES (1), (2)")
+        .unwrap();
+    get_rows(&conn, "SELECT x FROM idle");
+
+    let reader = db.connect();
+    reader.execute("BEGIN").unwrap();
+    let rows = get_rows(&reader, "SELECT x FROM test");
+    assert_eq!(rows, vec![vec![Value::Integer(0)]]);
+
+    conn.execute("PRAGMA mvcc_memory_limit = 1").unwrap();
+    for i in 1..=GC_COMMIT_INTERVAL {
+        conn.execute(format!("UPDATE test SET x = {i} WHERE id = 1"))
+            .unwrap();
+    }
+    // The table nobody changed since the reader began is checkpointed, the other one is not
+    let metrics = conn.mvcc_gc_metrics().unwrap();
+    assert!(metrics.collections >= 1);
+    assert!(metrics.versions_checkpointed > 0);
+    let rows = get_rows(&reader, "SELECT x FROM test");
+    assert_eq!(rows, vec![vec![Value::Integer(0)]]);
+    reader.execute("COMMIT").unwrap();
+
+    let rows = get_rows(&conn, "SELECT x FROM idle");
+    assert_eq!(rows, vec![vec![Value::Integer(1)], vec![Value::Integer(2)]]);
+    let rows = get_rows(&conn, "SELECT x FROM test");
+    assert_eq!(rows, vec![vec![Value::Integer(GC_COMMIT_INTERVAL as i64)]]);
+}
+
 #[test]
 fn test_commit_without_tx() {
     let db = MvccTestDbNoConn::new_with_random_db();
@@ -1487,10 +1959,204 @@ fn transaction_display() {
         tx_id,
         begin_ts,
         write_set,
+        index_write_set: SkipSet::new(),
         read_set,
+        scan_set: SkipSet::new(),
+        used_tables: SkipSet::new(),
+        in_conflict: AtomicBool::new(false),
+        out_conflict: AtomicBool::new(false),
     };
 
     let expected = "{ state: Preparing, id: 42, begin_ts: 20250914, write_set: [RowID { table_id: 1, row_id: 11 }, RowID { table_id: 1, row_id: 13 }], read_set: [RowID { table_id: 2, row_id: 17 }, RowID { table_id: 2, row_id: 19 }] }";
     let output = format!("{tx}");
     assert_eq!(output, expected);
 }
+
+/// Defers the syncs of the logical log to the next step of the IO, and counts them. The log is
+/// not synced to disk, which the tests that use it do not need.
+struct DeferredLogSyncIO {
+    inner: Arc<dyn crate::IO>,
+    deferred: Arc<Mutex<Vec<Completion>>>,
+    syncs: Arc<AtomicUsize>,
+}
+
+// SAFETY: the tests that use it run their connections on a single thread
+unsafe impl Send for DeferredLogSyncIO {}
+unsafe impl Sync for DeferredLogSyncIO {}
+
+struct DeferredLogSyncFile {
+    inner: Arc<dyn crate::io::File>,
+    deferred: Arc<Mutex<Vec<Completion>>>,
+    syncs: Arc<AtomicUsize>,
+}
+
+// SAFETY: likewise
+unsafe impl Send for DeferredLogSyncFile {}
+unsafe impl Sync for DeferredLogSyncFile {}
+
+impl crate::Clock for DeferredLogSyncIO {
+    fn now(&self) -> crate::Instant {
+        self.inner.now()
+    }
+}
+
+impl crate::IO for DeferredLogSyncIO {
+    fn open_file(
+        &self,
+        path: &str,
+        flags: crate::OpenFlags,
+        direct: bool,
+    ) -> Result<Arc<dyn crate::io::File>> {
+        let file = self.inner.open_file(path, flags, direct)?;
+        if !path.ends_with("-log") {
+            return Ok(file);
+        }
+        Ok(Arc::new(DeferredLogSyncFile {
+            inner: file,
+            deferred: self.deferred.clone(),
+            syncs: self.syncs.clone(),
+        }))
+    }
+
+    fn remove_file(&self, path: &str) -> Result<()> {
+        self.inner.remove_file(path)
+    }
+
+    fn step(&self) -> Result<()> {
+        for c in self.deferred.lock().drain(..) {
+            c.complete(0);
+        }
+        self.inner.step()
+    }
+}
+
+impl crate::io::File for DeferredLogSyncFile {
+    fn lock_file(&self, exclusive: bool) -> Result<()> {
+        self.inner.lock_file(exclusive)
+    }
+
+    fn unlock_file(&self) -> Result<()> {
+        self.inner.unlock_file()
+    }
+
+    fn pread(&self, pos: u64, c: Completion) -> Result<Completion> {
+        self.inner.pread(pos, c)
+    }
+
+    fn pwrite(&self, pos: u64, buffer: Arc<crate::Buffer>, c: Completion) -> Result<Completion> {
+        self.inner.pwrite(pos, buffer, c)
+    }
+
+    fn sync(&self, c: Completion) -> Result<Completion> {
+        self.syncs.fetch_add(1, Ordering::SeqCst);
+        self.deferred.lock().push(c.clone());
+        Ok(c)
+    }
+
+    fn size(&self) -> Result<u64> {
+        self.inner.size()
+    }
+
+    fn truncate(&self, len: u64, c: Completion) -> Result<Completion> {
+        self.inner.truncate(len, c)
+    }
+}
+
+#[test]
+fn test_logical_log_group_commit() {
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let path = temp_dir.path().join("test.db");
+    let syncs = Arc::new(AtomicUsize::new(0));
+    let io = Arc::new(DeferredLogSyncIO {
+        inner: Arc::new(PlatformIO::new().unwrap()),
+        deferred: Arc::new(Mutex::new(Vec::new())),
+        syncs: syncs.clone(),
+    });
+    let db = Database::open_file(io.clone(), path.to_str().unwrap(), true, true).unwrap();
+    let conn = db.connect().unwrap();
+    conn.execute("CREATE TABLE test (id INTEGER PRIMARY KEY, x)")
+        .unwrap();
+
+    let conns: Vec<_> = (0..8)
+        .map(|i| {
+            let conn = db.connect().unwrap();
+            conn.execute("BEGIN CONCURRENT").unwrap();
+            conn.execute(format!("INSERT INTO test (id, x) VALUES ({i}, {i})"))
+                .unwrap();
+            conn
+        })
+        .collect();
+    let syncs_before = syncs.load(Ordering::SeqCst);
+    // Step the commits in turns, so that they all wait for the log at once
+    let mut commits: Vec<_> = conns.iter().map(|c| c.prepare("COMMIT").unwrap()).collect();
+    let mut done = vec![false; commits.len()];
+    while done.contains(&false) {
+        for (stmt, done) in commits.iter_mut().zip(done.iter_mut()) {
+            if *done {
+                continue;
+            }
+            match stmt.step().unwrap() {
+                StepResult::Done => *done = true,
+                StepResult::IO => {}
+                _ => panic!("unexpected step result"),
+            }
+        }
+        crate::IO::step(io.as_ref()).unwrap();
+    }
+
+    let log_syncs = syncs.load(Ordering::SeqCst) - syncs_before;
+    assert!(
+        log_syncs < commits.len(),
+        "{log_syncs} log syncs for {} commits",
+        commits.len()
+    );
+    let rows = get_rows(&conn, "SELECT id FROM test");
+    assert_eq!(rows.len(), 8);
+}
+
+#[test]
+fn test_concurrent_inserts_into_unique_index() {
+    let db = MvccTestDbNoConn::new_with_random_db();
+    let conn = db.connect();
+    conn.execute("CREATE TABLE test (id INTEGER PRIMARY KEY, x UNIQUE)")
+        .unwrap();
+    let conn1 = db.connect();
+    let conn2 = db.connect();
+
+    // An entry that is not committed yet
+    conn1.execute("BEGIN CONCURRENT").unwrap();
+    conn2.execute("BEGIN CONCURRENT").unwrap();
+    conn1.execute("INSERT INTO test VALUES (1, 'a')").unwrap();
+    assert!(matches!(
+        conn2.execute("INSERT INTO test VALUES (2, 'a')"),
+        Err(LimboError::WriteWriteConflict)
+    ));
+
+    // The conflict rolled the transaction back. An entry committed after the transaction began
+    conn2.execute("BEGIN CONCURRENT").unwrap();
+    conn1.execute("COMMIT").unwrap();
+    assert!(matches!(
+        conn2.execute("INSERT INTO test VALUES (3, 'a')"),
+        Err(LimboError::WriteWriteConflict)
+    ));
+
+    // NULLs are never duplicates, and other values do not conflict
+    conn1.execute("BEGIN CONCURRENT").unwrap();
+    conn2.execute("BEGIN CONCURRENT").unwrap();
+    conn1.execute("INSERT INTO test VALUES (4, NULL)").unwrap();
+    conn2.execute("INSERT INTO test VALUES (5, NULL)").unwrap();
+    conn2.execute("INSERT INTO test VALUES (6, 'b')").unwrap();
+    conn1.execute("COMMIT").unwrap();
+    conn2.execute("COMMIT").unwrap();
+
+    let rows = get_rows(&conn, "SELECT id, x FROM test ORDER BY id");
+    assert_eq!(
+        rows,
+        vec![
+            vec![Value::Integer(1), Value::build_text("a")],
+            vec![Value::Integer(4), Value::Null],
+            vec![Value::Integer(5), Value::Null],
+            vec![Value::Integer(6), Value::build_text("b")],
+        ]
+    );
+}
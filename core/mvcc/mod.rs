@@ -37,7 +37,7 @@ pub mod database;
 pub mod persistent_storage;
 
 pub use clock::LocalClock;
-pub use database::MvStore;
+pub use database::{GcMetrics, MvStore};
 
 #[cfg(test)]
 mod tests {
@@ -0,0 +1,652 @@
+//! The logical log is a redo log of the transactions committed with MVCC.
+//!
+//! Every transaction that writes appends a [LogRecord] with the row and index entry versions it
+//! inserted and deleted, which is synced before the transaction writes them to the b-tree. After
+//! a crash, the records are replayed into the b-tree at open, and the log is then truncated. The
+//! log is also truncated while the database is open, once it grows past [TRUNCATE_THRESHOLD].
+//!
+//! The log is a sequence of records, each of them a header followed by a payload:
+//!
+//! | Offset | Size | Description                                |
+//! |--------|------|--------------------------------------------|
+//! | 0      | 4    | Payload size in bytes                      |
+//! | 4      | 8    | XXH3 checksum of the payload               |
+//! | 12     | 8    | Commit timestamp of the transaction        |
+//! | 20     | 4    | Number of row versions                     |
+//! | 24     | ...  | Row versions                               |
+//!
+//! and each row version is:
+//!
+//! | Size | Description                                                        |
+//! |------|--------------------------------------------------------------------|
+//! | 8    | Table ID, or index ID for an index entry                           |
+//! | 8    | Row ID, or rowid of the row an index entry points at               |
+//! | 4    | Number of columns                                                  |
+//! | 8    | Begin timestamp                                                    |
+//! | 1    | 1 if the version was deleted, 0 otherwise                          |
+//! | 8    | End timestamp, 0 if the version was not deleted                    |
+//! | 4    | Record size in bytes                                               |
+//! | ...  | Record                                                             |
+//!
+//! All integers are big-endian. A record whose checksum does not match was torn by a crash while
+//! it was being written, and ends the log.
+//!
+//! Transactions append their records before they take the commit lock, so that several of them
+//! can wait for the log at once (group commit). The records appended while a batch is being
+//! written form the next batch, which the first transaction to poll the log once the previous
+//! batch is durable writes and syncs with a single sync, and every transaction in it is resolved
+//! from that sync. Records are therefore not in commit order, but the transactions whose records
+//! are in flight together cannot have written the same rows.
+//!
+//! If writing or syncing a batch fails, only the transactions of that batch fail. The log is
+//! truncated back to the end of the last durable batch before the next one is written.
+
+use std::sync::{Arc, OnceLock};
+
+use parking_lot::Mutex;
+
+use crate::io::{Buffer, Completion, File, OpenFlags, IO};
+use crate::mvcc::database::{LogRecord, Row, RowID, RowVersion, TxTimestampOrID};
+use crate::{LimboError, Result};
+
+const RECORD_HEADER_SIZE: usize = 12;
+
+/// Size of the log past which it is truncated, once the transaction that grew it is in the
+/// b-tree.
+pub const TRUNCATE_THRESHOLD: u64 = 1024 * 1024;
+
+pub struct LogicalLog {
+    io: Arc<dyn IO>,
+    file: Arc<dyn File>,
+    state: Mutex<LogState>,
+}
+
+/// Whether a batch of records appended to the log, or a truncation of the log, is durable, which
+/// [LogicalLog::poll] drives the log until it knows.
+#[derive(Debug, Clone)]
+pub struct LogWrite(Arc<OnceLock<bool>>);
+
+impl LogWrite {
+    fn new() -> Self {
+        Self(Arc::new(OnceLock::new()))
+    }
+
+    fn resolve(&self, durable: bool) {
+        let _ = self.0.set(durable);
+    }
+
+    fn outcome(&self) -> Option<bool> {
+        self.0.get().copied()
+    }
+}
+
+/// Records appended to the log that are written together.
+#[derive(Debug)]
+struct Batch {
+    buf: Vec<u8>,
+    /// Whether any of the transactions of the batch needs it synced.
+    sync: bool,
+    write: LogWrite,
+}
+
+#[derive(Debug)]
+enum Phase {
+    Idle,
+    /// Writing a batch, which ends the log at `end`.
+    Writing {
+        end: u64,
+        sync: bool,
+        write: LogWrite,
+    },
+    Syncing {
+        end: u64,
+        write: LogWrite,
+    },
+    Truncating {
+        sync: bool,
+        write: LogWrite,
+    },
+    SyncingTruncation {
+        write: LogWrite,
+    },
+    /// Truncating the log back to its durable size after a write failed.
+    Rewinding,
+    SyncingRewind,
+}
+
+/// An IO of the log, which is started without holding the state lock.
+enum LogIo {
+    Write { offset: u64, buf: Vec<u8> },
+    Sync,
+    Truncate(u64),
+}
+
+/// The completion of the IO of the log in flight, which the connections waiting for the log all
+/// wait for.
+#[derive(Debug)]
+struct InFlight(Completion);
+
+// SAFETY: the completions of the log have callbacks that capture nothing, so other threads only
+// read whether they finished, which is behind a OnceLock.
+unsafe impl Send for InFlight {}
+
+#[derive(Debug)]
+struct LogState {
+    /// Size of the log up to the end of the last durable batch.
+    durable_size: u64,
+    /// Records appended since the batch in flight started.
+    pending: Option<Batch>,
+    /// A truncation requested by a transaction, with whether it is synced.
+    truncation: Option<(LogWrite, bool)>,
+    /// Whether a write failed, so the log must be truncated back to `durable_size` before it is
+    /// written again.
+    rewind: bool,
+    phase: Phase,
+    /// The IO of `phase`, None while a connection is starting it.
+    in_flight: Option<InFlight>,
+}
+
+impl LogState {
+    /// Picks the next IO of the log when it is idle.
+    fn start(&mut self) -> Option<LogIo> {
+        assert!(matches!(self.phase, Phase::Idle));
+        if self.rewind {
+            self.phase = Phase::Rewinding;
+            Some(LogIo::Truncate(self.durable_size))
+        } else if let Some((write, sync)) = self.truncation.take() {
+            // The log is rewound to nothing if the truncation fails, as everything it held is in
+            // the b-tree already
+            self.durable_size = 0;
+            self.phase = Phase::Truncating { sync, write };
+            Some(LogIo::Truncate(0))
+        } else if let Some(batch) = self.pending.take() {
+            let offset = self.durable_size;
+            self.phase = Phase::Writing {
+                end: offset + batch.buf.len() as u64,
+                sync: batch.sync,
+                write: batch.write,
+            };
+            Some(LogIo::Write {
+                offset,
+                buf: batch.buf,
+            })
+        } else {
+            None
+        }
+    }
+
+    /// Moves past the IO of the current phase, and returns the next IO of the same batch, if any.
+    /// Failures never start another IO.
+    fn finish(&mut self, ok: bool) -> Option<LogIo> {
+        let (next, io) = match std::mem::replace(&mut self.phase, Phase::Idle) {
+            Phase::Idle => unreachable!("no IO of the logical log is in flight"),
+            Phase::Writing { end, sync, write } if ok => {
+                if sync {
+                    (Phase::Syncing { end, write }, Some(LogIo::Sync))
+                } else {
+                    self.durable_size = end;
+                    write.resolve(true);
+                    (Phase::Idle, None)
+                }
+            }
+            Phase::Syncing { end, write } if ok => {
+                self.durable_size = end;
+                write.resolve(true);
+                (Phase::Idle, None)
+            }
+            Phase::Truncating { sync, write } if ok => {
+                if sync {
+                    (Phase::SyncingTruncation { write }, Some(LogIo::Sync))
+                } else {
+                    write.resolve(true);
+                    (Phase::Idle, None)
+                }
+            }
+            Phase::SyncingTruncation { write } if ok => {
+                write.resolve(true);
+                (Phase::Idle, None)
+            }
+            Phase::Rewinding if ok => (Phase::SyncingRewind, Some(LogIo::Sync)),
+            Phase::SyncingRewind if ok => {
+                self.rewind = false;
+                (Phase::Idle, None)
+            }
+            Phase::Writing { write, .. }
+            | Phase::Syncing { write, .. }
+            | Phase::Truncating { write, .. }
+            | Phase::SyncingTruncation { write } => {
+                write.resolve(false);
+                self.rewind = true;
+                (Phase::Idle, None)
+            }
+            Phase::Rewinding | Phase::SyncingRewind => {
+                // The records waiting for the log cannot be written where it ends, so they fail
+                // instead. The log is rewound again before the next batch.
+                if let Some(batch) = self.pending.take() {
+                    batch.write.resolve(false);
+                }
+                if let Some((write, _)) = self.truncation.take() {
+                    write.resolve(false);
+                }
+                (Phase::Idle, None)
+            }
+        };
+        self.phase = next;
+        io
+    }
+}
+
+impl std::fmt::Debug for LogicalLog {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LogicalLog")
+            .field("state", &*self.state.lock())
+            .finish()
+    }
+}
+
+impl LogicalLog {
+    pub fn open(io: Arc<dyn IO>, path: &str) -> Result<Self> {
+        let file = io.open_file(path, OpenFlags::Create, false)?;
+        Self::new(io, file)
+    }
+
+    fn new(io: Arc<dyn IO>, file: Arc<dyn File>) -> Result<Self> {
+        let durable_size = file.size()?;
+        Ok(Self {
+            io,
+            file,
+            state: Mutex::new(LogState {
+                durable_size,
+                pending: None,
+                truncation: None,
+                rewind: false,
+                phase: Phase::Idle,
+                in_flight: None,
+            }),
+        })
+    }
+
+    /// Adds `record` to the next batch written to the log, synced if `sync` is set, and returns
+    /// the outcome of the batch to [Self::poll].
+    pub fn append(&self, record: &LogRecord, sync: bool) -> LogWrite {
+        let buf = serialize_record(record);
+        let mut state = self.state.lock();
+        let batch = state.pending.get_or_insert_with(|| Batch {
+            buf: Vec::new(),
+            sync: false,
+            write: LogWrite::new(),
+        });
+        batch.buf.extend_from_slice(&buf);
+        batch.sync |= sync;
+        batch.write.clone()
+    }
+
+    /// Empties the log once the batch in flight is done, and returns the outcome of the
+    /// truncation to [Self::poll]. The records appended since are written after the truncation.
+    ///
+    /// The records of transactions that are waiting for the commit lock are discarded too. They
+    /// have not reported their commit yet, so a crash before they reach the b-tree loses them
+    /// like any transaction that did not commit.
+    pub fn truncate(&self, sync: bool) -> LogWrite {
+        let mut state = self.state.lock();
+        let (write, synced) = state
+            .truncation
+            .get_or_insert_with(|| (LogWrite::new(), false));
+        *synced |= sync;
+        write.clone()
+    }
+
+    /// Drives the log towards `write` being durable. Returns None once it is, or a completion to
+    /// wait for before polling again. Fails if the batch of `write` could not be made durable.
+    pub fn poll(&self, write: &LogWrite) -> Result<Option<Completion>> {
+        loop {
+            match write.outcome() {
+                Some(true) => return Ok(None),
+                Some(false) => {
+                    return Err(LimboError::InternalError(
+                        "writing the logical log failed".to_string(),
+                    ))
+                }
+                None => {}
+            }
+            let io = {
+                let mut state = self.state.lock();
+                match &state.in_flight {
+                    Some(InFlight(c)) if !c.finished() => return Ok(Some(c.clone())),
+                    Some(InFlight(c)) => {
+                        let ok = c.is_completed();
+                        state.in_flight = None;
+                        state.finish(ok)
+                    }
+                    // Another connection is starting the IO
+                    None if !matches!(state.phase, Phase::Idle) => {
+                        return Ok(Some(Completion::new_dummy()))
+                    }
+                    None => state.start(),
+                }
+            };
+            if let Some(io) = io {
+                self.start_io(io);
+            }
+        }
+    }
+
+    /// Starts `io` without holding the state lock, so that transactions can append to the next
+    /// batch while a sync is in progress.
+    fn start_io(&self, io: LogIo) {
+        let result = match io {
+            LogIo::Write { offset, buf } => self.file.pwrite(
+                offset,
+                Arc::new(Buffer::new(buf)),
+                Completion::new_write(|_| {}),
+            ),
+            LogIo::Sync => self.file.sync(Completion::new_sync(|_| {})),
+            LogIo::Truncate(len) => self.file.truncate(len, Completion::new_trunc(|_| {})),
+        };
+        let mut state = self.state.lock();
+        match result {
+            Ok(c) => state.in_flight = Some(InFlight(c)),
+            Err(e) => {
+                tracing::error!("logical log IO failed: {e}");
+                let next = state.finish(false);
+                debug_assert!(next.is_none());
+            }
+        }
+    }
+
+    /// Polls `write` until it is durable, blocking on the IO of the log.
+    pub fn wait(&self, write: &LogWrite) -> Result<()> {
+        while let Some(c) = self.poll(write)? {
+            while !c.finished() {
+                self.io.step()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads the records of the log, up to the first one torn by a crash.
+    pub fn read(&self) -> Result<Vec<LogRecord>> {
+        let size = self.file.size()? as usize;
+        if size == 0 {
+            return Ok(Vec::new());
+        }
+        let buffer = Arc::new(Buffer::new(vec![0; size]));
+        let c = Completion::new_read(buffer.clone(), |_| {});
+        let c = self.file.pread(0, c)?;
+        self.io.wait_for_completion(c)?;
+        let buf = buffer.as_slice();
+        let mut records = Vec::new();
+        let mut pos = 0;
+        while let Some((record, len)) = deserialize_record(&buf[pos..]) {
+            records.push(record);
+            pos += len;
+        }
+        if pos < size {
+            tracing::warn!(
+                "ignoring {} bytes torn at the end of the logical log",
+                size - pos
+            );
+        }
+        Ok(records)
+    }
+
+    /// Returns the size of the log once the records appended to it are written.
+    pub fn size(&self) -> u64 {
+        let state = self.state.lock();
+        let end = match &state.phase {
+            Phase::Writing { end, .. } | Phase::Syncing { end, .. } => *end,
+            _ => state.durable_size,
+        };
+        end + state
+            .pending
+            .as_ref()
+            .map_or(0, |batch| batch.buf.len() as u64)
+    }
+}
+
+fn serialize_record(record: &LogRecord) -> Vec<u8> {
+    let mut payload = Vec::new();
+    payload.extend_from_slice(&record.tx_timestamp.to_be_bytes());
+    payload.extend_from_slice(&(record.row_versions.len() as u32).to_be_bytes());
+    for version in &record.row_versions {
+        let row = &version.row;
+        payload.extend_from_slice(&row.id.table_id.to_be_bytes());
+        payload.extend_from_slice(&row.id.row_id.to_be_bytes());
+        payload.extend_from_slice(&(row.column_count as u32).to_be_bytes());
+        payload.extend_from_slice(&timestamp(&version.begin).to_be_bytes());
+        match &version.end {
+            Some(end) => {
+                payload.push(1);
+                payload.extend_from_slice(&timestamp(end).to_be_bytes());
+            }
+            None => {
+                payload.push(0);
+                payload.extend_from_slice(&0u64.to_be_bytes());
+            }
+        }
+        payload.extend_from_slice(&(row.data.len() as u32).to_be_bytes());
+        payload.extend_from_slice(&row.data);
+    }
+    let mut buf = Vec::with_capacity(RECORD_HEADER_SIZE + payload.len());
+    buf.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+    buf.extend_from_slice(&twox_hash::XxHash3_64::oneshot(&payload).to_be_bytes());
+    buf.extend_from_slice(&payload);
+    buf
+}
+
+fn timestamp(ts_or_id: &TxTimestampOrID) -> u64 {
+    match ts_or_id {
+        TxTimestampOrID::Timestamp(ts) => *ts,
+        // A version deleted by the transaction may have been inserted by another one that had not
+        // finished committing. Only whether a version was deleted matters to recovery.
+        TxTimestampOrID::TxID(_) => 0,
+    }
+}
+
+/// Deserializes the record at the start of `buf`, and returns it with its size, or None if `buf`
+/// does not start with a whole record.
+fn deserialize_record(buf: &[u8]) -> Option<(LogRecord, usize)> {
+    let mut reader = Reader { buf, pos: 0 };
+    let payload_len = reader.u32()? as usize;
+    let checksum = reader.u64()?;
+    let payload = reader.bytes(payload_len)?;
+    if twox_hash::XxHash3_64::oneshot(payload) != checksum {
+        return None;
+    }
+    let len = reader.pos;
+
+    let mut reader = Reader {
+        buf: payload,
+        pos: 0,
+    };
+    let mut record = LogRecord::new(reader.u64()?);
+    let count = reader.u32()?;
+    for _ in 0..count {
+        let id = RowID::new(reader.u64()?, reader.u64()? as i64);
+        let column_count = reader.u32()? as usize;
+        let begin = TxTimestampOrID::Timestamp(reader.u64()?);
+        let deleted = reader.bytes(1)?[0] != 0;
+        let end = reader.u64()?;
+        let end = deleted.then_some(TxTimestampOrID::Timestamp(end));
+        let data_len = reader.u32()? as usize;
+        let data = reader.bytes(data_len)?.to_vec();
+        record.row_versions.push(RowVersion {
+            begin,
+            end,
+            row: Row::new(id, data, column_count),
+        });
+    }
+    Some((record, len))
+}
+
+struct Reader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> Option<&'a [u8]> {
+        let bytes = self.buf.get(self.pos..self.pos.checked_add(len)?)?;
+        self.pos += len;
+        Some(bytes)
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        Some(u32::from_be_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Option<u64> {
+        Some(u64::from_be_bytes(self.bytes(8)?.try_into().unwrap()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CompletionError, MemoryIO};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn record(tx_timestamp: u64, row_ids: &[i64]) -> LogRecord {
+        let mut record = LogRecord::new(tx_timestamp);
+        for &row_id in row_ids {
+            record.row_versions.push(RowVersion {
+                begin: TxTimestampOrID::Timestamp(tx_timestamp),
+                end: (row_id % 2 == 0).then_some(TxTimestampOrID::Timestamp(tx_timestamp + 1)),
+                row: Row::new(RowID::new(2, row_id), vec![row_id as u8; 10], 3),
+            });
+        }
+        record
+    }
+
+    fn append(log: &LogicalLog, record: &LogRecord) {
+        log.wait(&log.append(record, true)).unwrap();
+    }
+
+    #[test]
+    fn test_append_read_truncate() {
+        let io: Arc<dyn IO> = Arc::new(MemoryIO::new());
+        let log = LogicalLog::open(io.clone(), "test.db-log").unwrap();
+        append(&log, &record(1, &[1, 2]));
+        append(&log, &record(3, &[3]));
+
+        let log = LogicalLog::open(io.clone(), "test.db-log").unwrap();
+        let records = log.read().unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].tx_timestamp, 1);
+        assert_eq!(records[0].row_versions, record(1, &[1, 2]).row_versions);
+        assert_eq!(records[1].row_versions, record(3, &[3]).row_versions);
+
+        log.wait(&log.truncate(true)).unwrap();
+        assert_eq!(log.size(), 0);
+        assert!(log.read().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_torn_record_ends_log() {
+        let io: Arc<dyn IO> = Arc::new(MemoryIO::new());
+        let log = LogicalLog::open(io.clone(), "test.db-log").unwrap();
+        append(&log, &record(1, &[1]));
+        append(&log, &record(2, &[2]));
+        // Tear the second record
+        let c = log
+            .file
+            .truncate(log.size() - 1, Completion::new_trunc(|_| {}))
+            .unwrap();
+        io.wait_for_completion(c).unwrap();
+
+        let records = log.read().unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].tx_timestamp, 1);
+    }
+
+    #[test]
+    fn test_records_appended_together_share_a_batch() {
+        let io: Arc<dyn IO> = Arc::new(MemoryIO::new());
+        let log = LogicalLog::open(io.clone(), "test.db-log").unwrap();
+        let first = log.append(&record(1, &[1]), true);
+        let second = log.append(&record(2, &[2]), false);
+        assert!(Arc::ptr_eq(&first.0, &second.0));
+        log.wait(&second).unwrap();
+        assert_eq!(first.outcome(), Some(true));
+
+        // Records appended once a batch started are written in the next one
+        let third = log.append(&record(3, &[3]), true);
+        assert!(!Arc::ptr_eq(&first.0, &third.0));
+        log.wait(&third).unwrap();
+        let records = log.read().unwrap();
+        let timestamps: Vec<u64> = records.iter().map(|r| r.tx_timestamp).collect();
+        assert_eq!(timestamps, vec![1, 2, 3]);
+    }
+
+    /// Fails the next `failing_syncs` syncs of the file.
+    struct FailingSyncFile {
+        inner: Arc<dyn File>,
+        failing_syncs: AtomicUsize,
+    }
+
+    impl File for FailingSyncFile {
+        fn lock_file(&self, exclusive: bool) -> Result<()> {
+            self.inner.lock_file(exclusive)
+        }
+
+        fn unlock_file(&self) -> Result<()> {
+            self.inner.unlock_file()
+        }
+
+        fn pread(&self, pos: u64, c: Completion) -> Result<Completion> {
+            self.inner.pread(pos, c)
+        }
+
+        fn pwrite(&self, pos: u64, buffer: Arc<Buffer>, c: Completion) -> Result<Completion> {
+            self.inner.pwrite(pos, buffer, c)
+        }
+
+        fn sync(&self, c: Completion) -> Result<Completion> {
+            let failing = self
+                .failing_syncs
+                .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1))
+                .is_ok();
+            if failing {
+                c.error(CompletionError::IOError(std::io::ErrorKind::Other));
+                return Ok(c);
+            }
+            self.inner.sync(c)
+        }
+
+        fn size(&self) -> Result<u64> {
+            self.inner.size()
+        }
+
+        fn truncate(&self, len: u64, c: Completion) -> Result<Completion> {
+            self.inner.truncate(len, c)
+        }
+    }
+
+    #[test]
+    fn test_failed_sync_fails_only_its_batch() {
+        let io: Arc<dyn IO> = Arc::new(MemoryIO::new());
+        let file = Arc::new(FailingSyncFile {
+            inner: io
+                .open_file("test.db-log", OpenFlags::Create, false)
+                .unwrap(),
+            failing_syncs: AtomicUsize::new(0),
+        });
+        let log = LogicalLog::new(io.clone(), file.clone()).unwrap();
+        append(&log, &record(1, &[1]));
+        let durable_size = log.size();
+
+        file.failing_syncs.store(1, Ordering::SeqCst);
+        let failed = log.append(&record(2, &[2]), true);
+        assert!(log.wait(&failed).is_err());
+        // The records of the failed batch are truncated away before the next batch is written
+        let next = log.append(&record(3, &[3]), true);
+        log.wait(&next).unwrap();
+        assert!(log.poll(&failed).is_err());
+
+        let records = log.read().unwrap();
+        let timestamps: Vec<u64> = records.iter().map(|r| r.tx_timestamp).collect();
+        assert_eq!(timestamps, vec![1, 3]);
+        assert_eq!(log.size(), file.size().unwrap());
+        assert!(log.size() > durable_size);
+    }
+}
@@ -1,25 +1,54 @@
 use std::fmt::Debug;
+use std::sync::Arc;
 
 use crate::mvcc::database::LogRecord;
-use crate::{LimboError, Result};
+use crate::{Completion, LimboError, Result, IO};
+
+mod logical_log;
+
+pub use logical_log::{LogWrite, LogicalLog, TRUNCATE_THRESHOLD};
 
 #[derive(Debug)]
 pub enum Storage {
     Noop,
+    LogicalLog(LogicalLog),
 }
 
 impl Storage {
     pub fn new_noop() -> Self {
         Self::Noop
     }
+
+    /// Opens the logical log at `path`, see [LogicalLog].
+    pub fn new_logical_log(io: Arc<dyn IO>, path: &str) -> Result<Self> {
+        Ok(Self::LogicalLog(LogicalLog::open(io, path)?))
+    }
 }
 
 impl Storage {
-    pub fn log_tx(&self, _m: LogRecord) -> Result<()> {
+    /// Appends the versions a transaction committed to the log, in a batch with the transactions
+    /// appending at the same time. They are durable once [Self::poll_tx_log] says so.
+    pub fn log_tx(&self, m: LogRecord, sync: bool) -> Option<LogWrite> {
+        match self {
+            Self::Noop => None,
+            Self::LogicalLog(log) => Some(log.append(&m, sync)),
+        }
+    }
+
+    /// Drives the log towards `write` being durable, see [LogicalLog::poll].
+    pub fn poll_tx_log(&self, write: &LogWrite) -> Result<Option<Completion>> {
+        match self {
+            Self::Noop => Ok(None),
+            Self::LogicalLog(log) => log.poll(write),
+        }
+    }
+
+    /// Polls `write` until it is durable, blocking on the IO of the log.
+    pub fn wait_tx_log(&self, write: &LogWrite) -> Result<()> {
         match self {
-            Self::Noop => (),
+            Self::Noop => Ok(()),
+            Self::LogicalLog(log) => log.wait(write),
         }
-        Ok(())
     }
 
     pub fn read_tx_log(&self) -> Result<Vec<LogRecord>> {
@@ -27,6 +56,24 @@ impl Storage {
             Self::Noop => Err(LimboError::InternalError(
                 "cannot read from Noop storage".to_string(),
             )),
+            Self::LogicalLog(log) => log.read(),
+        }
+    }
+
+    /// Discards the logged transactions, which must all have been written to the b-tree.
+    pub fn truncate_tx_log(&self, sync: bool) -> Option<LogWrite> {
+        match self {
+            Self::Noop => None,
+            Self::LogicalLog(log) => Some(log.truncate(sync)),
+        }
+    }
+
+    /// Whether the log grew past [TRUNCATE_THRESHOLD], in which case it is truncated once the
+    /// transaction that grew it is in the b-tree.
+    pub fn needs_truncation(&self) -> bool {
+        match self {
+            Self::Noop => false,
+            Self::LogicalLog(log) => log.size() >= TRUNCATE_THRESHOLD,
         }
     }
 }
@@ -35,6 +35,7 @@ pub fn pragma_for(pragma: &PragmaName) -> Pragma {
     use PragmaName::*;
 
     match pragma {
+        AnalysisLimit => Pragma::new(PragmaFlags::Result0, &["analysis_limit"]),
         ApplicationId => Pragma::new(
             PragmaFlags::NoColumns1 | PragmaFlags::Result0,
             &["application_id"],
@@ -51,10 +52,38 @@ pub fn pragma_for(pragma: &PragmaName) -> Pragma {
             &["data_sync_retry"],
         ),
         DatabaseList => Pragma::new(PragmaFlags::Result0, &["seq", "name", "file"]),
+        DeferForeignKeys => Pragma::new(
+            PragmaFlags::Result0 | PragmaFlags::NoColumns1,
+            &["defer_foreign_keys"],
+        ),
         Encoding => Pragma::new(
             PragmaFlags::Result0 | PragmaFlags::NoColumns1,
             &["encoding"],
         ),
+        ForeignKeyCheck => Pragma::new(
+            PragmaFlags::NeedSchema
+                | PragmaFlags::Result0
+                | PragmaFlags::Result1
+                | PragmaFlags::SchemaOpt,
+            &["table", "rowid", "parent", "fkid"],
+        ),
+        ForeignKeyList => Pragma::new(
+            PragmaFlags::NeedSchema | PragmaFlags::Result1 | PragmaFlags::SchemaOpt,
+            &[
+                "id",
+                "seq",
+                "table",
+                "from",
+                "to",
+                "on_update",
+                "on_delete",
+                "match",
+            ],
+        ),
+        ForeignKeys => Pragma::new(
+            PragmaFlags::Result0 | PragmaFlags::NoColumns1,
+            &["foreign_keys"],
+        ),
         JournalMode => Pragma::new(
             PragmaFlags::NeedSchema | PragmaFlags::Result0 | PragmaFlags::SchemaReq,
             &["journal_mode"],
@@ -66,6 +95,8 @@ pub fn pragma_for(pragma: &PragmaName) -> Pragma {
             PragmaFlags::NeedSchema | PragmaFlags::Result0 | PragmaFlags::SchemaReq,
             &["module_list"],
         ),
+        MvccMemoryLimit => Pragma::new(PragmaFlags::Result0, &["mvcc_memory_limit"]),
+        Optimize => Pragma::new(PragmaFlags::NeedSchema, &[]),
         PageCount => Pragma::new(
             PragmaFlags::NeedSchema | PragmaFlags::Result0 | PragmaFlags::SchemaReq,
             &["page_count"],
@@ -93,6 +124,10 @@ pub fn pragma_for(pragma: &PragmaName) -> Pragma {
             PragmaFlags::NeedSchema | PragmaFlags::Result1 | PragmaFlags::SchemaOpt,
             &["cid", "name", "type", "notnull", "dflt_value", "pk"],
         ),
+        TempStore => Pragma::new(
+            PragmaFlags::NoColumns1 | PragmaFlags::Result0,
+            &["temp_store"],
+        ),
         UserVersion => Pragma::new(
             PragmaFlags::NoColumns1 | PragmaFlags::Result0,
             &["user_version"],
@@ -114,6 +149,10 @@ pub fn pragma_for(pragma: &PragmaName) -> Pragma {
             PragmaFlags::Result0 | PragmaFlags::NoColumns1,
             &["query_only"],
         ),
+        QuickCheck => Pragma::new(
+            PragmaFlags::NeedSchema | PragmaFlags::ReadOnly | PragmaFlags::Result0,
+            &["message"],
+        ),
         FreelistCount => Pragma::new(PragmaFlags::Result0, &["freelist_count"]),
         EncryptionKey => Pragma::new(
             PragmaFlags::Result0 | PragmaFlags::SchemaReq | PragmaFlags::NoColumns1,
@@ -330,14 +369,14 @@ impl PragmaVirtualTableCursor {
 
         self.arg = arg;
 
-        if let Some(schema) = schema {
-            // Schema-qualified PRAGMA statements are not supported yet
-            return Err(LimboError::ParseError(format!(
-                "Schema argument is not supported yet (got schema: '{schema}')"
-            )));
-        }
-
-        let mut sql = format!("PRAGMA {}", self.pragma_name);
+        let mut sql = match schema {
+            Some(schema) => format!(
+                "PRAGMA \"{}\".{}",
+                schema.replace('"', "\"\""),
+                self.pragma_name
+            ),
+            None => format!("PRAGMA {}", self.pragma_name),
+        };
         if let Some(arg) = &self.arg {
             sql.push_str(&format!("=\"{arg}\""));
         }
@@ -12,12 +12,82 @@ pub struct View {
 /// Type alias for regular views collection
 pub type ViewsMap = HashMap<String, View>;
 
+/// A trigger parsed from its CREATE TRIGGER statement in sqlite_schema
+#[derive(Debug, Clone)]
+pub struct Trigger {
+    pub name: String,
+    pub sql: String,
+    /// The table (or view, for INSTEAD OF triggers) the trigger is attached to
+    pub table_name: String,
+    pub time: ast::TriggerTime,
+    pub event: ast::TriggerEvent,
+    pub when_clause: Option<Box<Expr>>,
+    pub commands: Vec<ast::TriggerCmd>,
+}
+
+impl Trigger {
+    pub fn from_sql(sql: &str) -> Result<Trigger> {
+        let mut parser = Parser::new(sql.as_bytes());
+        let cmd = parser.next_cmd()?;
+        match cmd {
+            Some(Cmd::Stmt(Stmt::CreateTrigger {
+                trigger_name,
+                time,
+                event,
+                tbl_name,
+                when_clause,
+                commands,
+                ..
+            })) => Ok(Trigger {
+                name: normalize_ident(trigger_name.name.as_str()),
+                sql: sql.to_string(),
+                table_name: normalize_ident(tbl_name.name.as_str()),
+                // BEFORE is the default when no time is specified
+                time: time.unwrap_or(ast::TriggerTime::Before),
+                event,
+                when_clause,
+                commands,
+            }),
+            _ => Err(LimboError::ParseError(format!(
+                "Expected CREATE TRIGGER statement, got: {sql}"
+            ))),
+        }
+    }
+
+    /// Whether the trigger fires for the given operation. For UPDATE triggers with an
+    /// `UPDATE OF` column list, `changed_columns` must contain one of the listed columns.
+    pub fn matches(
+        &self,
+        time: ast::TriggerTime,
+        event: &ast::TriggerEvent,
+        changed_columns: &[&str],
+    ) -> bool {
+        if self.time != time {
+            return false;
+        }
+        match (&self.event, event) {
+            (ast::TriggerEvent::Insert, ast::TriggerEvent::Insert)
+            | (ast::TriggerEvent::Delete, ast::TriggerEvent::Delete)
+            | (ast::TriggerEvent::Update, ast::TriggerEvent::Update) => true,
+            (ast::TriggerEvent::UpdateOf(columns), ast::TriggerEvent::Update) => {
+                columns.iter().any(|col| {
+                    let col = normalize_ident(col.as_str());
+                    changed_columns.iter().any(|c| c.eq_ignore_ascii_case(&col))
+                })
+            }
+            _ => false,
+        }
+    }
+}
+
 use crate::result::LimboResult;
 use crate::storage::btree::BTreeCursor;
 use crate::translate::collate::CollationSeq;
+use crate::translate::expr::{walk_expr, WalkControl};
 use crate::translate::plan::SelectPlan;
 use crate::util::{
     module_args_from_sql, module_name_from_sql, type_from_name, IOExt, UnparsedFromSqlIndex,
+    PRIMARY_KEY_AUTOMATIC_INDEX_NAME_PREFIX,
 };
 use crate::{
     contains_ignore_ascii_case, eq_ignore_ascii_case, match_ignore_ascii_case, LimboError,
@@ -40,16 +110,29 @@ use turso_parser::{
 
 const SCHEMA_TABLE_NAME: &str = "sqlite_schema";
 const SCHEMA_TABLE_NAME_ALT: &str = "sqlite_master";
+/// The names under which the schema table of the temp database is reachable without a
+/// `temp.` qualifier.
+const TEMP_SCHEMA_TABLE_NAME: &str = "sqlite_temp_schema";
+const TEMP_SCHEMA_TABLE_NAME_ALT: &str = "sqlite_temp_master";
 pub const DBSP_TABLE_PREFIX: &str = "__turso_internal_dbsp_state_";
+/// The table in which ANALYZE stores the statistics used by the query optimizer.
+pub const STAT1_TABLE_NAME: &str = "sqlite_stat1";
 
 /// Check if a table name refers to a system table that should be protected from direct writes
 pub fn is_system_table(table_name: &str) -> bool {
     let normalized = table_name.to_lowercase();
     normalized == SCHEMA_TABLE_NAME
         || normalized == SCHEMA_TABLE_NAME_ALT
+        || is_temp_schema_table(&normalized)
         || table_name.starts_with(DBSP_TABLE_PREFIX)
 }
 
+/// Check if a table name refers to the schema table of the temp database
+pub fn is_temp_schema_table(table_name: &str) -> bool {
+    table_name.eq_ignore_ascii_case(TEMP_SCHEMA_TABLE_NAME)
+        || table_name.eq_ignore_ascii_case(TEMP_SCHEMA_TABLE_NAME_ALT)
+}
+
 #[derive(Debug)]
 pub struct Schema {
     pub tables: HashMap<String, Arc<Table>>,
@@ -71,6 +154,40 @@ pub struct Schema {
 
     /// Mapping from table names to the materialized views that depend on them
     pub table_to_materialized_views: HashMap<String, Vec<String>>,
+
+    /// table_name to list of triggers on the table, in creation order
+    pub triggers: HashMap<String, Vec<Arc<Trigger>>>,
+
+    /// table_name to the statistics about the table and its indexes in sqlite_stat1
+    pub table_stats: HashMap<String, TableStats>,
+}
+
+/// Statistics about a table and its indexes, loaded from the rows of sqlite_stat1.
+#[derive(Debug, Clone, Default)]
+pub struct TableStats {
+    /// The number of rows in the table, from the row of sqlite_stat1 without an index.
+    pub row_count: Option<u64>,
+    /// index_name to the statistics about the index
+    pub indexes: HashMap<String, IndexStats>,
+}
+
+impl TableStats {
+    /// The estimated number of rows in the table. A table that has indexes only has rows
+    /// for its indexes in sqlite_stat1, which have as many entries as the table has rows.
+    pub fn estimated_row_count(&self) -> Option<u64> {
+        self.row_count
+            .or_else(|| self.indexes.values().map(|stats| stats.row_count).max())
+    }
+}
+
+/// Statistics about an index, parsed from the stat column of sqlite_stat1.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IndexStats {
+    /// The number of entries in the index.
+    pub row_count: u64,
+    /// The average number of entries that have the same values in the first 1, 2, ...
+    /// columns of the index.
+    pub rows_per_key: Vec<u64>,
 }
 
 impl Schema {
@@ -105,6 +222,36 @@ impl Schema {
             indexes_enabled,
             schema_version: 0,
             table_to_materialized_views,
+            triggers: HashMap::new(),
+            table_stats: HashMap::new(),
+        }
+    }
+
+    /// Records a row of sqlite_stat1. The stat column is a list of integers, the number of
+    /// rows followed, for an index, by the average number of rows per key of each prefix of
+    /// its columns. Rows that are malformed are ignored, as in SQLite.
+    pub fn add_stat1_row(&mut self, table_name: &str, index_name: Option<&str>, stat: &str) {
+        let mut counts = stat
+            .split_whitespace()
+            .map_while(|field| field.parse::<u64>().ok());
+        let Some(row_count) = counts.next() else {
+            return;
+        };
+        let table_stats = self
+            .table_stats
+            .entry(normalize_ident(table_name))
+            .or_default();
+        match index_name {
+            None => table_stats.row_count = Some(row_count),
+            Some(index_name) => {
+                table_stats.indexes.insert(
+                    normalize_ident(index_name),
+                    IndexStats {
+                        row_count,
+                        rows_per_key: counts.collect(),
+                    },
+                );
+            }
         }
     }
 
@@ -202,6 +349,65 @@ impl Schema {
         self.views.get(&name)
     }
 
+    pub fn add_trigger(&mut self, trigger: Trigger) {
+        let table_name = normalize_ident(&trigger.table_name);
+        self.triggers
+            .entry(table_name)
+            .or_default()
+            .push(Arc::new(trigger));
+    }
+
+    pub fn get_trigger(&self, name: &str) -> Option<&Arc<Trigger>> {
+        let name = normalize_ident(name);
+        self.triggers
+            .values()
+            .flat_map(|triggers| triggers.iter())
+            .find(|trigger| trigger.name == name)
+    }
+
+    /// Get all triggers attached to a table (or view), in creation order
+    pub fn get_triggers_for_table(&self, table_name: &str) -> &[Arc<Trigger>] {
+        let name = normalize_ident(table_name);
+        self.triggers
+            .get(&name)
+            .map_or_else(|| &[] as &[Arc<Trigger>], |v| v.as_slice())
+    }
+
+    /// Get the foreign keys that refer to a table, along with the tables that declare them
+    pub fn get_referencing_foreign_keys(
+        &self,
+        table_name: &str,
+    ) -> Vec<(Arc<BTreeTable>, Arc<ForeignKey>)> {
+        let name = normalize_ident(table_name);
+        let mut foreign_keys = self
+            .tables
+            .values()
+            .filter_map(|table| table.btree())
+            .flat_map(|child| {
+                child
+                    .foreign_keys
+                    .iter()
+                    .filter(|foreign_key| foreign_key.parent_table == name)
+                    .map(|foreign_key| (child.clone(), foreign_key.clone()))
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        // The order of the tables in the map is arbitrary
+        foreign_keys.sort_by(|(a, _), (b, _)| a.name.cmp(&b.name));
+        foreign_keys
+    }
+
+    pub fn remove_trigger(&mut self, name: &str) -> Result<()> {
+        let name = normalize_ident(name);
+        for triggers in self.triggers.values_mut() {
+            if let Some(pos) = triggers.iter().position(|t| t.name == name) {
+                triggers.remove(pos);
+                return Ok(());
+            }
+        }
+        Err(LimboError::ParseError(format!("no such trigger: {name}")))
+    }
+
     pub fn add_btree_table(&mut self, table: Arc<BTreeTable>) {
         let name = normalize_ident(&table.name);
         self.tables.insert(name, Table::BTree(table).into());
@@ -214,11 +420,12 @@ impl Schema {
 
     pub fn get_table(&self, name: &str) -> Option<Arc<Table>> {
         let name = normalize_ident(name);
-        let name = if name.eq_ignore_ascii_case(SCHEMA_TABLE_NAME_ALT) {
-            SCHEMA_TABLE_NAME
-        } else {
-            &name
-        };
+        let name =
+            if name.eq_ignore_ascii_case(SCHEMA_TABLE_NAME_ALT) || is_temp_schema_table(&name) {
+                SCHEMA_TABLE_NAME
+            } else {
+                &name
+            };
         self.tables.get(name).cloned()
     }
 
@@ -409,6 +616,28 @@ impl Schema {
             // Hence, we can process the singles first (unique_set.columns.len() == 1), and then the compounds (unique_set.columns.len() > 1).
             let table = self.get_btree_table(&automatic_index.0).unwrap();
             let mut automatic_indexes = automatic_index.1;
+            if !table.has_rowid {
+                // The primary key index of a WITHOUT ROWID table is the table b-tree itself.
+                // It has no sqlite_schema entry, but it still takes its place in the autoindex numbering.
+                let pk_pos = table
+                    .unique_sets
+                    .iter()
+                    .filter(|us| us.columns.len() == 1)
+                    .chain(table.unique_sets.iter().filter(|us| us.columns.len() > 1))
+                    .position(|us| us.is_primary_key)
+                    .ok_or_else(|| {
+                        LimboError::ParseError(format!(
+                            "PRIMARY KEY missing on table {}",
+                            table.name
+                        ))
+                    })?;
+                let index_name = format!(
+                    "{PRIMARY_KEY_AUTOMATIC_INDEX_NAME_PREFIX}{}_{}",
+                    table.name,
+                    pk_pos + 1
+                );
+                automatic_indexes.insert(pk_pos, (index_name, table.root_page));
+            }
             automatic_indexes.reverse(); // reverse so we can pop() without shifting array elements, while still processing in left-to-right order
             let mut pk_index_added = false;
             for unique_set in table.unique_sets.iter().filter(|us| us.columns.len() == 1) {
@@ -515,6 +744,7 @@ impl Schema {
                 has_rowid: true,
                 is_strict: false,
                 unique_sets: vec![],
+                foreign_keys: vec![],
             })));
 
             self.add_materialized_view(incremental_view, table, sql);
@@ -575,6 +805,12 @@ impl Schema {
                         dbsp_state_roots.insert(view_name, root_page as usize);
                     }
 
+                    // The primary key index of a WITHOUT ROWID table has no sqlite_schema entry,
+                    // make sure it is still populated along with the other automatic indexes.
+                    if !table.has_rowid {
+                        automatic_indices.entry(table.name.clone()).or_default();
+                    }
+
                     self.add_btree_table(Arc::new(table));
                 }
             }
@@ -659,6 +895,10 @@ impl Schema {
                     }
                 }
             }
+            "trigger" => {
+                let sql = maybe_sql.expect("sql should be present for trigger");
+                self.add_trigger(Trigger::from_sql(sql)?);
+            }
             _ => {}
         };
 
@@ -726,6 +966,8 @@ impl Clone for Schema {
             indexes_enabled: self.indexes_enabled,
             schema_version: self.schema_version,
             table_to_materialized_views: self.table_to_materialized_views.clone(),
+            triggers: self.triggers.clone(),
+            table_stats: self.table_stats.clone(),
         }
     }
 }
@@ -831,6 +1073,99 @@ pub struct BTreeTable {
     pub has_rowid: bool,
     pub is_strict: bool,
     pub unique_sets: Vec<UniqueSet>,
+    /// The FOREIGN KEY constraints of the table, in declaration order
+    pub foreign_keys: Vec<Arc<ForeignKey>>,
+}
+
+/// A FOREIGN KEY constraint, declared on the child table and referring to the parent table.
+#[derive(Clone, Debug)]
+pub struct ForeignKey {
+    /// The columns of the child table
+    pub child_columns: Vec<String>,
+    pub parent_table: String,
+    /// The referenced columns of the parent table, empty if the constraint refers to its primary key
+    pub parent_columns: Vec<String>,
+    pub on_delete: ast::RefAct,
+    pub on_update: ast::RefAct,
+    /// Whether the constraint is DEFERRABLE INITIALLY DEFERRED, i.e. checked at commit time
+    pub deferred: bool,
+}
+
+impl ForeignKey {
+    fn from_clause(
+        child_columns: Vec<String>,
+        clause: &ast::ForeignKeyClause,
+        deref_clause: Option<&ast::DeferSubclause>,
+    ) -> Result<ForeignKey> {
+        let parent_table = normalize_ident(clause.tbl_name.as_str());
+        let parent_columns = clause
+            .columns
+            .iter()
+            .map(|column| normalize_ident(column.col_name.as_str()))
+            .collect::<Vec<_>>();
+        if !parent_columns.is_empty() && parent_columns.len() != child_columns.len() {
+            return Err(LimboError::ParseError(
+                "number of columns in foreign key does not match the number of columns in the referenced table"
+                    .to_string(),
+            ));
+        }
+        let mut on_delete = ast::RefAct::NoAction;
+        let mut on_update = ast::RefAct::NoAction;
+        for arg in &clause.args {
+            match arg {
+                ast::RefArg::OnDelete(action) => on_delete = *action,
+                ast::RefArg::OnUpdate(action) => on_update = *action,
+                ast::RefArg::OnInsert(_) | ast::RefArg::Match(_) => {}
+            }
+        }
+        let deferred = deref_clause.is_some_and(|deref_clause| {
+            deref_clause.deferrable
+                && deref_clause.init_deferred == Some(ast::InitDeferredPred::InitiallyDeferred)
+        });
+        Ok(ForeignKey {
+            child_columns,
+            parent_table,
+            parent_columns,
+            on_delete,
+            on_update,
+            deferred,
+        })
+    }
+
+    /// The SQL of a foreign key action, e.g. `SET NULL`.
+    pub fn action_sql(action: ast::RefAct) -> &'static str {
+        match action {
+            ast::RefAct::SetNull => "SET NULL",
+            ast::RefAct::SetDefault => "SET DEFAULT",
+            ast::RefAct::Cascade => "CASCADE",
+            ast::RefAct::Restrict => "RESTRICT",
+            ast::RefAct::NoAction => "NO ACTION",
+        }
+    }
+
+    /// Reconstruct the SQL for the constraint, as a table constraint.
+    fn to_sql(&self) -> String {
+        let mut sql = format!(
+            "FOREIGN KEY ({}) REFERENCES {}",
+            self.child_columns.join(", "),
+            self.parent_table
+        );
+        if !self.parent_columns.is_empty() {
+            sql.push_str(&format!("({})", self.parent_columns.join(", ")));
+        }
+        if self.on_delete != ast::RefAct::NoAction {
+            sql.push_str(" ON DELETE ");
+            sql.push_str(Self::action_sql(self.on_delete));
+        }
+        if self.on_update != ast::RefAct::NoAction {
+            sql.push_str(" ON UPDATE ");
+            sql.push_str(Self::action_sql(self.on_update));
+        }
+        if self.deferred {
+            sql.push_str(" DEFERRABLE INITIALLY DEFERRED");
+        }
+        sql
+    }
 }
 
 impl BTreeTable {
@@ -911,14 +1246,157 @@ impl BTreeTable {
                 sql.push_str(" DEFAULT ");
                 sql.push_str(&default.to_string());
             }
+
+            if let Some(generated) = &column.generated {
+                sql.push(' ');
+                sql.push_str(&generated.to_sql());
+            }
+        }
+        for foreign_key in &self.foreign_keys {
+            sql.push_str(", ");
+            sql.push_str(&foreign_key.to_sql());
         }
         sql.push(')');
+        if !self.has_rowid {
+            sql.push_str(" WITHOUT ROWID");
+        }
         sql
     }
 
     pub fn column_collations(&self) -> Vec<Option<CollationSeq>> {
         self.columns.iter().map(|column| column.collation).collect()
     }
+
+    /// Returns the positions of the primary key columns in the table, in primary key order.
+    pub fn primary_key_column_positions(&self) -> Vec<usize> {
+        self.primary_key_columns
+            .iter()
+            .filter_map(|(name, _)| self.get_column(name).map(|(pos, _)| pos))
+            .collect()
+    }
+
+    /// Returns the position of a column in the records of the table.
+    /// A WITHOUT ROWID table is stored as an index on its primary key, so its records
+    /// hold the primary key columns first, followed by the remaining columns in table order.
+    /// VIRTUAL generated columns are not stored, so they have no position in the records.
+    pub fn column_pos_in_record(&self, pos_in_table: usize) -> usize {
+        if self.has_rowid && !self.has_virtual_columns() {
+            return pos_in_table;
+        }
+        self.record_column_positions()
+            .iter()
+            .position(|pos| *pos == pos_in_table)
+            .expect("VIRTUAL generated columns are not stored in records")
+    }
+
+    /// Returns the positions in the table of the columns of its records, in record order.
+    /// This is the inverse of [BTreeTable::column_pos_in_record].
+    pub fn record_column_positions(&self) -> Vec<usize> {
+        let mut positions = if self.has_rowid {
+            vec![]
+        } else {
+            self.primary_key_column_positions()
+        };
+        let rest = (0..self.columns.len())
+            .filter(|pos| !positions.contains(pos) && !self.columns[*pos].is_virtual_generated())
+            .collect::<Vec<_>>();
+        positions.extend(rest);
+        positions
+    }
+
+    /// Whether the records of the table hold every column in table order, in which case a
+    /// record can be built directly from the registers of a row.
+    pub fn stores_columns_in_table_order(&self) -> bool {
+        self.has_rowid && !self.has_virtual_columns()
+    }
+
+    pub fn has_virtual_columns(&self) -> bool {
+        self.columns
+            .iter()
+            .any(|column| column.is_virtual_generated())
+    }
+
+    pub fn has_generated_columns(&self) -> bool {
+        self.columns.iter().any(|column| column.is_generated())
+    }
+
+    /// Returns the positions of the columns that the expression of the generated column at
+    /// `pos` refers to.
+    pub fn generated_column_dependencies(&self, pos: usize) -> Result<Vec<usize>> {
+        let Some(generated) = &self.columns[pos].generated else {
+            return Ok(vec![]);
+        };
+        let mut dependencies = vec![];
+        walk_expr(&generated.expr, &mut |expr: &Expr| -> Result<WalkControl> {
+            let name = match expr {
+                Expr::Id(name) | Expr::Qualified(_, name) => name.as_str(),
+                _ => return Ok(WalkControl::Continue),
+            };
+            match self.get_column(name) {
+                Some((dependency, _)) => {
+                    if !dependencies.contains(&dependency) {
+                        dependencies.push(dependency);
+                    }
+                }
+                None => {
+                    return Err(LimboError::ParseError(format!("no such column: {name}")));
+                }
+            }
+            Ok(WalkControl::Continue)
+        })?;
+        Ok(dependencies)
+    }
+
+    /// Returns the positions of the generated columns, ordered so that every generated column
+    /// comes after the generated columns its expression refers to.
+    pub fn generated_columns_in_dependency_order(&self) -> Result<Vec<usize>> {
+        let mut ordered: Vec<usize> = vec![];
+        let mut pending = (0..self.columns.len())
+            .filter(|pos| self.columns[*pos].is_generated())
+            .map(|pos| Ok((pos, self.generated_column_dependencies(pos)?)))
+            .collect::<Result<Vec<_>>>()?;
+        while !pending.is_empty() {
+            let ready = pending.iter().position(|(_, dependencies)| {
+                dependencies.iter().all(|dependency| {
+                    !self.columns[*dependency].is_generated() || ordered.contains(dependency)
+                })
+            });
+            let Some(ready) = ready else {
+                let (pos, _) = pending[0];
+                return Err(LimboError::ParseError(format!(
+                    "generated column loop on \"{}\"",
+                    self.columns[pos].name.as_deref().unwrap_or_default()
+                )));
+            };
+            ordered.push(pending.remove(ready).0);
+        }
+        Ok(ordered)
+    }
+
+    /// Returns the primary key columns that an index of a WITHOUT ROWID table stores after
+    /// `key_columns` in place of a rowid, i.e. those that are not already among them.
+    pub fn primary_key_suffix_columns(&self, key_columns: &[IndexColumn]) -> Vec<IndexColumn> {
+        self.primary_key_columns
+            .iter()
+            .filter_map(|(name, order)| {
+                let (pos_in_table, column) = self.get_column(name)?;
+                if key_columns
+                    .iter()
+                    .any(|col| col.expr.is_none() && col.pos_in_table == pos_in_table)
+                {
+                    return None;
+                }
+                Some(IndexColumn {
+                    name: normalize_ident(name),
+                    order: *order,
+                    pos_in_table,
+                    collation: column.collation,
+                    default: column.default.clone(),
+                    expr: None,
+                })
+            })
+            .collect()
+    }
 }
 
 fn identifier_contains_special_chars(name: &str) -> bool {
@@ -954,6 +1432,43 @@ pub struct FromClauseSubquery {
     /// The start register for the result columns of the derived table;
     /// must be set before data is read from it.
     pub result_columns_start_reg: Option<usize>,
+    /// How the rows of the derived table are produced.
+    pub kind: SubqueryKind,
+}
+
+/// How the rows of a [FromClauseSubquery] are produced. Whatever the kind, the parent query
+/// reads the rows through a coroutine.
+#[derive(Debug, Clone)]
+pub enum SubqueryKind {
+    /// The plan runs inside the coroutine every time the derived table is scanned.
+    Coroutine,
+    /// A CTE declared `AS MATERIALIZED`, or referenced more than once without `NOT MATERIALIZED`:
+    /// the rows of the plan are computed into an ephemeral table once, and every scan of every
+    /// reference to the CTE reads that table.
+    Materialized,
+    /// A CTE of a `WITH RECURSIVE` clause that refers to itself. The plan is the initial select.
+    Recursive(RecursiveCte),
+    /// The reference to a recursive CTE from within one of its recursive selects. It yields the
+    /// row that the recursive CTE is currently processing, exactly once.
+    RecursiveReference,
+}
+
+/// The recursive part of a recursive CTE.
+///
+/// Rows are computed with a queue: the rows of the initial select are queued, then rows are
+/// taken from the queue one at a time, yielded to the parent query, and the recursive selects
+/// are run against that row, queueing the rows they return. This ends once the queue is empty.
+#[derive(Debug, Clone)]
+pub struct RecursiveCte {
+    /// The selects that refer to the CTE.
+    pub recursive_plans: Vec<SelectPlan>,
+    /// Whether the selects are combined with UNION rather than UNION ALL, in which case rows
+    /// that were queued before are not queued again.
+    pub distinct: bool,
+    /// The LIMIT of the CTE, the maximum number of rows taken from the queue.
+    pub limit: Option<Box<Expr>>,
+    /// The OFFSET of the CTE, the number of rows taken from the queue that are not yielded.
+    pub offset: Option<Box<Expr>>,
 }
 
 pub fn create_table(
@@ -968,6 +1483,8 @@ pub fn create_table(
     let mut cols = vec![];
     let is_strict: bool;
     let mut unique_sets: Vec<UniqueSet> = vec![];
+    let mut foreign_keys = vec![];
+    let mut table_foreign_keys = vec![];
     match body {
         CreateTableBody::ColumnsAndConstraints {
             columns,
@@ -1015,6 +1532,21 @@ pub fn create_table(
                         is_primary_key: false,
                     };
                     unique_sets.push(unique_set);
+                } else if let ast::TableConstraint::ForeignKey {
+                    columns,
+                    clause,
+                    deref_clause,
+                } = &c.constraint
+                {
+                    let child_columns = columns
+                        .iter()
+                        .map(|column| normalize_ident(column.col_name.as_str()))
+                        .collect();
+                    table_foreign_keys.push(Arc::new(ForeignKey::from_clause(
+                        child_columns,
+                        clause,
+                        deref_clause.as_ref(),
+                    )?));
                 }
             }
             for ast::ColumnDefinition {
@@ -1054,6 +1586,7 @@ pub fn create_table(
                 let mut order = SortOrder::Asc;
                 let mut unique = false;
                 let mut collation = None;
+                let mut generated = None;
                 for c_def in constraints {
                     match c_def.constraint {
                         ast::ColumnConstraint::PrimaryKey { order: o, .. } => {
@@ -1084,9 +1617,34 @@ pub fn create_table(
                         ast::ColumnConstraint::Collate { ref collation_name } => {
                             collation = Some(CollationSeq::new(collation_name.as_str())?);
                         }
+                        ast::ColumnConstraint::ForeignKey {
+                            ref clause,
+                            ref deref_clause,
+                        } => {
+                            if clause.columns.len() > 1 {
+                                return Err(LimboError::ParseError(format!(
+                                    "foreign key on {name} should reference only one column of table {}",
+                                    clause.tbl_name.as_str()
+                                )));
+                            }
+                            foreign_keys.push(Arc::new(ForeignKey::from_clause(
+                                vec![normalize_ident(&name)],
+                                clause,
+                                deref_clause.as_ref(),
+                            )?));
+                        }
+                        ast::ColumnConstraint::Generated { ref expr, ref typ } => {
+                            generated =
+                                Some(GeneratedColumn::from_constraint(&name, expr, typ.as_ref())?);
+                        }
                         _ => {}
                     }
                 }
+                if generated.is_some() && default.is_some() {
+                    return Err(LimboError::ParseError(
+                        "cannot use DEFAULT on a generated column".to_string(),
+                    ));
+                }
 
                 if primary_key {
                     primary_key_columns.push((name.clone(), order));
@@ -1108,15 +1666,42 @@ pub fn create_table(
                     unique,
                     collation,
                     hidden: false,
+                    generated: None,
+                    generated,
                 });
             }
             if options.contains(TableOptions::WITHOUT_ROWID) {
                 has_rowid = false;
             }
+            // Table constraints follow the column definitions
+            foreign_keys.extend(table_foreign_keys);
         }
         CreateTableBody::AsSelect(_) => todo!(),
     };
 
+    for foreign_key in &foreign_keys {
+        for column in &foreign_key.child_columns {
+            if !cols
+                .iter()
+                .any(|col| col.name.as_deref() == Some(column.as_str()))
+            {
+                return Err(LimboError::ParseError(format!(
+                    "unknown column \"{column}\" in foreign key definition"
+                )));
+            }
+        }
+    }
+
+    if let Some(column) = cols
+        .iter()
+        .find(|col| col.is_generated() && col.primary_key)
+    {
+        return Err(LimboError::ParseError(format!(
+            "generated columns cannot be part of the PRIMARY KEY: {}",
+            column.name.as_deref().unwrap_or_default()
+        )));
+    }
+
     // flip is_rowid_alias back to false if the table has multiple primary key columns
     // or if the table has no rowid
     if !has_rowid || primary_key_columns.len() > 1 {
@@ -1140,13 +1725,14 @@ pub fn create_table(
         }
     }
 
-    Ok(BTreeTable {
+    let table = BTreeTable {
         root_page,
         name: table_name,
         has_rowid,
         primary_key_columns,
         columns: cols,
         is_strict,
+        foreign_keys,
         unique_sets: {
             // If there are any unique sets that have identical column names in the same order (even if they are PRIMARY KEY and UNIQUE and have different sort orders), remove the duplicates.
             // Examples:
@@ -1176,7 +1762,10 @@ pub fn create_table(
             }
             unique_sets
         },
-    })
+    };
+    // Fails on references to unknown columns and on generated columns that depend on themselves
+    table.generated_columns_in_dependency_order()?;
+    Ok(table)
 }
 
 pub fn _build_pseudo_table(columns: &[ResultColumn]) -> PseudoCursorType {
@@ -1210,12 +1799,67 @@ pub struct Column {
     pub unique: bool,
     pub collation: Option<CollationSeq>,
     pub hidden: bool,
+    /// The expression of a generated column, see <https://www.sqlite.org/gencol.html>.
+    pub generated: Option<GeneratedColumn>,
 }
 
 impl Column {
     pub fn affinity(&self) -> Affinity {
         affinity(&self.ty_str)
     }
+
+    pub fn is_generated(&self) -> bool {
+        self.generated.is_some()
+    }
+
+    /// Whether the column is a VIRTUAL generated column. Its value is computed when it is read
+    /// and it has no place in the records of the table.
+    pub fn is_virtual_generated(&self) -> bool {
+        self.generated
+            .as_ref()
+            .is_some_and(|generated| generated.typ == GeneratedType::Virtual)
+    }
+}
+
+/// Whether the value of a generated column is computed when the column is read or when the row
+/// is written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GeneratedType {
+    Virtual,
+    Stored,
+}
+
+#[derive(Debug, Clone)]
+pub struct GeneratedColumn {
+    /// The `GENERATED ALWAYS AS (...)` expression, which refers to the other columns of the row
+    pub expr: Box<Expr>,
+    pub typ: GeneratedType,
+}
+
+impl GeneratedColumn {
+    fn from_constraint(column_name: &str, expr: &Expr, typ: Option<&ast::Name>) -> Result<Self> {
+        let typ = match typ.map(|typ| typ.as_str().to_ascii_uppercase()).as_deref() {
+            None | Some("VIRTUAL") => GeneratedType::Virtual,
+            Some("STORED") => GeneratedType::Stored,
+            Some(_) => {
+                return Err(LimboError::ParseError(format!(
+                    "error in generated column \"{column_name}\""
+                )))
+            }
+        };
+        Ok(Self {
+            expr: Box::new(expr.clone()),
+            typ,
+        })
+    }
+
+    pub fn to_sql(&self) -> String {
+        let typ = match self.typ {
+            GeneratedType::Virtual => "VIRTUAL",
+            GeneratedType::Stored => "STORED",
+        };
+        format!("GENERATED ALWAYS AS ({}) {typ}", self.expr)
+    }
 }
 
 // TODO: This might replace some of util::columns_from_create_table_body
@@ -1228,6 +1872,7 @@ impl From<&ColumnDefinition> for Column {
         let mut primary_key = false;
         let mut unique = false;
         let mut collation = None;
+        let mut generated = None;
 
         for ast::NamedColumnConstraint { constraint, .. } in &value.constraints {
             match constraint {
@@ -1243,6 +1888,9 @@ impl From<&ColumnDefinition> for Column {
                             .expect("collation should have been set correctly in create table"),
                     );
                 }
+                ast::ColumnConstraint::Generated { expr, typ } => {
+                    generated = GeneratedColumn::from_constraint(name, expr, typ.as_ref()).ok();
+                }
                 _ => {}
             };
         }
@@ -1271,6 +1919,7 @@ impl From<&ColumnDefinition> for Column {
             unique,
             collation,
             hidden,
+            generated,
         }
     }
 }
@@ -1485,6 +2134,7 @@ pub fn sqlite_schema_table() -> BTreeTable {
                 unique: false,
                 collation: None,
                 hidden: false,
+                generated: None,
             },
             Column {
                 name: Some("name".to_string()),
@@ -1497,6 +2147,7 @@ pub fn sqlite_schema_table() -> BTreeTable {
                 unique: false,
                 collation: None,
                 hidden: false,
+                generated: None,
             },
             Column {
                 name: Some("tbl_name".to_string()),
@@ -1509,6 +2160,7 @@ pub fn sqlite_schema_table() -> BTreeTable {
                 unique: false,
                 collation: None,
                 hidden: false,
+                generated: None,
             },
             Column {
                 name: Some("rootpage".to_string()),
@@ -1521,6 +2173,7 @@ pub fn sqlite_schema_table() -> BTreeTable {
                 unique: false,
                 collation: None,
                 hidden: false,
+                generated: None,
             },
             Column {
                 name: Some("sql".to_string()),
@@ -1533,9 +2186,11 @@ pub fn sqlite_schema_table() -> BTreeTable {
                 unique: false,
                 collation: None,
                 hidden: false,
+                generated: None,
             },
         ],
         unique_sets: vec![],
+        foreign_keys: vec![],
     }
 }
 
@@ -1551,9 +2206,16 @@ pub struct Index {
     /// Does the index have a rowid as the last column?
     /// This is the case for btree indexes (persistent or ephemeral) that
     /// have been created based on a table with a rowid.
-    /// For example, WITHOUT ROWID tables (not supported in Limbo yet),
+    /// For example, the indexes of WITHOUT ROWID tables,
     /// and  SELECT DISTINCT ephemeral indexes will not have a rowid.
     pub has_rowid: bool,
+    /// The number of leading columns that make up the key of the index, or None if all of them do.
+    /// An index of a WITHOUT ROWID table stores the primary key columns it does not already
+    /// contain after its own columns, and the primary key index (which is how the table itself
+    /// is stored) stores the rest of the table's columns after the primary key.
+    pub num_key_columns: Option<usize>,
+    /// The WHERE clause of a partial index, or None if the index covers every row of the table.
+    pub where_clause: Option<Box<Expr>>,
 }
 
 #[allow(dead_code)]
@@ -1566,11 +2228,19 @@ pub struct IndexColumn {
     /// CREATE TABLE t (a,b,c)
     /// CREATE INDEX idx ON t(b)
     /// b.pos_in_table == 1
+    ///
+    /// For a column of an expression index, this is [INDEX_EXPR_POS].
     pub pos_in_table: usize,
     pub collation: Option<CollationSeq>,
     pub default: Option<Box<Expr>>,
+    /// The indexed expression, if this is a column of an expression index, e.g. lower(b) in
+    /// CREATE INDEX idx ON t(lower(b))
+    pub expr: Option<Box<Expr>>,
 }
 
+/// The [IndexColumn::pos_in_table] of an index column on an expression rather than a table column.
+pub const INDEX_EXPR_POS: usize = usize::MAX;
+
 impl Index {
     pub fn from_sql(sql: &str, root_page: usize, table: &BTreeTable) -> Result<Index> {
         let mut parser = Parser::new(sql.as_bytes());
@@ -1581,12 +2251,24 @@ impl Index {
                 tbl_name,
                 columns,
                 unique,
+                where_clause,
                 ..
             })) => {
                 let index_name = normalize_ident(idx_name.name.as_str());
                 let mut index_columns = Vec::with_capacity(columns.len());
                 for col in columns.into_iter() {
                     let name = normalize_ident(&col.expr.to_string());
+                    if !matches!(col.expr.as_ref(), Expr::Id(_) | Expr::Name(_)) {
+                        index_columns.push(IndexColumn {
+                            name: col.expr.to_string(),
+                            order: col.order.unwrap_or(SortOrder::Asc),
+                            pos_in_table: INDEX_EXPR_POS,
+                            collation: None,
+                            default: None,
+                            expr: Some(col.expr),
+                        });
+                        continue;
+                    }
                     let Some((pos_in_table, _)) = table.get_column(&name) else {
                         return Err(crate::LimboError::InternalError(format!(
                             "Column {} is in index {} but not found in table {}",
@@ -1600,8 +2282,13 @@ impl Index {
                         pos_in_table,
                         collation: column.collation,
                         default: column.default.clone(),
+                        expr: None,
                     });
                 }
+                let num_key_columns = index_columns.len();
+                if !table.has_rowid {
+                    index_columns.extend(table.primary_key_suffix_columns(&index_columns));
+                }
                 Ok(Index {
                     name: index_name,
                     table_name: normalize_ident(tbl_name.as_str()),
@@ -1610,6 +2297,8 @@ impl Index {
                     unique,
                     ephemeral: false,
                     has_rowid: table.has_rowid,
+                    num_key_columns: (!table.has_rowid).then_some(num_key_columns),
+                    where_clause,
                 })
             }
             _ => todo!("Expected create index statement"),
@@ -1641,11 +2330,33 @@ impl Index {
                 pos_in_table,
                 collation: column.collation,
                 default: column.default.clone(),
+                expr: None,
             });
         }
 
         assert!(primary_keys.len() == column_count);
 
+        // The primary key index of a WITHOUT ROWID table is the table itself,
+        // so it stores the remaining columns after the primary key.
+        if !table.has_rowid {
+            for (pos_in_table, column) in table.columns.iter().enumerate() {
+                if primary_keys
+                    .iter()
+                    .any(|pk| pk.pos_in_table == pos_in_table)
+                {
+                    continue;
+                }
+                primary_keys.push(IndexColumn {
+                    name: normalize_ident(column.name.as_ref().unwrap()),
+                    order: SortOrder::Asc,
+                    pos_in_table,
+                    collation: column.collation,
+                    default: column.default.clone(),
+                    expr: None,
+                });
+            }
+        }
+
         Ok(Index {
             name: normalize_ident(index_name.as_str()),
             table_name: table.name.clone(),
@@ -1654,6 +2365,8 @@ impl Index {
             unique: true,
             ephemeral: false,
             has_rowid: table.has_rowid,
+            num_key_columns: (!table.has_rowid).then_some(column_count),
+            where_clause: None,
         })
     }
 
@@ -1664,7 +2377,7 @@ impl Index {
     ) -> Result<Index> {
         let (index_name, root_page) = auto_index;
 
-        let unique_cols = table
+        let mut unique_cols = table
             .columns
             .iter()
             .enumerate()
@@ -1678,9 +2391,15 @@ impl Index {
                     pos_in_table: *pos_in_table,
                     collation: col.collation,
                     default: col.default.clone(),
+                    expr: None,
                 })
             })
             .collect::<Vec<_>>();
+        let num_key_columns = unique_cols.len();
+        if !table.has_rowid {
+            let suffix = table.primary_key_suffix_columns(&unique_cols);
+            unique_cols.extend(suffix);
+        }
 
         Ok(Index {
             name: normalize_ident(index_name.as_str()),
@@ -1690,9 +2409,33 @@ impl Index {
             unique: true,
             ephemeral: false,
             has_rowid: table.has_rowid,
+            num_key_columns: (!table.has_rowid).then_some(num_key_columns),
+            where_clause: None,
         })
     }
 
+    /// The columns that make up the key of the index, i.e. the columns a UNIQUE index
+    /// enforces uniqueness on.
+    pub fn key_columns(&self) -> &[IndexColumn] {
+        &self.columns[..self.num_key_columns.unwrap_or(self.columns.len())]
+    }
+
+    /// Returns true if this is the primary key index of the WITHOUT ROWID table `table`,
+    /// i.e. the b-tree that holds the rows of the table rather than a separate index on it.
+    pub fn is_without_rowid_primary_key(&self, table: &BTreeTable) -> bool {
+        !table.has_rowid && !self.ephemeral && self.root_page == table.root_page
+    }
+
+    /// The name of the index in sqlite_stat1. Like SQLite, the statistics of the primary key
+    /// index of a WITHOUT ROWID table are recorded under the name of the table.
+    pub fn stat1_name<'a>(&'a self, table: &'a BTreeTable) -> &'a str {
+        if self.is_without_rowid_primary_key(table) {
+            &table.name
+        } else {
+            &self.name
+        }
+    }
+
     /// Given a column position in the table, return the position in the index.
     /// Returns None if the column is not found in the index.
     /// For example, given:
@@ -1988,6 +2731,37 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_foreign_keys() -> Result<()> {
+        let sql = r#"CREATE TABLE c (a REFERENCES p ON DELETE CASCADE, b, c, FOREIGN KEY (b, c) REFERENCES q(x, y) ON UPDATE SET NULL DEFERRABLE INITIALLY DEFERRED)"#;
+        let table = BTreeTable::from_sql(sql, 0)?;
+        assert_eq!(table.foreign_keys.len(), 2);
+        let column_fk = &table.foreign_keys[0];
+        assert_eq!(column_fk.child_columns, vec!["a"]);
+        assert_eq!(column_fk.parent_table, "p");
+        assert!(column_fk.parent_columns.is_empty());
+        assert_eq!(column_fk.on_delete, ast::RefAct::Cascade);
+        assert_eq!(column_fk.on_update, ast::RefAct::NoAction);
+        assert!(!column_fk.deferred);
+        let table_fk = &table.foreign_keys[1];
+        assert_eq!(table_fk.child_columns, vec!["b", "c"]);
+        assert_eq!(table_fk.parent_table, "q");
+        assert_eq!(table_fk.parent_columns, vec!["x", "y"]);
+        assert_eq!(table_fk.on_update, ast::RefAct::SetNull);
+        assert!(table_fk.deferred);
+        assert_eq!(
+            table.to_sql(),
+            "CREATE TABLE c (a, b, c, FOREIGN KEY (a) REFERENCES p ON DELETE CASCADE, FOREIGN KEY (b, c) REFERENCES q(x, y) ON UPDATE SET NULL DEFERRABLE INITIALLY DEFERRED)"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_foreign_key_column_count_mismatch() {
+        let sql = r#"CREATE TABLE c (a, b, FOREIGN KEY (a, b) REFERENCES p(x))"#;
+        assert!(BTreeTable::from_sql(sql, 0).is_err());
+    }
+
     #[test]
     #[should_panic]
     fn test_automatic_index_single_column() {
@@ -2018,6 +2792,64 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_without_rowid_record_column_positions() -> Result<()> {
+        let sql = r#"CREATE TABLE t1 (a, b, c, d, PRIMARY KEY(c, a)) WITHOUT ROWID;"#;
+        let table = BTreeTable::from_sql(sql, 0)?;
+        assert_eq!(table.record_column_positions(), vec![2, 0, 1, 3]);
+        assert_eq!(table.column_pos_in_record(0), 1);
+        assert_eq!(table.column_pos_in_record(1), 2);
+        assert_eq!(table.column_pos_in_record(2), 0);
+        assert_eq!(table.column_pos_in_record(3), 3);
+        Ok(())
+    }
+
+    #[test]
+    fn test_automatic_index_without_rowid_primary_key() -> Result<()> {
+        let sql = r#"CREATE TABLE t1 (a, b, c, PRIMARY KEY(b)) WITHOUT ROWID;"#;
+        let table = BTreeTable::from_sql(sql, 2)?;
+        let index =
+            Index::automatic_from_primary_key(&table, ("sqlite_autoindex_t1_1".to_string(), 2), 1)?;
+
+        assert!(index.unique);
+        assert!(!index.has_rowid);
+        assert!(index.is_without_rowid_primary_key(&table));
+        assert_eq!(index.key_columns().len(), 1);
+        assert_eq!(index.key_columns()[0].name, "b");
+        let names = index
+            .columns
+            .iter()
+            .map(|c| c.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["b", "a", "c"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_without_rowid_index_primary_key_suffix() -> Result<()> {
+        let table = BTreeTable::from_sql(
+            r#"CREATE TABLE t1 (a, b, c, PRIMARY KEY(a, b)) WITHOUT ROWID;"#,
+            2,
+        )?;
+        let index = Index::from_sql(r#"CREATE INDEX i1 ON t1 (c, b);"#, 3, &table)?;
+
+        assert!(!index.has_rowid);
+        assert!(!index.is_without_rowid_primary_key(&table));
+        let key_names = index
+            .key_columns()
+            .iter()
+            .map(|c| c.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(key_names, vec!["c", "b"]);
+        let names = index
+            .columns
+            .iter()
+            .map(|c| c.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["c", "b", "a"]);
+        Ok(())
+    }
+
     #[test]
     #[should_panic]
     fn test_automatic_index_no_primary_key() {
@@ -2047,8 +2879,10 @@ mod tests {
                 unique: false,
                 collation: None,
                 hidden: false,
+                generated: None,
             }],
             unique_sets: vec![],
+            foreign_keys: vec![],
         };
 
         let result =
@@ -2252,4 +3086,27 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_trigger_from_sql() -> Result<()> {
+        let sql = "CREATE TRIGGER \"Tr\" AFTER UPDATE OF a, b ON \"T1\" WHEN new.a > 0 BEGIN DELETE FROM t2; END";
+        let trigger = Trigger::from_sql(sql)?;
+        assert_eq!(trigger.name, "tr");
+        assert_eq!(trigger.table_name, "t1");
+        assert_eq!(trigger.sql, sql);
+        assert!(trigger.when_clause.is_some());
+        assert_eq!(trigger.commands.len(), 1);
+        assert!(trigger.matches(ast::TriggerTime::After, &ast::TriggerEvent::Update, &["B"]));
+        assert!(!trigger.matches(ast::TriggerTime::After, &ast::TriggerEvent::Update, &["c"]));
+        assert!(!trigger.matches(ast::TriggerTime::Before, &ast::TriggerEvent::Update, &["a"]));
+        assert!(!trigger.matches(ast::TriggerTime::After, &ast::TriggerEvent::Delete, &[]));
+        Ok(())
+    }
+
+    #[test]
+    fn test_trigger_from_sql_defaults_to_before() -> Result<()> {
+        let trigger = Trigger::from_sql("CREATE TRIGGER tr DELETE ON t1 BEGIN SELECT 1; END")?;
+        assert!(trigger.matches(ast::TriggerTime::Before, &ast::TriggerEvent::Delete, &[]));
+        Ok(())
+    }
 }
@@ -23,7 +23,7 @@ use crate::{
         RecordCursor, SeekResult,
     },
     util::IOExt,
-    Completion, MvCursor, Page,
+    Completion, MvCursor, MvIndexCursor, Page,
 };
 
 use crate::{
@@ -480,6 +480,8 @@ pub enum CursorSeekState {
 pub struct BTreeCursor {
     /// The multi-version cursor that is used to read and write to the database file.
     mv_cursor: Option<Rc<RefCell<MvCursor>>>,
+    /// The multi-version cursor that is used to read and write index entries.
+    mv_index_cursor: Option<Rc<RefCell<MvIndexCursor>>>,
     /// The pager that is used to read and write to the database file.
     pager: Rc<Pager>,
     /// Cached value of the usable space of a BTree page, since it is very expensive to call in a hot loop via pager.usable_space().
@@ -596,6 +598,7 @@ impl BTreeCursor {
         let usable_space = pager.usable_space();
         Self {
             mv_cursor,
+            mv_index_cursor: None,
             pager,
             root_page,
             usable_space_cached: usable_space,
@@ -643,13 +646,14 @@ impl BTreeCursor {
     }
 
     pub fn new_index(
-        mv_cursor: Option<Rc<RefCell<MvCursor>>>,
+        mv_index_cursor: Option<Rc<RefCell<MvIndexCursor>>>,
         pager: Rc<Pager>,
         root_page: usize,
         index: &Index,
         num_columns: usize,
     ) -> Self {
-        let mut cursor = Self::new(mv_cursor, pager, root_page, num_columns);
+        let mut cursor = Self::new(None, pager, root_page, num_columns);
+        cursor.mv_index_cursor = mv_index_cursor;
         cursor.index_info = Some(IndexInfo::new_from_index(index));
         cursor
     }
@@ -657,7 +661,7 @@ impl BTreeCursor {
     pub fn has_rowid(&self) -> bool {
         match &self.index_info {
             Some(index_key_info) => index_key_info.has_rowid,
-            None => true, // currently we don't support WITHOUT ROWID tables
+            None => true, // WITHOUT ROWID tables are read through index cursors
         }
     }
 
@@ -693,6 +697,10 @@ impl BTreeCursor {
                         let mv_cursor = mv_cursor.borrow();
                         return Ok(IOResult::Done(mv_cursor.is_empty()));
                     }
+                    if let Some(mv_index_cursor) = &self.mv_index_cursor {
+                        let mv_index_cursor = mv_index_cursor.borrow();
+                        return Ok(IOResult::Done(mv_index_cursor.is_empty()));
+                    }
                     let (page, c) = self.pager.read_page(self.root_page)?;
                     *self.is_empty_table_state.borrow_mut() = EmptyTableState::ReadPage { page };
                     if let Some(c) = c {
@@ -712,6 +720,9 @@ impl BTreeCursor {
     /// Used in backwards iteration.
     #[instrument(skip(self), level = Level::DEBUG, name = "prev")]
     pub fn get_prev_record(&mut self) -> Result<IOResult<bool>> {
+        if let Some(mv_index_cursor) = &self.mv_index_cursor {
+            return Ok(IOResult::Done(mv_index_cursor.borrow_mut().backward()));
+        }
         loop {
             let (old_top_idx, page_type, is_index, is_leaf, cell_count) = {
                 let page = self.stack.top_ref();
@@ -1228,6 +1239,8 @@ impl BTreeCursor {
                 }
                 None => return Ok(IOResult::Done(false)),
             }
+        } else if let Some(mv_index_cursor) = &self.mv_index_cursor {
+            return Ok(IOResult::Done(mv_index_cursor.borrow_mut().forward()));
         } else if self.stack.current_page == -1 {
             // This can happen in nested left joins. See:
             // https://github.com/tursodatabase/turso/issues/2924
@@ -4349,6 +4362,15 @@ impl BTreeCursor {
             match self.seek_to_last_state {
                 SeekToLastState::Start => {
                     assert!(self.mv_cursor.is_none());
+                    if let Some(mv_index_cursor) = &self.mv_index_cursor {
+                        let mut mv_index_cursor = mv_index_cursor.borrow_mut();
+                        mv_index_cursor.last();
+                        let has_record = !mv_index_cursor.is_empty();
+                        drop(mv_index_cursor);
+                        self.invalidate_record();
+                        self.has_record.replace(has_record);
+                        return Ok(IOResult::Done(()));
+                    }
                     let has_record = return_if_io!(self.move_to_rightmost());
                     self.invalidate_record();
                     self.has_record.replace(has_record);
@@ -4376,6 +4398,10 @@ impl BTreeCursor {
         self.root_page
     }
 
+    pub fn pager(&self) -> Rc<Pager> {
+        self.pager.clone()
+    }
+
     #[instrument(skip_all, level = Level::DEBUG)]
     pub fn rewind(&mut self) -> Result<IOResult<()>> {
         if self.valid_state == CursorValidState::Invalid {
@@ -4389,6 +4415,9 @@ impl BTreeCursor {
                     if let Some(mv_cursor) = &self.mv_cursor {
                         let mut mv_cursor = mv_cursor.borrow_mut();
                         mv_cursor.rewind();
+                    } else if let Some(mv_index_cursor) = &self.mv_index_cursor {
+                        let mut mv_index_cursor = mv_index_cursor.borrow_mut();
+                        mv_index_cursor.rewind();
                     } else {
                         let c = self.move_to_root()?;
                         if let Some(c) = c {
@@ -4410,6 +4439,15 @@ impl BTreeCursor {
     #[instrument(skip_all, level = Level::DEBUG)]
     pub fn last(&mut self) -> Result<IOResult<()>> {
         assert!(self.mv_cursor.is_none());
+        if let Some(mv_index_cursor) = &self.mv_index_cursor {
+            let mut mv_index_cursor = mv_index_cursor.borrow_mut();
+            mv_index_cursor.last();
+            let cursor_has_record = !mv_index_cursor.is_empty();
+            drop(mv_index_cursor);
+            self.has_record.replace(cursor_has_record);
+            self.invalidate_record();
+            return Ok(IOResult::Done(()));
+        }
         let cursor_has_record = return_if_io!(self.move_to_rightmost());
         self.has_record.replace(cursor_has_record);
         self.invalidate_record();
@@ -4490,6 +4528,13 @@ impl BTreeCursor {
             };
             return Ok(IOResult::Done(Some(rowid.row_id)));
         }
+        if self.mv_index_cursor.is_some() {
+            if !self.has_record.get() {
+                return Ok(IOResult::Done(None));
+            }
+            let _ = return_if_io!(self.record());
+            return Ok(IOResult::Done(self.get_index_rowid_from_record()));
+        }
         if self.get_null_flag() {
             return Ok(IOResult::Done(None));
         }
@@ -4516,6 +4561,15 @@ impl BTreeCursor {
             let mut mv_cursor = mv_cursor.borrow_mut();
             return mv_cursor.seek(key, op);
         }
+        if let Some(mv_index_cursor) = &self.mv_index_cursor {
+            let mut mv_index_cursor = mv_index_cursor.borrow_mut();
+            let seek_result = return_if_io!(mv_index_cursor.seek(key, op));
+            let has_record = !mv_index_cursor.is_empty();
+            drop(mv_index_cursor);
+            self.invalidate_record();
+            self.has_record.replace(has_record);
+            return Ok(IOResult::Done(seek_result));
+        }
         self.skip_advance.set(false);
         // Empty trace to capture the span information
         tracing::trace!("");
@@ -4569,6 +4623,25 @@ impl BTreeCursor {
                     .unwrap();
             return Ok(IOResult::Done(Some(record_ref)));
         }
+        if let Some(mv_index_cursor) = &self.mv_index_cursor {
+            let mv_index_cursor = mv_index_cursor.borrow();
+            let Some(record) = mv_index_cursor.current_record() else {
+                return Ok(IOResult::Done(None));
+            };
+            self.get_immutable_record_or_create()
+                .as_mut()
+                .unwrap()
+                .invalidate();
+            self.get_immutable_record_or_create()
+                .as_mut()
+                .unwrap()
+                .start_serialization(record.get_payload());
+            self.record_cursor.borrow_mut().invalidate();
+            let record_ref =
+                Ref::filter_map(self.reusable_immutable_record.borrow(), |opt| opt.as_ref())
+                    .unwrap();
+            return Ok(IOResult::Done(Some(record_ref)));
+        }
 
         let page = self.stack.top_ref();
         let contents = page.get_contents();
@@ -4630,8 +4703,17 @@ impl BTreeCursor {
                     let row = crate::mvcc::database::Row::new(row_id, record_buf, num_columns);
                     mv_cursor.borrow_mut().insert(row)?;
                 }
-                None => todo!("Support mvcc inserts with index btrees"),
+                None => unreachable!("index btrees are written through the MVCC index cursor"),
             },
+            None if self.mv_index_cursor.is_some() => {
+                let BTreeKey::IndexKey(record) = key else {
+                    unreachable!("index cursors are written with index keys");
+                };
+                let mv_index_cursor = self.mv_index_cursor.as_ref().unwrap();
+                mv_index_cursor.borrow_mut().insert(record)?;
+                self.invalidate_record();
+                self.has_record.replace(true);
+            }
             None => {
                 return_if_io!(self.insert_into_page(key));
                 if key.maybe_rowid().is_some() {
@@ -4661,6 +4743,10 @@ impl BTreeCursor {
             mv_cursor.borrow_mut().delete(rowid)?;
             return Ok(IOResult::Done(()));
         }
+        if let Some(mv_index_cursor) = &self.mv_index_cursor {
+            mv_index_cursor.borrow_mut().delete()?;
+            return Ok(IOResult::Done(()));
+        }
 
         if let CursorState::None = &self.state {
             self.state = CursorState::Delete(DeleteState::Start);
@@ -5444,8 +5530,11 @@ impl BTreeCursor {
     /// Only supposed to be used in the context of a simple Count Select Statement
     #[instrument(skip(self), level = Level::DEBUG)]
     pub fn count(&mut self) -> Result<IOResult<usize>> {
-        if let Some(_mv_cursor) = &self.mv_cursor {
-            todo!("Implement count for mvcc");
+        if let Some(mv_cursor) = &self.mv_cursor {
+            return Ok(IOResult::Done(mv_cursor.borrow_mut().count()));
+        }
+        if let Some(mv_index_cursor) = &self.mv_index_cursor {
+            return Ok(IOResult::Done(mv_index_cursor.borrow_mut().count()));
         }
 
         let mut mem_page;
@@ -5672,13 +5761,22 @@ pub enum IntegrityCheckError {
         references: Vec<u64>,
         page_category: PageCategory,
     },
-    #[error(
-        "Freelist count mismatch. actual_count={actual_count}, expected_count={expected_count}"
-    )]
+    #[error("Freelist: size is {actual_count} but should be {expected_count}")]
     FreelistCountMismatch {
         actual_count: usize,
         expected_count: usize,
     },
+    #[error(
+        "Page {page_id} cell {cell_idx}: overflow list length is {got} but should be {expected}"
+    )]
+    OverflowListLengthMismatch {
+        page_id: usize,
+        cell_idx: usize,
+        got: usize,
+        expected: usize,
+    },
+    #[error("Page {page_id}: never used")]
+    PageNeverUsed { page_id: u64 },
 }
 
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -5701,6 +5799,20 @@ struct IntegrityCheckPageEntry {
     level: usize,
     max_intkey: i64,
     page_category: PageCategory,
+    /// The position of an overflow page in the overflow chain of a cell.
+    overflow_chain: Option<OverflowChainPosition>,
+}
+
+/// Where an overflow page sits in the chain of overflow pages of a cell, used to check that the
+/// chain has as many pages as the payload of the cell needs.
+#[derive(Clone, Copy)]
+struct OverflowChainPosition {
+    /// The page holding the cell.
+    page_id: usize,
+    cell_idx: usize,
+    /// 1-based position of the page in the chain.
+    position: usize,
+    expected_length: usize,
 }
 pub struct IntegrityCheckState {
     page_stack: Vec<IntegrityCheckPageEntry>,
@@ -5747,12 +5859,67 @@ impl IntegrityCheckState {
                 level: 0,
                 max_intkey: i64::MAX,
                 page_category,
+                overflow_chain: None,
             },
             0,
             errors,
         );
     }
 
+    /// Pushes the first page of the overflow chain of a cell whose payload does not fit in the
+    /// page.
+    #[allow(clippy::too_many_arguments)]
+    fn push_overflow_chain(
+        &mut self,
+        page_id: usize,
+        cell_idx: usize,
+        first_overflow_page: u32,
+        payload_size: u64,
+        local_payload_size: usize,
+        usable_space: usize,
+        errors: &mut Vec<IntegrityCheckError>,
+    ) {
+        let overflow_size = payload_size as usize - local_payload_size;
+        self.push_page(
+            IntegrityCheckPageEntry {
+                page_idx: first_overflow_page as usize,
+                level: 0,
+                max_intkey: i64::MAX,
+                page_category: PageCategory::Overflow,
+                overflow_chain: Some(OverflowChainPosition {
+                    page_id,
+                    cell_idx,
+                    position: 1,
+                    // Every overflow page starts with the pointer to the next one
+                    expected_length: overflow_size.div_ceil(usable_space - 4),
+                }),
+            },
+            page_id as u64,
+            errors,
+        );
+    }
+
+    /// Reports the pages of the database that are neither part of a b-tree nor of the freelist.
+    /// Must only be called once every b-tree and the freelist have been checked.
+    pub fn check_page_usage(
+        &self,
+        database_size: usize,
+        page_size: usize,
+        errors: &mut Vec<IntegrityCheckError>,
+    ) {
+        // The page holding the byte at offset 2^30 is never used, see the lock-byte page in
+        // https://www.sqlite.org/fileformat.html
+        let pending_byte_page = (1usize << 30) / page_size + 1;
+        for page_id in 1..=database_size {
+            if page_id != pending_byte_page && !self.page_reference.contains_key(&(page_id as u64))
+            {
+                errors.push(IntegrityCheckError::PageNeverUsed {
+                    page_id: page_id as u64,
+                });
+            }
+        }
+    }
+
     fn push_page(
         &mut self,
         entry: IntegrityCheckPageEntry,
@@ -5784,7 +5951,7 @@ impl std::fmt::Debug for IntegrityCheckState {
 /// 2. There are no overlap between cells.
 /// 3. Cells do not scape outside expected range.
 /// 4. Depth of leaf pages are equal.
-/// 5. Overflow pages are correct (TODO)
+/// 5. Overflow chains have as many pages as the payload of their cell needs.
 ///
 /// In order to keep this reentrant, we keep a stack of pages we need to check. Ideally, like in
 /// SQLlite, we would have implemented a recursive solution which would make it easier to check the
@@ -5800,6 +5967,7 @@ pub fn integrity_check(
             page_category,
             level,
             max_intkey,
+            overflow_chain,
         }) = state.page_stack.last().cloned()
         else {
             return Ok(IOResult::Done(()));
@@ -5829,6 +5997,7 @@ pub fn integrity_check(
                         level,
                         max_intkey,
                         page_category: PageCategory::FreeListTrunk,
+                        overflow_chain: None,
                     },
                     page.get().id as u64,
                     errors,
@@ -5843,6 +6012,7 @@ pub fn integrity_check(
                         level,
                         max_intkey,
                         page_category: PageCategory::FreePage,
+                        overflow_chain: None,
                     },
                     page.get().id as u64,
                     errors,
@@ -5855,19 +6025,36 @@ pub fn integrity_check(
             continue;
         }
         if page_category == PageCategory::Overflow {
+            let chain = overflow_chain.expect("overflow page must be part of a chain");
             let next_overflow_page = contents.read_u32_no_offset(0);
-            if next_overflow_page != 0 {
-                state.push_page(
-                    IntegrityCheckPageEntry {
-                        page_idx: next_overflow_page as usize,
-                        level,
-                        max_intkey,
-                        page_category: PageCategory::Overflow,
-                    },
-                    page.get().id as u64,
-                    errors,
-                );
+            if chain.position == chain.expected_length {
+                // The pages the chain links to after this one are not needed by the cell, they
+                // are reported as never used.
+                continue;
+            }
+            if next_overflow_page == 0 {
+                errors.push(IntegrityCheckError::OverflowListLengthMismatch {
+                    page_id: chain.page_id,
+                    cell_idx: chain.cell_idx,
+                    got: chain.position,
+                    expected: chain.expected_length,
+                });
+                continue;
             }
+            state.push_page(
+                IntegrityCheckPageEntry {
+                    page_idx: next_overflow_page as usize,
+                    level,
+                    max_intkey,
+                    page_category: PageCategory::Overflow,
+                    overflow_chain: Some(OverflowChainPosition {
+                        position: chain.position + 1,
+                        ..chain
+                    }),
+                },
+                page.get().id as u64,
+                errors,
+            );
             continue;
         }
 
@@ -5917,6 +6104,7 @@ pub fn integrity_check(
                             level: level + 1,
                             max_intkey: table_interior_cell.rowid,
                             page_category: PageCategory::Normal,
+                            overflow_chain: None,
                         },
                         page.get().id as u64,
                         errors,
@@ -5958,14 +6146,13 @@ pub fn integrity_check(
                     }
                     next_rowid = rowid;
                     if let Some(first_overflow_page) = table_leaf_cell.first_overflow_page {
-                        state.push_page(
-                            IntegrityCheckPageEntry {
-                                page_idx: first_overflow_page as usize,
-                                level,
-                                max_intkey,
-                                page_category: PageCategory::Overflow,
-                            },
-                            page.get().id as u64,
+                        state.push_overflow_chain(
+                            page.get().id,
+                            cell_idx,
+                            first_overflow_page,
+                            table_leaf_cell.payload_size,
+                            table_leaf_cell.payload.len(),
+                            usable_space,
                             errors,
                         );
                     }
@@ -5977,19 +6164,19 @@ pub fn integrity_check(
                             level: level + 1,
                             max_intkey, // we don't care about intkey in non-table pages
                             page_category: PageCategory::Normal,
+                            overflow_chain: None,
                         },
                         page.get().id as u64,
                         errors,
                     );
                     if let Some(first_overflow_page) = index_interior_cell.first_overflow_page {
-                        state.push_page(
-                            IntegrityCheckPageEntry {
-                                page_idx: first_overflow_page as usize,
-                                level,
-                                max_intkey,
-                                page_category: PageCategory::Overflow,
-                            },
-                            page.get().id as u64,
+                        state.push_overflow_chain(
+                            page.get().id,
+                            cell_idx,
+                            first_overflow_page,
+                            index_interior_cell.payload_size,
+                            index_interior_cell.payload.len(),
+                            usable_space,
                             errors,
                         );
                     }
@@ -6008,14 +6195,13 @@ pub fn integrity_check(
                         state.first_leaf_level = Some(level);
                     }
                     if let Some(first_overflow_page) = index_leaf_cell.first_overflow_page {
-                        state.push_page(
-                            IntegrityCheckPageEntry {
-                                page_idx: first_overflow_page as usize,
-                                level,
-                                max_intkey,
-                                page_category: PageCategory::Overflow,
-                            },
-                            page.get().id as u64,
+                        state.push_overflow_chain(
+                            page.get().id,
+                            cell_idx,
+                            first_overflow_page,
+                            index_leaf_cell.payload_size,
+                            index_leaf_cell.payload.len(),
+                            usable_space,
                             errors,
                         );
                     }
@@ -6030,6 +6216,7 @@ pub fn integrity_check(
                     level: level + 1,
                     max_intkey,
                     page_category: PageCategory::Normal,
+                    overflow_chain: None,
                 },
                 page.get().id as u64,
                 errors,
@@ -8388,6 +8575,7 @@ mod tests {
                         collation: None,
                         pos_in_table: i,
                         default: None,
+                        expr: None,
                     })
                     .collect(),
                 table_name: "test".to_string(),
@@ -8395,6 +8583,8 @@ mod tests {
                 unique: false,
                 ephemeral: false,
                 has_rowid: false,
+                num_key_columns: None,
+                where_clause: None,
             };
             let num_columns = index_def.columns.len();
             let mut cursor = BTreeCursor::new_index(
@@ -8548,12 +8738,15 @@ mod tests {
                     collation: None,
                     pos_in_table: 0,
                     default: None,
+                    expr: None,
                 }],
                 table_name: "test".to_string(),
                 root_page: index_root_page,
                 unique: false,
                 ephemeral: false,
                 has_rowid: false,
+                num_key_columns: None,
+                where_clause: None,
             };
             let mut cursor =
                 BTreeCursor::new_index(None, pager.clone(), index_root_page, &index_def, 1);
@@ -6,7 +6,7 @@ use crate::storage::{
     sqlite3_ondisk::{
         self, parse_wal_frame_header, DatabaseHeader, PageContent, PageSize, PageType,
     },
-    wal::{CheckpointResult, Wal},
+    wal::{CheckpointResult, Wal, WalSavepoint},
 };
 use crate::types::{IOCompletions, WalState};
 use crate::util::IOExt as _;
@@ -17,7 +17,7 @@ use crate::{
 };
 use parking_lot::RwLock;
 use std::cell::{Cell, RefCell, UnsafeCell};
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::hash;
 use std::rc::Rc;
 use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
@@ -456,6 +456,18 @@ enum BtreeCreateVacuumFullState {
     PtrMapPut { allocated_page_id: u32 },
 }
 
+/// Contents of the pages dirtied by a write transaction, captured when a savepoint is opened.
+///
+/// Pages that are not part of the snapshot were unmodified at that point, or already spilled to
+/// the WAL by [Pager::cacheflush], so rolling back to the savepoint discards the frames appended
+/// since then, evicts those pages from the cache and lets them be read again from the WAL or
+/// database file.
+#[derive(Debug, Default)]
+pub struct PagerSavepoint {
+    pages: HashMap<usize, Vec<u8>>,
+    wal: Option<WalSavepoint>,
+}
+
 /// The pager interface implements the persistence layer by providing access
 /// to pages of the database file, including caching, concurrency control, and
 /// transaction management.
@@ -1057,8 +1069,9 @@ impl Pager {
             self.rollback(schema_did_change, connection, is_write)?;
             return Ok(IOResult::Done(PagerCommitResult::Rollback));
         }
+        // A checkpoint would make the commit impossible to roll back from the super-journal.
         let commit_status = return_if_io!(self.commit_dirty_pages(
-            connection.wal_auto_checkpoint_disabled.get(),
+            connection.wal_auto_checkpoint_disabled.get() || connection.has_super_journal(),
             connection.get_sync_mode(),
             connection.get_data_sync_retry()
         ));
@@ -1072,6 +1085,73 @@ impl Pager {
         Ok(IOResult::Done(commit_status))
     }
 
+    /// Ends a transaction on the temp database or on an attached database of `connection`.
+    /// Unlike [Pager::end_tx], the transaction state is tracked by the caller. The temp database
+    /// is never synced since its contents do not survive the connection.
+    ///
+    /// A write transaction committed while a super-journal is in place keeps its locks, so that
+    /// it can still be rolled back with [Pager::rewind_wal]. They are released by
+    /// [Pager::end_super_journal_tx].
+    #[instrument(skip_all, level = Level::DEBUG)]
+    pub fn end_attached_tx(
+        &self,
+        rollback: bool,
+        is_write: bool,
+        sync_mode: crate::SyncMode,
+        connection: &Connection,
+    ) -> Result<IOResult<()>> {
+        let Some(wal) = self.wal.as_ref() else {
+            return Ok(IOResult::Done(()));
+        };
+        if rollback {
+            if is_write {
+                wal.borrow().end_write_tx();
+            }
+            wal.borrow().end_read_tx();
+            self.rollback(false, connection, is_write)?;
+            return Ok(IOResult::Done(()));
+        }
+        if is_write {
+            return_if_io!(self.commit_dirty_pages(
+                connection.wal_auto_checkpoint_disabled.get() || connection.has_super_journal(),
+                sync_mode,
+                connection.get_data_sync_retry()
+            ));
+            if connection.has_super_journal() {
+                return Ok(IOResult::Done(()));
+            }
+            wal.borrow().end_write_tx();
+        }
+        wal.borrow().end_read_tx();
+        Ok(IOResult::Done(()))
+    }
+
+    /// Truncates the WAL back to its first `max_frame` frames, rolling back a commit made under
+    /// a super-journal, see [Wal::rewind].
+    pub fn rewind_wal(&self, max_frame: u64) -> Result<()> {
+        let Some(wal) = self.wal.as_ref() else {
+            return Ok(());
+        };
+        wal.borrow_mut().rewind(max_frame)
+    }
+
+    /// Releases the locks kept by a write transaction committed under a super-journal, once the
+    /// super-journal is deleted. With `rollback`, the commit was rolled back with
+    /// [Pager::rewind_wal], or did not complete.
+    pub fn end_super_journal_tx(&self, rollback: bool, connection: &Connection) -> Result<()> {
+        let Some(wal) = self.wal.as_ref() else {
+            return Ok(());
+        };
+        let result = if rollback {
+            self.rollback(false, connection, true)
+        } else {
+            Ok(())
+        };
+        wal.borrow().end_write_tx();
+        wal.borrow().end_read_tx();
+        result
+    }
+
     #[instrument(skip_all, level = Level::DEBUG)]
     pub fn end_read_tx(&self) -> Result<()> {
         let Some(wal) = self.wal.as_ref() else {
@@ -1638,7 +1718,8 @@ impl Pager {
         let mut checkpoint_result = self.io.block(|| wal.borrow_mut().checkpoint(self, mode))?;
 
         'ensure_sync: {
-            if checkpoint_result.num_backfilled != 0 {
+            // truncate mode reports zeros, but hands over the guard when it backfilled frames
+            if checkpoint_result.num_backfilled != 0 || checkpoint_result.holds_guard() {
                 if checkpoint_result.everything_backfilled() {
                     let db_size = self
                         .io
@@ -2159,6 +2240,108 @@ impl Pager {
         Ok(())
     }
 
+    /// Captures the current contents of every dirty page so that the transaction can later be
+    /// rolled back to this point with [Pager::rollback_to_savepoint].
+    pub fn savepoint(&self) -> PagerSavepoint {
+        let dirty_pages = self.dirty_pages.borrow();
+        let mut cache = self.page_cache.write();
+        let pages = dirty_pages
+            .iter()
+            .filter_map(|page_id| {
+                let page = cache.peek(&PageCacheKey::new(*page_id), false)?;
+                turso_assert!(page.is_loaded(), "dirty page {page_id} must be loaded");
+                Some((*page_id, page.get_contents().as_ptr().to_vec()))
+            })
+            .collect();
+        let wal = self.wal.as_ref().map(|wal| wal.borrow().savepoint());
+        PagerSavepoint { pages, wal }
+    }
+
+    /// Restores the pages modified by the current write transaction to their contents at the
+    /// time `savepoint` was taken.
+    pub fn rollback_to_savepoint(&self, savepoint: &PagerSavepoint) -> Result<()> {
+        // Frames spilled after the savepoint hold changes that are being rolled back.
+        if let (Some(wal), Some(wal_savepoint)) = (self.wal.as_ref(), savepoint.wal.as_ref()) {
+            wal.borrow_mut().rollback_to_savepoint(wal_savepoint);
+        }
+        let mut dirty_pages = self.dirty_pages.borrow_mut();
+        let mut cache = self.page_cache.write();
+        for page_id in dirty_pages.iter() {
+            if savepoint.pages.contains_key(page_id) {
+                continue;
+            }
+            // The page was first modified after the savepoint, or spilled before it: drop it so
+            // that the next read loads its version as of the savepoint.
+            let page_key = PageCacheKey::new(*page_id);
+            if let Some(page) = cache.peek(&page_key, false) {
+                page.clear_dirty();
+            }
+            cache.delete(page_key).map_err(|e| {
+                LimboError::InternalError(format!(
+                    "Failed to evict page {page_id} from cache: {e:?}"
+                ))
+            })?;
+        }
+        dirty_pages.clear();
+        for (page_id, contents) in savepoint.pages.iter() {
+            let page_key = PageCacheKey::new(*page_id);
+            let page = match cache.peek(&page_key, false) {
+                Some(page) => page,
+                None => {
+                    // Spilled and then evicted after the savepoint.
+                    let offset = if *page_id == DatabaseHeader::PAGE_ID {
+                        DatabaseHeader::SIZE
+                    } else {
+                        0
+                    };
+                    let page = allocate_new_page(*page_id, &self.buffer_pool, offset);
+                    cache.insert(page_key, page.clone()).map_err(|e| {
+                        LimboError::InternalError(format!(
+                            "Failed to insert page {page_id} into cache: {e:?}"
+                        ))
+                    })?;
+                    page
+                }
+            };
+            let page_contents = page.get_contents();
+            page_contents.as_ptr().copy_from_slice(contents);
+            page_contents.overflow_cells.clear();
+            // Any frame the page was spilled to after the savepoint is gone.
+            page.clear_wal_tag();
+            page.set_dirty();
+            dirty_pages.insert(*page_id);
+        }
+        Ok(())
+    }
+
+    /// Starts replacing the whole database with new pages, as done by VACUUM. The pages are then
+    /// added by [Pager::replace_page] and become dirty pages of the current write transaction,
+    /// which must not have modified anything yet.
+    pub fn begin_replacing_pages(&self) {
+        turso_assert!(
+            self.dirty_pages.borrow().is_empty(),
+            "pages can only be replaced by a write transaction without changes"
+        );
+        // Pages past the new end of the database must not be served from the cache again.
+        self.clear_page_cache();
+    }
+
+    /// Replaces page `page_id` with `contents`, see [Pager::begin_replacing_pages].
+    pub fn replace_page(&self, page_id: usize, contents: &[u8]) -> Result<()> {
+        let offset = if page_id == DatabaseHeader::PAGE_ID {
+            DatabaseHeader::SIZE
+        } else {
+            0
+        };
+        let page = allocate_new_page(page_id, &self.buffer_pool, offset);
+        page.get_contents().as_ptr().copy_from_slice(contents);
+        self.add_dirty(&page);
+        let mut cache = self.page_cache.write();
+        cache.insert(PageCacheKey::new(page_id), page).map_err(|e| {
+            LimboError::InternalError(format!("Failed to insert page {page_id} into cache: {e:?}"))
+        })
+    }
+
     fn reset_internal_states(&self) {
         self.checkpoint_state.replace(CheckpointState::Checkpoint);
         self.syncing.replace(false);
@@ -1653,6 +1653,7 @@ pub fn build_shared_wal(
         checkpoint_lock: TursoRwLock::new(),
         initialized: AtomicBool::new(false),
         epoch: AtomicU32::new(0),
+        wal_index: None,
     }));
 
     if size < WAL_HEADER_SIZE as u64 {
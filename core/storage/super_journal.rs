@@ -0,0 +1,251 @@
+//! Super-journals make a transaction that writes to several database files commit atomically.
+//!
+//! Each database file only commits atomically on its own, by appending a commit frame to its
+//! WAL. Like SQLite, a commit that spans more than one file first writes a super-journal next
+//! to the main database, listing every WAL written by the transaction together with the number
+//! of frames it held before the commit. The WALs are committed one by one and the super-journal
+//! is deleted once all of them are, which is the commit point of the whole transaction.
+//!
+//! Until the super-journal is deleted, every database file it lists stays locked for writing
+//! and keeps a read lock on the WAL as it was before the commit, so that neither another commit
+//! nor a checkpoint can make use of the frames being committed. If the commit fails midway, the
+//! WALs are truncated back to the frames they held before it, and only then is the
+//! super-journal deleted.
+//!
+//! If the process dies in between, the super-journal is still there the next time the main
+//! database is opened. Every WAL it lists is then truncated back to the frames it held before
+//! the commit, which rolls back the files that were already committed. The other database files
+//! point to the super-journal while the commit is in progress, so that the same happens when one
+//! of them is opened on its own.
+
+use std::sync::Arc;
+
+use crate::io::{Buffer, Completion};
+use crate::{OpenFlags, Result, IO};
+
+/// The suffix of super-journal files, followed by a random number: `<main db>-mjXXXXXXXX`.
+const SUPER_JOURNAL_SUFFIX: &str = "-mj";
+
+/// The suffix of the file holding the path of the super-journal of the commit in progress, next
+/// to the database files of that commit other than the main one: `<db>-sjptr`.
+const SUPER_JOURNAL_POINTER_SUFFIX: &str = "-sjptr";
+
+/// The size of the WAL header and of the header of each WAL frame.
+const WAL_HEADER_SIZE: u64 = 32;
+const WAL_FRAME_HEADER_SIZE: u64 = 24;
+
+/// A WAL written by a transaction that spans several database files.
+pub(crate) struct SuperJournalEntry {
+    /// The index of the database in the connection committing the transaction.
+    pub database_id: usize,
+    /// The path of the database, None for the main database, which holds the super-journal.
+    pub db_path: Option<String>,
+    pub wal_path: String,
+    /// The number of frames in the WAL before the transaction is committed.
+    pub max_frame: u64,
+}
+
+/// A super-journal written for a commit in progress.
+pub(crate) struct SuperJournal {
+    path: String,
+    entries: Vec<SuperJournalEntry>,
+}
+
+impl SuperJournal {
+    /// Writes and syncs a super-journal for the main database at `db_path`.
+    pub(crate) fn write(
+        io: &Arc<dyn IO>,
+        db_path: &str,
+        entries: Vec<SuperJournalEntry>,
+    ) -> Result<Self> {
+        let path = format!(
+            "{db_path}{SUPER_JOURNAL_SUFFIX}{:08X}",
+            io.generate_random_number() as u32
+        );
+        let content = entries
+            .iter()
+            .map(|entry| format!("{} {}\n", entry.max_frame, entry.wal_path))
+            .collect::<String>();
+        let file = io.open_file(&path, OpenFlags::Create, false)?;
+        let super_journal = Self { path, entries };
+        let written = (|| {
+            let buffer = Arc::new(Buffer::new(content.into_bytes()));
+            let c = file.pwrite(0, buffer, Completion::new_write(|_| {}))?;
+            io.wait_for_completion(c)?;
+            let c = file.sync(Completion::new_sync(|_| {}))?;
+            io.wait_for_completion(c)?;
+            sync_dir(&super_journal.path)?;
+            super_journal.write_pointers(io)
+        })();
+        if let Err(err) = written {
+            // Nothing is committed yet, the super-journal must not roll back later commits
+            let _ = super_journal.delete(io);
+            return Err(err);
+        }
+        Ok(super_journal)
+    }
+
+    /// Writes and syncs the pointers to the super-journal next to the databases it lists, other
+    /// than the main one.
+    fn write_pointers(&self, io: &Arc<dyn IO>) -> Result<()> {
+        // The other databases may be opened on their own from another directory
+        let path = std::fs::canonicalize(&self.path)
+            .map(|path| path.to_string_lossy().to_string())
+            .unwrap_or_else(|_| self.path.clone());
+        for db_path in self
+            .entries
+            .iter()
+            .filter_map(|entry| entry.db_path.as_deref())
+        {
+            let pointer_path = format!("{db_path}{SUPER_JOURNAL_POINTER_SUFFIX}");
+            let file = io.open_file(&pointer_path, OpenFlags::Create, false)?;
+            let buffer = Arc::new(Buffer::new(path.clone().into_bytes()));
+            let c = file.pwrite(0, buffer, Completion::new_write(|_| {}))?;
+            io.wait_for_completion(c)?;
+            // In case a pointer was left behind by a commit that failed to delete it
+            let c = file.truncate(path.len() as u64, Completion::new_trunc(|_| {}))?;
+            io.wait_for_completion(c)?;
+            let c = file.sync(Completion::new_sync(|_| {}))?;
+            io.wait_for_completion(c)?;
+            sync_dir(&pointer_path)?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn entries(&self) -> &[SuperJournalEntry] {
+        &self.entries
+    }
+
+    /// Deletes the super-journal, committing the transaction it was written for, or completing
+    /// its rollback once the WALs it lists are truncated.
+    pub(crate) fn delete(self, io: &Arc<dyn IO>) -> Result<()> {
+        io.remove_file(&self.path)?;
+        sync_dir(&self.path)?;
+        // A pointer left behind from here on points to no super-journal, and is deleted when its
+        // database is opened.
+        for db_path in self
+            .entries
+            .iter()
+            .filter_map(|entry| entry.db_path.as_deref())
+        {
+            let _ = io.remove_file(&format!("{db_path}{SUPER_JOURNAL_POINTER_SUFFIX}"));
+        }
+        Ok(())
+    }
+}
+
+/// Rolls back the transactions whose super-journals were left behind by a process that died while
+/// committing them: next to the main database at `db_path`, or pointed to from it if it was
+/// written by a transaction whose main database is another one.
+#[cfg(feature = "fs")]
+pub(crate) fn recover(io: &Arc<dyn IO>, db_path: &str) -> Result<()> {
+    let pointer_path = format!("{db_path}{SUPER_JOURNAL_POINTER_SUFFIX}");
+    if let Ok(super_journal_path) = std::fs::read_to_string(&pointer_path) {
+        let super_journal_path = std::path::Path::new(&super_journal_path);
+        // A pointer is synced before any WAL is written, so a torn one points to nothing
+        if super_journal_path.is_file() {
+            rollback(io, super_journal_path)?;
+        }
+        io.remove_file(&pointer_path)?;
+        sync_dir(&pointer_path)?;
+    }
+    let path = std::path::Path::new(db_path);
+    let Some(file_name) = path.file_name().and_then(|name| name.to_str()) else {
+        return Ok(());
+    };
+    let prefix = format!("{file_name}{SUPER_JOURNAL_SUFFIX}");
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => std::path::Path::new("."),
+    };
+    let Ok(dir_entries) = std::fs::read_dir(dir) else {
+        return Ok(());
+    };
+    for dir_entry in dir_entries.flatten() {
+        let name = dir_entry.file_name();
+        if !name.to_str().is_some_and(|name| name.starts_with(&prefix)) {
+            continue;
+        }
+        rollback(io, &dir_entry.path())?;
+    }
+    Ok(())
+}
+
+/// Rolls back the transaction of the super-journal at `super_journal_path` and deletes it.
+#[cfg(feature = "fs")]
+fn rollback(io: &Arc<dyn IO>, super_journal_path: &std::path::Path) -> Result<()> {
+    let content = std::fs::read_to_string(super_journal_path)
+        .map_err(|e| crate::LimboError::InternalError(format!("cannot read super-journal: {e}")))?;
+    for line in content.lines() {
+        let Some((max_frame, wal_path)) = line.split_once(' ') else {
+            // A super-journal is synced before any WAL is written, so a torn one means that
+            // nothing needs to be rolled back.
+            break;
+        };
+        let Ok(max_frame) = max_frame.parse::<u64>() else {
+            break;
+        };
+        truncate_wal(io, wal_path, max_frame)?;
+    }
+    let super_journal_path = super_journal_path.to_string_lossy();
+    tracing::info!("rolled back the transaction of super-journal {super_journal_path}");
+    io.remove_file(&super_journal_path)?;
+    sync_dir(&super_journal_path)
+}
+
+/// Syncs the directory holding the file at `path`, which makes the creation or the deletion of
+/// that file durable.
+#[cfg(all(feature = "fs", unix))]
+fn sync_dir(path: &str) -> Result<()> {
+    let dir = match std::path::Path::new(path).parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => std::path::Path::new("."),
+    };
+    // Not every IO backend is backed by the file system, there is nothing to sync then
+    if let Ok(dir) = std::fs::File::open(dir) {
+        dir.sync_all()?;
+    }
+    Ok(())
+}
+
+#[cfg(not(all(feature = "fs", unix)))]
+fn sync_dir(_path: &str) -> Result<()> {
+    Ok(())
+}
+
+/// Truncates the WAL at `wal_path` to its first `max_frame` frames.
+#[cfg(feature = "fs")]
+fn truncate_wal(io: &Arc<dyn IO>, wal_path: &str, max_frame: u64) -> Result<()> {
+    if !std::path::Path::new(wal_path).exists() {
+        return Ok(());
+    }
+    let file = io.open_file(wal_path, OpenFlags::None, false)?;
+    let size = file.size()?;
+    if size < WAL_HEADER_SIZE {
+        return Ok(());
+    }
+    let page_size = read_wal_page_size(io, &file)?;
+    if page_size == 0 {
+        return Ok(());
+    }
+    let len = WAL_HEADER_SIZE + max_frame * (WAL_FRAME_HEADER_SIZE + page_size);
+    if len >= size {
+        return Ok(());
+    }
+    let c = file.truncate(len, Completion::new_trunc(|_| {}))?;
+    io.wait_for_completion(c)?;
+    let c = file.sync(Completion::new_sync(|_| {}))?;
+    io.wait_for_completion(c)?;
+    Ok(())
+}
+
+/// Reads the page size stored in bytes 8..12 of a WAL header.
+#[cfg(feature = "fs")]
+fn read_wal_page_size(io: &Arc<dyn IO>, file: &Arc<dyn crate::io::File>) -> Result<u64> {
+    let buffer = Arc::new(Buffer::new_temporary(WAL_HEADER_SIZE as usize));
+    let c = Completion::new_read(buffer.clone(), |_| {});
+    let c = file.pread(0, c)?;
+    io.wait_for_completion(c)?;
+    let header = buffer.as_slice();
+    Ok(u32::from_be_bytes([header[8], header[9], header[10], header[11]]) as u64)
+}
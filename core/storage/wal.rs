@@ -15,8 +15,9 @@ use std::{cell::Cell, fmt, rc::Rc, sync::Arc};
 use super::buffer_pool::BufferPool;
 use super::pager::{PageRef, Pager};
 use super::sqlite3_ondisk::{self, checksum_wal, WalHeader, WAL_MAGIC_BE, WAL_MAGIC_LE};
+use super::wal_index::{CheckpointInfo, WalIndex, WalIndexHeader, WalIndexLock, WAL_NREADER};
 use crate::fast_lock::SpinLock;
-use crate::io::{clock, File, IO};
+use crate::io::{clock, File, OpenFlags, IO};
 use crate::result::LimboResult;
 use crate::storage::database::EncryptionOrChecksum;
 use crate::storage::sqlite3_ondisk::{
@@ -60,6 +61,10 @@ impl CheckpointResult {
     pub const fn everything_backfilled(&self) -> bool {
         self.num_attempted == self.num_backfilled
     }
+    /// Whether the checkpoint locks are still held so that the db file can be truncated.
+    pub(crate) fn holds_guard(&self) -> bool {
+        self.maybe_guard.is_some()
+    }
     pub fn release_guard(&mut self) {
         let _ = self.maybe_guard.take();
     }
@@ -223,6 +228,14 @@ impl TursoRwLock {
     }
 }
 
+/// Position in the frames appended by a write transaction, captured when a savepoint is opened.
+#[derive(Debug, Clone, Copy)]
+pub struct WalSavepoint {
+    max_frame: u64,
+    last_checksum: (u32, u32),
+    uncommitted_frames: usize,
+}
+
 /// Write-ahead log (WAL).
 pub trait Wal: Debug {
     /// Begin a read transaction.
@@ -302,6 +315,18 @@ pub trait Wal: Debug {
     fn get_min_frame(&self) -> u64;
     fn rollback(&mut self) -> Result<()>;
 
+    /// Truncate the WAL back to its first `max_frame` frames, rolling back the commits made
+    /// after them. The write lock must be held, along with a read lock keeping checkpoints from
+    /// backfilling those commits.
+    fn rewind(&mut self, max_frame: u64) -> Result<()>;
+
+    /// Capture the frames appended so far by the current write transaction.
+    fn savepoint(&self) -> WalSavepoint;
+
+    /// Discard the frames appended by the current write transaction after `savepoint` was
+    /// taken, so that the next append overwrites them.
+    fn rollback_to_savepoint(&mut self, savepoint: &WalSavepoint);
+
     /// Return unique set of pages changed **after** frame_watermark position and until current WAL session max_frame_no
     fn changed_pages_after(&self, frame_watermark: u64) -> Result<Vec<u32>>;
 
@@ -589,6 +614,12 @@ pub struct WalFile {
     checkpoint_guard: Option<CheckpointLocks>,
 
     io_ctx: RefCell<IOContext>,
+
+    /// Frames appended by the ongoing write transaction with the pages they hold, added to the
+    /// wal-index when the transaction commits.
+    uncommitted_frames: Vec<(u64, u64)>,
+    /// Database size in pages stored in the last commit frame appended.
+    db_size: u32,
 }
 
 impl fmt::Debug for WalFile {
@@ -674,8 +705,8 @@ pub struct WalFileShared {
     // Frame cache maps a Page to all the frames it has stored in WAL in ascending order.
     // This is to easily find the frame it must checkpoint each connection if a checkpoint is
     // necessary.
-    // One difference between SQLite and limbo is that we will never support multi process, meaning
-    // we don't need WAL's index file. So we can do stuff like this without shared memory.
+    // Unlike SQLite, each process keeps its own map of the WAL instead of looking frames up in
+    // the wal-index, and only reads the frames appended by other processes from the wal-index.
     // TODO: this will need refactoring because this is incredible memory inefficient.
     pub frame_cache: Arc<SpinLock<HashMap<u64, Vec<u64>>>>,
     pub last_checksum: (u32, u32), // Check of last frame in WAL, this is a cumulative checksum over all frames in the WAL
@@ -698,6 +729,9 @@ pub struct WalFileShared {
     /// Increments on each checkpoint, used to prevent stale cached pages being used for
     /// backfilling.
     pub epoch: AtomicU32,
+    /// The wal-index through which the WAL is shared with other processes, if it is shared. The
+    /// locks above are then also taken in the wal-index, see [WalFileShared::try_lock].
+    pub(crate) wal_index: Option<Arc<WalIndex>>,
 }
 
 impl fmt::Debug for WalFileShared {
@@ -735,41 +769,41 @@ impl CheckpointLocks {
     fn new(ptr: Arc<RwLock<WalFileShared>>, mode: CheckpointMode) -> Result<Self> {
         let ptr_clone = ptr.clone();
         {
-            let shared = ptr.write();
-            if !shared.checkpoint_lock.write() {
+            let shared = ptr.read();
+            if !shared.try_lock(WalIndexLock::Checkpoint, true) {
                 tracing::trace!("CheckpointGuard::new: checkpoint lock failed, returning Busy");
                 return Err(LimboError::Busy);
             }
             match mode {
                 CheckpointMode::Passive { .. } => {
-                    if !shared.read_locks[0].write() {
-                        shared.checkpoint_lock.unlock();
+                    if !shared.try_lock(WalIndexLock::Read(0), true) {
+                        shared.unlock(WalIndexLock::Checkpoint);
                         tracing::trace!("CheckpointGuard: read0 lock failed, returning Busy");
                         return Err(LimboError::Busy);
                     }
                 }
                 CheckpointMode::Full => {
-                    if !shared.read_locks[0].write() {
-                        shared.checkpoint_lock.unlock();
+                    if !shared.try_lock(WalIndexLock::Read(0), true) {
+                        shared.unlock(WalIndexLock::Checkpoint);
                         tracing::trace!("CheckpointGuard: read0 lock failed (Full), Busy");
                         return Err(LimboError::Busy);
                     }
-                    if !shared.write_lock.write() {
-                        shared.read_locks[0].unlock();
-                        shared.checkpoint_lock.unlock();
+                    if !shared.try_lock(WalIndexLock::Write, true) {
+                        shared.unlock(WalIndexLock::Read(0));
+                        shared.unlock(WalIndexLock::Checkpoint);
                         tracing::trace!("CheckpointGuard: write lock failed (Full), Busy");
                         return Err(LimboError::Busy);
                     }
                 }
                 CheckpointMode::Restart | CheckpointMode::Truncate { .. } => {
-                    if !shared.read_locks[0].write() {
-                        shared.checkpoint_lock.unlock();
+                    if !shared.try_lock(WalIndexLock::Read(0), true) {
+                        shared.unlock(WalIndexLock::Checkpoint);
                         tracing::trace!("CheckpointGuard: read0 lock failed, returning Busy");
                         return Err(LimboError::Busy);
                     }
-                    if !shared.write_lock.write() {
-                        shared.checkpoint_lock.unlock();
-                        shared.read_locks[0].unlock();
+                    if !shared.try_lock(WalIndexLock::Write, true) {
+                        shared.unlock(WalIndexLock::Checkpoint);
+                        shared.unlock(WalIndexLock::Read(0));
                         tracing::trace!("CheckpointGuard: write lock failed, returning Busy");
                         return Err(LimboError::Busy);
                     }
@@ -790,15 +824,15 @@ impl Drop for CheckpointLocks {
     fn drop(&mut self) {
         match self {
             CheckpointLocks::Writer { ptr: shared } => {
-                let guard = shared.write();
-                guard.write_lock.unlock();
-                guard.read_locks[0].unlock();
-                guard.checkpoint_lock.unlock();
+                let guard = shared.read();
+                guard.unlock(WalIndexLock::Write);
+                guard.unlock(WalIndexLock::Read(0));
+                guard.unlock(WalIndexLock::Checkpoint);
             }
             CheckpointLocks::Read0 { ptr: shared } => {
-                let guard = shared.write();
-                guard.read_locks[0].unlock();
-                guard.checkpoint_lock.unlock();
+                let guard = shared.read();
+                guard.unlock(WalIndexLock::Read(0));
+                guard.unlock(WalIndexLock::Checkpoint);
             }
         }
     }
@@ -817,6 +851,8 @@ impl Wal for WalFile {
             self.max_frame_read_lock_index.get(),
             NO_LOCK_HELD
         );
+        // Other processes may have changed the WAL since the last transaction
+        WalFileShared::refresh(&self.shared, &self.io, true)?;
         let (shared_max, nbackfills, last_checksum, checkpoint_seq) = {
             let shared = self.get_shared();
             let mx = shared.max_frame.load(Ordering::Acquire);
@@ -835,9 +871,13 @@ impl Wal for WalFile {
         // by taking read‑lock 0, and capturing the latest state.
         if shared_max == nbackfills {
             let lock_0_idx = 0;
-            if !self.get_shared().read_locks[lock_0_idx].read() {
+            if !self
+                .get_shared()
+                .try_lock(WalIndexLock::Read(lock_0_idx), false)
+            {
                 return Ok((LimboResult::Busy, db_changed));
             }
+            self.check_snapshot_unchanged(lock_0_idx, 0)?;
             // we need to keep self.max_frame set to the appropriate
             // max frame in the wal at the time this transaction starts.
             self.max_frame = shared_max;
@@ -854,8 +894,8 @@ impl Wal for WalFile {
         // Find largest mark <= mx among slots 1..N
         let mut best_idx: i64 = -1;
         let mut best_mark: u32 = 0;
-        for (idx, lock) in self.get_shared().read_locks.iter().enumerate().skip(1) {
-            let m = lock.get_value();
+        let read_marks = self.get_shared().read_marks()?;
+        for (idx, &m) in read_marks.iter().enumerate().skip(1) {
             if m != READMARK_NOT_USED && m <= shared_max as u32 && m > best_mark {
                 best_mark = m;
                 best_idx = idx as i64;
@@ -864,21 +904,17 @@ impl Wal for WalFile {
 
         // If none found or lagging, try to claim/update a slot
         if best_idx == -1 || (best_mark as u64) < shared_max {
-            for (idx, lock) in self
-                .get_shared_mut()
-                .read_locks
-                .iter_mut()
-                .enumerate()
-                .skip(1)
-            {
-                if !lock.write() {
+            let shared = self.get_shared();
+            for idx in 1..WAL_NREADER {
+                if !shared.try_lock(WalIndexLock::Read(idx), true) {
                     continue; // busy slot
                 }
                 // claim or bump this slot
-                lock.set_value_exclusive(shared_max as u32);
+                let result = shared.set_read_mark(idx, shared_max as u32);
+                shared.unlock(WalIndexLock::Read(idx));
+                result?;
                 best_idx = idx as i64;
                 best_mark = shared_max as u32;
-                lock.unlock();
                 break;
             }
         }
@@ -893,7 +929,7 @@ impl Wal for WalFile {
         // grab another snapshot of the shared state.
         let (mx2, nb2, cksm2, ckpt_seq2) = {
             let shared = self.get_shared();
-            if !shared.read_locks[best_idx as usize].read() {
+            if !shared.try_lock(WalIndexLock::Read(best_idx as usize), false) {
                 // TODO: we should retry here instead of always returning Busy
                 return Ok((LimboResult::Busy, db_changed));
             }
@@ -932,8 +968,11 @@ impl Wal for WalFile {
             || cksm2 != last_checksum
             || ckpt_seq2 != checkpoint_seq
         {
+            self.get_shared()
+                .unlock(WalIndexLock::Read(best_idx as usize));
             return Err(LimboError::Busy);
         }
+        self.check_snapshot_unchanged(best_idx as usize, best_mark)?;
         self.max_frame = best_mark as u64;
         self.max_frame_read_lock_index.set(best_idx as usize);
         tracing::debug!(
@@ -952,7 +991,7 @@ impl Wal for WalFile {
     fn end_read_tx(&self) {
         let slot = self.max_frame_read_lock_index.get();
         if slot != NO_LOCK_HELD {
-            self.get_shared_mut().read_locks[slot].unlock();
+            self.get_shared().unlock(WalIndexLock::Read(slot));
             self.max_frame_read_lock_index.set(NO_LOCK_HELD);
             tracing::debug!("end_read_tx(slot={slot})");
         } else {
@@ -963,7 +1002,6 @@ impl Wal for WalFile {
     /// Begin a write transaction
     #[instrument(skip_all, level = Level::DEBUG)]
     fn begin_write_tx(&mut self) -> Result<LimboResult> {
-        let shared = self.get_shared_mut();
         // sqlite/src/wal.c 3702
         // Cannot start a write transaction without first holding a read
         // transaction.
@@ -973,9 +1011,18 @@ impl Wal for WalFile {
             self.max_frame_read_lock_index.get() != NO_LOCK_HELD,
             "must have a read transaction to begin a write transaction"
         );
-        if !shared.write_lock.write() {
+        if !self.get_shared().try_lock(WalIndexLock::Write, true) {
             return Ok(LimboResult::Busy);
         }
+        // Another process may have committed since the read transaction started
+        if let Err(e) = WalFileShared::refresh(&self.shared, &self.io, false) {
+            self.get_shared().unlock(WalIndexLock::Write);
+            return match e {
+                LimboError::Busy => Ok(LimboResult::Busy),
+                e => Err(e),
+            };
+        }
+        let shared = self.get_shared();
         let (shared_max, nbackfills, last_checksum) = (
             shared.max_frame.load(Ordering::Acquire),
             shared.nbackfills.load(Ordering::Acquire),
@@ -991,7 +1038,7 @@ impl Wal for WalFile {
 
         // Snapshot is stale, give up and let caller retry from scratch
         tracing::debug!("unable to upgrade transaction from read to write: snapshot is stale, give up and let caller retry from scratch, self.max_frame={}, shared_max={}", self.max_frame, shared_max);
-        shared.write_lock.unlock();
+        shared.unlock(WalIndexLock::Write);
         Ok(LimboResult::Busy)
     }
 
@@ -999,7 +1046,7 @@ impl Wal for WalFile {
     #[instrument(skip_all, level = Level::DEBUG)]
     fn end_write_tx(&self) {
         tracing::debug!("end_write_txn");
-        self.get_shared().write_lock.unlock();
+        self.get_shared().unlock(WalIndexLock::Write);
     }
 
     /// Find the latest frame containing a page.
@@ -1289,6 +1336,7 @@ impl Wal for WalFile {
         self.io.wait_for_completion(c)?;
         self.complete_append_frame(page_id, frame_id, checksums);
         if db_size > 0 {
+            self.db_size = db_size as u32;
             self.finish_append_frames_commit()?;
         }
         Ok(())
@@ -1375,6 +1423,9 @@ impl Wal for WalFile {
             (result, frame_checksums)
         };
         self.complete_append_frame(page_id as u64, frame_id, checksums);
+        if db_size > 0 {
+            self.db_size = db_size;
+        }
         Ok(c)
     }
 
@@ -1455,12 +1506,63 @@ impl Wal for WalFile {
         };
         self.last_checksum = last_checksum;
         self.max_frame = max_frame;
+        self.uncommitted_frames.clear();
         self.reset_internal_states();
         Ok(())
     }
 
+    fn rewind(&mut self, max_frame: u64) -> Result<()> {
+        WalFileShared::rewind(&self.shared, &self.io, max_frame)?;
+        self.rollback()
+    }
+
+    fn savepoint(&self) -> WalSavepoint {
+        WalSavepoint {
+            max_frame: self.max_frame,
+            last_checksum: self.last_checksum,
+            uncommitted_frames: self.uncommitted_frames.len(),
+        }
+    }
+
+    fn rollback_to_savepoint(&mut self, savepoint: &WalSavepoint) {
+        turso_assert!(
+            savepoint.max_frame <= self.max_frame,
+            "savepoint frame {} is past the end of the WAL {}",
+            savepoint.max_frame,
+            self.max_frame
+        );
+        {
+            let shared = self.get_shared();
+            let mut frame_cache = shared.frame_cache.lock();
+            frame_cache.retain(|_page_id, frames| {
+                // keep frames <= savepoint.max_frame
+                while frames.last().is_some_and(|&f| f > savepoint.max_frame) {
+                    frames.pop();
+                }
+                !frames.is_empty()
+            });
+        }
+        self.max_frame = savepoint.max_frame;
+        self.last_checksum = savepoint.last_checksum;
+        self.uncommitted_frames
+            .truncate(savepoint.uncommitted_frames);
+    }
+
     #[instrument(skip_all, level = Level::DEBUG)]
     fn finish_append_frames_commit(&mut self) -> Result<()> {
+        let wal_index = self.get_shared().wal_index.clone();
+        let _state = wal_index.as_ref().map(|wal_index| wal_index.lock_state());
+        let frames = std::mem::take(&mut self.uncommitted_frames);
+        if let (Some(wal_index), false) = (&wal_index, frames.is_empty()) {
+            // Make the transaction visible to the other processes
+            wal_index.append(&frames)?;
+            let header = self.get_shared().wal_index_header(
+                self.max_frame,
+                self.last_checksum,
+                self.db_size,
+            );
+            wal_index.write_header(header)?;
+        }
         let mut shared = self.get_shared_mut();
         shared.max_frame.store(self.max_frame, Ordering::Release);
         tracing::trace!(self.max_frame, ?self.last_checksum);
@@ -1579,6 +1681,9 @@ impl Wal for WalFile {
         for (page, fid, csum) in &page_frame_and_checksum {
             self.complete_append_frame(page.get().id as u64, *fid, *csum);
         }
+        if let Some(db_size) = db_size_on_commit {
+            self.db_size = db_size;
+        }
 
         // single completion for the whole batch
         let total_len: i32 = iovecs.iter().map(|b| b.len() as i32).sum();
@@ -1672,6 +1777,8 @@ impl WalFile {
             checkpoint_guard: None,
             header,
             io_ctx: RefCell::new(IOContext::default()),
+            uncommitted_frames: Vec::new(),
+            db_size: 0,
         }
     }
 
@@ -1697,6 +1804,9 @@ impl WalFile {
         self.last_checksum = checksums;
         self.max_frame = frame_id;
         let shared = self.get_shared();
+        if shared.wal_index.is_some() {
+            self.uncommitted_frames.push((frame_id, page_id));
+        }
         {
             let mut frame_cache = shared.frame_cache.lock();
             match frame_cache.get_mut(&page_id) {
@@ -1710,6 +1820,32 @@ impl WalFile {
         }
     }
 
+    /// Checks that no other process changed the WAL, or read mark `idx`, while this connection
+    /// was taking the lock of the read mark. Otherwise the lock is released, and the read
+    /// transaction must be retried.
+    fn check_snapshot_unchanged(&self, idx: usize, read_mark: u32) -> Result<()> {
+        let Some(wal_index) = self.get_shared().wal_index.clone() else {
+            return Ok(());
+        };
+        let unchanged = {
+            let _state = wal_index.lock_state();
+            wal_index.read_header().and_then(|header| {
+                if header != Some(wal_index.last_header()) {
+                    return Ok(false);
+                }
+                Ok(idx == 0 || wal_index.read_checkpoint_info()?.read_marks[idx] == read_mark)
+            })
+        };
+        match unchanged {
+            Ok(true) => Ok(()),
+            result => {
+                self.get_shared().unlock(WalIndexLock::Read(idx));
+                result?;
+                Err(LimboError::Busy)
+            }
+        }
+    }
+
     fn reset_internal_states(&mut self) {
         self.max_frame_read_lock_index.set(NO_LOCK_HELD);
         self.ongoing_checkpoint.reset();
@@ -1783,6 +1919,8 @@ impl WalFile {
                 // so no other checkpointer can run. fsync WAL if there are unapplied frames.
                 // Decide the largest frame we are allowed to back‑fill.
                 CheckpointState::Start => {
+                    // Other processes may have committed or checkpointed
+                    WalFileShared::refresh(&self.shared, &self.io, false)?;
                     let (max_frame, nbackfills) = {
                         let shared = self.get_shared();
                         let max_frame = shared.max_frame.load(Ordering::Acquire);
@@ -1802,7 +1940,11 @@ impl WalFile {
                     }
                     // acquire the appropriate exclusive locks depending on the checkpoint mode
                     self.acquire_proper_checkpoint_guard(mode)?;
-                    let mut max_frame = self.determine_max_safe_checkpoint_frame();
+                    // No other checkpoint can run from now on, but one may have finished since
+                    // the refresh above
+                    WalFileShared::refresh(&self.shared, &self.io, false)?;
+                    let nbackfills = self.get_shared().nbackfills.load(Ordering::Acquire);
+                    let mut max_frame = self.determine_max_safe_checkpoint_frame()?;
 
                     if let CheckpointMode::Truncate {
                         upper_bound_inclusive: Some(upper_bound),
@@ -1822,6 +1964,9 @@ impl WalFile {
 
                     self.ongoing_checkpoint.max_frame = max_frame;
                     self.ongoing_checkpoint.min_frame = nbackfills + 1;
+                    if let Some(wal_index) = &self.get_shared().wal_index {
+                        wal_index.set_backfill_attempted(max_frame as u32)?;
+                    }
                     let to_checkpoint = {
                         let shared = self.get_shared();
                         let frame_cache = shared.frame_cache.lock();
@@ -1965,6 +2110,9 @@ impl WalFile {
                         self.ongoing_checkpoint.complete(),
                         "checkpoint pending flush must have finished"
                     );
+                    // sqlite reports zeros for truncate mode, so whether the db file may need
+                    // truncating has to be decided before the result is built.
+                    let backfilled_everything;
                     let mut checkpoint_result = {
                         let shared = self.get_shared();
                         let current_mx = shared.max_frame.load(Ordering::Acquire);
@@ -1979,6 +2127,8 @@ impl WalFile {
                         let checkpoint_max_frame = self.ongoing_checkpoint.max_frame;
                         let frames_checkpointed = checkpoint_max_frame
                             .saturating_sub(self.ongoing_checkpoint.min_frame - 1);
+                        backfilled_everything =
+                            frames_checkpointed > 0 && frames_checkpointed == frames_possible;
 
                         if matches!(mode, CheckpointMode::Truncate { .. }) {
                             // sqlite always returns zeros for truncate mode
@@ -2001,11 +2151,19 @@ impl WalFile {
                     };
 
                     // store the max frame we were able to successfully checkpoint.
-                    // NOTE: we don't have a .shm file yet, so it's safe to update nbackfills here
-                    // before we sync, because if we crash and then recover, we will checkpoint the entire db anyway.
-                    self.get_shared()
-                        .nbackfills
-                        .store(self.ongoing_checkpoint.max_frame, Ordering::Release);
+                    // NOTE: it's safe to update nbackfills here before we sync the db file: only
+                    // an OS crash loses the unsynced writes, and the wal-index does not survive
+                    // one, so after recovery we will checkpoint the entire WAL again anyway.
+                    {
+                        let wal_index = self.get_shared().wal_index.clone();
+                        let _state = wal_index.as_ref().map(|wal_index| wal_index.lock_state());
+                        if let Some(wal_index) = &wal_index {
+                            wal_index.set_backfilled(self.ongoing_checkpoint.max_frame as u32)?;
+                        }
+                        self.get_shared()
+                            .nbackfills
+                            .store(self.ongoing_checkpoint.max_frame, Ordering::Release);
+                    }
 
                     if mode.require_all_backfilled() && !checkpoint_result.everything_backfilled() {
                         return Err(LimboError::Busy);
@@ -2026,9 +2184,7 @@ impl WalFile {
                     // a. the max frame == num wal frames (everything backfilled)
                     // b. the physical db file size differs from the expected pages * page_size
                     // and truncate + sync the db file if necessary.
-                    if checkpoint_result.everything_backfilled()
-                        && checkpoint_result.num_backfilled > 0
-                    {
+                    if backfilled_everything {
                         checkpoint_result.maybe_guard = self.checkpoint_guard.take();
                     } else {
                         let _ = self.checkpoint_guard.take();
@@ -2082,15 +2238,15 @@ impl WalFile {
     ///
     /// We never modify slot values while a reader holds that slot's lock.
     /// TOOD: implement proper BUSY handling behavior
-    fn determine_max_safe_checkpoint_frame(&self) -> u64 {
-        let mut shared = self.get_shared_mut();
+    fn determine_max_safe_checkpoint_frame(&self) -> Result<u64> {
+        let shared = self.get_shared();
         let shared_max = shared.max_frame.load(Ordering::Acquire);
         let mut max_safe_frame = shared_max;
 
-        for (read_lock_idx, read_lock) in shared.read_locks.iter_mut().enumerate().skip(1) {
-            let this_mark = read_lock.get_value();
+        let read_marks = shared.read_marks()?;
+        for (read_lock_idx, &this_mark) in read_marks.iter().enumerate().skip(1) {
             if this_mark < max_safe_frame as u32 {
-                let busy = !read_lock.write();
+                let busy = !shared.try_lock(WalIndexLock::Read(read_lock_idx), true);
                 if !busy {
                     let val = if read_lock_idx == 1 {
                         // store the max_frame for the default read slot 1
@@ -2098,14 +2254,15 @@ impl WalFile {
                     } else {
                         READMARK_NOT_USED
                     };
-                    read_lock.set_value_exclusive(val);
-                    read_lock.unlock();
+                    let result = shared.set_read_mark(read_lock_idx, val);
+                    shared.unlock(WalIndexLock::Read(read_lock_idx));
+                    result?;
                 } else {
                     max_safe_frame = this_mark as u64;
                 }
             }
         }
-        max_safe_frame
+        Ok(max_safe_frame)
     }
 
     /// Called once the entire WAL has been back‑filled in RESTART or TRUNCATE mode.
@@ -2123,27 +2280,26 @@ impl WalFile {
         tracing::debug!("restart_log(mode={mode:?})");
         {
             // Block all readers
-            let mut shared = self.get_shared_mut();
-            for idx in 1..shared.read_locks.len() {
-                let lock = &mut shared.read_locks[idx];
-                if !lock.write() {
+            let shared = self.get_shared();
+            for idx in 1..WAL_NREADER {
+                if !shared.try_lock(WalIndexLock::Read(idx), true) {
                     // release everything we got so far
                     for j in 1..idx {
-                        shared.read_locks[j].unlock();
+                        shared.unlock(WalIndexLock::Read(j));
                     }
                     // Reader is active, cannot proceed
                     return Err(LimboError::Busy);
                 }
                 // after the log is reset, we must set all secondary marks to READMARK_NOT_USED so the next reader selects a fresh slot
-                lock.set_value_exclusive(READMARK_NOT_USED);
+                shared.read_locks[idx].set_value_exclusive(READMARK_NOT_USED);
             }
         }
 
         let unlock = |e: Option<&LimboError>| {
             // release all read locks we just acquired, the caller will take care of the others
-            let shared = self.shared.write();
-            for idx in 1..shared.read_locks.len() {
-                shared.read_locks[idx].unlock();
+            let shared = self.shared.read();
+            for idx in 1..WAL_NREADER {
+                shared.unlock(WalIndexLock::Read(idx));
             }
             if let Some(e) = e {
                 tracing::error!(
@@ -2153,11 +2309,25 @@ impl WalFile {
             }
         };
         // reinitialize in‑memory state
-        self.get_shared_mut()
-            .restart_wal_header(&self.io, mode)
-            .inspect_err(|e| {
-                unlock(Some(e));
-            })?;
+        {
+            let wal_index = self.shared.read().wal_index.clone();
+            let _state = wal_index.as_ref().map(|wal_index| wal_index.lock_state());
+            self.shared
+                .write()
+                .restart_wal_header(&self.io, mode)
+                .inspect_err(|e| {
+                    unlock(Some(e));
+                })?;
+            if let Some(wal_index) = &wal_index {
+                // The other processes learn about the restart from the new salts
+                self.shared
+                    .read()
+                    .publish_restart(wal_index)
+                    .inspect_err(|e| {
+                        unlock(Some(e));
+                    })?;
+            }
+        }
         let cksm = self.get_shared().last_checksum;
         self.last_checksum = cksm;
         self.max_frame = 0;
@@ -2262,14 +2432,42 @@ impl WalFile {
 }
 
 impl WalFileShared {
+    /// Opens the WAL at `path`. With `shm_path`, the WAL is shared with the other processes
+    /// through the wal-index at that path.
     pub fn open_shared_if_exists(
         io: &Arc<dyn IO>,
         path: &str,
+        shm_path: Option<&str>,
     ) -> Result<Arc<RwLock<WalFileShared>>> {
-        let file = io.open_file(path, crate::io::OpenFlags::Create, false)?;
-        if file.size()? == 0 {
-            return WalFileShared::new_noop();
+        let Some(shm_path) = shm_path else {
+            let file = io.open_file(path, OpenFlags::Create, false)?;
+            if file.size()? == 0 {
+                return WalFileShared::new_noop();
+            }
+            return Self::load(io, file);
+        };
+        let file = io.open_file(path, OpenFlags::Create | OpenFlags::NoLock, false)?;
+        let (wal_index, first) = WalIndex::open(io, shm_path)?;
+        let wal_index = Arc::new(wal_index);
+        if first {
+            // No other process has the WAL open, so the wal-index is rebuilt from the WAL
+            let shared = if file.size()? == 0 {
+                WalFileShared::new_shared(file)?
+            } else {
+                Self::load(io, file)?
+            };
+            shared.write().wal_index = Some(wal_index.clone());
+            Self::rebuild_wal_index(&shared, io, &wal_index)?;
+            wal_index.finish_open()?;
+            return Ok(shared);
         }
+        let shared = WalFileShared::new_shared(file)?;
+        shared.write().wal_index = Some(wal_index);
+        Self::refresh(&shared, io, true)?;
+        Ok(shared)
+    }
+
+    fn load(io: &Arc<dyn IO>, file: Arc<dyn File>) -> Result<Arc<RwLock<WalFileShared>>> {
         let wal_file_shared = sqlite3_ondisk::build_shared_wal(&file, io)?;
         turso_assert!(
             wal_file_shared
@@ -2316,6 +2514,7 @@ impl WalFileShared {
             loaded: AtomicBool::new(true),
             initialized: AtomicBool::new(false),
             epoch: AtomicU32::new(0),
+            wal_index: None,
         };
         Ok(Arc::new(RwLock::new(shared)))
     }
@@ -2360,6 +2559,7 @@ impl WalFileShared {
             loaded: AtomicBool::new(true),
             initialized: AtomicBool::new(false),
             epoch: AtomicU32::new(0),
+            wal_index: None,
         };
         Ok(Arc::new(RwLock::new(shared)))
     }
@@ -2397,6 +2597,337 @@ impl WalFileShared {
         self.wal_header.lock().page_size
     }
 
+    fn local_lock(&self, lock: WalIndexLock) -> &TursoRwLock {
+        match lock {
+            WalIndexLock::Write => &self.write_lock,
+            WalIndexLock::Checkpoint => &self.checkpoint_lock,
+            WalIndexLock::Read(idx) => &self.read_locks[idx],
+            WalIndexLock::Recover => {
+                unreachable!("the recover lock is only taken in the wal-index")
+            }
+        }
+    }
+
+    /// Takes `lock` without waiting. When the WAL is shared with other processes, the lock is
+    /// also taken in the wal-index.
+    pub(crate) fn try_lock(&self, lock: WalIndexLock, exclusive: bool) -> bool {
+        let local = self.local_lock(lock);
+        let acquired = if exclusive {
+            local.write()
+        } else {
+            local.read()
+        };
+        if !acquired {
+            return false;
+        }
+        let Some(wal_index) = &self.wal_index else {
+            return true;
+        };
+        match wal_index.lock(lock, exclusive) {
+            Ok(true) => true,
+            Ok(false) => {
+                local.unlock();
+                false
+            }
+            Err(e) => {
+                tracing::error!("failed to take {lock:?} in the wal-index: {e}");
+                local.unlock();
+                false
+            }
+        }
+    }
+
+    pub(crate) fn unlock(&self, lock: WalIndexLock) {
+        // The lock is released in the wal-index first, so that a connection of this process
+        // taking it next does not find it still held there
+        if let Some(wal_index) = &self.wal_index {
+            if let Err(e) = wal_index.unlock(lock) {
+                tracing::error!("failed to release {lock:?} in the wal-index: {e}");
+            }
+        }
+        self.local_lock(lock).unlock();
+    }
+
+    /// Returns the read marks, which other processes may have changed.
+    pub(crate) fn read_marks(&self) -> Result<[u32; WAL_NREADER]> {
+        match &self.wal_index {
+            Some(wal_index) => Ok(wal_index.read_checkpoint_info()?.read_marks),
+            None => Ok(array::from_fn(|idx| self.read_locks[idx].get_value())),
+        }
+    }
+
+    /// Sets read mark `idx`, whose lock must be held exclusively.
+    pub(crate) fn set_read_mark(&self, idx: usize, value: u32) -> Result<()> {
+        self.read_locks[idx].set_value_exclusive(value);
+        if let Some(wal_index) = &self.wal_index {
+            wal_index.set_read_mark(idx, value)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the wal-index header describing the WAL up to `max_frame`.
+    fn wal_index_header(
+        &self,
+        max_frame: u64,
+        frame_checksum: (u32, u32),
+        db_size: u32,
+    ) -> WalIndexHeader {
+        let header = self.wal_header.lock();
+        WalIndexHeader {
+            change: 0,
+            big_endian_checksum: header.magic & 1 != 0,
+            page_size: header.page_size,
+            max_frame: max_frame as u32,
+            db_size,
+            frame_checksum,
+            salt: (header.salt_1, header.salt_2),
+        }
+    }
+
+    /// Makes a restart of the WAL visible to the other processes, which find out about it from
+    /// the new salts.
+    fn publish_restart(&self, wal_index: &WalIndex) -> Result<()> {
+        wal_index.write_checkpoint_info(&CheckpointInfo {
+            backfilled: 0,
+            read_marks: array::from_fn(|idx| self.read_locks[idx].get_value()),
+            backfill_attempted: 0,
+        })?;
+        let db_size = wal_index.last_header().db_size;
+        wal_index.write_header(self.wal_index_header(0, self.last_checksum, db_size))
+    }
+
+    /// Brings the state of the WAL kept by this process up to date with the wal-index, in which
+    /// the other processes sharing the WAL publish their commits, checkpoints and restarts of
+    /// the WAL. If the wal-index header is not valid, the wal-index is rebuilt from the WAL with
+    /// `recover`, and Busy is returned otherwise.
+    pub(crate) fn refresh(
+        shared: &Arc<RwLock<WalFileShared>>,
+        io: &Arc<dyn IO>,
+        recover: bool,
+    ) -> Result<()> {
+        let Some(wal_index) = shared.read().wal_index.clone() else {
+            return Ok(());
+        };
+        let _state = wal_index.lock_state();
+        let Some(header) = wal_index.read_header()? else {
+            if !recover {
+                return Err(LimboError::Busy);
+            }
+            return Self::recover(shared, io, &wal_index);
+        };
+        let backfilled = wal_index.read_checkpoint_info()?.backfilled as u64;
+        let last_header = wal_index.last_header();
+        if header == last_header {
+            // Only a checkpoint may have happened
+            shared
+                .read()
+                .nbackfills
+                .store(backfilled, Ordering::Release);
+            return Ok(());
+        }
+        // Another process committed, after restarting the WAL if the salts changed
+        let restarted = header.salt != last_header.salt || header.max_frame < last_header.max_frame;
+        let first_frame = if restarted {
+            1
+        } else {
+            last_header.max_frame as u64 + 1
+        };
+        let frames = wal_index.frames(first_frame, header.max_frame as u64)?;
+        let wal_header = if restarted && header.max_frame > 0 {
+            let file = shared.read().file.clone().expect("a shared WAL has a file");
+            Some(read_wal_header(io, &file)?)
+        } else {
+            None
+        };
+
+        // No IO from here on, completions may need the shared state
+        let mut shared = shared.write();
+        {
+            let mut frame_cache = shared.frame_cache.lock();
+            if restarted {
+                frame_cache.clear();
+            }
+            for (frame, page) in frames {
+                frame_cache.entry(page).or_default().push(frame);
+            }
+        }
+        if restarted {
+            match wal_header {
+                Some(wal_header) => {
+                    *shared.wal_header.lock() = wal_header;
+                    shared.initialized.store(true, Ordering::Release);
+                }
+                None => {
+                    // The WAL header is written with the first frame
+                    let mut wal_header = shared.wal_header.lock();
+                    wal_header.magic = if header.big_endian_checksum {
+                        WAL_MAGIC_BE
+                    } else {
+                        WAL_MAGIC_LE
+                    };
+                    wal_header.file_format = 3007000;
+                    wal_header.page_size = header.page_size;
+                    wal_header.salt_1 = header.salt.0;
+                    wal_header.salt_2 = header.salt.1;
+                    shared.initialized.store(false, Ordering::Release);
+                }
+            }
+            shared.epoch.fetch_add(1, Ordering::Release);
+        }
+        shared
+            .max_frame
+            .store(header.max_frame as u64, Ordering::Release);
+        shared.nbackfills.store(backfilled, Ordering::Release);
+        shared.last_checksum = header.frame_checksum;
+        drop(shared);
+        wal_index.set_last_header(header);
+        Ok(())
+    }
+
+    /// Truncates the WAL back to its first `max_frame` frames, see [Wal::rewind]. The other
+    /// processes see the WAL shrink in the wal-index, and take it as a restart.
+    fn rewind(shared: &Arc<RwLock<WalFileShared>>, io: &Arc<dyn IO>, max_frame: u64) -> Result<()> {
+        let (file, page_size, header_checksum) = {
+            let shared = shared.read();
+            let header = shared.wal_header.lock();
+            let checksum = (header.checksum_1, header.checksum_2);
+            (shared.file.clone(), header.page_size, checksum)
+        };
+        let Some(file) = file else {
+            return Ok(());
+        };
+        if page_size == 0 {
+            // Nothing was ever written to the WAL
+            return Ok(());
+        }
+        let frame_size = (page_size as usize + WAL_FRAME_HEADER_SIZE) as u64;
+        let len = WAL_HEADER_SIZE as u64 + max_frame * frame_size;
+        if file.size()? > len {
+            io.wait_for_completion(file.truncate(len, Completion::new_trunc(|_| {}))?)?;
+            io.wait_for_completion(file.sync(Completion::new_sync(|_| {}))?)?;
+        }
+        if shared.read().max_frame.load(Ordering::Acquire) <= max_frame {
+            // The commits being rolled back did not make it to the shared state
+            return Ok(());
+        }
+        let (last_checksum, db_size) = if max_frame > 0 {
+            (
+                read_frame_checksum(io, &file, page_size, max_frame)?,
+                read_commit_db_size(io, &file, page_size, max_frame)?,
+            )
+        } else {
+            (header_checksum, 0)
+        };
+
+        // No IO from here on, completions may need the shared state
+        let wal_index = shared.read().wal_index.clone();
+        let _state = wal_index.as_ref().map(|wal_index| wal_index.lock_state());
+        let mut shared = shared.write();
+        shared.frame_cache.lock().retain(|_page_id, frames| {
+            frames.retain(|&frame| frame <= max_frame);
+            !frames.is_empty()
+        });
+        shared.max_frame.store(max_frame, Ordering::Release);
+        shared.last_checksum = last_checksum;
+        if let Some(wal_index) = &wal_index {
+            wal_index.write_header(shared.wal_index_header(max_frame, last_checksum, db_size))?;
+        }
+        Ok(())
+    }
+
+    /// Rebuilds the wal-index from the WAL, when the process that opened the wal-index first
+    /// crashed before building it, or a writer crashed while writing its header.
+    fn recover(
+        shared: &Arc<RwLock<WalFileShared>>,
+        io: &Arc<dyn IO>,
+        wal_index: &Arc<WalIndex>,
+    ) -> Result<()> {
+        let locks = [WalIndexLock::Write, WalIndexLock::Checkpoint];
+        {
+            let shared = shared.read();
+            for (i, &lock) in locks.iter().enumerate() {
+                if !shared.try_lock(lock, true) {
+                    locks[..i].iter().for_each(|&lock| shared.unlock(lock));
+                    return Err(LimboError::Busy);
+                }
+            }
+        }
+        let result = match wal_index.lock(WalIndexLock::Recover, true) {
+            Ok(true) => {
+                let result = Self::recover_locked(shared, io, wal_index);
+                wal_index.unlock(WalIndexLock::Recover).and(result)
+            }
+            Ok(false) => Err(LimboError::Busy),
+            Err(e) => Err(e),
+        };
+        let shared = shared.read();
+        locks.iter().for_each(|&lock| shared.unlock(lock));
+        result
+    }
+
+    fn recover_locked(
+        shared: &Arc<RwLock<WalFileShared>>,
+        io: &Arc<dyn IO>,
+        wal_index: &WalIndex,
+    ) -> Result<()> {
+        tracing::info!("rebuilding the wal-index from the WAL");
+        let file = shared.read().file.clone().expect("a shared WAL has a file");
+        let recovered = sqlite3_ondisk::build_shared_wal(&file, io)?;
+        {
+            let recovered = recovered.read();
+            let mut shared = shared.write();
+            *shared.wal_header.lock() = *recovered.wal_header.lock();
+            shared.max_frame.store(
+                recovered.max_frame.load(Ordering::Acquire),
+                Ordering::Release,
+            );
+            shared.nbackfills.store(0, Ordering::Release);
+            *shared.frame_cache.lock() = std::mem::take(&mut *recovered.frame_cache.lock());
+            shared.last_checksum = recovered.last_checksum;
+            shared.initialized.store(
+                recovered.initialized.load(Ordering::Acquire),
+                Ordering::Release,
+            );
+            shared.epoch.fetch_add(1, Ordering::Release);
+        }
+        Self::rebuild_wal_index(shared, io, wal_index)
+    }
+
+    /// Writes the state of the WAL loaded from the WAL file to the wal-index.
+    fn rebuild_wal_index(
+        shared: &Arc<RwLock<WalFileShared>>,
+        io: &Arc<dyn IO>,
+        wal_index: &WalIndex,
+    ) -> Result<()> {
+        let shared = shared.read();
+        let max_frame = shared.max_frame.load(Ordering::Acquire);
+        let mut frames = shared
+            .frame_cache
+            .lock()
+            .iter()
+            .flat_map(|(&page, frames)| {
+                frames
+                    .iter()
+                    .filter(|&&frame| frame <= max_frame)
+                    .map(move |&frame| (frame, page))
+            })
+            .collect::<Vec<_>>();
+        frames.sort_unstable();
+        wal_index.append(&frames)?;
+        let db_size = match &shared.file {
+            Some(file) if max_frame > 0 => {
+                read_commit_db_size(io, file, shared.page_size(), max_frame)?
+            }
+            _ => 0,
+        };
+        wal_index.write_checkpoint_info(&CheckpointInfo {
+            backfilled: 0,
+            read_marks: array::from_fn(|idx| shared.read_locks[idx].get_value()),
+            backfill_attempted: 0,
+        })?;
+        wal_index.write_header(shared.wal_index_header(max_frame, shared.last_checksum, db_size))
+    }
+
     /// Called after a successful RESTART/TRUNCATE mode checkpoint
     /// when all frames are back‑filled.
     ///
@@ -2433,6 +2964,8 @@ impl WalFileShared {
         }
 
         self.frame_cache.lock().clear();
+        // the WAL header is rewritten with the new salts before the next frame
+        self.initialized.store(false, Ordering::Release);
         // read-marks
         self.read_locks[0].set_value_exclusive(0);
         self.read_locks[1].set_value_exclusive(0);
@@ -2443,6 +2976,62 @@ impl WalFileShared {
     }
 }
 
+/// Reads the header of the WAL file.
+fn read_wal_header(io: &Arc<dyn IO>, file: &Arc<dyn File>) -> Result<WalHeader> {
+    let buffer = Arc::new(Buffer::new(vec![0; WAL_HEADER_SIZE]));
+    let c = Completion::new_read(buffer.clone(), |_| {});
+    let c = file.pread(0, c)?;
+    io.wait_for_completion(c)?;
+    let buf = buffer.as_slice();
+    let be_u32 = |pos: usize| u32::from_be_bytes(buf[pos..pos + 4].try_into().unwrap());
+    Ok(WalHeader {
+        magic: be_u32(0),
+        file_format: be_u32(4),
+        page_size: be_u32(8),
+        checkpoint_seq: be_u32(12),
+        salt_1: be_u32(16),
+        salt_2: be_u32(20),
+        checksum_1: be_u32(24),
+        checksum_2: be_u32(28),
+    })
+}
+
+/// Reads the cumulative checksum stored in the header of frame `frame_id`.
+fn read_frame_checksum(
+    io: &Arc<dyn IO>,
+    file: &Arc<dyn File>,
+    page_size: u32,
+    frame_id: u64,
+) -> Result<(u32, u32)> {
+    let frame_size = (page_size as usize + WAL_FRAME_HEADER_SIZE) as u64;
+    let offset = WAL_HEADER_SIZE as u64 + (frame_id - 1) * frame_size + 16;
+    let buffer = Arc::new(Buffer::new(vec![0; 8]));
+    let c = Completion::new_read(buffer.clone(), |_| {});
+    let c = file.pread(offset, c)?;
+    io.wait_for_completion(c)?;
+    let buf = buffer.as_slice();
+    Ok((
+        u32::from_be_bytes(buf[0..4].try_into().unwrap()),
+        u32::from_be_bytes(buf[4..8].try_into().unwrap()),
+    ))
+}
+
+/// Reads the database size stored in the header of commit frame `frame_id`.
+fn read_commit_db_size(
+    io: &Arc<dyn IO>,
+    file: &Arc<dyn File>,
+    page_size: u32,
+    frame_id: u64,
+) -> Result<u32> {
+    let frame_size = (page_size as usize + WAL_FRAME_HEADER_SIZE) as u64;
+    let offset = WAL_HEADER_SIZE as u64 + (frame_id - 1) * frame_size + 4;
+    let buffer = Arc::new(Buffer::new(vec![0; 4]));
+    let c = Completion::new_read(buffer.clone(), |_| {});
+    let c = file.pread(offset, c)?;
+    io.wait_for_completion(c)?;
+    Ok(u32::from_be_bytes(buffer.as_slice().try_into().unwrap()))
+}
+
 #[cfg(test)]
 pub mod test {
     use crate::{
@@ -3295,6 +3884,46 @@ pub mod test {
         std::fs::remove_dir_all(path).unwrap();
     }
 
+    #[test]
+    fn test_wal_checkpoint_truncate_shrinks_db_file() {
+        use std::io::Write as _;
+
+        let (db, path) = get_database();
+        let conn = db.connect().unwrap();
+        let dbpath = path.join("test.db");
+
+        conn.execute("create table test(id integer primary key, value text)")
+            .unwrap();
+        bulk_inserts(&conn, 1, 10);
+        conn.checkpoint(CheckpointMode::Truncate {
+            upper_bound_inclusive: None,
+        })
+        .unwrap();
+        let expected_size = std::fs::metadata(&dbpath).unwrap().len();
+
+        // Pages past the end of the database, like the ones left behind once VACUUM has
+        // shrunk it, must be cut off by the next checkpoint.
+        std::fs::OpenOptions::new()
+            .append(true)
+            .open(&dbpath)
+            .unwrap()
+            .write_all(&[0; 4 * 4096])
+            .unwrap();
+        conn.execute("insert into test(value) values ('after-grow')")
+            .unwrap();
+
+        // TRUNCATE reports zeros like sqlite does, but still has to truncate the db file
+        let result = conn
+            .checkpoint(CheckpointMode::Truncate {
+                upper_bound_inclusive: None,
+            })
+            .unwrap();
+        assert_eq!(result.num_backfilled, 0);
+        assert_eq!(std::fs::metadata(&dbpath).unwrap().len(), expected_size);
+        assert_eq!(count_test_table(&conn), 11);
+        std::fs::remove_dir_all(path).unwrap();
+    }
+
     fn read_wal_header(path: &std::path::Path) -> sqlite3_ondisk::WalHeader {
         use std::{fs::File, io::Read};
         let mut hdr = [0u8; 32];
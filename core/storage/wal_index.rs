@@ -0,0 +1,656 @@
+//! The wal-index, the `-shm` file through which the processes sharing a WAL database find the
+//! frames of the WAL and coordinate their transactions and checkpoints, see
+//! <https://www.sqlite.org/walformat.html#the_wal_index_file_format>.
+//!
+//! The file has the layout used by SQLite, so that Turso and SQLite processes can share a
+//! database. It starts with two copies of the [WalIndexHeader], which describes the committed
+//! content of the WAL. They are written one after the other, so that a reader can tell a header
+//! that is being written from a complete one. The [CheckpointInfo] follows: the number of frames
+//! copied into the database file and the read marks of the readers. The rest of the file is made
+//! of 32KB segments, each mapping up to 4096 frames to the pages they hold, with a hash table to
+//! look the frames of a page up. Each process keeps its own map of the pages of the WAL, which
+//! is brought up to date from the segments when the header shows that another process has
+//! changed the WAL.
+//!
+//! Like SQLite, every process maps the file into its memory in regions of the size of a
+//! segment, so that reading the wal-index, which is done at the start of every read
+//! transaction, never waits for I/O. Backends whose files are never shared between processes
+//! have no shared mappings, and read and write the file instead.
+//!
+//! The locks of the WAL are byte-range locks on the bytes 120..128 of the file. The byte 128 is
+//! locked by every process that has the wal-index open: the first process to open it locks it
+//! exclusively and rebuilds the wal-index from the WAL before letting other processes in.
+//!
+//! The locks are POSIX advisory locks, which a process loses as soon as it closes any descriptor
+//! of the file, even one it never locked through. The database file and the wal-index must
+//! therefore be opened only once per process, which is why a [crate::Database] is shared
+//! through the registry of open databases instead of being opened again for the same path.
+
+use std::ops::Range;
+use std::sync::Arc;
+
+use parking_lot::{Mutex, MutexGuard};
+
+use crate::io::{Buffer, Completion, File, OpenFlags, SharedMemory, IO};
+use crate::storage::sqlite3_ondisk::{checksum_wal, WalHeader};
+use crate::{bail_corrupt_error, LimboError, Result};
+
+/// The version of the wal-index format.
+const WAL_INDEX_VERSION: u32 = 3007000;
+/// The size of each of the two copies of the header.
+const HEADER_SIZE: usize = 48;
+/// The checkpoint info follows the two copies of the header.
+const CHECKPOINT_INFO_OFFSET: u64 = 2 * HEADER_SIZE as u64;
+const READ_MARKS_OFFSET: u64 = CHECKPOINT_INFO_OFFSET + 4;
+const BACKFILL_ATTEMPTED_OFFSET: u64 = 128;
+/// The size of the headers and the checkpoint info at the start of the file.
+const FILE_HEADER_SIZE: u64 = 136;
+/// The offset of the byte of the first lock, see [WalIndexLock].
+const LOCKS_OFFSET: u64 = 120;
+/// The byte locked by every process that has the wal-index open.
+const DMS_LOCK_OFFSET: u64 = 128;
+/// The number of read marks.
+pub const WAL_NREADER: usize = 5;
+const NUM_LOCKS: usize = 3 + WAL_NREADER;
+
+/// Each segment maps `SEGMENT_NPAGE` frames to their pages, followed by a hash table of
+/// `HASH_NSLOT` slots, which hold the position of a frame in the segment.
+const SEGMENT_SIZE: u64 = 32768;
+const SEGMENT_NPAGE: u64 = 4096;
+const HASH_NSLOT: usize = 8192;
+const HASH_OFFSET: u64 = SEGMENT_NPAGE * 4;
+/// The first segment shares its space with the header of the file.
+const FIRST_SEGMENT_NPAGE: u64 = SEGMENT_NPAGE - FILE_HEADER_SIZE / 4;
+
+/// A header is only read a few times while another process writes it.
+const HEADER_READ_ATTEMPTS: usize = 100;
+
+/// The bytes of the database file locked by SQLite to take a shared lock on it.
+const DB_SHARED_LOCK_OFFSET: u64 = 0x4000_0000 + 2;
+const DB_SHARED_LOCK_LEN: u64 = 510;
+
+/// The locks of the WAL, held in the wal-index by the process of the connection holding them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WalIndexLock {
+    /// Held by the only writer.
+    Write,
+    /// Held by the only checkpointer.
+    Checkpoint,
+    /// Held while the wal-index is rebuilt from the WAL.
+    Recover,
+    /// Held by the readers using a read mark, shared, or by a connection changing it.
+    Read(usize),
+}
+
+impl WalIndexLock {
+    fn index(self) -> usize {
+        match self {
+            WalIndexLock::Write => 0,
+            WalIndexLock::Checkpoint => 1,
+            WalIndexLock::Recover => 2,
+            WalIndexLock::Read(idx) => 3 + idx,
+        }
+    }
+}
+
+/// The connections of this process holding a lock of the wal-index.
+#[derive(Debug, Clone, Copy)]
+enum LockHolders {
+    None,
+    Shared(usize),
+    Exclusive,
+}
+
+/// The header of the wal-index, describing the committed content of the WAL.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct WalIndexHeader {
+    /// Changes on every write of the header.
+    pub change: u32,
+    /// Whether the checksums of the WAL are computed over big-endian words.
+    pub big_endian_checksum: bool,
+    pub page_size: u32,
+    /// The last committed frame.
+    pub max_frame: u32,
+    /// The size of the database in pages after the last commit.
+    pub db_size: u32,
+    /// The cumulative checksum of the last committed frame.
+    pub frame_checksum: (u32, u32),
+    /// The salts of the WAL header.
+    pub salt: (u32, u32),
+}
+
+impl WalIndexHeader {
+    fn to_bytes(self) -> [u8; HEADER_SIZE] {
+        let mut buf = [0u8; HEADER_SIZE];
+        buf[0..4].copy_from_slice(&WAL_INDEX_VERSION.to_ne_bytes());
+        buf[8..12].copy_from_slice(&self.change.to_ne_bytes());
+        buf[12] = 1;
+        buf[13] = self.big_endian_checksum as u8;
+        // A page size of 65536 does not fit in 16 bits and is stored as 1
+        let page_size = ((self.page_size & 0xff00) | (self.page_size >> 16)) as u16;
+        buf[14..16].copy_from_slice(&page_size.to_ne_bytes());
+        buf[16..20].copy_from_slice(&self.max_frame.to_ne_bytes());
+        buf[20..24].copy_from_slice(&self.db_size.to_ne_bytes());
+        buf[24..28].copy_from_slice(&self.frame_checksum.0.to_ne_bytes());
+        buf[28..32].copy_from_slice(&self.frame_checksum.1.to_ne_bytes());
+        // The salts are stored as they are in the WAL header
+        buf[32..36].copy_from_slice(&self.salt.0.to_be_bytes());
+        buf[36..40].copy_from_slice(&self.salt.1.to_be_bytes());
+        let (c1, c2) = header_checksum(&buf[..40]);
+        buf[40..44].copy_from_slice(&c1.to_ne_bytes());
+        buf[44..48].copy_from_slice(&c2.to_ne_bytes());
+        buf
+    }
+
+    /// Decodes a header, or returns None if it was never written or is corrupt.
+    fn from_bytes(buf: &[u8]) -> Option<Self> {
+        let ne_u32 = |pos: usize| u32::from_ne_bytes(buf[pos..pos + 4].try_into().unwrap());
+        let be_u32 = |pos: usize| u32::from_be_bytes(buf[pos..pos + 4].try_into().unwrap());
+        if buf[12] == 0 || ne_u32(0) != WAL_INDEX_VERSION {
+            return None;
+        }
+        if header_checksum(&buf[..40]) != (ne_u32(40), ne_u32(44)) {
+            return None;
+        }
+        let page_size = u16::from_ne_bytes([buf[14], buf[15]]) as u32;
+        Some(Self {
+            change: ne_u32(8),
+            big_endian_checksum: buf[13] != 0,
+            page_size: (page_size & 0xfe00) + ((page_size & 0x0001) << 16),
+            max_frame: ne_u32(16),
+            db_size: ne_u32(20),
+            frame_checksum: (ne_u32(24), ne_u32(28)),
+            salt: (be_u32(32), be_u32(36)),
+        })
+    }
+}
+
+/// The header is checksummed over native-endian words.
+fn header_checksum(buf: &[u8]) -> (u32, u32) {
+    checksum_wal(buf, &WalHeader::default(), (0, 0), true)
+}
+
+/// The state of the checkpoints and of the readers of the WAL.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CheckpointInfo {
+    /// The number of frames copied into the database file.
+    pub backfilled: u32,
+    /// The largest frame each reader using a read mark may read.
+    pub read_marks: [u32; WAL_NREADER],
+    /// The number of frames a checkpoint started copying into the database file.
+    pub backfill_attempted: u32,
+}
+
+/// The segment holding a frame, frames being numbered from 1.
+fn segment_of(frame: u64) -> u64 {
+    (frame + SEGMENT_NPAGE - FIRST_SEGMENT_NPAGE - 1) / SEGMENT_NPAGE
+}
+
+/// The number of frames before the first frame of a segment.
+fn segment_start(segment: u64) -> u64 {
+    if segment == 0 {
+        0
+    } else {
+        FIRST_SEGMENT_NPAGE + (segment - 1) * SEGMENT_NPAGE
+    }
+}
+
+fn segment_npage(segment: u64) -> usize {
+    if segment == 0 {
+        FIRST_SEGMENT_NPAGE as usize
+    } else {
+        SEGMENT_NPAGE as usize
+    }
+}
+
+/// The offset of the pages of the frames of a segment.
+fn page_numbers_offset(segment: u64) -> u64 {
+    if segment == 0 {
+        FILE_HEADER_SIZE
+    } else {
+        segment * SEGMENT_SIZE
+    }
+}
+
+fn hash_table_offset(segment: u64) -> u64 {
+    segment * SEGMENT_SIZE + HASH_OFFSET
+}
+
+fn hash_key(page: u32) -> usize {
+    page.wrapping_mul(383) as usize & (HASH_NSLOT - 1)
+}
+
+/// Removes the frames of a segment after the first `limit` ones from its hash table.
+fn truncate_segment(page_numbers: &mut [u32], hash: &mut [u16], limit: usize) {
+    for slot in hash.iter_mut() {
+        if *slot as usize > limit {
+            *slot = 0;
+        }
+    }
+    page_numbers[limit..].fill(0);
+}
+
+pub struct WalIndex {
+    io: Arc<dyn IO>,
+    file: Arc<dyn File>,
+    /// The segments of the file mapped so far, in order, or None if the backend of the file
+    /// has no shared mappings.
+    regions: Option<Mutex<Vec<SharedMemory>>>,
+    /// Byte-range locks are held by processes, so the connections of this process sharing a lock
+    /// are counted, and the lock is only released by the last of them. The connections of this
+    /// process never hold a lock exclusively at the same time, they take their own locks first.
+    holders: [Mutex<LockHolders>; NUM_LOCKS],
+    /// Serializes the updates of the state of the WAL kept by this process with the wal-index,
+    /// so that they are applied in the order they were made.
+    state: Mutex<()>,
+    /// The header last read or written by this process.
+    last_header: Mutex<WalIndexHeader>,
+}
+
+impl WalIndex {
+    /// Opens the wal-index at `path`, and returns whether no other process has it open. In that
+    /// case, the content of the wal-index cannot be trusted. It is emptied and must be rebuilt
+    /// from the WAL before [WalIndex::finish_open] lets other processes in.
+    pub fn open(io: &Arc<dyn IO>, path: &str) -> Result<(Self, bool)> {
+        let file = io.open_file(path, OpenFlags::Create | OpenFlags::NoLock, false)?;
+        let first = file.lock_range(DMS_LOCK_OFFSET, 1, true)?;
+        if first {
+            // The file is emptied before it is mapped, since the mapped pages past the end of
+            // the file cannot be accessed.
+            let c = file.truncate(0, Completion::new_trunc(|_| {}))?;
+            io.wait_for_completion(c)?;
+        } else if !file.lock_range(DMS_LOCK_OFFSET, 1, false)? {
+            // Another process is rebuilding the wal-index
+            return Err(LimboError::Busy);
+        }
+        let regions = file
+            .map_shared(0, SEGMENT_SIZE as usize)?
+            .map(|region| Mutex::new(vec![region]));
+        let index = Self {
+            io: io.clone(),
+            file,
+            regions,
+            holders: std::array::from_fn(|_| Mutex::new(LockHolders::None)),
+            state: Mutex::new(()),
+            last_header: Mutex::new(WalIndexHeader::default()),
+        };
+        if first {
+            // SQLite only reads the segments that the file spans entirely
+            index.write(SEGMENT_SIZE - 2, vec![0; 2])?;
+        }
+        Ok((index, first))
+    }
+
+    /// Lets other processes open the wal-index once it has been rebuilt.
+    pub fn finish_open(&self) -> Result<()> {
+        self.file.lock_range(DMS_LOCK_OFFSET, 1, false)?;
+        Ok(())
+    }
+
+    /// Takes `lock`, shared or exclusive, without waiting. Returns false if another process
+    /// holds it in a conflicting way.
+    pub fn lock(&self, lock: WalIndexLock, exclusive: bool) -> Result<bool> {
+        let mut holders = self.holders[lock.index()].lock();
+        let acquired = match *holders {
+            LockHolders::None => {
+                self.file
+                    .lock_range(LOCKS_OFFSET + lock.index() as u64, 1, exclusive)?
+            }
+            LockHolders::Shared(_) => !exclusive,
+            LockHolders::Exclusive => false,
+        };
+        if acquired {
+            *holders = match *holders {
+                LockHolders::Shared(n) => LockHolders::Shared(n + 1),
+                _ if exclusive => LockHolders::Exclusive,
+                _ => LockHolders::Shared(1),
+            };
+        }
+        Ok(acquired)
+    }
+
+    pub fn unlock(&self, lock: WalIndexLock) -> Result<()> {
+        let mut holders = self.holders[lock.index()].lock();
+        match *holders {
+            LockHolders::None => {}
+            LockHolders::Shared(n) if n > 1 => *holders = LockHolders::Shared(n - 1),
+            _ => {
+                self.file
+                    .unlock_range(LOCKS_OFFSET + lock.index() as u64, 1)?;
+                *holders = LockHolders::None;
+            }
+        }
+        Ok(())
+    }
+
+    /// Locks the state of the WAL kept by this process, see [WalIndex::state]. Must be taken
+    /// before the lock of that state.
+    pub fn lock_state(&self) -> MutexGuard<'_, ()> {
+        self.state.lock()
+    }
+
+    /// Reads the header, or returns None if it is not initialized or is being written.
+    pub fn read_header(&self) -> Result<Option<WalIndexHeader>> {
+        for _ in 0..HEADER_READ_ATTEMPTS {
+            let buf = self.read(0, 2 * HEADER_SIZE)?;
+            let (first, second) = buf.split_at(HEADER_SIZE);
+            if first == second {
+                return Ok(WalIndexHeader::from_bytes(first));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Writes the header, which makes the frames up to its max frame visible to other
+    /// processes.
+    pub fn write_header(&self, mut header: WalIndexHeader) -> Result<()> {
+        let mut last_header = self.last_header.lock();
+        header.change = last_header.change.wrapping_add(1);
+        let bytes = header.to_bytes();
+        // The second copy is written first: a reader seeing the same two copies sees a complete
+        // header.
+        self.write(HEADER_SIZE as u64, bytes.to_vec())?;
+        self.write(0, bytes.to_vec())?;
+        *last_header = header;
+        Ok(())
+    }
+
+    pub fn last_header(&self) -> WalIndexHeader {
+        *self.last_header.lock()
+    }
+
+    pub fn set_last_header(&self, header: WalIndexHeader) {
+        *self.last_header.lock() = header;
+    }
+
+    pub fn read_checkpoint_info(&self) -> Result<CheckpointInfo> {
+        let buf = self.read(
+            CHECKPOINT_INFO_OFFSET,
+            (FILE_HEADER_SIZE - CHECKPOINT_INFO_OFFSET) as usize,
+        )?;
+        let ne_u32 = |pos: usize| u32::from_ne_bytes(buf[pos..pos + 4].try_into().unwrap());
+        Ok(CheckpointInfo {
+            backfilled: ne_u32(0),
+            read_marks: std::array::from_fn(|idx| ne_u32(4 + 4 * idx)),
+            backfill_attempted: ne_u32(
+                (BACKFILL_ATTEMPTED_OFFSET - CHECKPOINT_INFO_OFFSET) as usize,
+            ),
+        })
+    }
+
+    pub fn write_checkpoint_info(&self, info: &CheckpointInfo) -> Result<()> {
+        // The lock bytes between the read marks and the backfill attempt are never written
+        let mut buf = info.backfilled.to_ne_bytes().to_vec();
+        for read_mark in info.read_marks {
+            buf.extend_from_slice(&read_mark.to_ne_bytes());
+        }
+        self.write(CHECKPOINT_INFO_OFFSET, buf)?;
+        self.set_backfill_attempted(info.backfill_attempted)
+    }
+
+    pub fn set_backfilled(&self, frames: u32) -> Result<()> {
+        self.write(CHECKPOINT_INFO_OFFSET, frames.to_ne_bytes().to_vec())
+    }
+
+    pub fn set_backfill_attempted(&self, frames: u32) -> Result<()> {
+        self.write(BACKFILL_ATTEMPTED_OFFSET, frames.to_ne_bytes().to_vec())
+    }
+
+    /// Sets read mark `idx`, which requires its lock to be held exclusively.
+    pub fn set_read_mark(&self, idx: usize, value: u32) -> Result<()> {
+        self.write(
+            READ_MARKS_OFFSET + 4 * idx as u64,
+            value.to_ne_bytes().to_vec(),
+        )
+    }
+
+    /// Adds frames, given with the pages they hold in ascending order of frame, to the
+    /// wal-index. They are only visible to other processes once the header is written.
+    pub fn append(&self, frames: &[(u64, u64)]) -> Result<()> {
+        let mut start = 0;
+        while start < frames.len() {
+            let segment = segment_of(frames[start].0);
+            let end = start
+                + frames[start..]
+                    .iter()
+                    .take_while(|(frame, _)| segment_of(*frame) == segment)
+                    .count();
+            self.append_to_segment(segment, &frames[start..end])?;
+            start = end;
+        }
+        Ok(())
+    }
+
+    fn append_to_segment(&self, segment: u64, frames: &[(u64, u64)]) -> Result<()> {
+        let start = segment_start(segment);
+        let (mut page_numbers, mut hash) = if frames[0].0 == start + 1 {
+            // The first frame of a segment starts it over
+            (vec![0; segment_npage(segment)], vec![0; HASH_NSLOT])
+        } else {
+            self.read_segment(segment)?
+        };
+        for &(frame, page) in frames {
+            let idx = (frame - start) as usize;
+            if page_numbers[idx - 1] != 0 {
+                // Left over by a transaction that was rolled back
+                truncate_segment(&mut page_numbers, &mut hash, idx - 1);
+            }
+            let mut key = hash_key(page as u32);
+            let mut collisions = 0;
+            while hash[key] != 0 {
+                collisions += 1;
+                if collisions > idx {
+                    bail_corrupt_error!("wal-index hash table of segment {segment} is full");
+                }
+                key = (key + 1) & (HASH_NSLOT - 1);
+            }
+            page_numbers[idx - 1] = page as u32;
+            hash[key] = idx as u16;
+        }
+        self.write_segment(segment, &page_numbers, &hash)
+    }
+
+    /// Returns the frames from `first` to `last` with the pages they hold.
+    pub fn frames(&self, first: u64, last: u64) -> Result<Vec<(u64, u64)>> {
+        let mut frames = Vec::with_capacity(last.saturating_sub(first) as usize + 1);
+        let mut frame = first;
+        while frame <= last {
+            let segment = segment_of(frame);
+            let start = segment_start(segment);
+            let segment_last = (start + segment_npage(segment) as u64).min(last);
+            let buf = self.read(
+                page_numbers_offset(segment) + (frame - start - 1) * 4,
+                (segment_last - frame + 1) as usize * 4,
+            )?;
+            let pages = buf
+                .chunks_exact(4)
+                .map(|page| u32::from_ne_bytes(page.try_into().unwrap()) as u64);
+            frames.extend((frame..=segment_last).zip(pages));
+            frame = segment_last + 1;
+        }
+        Ok(frames)
+    }
+
+    fn read_segment(&self, segment: u64) -> Result<(Vec<u32>, Vec<u16>)> {
+        let buf = self.read(page_numbers_offset(segment), segment_npage(segment) * 4)?;
+        let page_numbers = buf
+            .chunks_exact(4)
+            .map(|page| u32::from_ne_bytes(page.try_into().unwrap()))
+            .collect();
+        let buf = self.read(hash_table_offset(segment), HASH_NSLOT * 2)?;
+        let hash = buf
+            .chunks_exact(2)
+            .map(|slot| u16::from_ne_bytes(slot.try_into().unwrap()))
+            .collect();
+        Ok((page_numbers, hash))
+    }
+
+    fn write_segment(&self, segment: u64, page_numbers: &[u32], hash: &[u16]) -> Result<()> {
+        let buf = page_numbers
+            .iter()
+            .flat_map(|page| page.to_ne_bytes())
+            .collect();
+        self.write(page_numbers_offset(segment), buf)?;
+        let buf = hash.iter().flat_map(|slot| slot.to_ne_bytes()).collect();
+        self.write(hash_table_offset(segment), buf)
+    }
+
+    /// Calls `f` with every region holding some of the `len` bytes at `offset`, the offset of
+    /// these bytes in the region and their range in the `len` bytes. The regions that are not
+    /// mapped yet are mapped, which grows the file if it is shorter.
+    fn map_regions(
+        &self,
+        regions: &Mutex<Vec<SharedMemory>>,
+        offset: u64,
+        len: usize,
+        mut f: impl FnMut(&SharedMemory, usize, Range<usize>),
+    ) -> Result<()> {
+        let mut regions = regions.lock();
+        let mut pos = 0;
+        while pos < len {
+            let segment = (offset + pos as u64) / SEGMENT_SIZE;
+            while regions.len() as u64 <= segment {
+                let Some(region) = self
+                    .file
+                    .map_shared(regions.len() as u64 * SEGMENT_SIZE, SEGMENT_SIZE as usize)?
+                else {
+                    unreachable!("the first segment of the file was mapped");
+                };
+                regions.push(region);
+            }
+            let region_offset = ((offset + pos as u64) % SEGMENT_SIZE) as usize;
+            let n = (len - pos).min(SEGMENT_SIZE as usize - region_offset);
+            f(&regions[segment as usize], region_offset, pos..pos + n);
+            pos += n;
+        }
+        Ok(())
+    }
+
+    /// Reads `len` bytes at `offset`, the bytes past the end of the file being zeroes.
+    fn read(&self, offset: u64, len: usize) -> Result<Vec<u8>> {
+        if let Some(regions) = &self.regions {
+            let mut buf = vec![0; len];
+            self.map_regions(regions, offset, len, |region, region_offset, range| {
+                region.read(region_offset, &mut buf[range])
+            })?;
+            return Ok(buf);
+        }
+        let buffer = Arc::new(Buffer::new(vec![0; len]));
+        let c = Completion::new_read(buffer.clone(), |_| {});
+        let c = self.file.pread(offset, c)?;
+        self.io.wait_for_completion(c)?;
+        Ok(buffer.as_slice().to_vec())
+    }
+
+    fn write(&self, offset: u64, buf: Vec<u8>) -> Result<()> {
+        if let Some(regions) = &self.regions {
+            return self.map_regions(
+                regions,
+                offset,
+                buf.len(),
+                |region, region_offset, range| region.write(region_offset, &buf[range]),
+            );
+        }
+        let c = Completion::new_write(|_| {});
+        let c = self.file.pwrite(offset, Arc::new(Buffer::new(buf)), c)?;
+        self.io.wait_for_completion(c)
+    }
+}
+
+/// Takes a shared lock on the database file, held for as long as the database is open. Like in
+/// SQLite, this keeps the last process closing the database from deleting the WAL and the
+/// wal-index while other processes use them.
+pub fn lock_database_file(file: &Arc<dyn File>) -> Result<()> {
+    if !file.lock_range(DB_SHARED_LOCK_OFFSET, DB_SHARED_LOCK_LEN, false)? {
+        return Err(LimboError::LockingError(
+            "Failed locking file. File is locked by another process".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MemoryIO;
+
+    fn open_wal_index() -> WalIndex {
+        let io: Arc<dyn IO> = Arc::new(MemoryIO::new());
+        let (index, first) = WalIndex::open(&io, "test.db-shm").unwrap();
+        assert!(first);
+        index
+    }
+
+    #[test]
+    fn test_header_roundtrip() {
+        let index = open_wal_index();
+        assert_eq!(index.read_header().unwrap(), None);
+        let header = WalIndexHeader {
+            change: 0,
+            big_endian_checksum: true,
+            page_size: 65536,
+            max_frame: 7,
+            db_size: 3,
+            frame_checksum: (1, 2),
+            salt: (3, 4),
+        };
+        index.write_header(header).unwrap();
+        let read = index.read_header().unwrap().unwrap();
+        assert_eq!(read, index.last_header());
+        assert_eq!(read.page_size, 65536);
+        assert_eq!(read.max_frame, 7);
+        assert_eq!(read.change, 1);
+    }
+
+    #[test]
+    fn test_frames_across_segments() {
+        let index = open_wal_index();
+        let frames = (1..=10_000)
+            .map(|frame| (frame, frame % 37 + 1))
+            .collect::<Vec<_>>();
+        index.append(&frames).unwrap();
+        assert_eq!(index.frames(1, 10_000).unwrap(), frames);
+        assert_eq!(index.frames(4000, 4200).unwrap(), frames[3999..4200]);
+
+        // Frames left over by a transaction that was rolled back are replaced
+        let frames = (5001..=5010).map(|frame| (frame, 2)).collect::<Vec<_>>();
+        index.append(&frames).unwrap();
+        assert_eq!(index.frames(5001, 5010).unwrap(), frames);
+        let (page_numbers, hash) = index.read_segment(segment_of(5001)).unwrap();
+        let start = segment_start(segment_of(5001)) as usize;
+        assert!(page_numbers[5010 - start..].iter().all(|&page| page == 0));
+        assert!(hash.iter().all(|&slot| slot as usize <= 5010 - start));
+    }
+
+    #[cfg(target_family = "unix")]
+    #[test]
+    fn test_mapped_wal_index() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("test.db-shm");
+        let io: Arc<dyn IO> = Arc::new(crate::PlatformIO::new().unwrap());
+        let (index, first) = WalIndex::open(&io, path.to_str().unwrap()).unwrap();
+        assert!(first);
+        assert!(index.regions.is_some());
+
+        let frames = (1..=10_000)
+            .map(|frame| (frame, frame % 37 + 1))
+            .collect::<Vec<_>>();
+        index.append(&frames).unwrap();
+        assert_eq!(index.frames(1, 10_000).unwrap(), frames);
+        let header = WalIndexHeader {
+            page_size: 4096,
+            max_frame: 10_000,
+            ..Default::default()
+        };
+        index.write_header(header).unwrap();
+
+        // The mappings write through to the file, where the other processes find them
+        let bytes = std::fs::read(&path).unwrap();
+        assert_eq!(bytes.len() as u64, 3 * SEGMENT_SIZE);
+        assert_eq!(
+            WalIndexHeader::from_bytes(&bytes[..HEADER_SIZE]),
+            Some(index.last_header())
+        );
+        assert_eq!(&bytes[..HEADER_SIZE], &bytes[HEADER_SIZE..2 * HEADER_SIZE]);
+    }
+}
@@ -13,7 +13,7 @@ use crate::{
 use super::{
     emitter::{Resolver, TranslateCtx},
     expr::translate_expr,
-    plan::{Aggregate, Distinctness, SelectPlan, TableReferences},
+    plan::{AggOrderByCtx, Aggregate, Distinctness, SelectPlan, TableReferences},
     result_row::emit_select_result,
 };
 
@@ -28,6 +28,13 @@ pub fn emit_ungrouped_aggregation<'a>(
     let agg_start_reg = t_ctx.reg_agg_start.unwrap();
     for (i, agg) in plan.aggregates.iter().enumerate() {
         let agg_result_reg = agg_start_reg + i;
+        emit_ordered_aggregation_steps(
+            program,
+            &plan.table_references,
+            agg,
+            agg_result_reg,
+            &t_ctx.resolver,
+        )?;
         program.emit_insn(Insn::AggFinal {
             register: agg_result_reg,
             func: agg.func.clone(),
@@ -163,7 +170,7 @@ impl<'a> AggArgumentSource<'a> {
         col_start: usize,
         aggregate: &'a Aggregate,
     ) -> Self {
-        let dest_reg_start = program.alloc_registers(aggregate.args.len());
+        let dest_reg_start = program.alloc_registers(aggregate.num_inputs());
         Self::PseudoCursor {
             cursor_id,
             col_start,
@@ -185,7 +192,7 @@ impl<'a> AggArgumentSource<'a> {
         Self::Expression { aggregate }
     }
 
-    pub fn aggregate(&self) -> &Aggregate {
+    pub fn aggregate(&self) -> &'a Aggregate {
         match self {
             AggArgumentSource::PseudoCursor { aggregate, .. } => aggregate,
             AggArgumentSource::Register { aggregate, .. } => aggregate,
@@ -214,7 +221,8 @@ impl<'a> AggArgumentSource<'a> {
             AggArgumentSource::Expression { aggregate } => aggregate.args.len(),
         }
     }
-    /// Read the value of an aggregate function argument
+    /// Read the value of an aggregate function input, see [Aggregate::inputs]. The inputs
+    /// following the arguments are the FILTER clause and the ORDER BY keys.
     pub fn translate(
         &self,
         program: &mut ProgramBuilder,
@@ -242,13 +250,11 @@ impl<'a> AggArgumentSource<'a> {
             } => Ok(*start_reg + arg_idx),
             AggArgumentSource::Expression { aggregate } => {
                 let dest_reg = program.alloc_register();
-                translate_expr(
-                    program,
-                    Some(referenced_tables),
-                    &aggregate.args[arg_idx],
-                    dest_reg,
-                    resolver,
-                )
+                let expr = aggregate
+                    .inputs()
+                    .nth(arg_idx)
+                    .expect("aggregate input out of range");
+                translate_expr(program, Some(referenced_tables), expr, dest_reg, resolver)
             }
         }
     }
@@ -265,12 +271,170 @@ impl<'a> AggArgumentSource<'a> {
 /// * In `SELECT SUM(price) FROM t`, `price` is evaluated for each row and added to the accumulator.
 /// * In `SELECT product_category, SUM(price) FROM t GROUP BY product_category`, `price` is evaluated for
 ///   each row in the group and added to that group’s accumulator.
+///
+/// Rows for which the FILTER clause of the aggregate is not true are skipped. If the aggregate has
+/// an ORDER BY clause, the row is only collected here, see [emit_ordered_aggregation_steps].
 pub fn translate_aggregation_step(
     program: &mut ProgramBuilder,
     referenced_tables: &TableReferences,
     agg_arg_source: AggArgumentSource,
     target_register: usize,
     resolver: &Resolver,
+) -> Result<usize> {
+    let aggregate = agg_arg_source.aggregate();
+    let label_skip_row = program.allocate_label();
+    if aggregate.filter.is_some() {
+        let filter_reg =
+            agg_arg_source.translate(program, referenced_tables, resolver, aggregate.args.len())?;
+        program.emit_insn(Insn::IfNot {
+            reg: filter_reg,
+            target_pc: label_skip_row,
+            jump_if_null: true,
+        });
+    }
+    let dest = match &aggregate.order_by_ctx {
+        Some(ctx) => {
+            emit_ordered_aggregation_insert(
+                program,
+                referenced_tables,
+                &agg_arg_source,
+                ctx,
+                resolver,
+            )?;
+            target_register
+        }
+        None => emit_aggregation_step(
+            program,
+            referenced_tables,
+            agg_arg_source,
+            target_register,
+            resolver,
+        )?,
+    };
+    program.preassign_label_to_next_insn(label_skip_row);
+    Ok(dest)
+}
+
+/// Collects the arguments of a row into the ephemeral index of an aggregate with an ORDER BY
+/// clause, keyed by the ORDER BY keys of the row.
+fn emit_ordered_aggregation_insert(
+    program: &mut ProgramBuilder,
+    referenced_tables: &TableReferences,
+    agg_arg_source: &AggArgumentSource,
+    ctx: &AggOrderByCtx,
+    resolver: &Resolver,
+) -> Result<()> {
+    let aggregate = agg_arg_source.aggregate();
+    let num_args = aggregate.args.len();
+    let num_keys = aggregate.order_by.len();
+    let first_key_input = num_args + aggregate.filter.is_some() as usize;
+
+    // The record is made of the ORDER BY keys, the sequence number and the arguments
+    let record_start_reg = program.alloc_registers(num_keys + 1 + num_args);
+    for i in 0..num_keys {
+        let key_reg =
+            agg_arg_source.translate(program, referenced_tables, resolver, first_key_input + i)?;
+        program.emit_insn(Insn::Copy {
+            src_reg: key_reg,
+            dst_reg: record_start_reg + i,
+            extra_amount: 0,
+        });
+    }
+    program.emit_insn(Insn::AddImm {
+        register: ctx.reg_sequence,
+        value: 1,
+    });
+    program.emit_insn(Insn::Copy {
+        src_reg: ctx.reg_sequence,
+        dst_reg: record_start_reg + num_keys,
+        extra_amount: 0,
+    });
+    let args_start_reg = record_start_reg + num_keys + 1;
+    for i in 0..num_args {
+        let arg_reg = agg_arg_source.translate(program, referenced_tables, resolver, i)?;
+        program.emit_insn(Insn::Copy {
+            src_reg: arg_reg,
+            dst_reg: args_start_reg + i,
+            extra_amount: 0,
+        });
+    }
+    handle_distinct(program, aggregate, args_start_reg);
+
+    let record_reg = program.alloc_register();
+    program.emit_insn(Insn::MakeRecord {
+        start_reg: record_start_reg,
+        count: num_keys + 1 + num_args,
+        dest_reg: record_reg,
+        index_name: Some(ctx.ephemeral_index_name.clone()),
+        affinity_str: None,
+    });
+    program.emit_insn(Insn::IdxInsert {
+        cursor_id: ctx.cursor_id,
+        record_reg,
+        unpacked_start: None,
+        unpacked_count: None,
+        flags: IdxInsertFlags::new(),
+    });
+    Ok(())
+}
+
+/// Accumulates the rows collected for an aggregate with an ORDER BY clause, in the order of the
+/// clause. This is called once all the rows of a group have been collected, right before
+/// the aggregate is finalized. Does nothing for other aggregates.
+pub fn emit_ordered_aggregation_steps(
+    program: &mut ProgramBuilder,
+    referenced_tables: &TableReferences,
+    aggregate: &Aggregate,
+    target_register: usize,
+    resolver: &Resolver,
+) -> Result<()> {
+    let Some(ctx) = &aggregate.order_by_ctx else {
+        return Ok(());
+    };
+    let num_args = aggregate.args.len();
+    let first_arg_column = aggregate.order_by.len() + 1;
+    // The rows have already been filtered and deduplicated when they were collected
+    let collected = Aggregate {
+        distinctness: Distinctness::NonDistinct,
+        filter: None,
+        order_by: vec![],
+        order_by_ctx: None,
+        ..aggregate.clone()
+    };
+
+    let label_loop_start = program.allocate_label();
+    let label_loop_end = program.allocate_label();
+    program.emit_insn(Insn::Rewind {
+        cursor_id: ctx.cursor_id,
+        pc_if_empty: label_loop_end,
+    });
+    program.preassign_label_to_next_insn(label_loop_start);
+    let args_start_reg = program.alloc_registers(num_args);
+    for i in 0..num_args {
+        program.emit_column_or_rowid(ctx.cursor_id, first_arg_column + i, args_start_reg + i);
+    }
+    emit_aggregation_step(
+        program,
+        referenced_tables,
+        AggArgumentSource::new_from_registers(args_start_reg, &collected),
+        target_register,
+        resolver,
+    )?;
+    program.emit_insn(Insn::Next {
+        cursor_id: ctx.cursor_id,
+        pc_if_next: label_loop_start,
+    });
+    program.preassign_label_to_next_insn(label_loop_end);
+    Ok(())
+}
+
+/// Emits the AggStep of a row, see [translate_aggregation_step].
+fn emit_aggregation_step(
+    program: &mut ProgramBuilder,
+    referenced_tables: &TableReferences,
+    agg_arg_source: AggArgumentSource,
+    target_register: usize,
+    resolver: &Resolver,
 ) -> Result<usize> {
     let num_args = agg_arg_source.num_args();
     let func = agg_arg_source.agg_func();
@@ -312,33 +476,31 @@ pub fn translate_aggregation_step(
                 crate::bail_parse_error!("group_concat bad number of arguments");
             }
 
-            let delimiter_reg = program.alloc_register();
-
-            let delimiter_expr: ast::Expr;
-
-            if num_args == 2 {
-                match &agg_arg_source.args()[1] {
-                    arg @ ast::Expr::Column { .. } => {
-                        delimiter_expr = arg.clone();
-                    }
-                    ast::Expr::Literal(ast::Literal::String(s)) => {
-                        delimiter_expr = ast::Expr::Literal(ast::Literal::String(s.to_string()));
-                    }
-                    _ => crate::bail_parse_error!("Incorrect delimiter parameter"),
-                };
-            } else {
-                delimiter_expr = ast::Expr::Literal(ast::Literal::String(String::from("\",\"")));
+            if num_args == 2
+                && !matches!(
+                    &agg_arg_source.args()[1],
+                    ast::Expr::Column { .. } | ast::Expr::Literal(ast::Literal::String(_))
+                )
+            {
+                crate::bail_parse_error!("Incorrect delimiter parameter");
             }
 
             let expr_reg = agg_arg_source.translate(program, referenced_tables, resolver, 0)?;
             handle_distinct(program, agg_arg_source.aggregate(), expr_reg);
-            translate_expr(
-                program,
-                Some(referenced_tables),
-                &delimiter_expr,
-                delimiter_reg,
-                resolver,
-            )?;
+            // The delimiter is read like the other arguments, which may come from a sorter
+            let delimiter_reg = if num_args == 2 {
+                agg_arg_source.translate(program, referenced_tables, resolver, 1)?
+            } else {
+                let delimiter_reg = program.alloc_register();
+                translate_expr(
+                    program,
+                    Some(referenced_tables),
+                    &ast::Expr::Literal(ast::Literal::String(String::from("\",\""))),
+                    delimiter_reg,
+                    resolver,
+                )?;
+                delimiter_reg
+            };
 
             program.emit_insn(Insn::AggStep {
                 acc_reg: target_register,
@@ -418,24 +580,16 @@ pub fn translate_aggregation_step(
                 crate::bail_parse_error!("string_agg bad number of arguments");
             }
 
-            let delimiter_reg = program.alloc_register();
-
-            let delimiter_expr = match &agg_arg_source.args()[1] {
-                arg @ ast::Expr::Column { .. } => arg.clone(),
-                ast::Expr::Literal(ast::Literal::String(s)) => {
-                    ast::Expr::Literal(ast::Literal::String(s.to_string()))
-                }
-                _ => crate::bail_parse_error!("Incorrect delimiter parameter"),
-            };
+            if !matches!(
+                &agg_arg_source.args()[1],
+                ast::Expr::Column { .. } | ast::Expr::Literal(ast::Literal::String(_))
+            ) {
+                crate::bail_parse_error!("Incorrect delimiter parameter");
+            }
 
             let expr_reg = agg_arg_source.translate(program, referenced_tables, resolver, 0)?;
-            translate_expr(
-                program,
-                Some(referenced_tables),
-                &delimiter_expr,
-                delimiter_reg,
-                resolver,
-            )?;
+            let delimiter_reg =
+                agg_arg_source.translate(program, referenced_tables, resolver, 1)?;
 
             program.emit_insn(Insn::AggStep {
                 acc_reg: target_register,
@@ -3,16 +3,19 @@ use turso_parser::{ast, parser::Parser};
 
 use crate::{
     function::{AlterTableFunc, Func},
-    schema::{Column, Schema},
+    schema::{Column, GeneratedType, Schema},
     util::normalize_ident,
     vdbe::{
         builder::{CursorType, ProgramBuilder},
-        insn::{Cookie, Insn, RegisterOrLiteral},
+        insn::{Cookie, IdxInsertFlags, Insn, RegisterOrLiteral},
     },
     LimboError, Result, SymbolTable,
 };
 
-use super::{schema::SQLITE_TABLEID, update::translate_update_for_schema_change};
+use super::{
+    index::index_expr_refers_to_column, schema::SQLITE_TABLEID,
+    update::translate_update_for_schema_change,
+};
 
 pub fn translate_alter_table(
     alter: ast::AlterTable,
@@ -27,6 +30,11 @@ pub fn translate_alter_table(
         name: table_name,
         body: alter_table,
     } = alter;
+    match connection.resolve_database_id(&table_name)? {
+        0 => {}
+        1 => crate::bail_parse_error!("ALTER TABLE on TEMP tables is not supported yet"),
+        _ => crate::bail_parse_error!("ALTER TABLE on attached databases is not supported yet"),
+    }
     let table_name = table_name.name.as_str();
 
     // Check if someone is trying to ALTER a system table
@@ -85,6 +93,29 @@ pub fn translate_alter_table(
                 )));
             }
 
+            if let Some(index) = schema
+                .get_indices(table_name)
+                .iter()
+                .find(|index| index_expr_refers_to_column(index, &original_btree, dropped_index))
+            {
+                return Err(LimboError::ParseError(format!(
+                    "error in index {} after drop column: no such column: {column_name}",
+                    index.name
+                )));
+            }
+
+            if (0..btree.columns.len()).any(|pos| {
+                pos != dropped_index
+                    && btree.columns[pos].is_generated()
+                    && btree
+                        .generated_column_dependencies(pos)
+                        .is_ok_and(|dependencies| dependencies.contains(&dropped_index))
+            }) {
+                return Err(LimboError::ParseError(format!(
+                    "error in table {table_name} after drop column: no such column: {column_name}"
+                )));
+            }
+
             btree.columns.remove(dropped_index);
 
             let sql = btree.to_sql().replace('\'', "''");
@@ -111,10 +142,13 @@ pub fn translate_alter_table(
                 connection,
                 input,
                 |program| {
-                    let column_count = btree.columns.len();
+                    // VIRTUAL generated columns are not stored in the records
+                    let record_column_positions = btree.record_column_positions();
+                    let column_count = record_column_positions.len();
                     let root_page = btree.root_page;
                     let table_name = btree.name.clone();
 
+                    let has_rowid = original_btree.has_rowid;
                     let cursor_id = program.alloc_cursor_id(CursorType::BTreeTable(original_btree));
 
                     program.emit_insn(Insn::OpenWrite {
@@ -123,29 +157,29 @@ pub fn translate_alter_table(
                         db: 0,
                     });
 
-                    program.cursor_loop(cursor_id, |program, rowid| {
-                        let first_column = program.alloc_registers(column_count);
-
-                        let mut iter = first_column;
-
-                        for i in 0..(column_count + 1) {
-                            if i == dropped_index {
-                                continue;
-                            }
-
-                            program.emit_column_or_rowid(cursor_id, i, iter);
+                    let affinity_str = record_column_positions
+                        .iter()
+                        .map(|pos| btree.columns[*pos].affinity().aff_mask())
+                        .collect::<String>();
+
+                    if !has_rowid {
+                        // The rows of a WITHOUT ROWID table are keyed by their primary key, which
+                        // cannot contain the dropped column, so each row is rewritten in place.
+                        let loop_start = program.allocate_label();
+                        let loop_end = program.allocate_label();
+                        program.emit_insn(Insn::Rewind {
+                            cursor_id,
+                            pc_if_empty: loop_end,
+                        });
+                        program.preassign_label_to_next_insn(loop_start);
 
-                            iter += 1;
+                        let first_column = program.alloc_registers(column_count);
+                        for (i, pos) in record_column_positions.iter().enumerate() {
+                            let original_pos = if *pos >= dropped_index { pos + 1 } else { *pos };
+                            program.emit_column_or_rowid(cursor_id, original_pos, first_column + i);
                         }
 
                         let record = program.alloc_register();
-
-                        let affinity_str = btree
-                            .columns
-                            .iter()
-                            .map(|col| col.affinity().aff_mask())
-                            .collect::<String>();
-
                         program.emit_insn(Insn::MakeRecord {
                             start_reg: first_column,
                             count: column_count,
@@ -153,15 +187,57 @@ pub fn translate_alter_table(
                             index_name: None,
                             affinity_str: Some(affinity_str),
                         });
-
-                        program.emit_insn(Insn::Insert {
-                            cursor: cursor_id,
-                            key_reg: rowid,
-                            record_reg: record,
-                            flag: crate::vdbe::insn::InsertFlags(0),
+                        program.emit_insn(Insn::Delete {
+                            cursor_id,
                             table_name: table_name.clone(),
                         });
-                    });
+                        program.emit_insn(Insn::IdxInsert {
+                            cursor_id,
+                            record_reg: record,
+                            unpacked_start: Some(first_column),
+                            unpacked_count: Some(column_count as u16),
+                            flags: IdxInsertFlags::new(),
+                        });
+
+                        program.emit_insn(Insn::Next {
+                            cursor_id,
+                            pc_if_next: loop_start,
+                        });
+                        program.preassign_label_to_next_insn(loop_end);
+                    } else {
+                        program.cursor_loop(cursor_id, |program, rowid| {
+                            let first_column = program.alloc_registers(column_count);
+
+                            for (i, pos) in record_column_positions.iter().enumerate() {
+                                let original_pos =
+                                    if *pos >= dropped_index { pos + 1 } else { *pos };
+
+                                program.emit_column_or_rowid(
+                                    cursor_id,
+                                    original_pos,
+                                    first_column + i,
+                                );
+                            }
+
+                            let record = program.alloc_register();
+
+                            program.emit_insn(Insn::MakeRecord {
+                                start_reg: first_column,
+                                count: column_count,
+                                dest_reg: record,
+                                index_name: None,
+                                affinity_str: Some(affinity_str.clone()),
+                            });
+
+                            program.emit_insn(Insn::Insert {
+                                cursor: cursor_id,
+                                key_reg: rowid,
+                                record_reg: record,
+                                flag: crate::vdbe::insn::InsertFlags(0),
+                                table_name: table_name.clone(),
+                            });
+                        });
+                    }
 
                     program.emit_insn(Insn::SetCookie {
                         db: 0,
@@ -198,7 +274,18 @@ pub fn translate_alter_table(
                 }
             }
 
+            if column
+                .generated
+                .as_ref()
+                .is_some_and(|generated| generated.typ == GeneratedType::Stored)
+            {
+                return Err(LimboError::ParseError(
+                    "cannot add a STORED column".to_string(),
+                ));
+            }
+
             btree.columns.push(column.clone());
+            btree.generated_columns_in_dependency_order()?;
 
             let sql = btree.to_sql();
             let mut escaped = String::with_capacity(sql.len());
@@ -4,7 +4,7 @@ use turso_parser::ast;
 
 use crate::{
     bail_parse_error,
-    schema::{BTreeTable, Schema},
+    schema::{Affinity, BTreeTable, Index, Schema, STAT1_TABLE_NAME},
     storage::pager::CreateBTreeFlags,
     translate::{
         emitter::Resolver,
@@ -13,27 +13,36 @@ use crate::{
     util::normalize_ident,
     vdbe::{
         builder::{CursorType, ProgramBuilder},
-        insn::{Insn, RegisterOrLiteral},
+        insn::{CmpInsFlags, Insn, RegisterOrLiteral},
+        BranchOffset,
     },
-    Result, SymbolTable,
+    Connection, Result, SymbolTable,
 };
 
+/// The analysis limit used by `PRAGMA optimize` when `PRAGMA analysis_limit` is not set,
+/// so that refreshing statistics stays cheap on large tables.
+const OPTIMIZE_ANALYSIS_LIMIT: u64 = 400;
+
+/// `PRAGMA optimize` re-analyzes a table when its row count changed by more than this factor
+/// since the last ANALYZE.
+const OPTIMIZE_ROW_COUNT_CHANGE_FACTOR: u64 = 25;
+
+/// What a single ANALYZE pass gathers statistics for.
+enum AnalyzeTarget {
+    /// All indexes of a table, or the table itself if it has no indexes.
+    Table(Arc<BTreeTable>),
+    /// A single index of a table.
+    Index(Arc<BTreeTable>, Arc<Index>),
+}
+
 pub fn translate_analyze(
     target_opt: Option<ast::QualifiedName>,
     schema: &Schema,
     syms: &SymbolTable,
+    connection: &Arc<Connection>,
     mut program: ProgramBuilder,
 ) -> Result<ProgramBuilder> {
-    let Some(target) = target_opt else {
-        bail_parse_error!("ANALYZE with no target is not supported");
-    };
-    let normalized = normalize_ident(target.name.as_str());
-    let Some(target_schema) = schema.get_table(&normalized) else {
-        bail_parse_error!("ANALYZE <schema_name> is not supported");
-    };
-    let Some(target_btree) = target_schema.btree() else {
-        bail_parse_error!("ANALYZE on index is not supported");
-    };
+    let targets = resolve_analyze_targets(target_opt, schema)?;
 
     // This is emitted early because SQLite does, and thus generated VDBE matches a bit closer.
     let null_reg = program.alloc_register();
@@ -42,68 +51,197 @@ pub fn translate_analyze(
         dest_end: None,
     });
 
+    let (stat_cursor, stat1_exists) = open_stat1(&mut program, schema, syms)?;
+    let analysis_limit = connection.get_analysis_limit();
+    for target in targets.iter() {
+        emit_analyze_target(
+            &mut program,
+            schema,
+            stat_cursor,
+            stat1_exists,
+            target,
+            analysis_limit,
+        );
+    }
+    program.emit_insn(Insn::LoadAnalysis { db: 0 });
+    // FIXME: Emit Expire
+    Ok(program)
+}
+
+/// Translate `PRAGMA optimize`: ANALYZE every table with indexes that was never analyzed, or
+/// whose row count changed significantly since it was last analyzed.
+pub fn translate_optimize(
+    schema: &Schema,
+    syms: &SymbolTable,
+    connection: &Arc<Connection>,
+    mut program: ProgramBuilder,
+) -> Result<ProgramBuilder> {
+    let tables = analyzable_tables(schema)
+        .into_iter()
+        .filter(|table| !schema.get_indices(&table.name).is_empty())
+        .collect::<Vec<_>>();
+    if tables.is_empty() {
+        return Ok(program);
+    }
+
+    let (stat_cursor, stat1_exists) = open_stat1(&mut program, schema, syms)?;
+    let analysis_limit = match connection.get_analysis_limit() {
+        0 => OPTIMIZE_ANALYSIS_LIMIT,
+        limit => limit,
+    };
+    for table in tables {
+        let last_row_count = schema
+            .table_stats
+            .get(&normalize_ident(&table.name))
+            .and_then(|stats| stats.estimated_row_count());
+        let skip_label = program.allocate_label();
+        if let Some(last_row_count) = last_row_count {
+            // Only re-analyze if the table shrank or grew a lot since the last ANALYZE.
+            let analyze_label = program.allocate_label();
+            let table_cursor = program.alloc_cursor_id(CursorType::BTreeTable(table.clone()));
+            program.emit_insn(Insn::OpenRead {
+                cursor_id: table_cursor,
+                root_page: table.root_page,
+                db: 0,
+            });
+            let count_reg = program.alloc_register();
+            program.emit_insn(Insn::Count {
+                cursor_id: table_cursor,
+                target_reg: count_reg,
+                exact: true,
+            });
+            let low_reg = program.alloc_register();
+            program.emit_int(
+                (last_row_count / OPTIMIZE_ROW_COUNT_CHANGE_FACTOR) as i64,
+                low_reg,
+            );
+            let high_reg = program.alloc_register();
+            program.emit_int(
+                last_row_count.saturating_mul(OPTIMIZE_ROW_COUNT_CHANGE_FACTOR) as i64,
+                high_reg,
+            );
+            program.emit_insn(Insn::Lt {
+                lhs: count_reg,
+                rhs: low_reg,
+                target_pc: analyze_label,
+                flags: CmpInsFlags::default(),
+                collation: None,
+            });
+            program.emit_insn(Insn::Le {
+                lhs: count_reg,
+                rhs: high_reg,
+                target_pc: skip_label,
+                flags: CmpInsFlags::default(),
+                collation: None,
+            });
+            program.preassign_label_to_next_insn(analyze_label);
+        }
+        emit_analyze_target(
+            &mut program,
+            schema,
+            stat_cursor,
+            stat1_exists,
+            &AnalyzeTarget::Table(table),
+            analysis_limit,
+        );
+        program.preassign_label_to_next_insn(skip_label);
+    }
+    program.emit_insn(Insn::LoadAnalysis { db: 0 });
+    Ok(program)
+}
+
+/// Resolve the target of `ANALYZE`, `ANALYZE <schema>`, `ANALYZE <table>` or `ANALYZE <index>`.
+fn resolve_analyze_targets(
+    target_opt: Option<ast::QualifiedName>,
+    schema: &Schema,
+) -> Result<Vec<AnalyzeTarget>> {
+    let all_tables = || {
+        analyzable_tables(schema)
+            .into_iter()
+            .map(AnalyzeTarget::Table)
+            .collect()
+    };
+    let Some(target) = target_opt else {
+        return Ok(all_tables());
+    };
+    let normalized = normalize_ident(target.name.as_str());
+    match &target.db_name {
+        Some(db_name) => {
+            if normalize_ident(db_name.as_str()) != "main" {
+                bail_parse_error!("ANALYZE on attached databases is not supported");
+            }
+        }
+        None if normalized == "main" => return Ok(all_tables()),
+        None => {}
+    }
+
+    if let Some(table) = schema.get_btree_table(&normalized) {
+        if is_analyzable_table(schema, &table.name) {
+            return Ok(vec![AnalyzeTarget::Table(table)]);
+        }
+        return Ok(vec![]);
+    }
+    let index = schema
+        .indexes
+        .values()
+        .flatten()
+        .find(|index| normalize_ident(&index.name) == normalized);
+    if let Some(index) = index {
+        let table = schema
+            .get_btree_table(&index.table_name)
+            .expect("index must belong to a btree table");
+        if is_analyzable_table(schema, &table.name) {
+            return Ok(vec![AnalyzeTarget::Index(table, index.clone())]);
+        }
+        return Ok(vec![]);
+    }
+    match &target.db_name {
+        Some(db_name) => bail_parse_error!(
+            "no such table: {}.{}",
+            db_name.as_str(),
+            target.name.as_str()
+        ),
+        None => bail_parse_error!("no such table: {}", target.name.as_str()),
+    }
+}
+
+/// Tables whose statistics are gathered by a database-wide ANALYZE, in name order.
+fn analyzable_tables(schema: &Schema) -> Vec<Arc<BTreeTable>> {
+    let mut tables = schema
+        .tables
+        .values()
+        .filter_map(|table| table.btree())
+        .filter(|table| is_analyzable_table(schema, &table.name))
+        .collect::<Vec<_>>();
+    tables.sort_by(|a, b| a.name.cmp(&b.name));
+    tables
+}
+
+/// Like SQLite, ANALYZE never gathers statistics for internal tables.
+fn is_analyzable_table(schema: &Schema, table_name: &str) -> bool {
+    !table_name.to_lowercase().starts_with("sqlite_")
+        && !crate::schema::is_system_table(table_name)
+        && !schema.is_materialized_view(table_name)
+}
+
+/// Open a write cursor on sqlite_stat1, creating the table first if it does not exist yet.
+/// Returns the cursor and whether sqlite_stat1 already existed.
+fn open_stat1(
+    program: &mut ProgramBuilder,
+    schema: &Schema,
+    syms: &SymbolTable,
+) -> Result<(usize, bool)> {
     // After preparing/creating sqlite_stat1, we need to OpenWrite it, and how we acquire
     // the necessary BTreeTable for cursor creation and root page for the instruction changes
     // depending on which path we take.
     let sqlite_stat1_btreetable: Arc<BTreeTable>;
     let sqlite_stat1_source: RegisterOrLiteral<_>;
+    let stat1_exists;
 
-    if let Some(sqlite_stat1) = schema.get_btree_table("sqlite_stat1") {
+    if let Some(sqlite_stat1) = schema.get_btree_table(STAT1_TABLE_NAME) {
         sqlite_stat1_btreetable = sqlite_stat1.clone();
         sqlite_stat1_source = RegisterOrLiteral::Literal(sqlite_stat1.root_page);
-        // sqlite_stat1 already exists, so we need to remove the row
-        // corresponding to the stats for the table which we're about to
-        // ANALYZE. SQLite implements this as a full table scan over
-        // sqlite_stat1 deleting any rows where the first column (table_name)
-        // is the targeted table.
-        let cursor_id = program.alloc_cursor_id(CursorType::BTreeTable(sqlite_stat1.clone()));
-        program.emit_insn(Insn::OpenWrite {
-            cursor_id,
-            root_page: RegisterOrLiteral::Literal(sqlite_stat1.root_page),
-            db: 0,
-        });
-        let after_loop = program.allocate_label();
-        program.emit_insn(Insn::Rewind {
-            cursor_id,
-            pc_if_empty: after_loop,
-        });
-        let loophead = program.allocate_label();
-        program.preassign_label_to_next_insn(loophead);
-        let column_reg = program.alloc_register();
-        program.emit_insn(Insn::Column {
-            cursor_id,
-            column: 0,
-            dest: column_reg,
-            default: None,
-        });
-        let tablename_reg = program.alloc_register();
-        program.emit_insn(Insn::String8 {
-            value: target_schema.get_name().to_string(),
-            dest: tablename_reg,
-        });
-        program.mark_last_insn_constant();
-        // FIXME: The SQLite instruction says p4=BINARY-8 and p5=81.  Neither are currently supported in Turso.
-        program.emit_insn(Insn::Ne {
-            lhs: column_reg,
-            rhs: tablename_reg,
-            target_pc: after_loop,
-            flags: Default::default(),
-            collation: None,
-        });
-        let rowid_reg = program.alloc_register();
-        program.emit_insn(Insn::RowId {
-            cursor_id,
-            dest: rowid_reg,
-        });
-        program.emit_insn(Insn::Delete {
-            cursor_id,
-            table_name: "sqlite_stat1".to_string(),
-        });
-        program.emit_insn(Insn::Next {
-            cursor_id,
-            pc_if_next: loophead,
-        });
-        program.preassign_label_to_next_insn(after_loop);
+        stat1_exists = true;
     } else {
         // FIXME: Emit ReadCookie 0 3 2
         // FIXME: Emit If 3 +2 0
@@ -116,9 +254,7 @@ pub fn translate_analyze(
 
         // TODO: this code half-copies translate_create_table, because there's
         // no way to get the table_root_reg back out, and it's needed for later
-        // codegen to open the table we just created.  It's worth a future
-        // refactoring to remove the duplication one the rest of ANALYZE is
-        // implemented.
+        // codegen to open the table we just created.
         let table_root_reg = program.alloc_register();
         program.emit_insn(Insn::CreateBtree {
             db: 0,
@@ -130,6 +266,7 @@ pub fn translate_analyze(
         // way to initialize it with a correct value.
         sqlite_stat1_btreetable = Arc::new(BTreeTable::from_sql(sql, 0)?);
         sqlite_stat1_source = RegisterOrLiteral::Register(table_root_reg);
+        stat1_exists = false;
 
         let table = schema.get_btree_table(SQLITE_TABLEID).unwrap();
         let sqlite_schema_cursor_id =
@@ -143,13 +280,13 @@ pub fn translate_analyze(
         let resolver = Resolver::new(schema, syms);
         // Add the table entry to sqlite_schema
         emit_schema_entry(
-            &mut program,
+            program,
             &resolver,
             sqlite_schema_cursor_id,
             None,
             SchemaEntryType::Table,
-            "sqlite_stat1",
-            "sqlite_stat1",
+            STAT1_TABLE_NAME,
+            STAT1_TABLE_NAME,
             table_root_reg,
             Some(sql.to_string()),
         )?;
@@ -157,79 +294,358 @@ pub fn translate_analyze(
         let parse_schema_where_clause =
             "tbl_name = 'sqlite_stat1' AND type != 'trigger'".to_string();
         program.emit_insn(Insn::ParseSchema {
-            db: sqlite_schema_cursor_id,
+            db: 0,
             where_clause: Some(parse_schema_where_clause),
         });
     };
 
-    if target_schema.columns().iter().any(|c| c.primary_key) {
-        bail_parse_error!("ANALYZE on tables with primary key is not supported");
-    }
-    if !target_btree.has_rowid {
-        bail_parse_error!("ANALYZE on tables without rowid is not supported");
-    }
-
-    // Count the number of rows in the target table, and insert it into sqlite_stat1.
-    let sqlite_stat1 = sqlite_stat1_btreetable;
-    let stat_cursor = program.alloc_cursor_id(CursorType::BTreeTable(sqlite_stat1.clone()));
+    let stat_cursor = program.alloc_cursor_id(CursorType::BTreeTable(sqlite_stat1_btreetable));
     program.emit_insn(Insn::OpenWrite {
         cursor_id: stat_cursor,
         root_page: sqlite_stat1_source,
         db: 0,
     });
-    let target_cursor = program.alloc_cursor_id(CursorType::BTreeTable(target_btree.clone()));
+    Ok((stat_cursor, stat1_exists))
+}
+
+/// Replace the sqlite_stat1 rows of a table or index with freshly gathered statistics.
+fn emit_analyze_target(
+    program: &mut ProgramBuilder,
+    schema: &Schema,
+    stat_cursor: usize,
+    stat1_exists: bool,
+    target: &AnalyzeTarget,
+    analysis_limit: u64,
+) {
+    match target {
+        AnalyzeTarget::Table(table) => {
+            if stat1_exists {
+                emit_delete_stat1_rows(program, stat_cursor, 0, &table.name);
+            }
+            let indexes = schema.get_indices(&table.name);
+            if indexes.is_empty() {
+                emit_table_stat(program, stat_cursor, table);
+            }
+            for index in indexes {
+                emit_index_stat(program, stat_cursor, table, index, analysis_limit);
+            }
+        }
+        AnalyzeTarget::Index(table, index) => {
+            if stat1_exists {
+                emit_delete_stat1_rows(program, stat_cursor, 1, index.stat1_name(table));
+            }
+            emit_index_stat(program, stat_cursor, table, index, analysis_limit);
+        }
+    }
+}
+
+/// Delete the rows of sqlite_stat1 whose `column` (0 = tbl, 1 = idx) is `name`.
+/// SQLite implements this as a full table scan over sqlite_stat1.
+fn emit_delete_stat1_rows(
+    program: &mut ProgramBuilder,
+    stat_cursor: usize,
+    column: usize,
+    name: &str,
+) {
+    let after_loop = program.allocate_label();
+    program.emit_insn(Insn::Rewind {
+        cursor_id: stat_cursor,
+        pc_if_empty: after_loop,
+    });
+    let loophead = program.allocate_label();
+    program.preassign_label_to_next_insn(loophead);
+    let column_reg = program.alloc_register();
+    program.emit_insn(Insn::Column {
+        cursor_id: stat_cursor,
+        column,
+        dest: column_reg,
+        default: None,
+    });
+    let name_reg = program.alloc_register();
+    program.emit_insn(Insn::String8 {
+        value: name.to_string(),
+        dest: name_reg,
+    });
+    program.mark_last_insn_constant();
+    let next_label = program.allocate_label();
+    program.emit_insn(Insn::Ne {
+        lhs: column_reg,
+        rhs: name_reg,
+        target_pc: next_label,
+        flags: CmpInsFlags::default().jump_if_null(),
+        collation: None,
+    });
+    program.emit_insn(Insn::Delete {
+        cursor_id: stat_cursor,
+        table_name: STAT1_TABLE_NAME.to_string(),
+    });
+    program.preassign_label_to_next_insn(next_label);
+    program.emit_insn(Insn::Next {
+        cursor_id: stat_cursor,
+        pc_if_next: loophead,
+    });
+    program.preassign_label_to_next_insn(after_loop);
+}
+
+/// Count the rows of a table without indexes, and insert a `tbl||count` row into sqlite_stat1.
+fn emit_table_stat(program: &mut ProgramBuilder, stat_cursor: usize, table: &Arc<BTreeTable>) {
+    let table_cursor = program.alloc_cursor_id(CursorType::BTreeTable(table.clone()));
+    program.emit_insn(Insn::OpenRead {
+        cursor_id: table_cursor,
+        root_page: table.root_page,
+        db: 0,
+    });
+    let stat_reg = program.alloc_register();
+    program.emit_insn(Insn::Count {
+        cursor_id: table_cursor,
+        target_reg: stat_reg,
+        exact: true,
+    });
+    let after_insert = program.allocate_label();
+    program.emit_insn(Insn::IfNot {
+        reg: stat_reg,
+        target_pc: after_insert,
+        jump_if_null: false,
+    });
+    program.emit_insn(Insn::Cast {
+        reg: stat_reg,
+        affinity: Affinity::Text,
+    });
+    emit_stat1_insert(program, stat_cursor, &table.name, None, stat_reg);
+    program.preassign_label_to_next_insn(after_insert);
+    program.emit_insn(Insn::Close {
+        cursor_id: table_cursor,
+    });
+}
+
+/// Scan an index and insert its `tbl|idx|stat` row into sqlite_stat1.
+///
+/// The stat is the number of entries in the index, followed by the average number of entries
+/// that share the same values in the first 1, 2, ..., N columns of the index. The averages are
+/// computed like SQLite does, by counting how many times each prefix changes while walking the
+/// index in order. If `analysis_limit` is non-zero, at most that many entries are scanned and the
+/// averages are estimated from them.
+fn emit_index_stat(
+    program: &mut ProgramBuilder,
+    stat_cursor: usize,
+    table: &Arc<BTreeTable>,
+    index: &Arc<Index>,
+    analysis_limit: u64,
+) {
+    let num_cols = index.key_columns().len();
+    let index_cursor = program.alloc_cursor_id(CursorType::BTreeIndex(index.clone()));
     program.emit_insn(Insn::OpenRead {
-        cursor_id: target_cursor,
-        root_page: target_btree.root_page,
+        cursor_id: index_cursor,
+        root_page: index.root_page,
         db: 0,
     });
-    let rowid_reg = program.alloc_register();
-    let record_reg = program.alloc_register();
-    let tablename_reg = program.alloc_register();
-    let indexname_reg = program.alloc_register();
+
+    // Number of index entries scanned.
     let count_reg = program.alloc_register();
-    program.emit_insn(Insn::String8 {
-        value: target_schema.get_name().to_string(),
-        dest: tablename_reg,
+    program.emit_int(0, count_reg);
+    // Number of distinct values of each prefix of the index columns.
+    let distinct_start_reg = program.alloc_registers(num_cols);
+    for i in 0..num_cols {
+        program.emit_int(0, distinct_start_reg + i);
+    }
+    // Index columns of the previous entry.
+    let prev_start_reg = program.alloc_registers(num_cols);
+    let column_reg = program.alloc_register();
+    let limit_reg = (analysis_limit > 0).then(|| {
+        let limit_reg = program.alloc_register();
+        program.emit_int(analysis_limit as i64, limit_reg);
+        limit_reg
+    });
+
+    let loop_end = program.allocate_label();
+    let limit_reached = program.allocate_label();
+    program.emit_insn(Insn::Rewind {
+        cursor_id: index_cursor,
+        pc_if_empty: loop_end,
     });
+    let loop_start = program.allocate_label();
+    program.preassign_label_to_next_insn(loop_start);
+    if let Some(limit_reg) = limit_reg {
+        program.emit_insn(Insn::Ge {
+            lhs: count_reg,
+            rhs: limit_reg,
+            target_pc: limit_reached,
+            flags: CmpInsFlags::default(),
+            collation: None,
+        });
+    }
+
+    // Find the first column that differs from the previous entry. On the first entry, every
+    // prefix counts as a new distinct value.
+    let changed_labels: Vec<BranchOffset> =
+        (0..num_cols).map(|_| program.allocate_label()).collect();
+    program.emit_insn(Insn::IfNot {
+        reg: count_reg,
+        target_pc: changed_labels[0],
+        jump_if_null: false,
+    });
+    for (i, changed_label) in changed_labels.iter().enumerate() {
+        program.emit_insn(Insn::Column {
+            cursor_id: index_cursor,
+            column: i,
+            dest: column_reg,
+            default: None,
+        });
+        program.emit_insn(Insn::Ne {
+            lhs: column_reg,
+            rhs: prev_start_reg + i,
+            target_pc: *changed_label,
+            flags: CmpInsFlags::default().null_eq(),
+            collation: index.columns[i].collation,
+        });
+    }
+    let next_entry = program.allocate_label();
+    program.emit_insn(Insn::Goto {
+        target_pc: next_entry,
+    });
+    // If column i changed, all prefixes of at least i + 1 columns have a new distinct value.
+    for (i, changed_label) in changed_labels.iter().enumerate() {
+        program.preassign_label_to_next_insn(*changed_label);
+        program.emit_insn(Insn::AddImm {
+            register: distinct_start_reg + i,
+            value: 1,
+        });
+        program.emit_insn(Insn::Column {
+            cursor_id: index_cursor,
+            column: i,
+            dest: prev_start_reg + i,
+            default: None,
+        });
+    }
+    program.preassign_label_to_next_insn(next_entry);
+    program.emit_insn(Insn::AddImm {
+        register: count_reg,
+        value: 1,
+    });
+    program.emit_insn(Insn::Next {
+        cursor_id: index_cursor,
+        pc_if_next: loop_start,
+    });
+
+    // The total number of entries is the number scanned, unless the scan stopped early.
+    let total_reg = program.alloc_register();
+    let total_known = program.allocate_label();
+    program.preassign_label_to_next_insn(loop_end);
+    program.emit_insn(Insn::Copy {
+        src_reg: count_reg,
+        dst_reg: total_reg,
+        extra_amount: 0,
+    });
+    program.emit_insn(Insn::Goto {
+        target_pc: total_known,
+    });
+    program.preassign_label_to_next_insn(limit_reached);
     program.emit_insn(Insn::Count {
-        cursor_id: target_cursor,
-        target_reg: count_reg,
+        cursor_id: index_cursor,
+        target_reg: total_reg,
         exact: true,
     });
+    program.preassign_label_to_next_insn(total_known);
+
     let after_insert = program.allocate_label();
     program.emit_insn(Insn::IfNot {
         reg: count_reg,
         target_pc: after_insert,
         jump_if_null: false,
     });
-    program.emit_insn(Insn::Null {
-        dest: indexname_reg,
-        dest_end: None,
+
+    // stat = "<total> <avg_1> ... <avg_N>", where avg_i = ceil(count / distinct_i).
+    let stat_reg = program.alloc_register();
+    program.emit_insn(Insn::Copy {
+        src_reg: total_reg,
+        dst_reg: stat_reg,
+        extra_amount: 0,
+    });
+    program.emit_insn(Insn::Cast {
+        reg: stat_reg,
+        affinity: Affinity::Text,
+    });
+    let space_reg = program.alloc_register();
+    program.emit_string8(" ".to_string(), space_reg);
+    let avg_reg = program.alloc_register();
+    for i in 0..num_cols {
+        program.emit_insn(Insn::Add {
+            lhs: count_reg,
+            rhs: distinct_start_reg + i,
+            dest: avg_reg,
+        });
+        program.emit_insn(Insn::AddImm {
+            register: avg_reg,
+            value: -1,
+        });
+        program.emit_insn(Insn::Divide {
+            lhs: avg_reg,
+            rhs: distinct_start_reg + i,
+            dest: avg_reg,
+        });
+        program.emit_insn(Insn::Concat {
+            lhs: stat_reg,
+            rhs: space_reg,
+            dest: stat_reg,
+        });
+        program.emit_insn(Insn::Concat {
+            lhs: stat_reg,
+            rhs: avg_reg,
+            dest: stat_reg,
+        });
+    }
+    emit_stat1_insert(
+        program,
+        stat_cursor,
+        &table.name,
+        Some(index.stat1_name(table)),
+        stat_reg,
+    );
+    program.preassign_label_to_next_insn(after_insert);
+    program.emit_insn(Insn::Close {
+        cursor_id: index_cursor,
     });
+}
+
+/// Insert a `(tbl, idx, stat)` row into sqlite_stat1.
+fn emit_stat1_insert(
+    program: &mut ProgramBuilder,
+    stat_cursor: usize,
+    table_name: &str,
+    index_name: Option<&str>,
+    stat_reg: usize,
+) {
+    let record_start_reg = program.alloc_registers(3);
+    program.emit_string8(table_name.to_string(), record_start_reg);
+    match index_name {
+        Some(index_name) => program.emit_string8(index_name.to_string(), record_start_reg + 1),
+        None => program.emit_null(record_start_reg + 1, None),
+    }
+    program.emit_insn(Insn::Copy {
+        src_reg: stat_reg,
+        dst_reg: record_start_reg + 2,
+        extra_amount: 0,
+    });
+    let record_reg = program.alloc_register();
     program.emit_insn(Insn::MakeRecord {
-        start_reg: tablename_reg,
+        start_reg: record_start_reg,
         count: 3,
         dest_reg: record_reg,
         index_name: None,
         affinity_str: None,
     });
+    let rowid_reg = program.alloc_register();
     program.emit_insn(Insn::NewRowid {
         cursor: stat_cursor,
         rowid_reg,
         prev_largest_reg: 0,
     });
     // FIXME: SQLite sets OPFLAG_APPEND on the insert, but that's not supported in turso right now.
-    // SQLite doesn't emit the table name, but like... why not?
     program.emit_insn(Insn::Insert {
         cursor: stat_cursor,
         key_reg: rowid_reg,
         record_reg,
         flag: Default::default(),
-        table_name: "sqlite_stat1".to_string(),
+        table_name: STAT1_TABLE_NAME.to_string(),
     });
-    program.preassign_label_to_next_insn(after_insert);
-    // FIXME: Emit LoadAnalysis
-    // FIXME: Emit Expire
-    Ok(program)
 }
@@ -1,6 +1,8 @@
-use crate::schema::{Index, IndexColumn, Schema};
+use crate::schema::{Index, IndexColumn, PseudoCursorType, Schema};
+use crate::translate::collate::CollationSeq;
 use crate::translate::emitter::{emit_query, LimitCtx, TranslateCtx};
 use crate::translate::expr::translate_expr;
+use crate::translate::order_by::sort_key_collation;
 use crate::translate::plan::{Plan, QueryDestination, SelectPlan};
 use crate::translate::result_row::try_fold_expr_to_i64;
 use crate::vdbe::builder::{CursorType, ProgramBuilder};
@@ -9,7 +11,7 @@ use crate::vdbe::BranchOffset;
 use crate::{emit_explain, QueryMode, SymbolTable};
 use std::sync::Arc;
 use tracing::instrument;
-use turso_parser::ast::{CompoundOperator, SortOrder};
+use turso_parser::ast::{self, CompoundOperator, SortOrder};
 
 use tracing::Level;
 
@@ -19,23 +21,52 @@ pub fn emit_program_for_compound_select(
     plan: Plan,
     schema: &Schema,
     syms: &SymbolTable,
+) -> crate::Result<()> {
+    let Plan::CompoundSelect { right_most, .. } = &plan else {
+        crate::bail_parse_error!("expected compound select plan");
+    };
+
+    let right_plan = right_most.clone();
+    // When a compound SELECT is part of a query that yields results to a coroutine (e.g. within an INSERT clause),
+    // we must allocate registers for the result columns to be yielded. Each subselect will then yield to
+    // the coroutine using the same set of registers.
+    let reg_result_cols_start = match right_most.query_destination {
+        QueryDestination::CoroutineYield { .. } => {
+            Some(program.alloc_registers(right_most.result_columns.len()))
+        }
+        _ => None,
+    };
+    emit_compound_select_rows(program, plan, schema, syms, reg_result_cols_start)?;
+
+    program.result_columns = right_plan.result_columns;
+    program.table_references.extend(right_plan.table_references);
+
+    Ok(())
+}
+
+/// Emits the bytecode producing the rows of a compound SELECT, which are either returned to the
+/// caller or, if its SELECTs yield to a coroutine, yielded in the registers starting at
+/// `reg_result_cols_start`.
+pub fn emit_compound_select_rows(
+    program: &mut ProgramBuilder,
+    plan: Plan,
+    schema: &Schema,
+    syms: &SymbolTable,
+    reg_result_cols_start: Option<usize>,
 ) -> crate::Result<()> {
     let Plan::CompoundSelect {
         left: _left,
         right_most,
         limit,
         offset,
-        ..
+        order_by,
     } = &plan
     else {
         crate::bail_parse_error!("expected compound select plan");
     };
 
-    let right_plan = right_most.clone();
     // Trivial exit on LIMIT 0
     if matches!(limit.as_ref().and_then(try_fold_expr_to_i64), Some(v) if v == 0) {
-        program.result_columns = right_plan.result_columns;
-        program.table_references.extend(right_plan.table_references);
         return Ok(());
     }
 
@@ -87,33 +118,36 @@ pub fn emit_program_for_compound_select(
         reg
     });
 
-    // When a compound SELECT is part of a query that yields results to a coroutine (e.g. within an INSERT clause),
-    // we must allocate registers for the result columns to be yielded. Each subselect will then yield to
-    // the coroutine using the same set of registers.
-    let (yield_reg, reg_result_cols_start) = match right_most.query_destination {
-        QueryDestination::CoroutineYield { yield_reg, .. } => {
-            let start_reg = program.alloc_registers(right_most.result_columns.len());
-            (Some(yield_reg), Some(start_reg))
-        }
-        _ => (None, None),
+    let yield_reg = match right_most.query_destination {
+        QueryDestination::CoroutineYield { yield_reg, .. } => Some(yield_reg),
+        _ => None,
     };
 
     emit_explain!(program, true, "COMPOUND QUERY".to_owned());
-    emit_compound_select(
-        program,
-        plan,
-        schema,
-        syms,
-        limit_ctx,
-        offset_reg,
-        yield_reg,
-        reg_result_cols_start,
-    )?;
+    if order_by.is_some() {
+        emit_sorted_compound_select(
+            program,
+            plan,
+            schema,
+            syms,
+            limit_ctx,
+            offset_reg,
+            yield_reg,
+            reg_result_cols_start,
+        )?;
+    } else {
+        emit_compound_select(
+            program,
+            plan,
+            schema,
+            syms,
+            limit_ctx,
+            offset_reg,
+            yield_reg,
+            reg_result_cols_start,
+        )?;
+    }
     program.pop_current_parent_explain();
-
-    program.result_columns = right_plan.result_columns;
-    program.table_references.extend(right_plan.table_references);
-
     Ok(())
 }
 
@@ -141,6 +175,34 @@ fn emit_compound_select(
         unreachable!()
     };
 
+    // Without indexes, the rows of UNION, INTERSECT and EXCEPT are deduplicated with sorters
+    if !schema.indexes_enabled()
+        && left
+            .iter()
+            .any(|(_, operator)| *operator != CompoundOperator::UnionAll)
+    {
+        let label_done = program.allocate_label();
+        let output = CompoundOutput {
+            limit_ctx,
+            offset_reg,
+            yield_reg,
+            label_done,
+        };
+        emit_compound_rows_with_sorters(
+            program,
+            left,
+            right_most,
+            schema,
+            syms,
+            &mut |program, cols_start_reg, num_cols| {
+                output.emit_row(program, cols_start_reg, num_cols);
+                Ok(())
+            },
+        )?;
+        program.preassign_label_to_next_insn(label_done);
+        return Ok(());
+    }
+
     let mut right_most_ctx = TranslateCtx::new(
         program,
         schema,
@@ -203,7 +265,7 @@ fn emit_compound_select(
                     } => (cursor_id, index.clone()),
                     _ => {
                         new_dedupe_index = true;
-                        create_dedupe_index(program, &right_most, schema)?
+                        create_dedupe_index(program, &right_most)?
                     }
                 };
                 plan.query_destination = QueryDestination::EphemeralIndex {
@@ -258,8 +320,7 @@ fn emit_compound_select(
                     target_cursor_id = Some(cursor_id);
                 }
 
-                let (left_cursor_id, left_index) =
-                    create_dedupe_index(program, &right_most, schema)?;
+                let (left_cursor_id, left_index) = create_dedupe_index(program, &right_most)?;
                 plan.query_destination = QueryDestination::EphemeralIndex {
                     cursor_id: left_cursor_id,
                     index: left_index.clone(),
@@ -283,8 +344,7 @@ fn emit_compound_select(
                     reg_result_cols_start,
                 )?;
 
-                let (right_cursor_id, right_index) =
-                    create_dedupe_index(program, &right_most, schema)?;
+                let (right_cursor_id, right_index) = create_dedupe_index(program, &right_most)?;
                 right_most.query_destination = QueryDestination::EphemeralIndex {
                     cursor_id: right_cursor_id,
                     index: right_index,
@@ -312,7 +372,7 @@ fn emit_compound_select(
                     } => (cursor_id, index),
                     _ => {
                         new_index = true;
-                        create_dedupe_index(program, &right_most, schema)?
+                        create_dedupe_index(program, &right_most)?
                     }
                 };
                 plan.query_destination = QueryDestination::EphemeralIndex {
@@ -370,16 +430,471 @@ fn emit_compound_select(
     Ok(())
 }
 
+/// Emits the bytecode for a compound SELECT with an ORDER BY clause. The rows of the compound
+/// SELECT are produced by a coroutine and inserted into a sorter, which is then read in order.
+/// LIMIT and OFFSET apply to the sorted rows.
+#[allow(clippy::too_many_arguments)]
+fn emit_sorted_compound_select(
+    program: &mut ProgramBuilder,
+    plan: Plan,
+    schema: &Schema,
+    syms: &SymbolTable,
+    limit_ctx: Option<LimitCtx>,
+    offset_reg: Option<usize>,
+    yield_reg: Option<usize>,
+    reg_result_cols_start: Option<usize>,
+) -> crate::Result<()> {
+    let Plan::CompoundSelect {
+        mut left,
+        mut right_most,
+        order_by: Some(order_by),
+        ..
+    } = plan
+    else {
+        unreachable!()
+    };
+    let num_cols = right_most.result_columns.len();
+    let left_most = left.first().map(|(plan, _)| plan).unwrap_or(&right_most);
+    let mut sort_keys = Vec::with_capacity(order_by.len());
+    for (expr, order) in &order_by {
+        let (column_idx, collation) = compound_order_by_column(expr, left_most)?;
+        sort_keys.push((column_idx, *order, collation));
+    }
+
+    // The values yielded by the coroutine are read from the registers after the yield register
+    let rows_yield_reg = program.alloc_register();
+    let rows_start_reg = program.alloc_registers(num_cols);
+    let label_coroutine_start = program.allocate_label();
+    let label_coroutine_end = program.allocate_label();
+    let destination = QueryDestination::CoroutineYield {
+        yield_reg: rows_yield_reg,
+        coroutine_implementation_start: label_coroutine_start,
+    };
+    right_most.query_destination = destination.clone();
+    for (plan, _) in left.iter_mut() {
+        plan.query_destination = destination.clone();
+    }
+    program.emit_insn(Insn::InitCoroutine {
+        yield_reg: rows_yield_reg,
+        jump_on_definition: label_coroutine_end,
+        start_offset: label_coroutine_start,
+    });
+    program.preassign_label_to_next_insn(label_coroutine_start);
+    emit_compound_select(
+        program,
+        Plan::CompoundSelect {
+            left,
+            right_most,
+            limit: None,
+            offset: None,
+            order_by: None,
+        },
+        schema,
+        syms,
+        None,
+        None,
+        Some(rows_yield_reg),
+        Some(rows_start_reg),
+    )?;
+    program.emit_insn(Insn::EndCoroutine {
+        yield_reg: rows_yield_reg,
+    });
+    program.preassign_label_to_next_insn(label_coroutine_end);
+
+    emit_explain!(program, false, "USE TEMP B-TREE FOR ORDER BY".to_owned());
+    // The sorter has the sort keys first, then the result columns
+    let num_keys = sort_keys.len();
+    let sort_cursor = program.alloc_cursor_id(CursorType::Sorter);
+    program.emit_insn(Insn::SorterOpen {
+        cursor_id: sort_cursor,
+        columns: num_keys,
+        order: sort_keys.iter().map(|(_, order, _)| *order).collect(),
+        collations: sort_keys
+            .iter()
+            .map(|(_, _, collation)| *collation)
+            .collect(),
+    });
+    let label_insert_loop = program.allocate_label();
+    let label_sort = program.allocate_label();
+    program.preassign_label_to_next_insn(label_insert_loop);
+    program.emit_insn(Insn::Yield {
+        yield_reg: rows_yield_reg,
+        end_offset: label_sort,
+    });
+    let sorter_start_reg = program.alloc_registers(num_keys + num_cols);
+    for (i, (column_idx, _, _)) in sort_keys.iter().enumerate() {
+        program.emit_insn(Insn::Copy {
+            src_reg: rows_start_reg + column_idx,
+            dst_reg: sorter_start_reg + i,
+            extra_amount: 0,
+        });
+    }
+    program.emit_insn(Insn::Copy {
+        src_reg: rows_start_reg,
+        dst_reg: sorter_start_reg + num_keys,
+        extra_amount: num_cols - 1,
+    });
+    let record_reg = program.alloc_register();
+    program.emit_insn(Insn::MakeRecord {
+        start_reg: sorter_start_reg,
+        count: num_keys + num_cols,
+        dest_reg: record_reg,
+        index_name: None,
+        affinity_str: None,
+    });
+    program.emit_insn(Insn::SorterInsert {
+        cursor_id: sort_cursor,
+        record_reg,
+    });
+    program.emit_insn(Insn::Goto {
+        target_pc: label_insert_loop,
+    });
+
+    program.preassign_label_to_next_insn(label_sort);
+    let label_done = program.allocate_label();
+    let label_sort_loop = program.allocate_label();
+    let reg_sorter_data = program.alloc_register();
+    let pseudo_cursor = program.alloc_cursor_id(CursorType::Pseudo(PseudoCursorType {
+        column_count: num_keys + num_cols,
+    }));
+    program.emit_insn(Insn::OpenPseudo {
+        cursor_id: pseudo_cursor,
+        content_reg: reg_sorter_data,
+        num_fields: num_keys + num_cols,
+    });
+    program.emit_insn(Insn::SorterSort {
+        cursor_id: sort_cursor,
+        pc_if_empty: label_done,
+    });
+    program.preassign_label_to_next_insn(label_sort_loop);
+    program.emit_insn(Insn::SorterData {
+        cursor_id: sort_cursor,
+        dest_reg: reg_sorter_data,
+        pseudo_cursor,
+    });
+    let cols_start_reg = match (yield_reg, reg_result_cols_start) {
+        (Some(yield_reg), _) => yield_reg + 1,
+        (None, Some(start_reg)) => start_reg,
+        (None, None) => program.alloc_registers(num_cols),
+    };
+    for i in 0..num_cols {
+        program.emit_column_or_rowid(pseudo_cursor, num_keys + i, cols_start_reg + i);
+    }
+    let output = CompoundOutput {
+        limit_ctx,
+        offset_reg,
+        yield_reg,
+        label_done,
+    };
+    output.emit_row(program, cols_start_reg, num_cols);
+    program.emit_insn(Insn::SorterNext {
+        cursor_id: sort_cursor,
+        pc_if_next: label_sort_loop,
+    });
+    program.preassign_label_to_next_insn(label_done);
+    Ok(())
+}
+
+/// Returns the index of the result column that a term of the ORDER BY clause of a compound
+/// SELECT sorts by, and its collating sequence. The planner has already resolved the term to a
+/// column number, see [crate::translate::select::prepare_select_plan]. Unless the term has a
+/// COLLATE clause, the collating sequence is the one of the column of the left-most SELECT.
+fn compound_order_by_column(
+    expr: &ast::Expr,
+    left_most: &SelectPlan,
+) -> crate::Result<(usize, Option<CollationSeq>)> {
+    match expr {
+        ast::Expr::Collate(inner, collation) => {
+            let (column_idx, _) = compound_order_by_column(inner, left_most)?;
+            Ok((column_idx, Some(CollationSeq::new(collation.as_str())?)))
+        }
+        ast::Expr::Literal(ast::Literal::Numeric(num)) => {
+            let column_idx = num.parse::<usize>()? - 1;
+            let collation = sort_key_collation(
+                &left_most.result_columns[column_idx].expr,
+                &left_most.table_references,
+            )?;
+            Ok((column_idx, collation))
+        }
+        _ => crate::bail_parse_error!("unresolved ORDER BY term of compound SELECT: {expr}"),
+    }
+}
+
+/// Where the rows of a compound SELECT go once they have been computed, applying its LIMIT and
+/// OFFSET: to the caller, or to the parent query if the compound SELECT is a coroutine.
+struct CompoundOutput {
+    limit_ctx: Option<LimitCtx>,
+    offset_reg: Option<usize>,
+    yield_reg: Option<usize>,
+    /// Jumped to once the LIMIT is reached.
+    label_done: BranchOffset,
+}
+
+impl CompoundOutput {
+    fn emit_row(&self, program: &mut ProgramBuilder, cols_start_reg: usize, num_cols: usize) {
+        let label_skip = program.allocate_label();
+        if let Some(reg) = self.offset_reg {
+            program.emit_insn(Insn::IfPos {
+                reg,
+                target_pc: label_skip,
+                decrement_by: 1,
+            });
+        }
+        if let Some(yield_reg) = self.yield_reg {
+            // The parent query reads the values from the registers after the yield register
+            if cols_start_reg != yield_reg + 1 {
+                program.emit_insn(Insn::Copy {
+                    src_reg: cols_start_reg,
+                    dst_reg: yield_reg + 1,
+                    extra_amount: num_cols - 1,
+                });
+            }
+            program.emit_insn(Insn::Yield {
+                yield_reg,
+                end_offset: BranchOffset::Offset(0),
+            });
+        } else {
+            program.emit_insn(Insn::ResultRow {
+                start_reg: cols_start_reg,
+                count: num_cols,
+            });
+        }
+        if let Some(limit_ctx) = self.limit_ctx {
+            program.emit_insn(Insn::DecrJumpZero {
+                reg: limit_ctx.reg_limit,
+                target_pc: self.label_done,
+            });
+        }
+        program.preassign_label_to_next_insn(label_skip);
+    }
+}
+
+/// Receives the rows of a compound SELECT, or of a part of it, given the register holding their
+/// first column and their number of columns.
+type RowConsumer<'a> = dyn FnMut(&mut ProgramBuilder, usize, usize) -> crate::Result<()> + 'a;
+
+/// Emits the bytecode producing the rows of a compound SELECT without using any index, and passes
+/// them to `consume`. The rows of the two sides of a UNION, INTERSECT or EXCEPT are inserted into
+/// a sorter, tagged with the side they come from, so that equal rows end up next to each other
+/// and only one of them is kept:
+/// * UNION keeps the first row of every run of equal rows.
+/// * EXCEPT sorts the rows of the right side first in every run of equal rows, and keeps the
+///   first row of the runs that start with a row of the left side.
+/// * INTERSECT sorts the rows of the left side first in every run of equal rows, and keeps the
+///   first row of the right side that follows a row of the left side.
+fn emit_compound_rows_with_sorters(
+    program: &mut ProgramBuilder,
+    mut left: Vec<(SelectPlan, CompoundOperator)>,
+    right_most: SelectPlan,
+    schema: &Schema,
+    syms: &SymbolTable,
+    consume: &mut RowConsumer,
+) -> crate::Result<()> {
+    let Some((plan, operator)) = left.pop() else {
+        return emit_select_rows(program, right_most, schema, syms, consume);
+    };
+    if operator == CompoundOperator::UnionAll {
+        emit_compound_rows_with_sorters(program, left, plan, schema, syms, consume)?;
+        return emit_select_rows(program, right_most, schema, syms, consume);
+    }
+
+    // The sorter has the result columns and then the tag of the side the row comes from
+    let num_cols = right_most.result_columns.len();
+    let tag_order = if operator == CompoundOperator::Except {
+        SortOrder::Desc
+    } else {
+        SortOrder::Asc
+    };
+    let sort_cursor = program.alloc_cursor_id(CursorType::Sorter);
+    program.emit_insn(Insn::SorterOpen {
+        cursor_id: sort_cursor,
+        columns: num_cols + 1,
+        order: std::iter::repeat_n(SortOrder::Asc, num_cols)
+            .chain(std::iter::once(tag_order))
+            .collect(),
+        collations: vec![None; num_cols + 1],
+    });
+    let sorter_start_reg = program.alloc_registers(num_cols + 1);
+    let record_reg = program.alloc_register();
+    let insert_row = |tag: i64| {
+        move |program: &mut ProgramBuilder,
+              cols_start_reg: usize,
+              num_cols: usize|
+              -> crate::Result<()> {
+            program.emit_insn(Insn::Copy {
+                src_reg: cols_start_reg,
+                dst_reg: sorter_start_reg,
+                extra_amount: num_cols - 1,
+            });
+            program.emit_int(tag, sorter_start_reg + num_cols);
+            program.emit_insn(Insn::MakeRecord {
+                start_reg: sorter_start_reg,
+                count: num_cols + 1,
+                dest_reg: record_reg,
+                index_name: None,
+                affinity_str: None,
+            });
+            program.emit_insn(Insn::SorterInsert {
+                cursor_id: sort_cursor,
+                record_reg,
+            });
+            Ok(())
+        }
+    };
+    emit_compound_rows_with_sorters(program, left, plan, schema, syms, &mut insert_row(0))?;
+    emit_select_rows(program, right_most, schema, syms, &mut insert_row(1))?;
+
+    let label_end = program.allocate_label();
+    let label_loop = program.allocate_label();
+    let label_new_run = program.allocate_label();
+    let label_same_run = program.allocate_label();
+    let label_next = program.allocate_label();
+    let reg_sorter_data = program.alloc_register();
+    let pseudo_cursor = program.alloc_cursor_id(CursorType::Pseudo(PseudoCursorType {
+        column_count: num_cols + 1,
+    }));
+    program.emit_insn(Insn::OpenPseudo {
+        cursor_id: pseudo_cursor,
+        content_reg: reg_sorter_data,
+        num_fields: num_cols + 1,
+    });
+    // The previous row and its tag, to tell whether the current row starts a new run
+    let prev_start_reg = program.alloc_registers(num_cols + 1);
+    let reg_first_row = program.alloc_register();
+    program.emit_int(1, reg_first_row);
+    program.emit_insn(Insn::SorterSort {
+        cursor_id: sort_cursor,
+        pc_if_empty: label_end,
+    });
+    program.preassign_label_to_next_insn(label_loop);
+    program.emit_insn(Insn::SorterData {
+        cursor_id: sort_cursor,
+        dest_reg: reg_sorter_data,
+        pseudo_cursor,
+    });
+    let cols_start_reg = program.alloc_registers(num_cols + 1);
+    let tag_reg = cols_start_reg + num_cols;
+    for i in 0..=num_cols {
+        program.emit_column_or_rowid(pseudo_cursor, i, cols_start_reg + i);
+    }
+    program.emit_insn(Insn::If {
+        reg: reg_first_row,
+        target_pc: label_new_run,
+        jump_if_null: false,
+    });
+    program.emit_insn(Insn::Compare {
+        start_reg_a: prev_start_reg,
+        start_reg_b: cols_start_reg,
+        count: num_cols,
+        collation: None,
+    });
+    program.emit_insn(Insn::Jump {
+        target_pc_lt: label_new_run,
+        target_pc_eq: label_same_run,
+        target_pc_gt: label_new_run,
+    });
+
+    program.preassign_label_to_next_insn(label_new_run);
+    match operator {
+        CompoundOperator::Union => consume(program, cols_start_reg, num_cols)?,
+        CompoundOperator::Except => {
+            program.emit_insn(Insn::If {
+                reg: tag_reg,
+                target_pc: label_next,
+                jump_if_null: false,
+            });
+            consume(program, cols_start_reg, num_cols)?;
+        }
+        _ => {}
+    }
+    program.emit_insn(Insn::Goto {
+        target_pc: label_next,
+    });
+
+    program.preassign_label_to_next_insn(label_same_run);
+    if operator == CompoundOperator::Intersect {
+        program.emit_insn(Insn::IfNot {
+            reg: tag_reg,
+            target_pc: label_next,
+            jump_if_null: false,
+        });
+        program.emit_insn(Insn::If {
+            reg: prev_start_reg + num_cols,
+            target_pc: label_next,
+            jump_if_null: false,
+        });
+        consume(program, cols_start_reg, num_cols)?;
+    }
+
+    program.preassign_label_to_next_insn(label_next);
+    program.emit_insn(Insn::Copy {
+        src_reg: cols_start_reg,
+        dst_reg: prev_start_reg,
+        extra_amount: num_cols,
+    });
+    program.emit_int(0, reg_first_row);
+    program.emit_insn(Insn::SorterNext {
+        cursor_id: sort_cursor,
+        pc_if_next: label_loop,
+    });
+    program.preassign_label_to_next_insn(label_end);
+    Ok(())
+}
+
+/// Runs a SELECT of a compound SELECT as a coroutine and passes each of its rows to `consume`.
+fn emit_select_rows(
+    program: &mut ProgramBuilder,
+    mut plan: SelectPlan,
+    schema: &Schema,
+    syms: &SymbolTable,
+    consume: &mut RowConsumer,
+) -> crate::Result<()> {
+    let num_cols = plan.result_columns.len();
+    let yield_reg = program.alloc_register();
+    let cols_start_reg = program.alloc_registers(num_cols);
+    let label_coroutine_start = program.allocate_label();
+    let label_coroutine_end = program.allocate_label();
+    plan.query_destination = QueryDestination::CoroutineYield {
+        yield_reg,
+        coroutine_implementation_start: label_coroutine_start,
+    };
+    program.emit_insn(Insn::InitCoroutine {
+        yield_reg,
+        jump_on_definition: label_coroutine_end,
+        start_offset: label_coroutine_start,
+    });
+    program.preassign_label_to_next_insn(label_coroutine_start);
+    let mut t_ctx = TranslateCtx::new(
+        program,
+        schema,
+        syms,
+        plan.table_references.joined_tables().len(),
+    );
+    t_ctx.reg_result_cols_start = Some(cols_start_reg);
+    emit_query(program, &mut plan, &mut t_ctx)?;
+    program.emit_insn(Insn::EndCoroutine { yield_reg });
+    program.preassign_label_to_next_insn(label_coroutine_end);
+
+    let label_loop = program.allocate_label();
+    let label_end = program.allocate_label();
+    program.preassign_label_to_next_insn(label_loop);
+    program.emit_insn(Insn::Yield {
+        yield_reg,
+        end_offset: label_end,
+    });
+    consume(program, cols_start_reg, num_cols)?;
+    program.emit_insn(Insn::Goto {
+        target_pc: label_loop,
+    });
+    program.preassign_label_to_next_insn(label_end);
+    Ok(())
+}
+
 // Creates an ephemeral index that will be used to deduplicate the results of any sub-selects
 fn create_dedupe_index(
     program: &mut ProgramBuilder,
     select: &SelectPlan,
-    schema: &Schema,
 ) -> crate::Result<(usize, Arc<Index>)> {
-    if !schema.indexes_enabled {
-        crate::bail_parse_error!("UNION OR INTERSECT or EXCEPT is not supported without indexes");
-    }
-
     let dedupe_index = Arc::new(Index {
         columns: select
             .result_columns
@@ -393,6 +908,7 @@ fn create_dedupe_index(
                 pos_in_table: 0,
                 default: None,
                 collation: None, // FIXME: this should be inferred
+                expr: None,
             })
             .collect(),
         name: "compound_dedupe".to_string(),
@@ -401,6 +917,8 @@ fn create_dedupe_index(
         table_name: String::new(),
         unique: false,
         has_rowid: false,
+        num_key_columns: None,
+        where_clause: None,
     });
     let cursor_id = program.alloc_cursor_id(CursorType::BTreeIndex(dedupe_index.clone()));
     program.emit_insn(Insn::OpenEphemeral {
@@ -1,13 +1,17 @@
 use crate::schema::Table;
 use crate::translate::emitter::emit_program;
+use crate::translate::fkeys::has_foreign_key_actions;
 use crate::translate::optimizer::optimize_plan;
-use crate::translate::plan::{DeletePlan, Operation, Plan};
-use crate::translate::planner::{parse_limit, parse_where};
+use crate::translate::plan::{DeletePlan, IterationDirection, Operation, Plan};
+use crate::translate::planner::{parse_limit, parse_where, resolve_indexed_by};
+use crate::translate::subquery::{expr_contains_subquery, plan_subqueries_from_where_clause};
+use crate::translate::trigger::{has_triggers, translate_delete_from_view};
+use crate::translate::update::build_ephemeral_rowid_plan;
 use crate::util::normalize_ident;
-use crate::vdbe::builder::{ProgramBuilder, ProgramBuilderOpts, TableRefIdCounter};
+use crate::vdbe::builder::{ProgramBuilder, ProgramBuilderOpts};
 use crate::{schema::Schema, Result, SymbolTable};
 use std::sync::Arc;
-use turso_parser::ast::{Expr, Limit, QualifiedName, ResultColumn};
+use turso_parser::ast::{Expr, Indexed, Limit, QualifiedName, ResultColumn, TriggerEvent};
 
 use super::plan::{ColumnUsedMask, JoinedTable, TableReferences};
 
@@ -15,6 +19,7 @@ use super::plan::{ColumnUsedMask, JoinedTable, TableReferences};
 pub fn translate_delete(
     schema: &Schema,
     tbl_name: &QualifiedName,
+    indexed: Option<Indexed>,
     where_clause: Option<Box<Expr>>,
     limit: Option<Limit>,
     returning: Vec<ResultColumn>,
@@ -22,8 +27,28 @@ pub fn translate_delete(
     mut program: ProgramBuilder,
     connection: &Arc<crate::Connection>,
 ) -> Result<ProgramBuilder> {
+    let database_id = connection.resolve_database_id(tbl_name)?;
+    let database_schema = connection.database_schema(database_id)?;
+    let schema = database_schema.as_deref().unwrap_or(schema);
     let tbl_name = normalize_ident(tbl_name.name.as_str());
 
+    if schema.get_table(&tbl_name).is_none() {
+        if let Some(view) = schema.get_view(&tbl_name) {
+            if !returning.is_empty() {
+                crate::bail_parse_error!("RETURNING is not supported for views");
+            }
+            return translate_delete_from_view(
+                schema,
+                view,
+                where_clause,
+                limit,
+                syms,
+                program,
+                connection,
+            );
+        }
+    }
+
     // Check if this is a system table that should be protected from direct writes
     if crate::schema::is_system_table(&tbl_name) {
         crate::bail_parse_error!("table {} may not be modified", tbl_name);
@@ -49,10 +74,13 @@ pub fn translate_delete(
     let mut delete_plan = prepare_delete_plan(
         schema,
         tbl_name,
+        database_id,
+        indexed.as_ref(),
         where_clause,
         limit,
         result_columns,
-        &mut program.table_reference_counter,
+        &mut program,
+        syms,
         connection,
     )?;
     optimize_plan(&mut delete_plan, schema)?;
@@ -65,17 +93,21 @@ pub fn translate_delete(
         approx_num_labels: 0,
     };
     program.extend(&opts);
-    emit_program(&mut program, delete_plan, schema, syms, |_| {})?;
+    emit_program(&mut program, delete_plan, schema, syms, connection, |_| {})?;
     Ok(program)
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn prepare_delete_plan(
     schema: &Schema,
     tbl_name: String,
+    database_id: usize,
+    indexed: Option<&Indexed>,
     where_clause: Option<Box<Expr>>,
     limit: Option<Limit>,
     result_columns: Vec<super::plan::ResultSetColumn>,
-    table_ref_counter: &mut TableRefIdCounter,
+    program: &mut ProgramBuilder,
+    syms: &SymbolTable,
     connection: &Arc<crate::Connection>,
 ) -> Result<Plan> {
     let table = match schema.get_table(&tbl_name) {
@@ -96,27 +128,69 @@ pub fn prepare_delete_plan(
         crate::bail_parse_error!("Table is neither a virtual table nor a btree table");
     };
     let indexes = schema.get_indices(table.get_name()).to_vec();
+    let indexed = resolve_indexed_by(indexed, &indexes)?;
+    let has_delete_triggers = has_triggers(schema, table.get_name(), &TriggerEvent::Delete);
+    let has_delete_actions =
+        has_foreign_key_actions(schema, connection, table.get_name(), &TriggerEvent::Delete);
     let joined_tables = vec![JoinedTable {
         op: Operation::default_scan_for(&table),
         table,
         identifier: tbl_name,
-        internal_id: table_ref_counter.next(),
+        internal_id: program.table_reference_counter.next(),
         join_info: None,
         col_used_mask: ColumnUsedMask::default(),
-        database_id: 0,
+        database_id,
+        indexed: None,
     }];
     let mut table_references = TableReferences::new(joined_tables, vec![]);
 
     let mut where_predicates = vec![];
 
-    // Parse the WHERE clause
-    parse_where(
-        where_clause.as_deref(),
-        &mut table_references,
-        None,
-        &mut where_predicates,
-        connection,
-    )?;
+    // Triggers and the actions of foreign key constraints may modify the table rows are deleted
+    // from, so the rowids to delete are collected up front, like SQLite does. So are they when the WHERE clause has a subquery,
+    // as it may read the table rows are deleted from.
+    let where_has_subquery = where_clause.as_deref().is_some_and(expr_contains_subquery);
+    let mut param_idx = 1;
+    let ephemeral_plan = if has_delete_triggers || has_delete_actions || where_has_subquery {
+        build_ephemeral_rowid_plan(
+            program,
+            schema,
+            syms,
+            &table_references.joined_tables()[0].table,
+            database_id,
+            IterationDirection::Forwards,
+            indexed.clone(),
+            where_clause.as_deref(),
+            &result_columns,
+            connection,
+            &mut param_idx,
+        )?
+    } else {
+        None
+    };
+
+    if ephemeral_plan.is_none() {
+        // Otherwise the rows to delete are found by the statement's own loop,
+        // which INDEXED BY and NOT INDEXED apply to.
+        table_references.joined_tables_mut()[0].indexed = indexed;
+        // Parse the WHERE clause
+        parse_where(
+            where_clause.as_deref(),
+            &mut table_references,
+            None,
+            &mut where_predicates,
+            connection,
+        )?;
+        plan_subqueries_from_where_clause(
+            program,
+            &mut where_predicates,
+            &mut table_references,
+            schema,
+            syms,
+            connection,
+            &mut param_idx,
+        )?;
+    }
 
     // Parse the LIMIT/OFFSET clause
     let (resolved_limit, resolved_offset) =
@@ -131,6 +205,7 @@ pub fn prepare_delete_plan(
         offset: resolved_offset,
         contains_constant_false_condition: false,
         indexes,
+        ephemeral_plan,
     };
 
     Ok(Plan::Delete(plan))
@@ -12,7 +12,8 @@ use super::group_by::{
     group_by_agg_phase, group_by_emit_row_phase, init_group_by, GroupByMetadata, GroupByRowSource,
 };
 use super::main_loop::{
-    close_loop, emit_loop, init_distinct, init_loop, open_loop, LeftJoinMetadata, LoopLabels,
+    close_loop, emit_loop, emit_unmatched_right_join_rows, init_distinct, init_loop, open_loop,
+    LeftJoinMetadata, LoopLabels, RightJoinMetadata,
 };
 use super::order_by::{emit_order_by, init_order_by, SortMetadata};
 use super::plan::{
@@ -20,20 +21,27 @@ use super::plan::{
 };
 use super::select::emit_simple_count;
 use super::subquery::emit_subqueries;
+use super::window::{emit_window, init_window, WindowMetadata};
 use crate::error::SQLITE_CONSTRAINT_PRIMARYKEY;
 use crate::function::Func;
-use crate::schema::{BTreeTable, Column, Schema, Table};
+use crate::schema::{BTreeTable, Column, Index, Schema, Table};
 use crate::translate::compound_select::emit_program_for_compound_select;
 use crate::translate::expr::{emit_returning_results, ReturningValueRegisters};
+use crate::translate::fkeys::{emit_fk_actions, emit_fk_checks, has_foreign_keys};
+use crate::translate::generated::{
+    emit_generated_columns, emit_generated_columns_not_null_checks, emit_table_column,
+};
+use crate::translate::index::{emit_index_expr, emit_partial_index_check};
 use crate::translate::plan::{DeletePlan, Plan, QueryDestination, Search};
 use crate::translate::result_row::try_fold_expr_to_i64;
+use crate::translate::trigger::{fire_triggers, get_relevant_triggers, TriggerRow, TriggerRows};
 use crate::translate::values::emit_values;
 use crate::util::exprs_are_equivalent;
 use crate::vdbe::builder::{CursorKey, CursorType, ProgramBuilder};
 use crate::vdbe::insn::{CmpInsFlags, IdxInsertFlags, InsertFlags, RegisterOrLiteral};
 use crate::vdbe::CursorID;
 use crate::vdbe::{insn::Insn, BranchOffset};
-use crate::{bail_parse_error, Result, SymbolTable};
+use crate::{bail_parse_error, Connection, Result, SymbolTable};
 
 pub struct Resolver<'a> {
     pub schema: &'a Schema,
@@ -130,9 +138,14 @@ pub struct TranslateCtx<'a> {
     pub meta_group_by: Option<GroupByMetadata>,
     // metadata for the order by operator
     pub meta_sort: Option<SortMetadata>,
+    // metadata for the window operator
+    pub meta_window: Option<WindowMetadata>,
     /// mapping between table loop index and associated metadata (for left joins only)
     /// this metadata exists for the right table in a given left join
     pub meta_left_joins: Vec<Option<LeftJoinMetadata>>,
+    /// mapping between table loop index and associated metadata (for right joins only)
+    /// this metadata exists for the right table in a given right join
+    pub meta_right_joins: Vec<Option<RightJoinMetadata>>,
     pub resolver: Resolver<'a>,
     /// A list of expressions that are not aggregates, along with a flag indicating
     /// whether the expression should be included in the output for each group.
@@ -167,7 +180,9 @@ impl<'a> TranslateCtx<'a> {
             reg_result_cols_start: None,
             meta_group_by: None,
             meta_left_joins: (0..table_count).map(|_| None).collect(),
+            meta_right_joins: (0..table_count).map(|_| None).collect(),
             meta_sort: None,
+            meta_window: None,
             resolver: Resolver::new(schema, syms),
             non_aggregate_expressions: Vec::new(),
             cdc_cursor_id: None,
@@ -202,12 +217,15 @@ pub fn emit_program(
     plan: Plan,
     schema: &Schema,
     syms: &SymbolTable,
+    connection: &Arc<Connection>,
     after: impl FnOnce(&mut ProgramBuilder),
 ) -> Result<()> {
     match plan {
         Plan::Select(plan) => emit_program_for_select(program, plan, schema, syms),
-        Plan::Delete(plan) => emit_program_for_delete(program, plan, schema, syms),
-        Plan::Update(plan) => emit_program_for_update(program, plan, schema, syms, after),
+        Plan::Delete(plan) => emit_program_for_delete(program, plan, schema, syms, connection),
+        Plan::Update(plan) => {
+            emit_program_for_update(program, plan, schema, syms, connection, after)
+        }
         Plan::CompoundSelect { .. } => {
             emit_program_for_compound_select(program, plan, schema, syms)
         }
@@ -255,6 +273,16 @@ pub fn emit_query<'a>(
         return Ok(reg_result_cols_start);
     }
 
+    // Consumers of a coroutine read the yielded values from the registers right after the
+    // yield register, so allocate them before any subquery claims those registers.
+    if matches!(
+        plan.query_destination,
+        QueryDestination::CoroutineYield { .. }
+    ) && t_ctx.reg_result_cols_start.is_none()
+    {
+        t_ctx.reg_result_cols_start = Some(program.alloc_registers(plan.result_columns.len()));
+    }
+
     // Emit subqueries first so the results can be read in the main query loop.
     emit_subqueries(program, t_ctx, &mut plan.table_references)?;
 
@@ -299,6 +327,10 @@ pub fn emit_query<'a>(
         )?;
     }
 
+    if plan.window.is_some() {
+        init_window(program, t_ctx, plan)?;
+    }
+
     if let Some(ref group_by) = plan.group_by {
         init_group_by(
             program,
@@ -359,14 +391,25 @@ pub fn emit_query<'a>(
         None,
     )?;
 
+    // Emit the rows of RIGHT JOINs that had no match in the main loop
+    emit_unmatched_right_join_rows(
+        program,
+        t_ctx,
+        &plan.table_references,
+        &plan.join_order,
+        &plan.where_clause,
+    )?;
+
     program.preassign_label_to_next_insn(after_main_loop_label);
 
     let mut order_by_necessary =
         !plan.order_by.is_empty() && !plan.contains_constant_false_condition;
     let order_by = &plan.order_by;
 
-    // Handle GROUP BY and aggregation processing
-    if plan.group_by.is_some() {
+    // Handle window functions, GROUP BY and aggregation processing
+    if plan.window.is_some() {
+        emit_window(program, t_ctx, plan)?;
+    } else if plan.group_by.is_some() {
         let row_source = &t_ctx
             .meta_group_by
             .as_ref()
@@ -394,9 +437,10 @@ pub fn emit_query<'a>(
 #[instrument(skip_all, level = Level::DEBUG)]
 fn emit_program_for_delete(
     program: &mut ProgramBuilder,
-    plan: DeletePlan,
+    mut plan: DeletePlan,
     schema: &Schema,
     syms: &SymbolTable,
+    connection: &Arc<Connection>,
 ) -> Result<()> {
     let mut t_ctx = TranslateCtx::new(
         program,
@@ -425,6 +469,16 @@ fn emit_program_for_delete(
         });
     }
 
+    let ephemeral_plan = plan.ephemeral_plan.take();
+    let temp_cursor_id = ephemeral_plan
+        .as_ref()
+        .map(|plan| open_ephemeral_plan_cursor(program, plan));
+    if let Some(ephemeral_plan) = ephemeral_plan {
+        program.incr_nesting();
+        emit_program_for_select(program, ephemeral_plan, schema, syms)?;
+        program.decr_nesting();
+    }
+
     // Initialize cursors and other resources needed for query execution
     init_loop(
         program,
@@ -443,7 +497,7 @@ fn emit_program_for_delete(
         &plan.table_references,
         &[JoinOrderMember::default()],
         &plan.where_clause,
-        None,
+        temp_cursor_id,
     )?;
 
     emit_delete_insns(
@@ -451,6 +505,8 @@ fn emit_program_for_delete(
         &mut t_ctx,
         &plan.table_references,
         &plan.result_columns,
+        connection,
+        temp_cursor_id,
     )?;
 
     // Clean up and close the main execution loop
@@ -459,7 +515,7 @@ fn emit_program_for_delete(
         &mut t_ctx,
         &plan.table_references,
         &[JoinOrderMember::default()],
-        None,
+        temp_cursor_id,
     )?;
     program.preassign_label_to_next_insn(after_main_loop_label);
 
@@ -469,11 +525,92 @@ fn emit_program_for_delete(
     Ok(())
 }
 
+/// The registers holding the key of the row a DML statement is modifying: its rowid,
+/// or the primary key columns of a WITHOUT ROWID table.
+#[derive(Debug, Clone, Copy)]
+struct RowKey {
+    start_reg: usize,
+    num_regs: usize,
+    is_rowid: bool,
+}
+
+impl RowKey {
+    /// Reads the key of the current row of `table_cursor_id`, or of `temp_cursor_id` if the
+    /// keys of the rows to modify were collected into an ephemeral table or index up front.
+    fn load(
+        program: &mut ProgramBuilder,
+        btree: Option<&BTreeTable>,
+        table_cursor_id: CursorID,
+        temp_cursor_id: Option<CursorID>,
+    ) -> Self {
+        match btree {
+            Some(btree) if !btree.has_rowid => {
+                let pk_positions = btree.primary_key_column_positions();
+                let start_reg = program.alloc_registers(pk_positions.len());
+                for (i, pos_in_table) in pk_positions.iter().enumerate() {
+                    match temp_cursor_id {
+                        Some(temp_cursor_id) => {
+                            program.emit_column_or_rowid(temp_cursor_id, i, start_reg + i)
+                        }
+                        None => program.emit_column_or_rowid(
+                            table_cursor_id,
+                            *pos_in_table,
+                            start_reg + i,
+                        ),
+                    }
+                }
+                Self {
+                    start_reg,
+                    num_regs: pk_positions.len(),
+                    is_rowid: false,
+                }
+            }
+            _ => {
+                let start_reg = program.alloc_register();
+                program.emit_insn(Insn::RowId {
+                    cursor_id: temp_cursor_id.unwrap_or(table_cursor_id),
+                    dest: start_reg,
+                });
+                Self {
+                    start_reg,
+                    num_regs: 1,
+                    is_rowid: true,
+                }
+            }
+        }
+    }
+
+    /// Positions `table_cursor_id` on the row with this key, jumping to `target_pc` if there is none.
+    fn emit_seek(
+        &self,
+        program: &mut ProgramBuilder,
+        table_cursor_id: CursorID,
+        target_pc: BranchOffset,
+    ) {
+        if self.is_rowid {
+            program.emit_insn(Insn::NotExists {
+                cursor: table_cursor_id,
+                rowid_reg: self.start_reg,
+                target_pc,
+            });
+        } else {
+            program.emit_insn(Insn::NotFound {
+                cursor_id: table_cursor_id,
+                target_pc,
+                record_reg: self.start_reg,
+                num_regs: self.num_regs,
+            });
+        }
+    }
+}
+
 fn emit_delete_insns(
     program: &mut ProgramBuilder,
     t_ctx: &mut TranslateCtx,
     table_references: &TableReferences,
     result_columns: &[super::plan::ResultSetColumn],
+    connection: &Arc<Connection>,
+    temp_cursor_id: Option<CursorID>,
 ) -> Result<()> {
     let table_reference = table_references.joined_tables().first().unwrap();
     if table_reference
@@ -501,13 +638,90 @@ fn emit_delete_insns(
     };
     let main_table_cursor_id =
         program.resolve_cursor_id(&CursorKey::table(table_reference.internal_id));
+    let btree = table_reference.btree();
 
     // Emit the instructions to delete the row
-    let key_reg = program.alloc_register();
-    program.emit_insn(Insn::RowId {
-        cursor_id: main_table_cursor_id,
-        dest: key_reg,
-    });
+    let row_key = RowKey::load(
+        program,
+        btree.as_deref(),
+        main_table_cursor_id,
+        temp_cursor_id,
+    );
+    let key_reg = row_key.start_reg;
+
+    // When looping over the collected rowids, the row may already have been deleted
+    // by a trigger fired for a previous row.
+    let skip_row_label = program.allocate_label();
+    if temp_cursor_id.is_some() {
+        row_key.emit_seek(program, main_table_cursor_id, skip_row_label);
+    }
+
+    let (before_triggers, after_triggers) = match table_reference.btree() {
+        Some(btree_table) => (
+            get_relevant_triggers(
+                program,
+                t_ctx.resolver.schema,
+                &btree_table.name,
+                ast::TriggerTime::Before,
+                &ast::TriggerEvent::Delete,
+                &[],
+            ),
+            get_relevant_triggers(
+                program,
+                t_ctx.resolver.schema,
+                &btree_table.name,
+                ast::TriggerTime::After,
+                &ast::TriggerEvent::Delete,
+                &[],
+            ),
+        ),
+        None => (vec![], vec![]),
+    };
+    // The table whose foreign key constraints are checked, if any
+    let fk_table = btree
+        .as_ref()
+        .filter(|btree| has_foreign_keys(t_ctx.resolver.schema, connection, btree));
+    let old_row = if !before_triggers.is_empty() || !after_triggers.is_empty() || fk_table.is_some()
+    {
+        Some(TriggerRow::load_from_cursor(
+            program,
+            &t_ctx.resolver,
+            main_table_cursor_id,
+            table_reference.columns(),
+        )?)
+    } else {
+        None
+    };
+    if !before_triggers.is_empty() {
+        fire_triggers(
+            program,
+            t_ctx.resolver.schema,
+            t_ctx.resolver.symbol_table,
+            connection,
+            &before_triggers,
+            &TriggerRows {
+                columns: table_reference.columns(),
+                old: old_row.as_ref(),
+                new: None,
+            },
+            skip_row_label,
+        )?;
+        // The trigger programs may have moved the cursor or deleted the row.
+        row_key.emit_seek(program, main_table_cursor_id, skip_row_label);
+    }
+
+    if let (Some(btree), Some(old_row)) = (fk_table, old_row.as_ref()) {
+        emit_fk_checks(
+            program,
+            t_ctx.resolver.schema,
+            table_reference.database_id,
+            connection,
+            btree,
+            Some(old_row),
+            None,
+            &[],
+        )?;
+    }
 
     if table_reference.virtual_table().is_some() {
         let conflict_action = 0u16;
@@ -534,6 +748,12 @@ fn emit_delete_insns(
 
         // Get the index that is being used to iterate the deletion loop, if there is one.
         let iteration_index = table_reference.op.index();
+        // The primary key index of a WITHOUT ROWID table is the table itself.
+        let is_table_btree = |index: &Index| {
+            btree
+                .as_ref()
+                .is_some_and(|btree| index.is_without_rowid_primary_key(btree))
+        };
         // Get all indexes that are not the iteration index.
         let other_indexes = indexes
             .map(|indexes| {
@@ -543,6 +763,7 @@ fn emit_delete_insns(
                         iteration_index
                             .as_ref()
                             .is_none_or(|it_idx| !Arc::ptr_eq(it_idx, index))
+                            && !is_table_btree(index)
                     })
                     .map(|index| {
                         (
@@ -558,39 +779,88 @@ fn emit_delete_insns(
             .unwrap_or_default();
 
         for (index, index_cursor_id) in other_indexes {
-            let num_regs = index.columns.len() + 1;
+            // The indexes of a WITHOUT ROWID table hold its primary key instead of a rowid
+            let num_regs = index.columns.len() + index.has_rowid as usize;
             let start_reg = program.alloc_registers(num_regs);
-            // Emit columns that are part of the index
-            index
-                .columns
-                .iter()
-                .enumerate()
-                .for_each(|(reg_offset, column_index)| {
-                    program.emit_column_or_rowid(
-                        main_table_cursor_id,
-                        column_index.pos_in_table,
-                        start_reg + reg_offset,
-                    );
+            let rowid_reg = if index.has_rowid {
+                program.emit_insn(Insn::RowId {
+                    cursor_id: main_table_cursor_id,
+                    dest: start_reg + num_regs - 1,
                 });
-            program.emit_insn(Insn::RowId {
-                cursor_id: main_table_cursor_id,
-                dest: start_reg + num_regs - 1,
-            });
+                start_reg + num_regs - 1
+            } else {
+                key_reg
+            };
+            // A partial index has no entry for rows that do not satisfy its WHERE clause
+            let skip_index_label = program.allocate_label();
+            if let Some(btree_table) = table_reference.btree() {
+                emit_partial_index_check(
+                    program,
+                    &t_ctx.resolver,
+                    &index,
+                    &btree_table,
+                    |program, pos| {
+                        let reg = program.alloc_register();
+                        emit_table_column(
+                            program,
+                            &t_ctx.resolver,
+                            main_table_cursor_id,
+                            pos,
+                            reg,
+                        )?;
+                        Ok(reg)
+                    },
+                    rowid_reg,
+                    skip_index_label,
+                )?;
+            }
+            // Emit columns that are part of the index, evaluating indexed expressions
+            for (reg_offset, column_index) in index.columns.iter().enumerate() {
+                if let (Some(expr), Some(btree_table)) =
+                    (&column_index.expr, table_reference.btree())
+                {
+                    emit_index_expr(
+                        program,
+                        &t_ctx.resolver,
+                        &btree_table,
+                        expr,
+                        |program, pos| {
+                            let reg = program.alloc_register();
+                            emit_table_column(
+                                program,
+                                &t_ctx.resolver,
+                                main_table_cursor_id,
+                                pos,
+                                reg,
+                            )?;
+                            Ok(reg)
+                        },
+                        rowid_reg,
+                        start_reg + reg_offset,
+                    )?;
+                    continue;
+                }
+                emit_table_column(
+                    program,
+                    &t_ctx.resolver,
+                    main_table_cursor_id,
+                    column_index.pos_in_table,
+                    start_reg + reg_offset,
+                )?;
+            }
             program.emit_insn(Insn::IdxDelete {
                 start_reg,
                 num_regs,
                 cursor_id: index_cursor_id,
                 raise_error_if_no_matching_entry: true,
             });
+            program.preassign_label_to_next_insn(skip_index_label);
         }
 
         // Emit update in the CDC table if necessary (before DELETE updated the table)
         if let Some(cdc_cursor_id) = t_ctx.cdc_cursor_id {
             let rowid_reg = program.alloc_register();
-            program.emit_insn(Insn::RowId {
-                cursor_id: main_table_cursor_id,
-                dest: rowid_reg,
-            });
+            program.emit_rowid_or_null(main_table_cursor_id, rowid_reg);
             let cdc_has_before = program.capture_data_changes_mode().has_before();
             let before_record_reg = if cdc_has_before {
                 Some(emit_cdc_full_record(
@@ -619,17 +889,20 @@ fn emit_delete_insns(
         if !result_columns.is_empty() {
             // Get rowid for RETURNING
             let rowid_reg = program.alloc_register();
-            program.emit_insn(Insn::RowId {
-                cursor_id: main_table_cursor_id,
-                dest: rowid_reg,
-            });
+            program.emit_rowid_or_null(main_table_cursor_id, rowid_reg);
 
             // Allocate registers for column values
             let columns_start_reg = program.alloc_registers(table_reference.columns().len());
 
             // Read all column values from the row to be deleted
             for (i, _column) in table_reference.columns().iter().enumerate() {
-                program.emit_column_or_rowid(main_table_cursor_id, i, columns_start_reg + i);
+                emit_table_column(
+                    program,
+                    &t_ctx.resolver,
+                    main_table_cursor_id,
+                    i,
+                    columns_start_reg + i,
+                )?;
             }
 
             // Emit RETURNING results using the values we just read
@@ -642,10 +915,14 @@ fn emit_delete_insns(
             emit_returning_results(program, result_columns, &value_registers)?;
         }
 
-        program.emit_insn(Insn::Delete {
-            cursor_id: main_table_cursor_id,
-            table_name: table_reference.table.get_name().to_string(),
-        });
+        // When the loop iterates over the primary key index of a WITHOUT ROWID table, the row
+        // is deleted through the iteration cursor, as both cursors point into the same b-tree.
+        if !iteration_index.is_some_and(|index| is_table_btree(index)) {
+            program.emit_insn(Insn::Delete {
+                cursor_id: main_table_cursor_id,
+                table_name: table_reference.table.get_name().to_string(),
+            });
+        }
 
         if let Some(index) = iteration_index {
             let iteration_index_cursor = program.resolve_cursor_id(&CursorKey::index(
@@ -657,6 +934,37 @@ fn emit_delete_insns(
                 table_name: index.name.clone(),
             });
         }
+
+        if let (Some(btree), Some(old_row)) = (fk_table, old_row.as_ref()) {
+            emit_fk_actions(
+                program,
+                t_ctx.resolver.schema,
+                table_reference.database_id,
+                t_ctx.resolver.symbol_table,
+                connection,
+                btree,
+                old_row,
+                None,
+                &[],
+                skip_row_label,
+            )?;
+        }
+
+        if !after_triggers.is_empty() {
+            fire_triggers(
+                program,
+                t_ctx.resolver.schema,
+                t_ctx.resolver.symbol_table,
+                connection,
+                &after_triggers,
+                &TriggerRows {
+                    columns: table_reference.columns(),
+                    old: old_row.as_ref(),
+                    new: None,
+                },
+                skip_row_label,
+            )?;
+        }
     }
     if let Some(limit_ctx) = t_ctx.limit_ctx {
         program.emit_insn(Insn::DecrJumpZero {
@@ -664,6 +972,7 @@ fn emit_delete_insns(
             target_pc: t_ctx.label_main_loop_end.unwrap(),
         })
     }
+    program.preassign_label_to_next_insn(skip_row_label);
 
     Ok(())
 }
@@ -674,6 +983,7 @@ fn emit_program_for_update(
     mut plan: UpdatePlan,
     schema: &Schema,
     syms: &SymbolTable,
+    connection: &Arc<Connection>,
     after: impl FnOnce(&mut ProgramBuilder),
 ) -> Result<()> {
     let mut t_ctx = TranslateCtx::new(
@@ -702,17 +1012,10 @@ fn emit_program_for_update(
     }
 
     let ephemeral_plan = plan.ephemeral_plan.take();
-    let temp_cursor_id = ephemeral_plan.as_ref().map(|plan| {
-        let QueryDestination::EphemeralTable { cursor_id, .. } = &plan.query_destination else {
-            unreachable!()
-        };
-        *cursor_id
-    });
+    let temp_cursor_id = ephemeral_plan
+        .as_ref()
+        .map(|plan| open_ephemeral_plan_cursor(program, plan));
     if let Some(ephemeral_plan) = ephemeral_plan {
-        program.emit_insn(Insn::OpenEphemeral {
-            cursor_id: temp_cursor_id.unwrap(),
-            is_table: true,
-        });
         program.incr_nesting();
         emit_program_for_select(program, ephemeral_plan, schema, syms)?;
         program.decr_nesting();
@@ -746,7 +1049,7 @@ fn emit_program_for_update(
             program.emit_insn(Insn::OpenWrite {
                 cursor_id: cursor,
                 root_page: RegisterOrLiteral::Literal(index.root_page),
-                db: 0,
+                db: plan.table_references.joined_tables()[0].database_id,
             });
             cursor
         };
@@ -765,7 +1068,14 @@ fn emit_program_for_update(
     )?;
 
     // Emit update instructions
-    emit_update_insns(&plan, &t_ctx, program, index_cursors, temp_cursor_id)?;
+    emit_update_insns(
+        &plan,
+        &t_ctx,
+        program,
+        index_cursors,
+        temp_cursor_id,
+        connection,
+    )?;
 
     // Close the main loop
     close_loop(
@@ -792,6 +1102,7 @@ fn emit_update_insns(
     program: &mut ProgramBuilder,
     index_cursors: Vec<(usize, usize)>,
     temp_cursor_id: Option<CursorID>,
+    connection: &Arc<Connection>,
 ) -> crate::Result<()> {
     let table_ref = plan.table_references.joined_tables().first().unwrap();
     let loop_labels = t_ctx.labels_main_loop.first().unwrap();
@@ -831,10 +1142,24 @@ fn emit_update_insns(
                 1 // rowid reg
             },
     );
-    program.emit_insn(Insn::RowId {
-        cursor_id: temp_cursor_id.unwrap_or(cursor_id),
-        dest: beg,
-    });
+    let btree = table_ref.btree();
+    let without_rowid = btree.as_ref().is_some_and(|btree| !btree.has_rowid);
+    // The rows of a WITHOUT ROWID table are identified by their primary key, and the
+    // rowid register holds a NULL.
+    let row_key = if without_rowid {
+        program.emit_null(beg, None);
+        RowKey::load(program, btree.as_deref(), cursor_id, temp_cursor_id)
+    } else {
+        program.emit_insn(Insn::RowId {
+            cursor_id: temp_cursor_id.unwrap_or(cursor_id),
+            dest: beg,
+        });
+        RowKey {
+            start_reg: beg,
+            num_regs: 1,
+            is_rowid: true,
+        }
+    };
 
     // Check if rowid was provided (through INTEGER PRIMARY KEY as a rowid alias)
     let rowid_alias_index = table_ref.columns().iter().position(|c| c.is_rowid_alias);
@@ -852,19 +1177,60 @@ fn emit_update_insns(
         None
     };
 
-    let check_rowid_not_exists_label = if has_user_provided_rowid {
+    let (before_triggers, after_triggers) = match table_ref.btree() {
+        Some(btree_table) => {
+            let changed_columns = plan
+                .set_clauses
+                .iter()
+                .filter_map(|(idx, _)| table_ref.columns()[*idx].name.as_deref())
+                .collect::<Vec<_>>();
+            (
+                get_relevant_triggers(
+                    program,
+                    t_ctx.resolver.schema,
+                    &btree_table.name,
+                    ast::TriggerTime::Before,
+                    &ast::TriggerEvent::Update,
+                    &changed_columns,
+                ),
+                get_relevant_triggers(
+                    program,
+                    t_ctx.resolver.schema,
+                    &btree_table.name,
+                    ast::TriggerTime::After,
+                    &ast::TriggerEvent::Update,
+                    &changed_columns,
+                ),
+            )
+        }
+        None => (vec![], vec![]),
+    };
+    let has_triggers = !before_triggers.is_empty() || !after_triggers.is_empty();
+    // The table whose foreign key constraints are checked, if any
+    let fk_table = btree
+        .as_ref()
+        .filter(|btree| has_foreign_keys(t_ctx.resolver.schema, connection, btree));
+    let changed_columns = plan
+        .set_clauses
+        .iter()
+        .map(|(idx, _)| *idx)
+        .collect::<Vec<_>>();
+
+    let check_rowid_not_exists_label = if has_user_provided_rowid
+        || temp_cursor_id.is_some()
+        || has_triggers
+        || fk_table.is_some()
+    {
         Some(program.allocate_label())
     } else {
         None
     };
 
-    if has_user_provided_rowid {
-        program.emit_insn(Insn::NotExists {
-            cursor: cursor_id,
-            rowid_reg: beg,
-            target_pc: check_rowid_not_exists_label.unwrap(),
-        });
-    } else {
+    // When looping over the collected rowids, the main table cursor must be positioned on the row.
+    // The row may also have been deleted by a trigger fired for a previous row.
+    if has_user_provided_rowid || temp_cursor_id.is_some() {
+        row_key.emit_seek(program, cursor_id, check_rowid_not_exists_label.unwrap());
+    } else if !without_rowid {
         // if no rowid, we're done
         program.emit_insn(Insn::IsNull {
             reg: beg,
@@ -929,7 +1295,8 @@ fn emit_update_insns(
                     target_reg,
                     &t_ctx.resolver,
                 )?;
-                if table_column.notnull {
+                // The primary key columns of a WITHOUT ROWID table are implicitly NOT NULL
+                if table_column.notnull || (without_rowid && table_column.primary_key) {
                     use crate::error::SQLITE_CONSTRAINT_NOTNULL;
                     program.emit_insn(Insn::HaltIfNull {
                         target_reg,
@@ -1012,6 +1379,73 @@ fn emit_update_insns(
         }
     }
 
+    // Generated columns are computed over the new values of the columns they refer to
+    let generated_table = table_ref
+        .btree()
+        .filter(|btree| !is_virtual && btree.has_generated_columns());
+    if let Some(btree) = &generated_table {
+        emit_generated_columns(
+            program,
+            &t_ctx.resolver,
+            btree,
+            start,
+            rowid_set_clause_reg.unwrap_or(beg),
+        )?;
+    }
+
+    let old_row = if has_triggers || fk_table.is_some() {
+        Some(TriggerRow::load_from_cursor(
+            program,
+            &t_ctx.resolver,
+            cursor_id,
+            table_ref.columns(),
+        )?)
+    } else {
+        None
+    };
+    let new_row = TriggerRow::from_registers(
+        rowid_set_clause_reg.unwrap_or(beg),
+        start,
+        table_ref.columns(),
+    );
+    if !before_triggers.is_empty() {
+        fire_triggers(
+            program,
+            t_ctx.resolver.schema,
+            t_ctx.resolver.symbol_table,
+            connection,
+            &before_triggers,
+            &TriggerRows {
+                columns: table_ref.columns(),
+                old: old_row.as_ref(),
+                new: Some(&new_row),
+            },
+            check_rowid_not_exists_label.unwrap(),
+        )?;
+        // The trigger programs may have moved the cursor or deleted the row; in the latter
+        // case the row is not updated. Columns that are not assigned are read again, as
+        // the triggers may have changed them.
+        row_key.emit_seek(program, cursor_id, check_rowid_not_exists_label.unwrap());
+        for (idx, table_column) in table_ref.columns().iter().enumerate() {
+            if table_column.is_rowid_alias || plan.set_clauses.iter().any(|(i, _)| *i == idx) {
+                continue;
+            }
+            program.emit_column_or_rowid(cursor_id, idx, start + idx);
+        }
+        if let Some(btree) = &generated_table {
+            emit_generated_columns(
+                program,
+                &t_ctx.resolver,
+                btree,
+                start,
+                rowid_set_clause_reg.unwrap_or(beg),
+            )?;
+        }
+    }
+    if let Some(btree) = &generated_table {
+        emit_generated_columns_not_null_checks(program, btree, start);
+    }
+
     for (index, (idx_cursor_id, record_reg)) in plan.indexes_to_update.iter().zip(&index_cursors) {
         let num_cols = index.columns.len();
         // allocate scratch registers for the index columns plus rowid
@@ -1031,6 +1465,19 @@ fn emit_update_insns(
 
         // copy each index column from the table's column registers into these scratch regs
         for (i, col) in index.columns.iter().enumerate() {
+            // indexed expressions are evaluated over the new column values
+            if let (Some(expr), Some(btree_table)) = (&col.expr, table_ref.btree()) {
+                emit_index_expr(
+                    program,
+                    &t_ctx.resolver,
+                    &btree_table,
+                    expr,
+                    |_, pos| Ok(start + pos),
+                    rowid_reg,
+                    idx_start_reg + i,
+                )?;
+                continue;
+            }
             let col_in_table = table_ref
                 .columns()
                 .get(col.pos_in_table)
@@ -1046,17 +1493,19 @@ fn emit_update_insns(
                 extra_amount: 0,
             });
         }
-        // last register is the rowid
-        program.emit_insn(Insn::Copy {
-            src_reg: rowid_reg,
-            dst_reg: idx_start_reg + num_cols,
-            extra_amount: 0,
-        });
+        // last register is the rowid, unless the index stores the primary key of a WITHOUT ROWID table
+        if index.has_rowid {
+            program.emit_insn(Insn::Copy {
+                src_reg: rowid_reg,
+                dst_reg: idx_start_reg + num_cols,
+                extra_amount: 0,
+            });
+        }
 
         // this record will be inserted into the index later
         program.emit_insn(Insn::MakeRecord {
             start_reg: idx_start_reg,
-            count: num_cols + 1,
+            count: num_cols + index.has_rowid as usize,
             dest_reg: *record_reg,
             index_name: Some(index.name.clone()),
             affinity_str: None,
@@ -1068,14 +1517,26 @@ fn emit_update_insns(
 
         // check if the record already exists in the index for unique indexes and abort if so
         let constraint_check = program.allocate_label();
+        // the new row is only added to a partial index if it satisfies the WHERE clause
+        if let Some(btree_table) = table_ref.btree() {
+            emit_partial_index_check(
+                program,
+                &t_ctx.resolver,
+                index,
+                &btree_table,
+                |_, pos| Ok(start + pos),
+                rowid_reg,
+                constraint_check,
+            )?;
+        }
         program.emit_insn(Insn::NoConflict {
             cursor_id: *idx_cursor_id,
             target_pc: constraint_check,
             record_reg: idx_start_reg,
-            num_regs: num_cols,
+            num_regs: index.key_columns().len(),
         });
 
-        let column_names = index.columns.iter().enumerate().fold(
+        let column_names = index.key_columns().iter().enumerate().fold(
             String::with_capacity(50),
             |mut accum, (idx, col)| {
                 if idx > 0 {
@@ -1089,21 +1550,48 @@ fn emit_update_insns(
             },
         );
 
-        let idx_rowid_reg = program.alloc_register();
-        program.emit_insn(Insn::IdxRowId {
-            cursor_id: *idx_cursor_id,
-            dest: idx_rowid_reg,
-        });
-
         // Skip over the UNIQUE constraint failure if the existing row is the one that we are currently changing
-        let original_rowid_reg = beg;
-        program.emit_insn(Insn::Eq {
-            lhs: original_rowid_reg,
-            rhs: idx_rowid_reg,
-            target_pc: constraint_check,
-            flags: CmpInsFlags::default(), // TODO: not sure what type of comparison flag is needed
-            collation: program.curr_collation(),
-        });
+        if row_key.is_rowid {
+            let idx_rowid_reg = program.alloc_register();
+            program.emit_insn(Insn::IdxRowId {
+                cursor_id: *idx_cursor_id,
+                dest: idx_rowid_reg,
+            });
+
+            let original_rowid_reg = beg;
+            program.emit_insn(Insn::Eq {
+                lhs: original_rowid_reg,
+                rhs: idx_rowid_reg,
+                target_pc: constraint_check,
+                flags: CmpInsFlags::default(), // TODO: not sure what type of comparison flag is needed
+                collation: program.curr_collation(),
+            });
+        } else {
+            // The existing entry holds the primary key of the row it belongs to
+            let conflict_label = program.allocate_label();
+            let pk_positions = btree
+                .as_ref()
+                .expect("WITHOUT ROWID table must be a btree table")
+                .primary_key_column_positions();
+            let idx_pk_reg = program.alloc_register();
+            for (i, pos_in_table) in pk_positions.iter().enumerate() {
+                let pos_in_index = index
+                    .column_table_pos_to_index_pos(*pos_in_table)
+                    .expect("indexes of a WITHOUT ROWID table contain its primary key");
+                program.emit_column_or_rowid(*idx_cursor_id, pos_in_index, idx_pk_reg);
+                program.emit_insn(Insn::Ne {
+                    lhs: row_key.start_reg + i,
+                    rhs: idx_pk_reg,
+                    target_pc: conflict_label,
+                    flags: CmpInsFlags::default(),
+                    collation: program.curr_collation(),
+                });
+            }
+            program.emit_insn(Insn::Goto {
+                target_pc: constraint_check,
+            });
+            program.preassign_label_to_next_insn(conflict_label);
+        }
 
         program.emit_insn(Insn::Halt {
             err_code: SQLITE_CONSTRAINT_PRIMARYKEY, // TODO: distinct between primary key and unique index for error code
@@ -1159,53 +1647,167 @@ fn emit_update_insns(
             program.preassign_label_to_next_insn(record_label);
         }
 
+        // Changing the primary key of a WITHOUT ROWID table must not collide with another row.
+        let primary_key_changed = without_rowid
+            && plan
+                .set_clauses
+                .iter()
+                .any(|(idx, _)| table_ref.columns()[*idx].primary_key);
+        if primary_key_changed {
+            let record_label = program.allocate_label();
+            let conflict_label = program.allocate_label();
+            let pk_positions = btree_table.primary_key_column_positions();
+            let new_key_reg = program.alloc_registers(pk_positions.len());
+            for (i, pos_in_table) in pk_positions.iter().enumerate() {
+                program.emit_insn(Insn::Copy {
+                    src_reg: start + pos_in_table,
+                    dst_reg: new_key_reg + i,
+                    extra_amount: 0,
+                });
+            }
+            for i in 0..pk_positions.len() {
+                program.emit_insn(Insn::Ne {
+                    lhs: new_key_reg + i,
+                    rhs: row_key.start_reg + i,
+                    target_pc: conflict_label,
+                    flags: CmpInsFlags::default(),
+                    collation: program.curr_collation(),
+                });
+            }
+            program.emit_insn(Insn::Goto {
+                target_pc: record_label,
+            });
+            program.preassign_label_to_next_insn(conflict_label);
+            program.emit_insn(Insn::NoConflict {
+                cursor_id,
+                target_pc: record_label,
+                record_reg: new_key_reg,
+                num_regs: pk_positions.len(),
+            });
+            program.emit_insn(Insn::Halt {
+                err_code: SQLITE_CONSTRAINT_PRIMARYKEY,
+                description: pk_positions
+                    .iter()
+                    .map(|pos| {
+                        format!(
+                            "{}.{}",
+                            table_ref.table.get_name(),
+                            table_ref.columns()[*pos].name.as_deref().unwrap_or("")
+                        )
+                    })
+                    .collect::<Vec<_>>()
+                    .join(", "),
+            });
+            program.preassign_label_to_next_insn(record_label);
+        }
+
+        if let (Some(btree), Some(old_row)) = (fk_table, old_row.as_ref()) {
+            emit_fk_checks(
+                program,
+                t_ctx.resolver.schema,
+                table_ref.database_id,
+                connection,
+                btree,
+                Some(old_row),
+                Some(&new_row),
+                &changed_columns,
+            )?;
+        }
+
         let record_reg = program.alloc_register();
 
-        let affinity_str = table_ref
-            .columns()
+        // The records of a WITHOUT ROWID table hold the primary key columns first, and
+        // VIRTUAL generated columns are not stored at all
+        let record_column_positions = btree_table.record_column_positions();
+        let record_start_reg = if !btree_table.stores_columns_in_table_order() {
+            let record_start_reg = program.alloc_registers(record_column_positions.len());
+            for (i, pos_in_table) in record_column_positions.iter().enumerate() {
+                program.emit_insn(Insn::Copy {
+                    src_reg: start + pos_in_table,
+                    dst_reg: record_start_reg + i,
+                    extra_amount: 0,
+                });
+            }
+            record_start_reg
+        } else {
+            start
+        };
+
+        let affinity_str = record_column_positions
             .iter()
-            .map(|col| col.affinity().aff_mask())
+            .map(|pos| table_ref.columns()[*pos].affinity().aff_mask())
             .collect::<String>();
 
         program.emit_insn(Insn::MakeRecord {
-            start_reg: start,
-            count: table_ref.columns().len(),
+            start_reg: record_start_reg,
+            count: record_column_positions.len(),
             dest_reg: record_reg,
             index_name: None,
             affinity_str: Some(affinity_str),
         });
 
-        if has_user_provided_rowid {
-            program.emit_insn(Insn::NotExists {
-                cursor: cursor_id,
-                rowid_reg: beg,
-                target_pc: check_rowid_not_exists_label.unwrap(),
-            });
+        if has_user_provided_rowid || primary_key_changed {
+            row_key.emit_seek(program, cursor_id, check_rowid_not_exists_label.unwrap());
         }
 
         // For each index -> insert
         for (index, (idx_cursor_id, record_reg)) in plan.indexes_to_update.iter().zip(index_cursors)
         {
-            let num_regs = index.columns.len() + 1;
+            let num_regs = index.columns.len() + index.has_rowid as usize;
             let start_reg = program.alloc_registers(num_regs);
 
-            // Delete existing index key
-            index
-                .columns
-                .iter()
-                .enumerate()
-                .for_each(|(reg_offset, column_index)| {
-                    program.emit_column_or_rowid(
-                        cursor_id,
-                        column_index.pos_in_table,
-                        start_reg + reg_offset,
-                    );
+            // A partial index only has keys for the row images that satisfy its WHERE clause
+            let skip_delete_label = program.allocate_label();
+            let skip_insert_label = program.allocate_label();
+            let old_rowid_reg = if index.has_rowid {
+                program.emit_insn(Insn::RowId {
+                    cursor_id,
+                    dest: start_reg + num_regs - 1,
                 });
+                start_reg + num_regs - 1
+            } else {
+                beg
+            };
+            emit_partial_index_check(
+                program,
+                &t_ctx.resolver,
+                index,
+                &btree_table,
+                |program, pos| {
+                    let reg = program.alloc_register();
+                    emit_table_column(program, &t_ctx.resolver, cursor_id, pos, reg)?;
+                    Ok(reg)
+                },
+                old_rowid_reg,
+                skip_delete_label,
+            )?;
 
-            program.emit_insn(Insn::RowId {
-                cursor_id,
-                dest: start_reg + num_regs - 1,
-            });
+            // Delete existing index key
+            for (reg_offset, column_index) in index.columns.iter().enumerate() {
+                if let Some(expr) = &column_index.expr {
+                    emit_index_expr(
+                        program,
+                        &t_ctx.resolver,
+                        &btree_table,
+                        expr,
+                        |program, pos| {
+                            let reg = program.alloc_register();
+                            emit_table_column(program, &t_ctx.resolver, cursor_id, pos, reg)?;
+                            Ok(reg)
+                        },
+                        old_rowid_reg,
+                        start_reg + reg_offset,
+                    )?;
+                    continue;
+                }
+                emit_table_column(
+                    program,
+                    &t_ctx.resolver,
+                    cursor_id,
+                    column_index.pos_in_table,
+                    start_reg + reg_offset,
+                )?;
+            }
 
             program.emit_insn(Insn::IdxDelete {
                 start_reg,
@@ -1213,15 +1815,26 @@ fn emit_update_insns(
                 cursor_id: idx_cursor_id,
                 raise_error_if_no_matching_entry: true,
             });
+            program.preassign_label_to_next_insn(skip_delete_label);
 
+            emit_partial_index_check(
+                program,
+                &t_ctx.resolver,
+                index,
+                &btree_table,
+                |_, pos| Ok(start + pos),
+                rowid_set_clause_reg.unwrap_or(beg),
+                skip_insert_label,
+            )?;
             // Insert new index key (filled further above with values from set_clauses)
             program.emit_insn(Insn::IdxInsert {
                 cursor_id: idx_cursor_id,
                 record_reg,
                 unpacked_start: Some(start),
-                unpacked_count: Some((index.columns.len() + 1) as u16),
+                unpacked_count: Some((index.columns.len() + index.has_rowid as usize) as u16),
                 flags: IdxInsertFlags::new().nchange(true),
             });
+            program.preassign_label_to_next_insn(skip_insert_label);
         }
 
         // create alias for CDC rowid after the change (will differ from cdc_rowid_before_reg only in case of UPDATE with change in rowid alias)
@@ -1255,29 +1868,56 @@ fn emit_update_insns(
             None
         };
 
-        // If we are updating the rowid, we cannot rely on overwrite on the
-        // Insert instruction to update the cell. We need to first delete the current cell
-        // and later insert the updated record
-        if has_user_provided_rowid {
+        if without_rowid {
+            // The key of a WITHOUT ROWID table's record is the whole record, so the old
+            // record is always deleted before the new one is inserted. When the loop iterates
+            // over the primary key index, both are done through the iteration cursor, as it
+            // points into the table b-tree as well.
+            let write_cursor_id = match &index {
+                Some((index, index_cursor_id))
+                    if index.is_without_rowid_primary_key(&btree_table) =>
+                {
+                    *index_cursor_id
+                }
+                _ => cursor_id,
+            };
             program.emit_insn(Insn::Delete {
-                cursor_id,
+                cursor_id: write_cursor_id,
                 table_name: table_ref.table.get_name().to_string(),
             });
-        }
+            program.emit_insn(Insn::IdxInsert {
+                cursor_id: write_cursor_id,
+                record_reg,
+                unpacked_start: Some(record_start_reg),
+                unpacked_count: Some(table_ref.columns().len() as u16),
+                // The row was already counted as changed by the Delete above
+                flags: IdxInsertFlags::new(),
+            });
+        } else {
+            // If we are updating the rowid, we cannot rely on overwrite on the
+            // Insert instruction to update the cell. We need to first delete the current cell
+            // and later insert the updated record
+            if has_user_provided_rowid {
+                program.emit_insn(Insn::Delete {
+                    cursor_id,
+                    table_name: table_ref.table.get_name().to_string(),
+                });
+            }
 
-        program.emit_insn(Insn::Insert {
-            cursor: cursor_id,
-            key_reg: rowid_set_clause_reg.unwrap_or(beg),
-            record_reg,
-            flag: if has_user_provided_rowid {
-                // The previous Insn::NotExists and Insn::Delete seek to the old rowid,
-                // so to insert a new user-provided rowid, we need to seek to the correct place.
-                InsertFlags::new().require_seek().update_rowid_change()
-            } else {
-                InsertFlags::new()
-            },
-            table_name: table_ref.identifier.clone(),
-        });
+            program.emit_insn(Insn::Insert {
+                cursor: cursor_id,
+                key_reg: rowid_set_clause_reg.unwrap_or(beg),
+                record_reg,
+                flag: if has_user_provided_rowid {
+                    // The previous Insn::NotExists and Insn::Delete seek to the old rowid,
+                    // so to insert a new user-provided rowid, we need to seek to the correct place.
+                    InsertFlags::new().require_seek().update_rowid_change()
+                } else {
+                    InsertFlags::new()
+                },
+                table_name: table_ref.identifier.clone(),
+            });
+        }
 
         // Emit RETURNING results if specified
         if let Some(returning_columns) = &plan.returning {
@@ -1360,6 +2000,37 @@ fn emit_update_insns(
                 )?;
             }
         }
+
+        if let (Some(btree), Some(old_row)) = (fk_table, old_row.as_ref()) {
+            emit_fk_actions(
+                program,
+                t_ctx.resolver.schema,
+                table_ref.database_id,
+                t_ctx.resolver.symbol_table,
+                connection,
+                btree,
+                old_row,
+                Some(&new_row),
+                &changed_columns,
+                check_rowid_not_exists_label.unwrap(),
+            )?;
+        }
+
+        if !after_triggers.is_empty() {
+            fire_triggers(
+                program,
+                t_ctx.resolver.schema,
+                t_ctx.resolver.symbol_table,
+                connection,
+                &after_triggers,
+                &TriggerRows {
+                    columns: table_ref.columns(),
+                    old: old_row.as_ref(),
+                    new: Some(&new_row),
+                },
+                check_rowid_not_exists_label.unwrap(),
+            )?;
+        }
     } else if table_ref.virtual_table().is_some() {
         let arg_count = table_ref.columns().len() + 2;
         program.emit_insn(Insn::VUpdate {
@@ -1,14 +1,19 @@
+use std::sync::Arc;
+
 use tracing::{instrument, Level};
-use turso_parser::ast::{self, As, Expr, UnaryOperator};
+use turso_parser::ast::{self, As, Expr, SubqueryType, UnaryOperator};
 
 use super::emitter::Resolver;
+use super::generated::emit_column_affinity;
+use super::index::bind_index_expr;
 use super::optimizer::Optimizable;
 use super::plan::TableReferences;
+use super::subquery::emit_non_from_clause_subquery;
 #[cfg(feature = "json")]
 use crate::function::JsonFunc;
-use crate::function::{Func, FuncCtx, MathFuncArity, ScalarFunc, VectorFunc};
+use crate::function::{Func, FuncCtx, MathFuncArity, ScalarFunc, VectorFunc, WindowFunc};
 use crate::functions::datetime;
-use crate::schema::{affinity, Affinity, Table, Type};
+use crate::schema::{affinity, Affinity, Index, Table, Type};
 use crate::util::{exprs_are_equivalent, parse_numeric_literal};
 use crate::vdbe::builder::CursorKey;
 use crate::vdbe::{
@@ -274,6 +279,119 @@ fn translate_in_list(
     Ok(())
 }
 
+/// Evaluate `lhs [NOT] IN (SELECT ...)` into `target_register`, where the rows of the subquery
+/// have already been stored in the ephemeral index `cursor_id`.
+///
+/// Follows SQL NULL semantics: the result is NULL if `lhs` is NULL and the subquery returned any
+/// rows, or if `lhs` was not found and the subquery returned a NULL. NULLs sort first in the
+/// index, so the latter only needs a look at its first entry.
+#[allow(clippy::too_many_arguments)]
+fn translate_in_subquery(
+    program: &mut ProgramBuilder,
+    referenced_tables: Option<&TableReferences>,
+    lhs: &ast::Expr,
+    not_in: bool,
+    cursor_id: usize,
+    affinity: char,
+    target_register: usize,
+    resolver: &Resolver,
+) -> Result<()> {
+    let lhs_reg = program.alloc_register();
+    translate_expr(program, referenced_tables, lhs, lhs_reg, resolver)?;
+
+    let label_found = program.allocate_label();
+    let label_not_found = program.allocate_label();
+    let label_null = program.allocate_label();
+    let label_lhs_null = program.allocate_label();
+    let label_done = program.allocate_label();
+
+    program.emit_insn(Insn::IsNull {
+        reg: lhs_reg,
+        target_pc: label_lhs_null,
+    });
+    let record_reg = program.alloc_register();
+    program.emit_insn(Insn::MakeRecord {
+        start_reg: lhs_reg,
+        count: 1,
+        dest_reg: record_reg,
+        index_name: None,
+        affinity_str: Some(affinity.to_string()),
+    });
+    program.emit_insn(Insn::Found {
+        cursor_id,
+        target_pc: label_found,
+        record_reg,
+        num_regs: 0,
+    });
+    program.emit_insn(Insn::Rewind {
+        cursor_id,
+        pc_if_empty: label_not_found,
+    });
+    let first_value_reg = program.alloc_register();
+    program.emit_insn(Insn::Column {
+        cursor_id,
+        column: 0,
+        dest: first_value_reg,
+        default: None,
+    });
+    program.emit_insn(Insn::NotNull {
+        reg: first_value_reg,
+        target_pc: label_not_found,
+    });
+    program.emit_insn(Insn::Goto {
+        target_pc: label_null,
+    });
+
+    program.preassign_label_to_next_insn(label_lhs_null);
+    program.emit_insn(Insn::Rewind {
+        cursor_id,
+        pc_if_empty: label_not_found,
+    });
+    program.preassign_label_to_next_insn(label_null);
+    program.emit_insn(Insn::Null {
+        dest: target_register,
+        dest_end: None,
+    });
+    program.emit_insn(Insn::Goto {
+        target_pc: label_done,
+    });
+
+    program.preassign_label_to_next_insn(label_found);
+    program.emit_insn(Insn::Integer {
+        value: !not_in as i64,
+        dest: target_register,
+    });
+    program.emit_insn(Insn::Goto {
+        target_pc: label_done,
+    });
+    program.preassign_label_to_next_insn(label_not_found);
+    program.emit_insn(Insn::Integer {
+        value: not_in as i64,
+        dest: target_register,
+    });
+    program.preassign_label_to_next_insn(label_done);
+    Ok(())
+}
+
+/// Returns how to read the columns of a table of an enclosing query, i.e. a table that a
+/// correlated subquery refers to: from the table cursor if the enclosing query opened one,
+/// and otherwise from the covering index it reads the table with.
+fn outer_query_ref_index(
+    program: &ProgramBuilder,
+    table_ref_id: ast::TableInternalId,
+) -> (Option<Arc<Index>>, bool) {
+    if program
+        .resolve_cursor_id_safe(&CursorKey::table(table_ref_id))
+        .is_some()
+    {
+        return (None, false);
+    }
+    match program.resolve_index_for_table(table_ref_id) {
+        Some(index) => (Some(index), true),
+        None => (None, false),
+    }
+}
+
 #[instrument(skip(program, referenced_tables, expr, resolver), level = Level::DEBUG)]
 pub fn translate_condition_expr(
     program: &mut ProgramBuilder,
@@ -283,9 +401,6 @@ pub fn translate_condition_expr(
     resolver: &Resolver,
 ) -> Result<()> {
     match expr {
-        ast::Expr::Register(_) => {
-            crate::bail_parse_error!("Register in WHERE clause is currently unused. Consider removing Resolver::expr_to_reg_cache and using Expr::Register instead");
-        }
         ast::Expr::Collate(_, _) => {
             crate::bail_parse_error!("Collate in WHERE clause is not supported");
         }
@@ -295,13 +410,13 @@ pub fn translate_condition_expr(
             );
         }
         ast::Expr::Exists(_) => {
-            crate::bail_parse_error!("EXISTS in WHERE clause is not supported");
+            crate::bail_parse_error!("EXISTS is not supported in this context");
         }
         ast::Expr::Subquery(_) => {
-            crate::bail_parse_error!("Subquery in WHERE clause is not supported");
+            crate::bail_parse_error!("Subquery is not supported in this context");
         }
         ast::Expr::InSelect { .. } => {
-            crate::bail_parse_error!("IN (...subquery) in WHERE clause is not supported");
+            crate::bail_parse_error!("IN (...subquery) is not supported in this context");
         }
         ast::Expr::InTable { .. } => {
             crate::bail_parse_error!("Table expression in WHERE clause is not supported");
@@ -309,9 +424,6 @@ pub fn translate_condition_expr(
         ast::Expr::FunctionCallStar { .. } => {
             crate::bail_parse_error!("FunctionCallStar in WHERE clause is not supported");
         }
-        ast::Expr::Raise(_, _) => {
-            crate::bail_parse_error!("RAISE in WHERE clause is not supported");
-        }
         ast::Expr::Between { .. } => {
             crate::bail_parse_error!("BETWEEN expression should have been rewritten in optmizer")
         }
@@ -392,7 +504,10 @@ pub fn translate_condition_expr(
         | ast::Expr::FunctionCall { .. }
         | ast::Expr::Column { .. }
         | ast::Expr::RowId { .. }
-        | ast::Expr::Case { .. } => {
+        | ast::Expr::Case { .. }
+        | ast::Expr::Register(_)
+        | ast::Expr::SubqueryResult { .. }
+        | ast::Expr::Raise(..) => {
             let reg = program.alloc_register();
             translate_expr(program, Some(referenced_tables), expr, reg, resolver)?;
             emit_cond_jump(program, condition_metadata, reg);
@@ -680,15 +795,22 @@ pub fn translate_expr(
         ast::Expr::DoublyQualified(_, _, _) => {
             crate::bail_parse_error!("DoublyQualified should have been rewritten in optimizer")
         }
-        ast::Expr::Exists(_) => crate::bail_parse_error!("EXISTS in WHERE clause is not supported"),
+        ast::Expr::Exists(_) => crate::bail_parse_error!("EXISTS is not supported in this context"),
         ast::Expr::FunctionCall {
             name,
             distinctness: _,
             args,
-            filter_over: _,
+            filter_over,
             order_by: _,
         } => {
             let args_count = args.len();
+            // Window functions are computed by the window operator and read from the
+            // expression-to-register cache, so reaching this point means they are misused.
+            if filter_over.over_clause.is_some()
+                || WindowFunc::resolve_function(name.as_str(), args_count)?.is_some()
+            {
+                crate::bail_parse_error!("misuse of window function {}()", name.as_str());
+            }
             let func_type = resolver.resolve_function(name.as_str(), args_count);
 
             if func_type.is_none() {
@@ -1806,7 +1928,10 @@ pub fn translate_expr(
                 Func::AlterTable(_) => unreachable!(),
             }
         }
-        ast::Expr::FunctionCallStar { .. } => {
+        ast::Expr::FunctionCallStar { name, filter_over } => {
+            if filter_over.over_clause.is_some() {
+                crate::bail_parse_error!("misuse of window function {}()", name.as_str());
+            }
             crate::bail_parse_error!("FunctionCallStar in WHERE clause is not supported")
         }
         ast::Expr::Id(id) => {
@@ -1829,11 +1954,11 @@ pub fn translate_expr(
                     .find_joined_table_by_internal_id(*table_ref_id)
                 {
                     (
-                        table_reference.op.index(),
+                        table_reference.op.index().cloned(),
                         table_reference.utilizes_covering_index(),
                     )
                 } else {
-                    (None, false)
+                    outer_query_ref_index(program, *table_ref_id)
                 }
             };
 
@@ -1852,13 +1977,47 @@ pub fn translate_expr(
             // the table and read the column from the cursor.
             // If we have a covering index, we don't have an open table cursor so we read from the index cursor.
             match &table {
-                Table::BTree(_) => {
+                Table::BTree(btree) => {
+                    // VIRTUAL generated columns are computed from the columns they refer to,
+                    // unless they are read from an index that holds them
+                    let in_covering_index = use_covering_index
+                        && index.as_ref().is_some_and(|index| {
+                            index.column_table_pos_to_index_pos(*column).is_some()
+                        });
+                    if table_column.is_virtual_generated() && !in_covering_index {
+                        let generated = table_column
+                            .generated
+                            .as_ref()
+                            .expect("column is generated");
+                        let bound =
+                            bind_index_expr(&generated.expr, btree, |column| match column {
+                                Some(pos) => Expr::Column {
+                                    database: None,
+                                    table: *table_ref_id,
+                                    column: pos,
+                                    is_rowid_alias: btree.columns[pos].is_rowid_alias,
+                                },
+                                None => Expr::RowId {
+                                    database: None,
+                                    table: *table_ref_id,
+                                },
+                            })?;
+                        translate_expr(
+                            program,
+                            referenced_tables,
+                            &bound,
+                            target_register,
+                            resolver,
+                        )?;
+                        emit_column_affinity(program, table_column, target_register);
+                        return Ok(target_register);
+                    }
                     let table_cursor_id = if use_covering_index {
                         None
                     } else {
                         Some(program.resolve_cursor_id(&CursorKey::table(*table_ref_id)))
                     };
-                    let index_cursor_id = index.map(|index| {
+                    let index_cursor_id = index.as_ref().map(|index| {
                         program.resolve_cursor_id(&CursorKey::index(*table_ref_id, index.clone()))
                     });
                     if *is_rowid_alias {
@@ -1938,11 +2097,11 @@ pub fn translate_expr(
                     .find_joined_table_by_internal_id(*table_ref_id)
                 {
                     (
-                        table_reference.op.index(),
+                        table_reference.op.index().cloned(),
                         table_reference.utilizes_covering_index(),
                     )
                 } else {
-                    (None, false)
+                    outer_query_ref_index(program, *table_ref_id)
                 }
             };
 
@@ -2020,7 +2179,7 @@ pub fn translate_expr(
             Ok(result_reg)
         }
         ast::Expr::InSelect { .. } => {
-            crate::bail_parse_error!("IN (...subquery) in WHERE clause is not supported")
+            crate::bail_parse_error!("IN (...subquery) is not supported in this context")
         }
         ast::Expr::InTable { .. } => {
             crate::bail_parse_error!("Table expression in WHERE clause is not supported")
@@ -2106,9 +2265,88 @@ pub fn translate_expr(
         ast::Expr::Qualified(_, _) => {
             unreachable!("Qualified should be resolved to a Column before translation")
         }
-        ast::Expr::Raise(_, _) => crate::bail_parse_error!("RAISE is not supported"),
+        ast::Expr::Raise(resolve_type, message) => {
+            let Some(trigger) = program.current_trigger() else {
+                crate::bail_parse_error!("RAISE() may only be used within a trigger-program");
+            };
+            match resolve_type {
+                ast::ResolveType::Ignore => {
+                    let ignore_label = trigger.ignore_label;
+                    program.emit_insn(Insn::Goto {
+                        target_pc: ignore_label,
+                    });
+                }
+                ast::ResolveType::Rollback | ast::ResolveType::Abort | ast::ResolveType::Fail => {
+                    let description = match message.as_deref() {
+                        Some(ast::Expr::Literal(ast::Literal::String(s))) => sanitize_string(s),
+                        Some(ast::Expr::Literal(lit)) => lit.to_string(),
+                        _ => crate::bail_parse_error!("RAISE() message must be a literal"),
+                    };
+                    if *resolve_type == ast::ResolveType::Abort {
+                        program.use_statement_savepoint();
+                    }
+                    program.emit_insn(Insn::Raise {
+                        resolve_type: *resolve_type,
+                        description,
+                    });
+                }
+                ast::ResolveType::Replace => {
+                    crate::bail_parse_error!("REPLACE is not a valid RAISE() action")
+                }
+            }
+            Ok(target_register)
+        }
         ast::Expr::Subquery(_) => {
-            crate::bail_parse_error!("Subquery in WHERE clause is not supported")
+            crate::bail_parse_error!("Subquery is not supported in this context")
+        }
+        ast::Expr::SubqueryResult {
+            subquery_id,
+            lhs,
+            not_in,
+            query_type,
+            ..
+        } => {
+            emit_non_from_clause_subquery(program, *subquery_id, referenced_tables, resolver)?;
+            match query_type {
+                SubqueryType::Exists { result_reg } => {
+                    program.emit_insn(Insn::Copy {
+                        src_reg: *result_reg,
+                        dst_reg: target_register,
+                        extra_amount: 0,
+                    });
+                    Ok(target_register)
+                }
+                SubqueryType::RowValue {
+                    result_reg_start,
+                    num_regs,
+                } => {
+                    program.emit_insn(Insn::Copy {
+                        src_reg: *result_reg_start,
+                        dst_reg: target_register,
+                        extra_amount: num_regs - 1,
+                    });
+                    Ok(target_register)
+                }
+                SubqueryType::In {
+                    cursor_id,
+                    affinity,
+                } => {
+                    let lhs = lhs
+                        .as_ref()
+                        .expect("IN subquery must have a left hand side");
+                    translate_in_subquery(
+                        program,
+                        referenced_tables,
+                        lhs,
+                        *not_in,
+                        *cursor_id,
+                        *affinity,
+                        target_register,
+                        resolver,
+                    )?;
+                    Ok(target_register)
+                }
+            }
         }
         ast::Expr::Unary(op, expr) => match (op, expr.as_ref()) {
             (UnaryOperator::Positive, expr) => {
@@ -3171,6 +3409,11 @@ where
                     walk_expr(lhs, func)?;
                     // TODO: Walk through select statements if needed
                 }
+                ast::Expr::SubqueryResult { lhs, .. } => {
+                    if let Some(lhs) = lhs {
+                        walk_expr(lhs, func)?;
+                    }
+                }
                 ast::Expr::InTable { lhs, args, .. } => {
                     walk_expr(lhs, func)?;
                     for expr in args {
@@ -3348,6 +3591,11 @@ where
             walk_expr_mut(lhs, func)?;
             // TODO: Walk through select statements if needed
         }
+        ast::Expr::SubqueryResult { lhs, .. } => {
+            if let Some(lhs) = lhs {
+                walk_expr_mut(lhs, func)?;
+            }
+        }
         ast::Expr::InTable { lhs, args, .. } => {
             walk_expr_mut(lhs, func)?;
             for expr in args {
@@ -3721,6 +3969,7 @@ pub fn process_returning_clause(
             join_info: None,
             col_used_mask: ColumnUsedMask::default(),
             database_id: 0,
+            indexed: None,
         }],
         vec![],
     );
@@ -0,0 +1,992 @@
+//! Enforcement of FOREIGN KEY constraints, see <https://www.sqlite.org/foreignkeys.html>.
+//!
+//! Like SQLite, the constraints are not checked row by row. Instead, a counter of violations is
+//! maintained with [Insn::FkCounter]: it is incremented when a change leaves a child row without
+//! its parent row, and decremented when a change fixes such a row. Immediate constraints use a
+//! counter of the statement that must be zero once the statement is done, and deferred
+//! constraints a counter of the connection that must be zero once the transaction commits.
+//!
+//! The actions of the constraints (ON DELETE / ON UPDATE CASCADE, SET NULL and SET DEFAULT) are
+//! translated into DELETE and UPDATE statements on the child table that are inlined into the
+//! program, like trigger programs are. An action that fires itself again, as in a table that
+//! refers to itself, runs from a queue instead, see [emit_fk_actions].
+
+use std::sync::Arc;
+
+use turso_parser::ast::{self, Expr, RefAct, TriggerEvent};
+
+use crate::error::SQLITE_CONSTRAINT_FOREIGNKEY;
+use crate::schema::{BTreeTable, ForeignKey, Index, Schema};
+use crate::translate::insert::ephemeral_scratch_table;
+use crate::translate::trigger::{translate_trigger_cmd, TriggerRow};
+use crate::vdbe::builder::{CursorType, ProgramBuilder, TriggerFrame};
+use crate::vdbe::insn::{CmpInsFlags, InsertFlags, Insn};
+use crate::vdbe::BranchOffset;
+use crate::{bail_parse_error, Connection, Result, SymbolTable};
+
+/// A foreign key constraint along with the key of the parent table it refers to.
+struct ResolvedForeignKey {
+    /// The database of the child and parent tables
+    database_id: usize,
+    parent: Arc<BTreeTable>,
+    /// Positions of the child columns in the child table
+    child_positions: Vec<usize>,
+    /// Positions of the referenced columns in the parent table, in the order of the child columns
+    parent_positions: Vec<usize>,
+    /// The unique index of the parent table on the referenced columns, or None if they are the
+    /// rowid of the parent table
+    parent_index: Option<Arc<Index>>,
+}
+
+/// Finds the parent key of a foreign key constraint, failing if the parent table does not exist
+/// or if the referenced columns are not its primary key or the columns of a UNIQUE constraint.
+fn resolve_foreign_key(
+    schema: &Schema,
+    database_id: usize,
+    child: &BTreeTable,
+    foreign_key: &ForeignKey,
+) -> Result<ResolvedForeignKey> {
+    let Some(parent) = schema.get_btree_table(&foreign_key.parent_table) else {
+        let db_name = if database_id == 1 { "temp" } else { "main" };
+        bail_parse_error!("no such table: {db_name}.{}", foreign_key.parent_table);
+    };
+    let mismatch = || {
+        crate::LimboError::ParseError(format!(
+            "foreign key mismatch - \"{}\" referencing \"{}\"",
+            child.name, parent.name
+        ))
+    };
+    let parent_columns = if foreign_key.parent_columns.is_empty() {
+        parent
+            .primary_key_columns
+            .iter()
+            .map(|(name, _)| name.clone())
+            .collect()
+    } else {
+        foreign_key.parent_columns.clone()
+    };
+    if parent_columns.is_empty() || parent_columns.len() != foreign_key.child_columns.len() {
+        return Err(mismatch());
+    }
+    let parent_positions = parent_columns
+        .iter()
+        .map(|name| parent.get_column(name).map(|(pos, _)| pos))
+        .collect::<Option<Vec<_>>>()
+        .ok_or_else(mismatch)?;
+    let child_positions = foreign_key
+        .child_columns
+        .iter()
+        .map(|name| child.get_column(name).map(|(pos, _)| pos))
+        .collect::<Option<Vec<_>>>()
+        .ok_or_else(mismatch)?;
+
+    let refers_to_rowid =
+        parent_positions.len() == 1 && parent.columns[parent_positions[0]].is_rowid_alias;
+    let parent_index = if refers_to_rowid {
+        None
+    } else {
+        let index = schema
+            .get_indices(&parent.name)
+            .iter()
+            .find(|index| {
+                let key_columns = index.key_columns();
+                index.unique
+                    && index.where_clause.is_none()
+                    && key_columns.len() == parent_positions.len()
+                    && key_columns.iter().all(|column| {
+                        column.expr.is_none() && parent_positions.contains(&column.pos_in_table)
+                    })
+            })
+            .cloned()
+            .ok_or_else(mismatch)?;
+        Some(index)
+    };
+    Ok(ResolvedForeignKey {
+        database_id,
+        parent,
+        child_positions,
+        parent_positions,
+        parent_index,
+    })
+}
+
+/// Whether the foreign key constraints are enforced for changes to the rows of `table`, i.e.
+/// they are enabled and the table is the child or the parent table of a constraint.
+pub fn has_foreign_keys(schema: &Schema, connection: &Connection, table: &BTreeTable) -> bool {
+    connection.foreign_keys_enabled()
+        && (!table.foreign_keys.is_empty()
+            || !schema.get_referencing_foreign_keys(&table.name).is_empty())
+}
+
+/// Whether deleting or updating the rows of `table_name` runs actions of foreign key constraints,
+/// which may modify the table, so the rows to change must be collected up front.
+pub fn has_foreign_key_actions(
+    schema: &Schema,
+    connection: &Connection,
+    table_name: &str,
+    event: &TriggerEvent,
+) -> bool {
+    connection.foreign_keys_enabled()
+        && schema
+            .get_referencing_foreign_keys(table_name)
+            .iter()
+            .any(|(_, foreign_key)| {
+                let action = match event {
+                    TriggerEvent::Delete => foreign_key.on_delete,
+                    _ => foreign_key.on_update,
+                };
+                !matches!(action, RefAct::NoAction | RefAct::Restrict)
+            })
+}
+
+/// Emits the checks of the foreign key constraints involving `table` for a row that is about to
+/// be inserted (`old` is None), deleted (`new` is None) or updated, in which case
+/// `changed_columns` are the positions of the columns set by the UPDATE.
+///
+/// This must be emitted before the row is written, and before the indexes of the table are.
+#[allow(clippy::too_many_arguments)]
+pub fn emit_fk_checks(
+    program: &mut ProgramBuilder,
+    schema: &Schema,
+    database_id: usize,
+    connection: &Connection,
+    table: &BTreeTable,
+    old: Option<&TriggerRow>,
+    new: Option<&TriggerRow>,
+    changed_columns: &[usize],
+) -> Result<()> {
+    if !connection.foreign_keys_enabled() {
+        return Ok(());
+    }
+    let is_update = old.is_some() && new.is_some();
+    let is_changed = |positions: &[usize]| {
+        !is_update || positions.iter().any(|pos| changed_columns.contains(pos))
+    };
+
+    // The row as a child row: its parent row must exist
+    for foreign_key in &table.foreign_keys {
+        let resolved = resolve_foreign_key(schema, database_id, table, foreign_key)?;
+        if !is_changed(&resolved.child_positions) {
+            continue;
+        }
+        if let Some(old) = old {
+            emit_parent_check(program, table, foreign_key, &resolved, old, -1);
+        }
+        if let Some(new) = new {
+            emit_parent_check(program, table, foreign_key, &resolved, new, 1);
+        }
+    }
+
+    // The row as a parent row: the rows that refer to it must not be left without a parent row
+    for (child, foreign_key) in schema.get_referencing_foreign_keys(&table.name) {
+        let resolved = resolve_foreign_key(schema, database_id, &child, &foreign_key)?;
+        if !is_changed(&resolved.parent_positions) {
+            continue;
+        }
+        let done_label = program.allocate_label();
+        if let (Some(old), Some(new)) = (old, new) {
+            emit_skip_if_key_unchanged(program, &resolved.parent_positions, old, new, done_label);
+        }
+        let action = if is_update {
+            foreign_key.on_update
+        } else {
+            foreign_key.on_delete
+        };
+        if let Some(old) = old {
+            let restrict = action == RefAct::Restrict;
+            emit_child_scan(
+                program,
+                schema,
+                table,
+                &child,
+                &foreign_key,
+                &resolved,
+                old,
+                1,
+                restrict,
+            );
+        }
+        if let Some(new) = new {
+            emit_child_scan(
+                program,
+                schema,
+                table,
+                &child,
+                &foreign_key,
+                &resolved,
+                new,
+                -1,
+                false,
+            );
+        }
+        program.preassign_label_to_next_insn(done_label);
+    }
+    Ok(())
+}
+
+/// Emits the actions of the foreign key constraints that refer to `table` for a row that was
+/// deleted (`new` is None) or updated. This must be emitted after the row is written.
+///
+/// An action that changes rows firing the same action again, as in a table that refers to
+/// itself, queues the keys of those rows instead of being inlined into itself. The outermost
+/// firing of the action then runs it for the queued keys until the queue is empty, so that the
+/// action cascades to any depth.
+#[allow(clippy::too_many_arguments)]
+pub fn emit_fk_actions(
+    program: &mut ProgramBuilder,
+    schema: &Schema,
+    database_id: usize,
+    syms: &SymbolTable,
+    connection: &Arc<Connection>,
+    table: &BTreeTable,
+    old: &TriggerRow,
+    new: Option<&TriggerRow>,
+    changed_columns: &[usize],
+    ignore_label: BranchOffset,
+) -> Result<()> {
+    if !connection.foreign_keys_enabled() {
+        return Ok(());
+    }
+    for (child, foreign_key) in schema.get_referencing_foreign_keys(&table.name) {
+        let action = if new.is_some() {
+            foreign_key.on_update
+        } else {
+            foreign_key.on_delete
+        };
+        if matches!(action, RefAct::NoAction | RefAct::Restrict) {
+            continue;
+        }
+        let resolved = resolve_foreign_key(schema, database_id, &child, &foreign_key)?;
+        if new.is_some()
+            && !resolved
+                .parent_positions
+                .iter()
+                .any(|pos| changed_columns.contains(pos))
+        {
+            continue;
+        }
+        let id = child
+            .foreign_keys
+            .iter()
+            .position(|fk| Arc::ptr_eq(fk, &foreign_key))
+            .expect("foreign key of the child table");
+        let frame_name = format!("foreign key {id} of {}", child.name);
+
+        let skip_label = program.allocate_label();
+        if let Some(new) = new {
+            emit_skip_if_key_unchanged(program, &resolved.parent_positions, old, new, skip_label);
+        }
+        let key_regs = |row: &TriggerRow| {
+            resolved
+                .parent_positions
+                .iter()
+                .map(|pos| row.column_regs[*pos])
+                .collect::<Vec<_>>()
+        };
+        let old_key = key_regs(old);
+        let new_key = new.map(key_regs);
+
+        if let Some(queue) = program.cascade_queue(&frame_name) {
+            emit_enqueue_key(program, queue, &old_key, new_key.as_deref());
+            program.preassign_label_to_next_insn(skip_label);
+            continue;
+        }
+
+        // The queue holds the old parent key of each queued row, followed by its new one for an
+        // UPDATE. It is emptied by each firing of the action, so it is only opened once.
+        let key_len = old_key.len();
+        let queue_width = if new.is_some() { 2 * key_len } else { key_len };
+        let queue =
+            program.alloc_cursor_id(CursorType::BTreeTable(ephemeral_scratch_table(queue_width)));
+        let opened_label = program.allocate_label();
+        program.emit_insn(Insn::Once {
+            target_pc_when_reentered: opened_label,
+        });
+        program.emit_insn(Insn::OpenEphemeral {
+            cursor_id: queue,
+            is_table: true,
+        });
+        program.preassign_label_to_next_insn(opened_label);
+
+        let command = action_command(&child, action, &resolved, &old_key, new_key.as_deref());
+        emit_action(
+            program,
+            schema,
+            syms,
+            connection,
+            &frame_name,
+            queue,
+            ignore_label,
+            command,
+        )?;
+
+        // Run the action for the queued keys, which may queue more
+        let loop_label = program.allocate_label();
+        program.preassign_label_to_next_insn(loop_label);
+        program.emit_insn(Insn::Rewind {
+            cursor_id: queue,
+            pc_if_empty: skip_label,
+        });
+        let queued_regs = program.alloc_registers(queue_width);
+        for i in 0..queue_width {
+            program.emit_insn(Insn::Column {
+                cursor_id: queue,
+                column: i,
+                dest: queued_regs + i,
+                default: None,
+            });
+        }
+        program.emit_insn(Insn::Delete {
+            cursor_id: queue,
+            table_name: String::new(),
+        });
+        let old_key = (queued_regs..queued_regs + key_len).collect::<Vec<_>>();
+        let new_key =
+            new.map(|_| (queued_regs + key_len..queued_regs + queue_width).collect::<Vec<_>>());
+        let command = action_command(&child, action, &resolved, &old_key, new_key.as_deref());
+        emit_action(
+            program,
+            schema,
+            syms,
+            connection,
+            &frame_name,
+            queue,
+            ignore_label,
+            command,
+        )?;
+        program.emit_insn(Insn::Goto {
+            target_pc: loop_label,
+        });
+
+        program.preassign_label_to_next_insn(skip_label);
+    }
+    Ok(())
+}
+
+/// Builds the statement that runs `action` on the rows of `child` that refer to the parent key
+/// in the `old_key` registers, which is updated to the `new_key` registers for an UPDATE.
+fn action_command(
+    child: &BTreeTable,
+    action: RefAct,
+    resolved: &ResolvedForeignKey,
+    old_key: &[usize],
+    new_key: Option<&[usize]>,
+) -> ast::TriggerCmd {
+    let where_clause = resolved
+        .child_positions
+        .iter()
+        .zip(old_key)
+        .map(|(child_pos, old_reg)| {
+            Expr::Binary(
+                Box::new(child_column_expr(child, *child_pos)),
+                ast::Operator::Equals,
+                Box::new(Expr::Register(*old_reg)),
+            )
+        })
+        .reduce(|lhs, rhs| Expr::Binary(Box::new(lhs), ast::Operator::And, Box::new(rhs)))
+        .expect("foreign key has columns");
+    let tbl_name = ast::Name::Ident(child.name.clone());
+    match (action, new_key) {
+        (RefAct::Cascade, None) => ast::TriggerCmd::Delete {
+            tbl_name,
+            where_clause: Some(Box::new(where_clause)),
+        },
+        (action, new_key) => {
+            let sets = resolved
+                .child_positions
+                .iter()
+                .enumerate()
+                .map(|(i, child_pos)| {
+                    let column = &child.columns[*child_pos];
+                    let expr = match (action, new_key) {
+                        (RefAct::Cascade, Some(new_key)) => Expr::Register(new_key[i]),
+                        (RefAct::SetDefault, _) => column
+                            .default
+                            .as_deref()
+                            .cloned()
+                            .unwrap_or(Expr::Literal(ast::Literal::Null)),
+                        _ => Expr::Literal(ast::Literal::Null),
+                    };
+                    ast::Set {
+                        col_names: vec![ast::Name::Ident(
+                            column.name.clone().expect("column name"),
+                        )],
+                        expr: Box::new(expr),
+                    }
+                })
+                .collect();
+            ast::TriggerCmd::Update {
+                or_conflict: None,
+                tbl_name,
+                sets,
+                from: None,
+                where_clause: Some(Box::new(where_clause)),
+            }
+        }
+    }
+}
+
+/// Inlines `command` like a trigger program, in a frame that makes the rows it changes queue
+/// their keys in `queue` when they fire the same action again.
+#[allow(clippy::too_many_arguments)]
+fn emit_action(
+    program: &mut ProgramBuilder,
+    schema: &Schema,
+    syms: &SymbolTable,
+    connection: &Arc<Connection>,
+    frame_name: &str,
+    queue: usize,
+    ignore_label: BranchOffset,
+    command: ast::TriggerCmd,
+) -> Result<()> {
+    program.push_trigger(TriggerFrame {
+        trigger_name: frame_name.to_string(),
+        ignore_label,
+        cascade_queue: Some(queue),
+    });
+    program.incr_nesting();
+    let result_columns = std::mem::take(&mut program.result_columns);
+    let result = translate_trigger_cmd(program, schema, syms, connection, command);
+    program.result_columns = result_columns;
+    program.decr_nesting();
+    program.pop_trigger();
+    result
+}
+
+/// Queues the parent key in the `old_key` registers, followed by the `new_key` registers for an
+/// UPDATE, for the outermost firing of a foreign key action to run the action for it.
+fn emit_enqueue_key(
+    program: &mut ProgramBuilder,
+    queue: usize,
+    old_key: &[usize],
+    new_key: Option<&[usize]>,
+) {
+    let key_regs = old_key
+        .iter()
+        .chain(new_key.unwrap_or_default())
+        .collect::<Vec<_>>();
+    let count = key_regs.len();
+    let start_reg = program.alloc_registers(count);
+    for (i, src_reg) in key_regs.into_iter().enumerate() {
+        program.emit_insn(Insn::Copy {
+            src_reg: *src_reg,
+            dst_reg: start_reg + i,
+            extra_amount: 0,
+        });
+    }
+    let record_reg = program.alloc_register();
+    program.emit_insn(Insn::MakeRecord {
+        start_reg,
+        count,
+        dest_reg: record_reg,
+        index_name: None,
+        affinity_str: None,
+    });
+    let rowid_reg = program.alloc_register();
+    program.emit_insn(Insn::NewRowid {
+        cursor: queue,
+        rowid_reg,
+        prev_largest_reg: 0,
+    });
+    program.emit_insn(Insn::Insert {
+        cursor: queue,
+        key_reg: rowid_reg,
+        record_reg,
+        flag: InsertFlags::new().require_seek(),
+        table_name: String::new(),
+    });
+}
+
+/// Emits the rows of `PRAGMA foreign_key_check` for `table`: one row (table, rowid, parent, fkid)
+/// into the 4 registers at `result_reg` for each of its rows and each foreign key constraint of
+/// the row that refers to a missing parent row.
+pub fn emit_foreign_key_check(
+    program: &mut ProgramBuilder,
+    schema: &Schema,
+    database_id: usize,
+    table: &Arc<BTreeTable>,
+    result_reg: usize,
+) -> Result<()> {
+    if table.foreign_keys.is_empty() {
+        return Ok(());
+    }
+    // The constraints are numbered like in PRAGMA foreign_key_list, the last declared one first.
+    // A constraint whose parent table does not exist has no parent key.
+    let mut checks = Vec::with_capacity(table.foreign_keys.len());
+    for (fkid, foreign_key) in table.foreign_keys.iter().rev().enumerate() {
+        let resolved = match schema.get_btree_table(&foreign_key.parent_table) {
+            Some(_) => {
+                let resolved = resolve_foreign_key(schema, database_id, table, foreign_key)?;
+                let cursor_id = open_parent_cursor(program, &resolved);
+                Some((resolved, cursor_id))
+            }
+            None => None,
+        };
+        let child_positions = foreign_key
+            .child_columns
+            .iter()
+            .map(|name| table.get_column(name).map(|(pos, _)| pos))
+            .collect::<Option<Vec<_>>>()
+            .expect("child columns are checked by CREATE TABLE");
+        checks.push((fkid, foreign_key, child_positions, resolved));
+    }
+
+    let cursor_id = program.alloc_cursor_id(CursorType::BTreeTable(table.clone()));
+    program.emit_insn(Insn::OpenRead {
+        cursor_id,
+        root_page: table.root_page,
+        db: database_id,
+    });
+    let loop_start = program.allocate_label();
+    let done_label = program.allocate_label();
+    program.emit_insn(Insn::Rewind {
+        cursor_id,
+        pc_if_empty: done_label,
+    });
+    program.preassign_label_to_next_insn(loop_start);
+    for (fkid, foreign_key, child_positions, resolved) in &checks {
+        let next_label = program.allocate_label();
+        let violation_label = program.allocate_label();
+        let value_regs = program.alloc_registers(child_positions.len());
+        for (i, pos) in child_positions.iter().enumerate() {
+            program.emit_column_or_rowid(cursor_id, *pos, value_regs + i);
+            // A row with a NULL in any of its child columns refers to no parent row
+            program.emit_insn(Insn::IsNull {
+                reg: value_regs + i,
+                target_pc: next_label,
+            });
+        }
+        if let Some((resolved, parent_cursor_id)) = resolved {
+            let value_regs = (value_regs..value_regs + child_positions.len()).collect::<Vec<_>>();
+            emit_parent_lookup(
+                program,
+                resolved,
+                *parent_cursor_id,
+                &value_regs,
+                violation_label,
+            );
+            program.emit_insn(Insn::Goto {
+                target_pc: next_label,
+            });
+        }
+        program.preassign_label_to_next_insn(violation_label);
+        program.emit_string8(table.name.clone(), result_reg);
+        program.emit_rowid_or_null(cursor_id, result_reg + 1);
+        program.emit_string8(foreign_key.parent_table.clone(), result_reg + 2);
+        program.emit_int(*fkid as i64, result_reg + 3);
+        program.emit_result_row(result_reg, 4);
+        program.preassign_label_to_next_insn(next_label);
+    }
+    program.emit_insn(Insn::Next {
+        cursor_id,
+        pc_if_next: loop_start,
+    });
+    program.preassign_label_to_next_insn(done_label);
+    Ok(())
+}
+
+fn child_column_expr(child: &BTreeTable, pos: usize) -> Expr {
+    Expr::Id(ast::Name::Ident(
+        child.columns[pos].name.clone().expect("column name"),
+    ))
+}
+
+/// Jumps to `target_pc` if an UPDATE leaves the values of the columns at `positions` as they were.
+fn emit_skip_if_key_unchanged(
+    program: &mut ProgramBuilder,
+    positions: &[usize],
+    old: &TriggerRow,
+    new: &TriggerRow,
+    target_pc: BranchOffset,
+) {
+    let changed_label = program.allocate_label();
+    for pos in positions {
+        program.emit_insn(Insn::Ne {
+            lhs: old.column_regs[*pos],
+            rhs: new.column_regs[*pos],
+            target_pc: changed_label,
+            flags: CmpInsFlags::default().null_eq(),
+            collation: program.curr_collation(),
+        });
+    }
+    program.emit_insn(Insn::Goto { target_pc });
+    program.preassign_label_to_next_insn(changed_label);
+}
+
+/// Opens a cursor on the parent key of a foreign key constraint: the parent table if the key
+/// is its rowid, and the index on the key otherwise.
+fn open_parent_cursor(program: &mut ProgramBuilder, resolved: &ResolvedForeignKey) -> usize {
+    let (cursor_type, root_page) = match &resolved.parent_index {
+        None => (
+            CursorType::BTreeTable(resolved.parent.clone()),
+            resolved.parent.root_page,
+        ),
+        Some(index) => (CursorType::BTreeIndex(index.clone()), index.root_page),
+    };
+    let cursor_id = program.alloc_cursor_id(cursor_type);
+    program.emit_insn(Insn::OpenRead {
+        cursor_id,
+        root_page,
+        db: resolved.database_id,
+    });
+    cursor_id
+}
+
+/// Emits the lookup of the parent row that the child row in `value_regs` (the values of the
+/// child columns, in their order) refers to, jumping to `not_found_label` if there is none.
+/// `cursor_id` is a cursor opened with [open_parent_cursor].
+fn emit_parent_lookup(
+    program: &mut ProgramBuilder,
+    resolved: &ResolvedForeignKey,
+    cursor_id: usize,
+    value_regs: &[usize],
+    not_found_label: BranchOffset,
+) {
+    let parent = &resolved.parent;
+    match &resolved.parent_index {
+        None => {
+            // Values such as '1' refer to the row with rowid 1
+            let rowid_reg = program.alloc_register();
+            program.emit_insn(Insn::Copy {
+                src_reg: value_regs[0],
+                dst_reg: rowid_reg,
+                extra_amount: 0,
+            });
+            program.emit_insn(Insn::Affinity {
+                start_reg: rowid_reg,
+                count: std::num::NonZeroUsize::new(1).unwrap(),
+                affinities: parent.columns[resolved.parent_positions[0]]
+                    .affinity()
+                    .aff_mask()
+                    .to_string(),
+            });
+            program.emit_insn(Insn::SeekRowid {
+                cursor_id,
+                src_reg: rowid_reg,
+                target_pc: not_found_label,
+            });
+        }
+        Some(index) => {
+            let key_columns = index.key_columns();
+            let key_reg = program.alloc_registers(key_columns.len());
+            let mut affinities = String::with_capacity(key_columns.len());
+            for (i, column) in key_columns.iter().enumerate() {
+                let j = resolved
+                    .parent_positions
+                    .iter()
+                    .position(|pos| *pos == column.pos_in_table)
+                    .expect("index column is a parent column");
+                program.emit_insn(Insn::Copy {
+                    src_reg: value_regs[j],
+                    dst_reg: key_reg + i,
+                    extra_amount: 0,
+                });
+                affinities.push(parent.columns[column.pos_in_table].affinity().aff_mask());
+            }
+            program.emit_insn(Insn::Affinity {
+                start_reg: key_reg,
+                count: std::num::NonZeroUsize::new(key_columns.len()).unwrap(),
+                affinities,
+            });
+            program.emit_insn(Insn::NotFound {
+                cursor_id,
+                target_pc: not_found_label,
+                record_reg: key_reg,
+                num_regs: key_columns.len(),
+            });
+        }
+    }
+}
+
+/// Emits the check that the parent row of a child row exists, adding `increment` to the
+/// violation counter if it does not: 1 when the row is inserted, -1 when it is deleted.
+fn emit_parent_check(
+    program: &mut ProgramBuilder,
+    child: &BTreeTable,
+    foreign_key: &ForeignKey,
+    resolved: &ResolvedForeignKey,
+    row: &TriggerRow,
+    increment: i64,
+) {
+    let ok_label = program.allocate_label();
+    let not_found_label = program.allocate_label();
+    if increment < 0 {
+        // Deleting a child row can only fix a violation if there is one
+        program.emit_insn(Insn::FkIfZero {
+            deferred: foreign_key.deferred,
+            target_pc: ok_label,
+        });
+    }
+    let value_regs = resolved
+        .child_positions
+        .iter()
+        .map(|pos| row.column_regs[*pos])
+        .collect::<Vec<_>>();
+    // A child row with a NULL in any of its columns refers to no parent row
+    for reg in &value_regs {
+        program.emit_insn(Insn::IsNull {
+            reg: *reg,
+            target_pc: ok_label,
+        });
+    }
+    if increment > 0 && resolved.parent.name == child.name {
+        // A row may refer to itself, although it is not in the table yet
+        let lookup_label = program.allocate_label();
+        for (reg, parent_pos) in value_regs.iter().zip(&resolved.parent_positions) {
+            program.emit_insn(Insn::Ne {
+                lhs: *reg,
+                rhs: row.column_regs[*parent_pos],
+                target_pc: lookup_label,
+                flags: CmpInsFlags::default().jump_if_null(),
+                collation: program.curr_collation(),
+            });
+        }
+        program.emit_insn(Insn::Goto {
+            target_pc: ok_label,
+        });
+        program.preassign_label_to_next_insn(lookup_label);
+    }
+    let cursor_id = open_parent_cursor(program, resolved);
+    emit_parent_lookup(program, resolved, cursor_id, &value_regs, not_found_label);
+    program.emit_insn(Insn::Goto {
+        target_pc: ok_label,
+    });
+    program.preassign_label_to_next_insn(not_found_label);
+    program.emit_insn(Insn::FkCounter {
+        increment_value: increment,
+        deferred: foreign_key.deferred,
+    });
+    program.preassign_label_to_next_insn(ok_label);
+}
+
+/// Emits a scan of the child rows that refer to the parent row `row`, adding `increment` to the
+/// violation counter for each: 1 when the parent row is deleted, -1 when it is inserted. With
+/// `restrict`, the statement fails as soon as there is such a child row instead.
+#[allow(clippy::too_many_arguments)]
+fn emit_child_scan(
+    program: &mut ProgramBuilder,
+    schema: &Schema,
+    parent: &BTreeTable,
+    child: &Arc<BTreeTable>,
+    foreign_key: &ForeignKey,
+    resolved: &ResolvedForeignKey,
+    row: &TriggerRow,
+    increment: i64,
+    restrict: bool,
+) {
+    let done_label = program.allocate_label();
+    if increment < 0 {
+        // Inserting a parent row can only fix a violation if there is one
+        program.emit_insn(Insn::FkIfZero {
+            deferred: foreign_key.deferred,
+            target_pc: done_label,
+        });
+    }
+    let num_columns = resolved.child_positions.len();
+    let key_reg = program.alloc_registers(num_columns);
+    for (i, parent_pos) in resolved.parent_positions.iter().enumerate() {
+        // No child row refers to a parent row with a NULL in its key
+        program.emit_insn(Insn::IsNull {
+            reg: row.column_regs[*parent_pos],
+            target_pc: done_label,
+        });
+        program.emit_insn(Insn::Copy {
+            src_reg: row.column_regs[*parent_pos],
+            dst_reg: key_reg + i,
+            extra_amount: 0,
+        });
+    }
+    // A row that refers to itself is never counted, as its parent row is never missing
+    let exclude_row = child.name == parent.name;
+
+    // The child rows are found through an index on the child columns if there is one
+    let child_index = schema.get_indices(&child.name).iter().find(|index| {
+        let key_columns = index.key_columns();
+        index.where_clause.is_none()
+            && key_columns.len() >= num_columns
+            && key_columns[..num_columns].iter().all(|column| {
+                column.expr.is_none() && resolved.child_positions.contains(&column.pos_in_table)
+            })
+    });
+    let loop_start = program.allocate_label();
+    let next_label = program.allocate_label();
+    let cursor_id = match child_index {
+        Some(index) => {
+            let cursor_id = program.alloc_cursor_id(CursorType::BTreeIndex(index.clone()));
+            program.emit_insn(Insn::OpenRead {
+                cursor_id,
+                root_page: index.root_page,
+                db: resolved.database_id,
+            });
+            let seek_reg = program.alloc_registers(num_columns);
+            let mut affinities = String::with_capacity(num_columns);
+            for (i, column) in index.columns[..num_columns].iter().enumerate() {
+                let j = resolved
+                    .child_positions
+                    .iter()
+                    .position(|pos| *pos == column.pos_in_table)
+                    .expect("index column is a child column");
+                program.emit_insn(Insn::Copy {
+                    src_reg: key_reg + j,
+                    dst_reg: seek_reg + i,
+                    extra_amount: 0,
+                });
+                affinities.push(child.columns[column.pos_in_table].affinity().aff_mask());
+            }
+            program.emit_insn(Insn::Affinity {
+                start_reg: seek_reg,
+                count: std::num::NonZeroUsize::new(num_columns).unwrap(),
+                affinities,
+            });
+            program.emit_insn(Insn::SeekGE {
+                is_index: true,
+                cursor_id,
+                start_reg: seek_reg,
+                num_regs: num_columns,
+                target_pc: done_label,
+                eq_only: true,
+            });
+            program.preassign_label_to_next_insn(loop_start);
+            program.emit_insn(Insn::IdxGT {
+                cursor_id,
+                start_reg: seek_reg,
+                num_regs: num_columns,
+                target_pc: done_label,
+            });
+            if exclude_row {
+                if index.has_rowid {
+                    let rowid_reg = program.alloc_register();
+                    program.emit_insn(Insn::IdxRowId {
+                        cursor_id,
+                        dest: rowid_reg,
+                    });
+                    program.emit_insn(Insn::Eq {
+                        lhs: rowid_reg,
+                        rhs: row.rowid_reg,
+                        target_pc: next_label,
+                        flags: CmpInsFlags::default(),
+                        collation: program.curr_collation(),
+                    });
+                } else {
+                    emit_skip_if_same_primary_key(
+                        program,
+                        cursor_id,
+                        child,
+                        row,
+                        next_label,
+                        |pos| {
+                            index
+                                .column_table_pos_to_index_pos(pos)
+                                .expect("indexes of a WITHOUT ROWID table contain its primary key")
+                        },
+                    );
+                }
+            }
+            cursor_id
+        }
+        None => {
+            let cursor_id = program.alloc_cursor_id(CursorType::BTreeTable(child.clone()));
+            program.emit_insn(Insn::OpenRead {
+                cursor_id,
+                root_page: child.root_page,
+                db: resolved.database_id,
+            });
+            let affinities = resolved
+                .child_positions
+                .iter()
+                .map(|pos| child.columns[*pos].affinity().aff_mask())
+                .collect::<String>();
+            program.emit_insn(Insn::Affinity {
+                start_reg: key_reg,
+                count: std::num::NonZeroUsize::new(num_columns).unwrap(),
+                affinities,
+            });
+            program.emit_insn(Insn::Rewind {
+                cursor_id,
+                pc_if_empty: done_label,
+            });
+            program.preassign_label_to_next_insn(loop_start);
+            let column_reg = program.alloc_register();
+            for (i, child_pos) in resolved.child_positions.iter().enumerate() {
+                program.emit_column_or_rowid(cursor_id, *child_pos, column_reg);
+                program.emit_insn(Insn::Ne {
+                    lhs: column_reg,
+                    rhs: key_reg + i,
+                    target_pc: next_label,
+                    flags: CmpInsFlags::default().jump_if_null(),
+                    collation: program.curr_collation(),
+                });
+            }
+            if exclude_row {
+                if child.has_rowid {
+                    let rowid_reg = program.alloc_register();
+                    program.emit_insn(Insn::RowId {
+                        cursor_id,
+                        dest: rowid_reg,
+                    });
+                    program.emit_insn(Insn::Eq {
+                        lhs: rowid_reg,
+                        rhs: row.rowid_reg,
+                        target_pc: next_label,
+                        flags: CmpInsFlags::default(),
+                        collation: program.curr_collation(),
+                    });
+                } else {
+                    emit_skip_if_same_primary_key(
+                        program,
+                        cursor_id,
+                        child,
+                        row,
+                        next_label,
+                        |pos| pos,
+                    );
+                }
+            }
+            cursor_id
+        }
+    };
+    if restrict {
+        program.emit_insn(Insn::Halt {
+            err_code: SQLITE_CONSTRAINT_FOREIGNKEY,
+            description: String::new(),
+        });
+    } else {
+        program.emit_insn(Insn::FkCounter {
+            increment_value: increment,
+            deferred: foreign_key.deferred,
+        });
+    }
+    program.preassign_label_to_next_insn(next_label);
+    program.emit_insn(Insn::Next {
+        cursor_id,
+        pc_if_next: loop_start,
+    });
+    program.preassign_label_to_next_insn(done_label);
+}
+
+/// Jumps to `target_pc` if the row of a WITHOUT ROWID table that `cursor_id` points to has the
+/// primary key of `row`. `column_pos` maps the position of a column in the table to its
+/// position in the rows of the cursor.
+fn emit_skip_if_same_primary_key(
+    program: &mut ProgramBuilder,
+    cursor_id: usize,
+    table: &BTreeTable,
+    row: &TriggerRow,
+    target_pc: BranchOffset,
+    column_pos: impl Fn(usize) -> usize,
+) {
+    let different_label = program.allocate_label();
+    let column_reg = program.alloc_register();
+    for pos in table.primary_key_column_positions() {
+        program.emit_column_or_rowid(cursor_id, column_pos(pos), column_reg);
+        program.emit_insn(Insn::Ne {
+            lhs: column_reg,
+            rhs: row.column_regs[pos],
+            target_pc: different_label,
+            flags: CmpInsFlags::default(),
+            collation: program.curr_collation(),
+        });
+    }
+    program.emit_insn(Insn::Goto { target_pc });
+    program.preassign_label_to_next_insn(different_label);
+}
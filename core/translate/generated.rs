@@ -0,0 +1,131 @@
+//! Generated columns, see <https://www.sqlite.org/gencol.html>.
+//!
+//! STORED generated columns are computed when a row is inserted or updated and are stored in
+//! the record like the other columns. VIRTUAL generated columns have no place in the record:
+//! their values are computed whenever the column is read. Both are computed over the registers
+//! holding the other columns of the row, the same way indexed expressions are.
+
+use std::num::NonZeroUsize;
+
+use turso_parser::ast::{Expr, Literal};
+
+use crate::error::SQLITE_CONSTRAINT_NOTNULL;
+use crate::schema::{Affinity, BTreeTable, Column};
+use crate::translate::emitter::Resolver;
+use crate::translate::index::{bind_index_expr, emit_index_expr};
+use crate::vdbe::builder::{CursorType, ProgramBuilder};
+use crate::vdbe::insn::Insn;
+use crate::Result;
+
+/// Computes the generated columns of a row that is about to be written. The values of the
+/// columns are in consecutive registers starting at `columns_start_reg`; the registers of the
+/// generated columns are overwritten with their computed values.
+pub fn emit_generated_columns(
+    program: &mut ProgramBuilder,
+    resolver: &Resolver,
+    table: &BTreeTable,
+    columns_start_reg: usize,
+    rowid_reg: usize,
+) -> Result<()> {
+    for pos in table.generated_columns_in_dependency_order()? {
+        let column = &table.columns[pos];
+        let generated = column.generated.as_ref().expect("column is generated");
+        emit_index_expr(
+            program,
+            resolver,
+            table,
+            &generated.expr,
+            |_, pos| Ok(columns_start_reg + pos),
+            rowid_reg,
+            columns_start_reg + pos,
+        )?;
+        emit_column_affinity(program, column, columns_start_reg + pos);
+    }
+    Ok(())
+}
+
+/// Emits the NOT NULL checks of the generated columns of a row computed by
+/// [emit_generated_columns].
+pub fn emit_generated_columns_not_null_checks(
+    program: &mut ProgramBuilder,
+    table: &BTreeTable,
+    columns_start_reg: usize,
+) {
+    for (pos, column) in table.columns.iter().enumerate() {
+        if !column.is_generated() || !column.notnull {
+            continue;
+        }
+        program.emit_insn(Insn::HaltIfNull {
+            target_reg: columns_start_reg + pos,
+            err_code: SQLITE_CONSTRAINT_NOTNULL,
+            description: format!(
+                "{}.{}",
+                table.name,
+                column.name.as_deref().unwrap_or_default()
+            ),
+        });
+    }
+}
+
+/// Reads the column at `pos` of the row the table cursor points at into `dest`. Unlike
+/// [ProgramBuilder::emit_column_or_rowid], VIRTUAL generated columns are computed from the
+/// columns they refer to.
+pub fn emit_table_column(
+    program: &mut ProgramBuilder,
+    resolver: &Resolver,
+    cursor_id: usize,
+    pos: usize,
+    dest: usize,
+) -> Result<()> {
+    let table = match &program.cursor_ref[cursor_id].1 {
+        CursorType::BTreeTable(table) if table.columns[pos].is_virtual_generated() => table.clone(),
+        _ => {
+            program.emit_column_or_rowid(cursor_id, pos, dest);
+            return Ok(());
+        }
+    };
+    let column = &table.columns[pos];
+    let generated = column.generated.as_ref().expect("column is generated");
+
+    // The rowid is only loaded if the expression refers to it
+    let mut refers_to_rowid = false;
+    bind_index_expr(&generated.expr, &table, |dependency| {
+        if dependency.is_none_or(|dependency| table.columns[dependency].is_rowid_alias) {
+            refers_to_rowid = true;
+        }
+        Expr::Literal(Literal::Null)
+    })?;
+    let rowid_reg = program.alloc_register();
+    if refers_to_rowid {
+        program.emit_rowid_or_null(cursor_id, rowid_reg);
+    }
+    // The columns the expression refers to are computed as well if needed
+    emit_index_expr(
+        program,
+        resolver,
+        &table,
+        &generated.expr,
+        |program, pos| {
+            let reg = program.alloc_register();
+            emit_table_column(program, resolver, cursor_id, pos, reg)?;
+            Ok(reg)
+        },
+        rowid_reg,
+        dest,
+    )?;
+    emit_column_affinity(program, column, dest);
+    Ok(())
+}
+
+/// The value of a generated column takes the affinity of the column, like a value written to it.
+pub fn emit_column_affinity(program: &mut ProgramBuilder, column: &Column, reg: usize) {
+    let affinity = column.affinity();
+    if affinity == Affinity::Blob {
+        return;
+    }
+    program.emit_insn(Insn::Affinity {
+        start_reg: reg,
+        count: NonZeroUsize::new(1).unwrap(),
+        affinities: affinity.aff_mask().to_string(),
+    });
+}
@@ -7,7 +7,9 @@ use super::{
     plan::{Distinctness, GroupBy, SelectPlan},
     result_row::emit_select_result,
 };
-use crate::translate::aggregation::{translate_aggregation_step, AggArgumentSource};
+use crate::translate::aggregation::{
+    emit_ordered_aggregation_steps, translate_aggregation_step, AggArgumentSource,
+};
 use crate::translate::expr::{walk_expr, WalkControl};
 use crate::translate::plan::ResultSetColumn;
 use crate::{
@@ -121,7 +123,7 @@ pub fn init_group_by<'a>(
     // END BLOCK
 
     let reg_sorter_key = program.alloc_register();
-    let column_count = plan.agg_args_count() + t_ctx.non_aggregate_expressions.len();
+    let column_count = plan.agg_inputs_count() + t_ctx.non_aggregate_expressions.len();
     let reg_group_by_source_cols_start = program.alloc_registers(column_count);
 
     let row_source = if let Some(sort_order) = group_by.sort_order.as_ref() {
@@ -520,7 +522,7 @@ pub fn group_by_process_single_group(
                 .expect("distinct aggregate context not populated");
             program.preassign_label_to_next_insn(ctx.label_on_conflict);
         }
-        offset += agg.args.len();
+        offset += agg.num_inputs();
     }
 
     // We only need to store non-aggregate columns once per group
@@ -691,6 +693,13 @@ pub fn group_by_emit_row_phase<'a>(
             .reg_agg_start
             .expect("aggregate registers must be initialized");
         let agg_result_reg = agg_start_reg + i;
+        emit_ordered_aggregation_steps(
+            program,
+            &plan.table_references,
+            agg,
+            agg_result_reg,
+            &t_ctx.resolver,
+        )?;
         program.emit_insn(Insn::AggFinal {
             register: agg_result_reg,
             func: agg.func.clone(),
@@ -762,7 +771,7 @@ pub fn group_by_emit_row_phase<'a>(
     program.emit_insn(Insn::Null {
         dest: start_reg,
         dest_end: Some(
-            start_reg + t_ctx.non_aggregate_expressions.len() + plan.agg_args_count() - 1,
+            start_reg + t_ctx.non_aggregate_expressions.len() + plan.aggregates.len() - 1,
         ),
     });
 
@@ -786,6 +795,18 @@ pub fn group_by_emit_row_phase<'a>(
             });
         });
 
+    // Reopen ephemeral indexes for aggregates with an ORDER BY clause as well.
+    for ctx in plan
+        .aggregates
+        .iter()
+        .filter_map(|agg| agg.order_by_ctx.as_ref())
+    {
+        program.emit_insn(Insn::OpenEphemeral {
+            cursor_id: ctx.cursor_id,
+            is_table: false,
+        });
+    }
+
     program.emit_insn(Insn::Integer {
         value: 0,
         dest: registers.reg_data_in_acc_flag,
@@ -1,14 +1,20 @@
+use std::collections::HashMap;
 use std::sync::Arc;
 
+use crate::function::Func;
 use crate::translate::emitter::{
     emit_cdc_full_record, emit_cdc_insns, prepare_cdc_if_necessary, OperationMode, Resolver,
 };
+use crate::translate::expr::{translate_expr, walk_expr, walk_expr_mut, WalkControl};
+use crate::translate::generated::emit_table_column;
+use crate::translate::planner::{break_predicate_at_and_boundaries, ROWID};
 use crate::vdbe::insn::{CmpInsFlags, Cookie};
+use crate::vdbe::BranchOffset;
 use crate::SymbolTable;
 use crate::{
-    schema::{BTreeTable, Column, Index, IndexColumn, PseudoCursorType, Schema},
+    schema::{BTreeTable, Index, IndexColumn, PseudoCursorType, Schema, INDEX_EXPR_POS},
     storage::pager::CreateBTreeFlags,
-    util::normalize_ident,
+    util::{exprs_are_equivalent, normalize_ident},
     vdbe::{
         builder::{CursorType, ProgramBuilder},
         insn::{IdxInsertFlags, Insn, RegisterOrLiteral},
@@ -18,11 +24,14 @@ use turso_parser::ast::{self, Expr, SortOrder, SortedColumn};
 
 use super::schema::{emit_schema_entry, SchemaEntryType, SQLITE_TABLEID};
 
+#[allow(clippy::too_many_arguments)]
 pub fn translate_create_index(
     unique_if_not_exists: (bool, bool),
+    database_id: usize,
     idx_name: &str,
     tbl_name: &str,
     columns: &[SortedColumn],
+    where_clause: Option<Box<Expr>>,
     schema: &Schema,
     syms: &SymbolTable,
     mut program: ProgramBuilder,
@@ -56,48 +65,40 @@ pub fn translate_create_index(
     let Some(tbl) = tbl.btree() else {
         crate::bail_parse_error!("Error: table '{tbl_name}' is not a b-tree table.");
     };
-    let columns = resolve_sorted_columns(&tbl, columns)?;
+    let resolver = Resolver::new(schema, syms);
+    let mut columns = resolve_sorted_columns(&tbl, columns, &resolver)?;
+    if let Some(where_clause) = &where_clause {
+        validate_index_expr(where_clause, &tbl, &resolver, "partial index WHERE clauses")?;
+    }
+
+    // The index of a WITHOUT ROWID table points back to its rows by their primary key
+    let num_key_columns = (!tbl.has_rowid).then_some(columns.len());
+    if !tbl.has_rowid {
+        let suffix = tbl.primary_key_suffix_columns(&columns);
+        columns.extend(suffix);
+    }
 
     let idx = Arc::new(Index {
         name: idx_name.clone(),
         table_name: tbl.name.clone(),
         root_page: 0, //  we dont have access till its created, after we parse the schema table
-        columns: columns
-            .iter()
-            .map(|((pos_in_table, col), order)| IndexColumn {
-                name: col.name.as_ref().unwrap().clone(),
-                order: *order,
-                pos_in_table: *pos_in_table,
-                collation: col.collation,
-                default: col.default.clone(),
-            })
-            .collect(),
+        columns,
         unique: unique_if_not_exists.0,
         ephemeral: false,
         has_rowid: tbl.has_rowid,
+        num_key_columns,
+        where_clause,
     });
 
-    // Allocate the necessary cursors:
-    //
-    // 1. sqlite_schema_cursor_id - sqlite_schema table
-    // 2. btree_cursor_id         - new index btree
-    // 3. table_cursor_id         - table we are creating the index on
-    // 4. sorter_cursor_id        - sorter
-    // 5. pseudo_cursor_id        - pseudo table to store the sorted index values
+    // Allocate the cursor of the sqlite_schema table, the index population allocates its own
     let sqlite_table = schema.get_btree_table(SQLITE_TABLEID).unwrap();
     let sqlite_schema_cursor_id =
         program.alloc_cursor_id(CursorType::BTreeTable(sqlite_table.clone()));
-    let btree_cursor_id = program.alloc_cursor_id(CursorType::BTreeIndex(idx.clone()));
-    let table_cursor_id = program.alloc_cursor_id(CursorType::BTreeTable(tbl.clone()));
-    let sorter_cursor_id = program.alloc_cursor_id(CursorType::Sorter);
-    let pseudo_cursor_id = program.alloc_cursor_id(CursorType::Pseudo(PseudoCursorType {
-        column_count: tbl.columns.len(),
-    }));
 
     // Create a new B-Tree and store the root page index in a register
     let root_page_reg = program.alloc_register();
     program.emit_insn(Insn::CreateBtree {
-        db: 0,
+        db: database_id,
         root: root_page_reg,
         flags: CreateBTreeFlags::new_index(),
     });
@@ -106,11 +107,21 @@ pub fn translate_create_index(
     program.emit_insn(Insn::OpenWrite {
         cursor_id: sqlite_schema_cursor_id,
         root_page: RegisterOrLiteral::Literal(sqlite_table.root_page),
-        db: 0,
+        db: database_id,
     });
-    let sql = create_idx_stmt_to_sql(&tbl_name, &idx_name, unique_if_not_exists, &columns);
-    let resolver = Resolver::new(schema, syms);
-    let cdc_table = prepare_cdc_if_necessary(&mut program, schema, SQLITE_TABLEID)?;
+    let sql = create_idx_stmt_to_sql(
+        &tbl_name,
+        &idx_name,
+        unique_if_not_exists,
+        idx.key_columns(),
+        idx.where_clause.as_deref(),
+    );
+    // changes to the temp database are not captured
+    let cdc_table = if database_id == 0 {
+        prepare_cdc_if_necessary(&mut program, schema, SQLITE_TABLEID)?
+    } else {
+        None
+    };
     emit_schema_entry(
         &mut program,
         &resolver,
@@ -123,27 +134,83 @@ pub fn translate_create_index(
         Some(sql),
     )?;
 
+    emit_index_population(
+        &mut program,
+        &resolver,
+        database_id,
+        &tbl,
+        &idx,
+        RegisterOrLiteral::Register(root_page_reg),
+        false,
+    )?;
+
+    // The sqlite_schema cursor is kept open until ParseSchema has run
+    program.emit_insn(Insn::SetCookie {
+        db: database_id,
+        cookie: Cookie::SchemaVersion,
+        value: schema.schema_version as i32 + 1,
+        p5: 0,
+    });
+    // Parse the schema table to get the index root page and add new index to Schema
+    let parse_schema_where_clause = format!("name = '{idx_name}' AND type = 'index'");
+    program.emit_insn(Insn::ParseSchema {
+        db: database_id,
+        where_clause: Some(parse_schema_where_clause),
+    });
+    // Close the final sqlite_schema cursor
+    program.emit_insn(Insn::Close {
+        cursor_id: sqlite_schema_cursor_id,
+    });
+
+    Ok(program)
+}
+
+/// Fills the b-tree of `index`, rooted at `root_page`, with an entry for every row of `table`.
+/// The entries are sorted first, so that they are appended to the b-tree in order. If `clear` is
+/// set, the entries the b-tree already holds are deleted first, which is how REINDEX rebuilds it.
+pub fn emit_index_population(
+    program: &mut ProgramBuilder,
+    resolver: &Resolver,
+    database_id: usize,
+    table: &Arc<BTreeTable>,
+    index: &Arc<Index>,
+    root_page: RegisterOrLiteral<usize>,
+    clear: bool,
+) -> crate::Result<()> {
+    // Allocate the necessary cursors:
+    //
+    // 1. btree_cursor_id         - index btree
+    // 2. table_cursor_id         - table the index is on
+    // 3. sorter_cursor_id        - sorter
+    // 4. pseudo_cursor_id        - pseudo table to store the sorted index values
+    let btree_cursor_id = program.alloc_cursor_id(CursorType::BTreeIndex(index.clone()));
+    let table_cursor_id = program.alloc_cursor_id(CursorType::BTreeTable(table.clone()));
+    let sorter_cursor_id = program.alloc_cursor_id(CursorType::Sorter);
+    let pseudo_cursor_id = program.alloc_cursor_id(CursorType::Pseudo(PseudoCursorType {
+        column_count: table.columns.len(),
+    }));
+
     // determine the order of the columns in the index for the sorter
-    let order = idx.columns.iter().map(|c| c.order).collect();
+    let order = index.columns.iter().map(|c| c.order).collect();
     // open the sorter and the pseudo table
     program.emit_insn(Insn::SorterOpen {
         cursor_id: sorter_cursor_id,
-        columns: columns.len(),
+        columns: index.columns.len(),
         order,
-        collations: idx.columns.iter().map(|c| c.collation).collect(),
+        collations: index.columns.iter().map(|c| c.collation).collect(),
     });
     let content_reg = program.alloc_register();
     program.emit_insn(Insn::OpenPseudo {
         cursor_id: pseudo_cursor_id,
         content_reg,
-        num_fields: columns.len() + 1,
+        num_fields: index.columns.len() + index.has_rowid as usize,
     });
 
-    // open the table we are creating the index on for reading
+    // open the table the index is on for reading
     program.emit_insn(Insn::OpenRead {
         cursor_id: table_cursor_id,
-        root_page: tbl.root_page,
-        db: 0,
+        root_page: table.root_page,
+        db: database_id,
     });
 
     let loop_start_label = program.allocate_label();
@@ -158,22 +225,52 @@ pub fn translate_create_index(
     // Collect index values into start_reg..rowid_reg
     // emit MakeRecord (index key + rowid) into record_reg.
     //
-    // Then insert the record into the sorter
-    let start_reg = program.alloc_registers(columns.len() + 1);
-    for (i, (col, _)) in columns.iter().enumerate() {
-        program.emit_column_or_rowid(table_cursor_id, col.0, start_reg + i);
+    // Then insert the record into the sorter.
+    // Rows that do not satisfy the WHERE clause of a partial index are skipped.
+    let skip_row_label = program.allocate_label();
+    let start_reg = program.alloc_registers(index.columns.len() + 1);
+    let rowid_reg = start_reg + index.columns.len();
+    program.emit_rowid_or_null(table_cursor_id, rowid_reg);
+    let load_column = |program: &mut ProgramBuilder, pos: usize| {
+        let reg = program.alloc_register();
+        emit_table_column(program, resolver, table_cursor_id, pos, reg)?;
+        Ok(reg)
+    };
+    emit_partial_index_check(
+        program,
+        resolver,
+        index,
+        table,
+        load_column,
+        rowid_reg,
+        skip_row_label,
+    )?;
+    for (i, col) in index.columns.iter().enumerate() {
+        match &col.expr {
+            Some(expr) => emit_index_expr(
+                program,
+                resolver,
+                table,
+                expr,
+                load_column,
+                rowid_reg,
+                start_reg + i,
+            )?,
+            None => emit_table_column(
+                program,
+                resolver,
+                table_cursor_id,
+                col.pos_in_table,
+                start_reg + i,
+            )?,
+        }
     }
-    let rowid_reg = start_reg + columns.len();
-    program.emit_insn(Insn::RowId {
-        cursor_id: table_cursor_id,
-        dest: rowid_reg,
-    });
     let record_reg = program.alloc_register();
     program.emit_insn(Insn::MakeRecord {
         start_reg,
-        count: columns.len() + 1,
+        count: index.columns.len() + index.has_rowid as usize,
         dest_reg: record_reg,
-        index_name: Some(idx_name.clone()),
+        index_name: Some(index.name.clone()),
         affinity_str: None,
     });
     program.emit_insn(Insn::SorterInsert {
@@ -181,20 +278,39 @@ pub fn translate_create_index(
         record_reg,
     });
 
+    program.preassign_label_to_next_insn(skip_row_label);
     program.emit_insn(Insn::Next {
         cursor_id: table_cursor_id,
         pc_if_next: loop_start_label,
     });
     program.preassign_label_to_next_insn(loop_end_label);
 
-    // Open the index btree we created for writing to insert the
-    // newly sorted index records.
+    // Open the index btree for writing to insert the newly sorted index records.
     program.emit_insn(Insn::OpenWrite {
         cursor_id: btree_cursor_id,
-        root_page: RegisterOrLiteral::Register(root_page_reg),
-        db: 0,
+        root_page,
+        db: database_id,
     });
 
+    if clear {
+        let clear_loop_start = program.allocate_label();
+        let clear_loop_end = program.allocate_label();
+        program.emit_insn(Insn::Rewind {
+            cursor_id: btree_cursor_id,
+            pc_if_empty: clear_loop_end,
+        });
+        program.preassign_label_to_next_insn(clear_loop_start);
+        program.emit_insn(Insn::Delete {
+            cursor_id: btree_cursor_id,
+            table_name: index.name.clone(),
+        });
+        program.emit_insn(Insn::Next {
+            cursor_id: btree_cursor_id,
+            pc_if_next: clear_loop_start,
+        });
+        program.preassign_label_to_next_insn(clear_loop_end);
+    }
+
     let sorted_loop_start = program.allocate_label();
     let sorted_loop_end = program.allocate_label();
 
@@ -229,62 +345,306 @@ pub fn translate_create_index(
     });
     program.preassign_label_to_next_insn(sorted_loop_end);
 
-    // End of the outer loop
-    //
-    // Keep schema table open to emit ParseSchema, close the other cursors.
     program.close_cursors(&[sorter_cursor_id, table_cursor_id, btree_cursor_id]);
-
-    program.emit_insn(Insn::SetCookie {
-        db: 0,
-        cookie: Cookie::SchemaVersion,
-        value: schema.schema_version as i32 + 1,
-        p5: 0,
-    });
-    // Parse the schema table to get the index root page and add new index to Schema
-    let parse_schema_where_clause = format!("name = '{idx_name}' AND type = 'index'");
-    program.emit_insn(Insn::ParseSchema {
-        db: sqlite_schema_cursor_id,
-        where_clause: Some(parse_schema_where_clause),
-    });
-    // Close the final sqlite_schema cursor
-    program.emit_insn(Insn::Close {
-        cursor_id: sqlite_schema_cursor_id,
-    });
-
-    Ok(program)
+    Ok(())
 }
 
-fn resolve_sorted_columns<'a>(
-    table: &'a BTreeTable,
+fn resolve_sorted_columns(
+    table: &BTreeTable,
     cols: &[SortedColumn],
-) -> crate::Result<Vec<((usize, &'a Column), SortOrder)>> {
+    resolver: &Resolver,
+) -> crate::Result<Vec<IndexColumn>> {
     let mut resolved = Vec::with_capacity(cols.len());
     for sc in cols {
-        let ident = normalize_ident(match sc.expr.as_ref() {
-            // SQLite supports indexes on arbitrary expressions, but we don't (yet).
-            // See "How to use indexes on expressions" in https://www.sqlite.org/expridx.html
+        let order = sc.order.unwrap_or(SortOrder::Asc);
+        let col_name = match sc.expr.as_ref() {
             Expr::Id(ast::Name::Ident(col_name))
             | Expr::Id(ast::Name::Quoted(col_name))
             | Expr::Name(ast::Name::Ident(col_name))
             | Expr::Name(ast::Name::Quoted(col_name)) => col_name,
-            _ => crate::bail_parse_error!("Error: cannot use expressions in CREATE INDEX"),
-        });
-        let Some(col) = table.get_column(&ident) else {
+            Expr::Collate(..) => {
+                crate::bail_parse_error!("Error: cannot use COLLATE in CREATE INDEX")
+            }
+            // An index on an expression, see https://www.sqlite.org/expridx.html
+            expr => {
+                validate_index_expr(expr, table, resolver, "index expressions")?;
+                resolved.push(IndexColumn {
+                    name: expr.to_string(),
+                    order,
+                    pos_in_table: INDEX_EXPR_POS,
+                    collation: None,
+                    default: None,
+                    expr: Some(sc.expr.clone()),
+                });
+                continue;
+            }
+        };
+        let ident = normalize_ident(col_name);
+        let Some((pos_in_table, col)) = table.get_column(&ident) else {
             crate::bail_parse_error!(
                 "Error: column '{ident}' does not exist in table '{}'",
                 table.name
             );
         };
-        resolved.push((col, sc.order.unwrap_or(SortOrder::Asc)));
+        resolved.push(IndexColumn {
+            name: col.name.as_ref().unwrap().clone(),
+            order,
+            pos_in_table,
+            collation: col.collation,
+            default: col.default.clone(),
+            expr: None,
+        });
     }
     Ok(resolved)
 }
 
+/// Checks that an indexed expression or the WHERE clause of a partial index only refers to
+/// columns of the indexed table and does not contain subqueries, parameters, aggregates or
+/// non-deterministic functions. `context` names the kind of expression in error messages.
+fn validate_index_expr(
+    index_expr: &Expr,
+    table: &BTreeTable,
+    resolver: &Resolver,
+    context: &str,
+) -> crate::Result<()> {
+    bind_index_expr(index_expr, table, |_| Expr::Literal(ast::Literal::Null))?;
+    walk_expr(
+        index_expr,
+        &mut |expr: &Expr| -> crate::Result<WalkControl> {
+            match expr {
+                Expr::Exists(_)
+                | Expr::Subquery(_)
+                | Expr::InSelect { .. }
+                | Expr::InTable { .. } => {
+                    crate::bail_parse_error!("subqueries prohibited in {context}");
+                }
+                Expr::Variable(_) => {
+                    crate::bail_parse_error!("parameters prohibited in {context}");
+                }
+                Expr::FunctionCall { name, args, .. } => {
+                    match resolver.resolve_function(name.as_str(), args.len()) {
+                        None => crate::bail_parse_error!("no such function: {}", name.as_str()),
+                        Some(Func::Agg(_)) => {
+                            crate::bail_parse_error!(
+                                "misuse of aggregate function {}()",
+                                name.as_str()
+                            )
+                        }
+                        Some(func) if !func.is_deterministic() => crate::bail_parse_error!(
+                            "non-deterministic functions prohibited in {context}"
+                        ),
+                        Some(_) => {}
+                    }
+                }
+                Expr::FunctionCallStar { name, .. } => {
+                    crate::bail_parse_error!("misuse of aggregate function {}()", name.as_str())
+                }
+                _ => {}
+            }
+            Ok(WalkControl::Continue)
+        },
+    )?;
+    Ok(())
+}
+
+/// Returns a copy of an indexed expression or the WHERE clause of a partial index on `table`
+/// in which every column reference is replaced by `column_expr(Some(pos))`, where `pos` is the
+/// position of the column in the table, and every reference to the rowid by `column_expr(None)`.
+pub fn bind_index_expr(
+    index_expr: &Expr,
+    table: &BTreeTable,
+    mut column_expr: impl FnMut(Option<usize>) -> Expr,
+) -> crate::Result<Expr> {
+    let mut bound = index_expr.clone();
+    walk_expr_mut(&mut bound, &mut |expr: &mut Expr| -> crate::Result<()> {
+        let column_name = match expr {
+            Expr::Id(name) => normalize_ident(name.as_str()),
+            Expr::Qualified(tbl_name, name) => {
+                if normalize_ident(tbl_name.as_str()) != table.name {
+                    crate::bail_parse_error!(
+                        "no such column: {}.{}",
+                        tbl_name.as_str(),
+                        name.as_str()
+                    );
+                }
+                normalize_ident(name.as_str())
+            }
+            Expr::RowId { .. } => {
+                *expr = column_expr(None);
+                return Ok(());
+            }
+            _ => return Ok(()),
+        };
+        *expr = match table.get_column(&column_name) {
+            Some((pos, _)) => column_expr(Some(pos)),
+            None if table.has_rowid
+                && (column_name == ROWID || column_name == "oid" || column_name == "_rowid_") =>
+            {
+                column_expr(None)
+            }
+            None => crate::bail_parse_error!("no such column: {column_name}"),
+        };
+        Ok(())
+    })?;
+    Ok(bound)
+}
+
+/// Renames the references to column `from` in an indexed expression or the WHERE clause of a
+/// partial index to `to`.
+pub fn rename_index_expr_column(index_expr: &mut Expr, from: &str, to: &str) {
+    let _ = walk_expr_mut(index_expr, &mut |expr: &mut Expr| -> crate::Result<()> {
+        match expr {
+            Expr::Id(ast::Name::Ident(id)) | Expr::Qualified(_, ast::Name::Ident(id))
+                if normalize_ident(id) == from =>
+            {
+                *id = to.to_owned();
+            }
+            _ => {}
+        }
+        Ok(())
+    });
+}
+
+/// Returns true if an indexed expression or the WHERE clause of a partial index refers to the
+/// column at `pos` in `table`. The rowid alias column is also considered referenced when the
+/// expression uses the rowid.
+pub fn index_expr_refers_to_column(index: &Index, table: &BTreeTable, pos: usize) -> bool {
+    let mut refers = false;
+    for index_expr in index
+        .columns
+        .iter()
+        .filter_map(|col| col.expr.as_deref())
+        .chain(index.where_clause.as_deref())
+    {
+        let _ = bind_index_expr(index_expr, table, |column| {
+            match column {
+                Some(column) => refers |= column == pos,
+                None => refers |= table.columns[pos].is_rowid_alias,
+            }
+            Expr::Literal(ast::Literal::Null)
+        });
+    }
+    refers
+}
+
+/// Returns true if every row satisfying all of `terms` is known to satisfy the WHERE clause of
+/// a partial index. Each AND-ed term of `predicate` must either be equivalent to one of `terms`,
+/// or be an `x IS NOT NULL` check where `x` is an operand of a comparison in `terms`.
+pub fn terms_imply_partial_index_predicate(terms: &[&Expr], predicate: &Expr) -> bool {
+    let mut predicate_terms = Vec::new();
+    break_predicate_at_and_boundaries(predicate, &mut predicate_terms);
+    predicate_terms.iter().all(|predicate_term| {
+        let not_null_operand = match predicate_term {
+            Expr::NotNull(operand) => Some(operand.as_ref()),
+            Expr::Binary(operand, ast::Operator::IsNot, null)
+                if matches!(null.as_ref(), Expr::Literal(ast::Literal::Null)) =>
+            {
+                Some(operand.as_ref())
+            }
+            _ => None,
+        };
+        terms.iter().any(|term| {
+            if exprs_are_equivalent(term, predicate_term) {
+                return true;
+            }
+            let Some(operand) = not_null_operand else {
+                return false;
+            };
+            match term {
+                Expr::Binary(lhs, op, rhs)
+                    if matches!(
+                        op,
+                        ast::Operator::Equals
+                            | ast::Operator::NotEquals
+                            | ast::Operator::Less
+                            | ast::Operator::LessEquals
+                            | ast::Operator::Greater
+                            | ast::Operator::GreaterEquals
+                    ) =>
+                {
+                    exprs_are_equivalent(lhs, operand) || exprs_are_equivalent(rhs, operand)
+                }
+                _ => false,
+            }
+        })
+    })
+}
+
+/// Emits a jump to `skip_label` when the row being written does not satisfy the WHERE clause
+/// of a partial index, so that the index maintenance in between is skipped. Does nothing for
+/// an index on all rows. `column_reg` returns the register holding the value of the column
+/// at the given position; the rowid and the rowid alias column are read from `rowid_reg`.
+pub fn emit_partial_index_check(
+    program: &mut ProgramBuilder,
+    resolver: &Resolver,
+    index: &Index,
+    table: &BTreeTable,
+    column_reg: impl FnMut(&mut ProgramBuilder, usize) -> crate::Result<usize>,
+    rowid_reg: usize,
+    skip_label: BranchOffset,
+) -> crate::Result<()> {
+    let Some(where_clause) = &index.where_clause else {
+        return Ok(());
+    };
+    let predicate_reg = program.alloc_register();
+    emit_index_expr(
+        program,
+        resolver,
+        table,
+        where_clause,
+        column_reg,
+        rowid_reg,
+        predicate_reg,
+    )?;
+    program.emit_insn(Insn::IfNot {
+        reg: predicate_reg,
+        target_pc: skip_label,
+        jump_if_null: true,
+    });
+    Ok(())
+}
+
+/// Evaluates an indexed expression or the WHERE clause of a partial index for the row being
+/// written into `dest_reg`. `column_reg` returns the register holding the value of the column
+/// at the given position; the rowid and the rowid alias column are read from `rowid_reg`.
+pub fn emit_index_expr(
+    program: &mut ProgramBuilder,
+    resolver: &Resolver,
+    table: &BTreeTable,
+    index_expr: &Expr,
+    mut column_reg: impl FnMut(&mut ProgramBuilder, usize) -> crate::Result<usize>,
+    rowid_reg: usize,
+    dest_reg: usize,
+) -> crate::Result<()> {
+    // The registers of the columns are looked up before binding, since loading a column may
+    // itself evaluate an expression
+    let mut referenced = vec![];
+    bind_index_expr(index_expr, table, |column| {
+        referenced.extend(column);
+        Expr::Literal(ast::Literal::Null)
+    })?;
+    let mut column_regs = HashMap::new();
+    for pos in referenced {
+        if !table.columns[pos].is_rowid_alias && !column_regs.contains_key(&pos) {
+            column_regs.insert(pos, column_reg(program, pos)?);
+        }
+    }
+    let bound = bind_index_expr(index_expr, table, |column| {
+        Expr::Register(match column {
+            Some(pos) if !table.columns[pos].is_rowid_alias => column_regs[&pos],
+            _ => rowid_reg,
+        })
+    })?;
+    translate_expr(program, None, &bound, dest_reg, resolver)?;
+    Ok(())
+}
+
 fn create_idx_stmt_to_sql(
     tbl_name: &str,
     idx_name: &str,
     unique_if_not_exists: (bool, bool),
-    cols: &[((usize, &Column), SortOrder)],
+    cols: &[IndexColumn],
+    where_clause: Option<&Expr>,
 ) -> String {
     let mut sql = String::with_capacity(128);
     sql.push_str("CREATE ");
@@ -299,20 +659,25 @@ fn create_idx_stmt_to_sql(
     sql.push_str(" ON ");
     sql.push_str(tbl_name);
     sql.push_str(" (");
-    for (i, (col, order)) in cols.iter().enumerate() {
+    for (i, col) in cols.iter().enumerate() {
         if i > 0 {
             sql.push_str(", ");
         }
-        sql.push_str(col.1.name.as_ref().unwrap());
-        if *order == SortOrder::Desc {
+        sql.push_str(&col.name);
+        if col.order == SortOrder::Desc {
             sql.push_str(" DESC");
         }
     }
     sql.push(')');
+    if let Some(where_clause) = where_clause {
+        sql.push_str(" WHERE ");
+        sql.push_str(&where_clause.to_string());
+    }
     sql
 }
 
 pub fn translate_drop_index(
+    database_id: usize,
     idx_name: &str,
     if_exists: bool,
     schema: &Schema,
@@ -368,7 +733,12 @@ pub fn translate_drop_index(
         }
     }
 
-    let cdc_table = prepare_cdc_if_necessary(&mut program, schema, SQLITE_TABLEID)?;
+    // changes to the temp database are not captured
+    let cdc_table = if database_id == 0 {
+        prepare_cdc_if_necessary(&mut program, schema, SQLITE_TABLEID)?
+    } else {
+        None
+    };
 
     // According to sqlite should emit Null instruction
     // but why?
@@ -392,7 +762,7 @@ pub fn translate_drop_index(
     program.emit_insn(Insn::OpenWrite {
         cursor_id: sqlite_schema_cursor_id,
         root_page: RegisterOrLiteral::Literal(sqlite_table.root_page),
-        db: 0,
+        db: database_id,
     });
 
     let loop_start_label = program.allocate_label();
@@ -480,7 +850,7 @@ pub fn translate_drop_index(
     program.resolve_label(loop_end_label, program.offset());
 
     program.emit_insn(Insn::SetCookie {
-        db: 0,
+        db: database_id,
         cookie: Cookie::SchemaVersion,
         value: schema.schema_version as i32 + 1,
         p5: 0,
@@ -490,14 +860,14 @@ pub fn translate_drop_index(
     program.emit_insn(Insn::Destroy {
         root: maybe_index.unwrap().root_page,
         former_root_reg: 0,
-        is_temp: 0,
+        is_temp: database_id,
     });
 
     // Remove from the Schema any mention of the index
     if let Some(idx) = maybe_index {
         program.emit_insn(Insn::DropIndex {
             index: idx.clone(),
-            db: 0,
+            db: database_id,
         });
     }
 
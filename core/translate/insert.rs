@@ -1,18 +1,24 @@
 use std::sync::Arc;
 use turso_parser::ast::{
-    self, Expr, InsertBody, OneSelect, QualifiedName, ResolveType, ResultColumn, Upsert, UpsertDo,
-    With,
+    self, Expr, InsertBody, OneSelect, QualifiedName, ResolveType, ResultColumn, TriggerEvent,
+    TriggerTime, Upsert, UpsertDo, With,
 };
 
 use crate::error::{SQLITE_CONSTRAINT_NOTNULL, SQLITE_CONSTRAINT_PRIMARYKEY};
-use crate::schema::{self, Table};
+use crate::schema::{self, BTreeTable, Table};
 use crate::translate::emitter::{
     emit_cdc_insns, emit_cdc_patch_record, prepare_cdc_if_necessary, OperationMode,
 };
 use crate::translate::expr::{
     emit_returning_results, process_returning_clause, ReturningValueRegisters,
 };
+use crate::translate::fkeys::{emit_fk_checks, has_foreign_keys};
+use crate::translate::generated::emit_generated_columns;
+use crate::translate::index::{emit_index_expr, emit_partial_index_check};
 use crate::translate::planner::ROWID;
+use crate::translate::trigger::{
+    fire_triggers, get_relevant_triggers, translate_insert_into_view, TriggerRow, TriggerRows,
+};
 use crate::translate::upsert::{
     collect_set_clauses_for_upsert, emit_upsert, upsert_matches_index, upsert_matches_pk,
 };
@@ -32,8 +38,9 @@ use crate::{Result, SymbolTable, VirtualTable};
 use super::emitter::Resolver;
 use super::expr::{translate_expr, translate_expr_no_constant_opt, NoConstantOptReason};
 use super::optimizer::rewrite_expr;
-use super::plan::QueryDestination;
+use super::plan::{QueryDestination, TableReferences};
 use super::select::translate_select;
+use super::subquery::plan_subqueries_from_expr;
 
 struct TempTableCtx {
     cursor_id: usize,
@@ -68,6 +75,10 @@ pub fn translate_insert(
         crate::bail_parse_error!("ON CONFLICT clause is not supported");
     }
 
+    let database_id = connection.resolve_database_id(&tbl_name)?;
+    let database_schema = connection.database_schema(database_id)?;
+    let schema = database_schema.as_deref().unwrap_or(schema);
+
     if schema.table_has_indexes(&tbl_name.name.to_string()) && !schema.indexes_enabled() {
         // Let's disable altering a table with indices altogether instead of checking column by
         // column to be extra safe.
@@ -84,7 +95,17 @@ pub fn translate_insert(
 
     let table = match schema.get_table(table_name.as_str()) {
         Some(table) => table,
-        None => crate::bail_parse_error!("no such table: {}", table_name),
+        None => {
+            if let Some(view) = schema.get_view(table_name.as_str()) {
+                if !returning.is_empty() {
+                    crate::bail_parse_error!("RETURNING is not supported for views");
+                }
+                return translate_insert_into_view(
+                    schema, view, columns, body, syms, program, connection,
+                );
+            }
+            crate::bail_parse_error!("no such table: {}", table_name)
+        }
     };
 
     // Check if this is a materialized view
@@ -109,9 +130,6 @@ pub fn translate_insert(
     let Some(btree_table) = table.btree() else {
         crate::bail_parse_error!("no such table: {}", table_name);
     };
-    if !btree_table.has_rowid {
-        crate::bail_parse_error!("INSERT into WITHOUT ROWID table is not supported");
-    }
 
     let root_page = btree_table.root_page;
 
@@ -147,6 +165,15 @@ pub fn translate_insert(
                             }
                             _ => {}
                         }
+                        plan_subqueries_from_expr(
+                            &mut program,
+                            expr,
+                            &mut TableReferences::new(vec![], vec![]),
+                            schema,
+                            syms,
+                            connection,
+                            &mut param_idx,
+                        )?;
                         rewrite_expr(expr, &mut param_idx)?;
                     }
                     values = values_expr.pop();
@@ -162,7 +189,30 @@ pub fn translate_insert(
     let loop_start_label = program.allocate_label();
     let row_done_label = program.allocate_label();
 
-    let cdc_table = prepare_cdc_if_necessary(&mut program, schema, table.get_name())?;
+    let before_triggers = get_relevant_triggers(
+        &program,
+        schema,
+        table_name.as_str(),
+        TriggerTime::Before,
+        &TriggerEvent::Insert,
+        &[],
+    );
+    let after_triggers = get_relevant_triggers(
+        &program,
+        schema,
+        table_name.as_str(),
+        TriggerTime::After,
+        &TriggerEvent::Insert,
+        &[],
+    );
+    let has_triggers = !before_triggers.is_empty() || !after_triggers.is_empty();
+
+    // Changes to the tables of the temp database are not captured
+    let cdc_table = if database_id == 0 {
+        prepare_cdc_if_necessary(&mut program, schema, table.get_name())?
+    } else {
+        None
+    };
 
     // Process RETURNING clause using shared module
     let (mut result_columns, _) = process_returning_clause(
@@ -227,9 +277,12 @@ pub fn translate_insert(
                  ** of the tables being read by the SELECT statement.  Also use a
                  ** temp table in the case of row triggers.
                  */
-                if program.is_table_open(&table) {
-                    let temp_cursor_id =
-                        program.alloc_cursor_id(CursorType::BTreeTable(btree_table.clone()));
+                if program.is_table_open(&table) || has_triggers {
+                    // The temp table stores the values in the order they are produced by the
+                    // SELECT, which is not necessarily the column order of the target table.
+                    let temp_cursor_id = program.alloc_cursor_id(CursorType::BTreeTable(
+                        ephemeral_scratch_table(result.num_result_cols),
+                    ));
                     temp_table_ctx = Some(TempTableCtx {
                         cursor_id: temp_cursor_id,
                         loop_start_label: program.allocate_label(),
@@ -258,7 +311,7 @@ pub fn translate_insert(
                         btree_table
                             .columns
                             .iter()
-                            .filter(|col| !col.hidden)
+                            .filter(|col| !col.hidden && !col.is_generated())
                             .map(|col| col.affinity().aff_mask())
                             .collect::<String>()
                     } else {
@@ -310,13 +363,13 @@ pub fn translate_insert(
                     program.emit_insn(Insn::OpenWrite {
                         cursor_id,
                         root_page: RegisterOrLiteral::Literal(root_page),
-                        db: 0,
+                        db: database_id,
                     });
                 } else {
                     program.emit_insn(Insn::OpenWrite {
                         cursor_id,
                         root_page: RegisterOrLiteral::Literal(root_page),
-                        db: 0,
+                        db: database_id,
                     });
 
                     // Main loop
@@ -341,14 +394,19 @@ pub fn translate_insert(
 
     // allocate cursor id's for each btree index cursor we'll need to populate the indexes
     // (idx name, root_page, idx cursor id)
-    let idx_cursors = schema
+    // The primary key index of a WITHOUT ROWID table is the table itself.
+    let indexes = schema
         .get_indices(table_name.as_str())
+        .iter()
+        .filter(|idx| !idx.is_without_rowid_primary_key(&btree_table))
+        .collect::<Vec<_>>();
+    let idx_cursors = indexes
         .iter()
         .map(|idx| {
             (
                 &idx.name,
                 idx.root_page,
-                program.alloc_cursor_id(CursorType::BTreeIndex(idx.clone())),
+                program.alloc_cursor_id(CursorType::BTreeIndex((*idx).clone())),
             )
         })
         .collect::<Vec<(&String, usize, usize)>>();
@@ -368,7 +426,7 @@ pub fn translate_insert(
         program.emit_insn(Insn::OpenWrite {
             cursor_id,
             root_page: RegisterOrLiteral::Literal(root_page),
-            db: 0,
+            db: database_id,
         });
 
         translate_rows_single(&mut program, &values.unwrap(), &insertion, &resolver)?;
@@ -379,12 +437,61 @@ pub fn translate_insert(
         program.emit_insn(Insn::OpenWrite {
             cursor_id: idx_cursor.2,
             root_page: idx_cursor.1.into(),
-            db: 0,
+            db: database_id,
         });
     }
 
     // Common record insertion logic for both single and multiple rows
     let has_user_provided_rowid = insertion.key.is_provided_by_user();
+
+    if !before_triggers.is_empty() {
+        // The rowid is not allocated yet when BEFORE triggers run, so NEW.rowid is -1
+        // unless the statement provides one.
+        let new_rowid_reg = program.alloc_register();
+        if has_user_provided_rowid {
+            let rowid_done_label = program.allocate_label();
+            program.emit_insn(Insn::Copy {
+                src_reg: insertion.key_register(),
+                dst_reg: new_rowid_reg,
+                extra_amount: 0,
+            });
+            program.emit_insn(Insn::NotNull {
+                reg: new_rowid_reg,
+                target_pc: rowid_done_label,
+            });
+            program.emit_int(-1, new_rowid_reg);
+            program.preassign_label_to_next_insn(rowid_done_label);
+        } else {
+            program.emit_int(-1, new_rowid_reg);
+        }
+        if btree_table.has_generated_columns() {
+            emit_generated_columns(
+                &mut program,
+                &resolver,
+                &btree_table,
+                insertion.first_col_register(),
+                new_rowid_reg,
+            )?;
+        }
+        let new_row = TriggerRow::from_registers(
+            new_rowid_reg,
+            insertion.first_col_register(),
+            table.columns(),
+        );
+        fire_triggers(
+            &mut program,
+            schema,
+            syms,
+            connection,
+            &before_triggers,
+            &TriggerRows {
+                columns: table.columns(),
+                old: None,
+                new: Some(&new_row),
+            },
+            row_done_label,
+        )?;
+    }
     let check_rowid_is_integer_label = if has_user_provided_rowid {
         Some(program.allocate_label())
     } else {
@@ -397,12 +504,17 @@ pub fn translate_insert(
         });
     }
 
-    // Create new rowid if a) not provided by user or b) provided by user but is NULL
-    program.emit_insn(Insn::NewRowid {
-        cursor: cursor_id,
-        rowid_reg: insertion.key_register(),
-        prev_largest_reg: 0,
-    });
+    if btree_table.has_rowid {
+        // Create new rowid if a) not provided by user or b) provided by user but is NULL
+        program.emit_insn(Insn::NewRowid {
+            cursor: cursor_id,
+            rowid_reg: insertion.key_register(),
+            prev_largest_reg: 0,
+        });
+    } else {
+        // The rows of a WITHOUT ROWID table have no rowid
+        program.emit_null(insertion.key_register(), None);
+    }
 
     if let Some(must_be_int_label) = check_rowid_is_integer_label {
         program.resolve_label(must_be_int_label, program.offset());
@@ -412,6 +524,16 @@ pub fn translate_insert(
         });
     }
 
+    if btree_table.has_generated_columns() {
+        emit_generated_columns(
+            &mut program,
+            &resolver,
+            &btree_table,
+            insertion.first_col_register(),
+            insertion.key_register(),
+        )?;
+    }
+
     let emit_halt_with_constraint = |program: &mut ProgramBuilder, col_name: &str| {
         let mut description = String::with_capacity(table_name.as_str().len() + col_name.len() + 2);
         description.push_str(table_name.as_str());
@@ -454,6 +576,7 @@ pub fn translate_insert(
                             emit_upsert(
                                 &mut program,
                                 schema,
+                                database_id,
                                 &table,
                                 &insertion,
                                 cursor_id,
@@ -465,6 +588,7 @@ pub fn translate_insert(
                                 &mut result_columns,
                                 cdc_table.as_ref().map(|c| c.0),
                                 row_done_label,
+                                connection,
                             )?;
                         }
                     }
@@ -476,6 +600,90 @@ pub fn translate_insert(
         program.preassign_label_to_next_insn(make_record_label);
     }
 
+    // A WITHOUT ROWID table is keyed by its primary key, which must not collide with another row
+    if !btree_table.has_rowid {
+        let make_record_label = program.allocate_label();
+        let pk_positions = btree_table.primary_key_column_positions();
+        let pk_reg = program.alloc_registers(pk_positions.len());
+        for (i, pos_in_table) in pk_positions.iter().enumerate() {
+            program.emit_insn(Insn::Copy {
+                src_reg: insertion.col_mappings[*pos_in_table].register,
+                dst_reg: pk_reg + i,
+                extra_amount: 0,
+            });
+        }
+        program.emit_insn(Insn::NoConflict {
+            cursor_id,
+            target_pc: make_record_label,
+            record_reg: pk_reg,
+            num_regs: pk_positions.len(),
+        });
+        let primary_key_index = schema
+            .get_indices(table_name.as_str())
+            .iter()
+            .find(|idx| idx.is_without_rowid_primary_key(&btree_table))
+            .expect("WITHOUT ROWID table must have a primary key index");
+
+        // again, emit halt for every case *except* when upsert handles the conflict
+        'emit_halt: {
+            if let Some(ref mut upsert) = upsert_opt.as_mut() {
+                if upsert_matches_index(upsert, primary_key_index, &table) {
+                    match upsert.do_clause {
+                        UpsertDo::Nothing => {
+                            program.emit_insn(Insn::Goto {
+                                target_pc: row_done_label,
+                            });
+                        }
+                        UpsertDo::Set {
+                            ref mut sets,
+                            ref mut where_clause,
+                        } => {
+                            let mut rewritten_sets = collect_set_clauses_for_upsert(&table, sets)?;
+
+                            emit_upsert(
+                                &mut program,
+                                schema,
+                                database_id,
+                                &table,
+                                &insertion,
+                                cursor_id,
+                                pk_reg,
+                                &mut rewritten_sets,
+                                where_clause,
+                                &resolver,
+                                &idx_cursors,
+                                &mut result_columns,
+                                cdc_table.as_ref().map(|c| c.0),
+                                row_done_label,
+                                connection,
+                            )?;
+                        }
+                    }
+                    break 'emit_halt;
+                }
+            }
+            program.emit_insn(Insn::Halt {
+                err_code: SQLITE_CONSTRAINT_PRIMARYKEY,
+                description: pk_positions
+                    .iter()
+                    .map(|pos| {
+                        format!(
+                            "{}.{}",
+                            table_name.as_str(),
+                            insertion.col_mappings[*pos]
+                                .column
+                                .name
+                                .as_deref()
+                                .unwrap_or_default()
+                        )
+                    })
+                    .collect::<Vec<_>>()
+                    .join(", "),
+            });
+        }
+        program.preassign_label_to_next_insn(make_record_label);
+    }
+
     match table.btree() {
         Some(t) if t.is_strict => {
             program.emit_insn(Insn::TypeCheck {
@@ -488,11 +696,7 @@ pub fn translate_insert(
         _ => (),
     }
 
-    for index in schema.get_indices(table_name.as_str()) {
-        let column_mappings = index
-            .columns
-            .iter()
-            .map(|idx_col| insertion.get_col_mapping_by_name(&idx_col.name));
+    for index in indexes.iter() {
         // find which cursor we opened earlier for this index
         let idx_cursor_id = idx_cursors
             .iter()
@@ -500,14 +704,39 @@ pub fn translate_insert(
             .map(|(_, _, c_id)| *c_id)
             .expect("no cursor found for index");
 
+        // rows that do not satisfy the WHERE clause of a partial index are not added to it
+        let skip_index_label = program.allocate_label();
+        emit_partial_index_check(
+            &mut program,
+            &resolver,
+            index,
+            &btree_table,
+            |_, pos| Ok(insertion.col_mappings[pos].register),
+            insertion.key_register(),
+            skip_index_label,
+        )?;
+
         let num_cols = index.columns.len();
         // allocate scratch registers for the index columns plus rowid
         let idx_start_reg = program.alloc_registers(num_cols + 1);
 
         // copy each index column from the table's column registers into these scratch regs
-        for (i, column_mapping) in column_mappings.clone().enumerate() {
+        for (i, idx_col) in index.columns.iter().enumerate() {
+            // indexed expressions are evaluated over the table's column registers
+            if let Some(expr) = &idx_col.expr {
+                emit_index_expr(
+                    &mut program,
+                    &resolver,
+                    &btree_table,
+                    expr,
+                    |_, pos| Ok(insertion.col_mappings[pos].register),
+                    insertion.key_register(),
+                    idx_start_reg + i,
+                )?;
+                continue;
+            }
             // copy from the table's column register over to the index's scratch register
-            let Some(col_mapping) = column_mapping else {
+            let Some(col_mapping) = insertion.get_col_mapping_by_name(&idx_col.name) else {
                 return Err(crate::LimboError::PlanningError(
                     "Column not found in INSERT".to_string(),
                 ));
@@ -518,17 +747,19 @@ pub fn translate_insert(
                 extra_amount: 0,
             });
         }
-        // last register is the rowid
-        program.emit_insn(Insn::Copy {
-            src_reg: insertion.key_register(),
-            dst_reg: idx_start_reg + num_cols,
-            extra_amount: 0,
-        });
+        // last register is the rowid, unless the index stores the primary key of a WITHOUT ROWID table
+        if index.has_rowid {
+            program.emit_insn(Insn::Copy {
+                src_reg: insertion.key_register(),
+                dst_reg: idx_start_reg + num_cols,
+                extra_amount: 0,
+            });
+        }
 
         let record_reg = program.alloc_register();
         program.emit_insn(Insn::MakeRecord {
             start_reg: idx_start_reg,
-            count: num_cols + 1,
+            count: num_cols + index.has_rowid as usize,
             dest_reg: record_reg,
             index_name: Some(index.name.clone()),
             affinity_str: None,
@@ -540,9 +771,9 @@ pub fn translate_insert(
                 cursor_id: idx_cursor_id,
                 target_pc: label_idx_insert,
                 record_reg: idx_start_reg,
-                num_regs: num_cols,
+                num_regs: index.key_columns().len(),
             });
-            let column_names = index.columns.iter().enumerate().fold(
+            let column_names = index.key_columns().iter().enumerate().fold(
                 String::with_capacity(50),
                 |mut accum, (idx, column)| {
                     if idx > 0 {
@@ -571,14 +802,34 @@ pub fn translate_insert(
                             } => {
                                 let mut rewritten_sets =
                                     collect_set_clauses_for_upsert(&table, sets)?;
-                                let conflict_rowid_reg = program.alloc_register();
-                                program.emit_insn(Insn::IdxRowId {
-                                    cursor_id: idx_cursor_id,
-                                    dest: conflict_rowid_reg,
-                                });
+                                let conflict_rowid_reg = if index.has_rowid {
+                                    let conflict_rowid_reg = program.alloc_register();
+                                    program.emit_insn(Insn::IdxRowId {
+                                        cursor_id: idx_cursor_id,
+                                        dest: conflict_rowid_reg,
+                                    });
+                                    conflict_rowid_reg
+                                } else {
+                                    // The conflicting entry holds the primary key of its row
+                                    let pk_positions = btree_table.primary_key_column_positions();
+                                    let conflict_pk_reg =
+                                        program.alloc_registers(pk_positions.len());
+                                    for (i, pos_in_table) in pk_positions.iter().enumerate() {
+                                        let pos_in_index = index
+                                            .column_table_pos_to_index_pos(*pos_in_table)
+                                            .expect("indexes of a WITHOUT ROWID table contain its primary key");
+                                        program.emit_column_or_rowid(
+                                            idx_cursor_id,
+                                            pos_in_index,
+                                            conflict_pk_reg + i,
+                                        );
+                                    }
+                                    conflict_pk_reg
+                                };
                                 emit_upsert(
                                     &mut program,
                                     schema,
+                                    database_id,
                                     &table,
                                     &insertion,
                                     cursor_id,
@@ -590,6 +841,7 @@ pub fn translate_insert(
                                     &mut result_columns,
                                     cdc_table.as_ref().map(|c| c.0),
                                     row_done_label,
+                                    connection,
                                 )?;
                             }
                         }
@@ -609,17 +861,18 @@ pub fn translate_insert(
             cursor_id: idx_cursor_id,
             record_reg,
             unpacked_start: Some(idx_start_reg), // TODO: enable optimization
-            unpacked_count: Some((num_cols + 1) as u16),
+            unpacked_count: Some((num_cols + index.has_rowid as usize) as u16),
             // TODO: figure out how to determine whether or not we need to seek prior to insert.
             flags: IdxInsertFlags::new().nchange(true),
         });
+        program.preassign_label_to_next_insn(skip_index_label);
     }
 
-    for column_mapping in insertion
-        .col_mappings
-        .iter()
-        .filter(|column_mapping| column_mapping.column.notnull)
-    {
+    // The primary key columns of a WITHOUT ROWID table are implicitly NOT NULL
+    for column_mapping in insertion.col_mappings.iter().filter(|column_mapping| {
+        column_mapping.column.notnull
+            || (!btree_table.has_rowid && column_mapping.column.primary_key)
+    }) {
         // if this is rowid alias - turso-db will emit NULL as a column value and always use rowid for the row as a column value
         if column_mapping.column.is_rowid_alias {
             continue;
@@ -651,27 +904,70 @@ pub fn translate_insert(
             },
         });
     }
+    if has_foreign_keys(schema, connection, &btree_table) {
+        let new_row = TriggerRow::from_registers(
+            insertion.key_register(),
+            insertion.first_col_register(),
+            table.columns(),
+        );
+        emit_fk_checks(
+            &mut program,
+            schema,
+            database_id,
+            connection,
+            &btree_table,
+            None,
+            Some(&new_row),
+            &[],
+        )?;
+    }
+
     // Create and insert the record
-    let affinity_str = insertion
-        .col_mappings
+    // The records of a WITHOUT ROWID table hold the primary key columns first, and VIRTUAL
+    // generated columns are not stored
+    let record_column_positions = btree_table.record_column_positions();
+    let record_start_reg = if btree_table.stores_columns_in_table_order() {
+        insertion.first_col_register()
+    } else {
+        let record_start_reg = program.alloc_registers(record_column_positions.len());
+        for (i, pos_in_table) in record_column_positions.iter().enumerate() {
+            program.emit_insn(Insn::Copy {
+                src_reg: insertion.col_mappings[*pos_in_table].register,
+                dst_reg: record_start_reg + i,
+                extra_amount: 0,
+            });
+        }
+        record_start_reg
+    };
+    let affinity_str = record_column_positions
         .iter()
-        .map(|col_mapping| col_mapping.column.affinity().aff_mask())
+        .map(|pos| insertion.col_mappings[*pos].column.affinity().aff_mask())
         .collect::<String>();
 
     program.emit_insn(Insn::MakeRecord {
-        start_reg: insertion.first_col_register(),
-        count: insertion.col_mappings.len(),
+        start_reg: record_start_reg,
+        count: record_column_positions.len(),
         dest_reg: insertion.record_register(),
         index_name: None,
         affinity_str: Some(affinity_str),
     });
-    program.emit_insn(Insn::Insert {
-        cursor: cursor_id,
-        key_reg: insertion.key_register(),
-        record_reg: insertion.record_register(),
-        flag: InsertFlags::new(),
-        table_name: table_name.to_string(),
-    });
+    if btree_table.has_rowid {
+        program.emit_insn(Insn::Insert {
+            cursor: cursor_id,
+            key_reg: insertion.key_register(),
+            record_reg: insertion.record_register(),
+            flag: InsertFlags::new(),
+            table_name: table_name.to_string(),
+        });
+    } else {
+        program.emit_insn(Insn::IdxInsert {
+            cursor_id,
+            record_reg: insertion.record_register(),
+            unpacked_start: Some(record_start_reg),
+            unpacked_count: Some(insertion.col_mappings.len() as u16),
+            flags: IdxInsertFlags::new().nchange(true),
+        });
+    }
 
     // Emit update in the CDC table if necessary (after the INSERT updated the table)
     if let Some((cdc_cursor_id, _)) = &cdc_table {
@@ -711,6 +1007,27 @@ pub fn translate_insert(
         emit_returning_results(&mut program, &result_columns, &value_registers)?;
     }
 
+    if !after_triggers.is_empty() {
+        let new_row = TriggerRow::from_registers(
+            insertion.key_register(),
+            insertion.first_col_register(),
+            table.columns(),
+        );
+        fire_triggers(
+            &mut program,
+            schema,
+            syms,
+            connection,
+            &after_triggers,
+            &TriggerRows {
+                columns: table.columns(),
+                old: None,
+                new: Some(&new_row),
+            },
+            row_done_label,
+        )?;
+    }
+
     if inserting_multiple_rows {
         if let Some(temp_table_ctx) = temp_table_ctx {
             program.resolve_label(row_done_label, program.offset());
@@ -751,8 +1068,37 @@ pub const ROWID_COLUMN: &Column = &Column {
     unique: false,
     collation: None,
     hidden: false,
+    generated: None,
 };
 
+/// A table definition for an ephemeral table that stores `num_columns` untyped values per row.
+pub fn ephemeral_scratch_table(num_columns: usize) -> Arc<BTreeTable> {
+    Arc::new(BTreeTable {
+        root_page: 0, // Not relevant for ephemeral table definition
+        name: "ephemeral_scratch".to_string(),
+        has_rowid: true,
+        primary_key_columns: vec![],
+        columns: (0..num_columns)
+            .map(|_| Column {
+                name: None,
+                ty: schema::Type::Null,
+                ty_str: String::new(),
+                primary_key: false,
+                is_rowid_alias: false,
+                notnull: false,
+                default: None,
+                unique: false,
+                collation: None,
+                hidden: false,
+                generated: None,
+            })
+            .collect(),
+        is_strict: false,
+        unique_sets: vec![],
+        foreign_keys: vec![],
+    })
+}
+
 /// Represents how a table should be populated during an INSERT.
 #[derive(Debug)]
 pub struct Insertion<'a> {
@@ -892,18 +1238,22 @@ fn build_insertion<'a>(
 
     if columns.is_empty() {
         // Case 1: No columns specified - map values to columns in order
-        if num_values != table_columns.iter().filter(|c| !c.hidden).count() {
+        // Hidden and generated columns are not taken into account.
+        let num_columns = table_columns
+            .iter()
+            .filter(|c| !c.hidden && !c.is_generated())
+            .count();
+        if num_values != num_columns {
             crate::bail_parse_error!(
                 "table {} has {} columns but {} values were supplied",
                 &table.get_name(),
-                table_columns.len(),
+                num_columns,
                 num_values
             );
         }
         let mut value_idx = 0;
         for (i, col) in table_columns.iter().enumerate() {
-            if col.hidden {
-                // Hidden columns are not taken into account.
+            if col.hidden || col.is_generated() {
                 continue;
             }
             if col.is_rowid_alias {
@@ -923,6 +1273,12 @@ fn build_insertion<'a>(
         for (value_index, column_name) in columns.iter().enumerate() {
             let column_name = normalize_ident(column_name.as_str());
             if let Some((idx_in_table, col_in_table)) = table.get_column_by_name(&column_name) {
+                if col_in_table.is_generated() {
+                    crate::bail_parse_error!(
+                        "cannot INSERT into generated column \"{}\"",
+                        column_name
+                    );
+                }
                 // Named column
                 if col_in_table.is_rowid_alias {
                     insertion_key = InsertionKey::RowidAlias(ColMapping {
@@ -933,7 +1289,7 @@ fn build_insertion<'a>(
                 } else {
                     column_mappings[idx_in_table].value_index = Some(value_index);
                 }
-            } else if column_name == ROWID {
+            } else if column_name == ROWID && table.btree().is_none_or(|btree| btree.has_rowid) {
                 // Explicit use of the 'rowid' keyword
                 if let Some(col_in_table) = table.columns().iter().find(|c| c.is_rowid_alias) {
                     insertion_key = InsertionKey::RowidAlias(ColMapping {
@@ -1101,6 +1457,12 @@ fn translate_column(
             dest: column_register,
             dest_end: None,
         });
+    } else if column.is_generated() {
+        // Generated columns are computed once the other columns of the row are known.
+        program.emit_insn(Insn::Null {
+            dest: column_register,
+            dest_end: None,
+        });
     } else if let Some(default_expr) = column.default.as_ref() {
         translate_expr(program, None, default_expr, column_register, resolver)?;
     } else {
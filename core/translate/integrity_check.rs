@@ -1,32 +1,361 @@
+//! PRAGMA integrity_check and PRAGMA quick_check, see
+//! <https://www.sqlite.org/pragma.html#pragma_integrity_check>.
+//!
+//! The structure of every b-tree, the freelist and the use of every page of the database file are
+//! checked by [Insn::IntegrityCk]. The rows of every table are then scanned to check their NOT
+//! NULL constraints and, unless this is a quick check, that every index has exactly one entry per
+//! row of its table.
+
+use std::sync::Arc;
+
+use turso_parser::ast::{self, Literal};
+
 use crate::{
-    schema::Schema,
-    vdbe::{builder::ProgramBuilder, insn::Insn},
+    bail_parse_error,
+    schema::{BTreeTable, Index, Schema},
+    translate::{
+        emitter::Resolver,
+        generated::emit_table_column,
+        index::{emit_index_expr, emit_partial_index_check},
+    },
+    util::normalize_ident,
+    vdbe::{
+        builder::{CursorType, ProgramBuilder},
+        insn::{CmpInsFlags, Insn},
+        BranchOffset,
+    },
+    SymbolTable,
 };
 
-/// Maximum number of errors to report with integrity check. If we exceed this number we will short
-/// circuit the procedure and return early to not waste time.
-const MAX_INTEGRITY_CHECK_ERRORS: usize = 10;
+/// Maximum number of errors to report when the pragma is not given a limit. Once this many errors
+/// have been reported, the check stops early to not waste time.
+const DEFAULT_MAX_INTEGRITY_CHECK_ERRORS: i64 = 100;
 
 pub fn translate_integrity_check(
     schema: &Schema,
+    syms: &SymbolTable,
     program: &mut ProgramBuilder,
+    quick: bool,
+    value: Option<ast::Expr>,
 ) -> crate::Result<()> {
-    let mut root_pages = Vec::with_capacity(schema.tables.len() + schema.indexes.len());
+    // The argument is either the maximum number of errors to report or the table to check
+    let (max_errors, table_name) = match value {
+        None => (DEFAULT_MAX_INTEGRITY_CHECK_ERRORS, None),
+        Some(ast::Expr::Literal(Literal::Numeric(n))) => match n.parse::<i64>() {
+            Ok(n) if n > 0 => (n, None),
+            _ => (DEFAULT_MAX_INTEGRITY_CHECK_ERRORS, None),
+        },
+        Some(ast::Expr::Name(name)) => (
+            DEFAULT_MAX_INTEGRITY_CHECK_ERRORS,
+            Some(normalize_ident(name.as_str())),
+        ),
+        Some(value) => bail_parse_error!("Invalid value for PRAGMA integrity_check: {value:?}"),
+    };
+    let tables = match &table_name {
+        Some(name) => match schema.get_btree_table(name) {
+            Some(table) => vec![table],
+            None => bail_parse_error!("no such table: {}", name),
+        },
+        None => {
+            let mut tables = schema
+                .tables
+                .values()
+                .filter_map(|table| table.btree())
+                .collect::<Vec<_>>();
+            tables.sort_by(|a, b| a.name.cmp(&b.name));
+            tables
+        }
+    };
+
     // Collect root pages to run integrity check on
-    for table in schema.tables.values() {
-        if let crate::schema::Table::BTree(table) = table.as_ref() {
-            root_pages.push(table.root_page);
-        };
+    let mut root_pages = Vec::with_capacity(schema.tables.len() + schema.indexes.len());
+    for table in &tables {
+        root_pages.push(table.root_page);
+        for index in schema.get_indices(&table.name) {
+            // The primary key index of a WITHOUT ROWID table is the table b-tree itself.
+            if !root_pages.contains(&index.root_page) {
+                root_pages.push(index.root_page);
+            }
+        }
     }
-    let message_register = program.alloc_register();
+
+    let max_errors_reg = program.alloc_register();
+    program.emit_int(max_errors, max_errors_reg);
+    let errors_left_reg = program.alloc_register();
+    program.emit_int(max_errors, errors_left_reg);
+    let message_reg = program.alloc_register();
+    let done_label = program.allocate_label();
+
     program.emit_insn(Insn::IntegrityCk {
-        max_errors: MAX_INTEGRITY_CHECK_ERRORS,
+        errors_left_register: errors_left_reg,
         roots: root_pages,
-        message_register,
+        message_register: message_reg,
+        // Only a check of every b-tree can tell whether a page is used
+        check_page_usage: table_name.is_none(),
     });
-    program.emit_insn(Insn::ResultRow {
-        start_reg: message_register,
-        count: 1,
+    let btree_ok_label = program.allocate_label();
+    program.emit_insn(Insn::IsNull {
+        reg: message_reg,
+        target_pc: btree_ok_label,
     });
+    let prefix_reg = program.emit_string8_new_reg("*** in database main ***\n".to_string());
+    program.emit_insn(Insn::Concat {
+        lhs: prefix_reg,
+        rhs: message_reg,
+        dest: message_reg,
+    });
+    program.emit_result_row(message_reg, 1);
+    program.emit_insn(Insn::IfNot {
+        reg: errors_left_reg,
+        target_pc: done_label,
+        jump_if_null: false,
+    });
+    program.preassign_label_to_next_insn(btree_ok_label);
+
+    let resolver = Resolver::new(schema, syms);
+    let checker = RowChecker {
+        message_reg,
+        errors_left_reg,
+        done_label,
+    };
+    for table in &tables {
+        let indexes = if quick {
+            vec![]
+        } else {
+            schema
+                .get_indices(&table.name)
+                .iter()
+                .filter(|index| !index.is_without_rowid_primary_key(table))
+                .cloned()
+                .collect()
+        };
+        checker.emit_table_check(program, &resolver, table, &indexes)?;
+    }
+
+    // Nothing has been reported if no error has been counted
+    program.emit_insn(Insn::Ne {
+        lhs: errors_left_reg,
+        rhs: max_errors_reg,
+        target_pc: done_label,
+        flags: CmpInsFlags::default(),
+        collation: None,
+    });
+    program.emit_string8("ok".to_string(), message_reg);
+    program.emit_result_row(message_reg, 1);
+    program.preassign_label_to_next_insn(done_label);
     Ok(())
 }
+
+/// Emits the checks of the rows of the tables. Every problem found is reported as a row of its
+/// own, until the maximum number of errors has been reported.
+struct RowChecker {
+    message_reg: usize,
+    errors_left_reg: usize,
+    done_label: BranchOffset,
+}
+
+impl RowChecker {
+    fn emit_table_check(
+        &self,
+        program: &mut ProgramBuilder,
+        resolver: &Resolver,
+        table: &Arc<BTreeTable>,
+        indexes: &[Arc<Index>],
+    ) -> crate::Result<()> {
+        let has_not_null_columns = table.columns.iter().any(|column| column.notnull);
+        if !has_not_null_columns && indexes.is_empty() {
+            return Ok(());
+        }
+
+        let table_cursor_id = program.alloc_cursor_id(CursorType::BTreeTable(table.clone()));
+        program.emit_insn(Insn::OpenRead {
+            cursor_id: table_cursor_id,
+            root_page: table.root_page,
+            db: 0,
+        });
+        let mut index_cursors = Vec::with_capacity(indexes.len());
+        for index in indexes {
+            let cursor_id = program.alloc_cursor_id(CursorType::BTreeIndex(index.clone()));
+            program.emit_insn(Insn::OpenRead {
+                cursor_id,
+                root_page: index.root_page,
+                db: 0,
+            });
+            // The number of entries the index should have
+            let entries_reg = program.alloc_register();
+            program.emit_int(0, entries_reg);
+            index_cursors.push((index, cursor_id, entries_reg));
+        }
+
+        // Rows are identified by their rowid, or by their position in WITHOUT ROWID tables
+        let row_number_reg = program.alloc_register();
+        program.emit_int(0, row_number_reg);
+        let rowid_reg = program.alloc_register();
+
+        let loop_start_label = program.allocate_label();
+        let loop_end_label = program.allocate_label();
+        program.emit_insn(Insn::Rewind {
+            cursor_id: table_cursor_id,
+            pc_if_empty: loop_end_label,
+        });
+        program.preassign_label_to_next_insn(loop_start_label);
+        program.emit_insn(Insn::AddImm {
+            register: row_number_reg,
+            value: 1,
+        });
+        program.emit_rowid_or_null(table_cursor_id, rowid_reg);
+        let row_reg = if table.has_rowid {
+            rowid_reg
+        } else {
+            row_number_reg
+        };
+
+        for (pos, column) in table.columns.iter().enumerate() {
+            if !column.notnull || column.is_rowid_alias {
+                continue;
+            }
+            let column_reg = program.alloc_register();
+            emit_table_column(program, resolver, table_cursor_id, pos, column_reg)?;
+            let not_null_label = program.allocate_label();
+            program.emit_insn(Insn::NotNull {
+                reg: column_reg,
+                target_pc: not_null_label,
+            });
+            program.emit_string8(
+                format!(
+                    "NULL value in {}.{}",
+                    table.name,
+                    column.name.as_deref().unwrap_or_default()
+                ),
+                self.message_reg,
+            );
+            self.emit_error(program);
+            program.preassign_label_to_next_insn(not_null_label);
+        }
+
+        let load_column = |program: &mut ProgramBuilder, pos: usize| {
+            let reg = program.alloc_register();
+            emit_table_column(program, resolver, table_cursor_id, pos, reg)?;
+            Ok(reg)
+        };
+        for (index, cursor_id, entries_reg) in &index_cursors {
+            // Build the key of the entry of the row the same way the index is populated
+            let not_indexed_label = program.allocate_label();
+            emit_partial_index_check(
+                program,
+                resolver,
+                index,
+                table,
+                load_column,
+                rowid_reg,
+                not_indexed_label,
+            )?;
+            program.emit_insn(Insn::AddImm {
+                register: *entries_reg,
+                value: 1,
+            });
+            let key_start_reg = program.alloc_registers(index.columns.len() + 1);
+            for (i, col) in index.columns.iter().enumerate() {
+                match &col.expr {
+                    Some(expr) => emit_index_expr(
+                        program,
+                        resolver,
+                        table,
+                        expr,
+                        load_column,
+                        rowid_reg,
+                        key_start_reg + i,
+                    )?,
+                    None => emit_table_column(
+                        program,
+                        resolver,
+                        table_cursor_id,
+                        col.pos_in_table,
+                        key_start_reg + i,
+                    )?,
+                }
+            }
+            program.emit_insn(Insn::Copy {
+                src_reg: rowid_reg,
+                dst_reg: key_start_reg + index.columns.len(),
+                extra_amount: 0,
+            });
+            program.emit_insn(Insn::Found {
+                cursor_id: *cursor_id,
+                target_pc: not_indexed_label,
+                record_reg: key_start_reg,
+                num_regs: index.columns.len() + index.has_rowid as usize,
+            });
+            let prefix_reg = program.emit_string8_new_reg("row ".to_string());
+            program.emit_insn(Insn::Concat {
+                lhs: prefix_reg,
+                rhs: row_reg,
+                dest: self.message_reg,
+            });
+            let suffix_reg =
+                program.emit_string8_new_reg(format!(" missing from index {}", index.name));
+            program.emit_insn(Insn::Concat {
+                lhs: self.message_reg,
+                rhs: suffix_reg,
+                dest: self.message_reg,
+            });
+            self.emit_error(program);
+            program.preassign_label_to_next_insn(not_indexed_label);
+        }
+
+        program.emit_insn(Insn::Next {
+            cursor_id: table_cursor_id,
+            pc_if_next: loop_start_label,
+        });
+        program.preassign_label_to_next_insn(loop_end_label);
+
+        // An index with more entries than its table has rows holds entries of no row
+        for (index, cursor_id, entries_reg) in &index_cursors {
+            let count_reg = program.alloc_register();
+            program.emit_insn(Insn::Count {
+                cursor_id: *cursor_id,
+                target_reg: count_reg,
+                exact: true,
+            });
+            let count_ok_label = program.allocate_label();
+            program.emit_insn(Insn::Eq {
+                lhs: count_reg,
+                rhs: *entries_reg,
+                target_pc: count_ok_label,
+                flags: CmpInsFlags::default(),
+                collation: None,
+            });
+            program.emit_string8(
+                format!("wrong # of entries in index {}", index.name),
+                self.message_reg,
+            );
+            self.emit_error(program);
+            program.preassign_label_to_next_insn(count_ok_label);
+        }
+
+        program.emit_insn(Insn::Close {
+            cursor_id: table_cursor_id,
+        });
+        for (_, cursor_id, _) in &index_cursors {
+            program.emit_insn(Insn::Close {
+                cursor_id: *cursor_id,
+            });
+        }
+        Ok(())
+    }
+
+    /// Reports the error in the message register and stops the check once the maximum number of
+    /// errors has been reported.
+    fn emit_error(&self, program: &mut ProgramBuilder) {
+        program.emit_result_row(self.message_reg, 1);
+        program.emit_insn(Insn::AddImm {
+            register: self.errors_left_reg,
+            value: -1,
+        });
+        program.emit_insn(Insn::IfNot {
+            reg: self.errors_left_reg,
+            target_pc: self.done_label,
+            jump_if_null: false,
+        });
+    }
+}
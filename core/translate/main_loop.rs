@@ -1,4 +1,4 @@
-use turso_parser::ast::{fmt::ToTokens, SortOrder};
+use turso_parser::ast::{self, fmt::ToTokens, SortOrder};
 
 use std::sync::Arc;
 
@@ -6,7 +6,8 @@ use crate::{
     schema::{Affinity, Index, IndexColumn, Table},
     translate::{
         emitter::prepare_cdc_if_necessary,
-        plan::{DistinctCtx, Distinctness, Scan},
+        generated::emit_table_column,
+        plan::{AggOrderByCtx, DistinctCtx, Distinctness, Scan},
         result_row::emit_select_result,
     },
     types::SeekOp,
@@ -21,18 +22,20 @@ use crate::{
 use super::{
     aggregation::{translate_aggregation_step, AggArgumentSource},
     display::PlanContext,
-    emitter::{OperationMode, TranslateCtx},
+    emitter::{OperationMode, Resolver, TranslateCtx},
     expr::{
         translate_condition_expr, translate_expr, translate_expr_no_constant_opt,
         ConditionMetadata, NoConstantOptReason,
     },
     group_by::{group_by_agg_phase, GroupByMetadata, GroupByRowSource},
     optimizer::Optimizable,
-    order_by::{order_by_sorter_insert, sorter_insert},
+    order_by::{order_by_sorter_insert, sort_key_collation, sorter_insert},
     plan::{
-        Aggregate, GroupBy, IterationDirection, JoinOrderMember, Operation, QueryDestination,
-        Search, SeekDef, SelectPlan, TableReferences, WhereTerm,
+        Aggregate, EvalAt, GroupBy, IterationDirection, JoinOrderMember, JoinedTable, Operation,
+        QueryDestination, Search, SeekDef, SelectPlan, TableReferences, WhereTerm,
     },
+    planner::determine_where_to_eval_term,
+    window::window_sorter_insert,
 };
 
 // Metadata for handling LEFT JOIN operations
@@ -46,6 +49,30 @@ pub struct LeftJoinMetadata {
     pub label_match_flag_check_value: BranchOffset,
 }
 
+// Metadata for handling RIGHT JOIN (and FULL OUTER JOIN) operations
+#[derive(Debug)]
+pub struct RightJoinMetadata {
+    // cursor of the ephemeral index that holds the keys of the rows of the right table that had a match
+    pub matched_cursor_id: CursorID,
+    // integer register that is set to true while the unmatched rows of the right table are emitted
+    pub reg_unmatched_pass: usize,
+    // integer register that numbers the rows of a subquery, which have no rowid to use as the key
+    pub reg_row_number: Option<usize>,
+    // positions of the primary key columns of a WITHOUT ROWID table, which are the key instead of the rowid
+    pub key_columns: Vec<usize>,
+    // label for the instruction after the right table's join conditions, where the unmatched rows join the loop
+    pub label_loop_body: BranchOffset,
+    // label for the instruction that advances to the next row of the right table in the unmatched rows pass
+    pub label_unmatched_next: BranchOffset,
+}
+
+impl RightJoinMetadata {
+    /// Number of registers of the key of a row of the right table.
+    pub fn num_key_regs(&self) -> usize {
+        self.key_columns.len().max(1)
+    }
+}
+
 /// Jump labels for each loop in the query's main execution loop
 #[derive(Debug, Clone, Copy)]
 pub struct LoopLabels {
@@ -88,11 +115,14 @@ pub fn init_distinct(program: &mut ProgramBuilder, plan: &SelectPlan) -> Distinc
                     pos_in_table: i,
                     collation: None, // FIXME: this should be determined based on the result column expression!
                     default: None, // FIXME: this should be determined based on the result column expression!
+                    expr: None,
                 }
             })
             .collect(),
         unique: false,
         has_rowid: false,
+        num_key_columns: None,
+        where_clause: None,
     });
     let cursor_id = program.alloc_cursor_id(CursorType::BTreeIndex(index.clone()));
     let ctx = DistinctCtx {
@@ -123,17 +153,27 @@ pub fn init_loop(
         t_ctx.meta_left_joins.len() == tables.joined_tables().len(),
         "meta_left_joins length does not match tables length"
     );
+    assert!(
+        t_ctx.meta_right_joins.len() == tables.joined_tables().len(),
+        "meta_right_joins length does not match tables length"
+    );
 
     if matches!(
         mode,
         OperationMode::INSERT | OperationMode::UPDATE | OperationMode::DELETE
     ) {
         assert!(tables.joined_tables().len() == 1);
-        let changed_table = &tables.joined_tables()[0].table;
-        let prepared =
-            prepare_cdc_if_necessary(program, t_ctx.resolver.schema, changed_table.get_name())?;
-        if let Some((cdc_cursor_id, _)) = prepared {
-            t_ctx.cdc_cursor_id = Some(cdc_cursor_id);
+        let changed_table = &tables.joined_tables()[0];
+        // Changes to the tables of the temp database are not captured
+        if changed_table.database_id == 0 {
+            let prepared = prepare_cdc_if_necessary(
+                program,
+                t_ctx.resolver.schema,
+                changed_table.table.get_name(),
+            )?;
+            if let Some((cdc_cursor_id, _)) = prepared {
+                t_ctx.cdc_cursor_id = Some(cdc_cursor_id);
+            }
         }
     }
 
@@ -163,9 +203,12 @@ pub fn init_loop(
                 pos_in_table: 0,
                 collation: None, // FIXME: this should be inferred from the expression
                 default: None,   // FIXME: this should be inferred from the expression
+                expr: None,
             }],
             has_rowid: false,
+            num_key_columns: None,
             unique: false,
+            where_clause: None,
         });
         let cursor_id = program.alloc_cursor_id(CursorType::BTreeIndex(index.clone()));
         if group_by.is_none() {
@@ -184,6 +227,70 @@ pub fn init_loop(
             }),
         };
     }
+    // Initialize ephemeral indexes for aggregates with an ORDER BY clause
+    for (i, agg) in aggregates
+        .iter_mut()
+        .enumerate()
+        .filter(|(_, agg)| !agg.order_by.is_empty())
+    {
+        let index_name = format!("ordered_agg_{i}");
+        let mut columns = Vec::with_capacity(agg.order_by.len() + 1 + agg.args.len());
+        for (expr, order) in agg.order_by.iter() {
+            columns.push(IndexColumn {
+                name: expr.displayer(&PlanContext(&[tables])).to_string(),
+                order: *order,
+                pos_in_table: columns.len(),
+                collation: sort_key_collation(expr, tables)?,
+                default: None,
+                expr: None,
+            });
+        }
+        columns.push(IndexColumn {
+            name: "sequence".to_string(),
+            order: SortOrder::Asc,
+            pos_in_table: columns.len(),
+            collation: None,
+            default: None,
+            expr: None,
+        });
+        for arg in agg.args.iter() {
+            columns.push(IndexColumn {
+                name: arg.displayer(&PlanContext(&[tables])).to_string(),
+                order: SortOrder::Asc,
+                pos_in_table: columns.len(),
+                collation: None,
+                default: None,
+                expr: None,
+            });
+        }
+        let index = Arc::new(Index {
+            name: index_name.clone(),
+            table_name: String::new(),
+            ephemeral: true,
+            root_page: 0,
+            columns,
+            has_rowid: false,
+            num_key_columns: None,
+            unique: false,
+            where_clause: None,
+        });
+        let cursor_id = program.alloc_cursor_id(CursorType::BTreeIndex(index));
+        if group_by.is_none() {
+            // In GROUP BY, the ephemeral index is reinitialized for every group
+            // in the clear accumulator subroutine, so we only do it here if there is no GROUP BY.
+            program.emit_insn(Insn::OpenEphemeral {
+                cursor_id,
+                is_table: false,
+            });
+        }
+        let reg_sequence = program.alloc_register();
+        program.emit_int(0, reg_sequence);
+        agg.order_by_ctx = Some(AggOrderByCtx {
+            cursor_id,
+            ephemeral_index_name: index_name,
+            reg_sequence,
+        });
+    }
     for (table_index, table) in tables.joined_tables().iter().enumerate() {
         // Initialize bookkeeping for OUTER JOIN
         if let Some(join_info) = table.join_info.as_ref() {
@@ -195,6 +302,53 @@ pub fn init_loop(
                 };
                 t_ctx.meta_left_joins[table_index] = Some(lj_metadata);
             }
+            if join_info.right {
+                let key_columns = match &table.table {
+                    Table::BTree(btree) if !btree.has_rowid => btree
+                        .primary_key_columns
+                        .iter()
+                        .map(|(name, _)| btree.get_column(name).expect("primary key column").0)
+                        .collect(),
+                    _ => vec![],
+                };
+                let index = Arc::new(Index {
+                    name: format!("right_join_matched_{table_index}"),
+                    table_name: String::new(),
+                    ephemeral: true,
+                    root_page: 0,
+                    columns: (0..key_columns.len().max(1))
+                        .map(|i| IndexColumn {
+                            name: format!("key{i}"),
+                            order: SortOrder::Asc,
+                            pos_in_table: i,
+                            collation: None,
+                            default: None,
+                            expr: None,
+                        })
+                        .collect(),
+                    unique: false,
+                    has_rowid: false,
+                    num_key_columns: None,
+                    where_clause: None,
+                });
+                let matched_cursor_id = program.alloc_cursor_id(CursorType::BTreeIndex(index));
+                program.emit_insn(Insn::OpenEphemeral {
+                    cursor_id: matched_cursor_id,
+                    is_table: false,
+                });
+                let reg_unmatched_pass = program.alloc_register();
+                program.emit_int(0, reg_unmatched_pass);
+                let rj_metadata = RightJoinMetadata {
+                    matched_cursor_id,
+                    reg_unmatched_pass,
+                    reg_row_number: matches!(table.table, Table::FromClauseSubquery(_))
+                        .then(|| program.alloc_register()),
+                    key_columns,
+                    label_loop_body: program.allocate_label(),
+                    label_unmatched_next: program.allocate_label(),
+                };
+                t_ctx.meta_right_joins[table_index] = Some(rj_metadata);
+            }
         }
         let (table_cursor_id, index_cursor_id) =
             table.open_cursors(program, mode, t_ctx.resolver.schema)?;
@@ -239,6 +393,7 @@ pub fn init_loop(
                                 .op
                                 .index()
                                 .is_some_and(|table_index| table_index.name == index.name)
+                                || index.is_without_rowid_primary_key(btree)
                             {
                                 continue;
                             }
@@ -320,6 +475,9 @@ pub fn init_loop(
                                         .op
                                         .index()
                                         .is_some_and(|table_index| table_index.name == index.name)
+                                        || table.btree().is_some_and(|btree| {
+                                            index.is_without_rowid_primary_key(&btree)
+                                        })
                                     {
                                         continue;
                                     }
@@ -517,6 +675,12 @@ pub fn open_loop(
                             jump_on_definition: BranchOffset::Offset(0),
                             start_offset: coroutine_implementation_start,
                         });
+                        let reg_row_number = t_ctx.meta_right_joins[joined_table_index]
+                            .as_ref()
+                            .and_then(|rj_meta| rj_meta.reg_row_number);
+                        if let Some(reg_row_number) = reg_row_number {
+                            program.emit_int(0, reg_row_number);
+                        }
                         program.preassign_label_to_next_insn(loop_start);
                         // A subquery within the main loop of a parent query has no cursor, so instead of advancing the cursor,
                         // it emits a Yield which jumps back to the main loop of the subquery itself to retrieve the next row.
@@ -526,6 +690,12 @@ pub fn open_loop(
                             yield_reg,
                             end_offset: loop_end,
                         });
+                        if let Some(reg_row_number) = reg_row_number {
+                            program.emit_insn(Insn::AddImm {
+                                register: reg_row_number,
+                                value: 1,
+                            });
+                        }
                     }
                     _ => unreachable!(
                         "{:?} scan cannot be used with {:?} table",
@@ -535,10 +705,13 @@ pub fn open_loop(
 
                 if let Some(table_cursor_id) = table_cursor_id {
                     if let Some(index_cursor_id) = index_cursor_id {
-                        program.emit_insn(Insn::DeferredSeek {
+                        emit_seek_table_from_index(
+                            program,
+                            table,
                             index_cursor_id,
                             table_cursor_id,
-                        });
+                            next,
+                        );
                     }
                 }
             }
@@ -578,6 +751,7 @@ pub fn open_loop(
                             };
                             Some(emit_autoindex(
                                 program,
+                                &t_ctx.resolver,
                                 index,
                                 table_cursor_id
                                     .expect("an ephemeral index must have a source table cursor"),
@@ -628,11 +802,13 @@ pub fn open_loop(
 
                     if let Some(index_cursor_id) = index_cursor_id {
                         if let Some(table_cursor_id) = table_cursor_id {
-                            // Don't do a btree table seek until it's actually necessary to read from the table.
-                            program.emit_insn(Insn::DeferredSeek {
+                            emit_seek_table_from_index(
+                                program,
+                                table,
                                 index_cursor_id,
                                 table_cursor_id,
-                            });
+                                next,
+                            );
                         }
                     }
                 }
@@ -651,6 +827,36 @@ pub fn open_loop(
             true,
         )?;
 
+        // If this is a RIGHT JOIN, the current row of the right table has a match, so remember its key.
+        // The unmatched rows pass jumps here after emitting a NULL row for the tables to the left.
+        if let Some(rj_meta) = t_ctx.meta_right_joins[joined_table_index].as_ref() {
+            let key_reg = emit_right_join_key(program, t_ctx, table_references, table, rj_meta)?;
+            let label_already_matched = program.allocate_label();
+            program.emit_insn(Insn::Found {
+                cursor_id: rj_meta.matched_cursor_id,
+                target_pc: label_already_matched,
+                record_reg: key_reg,
+                num_regs: rj_meta.num_key_regs(),
+            });
+            let record_reg = program.alloc_register();
+            program.emit_insn(Insn::MakeRecord {
+                start_reg: key_reg,
+                count: rj_meta.num_key_regs(),
+                dest_reg: record_reg,
+                index_name: None,
+                affinity_str: None,
+            });
+            program.emit_insn(Insn::IdxInsert {
+                cursor_id: rj_meta.matched_cursor_id,
+                record_reg,
+                unpacked_start: Some(key_reg),
+                unpacked_count: Some(rj_meta.num_key_regs() as u16),
+                flags: IdxInsertFlags::new(),
+            });
+            program.preassign_label_to_next_insn(label_already_matched);
+            program.resolve_label(rj_meta.label_loop_body, program.offset());
+        }
+
         // Set the match flag to true if this is a LEFT JOIN.
         // At this point of execution we are going to emit columns for the left table,
         // and either emit columns or NULLs for the right table, depending on whether the null_flag is set
@@ -732,6 +938,7 @@ enum LoopEmitTarget {
     OrderBySorter,
     AggStep,
     QueryResult,
+    Window,
 }
 
 /// Emits the bytecode for the inner loop of a query.
@@ -741,6 +948,11 @@ pub fn emit_loop(
     t_ctx: &mut TranslateCtx,
     plan: &SelectPlan,
 ) -> Result<()> {
+    // if we have window functions, we emit a record into the window sorter;
+    // the result rows are emitted once the window functions have been computed.
+    if plan.window.is_some() {
+        return emit_loop_source(program, t_ctx, plan, LoopEmitTarget::Window);
+    }
     // if we have a group by, we emit a record into the group by sorter,
     // or if the rows are already sorted, we do the group by aggregation phase directly.
     if plan.group_by.is_some() {
@@ -809,12 +1021,12 @@ fn emit_loop_source(
             }
 
             // Step 2: Process arguments for all aggregate functions
-            // For each aggregate, translate all its argument expressions
+            // For each aggregate, translate all its argument expressions, along with its FILTER clause and ORDER BY keys
             for agg in aggregates.iter() {
                 // For a query like: SELECT group_col, SUM(val1), AVG(val2) FROM table GROUP BY group_col
                 // we'll process val1 and val2 here, storing them in the sorter so they're available
                 // when computing the aggregates after sorting by group_col
-                for expr in agg.args.iter() {
+                for expr in agg.inputs() {
                     let agg_reg = cur_reg;
                     cur_reg += 1;
                     translate_expr(
@@ -847,6 +1059,7 @@ fn emit_loop_source(
 
             Ok(())
         }
+        LoopEmitTarget::Window => window_sorter_insert(program, t_ctx, plan),
         LoopEmitTarget::OrderBySorter => {
             order_by_sorter_insert(
                 program,
@@ -981,7 +1194,7 @@ pub fn close_loop(
     //     CLOSE t3
     //   CLOSE t2
     // CLOSE t1
-    for join in join_order.iter().rev() {
+    for (join_index, join) in join_order.iter().enumerate().rev() {
         let table_index = join.original_idx;
         let table = &tables.joined_tables()[table_index];
         let loop_labels = *t_ctx
@@ -994,6 +1207,7 @@ pub fn close_loop(
         match &table.op {
             Operation::Scan(scan) => {
                 program.resolve_label(loop_labels.next, program.offset());
+                emit_unmatched_pass_checks(program, t_ctx, &join_order[join_index..]);
                 match scan {
                     Scan::BTreeTable { iter_dir, .. } => {
                         let iteration_cursor_id = temp_cursor_id.unwrap_or_else(|| {
@@ -1039,6 +1253,7 @@ pub fn close_loop(
                     "Subqueries do not support index seeks"
                 );
                 program.resolve_label(loop_labels.next, program.offset());
+                emit_unmatched_pass_checks(program, t_ctx, &join_order[join_index..]);
                 let iteration_cursor_id = temp_cursor_id.unwrap_or_else(|| {
                     index_cursor_id.unwrap_or_else(|| {
                         table_cursor_id
@@ -1114,6 +1329,227 @@ pub fn close_loop(
     Ok(())
 }
 
+/// While the unmatched rows of a RIGHT JOIN are emitted, the loops of the tables to its left are not running,
+/// so advancing to the next row of one of those tables, or of the right table itself, advances the unmatched rows pass instead.
+fn emit_unmatched_pass_checks(
+    program: &mut ProgramBuilder,
+    t_ctx: &TranslateCtx,
+    join_order: &[JoinOrderMember],
+) {
+    for join in join_order.iter() {
+        if let Some(rj_meta) = t_ctx.meta_right_joins[join.original_idx].as_ref() {
+            program.emit_insn(Insn::If {
+                reg: rj_meta.reg_unmatched_pass,
+                target_pc: rj_meta.label_unmatched_next,
+                jump_if_null: false,
+            });
+        }
+    }
+}
+
+/// Emits the key that identifies the current row of the right table of a RIGHT JOIN, and returns its first register.
+/// The key is the rowid of a table, the primary key of a WITHOUT ROWID table, or the row number of a subquery.
+fn emit_right_join_key(
+    program: &mut ProgramBuilder,
+    t_ctx: &TranslateCtx,
+    table_references: &TableReferences,
+    table: &JoinedTable,
+    rj_meta: &RightJoinMetadata,
+) -> Result<usize> {
+    if let Some(reg_row_number) = rj_meta.reg_row_number {
+        return Ok(reg_row_number);
+    }
+    if !rj_meta.key_columns.is_empty() {
+        let key_reg = program.alloc_registers(rj_meta.key_columns.len());
+        for (i, column) in rj_meta.key_columns.iter().enumerate() {
+            translate_expr(
+                program,
+                Some(table_references),
+                &ast::Expr::Column {
+                    database: None,
+                    table: table.internal_id,
+                    column: *column,
+                    is_rowid_alias: false,
+                },
+                key_reg + i,
+                &t_ctx.resolver,
+            )?;
+        }
+        return Ok(key_reg);
+    }
+    let key_reg = program.alloc_register();
+    translate_expr(
+        program,
+        Some(table_references),
+        &ast::Expr::RowId {
+            database: None,
+            table: table.internal_id,
+        },
+        key_reg,
+        &t_ctx.resolver,
+    )?;
+    Ok(key_reg)
+}
+
+/// Emits the rows of the right tables of RIGHT JOINs (and FULL OUTER JOINs) that had no match
+/// in the tables to their left. This runs after the main loop is closed.
+///
+/// For each right table, the rows whose key was not recorded in the main loop are read with a full scan,
+/// the tables to the left are set to a NULL row, and the WHERE conditions that refer to those tables are evaluated.
+/// The rows that pass then jump into the main loop right after the join conditions of the right table,
+/// so that the loops of the tables to its right and the loop body are shared with the main loop.
+pub fn emit_unmatched_right_join_rows(
+    program: &mut ProgramBuilder,
+    t_ctx: &TranslateCtx,
+    table_references: &TableReferences,
+    join_order: &[JoinOrderMember],
+    predicates: &[WhereTerm],
+) -> Result<()> {
+    for (join_index, join) in join_order.iter().enumerate() {
+        let Some(rj_meta) = t_ctx.meta_right_joins[join.original_idx].as_ref() else {
+            continue;
+        };
+        let table = &table_references.joined_tables()[join.original_idx];
+        program.emit_int(1, rj_meta.reg_unmatched_pass);
+
+        for left_join in join_order[..join_index].iter() {
+            let left_table = &table_references.joined_tables()[left_join.original_idx];
+            match &left_table.table {
+                Table::FromClauseSubquery(from_clause_subquery) => {
+                    let start_reg = from_clause_subquery
+                        .result_columns_start_reg
+                        .expect("Subquery result_columns_start_reg must be set");
+                    program.emit_insn(Insn::Null {
+                        dest: start_reg,
+                        dest_end: Some(
+                            start_reg + from_clause_subquery.plan.result_columns.len() - 1,
+                        ),
+                    });
+                }
+                _ => {
+                    let (table_cursor_id, index_cursor_id) = left_table.resolve_cursors(program)?;
+                    for cursor_id in [table_cursor_id, index_cursor_id].into_iter().flatten() {
+                        program.emit_insn(Insn::NullRow { cursor_id });
+                    }
+                }
+            }
+        }
+
+        let loop_start = program.allocate_label();
+        let loop_end = program.allocate_label();
+        let (table_cursor_id, index_cursor_id) = table.resolve_cursors(program)?;
+        let iteration_cursor_id = match &table.table {
+            Table::FromClauseSubquery(from_clause_subquery) => {
+                let QueryDestination::CoroutineYield {
+                    yield_reg,
+                    coroutine_implementation_start,
+                } = &from_clause_subquery.plan.query_destination
+                else {
+                    unreachable!("Subquery table with non-subquery query type");
+                };
+                program.emit_insn(Insn::InitCoroutine {
+                    yield_reg: *yield_reg,
+                    jump_on_definition: BranchOffset::Offset(0),
+                    start_offset: *coroutine_implementation_start,
+                });
+                let reg_row_number = rj_meta
+                    .reg_row_number
+                    .expect("subquery in a RIGHT JOIN must have a row number register");
+                program.emit_int(0, reg_row_number);
+                program.preassign_label_to_next_insn(loop_start);
+                program.emit_insn(Insn::Yield {
+                    yield_reg: *yield_reg,
+                    end_offset: loop_end,
+                });
+                program.emit_insn(Insn::AddImm {
+                    register: reg_row_number,
+                    value: 1,
+                });
+                None
+            }
+            _ => {
+                // Read every row of the table with the same cursors as the main loop,
+                // so that the columns are read from the same cursors as well.
+                let iteration_cursor_id = index_cursor_id.or(table_cursor_id).expect(
+                    "Either index or table cursor must be opened for the right table of a RIGHT JOIN",
+                );
+                program.emit_insn(Insn::Rewind {
+                    cursor_id: iteration_cursor_id,
+                    pc_if_empty: loop_end,
+                });
+                program.preassign_label_to_next_insn(loop_start);
+                if let (Some(index_cursor_id), Some(table_cursor_id)) =
+                    (index_cursor_id, table_cursor_id)
+                {
+                    emit_seek_table_from_index(
+                        program,
+                        table,
+                        index_cursor_id,
+                        table_cursor_id,
+                        rj_meta.label_unmatched_next,
+                    );
+                }
+                Some(iteration_cursor_id)
+            }
+        };
+
+        // Skip the rows that had a match in the main loop.
+        let key_reg = emit_right_join_key(program, t_ctx, table_references, table, rj_meta)?;
+        program.emit_insn(Insn::Found {
+            cursor_id: rj_meta.matched_cursor_id,
+            target_pc: rj_meta.label_unmatched_next,
+            record_reg: key_reg,
+            num_regs: rj_meta.num_key_regs(),
+        });
+
+        // The WHERE conditions that the main loop evaluates up to the loop of the right table must be evaluated
+        // again for the NULL row, including the ones that were consumed as seek keys.
+        // The join conditions of the right table and of the tables to its left do not apply to unmatched rows.
+        for cond in predicates
+            .iter()
+            .filter(|cond| cond.from_outer_join.is_none())
+        {
+            if !matches!(
+                determine_where_to_eval_term(cond, join_order)?,
+                EvalAt::Loop(loop_idx) if loop_idx <= join_index
+            ) {
+                continue;
+            }
+            let jump_target_when_true = program.allocate_label();
+            let condition_metadata = ConditionMetadata {
+                jump_if_condition_is_true: false,
+                jump_target_when_true,
+                jump_target_when_false: rj_meta.label_unmatched_next,
+            };
+            translate_condition_expr(
+                program,
+                table_references,
+                &cond.expr,
+                condition_metadata,
+                &t_ctx.resolver,
+            )?;
+            program.preassign_label_to_next_insn(jump_target_when_true);
+        }
+        program.emit_insn(Insn::Goto {
+            target_pc: rj_meta.label_loop_body,
+        });
+
+        program.resolve_label(rj_meta.label_unmatched_next, program.offset());
+        match iteration_cursor_id {
+            Some(cursor_id) => program.emit_insn(Insn::Next {
+                cursor_id,
+                pc_if_next: loop_start,
+            }),
+            None => program.emit_insn(Insn::Goto {
+                target_pc: loop_start,
+            }),
+        }
+        program.preassign_label_to_next_insn(loop_end);
+        program.emit_int(0, rj_meta.reg_unmatched_pass);
+    }
+    Ok(())
+}
+
 /// Emits instructions for an index seek. See e.g. [crate::translate::plan::SeekDef]
 /// for more details about the seek definition.
 ///
@@ -1124,6 +1560,48 @@ pub fn close_loop(
 /// If either 1. the seek finds no rows or 2. the termination condition is reached,
 /// the loop for that given table/index is fully exited.
 #[allow(clippy::too_many_arguments)]
+/// Positions the table cursor on the row that the index cursor points to.
+/// Rowid tables defer the seek until a column is read from the table. A WITHOUT ROWID table
+/// has no rowid to defer the seek on, so its primary key is read from the index entry and
+/// looked up in the table right away, jumping to `not_found` if there is no such row.
+fn emit_seek_table_from_index(
+    program: &mut ProgramBuilder,
+    table: &JoinedTable,
+    index_cursor_id: CursorID,
+    table_cursor_id: CursorID,
+    not_found: BranchOffset,
+) {
+    let (Table::BTree(btree), Some(index)) = (&table.table, table.op.index()) else {
+        program.emit_insn(Insn::DeferredSeek {
+            index_cursor_id,
+            table_cursor_id,
+        });
+        return;
+    };
+    if btree.has_rowid {
+        // Don't do a btree table seek until it's actually necessary to read from the table.
+        program.emit_insn(Insn::DeferredSeek {
+            index_cursor_id,
+            table_cursor_id,
+        });
+        return;
+    }
+    let pk_positions = btree.primary_key_column_positions();
+    let start_reg = program.alloc_registers(pk_positions.len());
+    for (i, pos_in_table) in pk_positions.iter().enumerate() {
+        let pos_in_index = index
+            .column_table_pos_to_index_pos(*pos_in_table)
+            .expect("indexes of a WITHOUT ROWID table contain its primary key");
+        program.emit_column_or_rowid(index_cursor_id, pos_in_index, start_reg + i);
+    }
+    program.emit_insn(Insn::NotFound {
+        cursor_id: table_cursor_id,
+        target_pc: not_found,
+        record_reg: start_reg,
+        num_regs: pk_positions.len(),
+    });
+}
+
 fn emit_seek(
     program: &mut ProgramBuilder,
     tables: &TableReferences,
@@ -1388,6 +1866,7 @@ fn emit_seek_termination(
 /// Returns the cursor id of the ephemeral index cursor.
 fn emit_autoindex(
     program: &mut ProgramBuilder,
+    resolver: &Resolver,
     index: &Arc<Index>,
     table_cursor_id: CursorID,
     index_cursor_id: CursorID,
@@ -1415,7 +1894,7 @@ fn emit_autoindex(
     let ephemeral_cols_start_reg = program.alloc_registers(num_regs_to_reserve);
     for (i, col) in index.columns.iter().enumerate() {
         let reg = ephemeral_cols_start_reg + i;
-        program.emit_column_or_rowid(table_cursor_id, col.pos_in_table, reg);
+        emit_table_column(program, resolver, table_cursor_id, col.pos_in_table, reg)?;
     }
     if table_has_rowid {
         program.emit_insn(Insn::RowId {
@@ -17,6 +17,8 @@ pub(crate) mod delete;
 pub(crate) mod display;
 pub(crate) mod emitter;
 pub(crate) mod expr;
+pub(crate) mod fkeys;
+pub(crate) mod generated;
 pub(crate) mod group_by;
 pub(crate) mod index;
 pub(crate) mod insert;
@@ -28,16 +30,20 @@ pub(crate) mod order_by;
 pub(crate) mod plan;
 pub(crate) mod planner;
 pub(crate) mod pragma;
+pub(crate) mod reindex;
 pub(crate) mod result_row;
 pub(crate) mod rollback;
 pub(crate) mod schema;
 pub(crate) mod select;
 pub(crate) mod subquery;
 pub(crate) mod transaction;
+pub(crate) mod trigger;
 pub(crate) mod update;
 pub(crate) mod upsert;
+pub(crate) mod vacuum;
 mod values;
 pub(crate) mod view;
+pub(crate) mod window;
 
 use crate::schema::Schema;
 use crate::storage::pager::Pager;
@@ -50,13 +56,18 @@ use analyze::translate_analyze;
 use index::{translate_create_index, translate_drop_index};
 use insert::translate_insert;
 use rollback::translate_rollback;
-use schema::{translate_create_table, translate_create_virtual_table, translate_drop_table};
+use schema::{
+    resolve_create_database_id, translate_create_table, translate_create_virtual_table,
+    translate_drop_table,
+};
 use select::translate_select;
 use std::rc::Rc;
 use std::sync::Arc;
 use tracing::{instrument, Level};
-use transaction::{translate_tx_begin, translate_tx_commit};
-use turso_parser::ast::{self, Indexed};
+use transaction::{
+    translate_release, translate_savepoint, translate_tx_begin, translate_tx_commit,
+};
+use turso_parser::ast;
 use update::translate_update;
 
 #[instrument(skip_all, level = Level::DEBUG)]
@@ -91,6 +102,7 @@ pub fn translate(
     );
 
     program.prologue();
+    program.set_attached_schema_cookies(connection.attached_schema_cookies());
 
     program = match stmt {
         // There can be no nesting with pragma, so lift it up here
@@ -125,6 +137,7 @@ pub fn translate_inner(
     let is_write = matches!(
         stmt,
         ast::Stmt::AlterTable { .. }
+            | ast::Stmt::Analyze { .. }
             | ast::Stmt::CreateIndex { .. }
             | ast::Stmt::CreateTable { .. }
             | ast::Stmt::CreateTrigger { .. }
@@ -134,10 +147,12 @@ pub fn translate_inner(
             | ast::Stmt::Delete { .. }
             | ast::Stmt::DropIndex { .. }
             | ast::Stmt::DropTable { .. }
+            | ast::Stmt::DropTrigger { .. }
             | ast::Stmt::DropView { .. }
             | ast::Stmt::Reindex { .. }
             | ast::Stmt::Update { .. }
             | ast::Stmt::Insert { .. }
+            | ast::Stmt::Vacuum { into: None, .. }
     );
 
     if is_write && connection.get_query_only() {
@@ -150,7 +165,7 @@ pub fn translate_inner(
         ast::Stmt::AlterTable(alter) => {
             translate_alter_table(alter, syms, schema, program, connection, input)?
         }
-        ast::Stmt::Analyze { name } => translate_analyze(name, schema, syms, program)?,
+        ast::Stmt::Analyze { name } => translate_analyze(name, schema, syms, connection, program)?,
         ast::Stmt::Attach { expr, db_name, key } => {
             attach::translate_attach(&expr, &db_name, &key, schema, syms, program)?
         }
@@ -164,15 +179,22 @@ pub fn translate_inner(
             columns,
             where_clause,
         } => {
-            if where_clause.is_some() {
-                bail_parse_error!("Partial indexes are not supported");
-            }
+            // Like in SQLite, an index is created in the database of its table.
+            let database_id = match &idx_name.db_name {
+                Some(db_name) => connection.resolve_database_name(db_name.as_str())?,
+                None => {
+                    connection.resolve_database_id(&ast::QualifiedName::single(tbl_name.clone()))?
+                }
+            };
+            let database_schema = connection.database_schema(database_id)?;
             translate_create_index(
                 (unique, if_not_exists),
+                database_id,
                 idx_name.name.as_str(),
                 tbl_name.as_str(),
                 &columns,
-                schema,
+                where_clause,
+                database_schema.as_deref().unwrap_or(schema),
                 syms,
                 program,
             )?
@@ -192,21 +214,29 @@ pub fn translate_inner(
             connection,
             program,
         )?,
-        ast::Stmt::CreateTrigger { .. } => bail_parse_error!("CREATE TRIGGER not supported yet"),
+        stmt @ ast::Stmt::CreateTrigger { .. } => {
+            trigger::translate_create_trigger(schema, stmt, syms, connection, program)?
+        }
         ast::Stmt::CreateView {
+            temporary,
             view_name,
             select,
             columns,
             ..
-        } => view::translate_create_view(
-            schema,
-            view_name.name.as_str(),
-            &select,
-            &columns,
-            connection.clone(),
-            syms,
-            program,
-        )?,
+        } => {
+            let database_id = resolve_create_database_id(connection, &view_name, temporary)?;
+            let database_schema = connection.database_schema(database_id)?;
+            view::translate_create_view(
+                database_schema.as_deref().unwrap_or(schema),
+                database_id,
+                view_name.name.as_str(),
+                &select,
+                &columns,
+                connection.clone(),
+                syms,
+                program,
+            )?
+        }
         ast::Stmt::CreateMaterializedView {
             view_name, select, ..
         } => view::translate_create_materialized_view(
@@ -232,15 +262,13 @@ pub fn translate_inner(
             if with.is_some() {
                 bail_parse_error!("WITH clause is not supported in DELETE");
             }
-            if indexed.is_some_and(|i| matches!(i, Indexed::IndexedBy(_))) {
-                bail_parse_error!("INDEXED BY clause is not supported in DELETE");
-            }
             if !order_by.is_empty() {
                 bail_parse_error!("ORDER BY clause is not supported in DELETE");
             }
             translate_delete(
                 schema,
                 &tbl_name,
+                indexed,
                 where_clause,
                 limit,
                 returning,
@@ -253,26 +281,82 @@ pub fn translate_inner(
         ast::Stmt::DropIndex {
             if_exists,
             idx_name,
-        } => translate_drop_index(idx_name.name.as_str(), if_exists, schema, syms, program)?,
+        } => {
+            let database_id = connection.resolve_database_id_with(&idx_name, |schema, name| {
+                schema
+                    .indexes
+                    .values()
+                    .flatten()
+                    .any(|idx| idx.name == name)
+            })?;
+            let database_schema = connection.database_schema(database_id)?;
+            translate_drop_index(
+                database_id,
+                idx_name.name.as_str(),
+                if_exists,
+                database_schema.as_deref().unwrap_or(schema),
+                syms,
+                program,
+            )?
+        }
         ast::Stmt::DropTable {
             if_exists,
             tbl_name,
-        } => translate_drop_table(tbl_name, if_exists, schema, syms, program)?,
-        ast::Stmt::DropTrigger { .. } => bail_parse_error!("DROP TRIGGER not supported yet"),
+        } => {
+            let database_id = connection.resolve_database_id(&tbl_name)?;
+            let database_schema = connection.database_schema(database_id)?;
+            translate_drop_table(
+                tbl_name,
+                database_id,
+                if_exists,
+                database_schema.as_deref().unwrap_or(schema),
+                syms,
+                program,
+            )?
+        }
+        ast::Stmt::DropTrigger {
+            if_exists,
+            trigger_name,
+        } => {
+            let database_id = connection
+                .resolve_database_id_with(&trigger_name, |schema, name| {
+                    schema.get_trigger(name).is_some()
+                })?;
+            let database_schema = connection.database_schema(database_id)?;
+            trigger::translate_drop_trigger(
+                database_schema.as_deref().unwrap_or(schema),
+                database_id,
+                trigger_name.name.as_str(),
+                if_exists,
+                program,
+            )?
+        }
         ast::Stmt::DropView {
             if_exists,
             view_name,
-        } => view::translate_drop_view(schema, view_name.name.as_str(), if_exists, program)?,
+        } => {
+            let database_id = connection.resolve_database_id(&view_name)?;
+            let database_schema = connection.database_schema(database_id)?;
+            view::translate_drop_view(
+                database_schema.as_deref().unwrap_or(schema),
+                database_id,
+                view_name.name.as_str(),
+                if_exists,
+                program,
+            )?
+        }
         ast::Stmt::Pragma { .. } => {
             bail_parse_error!("PRAGMA statement cannot be evaluated in a nested context")
         }
-        ast::Stmt::Reindex { .. } => bail_parse_error!("REINDEX not supported yet"),
-        ast::Stmt::Release { .. } => bail_parse_error!("RELEASE not supported yet"),
+        ast::Stmt::Reindex { name } => {
+            reindex::translate_reindex(name, schema, syms, connection, program)?
+        }
+        ast::Stmt::Release { name } => translate_release(name, program)?,
         ast::Stmt::Rollback {
             tx_name,
             savepoint_name,
         } => translate_rollback(schema, syms, program, tx_name, savepoint_name)?,
-        ast::Stmt::Savepoint { .. } => bail_parse_error!("SAVEPOINT not supported yet"),
+        ast::Stmt::Savepoint { name } => translate_savepoint(name, program)?,
         ast::Stmt::Select(select) => {
             translate_select(
                 schema,
@@ -287,7 +371,9 @@ pub fn translate_inner(
         ast::Stmt::Update(mut update) => {
             translate_update(schema, &mut update, syms, program, connection)?
         }
-        ast::Stmt::Vacuum { .. } => bail_parse_error!("VACUUM not supported yet"),
+        ast::Stmt::Vacuum { name, into } => {
+            vacuum::translate_vacuum(name, into, schema, syms, program)?
+        }
         ast::Stmt::Insert {
             with,
             or_conflict,
@@ -3,10 +3,10 @@ use std::sync::Arc;
 use turso_ext::{ConstraintInfo, ConstraintUsage, ResultCode};
 use turso_parser::ast::SortOrder;
 
-use crate::translate::optimizer::constraints::{convert_to_vtab_constraint, Constraint};
+use crate::translate::optimizer::constraints::convert_to_vtab_constraint;
 use crate::{
     schema::{Index, Table},
-    translate::plan::{IterationDirection, JoinOrderMember, JoinedTable},
+    translate::plan::{IndexedBy, IterationDirection, JoinOrderMember, JoinedTable},
     vtab::VirtualTable,
     LimboError, Result,
 };
@@ -14,7 +14,7 @@ use crate::{
 use super::{
     constraints::{usable_constraints_for_join_order, ConstraintRef, TableConstraints},
     cost::{estimate_cost_for_scan_or_seek, Cost, IndexInfo},
-    order::OrderTarget,
+    order::{OrderColumn, OrderTarget},
 };
 
 #[derive(Debug, Clone)]
@@ -72,14 +72,18 @@ pub fn find_best_access_method_for_join_order<'a>(
             maybe_order_target,
             input_cardinality,
         ),
-        Table::Virtual(vtab) => find_best_access_method_for_vtab(
-            vtab,
-            &rhs_constraints.constraints,
-            join_order,
-            input_cardinality,
-        ),
+        Table::Virtual(vtab) => {
+            find_best_access_method_for_vtab(vtab, rhs_constraints, join_order, input_cardinality)
+        }
         Table::FromClauseSubquery(_) => Ok(Some(AccessMethod {
-            cost: estimate_cost_for_scan_or_seek(None, &[], &[], input_cardinality),
+            cost: estimate_cost_for_scan_or_seek(
+                None,
+                None,
+                &[],
+                &[],
+                input_cardinality,
+                rhs_constraints.row_count,
+            ),
             params: AccessMethodParams::Subquery,
         })),
     }
@@ -93,7 +97,23 @@ fn find_best_access_method_for_btree<'a>(
     input_cardinality: f64,
 ) -> Result<Option<AccessMethod<'a>>> {
     let table_no = join_order.last().unwrap().table_id;
-    let mut best_cost = estimate_cost_for_scan_or_seek(None, &[], &[], input_cardinality);
+    // With INDEXED BY, a full table scan is not an option: the table must be accessed through the named index.
+    let forced_index = match &rhs_table.indexed {
+        Some(IndexedBy::Index(index)) => Some(index),
+        _ => None,
+    };
+    let mut best_cost = if forced_index.is_some() {
+        Cost(f64::MAX)
+    } else {
+        estimate_cost_for_scan_or_seek(
+            None,
+            None,
+            &[],
+            &[],
+            input_cardinality,
+            rhs_constraints.row_count,
+        )
+    };
     let mut best_params = AccessMethodParams::BTreeTable {
         iter_dir: IterationDirection::Forwards,
         index: None,
@@ -122,9 +142,11 @@ fn find_best_access_method_for_btree<'a>(
         );
         let cost = estimate_cost_for_scan_or_seek(
             Some(index_info),
+            candidate.stats.as_ref(),
             &rhs_constraints.constraints,
             usable_constraint_refs,
             input_cardinality,
+            rhs_constraints.row_count,
         );
 
         // All other things being equal, prefer an access method that satisfies the order target.
@@ -138,10 +160,10 @@ fn find_best_access_method_for_btree<'a>(
                 let correct_table = order_target.0[i].table_id == table_no;
                 let correct_column = {
                     match &candidate.index {
-                        Some(index) => index.columns[i].pos_in_table == order_target.0[i].column_no,
-                        None => {
-                            rowid_column_idx.is_some_and(|idx| idx == order_target.0[i].column_no)
-                        }
+                        Some(index) => order_target.0[i].matches_index_column(index, i, rhs_table),
+                        None => rowid_column_idx.is_some_and(|idx| {
+                            order_target.0[i].column == OrderColumn::Column(idx)
+                        }),
                     }
                 };
                 if !correct_table || !correct_column {
@@ -186,6 +208,20 @@ fn find_best_access_method_for_btree<'a>(
         }
     }
 
+    if let Some(forced_index) = forced_index {
+        let uses_forced_index = matches!(
+            &best_params,
+            AccessMethodParams::BTreeTable { index: Some(index), .. } if index.name == forced_index.name
+        );
+        if !uses_forced_index {
+            return Err(LimboError::ParseError(format!(
+                "no query solution: index {} cannot be used to access table {}",
+                forced_index.name,
+                rhs_table.table.get_name()
+            )));
+        }
+    }
+
     Ok(Some(AccessMethod {
         cost: best_cost,
         params: best_params,
@@ -194,11 +230,11 @@ fn find_best_access_method_for_btree<'a>(
 
 fn find_best_access_method_for_vtab<'a>(
     vtab: &VirtualTable,
-    constraints: &[Constraint],
+    rhs_constraints: &TableConstraints,
     join_order: &[JoinOrderMember],
     input_cardinality: f64,
 ) -> Result<Option<AccessMethod<'a>>> {
-    let vtab_constraints = convert_to_vtab_constraint(constraints, join_order);
+    let vtab_constraints = convert_to_vtab_constraint(&rhs_constraints.constraints, join_order);
 
     // TODO: get proper order_by information to pass to the vtab.
     // maybe encode more info on t_ctx? we need: [col_idx , is_descending]
@@ -208,7 +244,14 @@ fn find_best_access_method_for_vtab<'a>(
         Ok(index_info) => {
             Ok(Some(AccessMethod {
                 // TODO: Base cost on `IndexInfo::estimated_cost` and output cardinality on `IndexInfo::estimated_rows`
-                cost: estimate_cost_for_scan_or_seek(None, &[], &[], input_cardinality),
+                cost: estimate_cost_for_scan_or_seek(
+                    None,
+                    None,
+                    &[],
+                    &[],
+                    input_cardinality,
+                    rhs_constraints.row_count,
+                ),
                 params: AccessMethodParams::VirtualTable {
                     idx_num: index_info.idx_num,
                     idx_str: index_info.idx_str,
@@ -1,12 +1,14 @@
 use std::{cmp::Ordering, collections::HashMap, sync::Arc};
 
 use crate::{
-    schema::{Column, Index},
+    schema::{Column, Index, IndexStats, TableStats},
     translate::{
         expr::as_binary_components,
-        plan::{JoinOrderMember, TableReferences, WhereTerm},
+        index::{bind_index_expr, terms_imply_partial_index_predicate},
+        plan::{IndexedBy, JoinOrderMember, JoinedTable, TableReferences, WhereTerm},
         planner::{table_mask_from_expr, TableMask},
     },
+    util::exprs_are_equivalent,
     Result,
 };
 use turso_ext::{ConstraintInfo, ConstraintOp};
@@ -15,7 +17,8 @@ use turso_parser::ast::{self, SortOrder, TableInternalId};
 use super::cost::ESTIMATED_HARDCODED_ROWS_PER_TABLE;
 
 /// Represents a single condition derived from a `WHERE` clause term
-/// that constrains a specific column of a table.
+/// that constrains a specific column of a table, or an expression of the table
+/// that is indexed by an expression index.
 ///
 /// Constraints are precomputed for each table involved in a query. They are used
 /// during query optimization to estimate the cost of different access paths (e.g., using an index)
@@ -39,8 +42,9 @@ pub struct Constraint {
     pub where_clause_pos: (usize, BinaryExprSide),
     /// The comparison operator (e.g., `=`, `>`, `<`) used in the constraint.
     pub operator: ast::Operator,
-    /// The zero-based index of the constrained column within the table's schema.
-    pub table_col_pos: usize,
+    /// The zero-based index of the constrained column within the table's schema,
+    /// or None if the constrained side is an indexed expression, e.g. lower(t.x).
+    pub table_col_pos: Option<usize>,
     /// A bitmask representing the set of tables that appear on the *constraining* side
     /// of the comparison expression. For example, in SELECT * FROM t1,t2,t3 WHERE t1.x = t2.x + t3.x,
     /// the lhs_mask contains t2 and t3. Thus, this constraint can only be used if t2 and t3
@@ -71,6 +75,20 @@ impl Constraint {
             rhs.clone()
         }
     }
+
+    /// Get the constrained expression, e.g. 'lower(t.x)' from 'lower(t.x) = 2+3'
+    pub fn get_constrained_expr(&self, where_clause: &[WhereTerm]) -> ast::Expr {
+        let (idx, side) = self.where_clause_pos;
+        let where_term = &where_clause[idx];
+        let Ok(Some((lhs, _, rhs))) = as_binary_components(&where_term.expr) else {
+            panic!("Expected a valid binary expression");
+        };
+        if side == BinaryExprSide::Lhs {
+            rhs.clone()
+        } else {
+            lhs.clone()
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -129,6 +147,8 @@ pub struct ConstraintUseCandidate {
     pub index: Option<Arc<Index>>,
     /// References to the constraints that may be used as an access path for the index.
     pub refs: Vec<ConstraintRef>,
+    /// The statistics about the index in sqlite_stat1, if it has been analyzed.
+    pub stats: Option<IndexStats>,
 }
 
 #[derive(Debug)]
@@ -140,6 +160,8 @@ pub struct TableConstraints {
     pub constraints: Vec<Constraint>,
     /// Candidates for indexes that may use the constraints to perform a lookup.
     pub candidates: Vec<ConstraintUseCandidate>,
+    /// The estimated number of rows in the table, from sqlite_stat1 if the table has been analyzed.
+    pub row_count: f64,
 }
 
 /// In lieu of statistics, we estimate that an equality filter will reduce the output set to 1% of its size.
@@ -149,14 +171,22 @@ const SELECTIVITY_RANGE: f64 = 0.4;
 /// In lieu of statistics, we estimate that other filters will reduce the output set to 90% of its size.
 const SELECTIVITY_OTHER: f64 = 0.9;
 
-const SELECTIVITY_UNIQUE_EQUALITY: f64 = 1.0 / ESTIMATED_HARDCODED_ROWS_PER_TABLE as f64;
-
 /// Estimate the selectivity of a constraint based on the operator and the column type.
-fn estimate_selectivity(column: &Column, op: ast::Operator) -> f64 {
+/// An indexed expression has no column and is estimated by the operator only.
+/// `rows_per_value` is the average number of rows with the same value in the column,
+/// if known from sqlite_stat1, and `row_count` is the estimated number of rows in the table.
+fn estimate_selectivity(
+    column: Option<&Column>,
+    op: ast::Operator,
+    rows_per_value: Option<f64>,
+    row_count: f64,
+) -> f64 {
     match op {
         ast::Operator::Equals => {
-            if column.is_rowid_alias || column.primary_key {
-                SELECTIVITY_UNIQUE_EQUALITY
+            if column.is_some_and(|column| column.is_rowid_alias || column.primary_key) {
+                1.0 / row_count
+            } else if let Some(rows_per_value) = rows_per_value {
+                (rows_per_value / row_count).min(1.0)
             } else {
                 SELECTIVITY_EQ
             }
@@ -175,6 +205,7 @@ pub fn constraints_from_where_clause(
     where_clause: &[WhereTerm],
     table_references: &TableReferences,
     available_indexes: &HashMap<String, Vec<Arc<Index>>>,
+    table_stats: &HashMap<String, TableStats>,
 ) -> Result<Vec<TableConstraints>> {
     let mut constraints = Vec::new();
 
@@ -184,26 +215,86 @@ pub fn constraints_from_where_clause(
             .columns()
             .iter()
             .position(|c| c.is_rowid_alias);
+        // Indexes and statistics only describe tables of the main database, so tables of other
+        // databases are always scanned.
+        let in_main_database = table_reference.database_id == 0;
+        let indexes = available_indexes
+            .get(table_reference.table.get_name())
+            .filter(|_| in_main_database)
+            .map_or(&[][..], |indexes| indexes.as_slice());
+        // The indexed expressions of each index, bound to this table, e.g. lower(t.x).
+        let index_exprs = indexes
+            .iter()
+            .map(|index| {
+                index
+                    .columns
+                    .iter()
+                    .map(|column| {
+                        column
+                            .expr
+                            .as_ref()
+                            .and_then(|expr| bind_to_table_reference(expr, table_reference))
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        let stats = table_stats
+            .get(table_reference.table.get_name())
+            .filter(|_| in_main_database);
+        let row_count = stats
+            .and_then(|stats| stats.estimated_row_count())
+            .map_or(ESTIMATED_HARDCODED_ROWS_PER_TABLE as f64, |row_count| {
+                row_count.max(1) as f64
+            });
+        let btree = table_reference.btree();
+        let index_stats = |index: &Index| {
+            let index_name = match &btree {
+                Some(btree) => index.stat1_name(btree),
+                None => index.name.as_str(),
+            };
+            stats.and_then(|stats| stats.indexes.get(index_name))
+        };
+        // The average number of rows per value of a column is known from the statistics of
+        // the indexes whose first column it is.
+        let rows_per_value = |column: usize| {
+            indexes
+                .iter()
+                .filter(|index| {
+                    index
+                        .columns
+                        .first()
+                        .is_some_and(|c| c.expr.is_none() && c.pos_in_table == column)
+                })
+                .filter_map(|index| index_stats(index)?.rows_per_key.first())
+                .min()
+                .map(|rows_per_value| *rows_per_value as f64)
+        };
+        let is_indexed_expr = |expr: &ast::Expr| {
+            index_exprs
+                .iter()
+                .flatten()
+                .flatten()
+                .any(|index_expr| exprs_are_equivalent(index_expr, expr))
+        };
 
         let mut cs = TableConstraints {
             table_id: table_reference.internal_id,
             constraints: Vec::new(),
-            candidates: available_indexes
-                .get(table_reference.table.get_name())
-                .map_or(Vec::new(), |indexes| {
-                    indexes
-                        .iter()
-                        .map(|index| ConstraintUseCandidate {
-                            index: Some(index.clone()),
-                            refs: Vec::new(),
-                        })
-                        .collect()
-                }),
+            candidates: indexes
+                .iter()
+                .map(|index| ConstraintUseCandidate {
+                    index: Some(index.clone()),
+                    refs: Vec::new(),
+                    stats: index_stats(index).cloned(),
+                })
+                .collect(),
+            row_count,
         };
         // Add a candidate for the rowid index, which is always available when the table has a rowid alias.
         cs.candidates.push(ConstraintUseCandidate {
             index: None,
             refs: Vec::new(),
+            stats: None,
         });
 
         for (i, term) in where_clause.iter().enumerate() {
@@ -219,7 +310,8 @@ pub fn constraints_from_where_clause(
                 }
             }
 
-            // If either the LHS or RHS of the constraint is a column from the table, add the constraint.
+            // If either the LHS or RHS of the constraint is a column from the table,
+            // or an indexed expression of the table, add the constraint.
             match lhs {
                 ast::Expr::Column { table, column, .. } => {
                     if *table == table_reference.internal_id {
@@ -227,9 +319,14 @@ pub fn constraints_from_where_clause(
                         cs.constraints.push(Constraint {
                             where_clause_pos: (i, BinaryExprSide::Rhs),
                             operator,
-                            table_col_pos: *column,
+                            table_col_pos: Some(*column),
                             lhs_mask: table_mask_from_expr(rhs, table_references)?,
-                            selectivity: estimate_selectivity(table_column, operator),
+                            selectivity: estimate_selectivity(
+                                Some(table_column),
+                                operator,
+                                rows_per_value(*column),
+                                row_count,
+                            ),
                         });
                     }
                 }
@@ -243,12 +340,26 @@ pub fn constraints_from_where_clause(
                         cs.constraints.push(Constraint {
                             where_clause_pos: (i, BinaryExprSide::Rhs),
                             operator,
-                            table_col_pos: rowid_alias_column.unwrap(),
+                            table_col_pos: rowid_alias_column,
                             lhs_mask: table_mask_from_expr(rhs, table_references)?,
-                            selectivity: estimate_selectivity(table_column, operator),
+                            selectivity: estimate_selectivity(
+                                Some(table_column),
+                                operator,
+                                None,
+                                row_count,
+                            ),
                         });
                     }
                 }
+                expr if is_indexed_expr(expr) => {
+                    cs.constraints.push(Constraint {
+                        where_clause_pos: (i, BinaryExprSide::Rhs),
+                        operator,
+                        table_col_pos: None,
+                        lhs_mask: table_mask_from_expr(rhs, table_references)?,
+                        selectivity: estimate_selectivity(None, operator, None, row_count),
+                    });
+                }
                 _ => {}
             };
             match rhs {
@@ -258,9 +369,14 @@ pub fn constraints_from_where_clause(
                         cs.constraints.push(Constraint {
                             where_clause_pos: (i, BinaryExprSide::Lhs),
                             operator: opposite_cmp_op(operator),
-                            table_col_pos: *column,
+                            table_col_pos: Some(*column),
                             lhs_mask: table_mask_from_expr(lhs, table_references)?,
-                            selectivity: estimate_selectivity(table_column, operator),
+                            selectivity: estimate_selectivity(
+                                Some(table_column),
+                                operator,
+                                rows_per_value(*column),
+                                row_count,
+                            ),
                         });
                     }
                 }
@@ -271,12 +387,26 @@ pub fn constraints_from_where_clause(
                         cs.constraints.push(Constraint {
                             where_clause_pos: (i, BinaryExprSide::Lhs),
                             operator: opposite_cmp_op(operator),
-                            table_col_pos: rowid_alias_column.unwrap(),
+                            table_col_pos: rowid_alias_column,
                             lhs_mask: table_mask_from_expr(lhs, table_references)?,
-                            selectivity: estimate_selectivity(table_column, operator),
+                            selectivity: estimate_selectivity(
+                                Some(table_column),
+                                operator,
+                                None,
+                                row_count,
+                            ),
                         });
                     }
                 }
+                expr if is_indexed_expr(expr) => {
+                    cs.constraints.push(Constraint {
+                        where_clause_pos: (i, BinaryExprSide::Lhs),
+                        operator: opposite_cmp_op(operator),
+                        table_col_pos: None,
+                        lhs_mask: table_mask_from_expr(lhs, table_references)?,
+                        selectivity: estimate_selectivity(None, operator, None, row_count),
+                    });
+                }
                 _ => {}
             };
         }
@@ -294,7 +424,8 @@ pub fn constraints_from_where_clause(
 
         // For each constraint we found, add a reference to it for each index that may be able to use it.
         for (i, constraint) in cs.constraints.iter().enumerate() {
-            if rowid_alias_column == Some(constraint.table_col_pos) {
+            if constraint.table_col_pos.is_some() && rowid_alias_column == constraint.table_col_pos
+            {
                 let rowid_candidate = cs
                     .candidates
                     .iter_mut()
@@ -312,13 +443,22 @@ pub fn constraints_from_where_clause(
                     sort_order: SortOrder::Asc,
                 });
             }
-            for index in available_indexes
-                .get(table_reference.table.get_name())
-                .unwrap_or(&Vec::new())
-            {
-                if let Some(position_in_index) =
-                    index.column_table_pos_to_index_pos(constraint.table_col_pos)
-                {
+            let constrained_expr = constraint
+                .table_col_pos
+                .is_none()
+                .then(|| constraint.get_constrained_expr(where_clause));
+            for (index, index_exprs) in indexes.iter().zip(&index_exprs) {
+                let position_in_index = match &constrained_expr {
+                    Some(constrained_expr) => index_exprs.iter().position(|index_expr| {
+                        index_expr.as_ref().is_some_and(|index_expr| {
+                            exprs_are_equivalent(index_expr, constrained_expr)
+                        })
+                    }),
+                    None => constraint
+                        .table_col_pos
+                        .and_then(|pos| index.column_table_pos_to_index_pos(pos)),
+                };
+                if let Some(position_in_index) = position_in_index {
                     let index_candidate = cs
                         .candidates
                         .iter_mut()
@@ -365,12 +505,73 @@ pub fn constraints_from_where_clause(
                 candidate.refs.truncate(first_inequality + 1);
             }
         }
+
+        // A partial index can only be used if the WHERE clause implies the index predicate,
+        // since the index has no entries for the rows that do not satisfy it.
+        // ON terms of a RIGHT JOIN do not restrict which rows of this table are emitted.
+        let implied_terms = where_clause
+            .iter()
+            .filter(|term| {
+                term.from_outer_join.is_none_or(|outer_join_tbl| {
+                    outer_join_tbl == table_reference.internal_id
+                        && table_reference.join_info.as_ref().is_none_or(|j| !j.right)
+                })
+            })
+            .map(|term| &term.expr)
+            .collect::<Vec<_>>();
+        cs.candidates.retain(|candidate| {
+            let Some(index) = &candidate.index else {
+                return true;
+            };
+            let Some(where_clause) = &index.where_clause else {
+                return true;
+            };
+            bind_to_table_reference(where_clause, table_reference).is_some_and(|predicate| {
+                terms_imply_partial_index_predicate(&implied_terms, &predicate)
+            })
+        });
+
+        // INDEXED BY and NOT INDEXED restrict which indexes may be used to access the table.
+        match &table_reference.indexed {
+            Some(IndexedBy::Index(index)) => cs.candidates.retain(|candidate| {
+                candidate
+                    .index
+                    .as_ref()
+                    .is_some_and(|i| i.name == index.name)
+            }),
+            Some(IndexedBy::NotIndexed) => {
+                cs.candidates.retain(|candidate| candidate.index.is_none())
+            }
+            None => {}
+        }
         constraints.push(cs);
     }
 
     Ok(constraints)
 }
 
+/// Binds an indexed expression or the WHERE clause of a partial index to the columns of
+/// `table_reference`, so that it can be compared with the terms of a query.
+pub fn bind_to_table_reference(
+    index_expr: &ast::Expr,
+    table_reference: &JoinedTable,
+) -> Option<ast::Expr> {
+    let btree_table = table_reference.btree()?;
+    bind_index_expr(index_expr, &btree_table, |column| match column {
+        Some(pos) => ast::Expr::Column {
+            database: None,
+            table: table_reference.internal_id,
+            column: pos,
+            is_rowid_alias: btree_table.columns[pos].is_rowid_alias,
+        },
+        None => ast::Expr::RowId {
+            database: None,
+            table: table_reference.internal_id,
+        },
+    })
+    .ok()
+}
+
 /// Find which [Constraint]s are usable for a given join order.
 /// Returns a slice of the references to the constraints that are usable.
 /// A constraint is considered usable for a given table if all of the other tables referenced by the constraint
@@ -418,13 +619,14 @@ pub fn convert_to_vtab_constraint(
         .iter()
         .enumerate()
         .filter_map(|(i, constraint)| {
+            let table_col_pos = constraint.table_col_pos?;
             let other_side_refers_to_self = constraint.lhs_mask.contains_table(table_idx);
             if other_side_refers_to_self {
                 return None;
             }
             let all_required_tables_are_on_left_side = lhs_mask.contains_all(&constraint.lhs_mask);
             to_ext_constraint_op(&constraint.operator).map(|op| ConstraintInfo {
-                column_index: constraint.table_col_pos as u32,
+                column_index: table_col_pos as u32,
                 op,
                 usable: all_required_tables_are_on_left_side,
                 index: i,
@@ -1,3 +1,7 @@
+use turso_parser::ast;
+
+use crate::schema::IndexStats;
+
 use super::constraints::{Constraint, ConstraintRef};
 
 /// A simple newtype wrapper over a f64 that represents the cost of an operation.
@@ -40,19 +44,40 @@ pub fn estimate_page_io_cost(rowcount: f64) -> Cost {
 ///
 /// This is a very simple model that estimates the number of pages read
 /// based on the number of rows read, ignoring any CPU costs.
+/// `row_count` is the estimated number of rows in the table.
 pub fn estimate_cost_for_scan_or_seek(
     index_info: Option<IndexInfo>,
+    index_stats: Option<&IndexStats>,
     constraints: &[Constraint],
     usable_constraint_refs: &[ConstraintRef],
     input_cardinality: f64,
+    row_count: f64,
 ) -> Cost {
     let Some(index_info) = index_info else {
-        return estimate_page_io_cost(
-            input_cardinality * ESTIMATED_HARDCODED_ROWS_PER_TABLE as f64,
-        );
+        return estimate_page_io_cost(input_cardinality * row_count);
+    };
+
+    // If the index has been analyzed, the number of rows that have the same values in the
+    // columns constrained by equalities is known, and only the remaining constraints are
+    // estimated by their selectivity.
+    let num_equalities = usable_constraint_refs
+        .iter()
+        .take_while(|cref| constraints[cref.constraint_vec_pos].operator == ast::Operator::Equals)
+        .count();
+    let rows_per_key = index_stats.and_then(|stats| {
+        num_equalities
+            .checked_sub(1)
+            .and_then(|i| stats.rows_per_key.get(i))
+    });
+    let (matched_rows, estimated_constraint_refs) = match rows_per_key {
+        Some(rows_per_key) => (
+            *rows_per_key as f64,
+            &usable_constraint_refs[num_equalities..],
+        ),
+        None => (row_count, usable_constraint_refs),
     };
 
-    let selectivity_multiplier: f64 = usable_constraint_refs
+    let selectivity_multiplier: f64 = estimated_constraint_refs
         .iter()
         .map(|cref| {
             let constraint = &constraints[cref.constraint_vec_pos];
@@ -64,9 +89,6 @@ pub fn estimate_cost_for_scan_or_seek(
     let covering_multiplier = if index_info.covering { 0.9 } else { 1.0 };
 
     estimate_page_io_cost(
-        selectivity_multiplier
-            * ESTIMATED_HARDCODED_ROWS_PER_TABLE as f64
-            * input_cardinality
-            * covering_multiplier,
+        selectivity_multiplier * matched_rows * input_cardinality * covering_multiplier,
     )
 }
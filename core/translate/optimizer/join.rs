@@ -14,7 +14,6 @@ use crate::{
 use super::{
     access_method::{find_best_access_method_for_join_order, AccessMethod},
     constraints::TableConstraints,
-    cost::ESTIMATED_HARDCODED_ROWS_PER_TABLE,
     order::OrderTarget,
 };
 
@@ -98,10 +97,9 @@ pub fn join_lhs_and_rhs<'a>(
     // Produce a number of rows estimated to be returned when this table is filtered by the WHERE clause.
     // If this table is the rightmost table in the join order, we multiply by the input cardinality,
     // which is the output cardinality of the previous tables.
-    let output_cardinality = (input_cardinality as f64
-        * ESTIMATED_HARDCODED_ROWS_PER_TABLE as f64
-        * output_cardinality_multiplier)
-        .ceil() as usize;
+    let output_cardinality =
+        (input_cardinality as f64 * rhs_constraints.row_count * output_cardinality_multiplier)
+            .ceil() as usize;
 
     Ok(Some(JoinN {
         data: best_access_methods,
@@ -218,10 +216,19 @@ pub fn compute_best_join_order<'a>(
     // Example:
     // "a LEFT JOIN b" can NOT be reordered as "b LEFT JOIN a".
     // If there are outer joins in the plan, ensure correct ordering.
+    // The same goes for RIGHT JOINs, whose unmatched rows are emitted in a separate pass
+    // that NULLs out the tables to their left and runs the loops of the tables to their right,
+    // so the tables to the right of a RIGHT JOIN can not be moved before it either.
+    // Finally, CROSS JOIN is the user's way of pinning the join order: like in SQLite, the
+    // rhs of a CROSS JOIN is never moved before any of the tables to its left.
     let left_join_illegal_map = {
         let left_join_count = joined_tables
             .iter()
-            .filter(|t| t.join_info.as_ref().is_some_and(|j| j.outer))
+            .filter(|t| {
+                t.join_info
+                    .as_ref()
+                    .is_some_and(|j| j.outer || j.right || j.cross)
+            })
             .count();
         if left_join_count == 0 {
             None
@@ -229,9 +236,15 @@ pub fn compute_best_join_order<'a>(
             // map from rhs table index to lhs table index
             let mut left_join_illegal_map: HashMap<usize, TableMask> =
                 HashMap::with_capacity(left_join_count);
-            for (i, _) in joined_tables.iter().enumerate() {
+            for (i, table) in joined_tables.iter().enumerate() {
+                let is_right_join = table.join_info.as_ref().is_some_and(|j| j.right);
                 for (j, joined_table) in joined_tables.iter().enumerate().skip(i + 1) {
-                    if joined_table.join_info.as_ref().is_some_and(|j| j.outer) {
+                    if is_right_join
+                        || joined_table
+                            .join_info
+                            .as_ref()
+                            .is_some_and(|j| j.outer || j.right || j.cross)
+                    {
                         // bitwise OR the masks
                         if let Some(illegal_lhs) = left_join_illegal_map.get_mut(&i) {
                             illegal_lhs.add_table(j);
@@ -507,14 +520,18 @@ mod tests {
 
     use super::*;
     use crate::{
-        schema::{BTreeTable, Column, Index, IndexColumn, Table, Type},
+        schema::{
+            BTreeTable, Column, Index, IndexColumn, IndexStats, Table, TableStats, Type,
+            INDEX_EXPR_POS,
+        },
         translate::{
             optimizer::access_method::AccessMethodParams,
             optimizer::constraints::{
                 constraints_from_where_clause, BinaryExprSide, ConstraintRef,
             },
             plan::{
-                ColumnUsedMask, IterationDirection, JoinInfo, Operation, TableReferences, WhereTerm,
+                ColumnUsedMask, IndexedBy, IterationDirection, JoinInfo, Operation,
+                TableReferences, WhereTerm,
             },
             planner::TableMask,
         },
@@ -540,9 +557,13 @@ mod tests {
         let where_clause = vec![];
 
         let access_methods_arena = RefCell::new(Vec::new());
-        let table_constraints =
-            constraints_from_where_clause(&where_clause, &table_references, &available_indexes)
-                .unwrap();
+        let table_constraints = constraints_from_where_clause(
+            &where_clause,
+            &table_references,
+            &available_indexes,
+            &HashMap::new(),
+        )
+        .unwrap();
 
         let result = compute_best_join_order(
             table_references.joined_tables(),
@@ -569,9 +590,13 @@ mod tests {
         let where_clause = vec![];
 
         let access_methods_arena = RefCell::new(Vec::new());
-        let table_constraints =
-            constraints_from_where_clause(&where_clause, &table_references, &available_indexes)
-                .unwrap();
+        let table_constraints = constraints_from_where_clause(
+            &where_clause,
+            &table_references,
+            &available_indexes,
+            &HashMap::new(),
+        )
+        .unwrap();
 
         // SELECT * from test_table
         // expecting best_best_plan() not to do any work due to empty where clause.
@@ -610,9 +635,13 @@ mod tests {
         let table_references = TableReferences::new(joined_tables, vec![]);
         let access_methods_arena = RefCell::new(Vec::new());
         let available_indexes = HashMap::new();
-        let table_constraints =
-            constraints_from_where_clause(&where_clause, &table_references, &available_indexes)
-                .unwrap();
+        let table_constraints = constraints_from_where_clause(
+            &where_clause,
+            &table_references,
+            &available_indexes,
+            &HashMap::new(),
+        )
+        .unwrap();
 
         // SELECT * FROM test_table WHERE id = 42
         // expecting a RowidEq access method because id is a rowid alias.
@@ -670,17 +699,24 @@ mod tests {
                 pos_in_table: 0,
                 collation: None,
                 default: None,
+                expr: None,
             }],
             unique: true,
             ephemeral: false,
             root_page: 1,
             has_rowid: true,
+            num_key_columns: None,
+            where_clause: None,
         });
         available_indexes.insert("test_table".to_string(), vec![index]);
 
-        let table_constraints =
-            constraints_from_where_clause(&where_clause, &table_references, &available_indexes)
-                .unwrap();
+        let table_constraints = constraints_from_where_clause(
+            &where_clause,
+            &table_references,
+            &available_indexes,
+            &HashMap::new(),
+        )
+        .unwrap();
         // SELECT * FROM test_table WHERE id = 42
         // expecting an IndexScan access method because id is a primary key with an index
         let result = compute_best_join_order(
@@ -719,6 +755,8 @@ mod tests {
                 t2.clone(),
                 Some(JoinInfo {
                     outer: false,
+                    right: false,
+                    cross: false,
                     using: vec![],
                 }),
                 table_id_counter.next(),
@@ -739,11 +777,14 @@ mod tests {
                 pos_in_table: 0,
                 collation: None,
                 default: None,
+                expr: None,
             }],
             unique: true,
             ephemeral: false,
             root_page: 1,
             has_rowid: true,
+            num_key_columns: None,
+            where_clause: None,
         });
         available_indexes.insert("table1".to_string(), vec![index1]);
 
@@ -757,9 +798,13 @@ mod tests {
 
         let table_references = TableReferences::new(joined_tables, vec![]);
         let access_methods_arena = RefCell::new(Vec::new());
-        let table_constraints =
-            constraints_from_where_clause(&where_clause, &table_references, &available_indexes)
-                .unwrap();
+        let table_constraints = constraints_from_where_clause(
+            &where_clause,
+            &table_references,
+            &available_indexes,
+            &HashMap::new(),
+        )
+        .unwrap();
 
         let result = compute_best_join_order(
             table_references.joined_tables(),
@@ -788,6 +833,607 @@ mod tests {
         );
     }
 
+    #[test]
+    /// Test that [compute_best_join_order] does not move the right table of a RIGHT JOIN to the outer position,
+    /// even when an index can be used on the left table.
+    fn test_compute_best_join_order_right_join() {
+        let t1 = _create_btree_table("table1", _create_column_list(&["id"], Type::Integer));
+        let t2 = _create_btree_table("table2", _create_column_list(&["id"], Type::Integer));
+        let t3 = _create_btree_table("table3", _create_column_list(&["id"], Type::Integer));
+
+        let mut table_id_counter = TableRefIdCounter::new();
+        let joined_tables = vec![
+            _create_table_reference(t1.clone(), None, table_id_counter.next()),
+            _create_table_reference(
+                t2.clone(),
+                Some(JoinInfo {
+                    outer: false,
+                    right: true,
+                    cross: false,
+                    using: vec![],
+                }),
+                table_id_counter.next(),
+            ),
+            _create_table_reference(
+                t3.clone(),
+                Some(JoinInfo {
+                    outer: false,
+                    right: false,
+                    cross: false,
+                    using: vec![],
+                }),
+                table_id_counter.next(),
+            ),
+        ];
+
+        const TABLE1: usize = 0;
+        const TABLE2: usize = 1;
+        const TABLE3: usize = 2;
+
+        let mut available_indexes = HashMap::new();
+        // Index on the left table of the RIGHT JOIN (table1)
+        let index1 = Arc::new(Index {
+            name: "index1".to_string(),
+            table_name: "table1".to_string(),
+            columns: vec![IndexColumn {
+                name: "id".to_string(),
+                order: SortOrder::Asc,
+                pos_in_table: 0,
+                collation: None,
+                default: None,
+                expr: None,
+            }],
+            unique: true,
+            ephemeral: false,
+            root_page: 1,
+            has_rowid: true,
+            num_key_columns: None,
+            where_clause: None,
+        });
+        available_indexes.insert("table1".to_string(), vec![index1]);
+
+        // SELECT * FROM table1 RIGHT JOIN table2 JOIN table3 WHERE table1.id = table2.id AND table1.id = table3.id
+        // an inner join would put table1 in the inner position to use its index,
+        // but the RIGHT JOIN requires table1 to come before table2, and table3 to come after table2.
+        let where_clause = vec![
+            _create_binary_expr(
+                _create_column_expr(joined_tables[TABLE1].internal_id, 0, false), // table1.id
+                ast::Operator::Equals,
+                _create_column_expr(joined_tables[TABLE2].internal_id, 0, false), // table2.id
+            ),
+            _create_binary_expr(
+                _create_column_expr(joined_tables[TABLE1].internal_id, 0, false), // table1.id
+                ast::Operator::Equals,
+                _create_column_expr(joined_tables[TABLE3].internal_id, 0, false), // table3.id
+            ),
+        ];
+
+        let table_references = TableReferences::new(joined_tables, vec![]);
+        let access_methods_arena = RefCell::new(Vec::new());
+        let table_constraints = constraints_from_where_clause(
+            &where_clause,
+            &table_references,
+            &available_indexes,
+            &HashMap::new(),
+        )
+        .unwrap();
+
+        let result = compute_best_join_order(
+            table_references.joined_tables(),
+            None,
+            &table_constraints,
+            &access_methods_arena,
+        )
+        .unwrap();
+        assert!(result.is_some());
+        let BestJoinOrderResult { best_plan, .. } = result.unwrap();
+        assert_eq!(
+            best_plan.table_numbers().collect::<Vec<_>>(),
+            vec![TABLE1, TABLE2, TABLE3]
+        );
+    }
+
+    #[test]
+    /// Test that [compute_best_join_order] does not move the right table of a CROSS JOIN to the outer position,
+    /// even when an index can be used on the left table.
+    fn test_compute_best_join_order_cross_join() {
+        let t1 = _create_btree_table("table1", _create_column_list(&["id"], Type::Integer));
+        let t2 = _create_btree_table("table2", _create_column_list(&["id"], Type::Integer));
+
+        let mut table_id_counter = TableRefIdCounter::new();
+        let joined_tables = vec![
+            _create_table_reference(t1.clone(), None, table_id_counter.next()),
+            _create_table_reference(
+                t2.clone(),
+                Some(JoinInfo {
+                    outer: false,
+                    right: false,
+                    cross: true,
+                    using: vec![],
+                }),
+                table_id_counter.next(),
+            ),
+        ];
+
+        const TABLE1: usize = 0;
+        const TABLE2: usize = 1;
+
+        let mut available_indexes = HashMap::new();
+        // Index on the left table of the CROSS JOIN (table1)
+        let index1 = Arc::new(Index {
+            name: "index1".to_string(),
+            table_name: "table1".to_string(),
+            columns: vec![IndexColumn {
+                name: "id".to_string(),
+                order: SortOrder::Asc,
+                pos_in_table: 0,
+                collation: None,
+                default: None,
+                expr: None,
+            }],
+            unique: true,
+            ephemeral: false,
+            root_page: 1,
+            has_rowid: true,
+            num_key_columns: None,
+            where_clause: None,
+        });
+        available_indexes.insert("table1".to_string(), vec![index1]);
+
+        // SELECT * FROM table1 CROSS JOIN table2 WHERE table1.id = table2.id
+        // an inner join would put table2 first to use the index on table1.id,
+        // but CROSS JOIN pins table1 as the outer table.
+        let where_clause = vec![_create_binary_expr(
+            _create_column_expr(joined_tables[TABLE1].internal_id, 0, false), // table1.id
+            ast::Operator::Equals,
+            _create_column_expr(joined_tables[TABLE2].internal_id, 0, false), // table2.id
+        )];
+
+        let table_references = TableReferences::new(joined_tables, vec![]);
+        let access_methods_arena = RefCell::new(Vec::new());
+        let table_constraints = constraints_from_where_clause(
+            &where_clause,
+            &table_references,
+            &available_indexes,
+            &HashMap::new(),
+        )
+        .unwrap();
+
+        let result = compute_best_join_order(
+            table_references.joined_tables(),
+            None,
+            &table_constraints,
+            &access_methods_arena,
+        )
+        .unwrap();
+        assert!(result.is_some());
+        let BestJoinOrderResult { best_plan, .. } = result.unwrap();
+        assert_eq!(
+            best_plan.table_numbers().collect::<Vec<_>>(),
+            vec![TABLE1, TABLE2]
+        );
+    }
+
+    #[test]
+    /// Test that [compute_best_join_order] uses the index named by INDEXED BY, even if another index could be used for a seek.
+    fn test_compute_best_join_order_indexed_by() {
+        let t1 = _create_btree_table("table1", _create_column_list(&["id", "x"], Type::Integer));
+        let index1 = Arc::new(Index {
+            name: "index1".to_string(),
+            table_name: "table1".to_string(),
+            columns: vec![IndexColumn {
+                name: "id".to_string(),
+                order: SortOrder::Asc,
+                pos_in_table: 0,
+                collation: None,
+                default: None,
+                expr: None,
+            }],
+            unique: true,
+            ephemeral: false,
+            root_page: 1,
+            has_rowid: true,
+            num_key_columns: None,
+            where_clause: None,
+        });
+        let index2 = Arc::new(Index {
+            name: "index2".to_string(),
+            table_name: "table1".to_string(),
+            columns: vec![IndexColumn {
+                name: "x".to_string(),
+                order: SortOrder::Asc,
+                pos_in_table: 1,
+                collation: None,
+                default: None,
+                expr: None,
+            }],
+            unique: false,
+            ephemeral: false,
+            root_page: 2,
+            has_rowid: true,
+            num_key_columns: None,
+            where_clause: None,
+        });
+        let mut available_indexes = HashMap::new();
+        available_indexes.insert("table1".to_string(), vec![index1, index2.clone()]);
+
+        let mut table_id_counter = TableRefIdCounter::new();
+        let mut table_ref = _create_table_reference(t1.clone(), None, table_id_counter.next());
+        table_ref.indexed = Some(IndexedBy::Index(index2));
+        let joined_tables = vec![table_ref];
+
+        // SELECT * FROM table1 INDEXED BY index2 WHERE id = 42
+        // expecting a full scan of index2, although index1 could be used for a seek.
+        let where_clause = vec![_create_binary_expr(
+            _create_column_expr(joined_tables[0].internal_id, 0, false), // table1.id
+            ast::Operator::Equals,
+            _create_numeric_literal("42"),
+        )];
+
+        let table_references = TableReferences::new(joined_tables, vec![]);
+        let access_methods_arena = RefCell::new(Vec::new());
+        let table_constraints = constraints_from_where_clause(
+            &where_clause,
+            &table_references,
+            &available_indexes,
+            &HashMap::new(),
+        )
+        .unwrap();
+
+        let result = compute_best_join_order(
+            table_references.joined_tables(),
+            None,
+            &table_constraints,
+            &access_methods_arena,
+        )
+        .unwrap();
+        assert!(result.is_some());
+        let BestJoinOrderResult { best_plan, .. } = result.unwrap();
+        let access_method = &access_methods_arena.borrow()[best_plan.data[0].1];
+        let (_, index, constraint_refs) = _as_btree(access_method);
+        assert!(constraint_refs.is_empty());
+        assert!(index.as_ref().unwrap().name == "index2");
+    }
+
+    #[test]
+    /// Test that [compute_best_join_order] does not use an index for a NOT INDEXED table.
+    fn test_compute_best_join_order_not_indexed() {
+        let t1 = _create_btree_table("table1", _create_column_list(&["id"], Type::Integer));
+        let index1 = Arc::new(Index {
+            name: "index1".to_string(),
+            table_name: "table1".to_string(),
+            columns: vec![IndexColumn {
+                name: "id".to_string(),
+                order: SortOrder::Asc,
+                pos_in_table: 0,
+                collation: None,
+                default: None,
+                expr: None,
+            }],
+            unique: true,
+            ephemeral: false,
+            root_page: 1,
+            has_rowid: true,
+            num_key_columns: None,
+            where_clause: None,
+        });
+        let mut available_indexes = HashMap::new();
+        available_indexes.insert("table1".to_string(), vec![index1]);
+
+        let mut table_id_counter = TableRefIdCounter::new();
+        let mut table_ref = _create_table_reference(t1.clone(), None, table_id_counter.next());
+        table_ref.indexed = Some(IndexedBy::NotIndexed);
+        let joined_tables = vec![table_ref];
+
+        // SELECT * FROM table1 NOT INDEXED WHERE id = 42
+        // expecting a full table scan, although index1 could be used for a seek.
+        let where_clause = vec![_create_binary_expr(
+            _create_column_expr(joined_tables[0].internal_id, 0, false), // table1.id
+            ast::Operator::Equals,
+            _create_numeric_literal("42"),
+        )];
+
+        let table_references = TableReferences::new(joined_tables, vec![]);
+        let access_methods_arena = RefCell::new(Vec::new());
+        let table_constraints = constraints_from_where_clause(
+            &where_clause,
+            &table_references,
+            &available_indexes,
+            &HashMap::new(),
+        )
+        .unwrap();
+
+        let result = compute_best_join_order(
+            table_references.joined_tables(),
+            None,
+            &table_constraints,
+            &access_methods_arena,
+        )
+        .unwrap();
+        assert!(result.is_some());
+        let BestJoinOrderResult { best_plan, .. } = result.unwrap();
+        let access_method = &access_methods_arena.borrow()[best_plan.data[0].1];
+        let (_, index, constraint_refs) = _as_btree(access_method);
+        assert!(constraint_refs.is_empty());
+        assert!(index.is_none());
+    }
+
+    #[test]
+    /// Test that [compute_best_join_order] only uses a partial index when the WHERE clause implies its predicate.
+    fn test_compute_best_join_order_partial_index() {
+        let t1 = _create_btree_table("table1", _create_column_list(&["id", "x"], Type::Integer));
+        let index1 = Arc::new(Index {
+            name: "index1".to_string(),
+            table_name: "table1".to_string(),
+            columns: vec![IndexColumn {
+                name: "x".to_string(),
+                order: SortOrder::Asc,
+                pos_in_table: 1,
+                collation: None,
+                default: None,
+                expr: None,
+            }],
+            unique: false,
+            ephemeral: false,
+            root_page: 1,
+            has_rowid: true,
+            num_key_columns: None,
+            // CREATE INDEX index1 ON table1(x) WHERE x > 0
+            where_clause: Some(Box::new(Expr::Binary(
+                Box::new(Expr::Id(ast::Name::Ident("x".to_string()))),
+                ast::Operator::Greater,
+                Box::new(_create_numeric_literal("0")),
+            ))),
+        });
+        let mut available_indexes = HashMap::new();
+        available_indexes.insert("table1".to_string(), vec![index1]);
+
+        let mut table_id_counter = TableRefIdCounter::new();
+        let joined_tables = vec![_create_table_reference(
+            t1.clone(),
+            None,
+            table_id_counter.next(),
+        )];
+        let table_id = joined_tables[0].internal_id;
+        let table_references = TableReferences::new(joined_tables, vec![]);
+
+        // SELECT * FROM table1 WHERE x = 42
+        // expecting a full table scan, since rows with x = 42 are not known to satisfy x > 0.
+        let mut where_clause = vec![_create_binary_expr(
+            _create_column_expr(table_id, 1, false), // table1.x
+            ast::Operator::Equals,
+            _create_numeric_literal("42"),
+        )];
+        let access_methods_arena = RefCell::new(Vec::new());
+        let table_constraints = constraints_from_where_clause(
+            &where_clause,
+            &table_references,
+            &available_indexes,
+            &HashMap::new(),
+        )
+        .unwrap();
+        let result = compute_best_join_order(
+            table_references.joined_tables(),
+            None,
+            &table_constraints,
+            &access_methods_arena,
+        )
+        .unwrap();
+        let BestJoinOrderResult { best_plan, .. } = result.unwrap();
+        let access_method = &access_methods_arena.borrow()[best_plan.data[0].1];
+        let (_, index, constraint_refs) = _as_btree(access_method);
+        assert!(constraint_refs.is_empty());
+        assert!(index.is_none());
+
+        // SELECT * FROM table1 WHERE x = 42 AND x > 0
+        // expecting a seek on index1, since the WHERE clause implies the index predicate.
+        where_clause.push(_create_binary_expr(
+            _create_column_expr(table_id, 1, false), // table1.x
+            ast::Operator::Greater,
+            _create_numeric_literal("0"),
+        ));
+        let access_methods_arena = RefCell::new(Vec::new());
+        let table_constraints = constraints_from_where_clause(
+            &where_clause,
+            &table_references,
+            &available_indexes,
+            &HashMap::new(),
+        )
+        .unwrap();
+        let result = compute_best_join_order(
+            table_references.joined_tables(),
+            None,
+            &table_constraints,
+            &access_methods_arena,
+        )
+        .unwrap();
+        let BestJoinOrderResult { best_plan, .. } = result.unwrap();
+        let access_method = &access_methods_arena.borrow()[best_plan.data[0].1];
+        let (_, index, constraint_refs) = _as_btree(access_method);
+        assert!(!constraint_refs.is_empty());
+        assert!(index.as_ref().unwrap().name == "index1");
+    }
+
+    #[test]
+    /// Test that [compute_best_join_order] uses an expression index for a term on the indexed expression.
+    fn test_compute_best_join_order_expression_index() {
+        let t1 = _create_btree_table("table1", _create_column_list(&["id", "x"], Type::Integer));
+        // CREATE INDEX index1 ON table1(-x)
+        let index1 = Arc::new(Index {
+            name: "index1".to_string(),
+            table_name: "table1".to_string(),
+            columns: vec![IndexColumn {
+                name: "-x".to_string(),
+                order: SortOrder::Asc,
+                pos_in_table: INDEX_EXPR_POS,
+                collation: None,
+                default: None,
+                expr: Some(Box::new(Expr::Unary(
+                    ast::UnaryOperator::Negative,
+                    Box::new(Expr::Id(ast::Name::Ident("x".to_string()))),
+                ))),
+            }],
+            unique: false,
+            ephemeral: false,
+            root_page: 1,
+            has_rowid: true,
+            num_key_columns: None,
+            where_clause: None,
+        });
+        let mut available_indexes = HashMap::new();
+        available_indexes.insert("table1".to_string(), vec![index1]);
+
+        let mut table_id_counter = TableRefIdCounter::new();
+        let joined_tables = vec![_create_table_reference(
+            t1.clone(),
+            None,
+            table_id_counter.next(),
+        )];
+        let table_id = joined_tables[0].internal_id;
+        let table_references = TableReferences::new(joined_tables, vec![]);
+
+        // SELECT * FROM table1 WHERE -x = 42
+        // expecting a seek on index1, since the term constrains the indexed expression.
+        let where_clause = vec![_create_binary_expr(
+            Expr::Unary(
+                ast::UnaryOperator::Negative,
+                Box::new(_create_column_expr(table_id, 1, false)), // -table1.x
+            ),
+            ast::Operator::Equals,
+            _create_numeric_literal("42"),
+        )];
+        let access_methods_arena = RefCell::new(Vec::new());
+        let table_constraints = constraints_from_where_clause(
+            &where_clause,
+            &table_references,
+            &available_indexes,
+            &HashMap::new(),
+        )
+        .unwrap();
+        assert_eq!(table_constraints[0].constraints.len(), 1);
+        assert!(table_constraints[0].constraints[0].table_col_pos.is_none());
+        let result = compute_best_join_order(
+            table_references.joined_tables(),
+            None,
+            &table_constraints,
+            &access_methods_arena,
+        )
+        .unwrap();
+        let BestJoinOrderResult { best_plan, .. } = result.unwrap();
+        let access_method = &access_methods_arena.borrow()[best_plan.data[0].1];
+        let (_, index, constraint_refs) = _as_btree(access_method);
+        assert_eq!(constraint_refs.len(), 1);
+        assert!(index.as_ref().unwrap().name == "index1");
+    }
+
+    #[test]
+    /// Test that [compute_best_join_order] uses sqlite_stat1 statistics to pick the most selective index.
+    fn test_compute_best_join_order_uses_index_stats() {
+        let t1 = _create_btree_table(
+            "table1",
+            _create_column_list(&["id", "a", "b"], Type::Integer),
+        );
+        let create_index = |name: &str, column: &str, pos_in_table: usize| {
+            Arc::new(Index {
+                name: name.to_string(),
+                table_name: "table1".to_string(),
+                columns: vec![IndexColumn {
+                    name: column.to_string(),
+                    order: SortOrder::Asc,
+                    pos_in_table,
+                    collation: None,
+                    default: None,
+                    expr: None,
+                }],
+                unique: false,
+                ephemeral: false,
+                root_page: 1,
+                has_rowid: true,
+                num_key_columns: None,
+                where_clause: None,
+            })
+        };
+        // CREATE INDEX index_a ON table1(a); CREATE INDEX index_b ON table1(b)
+        let mut available_indexes = HashMap::new();
+        available_indexes.insert(
+            "table1".to_string(),
+            vec![
+                create_index("index_a", "a", 1),
+                create_index("index_b", "b", 2),
+            ],
+        );
+
+        // index_a has few distinct values, index_b is nearly unique.
+        let mut table_stats = HashMap::new();
+        table_stats.insert(
+            "table1".to_string(),
+            TableStats {
+                row_count: Some(10000),
+                indexes: HashMap::from([
+                    (
+                        "index_a".to_string(),
+                        IndexStats {
+                            row_count: 10000,
+                            rows_per_key: vec![5000],
+                        },
+                    ),
+                    (
+                        "index_b".to_string(),
+                        IndexStats {
+                            row_count: 10000,
+                            rows_per_key: vec![1],
+                        },
+                    ),
+                ]),
+            },
+        );
+
+        let mut table_id_counter = TableRefIdCounter::new();
+        let joined_tables = vec![_create_table_reference(
+            t1.clone(),
+            None,
+            table_id_counter.next(),
+        )];
+        let table_id = joined_tables[0].internal_id;
+        let table_references = TableReferences::new(joined_tables, vec![]);
+
+        // SELECT * FROM table1 WHERE a = 1 AND b = 2
+        // expecting a seek on index_b, since it matches far fewer rows than index_a.
+        let where_clause = vec![
+            _create_binary_expr(
+                _create_column_expr(table_id, 1, false), // table1.a
+                ast::Operator::Equals,
+                _create_numeric_literal("1"),
+            ),
+            _create_binary_expr(
+                _create_column_expr(table_id, 2, false), // table1.b
+                ast::Operator::Equals,
+                _create_numeric_literal("2"),
+            ),
+        ];
+        let access_methods_arena = RefCell::new(Vec::new());
+        let table_constraints = constraints_from_where_clause(
+            &where_clause,
+            &table_references,
+            &available_indexes,
+            &table_stats,
+        )
+        .unwrap();
+        assert_eq!(table_constraints[0].row_count, 10000.0);
+        let result = compute_best_join_order(
+            table_references.joined_tables(),
+            None,
+            &table_constraints,
+            &access_methods_arena,
+        )
+        .unwrap();
+        let BestJoinOrderResult { best_plan, .. } = result.unwrap();
+        let access_method = &access_methods_arena.borrow()[best_plan.data[0].1];
+        let (_, index, constraint_refs) = _as_btree(access_method);
+        assert_eq!(constraint_refs.len(), 1);
+        assert!(index.as_ref().unwrap().name == "index_b");
+    }
+
     #[test]
     /// Test that [compute_best_join_order] returns a sensible order and plan for three tables, each with indexes.
     fn test_compute_best_join_order_three_tables_indexed() {
@@ -823,6 +1469,8 @@ mod tests {
                 table_customers.clone(),
                 Some(JoinInfo {
                     outer: false,
+                    right: false,
+                    cross: false,
                     using: vec![],
                 }),
                 table_id_counter.next(),
@@ -831,6 +1479,8 @@ mod tests {
                 table_order_items.clone(),
                 Some(JoinInfo {
                     outer: false,
+                    right: false,
+                    cross: false,
                     using: vec![],
                 }),
                 table_id_counter.next(),
@@ -856,11 +1506,14 @@ mod tests {
                         pos_in_table: 0,
                         collation: None,
                         default: None,
+                        expr: None,
                     }],
                     unique: true,
                     ephemeral: false,
                     root_page: 1,
                     has_rowid: true,
+                    num_key_columns: None,
+                    where_clause: None,
                 });
                 available_indexes.insert(table_name.to_string(), vec![index]);
             });
@@ -873,11 +1526,14 @@ mod tests {
                 pos_in_table: 1,
                 collation: None,
                 default: None,
+                expr: None,
             }],
             unique: false,
             ephemeral: false,
             root_page: 1,
             has_rowid: true,
+            num_key_columns: None,
+            where_clause: None,
         });
         let order_id_idx = Arc::new(Index {
             name: "order_items_order_id_idx".to_string(),
@@ -888,11 +1544,14 @@ mod tests {
                 pos_in_table: 1,
                 collation: None,
                 default: None,
+                expr: None,
             }],
             unique: false,
             ephemeral: false,
             root_page: 1,
             has_rowid: true,
+            num_key_columns: None,
+            where_clause: None,
         });
 
         available_indexes
@@ -930,9 +1589,13 @@ mod tests {
 
         let table_references = TableReferences::new(joined_tables, vec![]);
         let access_methods_arena = RefCell::new(Vec::new());
-        let table_constraints =
-            constraints_from_where_clause(&where_clause, &table_references, &available_indexes)
-                .unwrap();
+        let table_constraints = constraints_from_where_clause(
+            &where_clause,
+            &table_references,
+            &available_indexes,
+            &HashMap::new(),
+        )
+        .unwrap();
 
         let result = compute_best_join_order(
             table_references.joined_tables(),
@@ -1007,6 +1670,8 @@ mod tests {
                 t2.clone(),
                 Some(JoinInfo {
                     outer: false,
+                    right: false,
+                    cross: false,
                     using: vec![],
                 }),
                 table_id_counter.next(),
@@ -1015,6 +1680,8 @@ mod tests {
                 t3.clone(),
                 Some(JoinInfo {
                     outer: false,
+                    right: false,
+                    cross: false,
                     using: vec![],
                 }),
                 table_id_counter.next(),
@@ -1039,9 +1706,13 @@ mod tests {
         let table_references = TableReferences::new(joined_tables, vec![]);
         let available_indexes = HashMap::new();
         let access_methods_arena = RefCell::new(Vec::new());
-        let table_constraints =
-            constraints_from_where_clause(&where_clause, &table_references, &available_indexes)
-                .unwrap();
+        let table_constraints = constraints_from_where_clause(
+            &where_clause,
+            &table_references,
+            &available_indexes,
+            &HashMap::new(),
+        )
+        .unwrap();
 
         let BestJoinOrderResult { best_plan, .. } = compute_best_join_order(
             table_references.joined_tables(),
@@ -1113,6 +1784,8 @@ mod tests {
                     t.clone(),
                     Some(JoinInfo {
                         outer: false,
+                        right: false,
+                        cross: false,
                         using: vec![],
                     }),
                     table_id_counter.next(),
@@ -1122,6 +1795,8 @@ mod tests {
                 fact_table.clone(),
                 Some(JoinInfo {
                     outer: false,
+                    right: false,
+                    cross: false,
                     using: vec![],
                 }),
                 table_id_counter.next(),
@@ -1145,9 +1820,13 @@ mod tests {
         let table_references = TableReferences::new(joined_tables, vec![]);
         let access_methods_arena = RefCell::new(Vec::new());
         let available_indexes = HashMap::new();
-        let table_constraints =
-            constraints_from_where_clause(&where_clause, &table_references, &available_indexes)
-                .unwrap();
+        let table_constraints = constraints_from_where_clause(
+            &where_clause,
+            &table_references,
+            &available_indexes,
+            &HashMap::new(),
+        )
+        .unwrap();
 
         let result = compute_best_join_order(
             table_references.joined_tables(),
@@ -1229,9 +1908,13 @@ mod tests {
 
         let table_references = TableReferences::new(joined_tables, vec![]);
         let access_methods_arena = RefCell::new(Vec::new());
-        let table_constraints =
-            constraints_from_where_clause(&where_clause, &table_references, &available_indexes)
-                .unwrap();
+        let table_constraints = constraints_from_where_clause(
+            &where_clause,
+            &table_references,
+            &available_indexes,
+            &HashMap::new(),
+        )
+        .unwrap();
 
         // Run the optimizer
         let BestJoinOrderResult { best_plan, .. } = compute_best_join_order(
@@ -1302,6 +1985,7 @@ mod tests {
                     pos_in_table: 0,
                     collation: None,
                     default: None,
+                    expr: None,
                 },
                 IndexColumn {
                     name: "y".to_string(),
@@ -1309,12 +1993,15 @@ mod tests {
                     pos_in_table: 1,
                     collation: None,
                     default: None,
+                    expr: None,
                 },
             ],
             unique: false,
             root_page: 2,
             ephemeral: false,
             has_rowid: true,
+            num_key_columns: None,
+            where_clause: None,
         });
 
         let mut available_indexes = HashMap::new();
@@ -1329,6 +2016,7 @@ mod tests {
             join_info: None,
             col_used_mask: ColumnUsedMask::default(),
             database_id: 0,
+            indexed: None,
         });
 
         // Create where clause that only references second column
@@ -1349,9 +2037,13 @@ mod tests {
 
         let table_references = TableReferences::new(joined_tables, vec![]);
         let access_methods_arena = RefCell::new(Vec::new());
-        let table_constraints =
-            constraints_from_where_clause(&where_clause, &table_references, &available_indexes)
-                .unwrap();
+        let table_constraints = constraints_from_where_clause(
+            &where_clause,
+            &table_references,
+            &available_indexes,
+            &HashMap::new(),
+        )
+        .unwrap();
 
         let BestJoinOrderResult { best_plan, .. } = compute_best_join_order(
             table_references.joined_tables(),
@@ -1388,6 +2080,7 @@ mod tests {
                     pos_in_table: 0,
                     collation: None,
                     default: None,
+                    expr: None,
                 },
                 IndexColumn {
                     name: "c2".to_string(),
@@ -1395,6 +2088,7 @@ mod tests {
                     pos_in_table: 1,
                     collation: None,
                     default: None,
+                    expr: None,
                 },
                 IndexColumn {
                     name: "c3".to_string(),
@@ -1402,12 +2096,15 @@ mod tests {
                     pos_in_table: 2,
                     collation: None,
                     default: None,
+                    expr: None,
                 },
             ],
             unique: false,
             root_page: 2,
             ephemeral: false,
             has_rowid: true,
+            num_key_columns: None,
+            where_clause: None,
         });
         available_indexes.insert("t1".to_string(), vec![index]);
 
@@ -1420,6 +2117,7 @@ mod tests {
             join_info: None,
             col_used_mask: ColumnUsedMask::default(),
             database_id: 0,
+            indexed: None,
         });
 
         // Create where clause that references first and third columns
@@ -1456,9 +2154,13 @@ mod tests {
 
         let table_references = TableReferences::new(joined_tables, vec![]);
         let access_methods_arena = RefCell::new(Vec::new());
-        let table_constraints =
-            constraints_from_where_clause(&where_clause, &table_references, &available_indexes)
-                .unwrap();
+        let table_constraints = constraints_from_where_clause(
+            &where_clause,
+            &table_references,
+            &available_indexes,
+            &HashMap::new(),
+        )
+        .unwrap();
 
         let BestJoinOrderResult { best_plan, .. } = compute_best_join_order(
             table_references.joined_tables(),
@@ -1476,7 +2178,7 @@ mod tests {
         assert!(constraint_refs.len() == 1);
         let constraint = &table_constraints[0].constraints[constraint_refs[0].constraint_vec_pos];
         assert!(constraint.operator == ast::Operator::Equals);
-        assert!(constraint.table_col_pos == 0); // c1
+        assert!(constraint.table_col_pos == Some(0)); // c1
     }
 
     #[test]
@@ -1499,6 +2201,7 @@ mod tests {
                     pos_in_table: 0,
                     collation: None,
                     default: None,
+                    expr: None,
                 },
                 IndexColumn {
                     name: "c2".to_string(),
@@ -1506,6 +2209,7 @@ mod tests {
                     pos_in_table: 1,
                     collation: None,
                     default: None,
+                    expr: None,
                 },
                 IndexColumn {
                     name: "c3".to_string(),
@@ -1513,12 +2217,15 @@ mod tests {
                     pos_in_table: 2,
                     collation: None,
                     default: None,
+                    expr: None,
                 },
             ],
             root_page: 2,
             ephemeral: false,
             has_rowid: true,
+            num_key_columns: None,
             unique: false,
+            where_clause: None,
         });
         available_indexes.insert("t1".to_string(), vec![index]);
 
@@ -1531,6 +2238,7 @@ mod tests {
             join_info: None,
             col_used_mask: ColumnUsedMask::default(),
             database_id: 0,
+            indexed: None,
         });
 
         // Create where clause: c1 = 5 AND c2 > 10 AND c3 = 7
@@ -1581,9 +2289,13 @@ mod tests {
 
         let table_references = TableReferences::new(joined_tables, vec![]);
         let access_methods_arena = RefCell::new(Vec::new());
-        let table_constraints =
-            constraints_from_where_clause(&where_clause, &table_references, &available_indexes)
-                .unwrap();
+        let table_constraints = constraints_from_where_clause(
+            &where_clause,
+            &table_references,
+            &available_indexes,
+            &HashMap::new(),
+        )
+        .unwrap();
 
         let BestJoinOrderResult { best_plan, .. } = compute_best_join_order(
             table_references.joined_tables(),
@@ -1602,10 +2314,10 @@ mod tests {
         assert!(constraint_refs.len() == 2);
         let constraint = &table_constraints[0].constraints[constraint_refs[0].constraint_vec_pos];
         assert!(constraint.operator == ast::Operator::Equals);
-        assert!(constraint.table_col_pos == 0); // c1
+        assert!(constraint.table_col_pos == Some(0)); // c1
         let constraint = &table_constraints[0].constraints[constraint_refs[1].constraint_vec_pos];
         assert!(constraint.operator == ast::Operator::Greater);
-        assert!(constraint.table_col_pos == 1); // c2
+        assert!(constraint.table_col_pos == Some(1)); // c2
     }
 
     fn _create_column(c: &TestColumn) -> Column {
@@ -1620,6 +2332,7 @@ mod tests {
             unique: false,
             collation: None,
             hidden: false,
+            generated: None,
         }
     }
     fn _create_column_of_type(name: &str, ty: Type) -> Column {
@@ -1655,6 +2368,7 @@ mod tests {
             has_rowid: true,
             is_strict: false,
             unique_sets: vec![],
+            foreign_keys: vec![],
         })
     }
 
@@ -1674,6 +2388,7 @@ mod tests {
             join_info,
             col_used_mask: ColumnUsedMask::default(),
             database_id: 0,
+            indexed: None,
         }
     }
 
@@ -13,7 +13,7 @@ use turso_parser::ast::{self, Expr, SortOrder};
 
 use crate::{
     parameters::PARAM_PREFIX,
-    schema::{Index, IndexColumn, Schema, Table},
+    schema::{Index, IndexColumn, Schema, SubqueryKind, Table},
     translate::{
         expr::walk_expr_mut, optimizer::access_method::AccessMethodParams,
         optimizer::constraints::TableConstraints, plan::Scan, plan::TerminationKey,
@@ -25,9 +25,11 @@ use crate::{
 use super::{
     emitter::Resolver,
     plan::{
-        DeletePlan, GroupBy, IterationDirection, JoinOrderMember, JoinedTable, Operation, Plan,
-        Search, SeekDef, SeekKey, SelectPlan, TableReferences, UpdatePlan, WhereTerm,
+        DeletePlan, GroupBy, IndexedBy, IterationDirection, JoinOrderMember, JoinedTable,
+        Operation, Plan, Search, SeekDef, SeekKey, SelectPlan, TableReferences, UpdatePlan,
+        WhereTerm,
     },
+    window::refresh_window_exprs,
 };
 
 pub(crate) mod access_method;
@@ -36,6 +38,7 @@ pub(crate) mod cost;
 pub(crate) mod join;
 pub(crate) mod lift_common_subexpressions;
 pub(crate) mod order;
+pub(crate) mod semi_join;
 
 #[tracing::instrument(skip_all, level = tracing::Level::DEBUG)]
 pub fn optimize_plan(plan: &mut Plan, schema: &Schema) -> Result<()> {
@@ -72,12 +75,20 @@ pub fn optimize_select_plan(plan: &mut SelectPlan, schema: &Schema) -> Result<()
         return Ok(());
     }
 
+    // With window functions, the rows produced by the main loop are sorted again by the
+    // window sorter, so the ORDER BY clause cannot be satisfied by the table access order.
+    let mut no_order_by = vec![];
+    let order_by = if plan.window.is_some() {
+        &mut no_order_by
+    } else {
+        &mut plan.order_by
+    };
     let best_join_order = optimize_table_access(
         schema,
         &mut plan.table_references,
         &schema.indexes,
         &mut plan.where_clause,
-        &mut plan.order_by,
+        order_by,
         &mut plan.group_by,
     )?;
 
@@ -143,6 +154,14 @@ fn optimize_update_plan(plan: &mut UpdatePlan, schema: &Schema) -> Result<()> {
     if table_ref.op.returns_max_1_row() {
         return Ok(());
     }
+    // INDEXED BY pins the iteration index, so there is nothing to fall back to.
+    if matches!(table_ref.indexed, Some(IndexedBy::Index(_))) {
+        crate::bail_parse_error!(
+            "no query solution: index {} cannot be used to iterate over table {} while it is being updated",
+            index.name,
+            table_ref.table.get_name()
+        );
+    }
     // Otherwise, fall back to a table scan.
     table_ref.op = Operation::Scan(Scan::BTreeTable {
         iter_dir: IterationDirection::Forwards,
@@ -159,6 +178,16 @@ fn optimize_update_plan(plan: &mut UpdatePlan, schema: &Schema) -> Result<()> {
 fn optimize_subqueries(plan: &mut SelectPlan, schema: &Schema) -> Result<()> {
     for table in plan.table_references.joined_tables_mut() {
         if let Table::FromClauseSubquery(from_clause_subquery) = &mut table.table {
+            match &mut from_clause_subquery.kind {
+                // The plan of a recursive reference is never run.
+                SubqueryKind::RecursiveReference => continue,
+                SubqueryKind::Recursive(cte) => {
+                    for plan in cte.recursive_plans.iter_mut() {
+                        optimize_select_plan(plan, schema)?;
+                    }
+                }
+                SubqueryKind::Coroutine | SubqueryKind::Materialized => {}
+            }
             optimize_select_plan(&mut from_clause_subquery.plan, schema)?;
         }
     }
@@ -186,9 +215,20 @@ fn optimize_table_access(
     group_by: &mut Option<GroupBy>,
 ) -> Result<Option<Vec<JoinOrderMember>>> {
     let access_methods_arena = RefCell::new(Vec::new());
-    let maybe_order_target = compute_order_target(order_by, group_by.as_mut());
-    let constraints_per_table =
-        constraints_from_where_clause(where_clause, table_references, available_indexes)?;
+    // The unmatched rows of a RIGHT JOIN are emitted after all the other rows,
+    // so no join order can produce the rows in a given order.
+    let has_right_join = table_references
+        .joined_tables()
+        .iter()
+        .any(|t| t.join_info.as_ref().is_some_and(|j| j.right));
+    let maybe_order_target =
+        compute_order_target(order_by, group_by.as_mut()).filter(|_| !has_right_join);
+    let constraints_per_table = constraints_from_where_clause(
+        where_clause,
+        table_references,
+        available_indexes,
+        &schema.table_stats,
+    )?;
 
     // Currently the expressions we evaluate as constraints are binary expressions that will never be true for a NULL operand.
     // If there are any constraints on the right hand side table of an outer join that are not part of the outer join condition,
@@ -307,7 +347,17 @@ fn optimize_table_access(
                     let try_to_build_ephemeral_index = if schema.indexes_enabled() {
                         let is_leftmost_table = i == 0;
                         let uses_index = index.is_some();
-                        !is_leftmost_table && !uses_index
+                        // The unmatched rows pass of a RIGHT JOIN reads the cursors of the tables
+                        // even if their loops never ran, in which case an ephemeral index would not exist.
+                        // INDEXED BY and NOT INDEXED also rule out automatic indexes, as in SQLite.
+                        // A WITHOUT ROWID table has no rowid for an ephemeral index to point back to.
+                        !is_leftmost_table
+                            && !uses_index
+                            && !has_right_join
+                            && joined_tables[table_idx].indexed.is_none()
+                            && joined_tables[table_idx]
+                                .btree()
+                                .is_some_and(|btree| btree.has_rowid)
                     } else {
                         false
                     };
@@ -331,11 +381,16 @@ fn optimize_table_access(
                         });
                         continue;
                     };
-                    let temp_constraint_refs = (0..table_constraints.constraints.len())
-                        .map(|i| ConstraintRef {
-                            constraint_vec_pos: i,
-                            index_col_pos: table_constraints.constraints[i].table_col_pos,
-                            sort_order: SortOrder::Asc,
+                    let temp_constraint_refs = table_constraints
+                        .constraints
+                        .iter()
+                        .enumerate()
+                        .filter_map(|(i, constraint)| {
+                            Some(ConstraintRef {
+                                constraint_vec_pos: i,
+                                index_col_pos: constraint.table_col_pos?,
+                                sort_order: SortOrder::Asc,
+                            })
                         })
                         .collect::<Vec<_>>();
                     let usable_constraint_refs = usable_constraints_for_join_order(
@@ -578,6 +633,7 @@ fn rewrite_exprs_select(plan: &mut SelectPlan) -> Result<()> {
     for (expr, _) in plan.order_by.iter_mut() {
         rewrite_expr(expr, &mut param_count)?;
     }
+    refresh_window_exprs(plan)?;
 
     Ok(())
 }
@@ -710,6 +766,7 @@ impl Optimizable for ast::Expr {
             }
             Expr::Raise(..) => false,
             Expr::Subquery(..) => false,
+            Expr::SubqueryResult { .. } => false,
             Expr::Unary(_, expr) => expr.is_nonnull(tables),
             Expr::Variable(..) => false,
             Expr::Register(..) => false, // Register values can be null
@@ -786,8 +843,11 @@ impl Optimizable for ast::Expr {
             Expr::Qualified(_, _) => {
                 panic!("Qualified should have been rewritten as Column")
             }
-            Expr::Raise(_, expr) => expr.as_ref().is_none_or(|expr| expr.is_constant(resolver)),
+            // RAISE() has side effects and must never be hoisted out of its trigger program
+            Expr::Raise(..) => false,
             Expr::Subquery(_) => false,
+            // The result of a subquery is only available once the subquery has been evaluated
+            Expr::SubqueryResult { .. } => false,
             Expr::Unary(_, expr) => expr.is_constant(resolver),
             Expr::Variable(_) => false,
             Expr::Register(_) => false, // Register values are not constants
@@ -919,6 +979,7 @@ fn ephemeral_index_build(
             pos_in_table: i,
             collation: c.collation,
             default: c.default.clone(),
+            expr: None,
         })
         // only include columns that are used in the query
         .filter(|c| table_reference.column_is_used(c.pos_in_table))
@@ -928,11 +989,11 @@ fn ephemeral_index_build(
         let a_constraint = constraint_refs
             .iter()
             .enumerate()
-            .find(|(_, c)| constraints[c.constraint_vec_pos].table_col_pos == a.pos_in_table);
+            .find(|(_, c)| constraints[c.constraint_vec_pos].table_col_pos == Some(a.pos_in_table));
         let b_constraint = constraint_refs
             .iter()
             .enumerate()
-            .find(|(_, c)| constraints[c.constraint_vec_pos].table_col_pos == b.pos_in_table);
+            .find(|(_, c)| constraints[c.constraint_vec_pos].table_col_pos == Some(b.pos_in_table));
         match (a_constraint, b_constraint) {
             (Some(_), None) => Ordering::Less,
             (None, Some(_)) => Ordering::Greater,
@@ -955,6 +1016,8 @@ fn ephemeral_index_build(
             .table
             .btree()
             .is_some_and(|btree| btree.has_rowid),
+        num_key_columns: None,
+        where_clause: None,
     };
 
     ephemeral_index
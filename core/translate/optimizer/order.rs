@@ -3,21 +3,68 @@ use std::cell::RefCell;
 use turso_parser::ast::{self, SortOrder, TableInternalId};
 
 use crate::{
+    schema::Index,
+    translate::expr::{walk_expr, WalkControl},
     translate::optimizer::access_method::AccessMethodParams,
     translate::plan::{GroupBy, IterationDirection, JoinedTable},
     util::exprs_are_equivalent,
+    Result,
 };
 
-use super::{access_method::AccessMethod, join::JoinN};
+use super::{access_method::AccessMethod, constraints::bind_to_table_reference, join::JoinN};
 
 #[derive(Debug, PartialEq, Clone)]
-/// A convenience struct for representing a (table_no, column_no, [SortOrder]) tuple.
+/// A column of a table, or an expression over the columns of a single table, which
+/// can be delivered in order by an index on that expression.
+pub enum OrderColumn {
+    Column(usize),
+    Expr(ast::Expr),
+}
+
+#[derive(Debug, PartialEq, Clone)]
+/// A convenience struct for representing a (table_no, [OrderColumn], [SortOrder]) tuple.
 pub struct ColumnOrder {
     pub table_id: TableInternalId,
-    pub column_no: usize,
+    pub column: OrderColumn,
     pub order: SortOrder,
 }
 
+impl ColumnOrder {
+    /// Returns true if the column at `index_col_pos` in `index` on the table of `table_ref`
+    /// is the same column or expression as this one.
+    pub fn matches_index_column(
+        &self,
+        index: &Index,
+        index_col_pos: usize,
+        table_ref: &JoinedTable,
+    ) -> bool {
+        let index_col = &index.columns[index_col_pos];
+        match (&self.column, &index_col.expr) {
+            (OrderColumn::Column(column_no), None) => *column_no == index_col.pos_in_table,
+            (OrderColumn::Expr(expr), Some(index_expr)) => {
+                bind_to_table_reference(index_expr, table_ref)
+                    .is_some_and(|index_expr| exprs_are_equivalent(&index_expr, expr))
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Returns the table whose columns are referenced by an expression,
+/// or None if it references no columns or the columns of several tables.
+fn single_table_of_expr(expr: &ast::Expr) -> Option<TableInternalId> {
+    let mut table_id = None;
+    let mut single_table = true;
+    let _ = walk_expr(expr, &mut |expr: &ast::Expr| -> Result<WalkControl> {
+        if let ast::Expr::Column { table, .. } | ast::Expr::RowId { table, .. } = expr {
+            single_table &= table_id.is_none_or(|id| id == *table);
+            table_id = Some(*table);
+        }
+        Ok(WalkControl::Continue)
+    });
+    table_id.filter(|_| single_table)
+}
+
 #[derive(Debug, PartialEq, Clone)]
 /// If an [OrderTarget] is satisfied, then [EliminatesSort] describes which part of the query no longer requires sorting.
 pub enum EliminatesSortBy {
@@ -40,26 +87,23 @@ impl OrderTarget {
         if list.clone().count() == 0 {
             return None;
         }
-        if list
-            .clone()
-            .any(|(expr, _)| !matches!(expr, ast::Expr::Column { .. }))
-        {
-            return None;
-        }
-        Some(OrderTarget(
-            list.map(|(expr, order)| {
-                let ast::Expr::Column { table, column, .. } = expr else {
-                    unreachable!();
-                };
-                ColumnOrder {
+        // Expressions other than columns can only be delivered in order by an index on
+        // the expression, so they must refer to a single table.
+        let columns = list
+            .map(|(expr, order)| match expr {
+                ast::Expr::Column { table, column, .. } => Some(ColumnOrder {
                     table_id: *table,
-                    column_no: *column,
+                    column: OrderColumn::Column(*column),
                     order,
-                }
+                }),
+                expr => single_table_of_expr(expr).map(|table_id| ColumnOrder {
+                    table_id,
+                    column: OrderColumn::Expr(expr.clone()),
+                    order,
+                }),
             })
-            .collect(),
-            eliminates_sort,
-        ))
+            .collect::<Option<Vec<_>>>()?;
+        Some(OrderTarget(columns, eliminates_sort))
     }
 }
 
@@ -188,7 +232,8 @@ pub fn plan_satisfies_order_target(
                         let Some(rowid_alias_col) = rowid_alias_col else {
                             return false;
                         };
-                        let correct_column = target_col.column_no == rowid_alias_col;
+                        let correct_column =
+                            target_col.column == OrderColumn::Column(rowid_alias_col);
                         if !correct_column {
                             return false;
                         }
@@ -210,9 +255,10 @@ pub fn plan_satisfies_order_target(
                     }
                     Some(index) => {
                         // All of the index columns must match the next required columns in the order target.
-                        for index_col in index.columns.iter() {
+                        for (index_col_pos, index_col) in index.columns.iter().enumerate() {
                             let target_col = &order_target.0[target_col_idx];
-                            let correct_column = target_col.column_no == index_col.pos_in_table;
+                            let correct_column =
+                                target_col.matches_index_column(index, index_col_pos, table_ref);
                             if !correct_column {
                                 return false;
                             }
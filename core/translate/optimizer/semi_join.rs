@@ -0,0 +1,186 @@
+use turso_parser::ast::{Expr, Literal, Operator};
+
+use crate::{
+    schema::{Schema, Table},
+    translate::{
+        collate::CollationSeq,
+        expr::{walk_expr, WalkControl},
+        plan::{Distinctness, ResultSetColumn, SelectPlan, TableReferences, WhereTerm},
+    },
+};
+
+/// Rewrites the plan of a correlated `EXISTS (SELECT ...)` subquery of a WHERE clause into an
+/// uncorrelated `SELECT` of the rows it can match, so that it is evaluated as a semi-join (or,
+/// for `NOT EXISTS`, an anti-join) against an ephemeral index built once, instead of running
+/// the subquery again for every row of the enclosing query. For example:
+///
+/// ```sql
+/// SELECT * FROM t WHERE EXISTS (SELECT 1 FROM u WHERE u.c = t.a AND u.d > 5)
+/// ```
+///
+/// becomes, in effect:
+///
+/// ```sql
+/// SELECT * FROM t WHERE t.a IN (SELECT u.c FROM u WHERE u.d > 5 AND u.c IS NOT NULL)
+/// ```
+///
+/// The subquery must be correlated through a single equality between a column of its tables
+/// and a column of `outer_tables`, and nowhere else. The rewrite is only done when it is
+/// profitable, i.e. when the subquery could not look the rows up by that column through the
+/// rowid or an index and would scan its tables for every row of the enclosing query.
+///
+/// Returns the outer column, which becomes the left hand side of the IN, if `plan` was rewritten.
+/// The result of the subquery never contains NULL, so `NOT EXISTS` is `lhs IS NULL OR lhs NOT IN`.
+pub(crate) fn rewrite_exists_as_semi_join(
+    plan: &mut SelectPlan,
+    outer_tables: &TableReferences,
+    schema: &Schema,
+) -> Option<Expr> {
+    if plan.group_by.is_some()
+        || !plan.aggregates.is_empty()
+        || plan.window.is_some()
+        || plan.offset.is_some()
+        || !plan.values.is_empty()
+    {
+        return None;
+    }
+    // EXISTS only looks for the first row, so a positive LIMIT does not change its result.
+    match plan.limit.as_deref() {
+        None => {}
+        Some(Expr::Literal(Literal::Numeric(n))) if n.parse::<i64>().is_ok_and(|n| n > 0) => {}
+        Some(_) => return None,
+    }
+    if plan
+        .joined_tables()
+        .iter()
+        .any(|table| !matches!(table.table, Table::BTree(_)))
+    {
+        return None;
+    }
+
+    let (term_idx, inner, outer) = plan.where_clause.iter().enumerate().find_map(|(i, term)| {
+        if term.from_outer_join.is_some() {
+            return None;
+        }
+        let Expr::Binary(lhs, Operator::Equals, rhs) = &term.expr else {
+            return None;
+        };
+        let is_inner = |expr: &Expr| {
+            matches!(expr, Expr::Column { table, .. }
+                if plan.table_references.find_joined_table_by_internal_id(*table).is_some())
+        };
+        let is_outer = |expr: &Expr| {
+            matches!(expr, Expr::Column { table, .. }
+                if outer_tables.find_joined_table_by_internal_id(*table).is_some())
+        };
+        if is_inner(lhs) && is_outer(rhs) {
+            Some((i, lhs.as_ref().clone(), rhs.as_ref().clone()))
+        } else if is_outer(lhs) && is_inner(rhs) {
+            Some((i, rhs.as_ref().clone(), lhs.as_ref().clone()))
+        } else {
+            None
+        }
+    })?;
+
+    // The result columns are replaced, so they must not hold anonymous parameters, which would
+    // otherwise never be numbered, or subqueries.
+    for expr in plan
+        .result_columns
+        .iter()
+        .map(|rc| &rc.expr)
+        .chain(plan.order_by.iter().map(|(expr, _)| expr.as_ref()))
+    {
+        let mut discarded = false;
+        let _ = walk_expr(expr, &mut |expr: &Expr| -> crate::Result<WalkControl> {
+            discarded |= matches!(
+                expr,
+                Expr::Variable(_) | Expr::Exists(_) | Expr::Subquery(_) | Expr::InSelect { .. }
+            );
+            Ok(WalkControl::Continue)
+        });
+        if discarded {
+            return None;
+        }
+    }
+
+    // Nothing else may refer to the enclosing query, including the subqueries of the WHERE
+    // clause, which have not been planned yet.
+    for (i, term) in plan.where_clause.iter().enumerate() {
+        if i == term_idx {
+            continue;
+        }
+        let mut correlated = false;
+        let _ = walk_expr(
+            &term.expr,
+            &mut |expr: &Expr| -> crate::Result<WalkControl> {
+                correlated |= match expr {
+                    Expr::Column { table, .. } | Expr::RowId { table, .. } => plan
+                        .table_references
+                        .find_outer_query_ref_by_internal_id(*table)
+                        .is_some(),
+                    Expr::Exists(_) | Expr::Subquery(_) | Expr::InSelect { .. } => true,
+                    _ => false,
+                };
+                Ok(WalkControl::Continue)
+            },
+        );
+        if correlated {
+            return None;
+        }
+    }
+
+    // The ephemeral index compares with the BINARY collation, like the equality only does if
+    // neither column has another one.
+    let column_of = |tables: &TableReferences, expr: &Expr| {
+        let Expr::Column { table, column, .. } = expr else {
+            unreachable!("semi-join key must be a column");
+        };
+        tables
+            .find_table_by_internal_id(*table)
+            .and_then(|table| table.get_column_at(*column))
+            .cloned()
+    };
+    let inner_column = column_of(&plan.table_references, &inner)?;
+    let outer_column = column_of(outer_tables, &outer)?;
+    if [&inner_column, &outer_column]
+        .iter()
+        .any(|column| !matches!(column.collation, None | Some(CollationSeq::Binary)))
+    {
+        return None;
+    }
+
+    // A lookup by rowid or through an index is as cheap as a probe of the ephemeral index.
+    let Expr::Column { table, column, .. } = &inner else {
+        unreachable!("semi-join key must be a column");
+    };
+    let Some(Table::BTree(btree)) = plan.table_references.find_table_by_internal_id(*table) else {
+        return None;
+    };
+    if inner_column.is_rowid_alias
+        || schema.get_indices(&btree.name).iter().any(|index| {
+            index.where_clause.is_none()
+                && index
+                    .columns
+                    .first()
+                    .is_some_and(|index_column| index_column.pos_in_table == *column)
+        })
+    {
+        return None;
+    }
+
+    plan.where_clause.remove(term_idx);
+    plan.where_clause.push(WhereTerm {
+        expr: Expr::NotNull(Box::new(inner.clone())),
+        from_outer_join: None,
+        consumed: false,
+    });
+    plan.result_columns = vec![ResultSetColumn {
+        expr: inner,
+        alias: None,
+        contains_aggregates: false,
+    }];
+    plan.order_by.clear();
+    plan.limit = None;
+    plan.distinctness = Distinctness::NonDistinct;
+    Some(outer)
+}
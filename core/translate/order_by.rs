@@ -47,30 +47,9 @@ pub fn init_order_by(
         remappings: order_by_deduplicate_result_columns(order_by, result_columns),
     });
 
-    /*
-     * Terms of the ORDER BY clause that is part of a SELECT statement may be assigned a collating sequence using the COLLATE operator,
-     * in which case the specified collating function is used for sorting.
-     * Otherwise, if the expression sorted by an ORDER BY clause is a column,
-     * then the collating sequence of the column is used to determine sort order.
-     * If the expression is not a column and has no COLLATE clause, then the BINARY collating sequence is used.
-     */
     let collations = order_by
         .iter()
-        .map(|(expr, _)| match expr.as_ref() {
-            ast::Expr::Collate(_, collation_name) => {
-                CollationSeq::new(collation_name.as_str()).map(Some)
-            }
-            ast::Expr::Column { table, column, .. } => {
-                let table = referenced_tables.find_table_by_internal_id(*table).unwrap();
-
-                let Some(table_column) = table.get_column_at(*column) else {
-                    crate::bail_parse_error!("column index out of bounds");
-                };
-
-                Ok(table_column.collation)
-            }
-            _ => Ok(Some(CollationSeq::default())),
-        })
+        .map(|(expr, _)| sort_key_collation(expr, referenced_tables))
         .collect::<Result<Vec<_>>>()?;
     program.emit_insn(Insn::SorterOpen {
         cursor_id: sort_cursor,
@@ -81,6 +60,34 @@ pub fn init_order_by(
     Ok(())
 }
 
+/// Returns the collating sequence used to sort by `expr`.
+///
+/// Terms of the ORDER BY clause that is part of a SELECT statement may be assigned a collating sequence using the COLLATE operator,
+/// in which case the specified collating function is used for sorting.
+/// Otherwise, if the expression sorted by an ORDER BY clause is a column,
+/// then the collating sequence of the column is used to determine sort order.
+/// If the expression is not a column and has no COLLATE clause, then the BINARY collating sequence is used.
+pub fn sort_key_collation(
+    expr: &ast::Expr,
+    referenced_tables: &TableReferences,
+) -> Result<Option<CollationSeq>> {
+    match expr {
+        ast::Expr::Collate(_, collation_name) => {
+            CollationSeq::new(collation_name.as_str()).map(Some)
+        }
+        ast::Expr::Column { table, column, .. } => {
+            let table = referenced_tables.find_table_by_internal_id(*table).unwrap();
+
+            let Some(table_column) = table.get_column_at(*column) else {
+                crate::bail_parse_error!("column index out of bounds");
+            };
+
+            Ok(table_column.collation)
+        }
+        _ => Ok(Some(CollationSeq::default())),
+    }
+}
+
 /// Emits the bytecode for outputting rows from an ORDER BY sorter.
 /// This is called when the main query execution loop has finished processing,
 /// and we can now emit rows from the ORDER BY sorter.
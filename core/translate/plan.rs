@@ -2,8 +2,8 @@ use std::{cmp::Ordering, sync::Arc};
 use turso_parser::ast::{self, SortOrder};
 
 use crate::{
-    function::AggFunc,
-    schema::{BTreeTable, Column, FromClauseSubquery, Index, Schema, Table},
+    function::{AggFunc, WindowFunc},
+    schema::{BTreeTable, Column, FromClauseSubquery, Index, Schema, SubqueryKind, Table},
     vdbe::{
         builder::{CursorKey, CursorType, ProgramBuilder},
         insn::{IdxInsertFlags, Insn},
@@ -162,6 +162,38 @@ pub enum Plan {
     Update(UpdatePlan),
 }
 
+impl Plan {
+    /// The SELECTs of a SELECT or compound SELECT plan, from left to right.
+    pub fn select_plans(&self) -> Vec<&SelectPlan> {
+        match self {
+            Plan::Select(plan) => vec![plan],
+            Plan::CompoundSelect {
+                left, right_most, ..
+            } => left
+                .iter()
+                .map(|(plan, _)| plan)
+                .chain(std::iter::once(right_most))
+                .collect(),
+            Plan::Delete(_) | Plan::Update(_) => vec![],
+        }
+    }
+
+    /// Mutable version of [Plan::select_plans].
+    pub fn select_plans_mut(&mut self) -> Vec<&mut SelectPlan> {
+        match self {
+            Plan::Select(plan) => vec![plan],
+            Plan::CompoundSelect {
+                left, right_most, ..
+            } => left
+                .iter_mut()
+                .map(|(plan, _)| plan)
+                .chain(std::iter::once(right_most))
+                .collect(),
+            Plan::Delete(_) | Plan::Update(_) => vec![],
+        }
+    }
+}
+
 /// The destination of the results of a query.
 /// Typically, the results of a query are returned to the caller.
 /// However, there are some cases where the results are not returned to the caller,
@@ -196,6 +228,9 @@ pub enum QueryDestination {
         /// The table that will be used to store the results.
         table: Arc<BTreeTable>,
     },
+    /// The results of the query are computed for their side effects only and then
+    /// thrown away, e.g. a SELECT statement in a trigger program.
+    Discard,
 }
 
 #[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
@@ -304,6 +339,8 @@ pub struct SelectPlan {
     pub distinctness: Distinctness,
     /// values: https://sqlite.org/syntax/select-core.html
     pub values: Vec<Vec<Expr>>,
+    /// the window functions of the query, if any
+    pub window: Option<Window>,
 }
 
 impl SelectPlan {
@@ -311,8 +348,9 @@ impl SelectPlan {
         self.table_references.joined_tables()
     }
 
-    pub fn agg_args_count(&self) -> usize {
-        self.aggregates.iter().map(|agg| agg.args.len()).sum()
+    /// The number of expressions evaluated per row for all aggregates, see [Aggregate::inputs].
+    pub fn agg_inputs_count(&self) -> usize {
+        self.aggregates.iter().map(|agg| agg.num_inputs()).sum()
     }
 
     /// Reference: https://github.com/sqlite/sqlite/blob/5db695197b74580c777b37ab1b787531f15f7f9f/src/select.c#L8613
@@ -339,7 +377,7 @@ impl SelectPlan {
             return false;
         }
         let agg = self.aggregates.first().unwrap();
-        if !matches!(agg.func, AggFunc::Count0) {
+        if !matches!(agg.func, AggFunc::Count0) || agg.filter.is_some() {
             return false;
         }
 
@@ -386,6 +424,9 @@ pub struct DeletePlan {
     pub contains_constant_false_condition: bool,
     /// Indexes that must be updated by the delete operation.
     pub indexes: Vec<Arc<Index>>,
+    /// If the table has DELETE triggers, gather all the target rowids into an ephemeral table,
+    /// and then use that table to drive the actual DELETE loop.
+    pub ephemeral_plan: Option<SelectPlan>,
 }
 
 #[derive(Debug, Clone)]
@@ -416,7 +457,57 @@ pub enum IterationDirection {
 }
 
 pub fn select_star(tables: &[JoinedTable], out_columns: &mut Vec<ResultSetColumn>) {
+    let first_star_column = out_columns.len();
     for table in tables.iter() {
+        // In a RIGHT or FULL OUTER JOIN, the USING columns of the left table may be NULL,
+        // so the value of a USING column is the first non-NULL value of the joined columns.
+        if let Some(join_info) = table.join_info.as_ref().filter(|j| j.right) {
+            for using_col in join_info.using.iter() {
+                let Some(right_col_idx) = table.columns().iter().position(|col| {
+                    col.name
+                        .as_ref()
+                        .is_some_and(|name| name.eq_ignore_ascii_case(using_col.as_str()))
+                }) else {
+                    continue;
+                };
+                let Some(left_column) = out_columns[first_star_column..].iter_mut().find(|rc| {
+                    let name = match &rc.expr {
+                        ast::Expr::Column { table, column, .. } => tables
+                            .iter()
+                            .find(|t| t.internal_id == *table)
+                            .and_then(|t| t.columns()[*column].name.as_deref()),
+                        _ => rc.alias.as_deref(),
+                    };
+                    name.is_some_and(|name| name.eq_ignore_ascii_case(using_col.as_str()))
+                }) else {
+                    continue;
+                };
+                let right_col = &table.columns()[right_col_idx];
+                let left_expr = std::mem::replace(
+                    &mut left_column.expr,
+                    ast::Expr::Literal(ast::Literal::Null),
+                );
+                left_column.expr = ast::Expr::FunctionCall {
+                    name: ast::Name::new("coalesce"),
+                    distinctness: None,
+                    args: vec![
+                        Box::new(left_expr),
+                        Box::new(ast::Expr::Column {
+                            database: None,
+                            table: table.internal_id,
+                            column: right_col_idx,
+                            is_rowid_alias: right_col.is_rowid_alias,
+                        }),
+                    ],
+                    order_by: vec![],
+                    filter_over: ast::FunctionTail {
+                        filter_clause: None,
+                        over_clause: None,
+                    },
+                };
+                left_column.alias = right_col.name.clone();
+            }
+        }
         out_columns.extend(
             table
                 .columns()
@@ -453,8 +544,15 @@ pub fn select_star(tables: &[JoinedTable], out_columns: &mut Vec<ResultSetColumn
 /// Join information for a table reference.
 #[derive(Debug, Clone)]
 pub struct JoinInfo {
-    /// Whether this is an OUTER JOIN.
+    /// Whether this is a LEFT (or FULL) OUTER JOIN, i.e. rows of the tables to the left
+    /// that have no match in this table are emitted with NULLs for this table.
     pub outer: bool,
+    /// Whether this is a RIGHT (or FULL) OUTER JOIN, i.e. rows of this table that have no match
+    /// in the tables to the left are emitted with NULLs for those tables.
+    pub right: bool,
+    /// Whether this is a CROSS JOIN, i.e. the optimizer must not move this table before
+    /// any of the tables to its left in the join order.
+    pub cross: bool,
     /// The USING clause for the join, if any. NATURAL JOIN is transformed into USING (col1, col2, ...).
     pub using: Vec<ast::Name>,
 }
@@ -468,7 +566,7 @@ pub struct JoinInfo {
 /// - all have [Operation::Scan]
 /// - identifiers are `t`, `p`, `sub`
 /// - `t` and `p` are [Table::BTree] while `sub` is [Table::FromClauseSubquery]
-/// - join_info is None for the first table reference, and Some(JoinInfo { outer: false, using: None }) for the second and third table references
+/// - join_info is None for the first table reference, and Some(JoinInfo { outer: false, right: false, cross: false, using: None }) for the second and third table references
 #[derive(Debug, Clone)]
 pub struct JoinedTable {
     /// The operation that this table reference performs.
@@ -486,6 +584,33 @@ pub struct JoinedTable {
     pub col_used_mask: ColumnUsedMask,
     /// The index of the database. "main" is always zero.
     pub database_id: usize,
+    /// The INDEXED BY or NOT INDEXED clause of the table reference, if any.
+    pub indexed: Option<IndexedBy>,
+}
+
+/// An `INDEXED BY` or `NOT INDEXED` clause, which restricts the access methods
+/// the optimizer may choose for a table.
+#[derive(Debug, Clone)]
+pub enum IndexedBy {
+    /// `INDEXED BY`: the table must be accessed through this index.
+    Index(Arc<Index>),
+    /// `NOT INDEXED`: the table must not be accessed through an index,
+    /// although rowid lookups are still allowed.
+    NotIndexed,
+}
+
+/// A subquery that appears in an expression, e.g. `EXISTS (SELECT ...)`, `x IN (SELECT ...)`
+/// or a scalar `(SELECT ...)`. The expression is replaced with an [ast::Expr::SubqueryResult]
+/// that refers to the subquery by its internal ID.
+#[derive(Debug, Clone)]
+pub struct NonFromClauseSubquery {
+    pub internal_id: TableInternalId,
+    /// A SELECT or compound SELECT plan.
+    pub plan: Plan,
+    pub query_type: ast::SubqueryType,
+    /// Whether the subquery refers to tables of an enclosing query, in which case it has to be
+    /// evaluated again for every row of those tables, instead of only once.
+    pub correlated: bool,
 }
 
 #[derive(Debug, Clone)]
@@ -579,6 +704,11 @@ impl TableReferences {
         &self.outer_query_refs
     }
 
+    /// Returns a mutable reference to the [OuterQueryReference]s in the query plan.
+    pub fn outer_query_refs_mut(&mut self) -> &mut Vec<OuterQueryReference> {
+        &mut self.outer_query_refs
+    }
+
     /// Returns an immutable reference to the [OuterQueryReference] with the given internal ID.
     pub fn find_outer_query_ref_by_internal_id(
         &self,
@@ -816,6 +946,7 @@ impl JoinedTable {
                 unique: false,
                 collation: None, // FIXME: infer collation from subquery
                 hidden: false,
+                generated: None,
             })
             .collect();
 
@@ -824,6 +955,7 @@ impl JoinedTable {
             plan: Box::new(plan),
             columns,
             result_columns_start_reg: None,
+            kind: SubqueryKind::Coroutine,
         });
         Self {
             op: Operation::default_scan_for(&table),
@@ -833,6 +965,7 @@ impl JoinedTable {
             join_info,
             col_used_mask: ColumnUsedMask::default(),
             database_id: 0,
+            indexed: None,
         }
     }
 
@@ -842,8 +975,26 @@ impl JoinedTable {
 
     /// Mark a column as used in the query.
     /// This is used to determine whether a covering index can be used.
+    /// VIRTUAL generated columns are computed from the columns they refer to, which are marked
+    /// as used as well.
     pub fn mark_column_used(&mut self, index: usize) {
         self.col_used_mask.set(index);
+        let dependencies = match &self.table {
+            Table::BTree(btree)
+                if btree
+                    .columns
+                    .get(index)
+                    .is_some_and(|column| column.is_virtual_generated()) =>
+            {
+                btree
+                    .generated_column_dependencies(index)
+                    .unwrap_or_default()
+            }
+            _ => return,
+        };
+        for dependency in dependencies {
+            self.mark_column_used(dependency);
+        }
     }
 
     /// Open the necessary cursors for this table reference.
@@ -919,7 +1070,8 @@ impl JoinedTable {
             return false;
         }
         let mut index_cols_mask = ColumnUsedMask::default();
-        for col in index.columns.iter() {
+        // Indexed expressions do not cover the columns they are computed from.
+        for col in index.columns.iter().filter(|col| col.expr.is_none()) {
             index_cols_mask.set(col.pos_in_table);
         }
 
@@ -1054,6 +1206,12 @@ pub struct Aggregate {
     pub args: Vec<ast::Expr>,
     pub original_expr: ast::Expr,
     pub distinctness: Distinctness,
+    /// The FILTER clause. Rows for which it is not true are not accumulated.
+    pub filter: Option<ast::Expr>,
+    /// The ORDER BY clause inside the call, the order in which the rows are accumulated.
+    pub order_by: Vec<(ast::Expr, SortOrder)>,
+    /// Populated during translation if the aggregate has an ORDER BY clause.
+    pub order_by_ctx: Option<AggOrderByCtx>,
 }
 
 impl Aggregate {
@@ -1067,15 +1225,127 @@ impl Aggregate {
         } else {
             args.iter().map(|arg| *arg.clone()).collect()
         };
+        let (filter, order_by) = match expr {
+            Expr::FunctionCall {
+                filter_over,
+                order_by,
+                ..
+            } => (
+                filter_over.filter_clause.as_deref().cloned(),
+                order_by
+                    .iter()
+                    .map(|col| (*col.expr.clone(), col.order.unwrap_or(SortOrder::Asc)))
+                    .collect(),
+            ),
+            Expr::FunctionCallStar { filter_over, .. } => {
+                (filter_over.filter_clause.as_deref().cloned(), vec![])
+            }
+            _ => (None, vec![]),
+        };
         Aggregate {
             func,
             args: agg_args,
             original_expr: expr.clone(),
             distinctness,
+            filter,
+            order_by,
+            order_by_ctx: None,
         }
     }
 
     pub fn is_distinct(&self) -> bool {
         self.distinctness.is_distinct()
     }
+
+    /// The expressions evaluated for every row the aggregate is computed over: the arguments,
+    /// then the FILTER clause, then the ORDER BY keys. In GROUP BY queries they are all stored
+    /// in the GROUP BY sorter.
+    pub fn inputs(&self) -> impl Iterator<Item = &ast::Expr> {
+        self.args
+            .iter()
+            .chain(self.filter.iter())
+            .chain(self.order_by.iter().map(|(expr, _)| expr))
+    }
+
+    pub fn inputs_mut(&mut self) -> impl Iterator<Item = &mut ast::Expr> {
+        self.args
+            .iter_mut()
+            .chain(self.filter.iter_mut())
+            .chain(self.order_by.iter_mut().map(|(expr, _)| expr))
+    }
+
+    pub fn num_inputs(&self) -> usize {
+        self.args.len() + self.filter.is_some() as usize + self.order_by.len()
+    }
+}
+
+/// Translation context for an aggregate with an ORDER BY clause. The arguments of the rows are
+/// collected into an ephemeral index sorted by the ORDER BY keys, and only accumulated once the
+/// last row has been seen.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AggOrderByCtx {
+    /// The cursor ID of the ephemeral index.
+    pub cursor_id: usize,
+    /// The index name for the ephemeral index, needed to lookup the cursor ID.
+    pub ephemeral_index_name: String,
+    /// Register holding the number of rows collected so far. It is part of the key of the
+    /// ephemeral index, so that rows with equal ORDER BY keys are accumulated in the order they
+    /// were seen.
+    pub reg_sequence: usize,
+}
+
+/// The window functions of a SELECT.
+///
+/// All window functions of a query must currently share the same PARTITION BY and
+/// ORDER BY clauses; their frames may differ.
+#[derive(Debug, Clone)]
+pub struct Window {
+    /// The window functions, without duplicates, in the order they appear in the query.
+    pub functions: Vec<WindowFunction>,
+    /// Expressions evaluated in the main loop whose values are needed after the window
+    /// functions have been computed, i.e. the columns referenced by the result columns.
+    pub passthrough: Vec<ast::Expr>,
+}
+
+impl Window {
+    /// The window definition shared by all window functions.
+    pub fn definition(&self) -> &ast::Window {
+        self.functions[0].definition()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct WindowFunction {
+    /// The function call, e.g. `sum(x) OVER (PARTITION BY y)`. Named windows have already
+    /// been replaced by their definitions.
+    pub original_expr: ast::Expr,
+    pub func: WindowFunctionKind,
+}
+
+impl WindowFunction {
+    pub fn args(&self) -> &[Box<ast::Expr>] {
+        match &self.original_expr {
+            ast::Expr::FunctionCall { args, .. } => args,
+            _ => &[],
+        }
+    }
+
+    pub fn definition(&self) -> &ast::Window {
+        match &self.original_expr {
+            ast::Expr::FunctionCall { filter_over, .. }
+            | ast::Expr::FunctionCallStar { filter_over, .. } => match &filter_over.over_clause {
+                Some(ast::Over::Window(window)) => window,
+                _ => unreachable!("window function without a window definition"),
+            },
+            _ => unreachable!("window function is not a function call"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum WindowFunctionKind {
+    /// A built-in window function such as row_number() or lag().
+    Builtin(WindowFunc),
+    /// An aggregate function evaluated over the frame of each row.
+    Aggregate(AggFunc),
 }
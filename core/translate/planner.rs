@@ -3,19 +3,19 @@ use std::sync::Arc;
 use super::{
     expr::walk_expr,
     plan::{
-        Aggregate, ColumnUsedMask, Distinctness, EvalAt, IterationDirection, JoinInfo,
+        Aggregate, ColumnUsedMask, Distinctness, EvalAt, IndexedBy, IterationDirection, JoinInfo,
         JoinOrderMember, JoinedTable, Operation, OuterQueryReference, Plan, QueryDestination,
-        ResultSetColumn, Scan, TableReferences, WhereTerm,
+        ResultSetColumn, Scan, SelectPlan, TableReferences, WhereTerm,
     },
-    select::prepare_select_plan,
+    select::{prepare_one_select_plan, prepare_select_plan},
     SymbolTable,
 };
-use crate::function::{AggFunc, ExtFunc};
+use crate::function::{AggFunc, ExtFunc, WindowFunc};
 use crate::translate::expr::WalkControl;
 use crate::{
     ast::Limit,
     function::Func,
-    schema::{Schema, Table},
+    schema::{Column, FromClauseSubquery, Index, RecursiveCte, Schema, SubqueryKind, Table},
     translate::expr::walk_expr_mut,
     util::{exprs_are_equivalent, normalize_ident},
     vdbe::{builder::TableRefIdCounter, BranchOffset},
@@ -46,15 +46,9 @@ pub fn resolve_aggregates(
                 filter_over,
                 order_by,
             } => {
-                if filter_over.filter_clause.is_some() || filter_over.over_clause.is_some() {
-                    crate::bail_parse_error!(
-                        "FILTER clause is not supported yet in aggregate functions"
-                    );
-                }
-                if !order_by.is_empty() {
-                    crate::bail_parse_error!(
-                        "ORDER BY clause is not supported yet in aggregate functions"
-                    );
+                // Window functions are planned separately, see window::plan_windows().
+                if filter_over.over_clause.is_some() {
+                    return Ok(WalkControl::SkipChildren);
                 }
                 let args_count = args.len();
                 let distinctness = Distinctness::from_ast(distinctness.as_ref());
@@ -88,19 +82,36 @@ pub fn resolve_aggregates(
                                 contains_aggregates = true;
                                 return Ok(WalkControl::SkipChildren);
                             }
+                        } else if WindowFunc::resolve_function(name.as_str(), args_count)?.is_some()
+                        {
+                            crate::bail_parse_error!(
+                                "misuse of window function {}()",
+                                name.as_str()
+                            );
                         } else {
                             return Err(e);
                         }
                     }
                     _ => {}
                 }
-            }
-            Expr::FunctionCallStar { name, filter_over } => {
-                if filter_over.filter_clause.is_some() || filter_over.over_clause.is_some() {
+                // Only aggregate functions accept FILTER and ORDER BY clauses
+                if filter_over.filter_clause.is_some() {
+                    crate::bail_parse_error!(
+                        "FILTER may not be used with non-aggregate {}()",
+                        name.as_str()
+                    );
+                }
+                if !order_by.is_empty() {
                     crate::bail_parse_error!(
-                        "FILTER clause is not supported yet in aggregate functions"
+                        "ORDER BY may not be used with non-aggregate {}()",
+                        name.as_str()
                     );
                 }
+            }
+            Expr::FunctionCallStar { name, filter_over } => {
+                if filter_over.over_clause.is_some() {
+                    return Ok(WalkControl::SkipChildren);
+                }
                 match Func::resolve_function(name.as_str(), 0) {
                     Ok(Func::Agg(f)) => {
                         add_aggregate_if_not_exists(aggs, expr, &[], Distinctness::NonDistinct, f);
@@ -167,7 +178,12 @@ pub fn bind_column_references(
                 });
                 let normalized_id = normalize_ident(id.as_str());
 
-                if !referenced_tables.joined_tables().is_empty() {
+                // A WITHOUT ROWID table has no rowid, so the name can only refer to a column.
+                if !referenced_tables.joined_tables().is_empty()
+                    && referenced_tables.joined_tables()[0]
+                        .btree()
+                        .is_none_or(|btree| btree.has_rowid)
+                {
                     if let Some(row_id_expr) = parse_row_id(
                         &normalized_id,
                         referenced_tables.joined_tables()[0].internal_id,
@@ -269,10 +285,12 @@ pub fn bind_column_references(
                 let (tbl_id, tbl) = matching_tbl.unwrap();
                 let normalized_id = normalize_ident(id.as_str());
 
-                if let Some(row_id_expr) = parse_row_id(&normalized_id, tbl_id, || false)? {
-                    *expr = row_id_expr;
+                if tbl.btree().is_none_or(|btree| btree.has_rowid) {
+                    if let Some(row_id_expr) = parse_row_id(&normalized_id, tbl_id, || false)? {
+                        *expr = row_id_expr;
 
-                    return Ok(());
+                        return Ok(());
+                    }
                 }
                 let col_idx = tbl.columns().iter().position(|c| {
                     c.name
@@ -372,13 +390,13 @@ fn parse_from_clause_table(
     table: ast::SelectTable,
     table_references: &mut TableReferences,
     vtab_predicates: &mut Vec<Expr>,
-    ctes: &mut Vec<JoinedTable>,
+    ctes: &mut [Cte],
     syms: &SymbolTable,
     table_ref_counter: &mut TableRefIdCounter,
     connection: &Arc<crate::Connection>,
 ) -> Result<()> {
     match table {
-        ast::SelectTable::Table(qualified_name, maybe_alias, _) => parse_table(
+        ast::SelectTable::Table(qualified_name, maybe_alias, indexed) => parse_table(
             schema,
             syms,
             table_references,
@@ -387,6 +405,7 @@ fn parse_from_clause_table(
             vtab_predicates,
             &qualified_name,
             maybe_alias.as_ref(),
+            indexed.as_ref(),
             &[],
             connection,
         ),
@@ -431,6 +450,7 @@ fn parse_from_clause_table(
             vtab_predicates,
             &qualified_name,
             maybe_alias.as_ref(),
+            None,
             &args,
             connection,
         ),
@@ -443,11 +463,12 @@ fn parse_table(
     schema: &Schema,
     syms: &SymbolTable,
     table_references: &mut TableReferences,
-    ctes: &mut Vec<JoinedTable>,
+    ctes: &mut [Cte],
     table_ref_counter: &mut TableRefIdCounter,
     vtab_predicates: &mut Vec<Expr>,
     qualified_name: &QualifiedName,
     maybe_alias: Option<&As>,
+    indexed: Option<&ast::Indexed>,
     args: &[Box<Expr>],
     connection: &Arc<crate::Connection>,
 ) -> Result<()> {
@@ -456,12 +477,50 @@ fn parse_table(
     let table_name = &qualified_name.name;
 
     // Check if the FROM clause table is referring to a CTE in the current scope.
-    if let Some(cte_idx) = ctes
-        .iter()
-        .position(|cte| cte.identifier == normalized_qualified_name)
+    if let Some(cte) = ctes
+        .iter_mut()
+        .find(|cte| cte.name == normalized_qualified_name)
     {
-        // TODO: what if the CTE is referenced multiple times?
-        let cte_table = ctes.remove(cte_idx);
+        resolve_indexed_by(indexed, &[])?;
+        // The first reference uses the plan made for the WITH clause; every other reference
+        // gets a plan of its own, so that the tables of the plans do not share cursors.
+        let mut cte_table = if cte.num_references == 0 {
+            cte.table.clone()
+        } else {
+            plan_cte(
+                schema,
+                cte.definition.clone(),
+                cte.name.clone(),
+                cte.recursive,
+                syms,
+                &cte.outer_query_refs,
+                table_ref_counter,
+                connection,
+            )?
+        };
+        cte.num_references += 1;
+        // Like in SQLite, a CTE that is referenced more than once is computed only once, unless
+        // it is declared NOT MATERIALIZED or refers to an enclosing query, whose rows change it.
+        if cte.num_references > 1
+            && cte.definition.materialized == Materialized::Any
+            && !cte_is_correlated(&cte.table)
+        {
+            let first_reference = table_references
+                .find_joined_table_by_internal_id_mut(cte.table.internal_id)
+                .expect("first reference to the CTE must be in the FROM clause");
+            for table in [first_reference, &mut cte_table] {
+                if let Table::FromClauseSubquery(subquery) = &mut table.table {
+                    if matches!(subquery.kind, SubqueryKind::Coroutine) {
+                        subquery.kind = SubqueryKind::Materialized;
+                    }
+                }
+            }
+        }
+        if let Some(alias) = maybe_alias {
+            cte_table.identifier = normalize_ident(match alias {
+                ast::As::As(id) | ast::As::Elided(id) => id.as_str(),
+            });
+        }
         table_references.add_joined_table(cte_table);
         return Ok(());
     };
@@ -477,6 +536,10 @@ fn parse_table(
             })
             .map(|a| normalize_ident(a.as_str()));
         let internal_id = table_ref_counter.next();
+        let indexes = connection.with_schema(database_id, |schema| {
+            schema.get_indices(table_name.as_str()).to_vec()
+        });
+        let indexed = resolve_indexed_by(indexed, &indexes)?;
         let tbl_ref = if let Table::Virtual(tbl) = table.as_ref() {
             transform_args_into_where_terms(args, internal_id, vtab_predicates, table.as_ref())?;
             Table::Virtual(tbl.clone())
@@ -495,6 +558,7 @@ fn parse_table(
             join_info: None,
             col_used_mask: ColumnUsedMask::default(),
             database_id,
+            indexed,
         });
         return Ok(());
     };
@@ -503,6 +567,7 @@ fn parse_table(
         schema.get_view(table_name.as_str()).cloned()
     });
     if let Some(view) = regular_view {
+        resolve_indexed_by(indexed, &[])?;
         // Views are essentially query aliases, so just Expand the view as a subquery
         let view_select = view.select_stmt.clone();
         let subselect = Box::new(view_select);
@@ -529,6 +594,7 @@ fn parse_table(
         schema.get_materialized_view(table_name.as_str())
     });
     if let Some(view) = view {
+        resolve_indexed_by(indexed, &[])?;
         // Check if this materialized view has persistent storage
         let view_guard = view.lock().unwrap();
         let root_page = view_guard.get_root_page();
@@ -550,6 +616,7 @@ fn parse_table(
             has_rowid: true,
             is_strict: false,
             unique_sets: vec![],
+            foreign_keys: vec![],
         });
         drop(view_guard);
 
@@ -571,6 +638,7 @@ fn parse_table(
             join_info: None,
             col_used_mask: ColumnUsedMask::default(),
             database_id,
+            indexed: None,
         });
         return Ok(());
     }
@@ -586,14 +654,22 @@ fn parse_table(
         table_references.find_outer_query_ref_by_identifier(&normalized_qualified_name)
     {
         if matches!(outer_ref.table, Table::FromClauseSubquery(_)) {
+            resolve_indexed_by(indexed, &[])?;
+            let identifier = maybe_alias.map_or_else(
+                || outer_ref.identifier.clone(),
+                |alias| match alias {
+                    ast::As::As(id) | ast::As::Elided(id) => normalize_ident(id.as_str()),
+                },
+            );
             table_references.add_joined_table(JoinedTable {
                 op: Operation::default_scan_for(&outer_ref.table),
                 table: outer_ref.table.clone(),
-                identifier: outer_ref.identifier.clone(),
+                identifier,
                 internal_id: table_ref_counter.next(),
                 join_info: None,
                 col_used_mask: ColumnUsedMask::default(),
                 database_id,
+                indexed: None,
             });
             return Ok(());
         }
@@ -602,6 +678,24 @@ fn parse_table(
     crate::bail_parse_error!("no such table: {}", normalized_qualified_name);
 }
 
+/// Resolve the INDEXED BY or NOT INDEXED clause of a table reference against the indexes of the table.
+pub fn resolve_indexed_by(
+    indexed: Option<&ast::Indexed>,
+    indexes: &[Arc<Index>],
+) -> Result<Option<IndexedBy>> {
+    match indexed {
+        None => Ok(None),
+        Some(ast::Indexed::NotIndexed) => Ok(Some(IndexedBy::NotIndexed)),
+        Some(ast::Indexed::IndexedBy(name)) => {
+            let name = normalize_ident(name.as_str());
+            match indexes.iter().find(|index| index.name == name) {
+                Some(index) => Ok(Some(IndexedBy::Index(index.clone()))),
+                None => crate::bail_parse_error!("no such index: {}", name),
+            }
+        }
+    }
+}
+
 fn transform_args_into_where_terms(
     args: &[Box<Expr>],
     internal_id: TableInternalId,
@@ -666,17 +760,8 @@ pub fn parse_from(
     let mut ctes_as_subqueries = vec![];
 
     if let Some(with) = with {
-        if with.recursive {
-            crate::bail_parse_error!("Recursive CTEs are not yet supported");
-        }
+        let recursive = with.recursive;
         for cte in with.ctes {
-            if cte.materialized == Materialized::Yes {
-                crate::bail_parse_error!("Materialized CTEs are not yet supported");
-            }
-            if !cte.columns.is_empty() {
-                crate::bail_parse_error!("CTE columns are not yet supported");
-            }
-
             // Check if normalized name conflicts with catalog tables or other CTEs
             // TODO: sqlite actually allows overriding a catalog table with a CTE.
             // We should carry over the 'Scope' struct to all of our identifier resolution.
@@ -700,37 +785,36 @@ pub fn parse_from(
             }
 
             let mut outer_query_refs_for_cte = table_references.outer_query_refs().to_vec();
-            outer_query_refs_for_cte.extend(ctes_as_subqueries.iter().map(|t: &JoinedTable| {
+            outer_query_refs_for_cte.extend(ctes_as_subqueries.iter().map(|cte: &Cte| {
                 OuterQueryReference {
-                    identifier: t.identifier.clone(),
-                    internal_id: t.internal_id,
-                    table: t.table.clone(),
+                    identifier: cte.table.identifier.clone(),
+                    internal_id: cte.table.internal_id,
+                    table: cte.table.table.clone(),
                     col_used_mask: ColumnUsedMask::default(),
                 }
             }));
 
             // CTE can refer to other CTEs that came before it, plus any schema tables or tables in the outer scope.
-            let cte_plan = prepare_select_plan(
+            let cte_recursive =
+                recursive && select_references_table(&cte.select, &cte_name_normalized);
+            let cte_table = plan_cte(
                 schema,
-                cte.select,
+                cte.clone(),
+                cte_name_normalized.clone(),
+                cte_recursive,
                 syms,
                 &outer_query_refs_for_cte,
                 table_ref_counter,
-                QueryDestination::CoroutineYield {
-                    yield_reg: usize::MAX, // will be set later in bytecode emission
-                    coroutine_implementation_start: BranchOffset::Placeholder, // will be set later in bytecode emission
-                },
                 connection,
             )?;
-            let Plan::Select(cte_plan) = cte_plan else {
-                crate::bail_parse_error!("Only SELECT queries are currently supported in CTEs");
-            };
-            ctes_as_subqueries.push(JoinedTable::new_subquery(
-                cte_name_normalized,
-                cte_plan,
-                None,
-                table_ref_counter.next(),
-            ));
+            ctes_as_subqueries.push(Cte {
+                name: cte_name_normalized,
+                definition: cte,
+                recursive: cte_recursive,
+                outer_query_refs: outer_query_refs_for_cte,
+                table: cte_table,
+                num_references: 0,
+            });
         }
     }
 
@@ -765,6 +849,287 @@ pub fn parse_from(
     Ok(())
 }
 
+/// A CTE of a WITH clause, which the FROM clause of its SELECT may refer to any number of times.
+struct Cte {
+    /// The normalized name of the CTE.
+    name: String,
+    definition: ast::CommonTableExpr,
+    /// Whether the CTE is a recursive CTE that refers to itself.
+    recursive: bool,
+    /// The tables that the CTE can refer to: the outer query and the CTEs that came before it.
+    outer_query_refs: Vec<OuterQueryReference>,
+    /// The CTE planned as a derived table, used by its first reference.
+    table: JoinedTable,
+    num_references: usize,
+}
+
+/// Returns true if the plan of the CTE `table` refers to the columns of an enclosing query.
+fn cte_is_correlated(table: &JoinedTable) -> bool {
+    let Table::FromClauseSubquery(subquery) = &table.table else {
+        return false;
+    };
+    subquery
+        .plan
+        .table_references
+        .outer_query_refs()
+        .iter()
+        .any(|outer_query_ref| outer_query_ref.is_used())
+}
+
+/// Plans the CTE `cte` named `name` as a derived table.
+#[allow(clippy::too_many_arguments)]
+fn plan_cte(
+    schema: &Schema,
+    cte: ast::CommonTableExpr,
+    name: String,
+    recursive: bool,
+    syms: &SymbolTable,
+    outer_query_refs: &[OuterQueryReference],
+    table_ref_counter: &mut TableRefIdCounter,
+    connection: &Arc<crate::Connection>,
+) -> Result<JoinedTable> {
+    if recursive {
+        return parse_recursive_cte(
+            schema,
+            cte,
+            name,
+            syms,
+            outer_query_refs,
+            table_ref_counter,
+            connection,
+        );
+    }
+    let cte_plan = prepare_select_plan(
+        schema,
+        cte.select,
+        syms,
+        outer_query_refs,
+        table_ref_counter,
+        QueryDestination::CoroutineYield {
+            yield_reg: usize::MAX, // will be set later in bytecode emission
+            coroutine_implementation_start: BranchOffset::Placeholder, // will be set later in bytecode emission
+        },
+        connection,
+    )?;
+    let Plan::Select(cte_plan) = cte_plan else {
+        crate::bail_parse_error!("Only SELECT queries are currently supported in CTEs");
+    };
+    let mut cte_table = JoinedTable::new_subquery(name, cte_plan, None, table_ref_counter.next());
+    let Table::FromClauseSubquery(subquery) = &mut cte_table.table else {
+        unreachable!("new_subquery always creates a FromClauseSubquery");
+    };
+    apply_cte_column_names(&subquery.name, &mut subquery.columns, &cte.columns)?;
+    if cte.materialized == Materialized::Yes {
+        subquery.kind = SubqueryKind::Materialized;
+    }
+    Ok(cte_table)
+}
+
+/// Returns true if the FROM clause of any SELECT in `select` refers to the table `name`.
+/// Subqueries are not searched.
+fn select_references_table(select: &ast::Select, name: &str) -> bool {
+    std::iter::once(&select.body.select)
+        .chain(
+            select
+                .body
+                .compounds
+                .iter()
+                .map(|compound| &compound.select),
+        )
+        .any(|select| one_select_references_table(select, name))
+}
+
+fn one_select_references_table(select: &ast::OneSelect, name: &str) -> bool {
+    match select {
+        ast::OneSelect::Select {
+            from: Some(from), ..
+        } => from_clause_references_table(from, name),
+        _ => false,
+    }
+}
+
+fn from_clause_references_table(from: &FromClause, name: &str) -> bool {
+    std::iter::once(from.select.as_ref())
+        .chain(from.joins.iter().map(|join| join.table.as_ref()))
+        .any(|table| match table {
+            ast::SelectTable::Table(qualified_name, _, _)
+            | ast::SelectTable::TableCall(qualified_name, _, _) => {
+                qualified_name.db_name.is_none()
+                    && normalize_ident(qualified_name.name.as_str()) == name
+            }
+            ast::SelectTable::Sub(from, _) => from_clause_references_table(from, name),
+            ast::SelectTable::Select(..) => false,
+        })
+}
+
+/// Renames the columns of a CTE to the names of its column list, e.g. `WITH t(a, b) AS (...)`.
+fn apply_cte_column_names(
+    cte_name: &str,
+    columns: &mut [Column],
+    column_names: &[ast::IndexedColumn],
+) -> Result<()> {
+    if column_names.is_empty() {
+        return Ok(());
+    }
+    if column_names.len() != columns.len() {
+        crate::bail_parse_error!(
+            "table {} has {} values for {} columns",
+            cte_name,
+            columns.len(),
+            column_names.len()
+        );
+    }
+    for (column, column_name) in columns.iter_mut().zip(column_names) {
+        column.name = Some(normalize_ident(column_name.col_name.as_str()));
+    }
+    Ok(())
+}
+
+/// Plans a CTE of a `WITH RECURSIVE` clause that refers to itself.
+///
+/// The CTE must be an initial SELECT that does not refer to the CTE, followed by one or more
+/// recursive SELECTs that do, all combined with either UNION or UNION ALL. The recursive SELECTs
+/// see the CTE as a table holding the single row being processed, see [RecursiveCte].
+#[allow(clippy::too_many_arguments)]
+fn parse_recursive_cte(
+    schema: &Schema,
+    cte: ast::CommonTableExpr,
+    name: String,
+    syms: &SymbolTable,
+    outer_query_refs: &[OuterQueryReference],
+    table_ref_counter: &mut TableRefIdCounter,
+    connection: &Arc<crate::Connection>,
+) -> Result<JoinedTable> {
+    let ast::Select {
+        with,
+        body,
+        order_by,
+        limit,
+    } = cte.select;
+    if with.is_some() {
+        crate::bail_parse_error!("WITH is not supported in recursive CTE {}", name);
+    }
+    if !order_by.is_empty() {
+        crate::bail_parse_error!("ORDER BY is not supported in recursive CTE {}", name);
+    }
+    if body.compounds.is_empty() || one_select_references_table(&body.select, &name) {
+        crate::bail_parse_error!("circular reference: {}", name);
+    }
+    let mut distinct = None;
+    for compound in body.compounds.iter() {
+        let compound_distinct = match compound.operator {
+            ast::CompoundOperator::Union => true,
+            ast::CompoundOperator::UnionAll => false,
+            operator => {
+                crate::bail_parse_error!("{} is not supported in recursive CTE {}", operator, name)
+            }
+        };
+        if *distinct.get_or_insert(compound_distinct) != compound_distinct {
+            crate::bail_parse_error!("recursive CTE {} cannot mix UNION and UNION ALL", name);
+        }
+        if !one_select_references_table(&compound.select, &name) {
+            crate::bail_parse_error!("recursive CTE {} must have a single initial SELECT", name);
+        }
+    }
+
+    let coroutine = || QueryDestination::CoroutineYield {
+        yield_reg: usize::MAX, // will be set later in bytecode emission
+        coroutine_implementation_start: BranchOffset::Placeholder, // will be set later in bytecode emission
+    };
+    let initial_plan = prepare_one_select_plan(
+        schema,
+        body.select,
+        None,
+        vec![],
+        None,
+        syms,
+        outer_query_refs,
+        table_ref_counter,
+        coroutine(),
+        connection,
+    )?;
+    let mut cte_table =
+        JoinedTable::new_subquery(name.clone(), initial_plan, None, table_ref_counter.next());
+    let Table::FromClauseSubquery(subquery) = &mut cte_table.table else {
+        unreachable!("new_subquery always creates a FromClauseSubquery");
+    };
+    apply_cte_column_names(&name, &mut subquery.columns, &cte.columns)?;
+
+    let mut outer_query_refs_for_recursive = outer_query_refs.to_vec();
+    outer_query_refs_for_recursive.push(OuterQueryReference {
+        identifier: name.clone(),
+        internal_id: table_ref_counter.next(),
+        table: Table::FromClauseSubquery(FromClauseSubquery {
+            name: name.clone(),
+            // The plan of a recursive reference is never run.
+            plan: subquery.plan.clone(),
+            columns: subquery.columns.clone(),
+            result_columns_start_reg: None,
+            kind: SubqueryKind::RecursiveReference,
+        }),
+        col_used_mask: ColumnUsedMask::default(),
+    });
+
+    let mut recursive_plans = Vec::with_capacity(body.compounds.len());
+    for compound in body.compounds {
+        let plan = prepare_one_select_plan(
+            schema,
+            compound.select,
+            None,
+            vec![],
+            None,
+            syms,
+            &outer_query_refs_for_recursive,
+            table_ref_counter,
+            coroutine(),
+            connection,
+        )?;
+        if plan.result_columns.len() != subquery.columns.len() {
+            crate::bail_parse_error!(
+                "SELECTs to the left and right of {} do not have the same number of result columns",
+                compound.operator
+            );
+        }
+        let mut num_references = 0;
+        for table in plan.joined_tables() {
+            let Table::FromClauseSubquery(table_subquery) = &table.table else {
+                continue;
+            };
+            if matches!(table_subquery.kind, SubqueryKind::RecursiveReference) {
+                num_references += 1;
+            } else if plan_references_recursive_cte(&table_subquery.plan) {
+                crate::bail_parse_error!("recursive reference in a subquery: {}", name);
+            }
+        }
+        if num_references > 1 {
+            crate::bail_parse_error!("multiple references to recursive table: {}", name);
+        }
+        recursive_plans.push(plan);
+    }
+
+    let (limit, offset) =
+        limit.map_or(Ok((None, None)), |mut l| parse_limit(&mut l, connection))?;
+    subquery.kind = SubqueryKind::Recursive(RecursiveCte {
+        recursive_plans,
+        distinct: distinct.unwrap_or_default(),
+        limit,
+        offset,
+    });
+    Ok(cte_table)
+}
+
+/// Returns true if `plan` or any of its FROM clause subqueries reads a recursive CTE from within
+/// one of the recursive SELECTs of that CTE.
+fn plan_references_recursive_cte(plan: &SelectPlan) -> bool {
+    plan.joined_tables().iter().any(|table| match &table.table {
+        Table::FromClauseSubquery(subquery) => {
+            matches!(subquery.kind, SubqueryKind::RecursiveReference)
+                || plan_references_recursive_cte(&subquery.plan)
+        }
+        _ => false,
+    })
+}
+
 pub fn parse_where(
     where_clause: Option<&Expr>,
     table_references: &mut TableReferences,
@@ -935,6 +1300,20 @@ pub fn table_mask_from_expr(
                     crate::bail_parse_error!("table not found in joined_tables");
                 }
             }
+            // A correlated subquery can only be evaluated once the tables it refers to are in scope.
+            Expr::SubqueryResult {
+                correlated_tables, ..
+            } => {
+                for table in correlated_tables {
+                    if let Some(table_idx) = table_references
+                        .joined_tables()
+                        .iter()
+                        .position(|t| t.internal_id == *table)
+                    {
+                        mask.add_table(table_idx);
+                    }
+                }
+            }
             _ => {}
         }
         Ok(WalkControl::Continue)
@@ -950,12 +1329,21 @@ pub fn determine_where_to_eval_expr(
     let mut eval_at: EvalAt = EvalAt::BeforeLoop;
     walk_expr(top_level_expr, &mut |expr: &Expr| -> Result<WalkControl> {
         match expr {
+            // Tables that are not in the join order belong to an outer query scope,
+            // so they are already in scope when this query runs.
             Expr::Column { table, .. } | Expr::RowId { table, .. } => {
-                let join_idx = join_order
-                    .iter()
-                    .position(|t| t.table_id == *table)
-                    .unwrap_or(usize::MAX);
-                eval_at = eval_at.max(EvalAt::Loop(join_idx));
+                if let Some(join_idx) = join_order.iter().position(|t| t.table_id == *table) {
+                    eval_at = eval_at.max(EvalAt::Loop(join_idx));
+                }
+            }
+            Expr::SubqueryResult {
+                correlated_tables, ..
+            } => {
+                for table in correlated_tables {
+                    if let Some(join_idx) = join_order.iter().position(|t| t.table_id == *table) {
+                        eval_at = eval_at.max(EvalAt::Loop(join_idx));
+                    }
+                }
             }
             _ => {}
         }
@@ -970,7 +1358,7 @@ fn parse_join(
     schema: &Schema,
     join: ast::JoinedSelectTable,
     syms: &SymbolTable,
-    ctes: &mut Vec<JoinedTable>,
+    ctes: &mut [Cte],
     out_where_clause: &mut Vec<WhereTerm>,
     vtab_predicates: &mut Vec<Expr>,
     table_references: &mut TableReferences,
@@ -994,21 +1382,29 @@ fn parse_join(
         connection,
     )?;
 
-    let (outer, natural) = match join_operator {
+    let (outer, right, cross, natural) = match join_operator {
         ast::JoinOperator::TypedJoin(Some(join_type)) => {
-            if join_type.contains(JoinType::RIGHT) {
-                crate::bail_parse_error!("RIGHT JOIN is not supported");
-            }
-            if join_type.contains(JoinType::CROSS) {
-                crate::bail_parse_error!("CROSS JOIN is not supported");
-            }
-            let is_outer = join_type.contains(JoinType::OUTER);
+            // FULL OUTER JOIN is both a LEFT and a RIGHT join.
+            let is_outer = join_type.contains(JoinType::LEFT);
+            let is_right = join_type.contains(JoinType::RIGHT);
+            let is_cross = join_type.contains(JoinType::CROSS);
             let is_natural = join_type.contains(JoinType::NATURAL);
-            (is_outer, is_natural)
+            (is_outer, is_right, is_cross, is_natural)
         }
-        _ => (false, false),
+        _ => (false, false, false, false),
     };
 
+    // The rows of the tables to the left of a RIGHT JOIN are set to NULL with NullRow,
+    // which virtual tables do not support.
+    if right
+        && table_references
+            .joined_tables()
+            .iter()
+            .any(|t| matches!(t.table, Table::Virtual(_)))
+    {
+        crate::bail_parse_error!("RIGHT and FULL OUTER JOIN are not supported with virtual tables");
+    }
+
     if natural && constraint.is_some() {
         crate::bail_parse_error!("NATURAL JOIN cannot be combined with ON or USING clause");
     }
@@ -1063,7 +1459,7 @@ fn parse_join(
                 for pred in preds {
                     out_where_clause.push(WhereTerm {
                         expr: pred,
-                        from_outer_join: if outer {
+                        from_outer_join: if outer || right {
                             Some(table_references.joined_tables().last().unwrap().internal_id)
                         } else {
                             None
@@ -1144,7 +1540,7 @@ fn parse_join(
                     right_table.mark_column_used(right_col_idx);
                     out_where_clause.push(WhereTerm {
                         expr,
-                        from_outer_join: if outer {
+                        from_outer_join: if outer || right {
                             Some(right_table.internal_id)
                         } else {
                             None
@@ -1163,7 +1559,12 @@ fn parse_join(
         .joined_tables_mut()
         .get_mut(last_idx)
         .unwrap();
-    rightmost_table.join_info = Some(JoinInfo { outer, using });
+    rightmost_table.join_info = Some(JoinInfo {
+        outer,
+        right,
+        cross,
+        using,
+    });
 
     Ok(())
 }
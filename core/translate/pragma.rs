@@ -8,17 +8,21 @@ use turso_macros::match_ignore_ascii_case;
 use turso_parser::ast::{self, ColumnDefinition, Expr, Literal, Name};
 use turso_parser::ast::{PragmaName, QualifiedName};
 
+use super::analyze::translate_optimize;
 use super::integrity_check::translate_integrity_check;
 use crate::pragma::pragma_for;
-use crate::schema::Schema;
+use crate::schema::{ForeignKey, Schema};
 use crate::storage::encryption::{CipherMode, EncryptionKey};
 use crate::storage::pager::AutoVacuumMode;
 use crate::storage::pager::Pager;
 use crate::storage::sqlite3_ondisk::CacheSize;
 use crate::storage::wal::CheckpointMode;
 use crate::translate::emitter::TransactionMode;
+use crate::translate::fkeys::emit_foreign_key_check;
 use crate::translate::schema::translate_create_table;
-use crate::util::{normalize_ident, parse_signed_number, parse_string, IOExt as _};
+use crate::util::{
+    normalize_ident, parse_pragma_bool, parse_signed_number, parse_string, IOExt as _,
+};
 use crate::vdbe::builder::{ProgramBuilder, ProgramBuilderOpts};
 use crate::vdbe::insn::{Cookie, Insn};
 use crate::{bail_parse_error, CaptureDataChangesMode, LimboError, SymbolTable, Value};
@@ -60,13 +64,63 @@ pub fn translate_pragma(
         Err(_) => bail_parse_error!("Not a valid pragma name"),
     };
 
+    // PRAGMA schema.name applies to the given database, the main one if it is omitted
+    let database_id = match &name.db_name {
+        Some(db_name) => connection.resolve_database_name(db_name.as_str())?,
+        None => 0,
+    };
+    let database_schema = match database_id {
+        0 => None,
+        _ if pragma_supports_schema(pragma) => connection.database_schema(database_id)?,
+        _ => bail_parse_error!("PRAGMA {pragma} is not supported on attached databases yet"),
+    };
+    let schema = database_schema.as_deref().unwrap_or(schema);
+
     let (mut program, mode) = match body {
-        None => query_pragma(pragma, schema, None, pager, connection, program)?,
+        None if pragma == PragmaName::Optimize => (
+            translate_optimize(schema, syms, &connection, program)?,
+            TransactionMode::Write,
+        ),
+        body if matches!(pragma, PragmaName::IntegrityCheck | PragmaName::QuickCheck) => {
+            let value = body.map(|body| match body {
+                ast::PragmaBody::Equals(value) | ast::PragmaBody::Call(value) => *value,
+            });
+            let quick = pragma == PragmaName::QuickCheck;
+            translate_integrity_check(schema, syms, &mut program, quick, value)?;
+            program.add_pragma_result_column(pragma.to_string());
+            (program, TransactionMode::Read)
+        }
+        None => query_pragma(
+            pragma,
+            schema,
+            database_id,
+            None,
+            pager,
+            connection,
+            program,
+        )?,
         Some(ast::PragmaBody::Equals(value) | ast::PragmaBody::Call(value)) => match pragma {
-            PragmaName::TableInfo => {
-                query_pragma(pragma, schema, Some(*value), pager, connection, program)?
+            PragmaName::TableInfo | PragmaName::ForeignKeyList | PragmaName::ForeignKeyCheck => {
+                query_pragma(
+                    pragma,
+                    schema,
+                    database_id,
+                    Some(*value),
+                    pager,
+                    connection,
+                    program,
+                )?
             }
-            _ => update_pragma(pragma, schema, syms, *value, pager, connection, program)?,
+            _ => update_pragma(
+                pragma,
+                schema,
+                database_id,
+                syms,
+                *value,
+                pager,
+                connection,
+                program,
+            )?,
         },
     };
     match mode {
@@ -85,9 +139,25 @@ pub fn translate_pragma(
     Ok(program)
 }
 
+/// Whether a pragma can be applied to the temp database or to an attached database
+fn pragma_supports_schema(pragma: PragmaName) -> bool {
+    matches!(
+        pragma,
+        PragmaName::ApplicationId
+            | PragmaName::ForeignKeyCheck
+            | PragmaName::ForeignKeyList
+            | PragmaName::PageCount
+            | PragmaName::SchemaVersion
+            | PragmaName::TableInfo
+            | PragmaName::UserVersion
+    )
+}
+
+#[allow(clippy::too_many_arguments)]
 fn update_pragma(
     pragma: PragmaName,
     schema: &Schema,
+    database_id: usize,
     syms: &SymbolTable,
     value: ast::Expr,
     pager: Rc<Pager>,
@@ -95,6 +165,26 @@ fn update_pragma(
     mut program: ProgramBuilder,
 ) -> crate::Result<(ProgramBuilder, TransactionMode)> {
     match pragma {
+        PragmaName::AnalysisLimit => {
+            let limit = match parse_signed_number(&value)? {
+                Value::Integer(limit) => limit,
+                Value::Float(limit) => limit as i64,
+                _ => bail_parse_error!("Invalid value for analysis_limit pragma"),
+            };
+            // Like SQLite, negative limits are ignored.
+            if limit >= 0 {
+                connection.set_analysis_limit(limit as u64);
+            }
+            query_pragma(
+                PragmaName::AnalysisLimit,
+                schema,
+                database_id,
+                None,
+                pager,
+                connection,
+                program,
+            )
+        }
         PragmaName::ApplicationId => {
             let data = parse_signed_number(&value)?;
             let app_id_value = match data {
@@ -104,7 +194,7 @@ fn update_pragma(
             };
 
             program.emit_insn(Insn::SetCookie {
-                db: 0,
+                db: database_id,
                 cookie: Cookie::ApplicationId,
                 value: app_id_value,
                 p5: 1,
@@ -145,15 +235,46 @@ fn update_pragma(
         PragmaName::WalCheckpoint => query_pragma(
             PragmaName::WalCheckpoint,
             schema,
+            database_id,
             Some(value),
             pager,
             connection,
             program,
         ),
         PragmaName::ModuleList => Ok((program, TransactionMode::None)),
+        PragmaName::MvccMemoryLimit => {
+            let limit = match parse_signed_number(&value)? {
+                Value::Integer(limit) => limit,
+                Value::Float(limit) => limit as i64,
+                _ => bail_parse_error!("Invalid value for mvcc_memory_limit pragma"),
+            };
+            // Negative limits are ignored, like those of analysis_limit.
+            if limit >= 0 {
+                connection.set_mvcc_memory_limit(limit as usize);
+            }
+            query_pragma(
+                PragmaName::MvccMemoryLimit,
+                schema,
+                database_id,
+                None,
+                pager,
+                connection,
+                program,
+            )
+        }
+        PragmaName::Optimize => {
+            // The mask argument selects which optimizations to run. Only ANALYZE is implemented,
+            // so the mask is validated but otherwise ignored.
+            parse_signed_number(&value)?;
+            Ok((
+                translate_optimize(schema, syms, &connection, program)?,
+                TransactionMode::Write,
+            ))
+        }
         PragmaName::PageCount => query_pragma(
             PragmaName::PageCount,
             schema,
+            database_id,
             None,
             pager,
             connection,
@@ -186,7 +307,7 @@ fn update_pragma(
             };
 
             program.emit_insn(Insn::SetCookie {
-                db: 0,
+                db: database_id,
                 cookie: Cookie::UserVersion,
                 value: version_value,
                 p5: 1,
@@ -270,7 +391,9 @@ fn update_pragma(
             });
             Ok((program, TransactionMode::None))
         }
-        PragmaName::IntegrityCheck => unreachable!("integrity_check cannot be set"),
+        PragmaName::IntegrityCheck | PragmaName::QuickCheck => {
+            unreachable!("{pragma} is translated by translate_pragma")
+        }
         PragmaName::UnstableCaptureDataChangesConn => {
             let value = parse_string(&value)?;
             // todo(sivukhin): ideally, we should consistently update capture_data_changes connection flag only after successfull execution of schema change statement
@@ -301,9 +424,24 @@ fn update_pragma(
             Ok((program, TransactionMode::Write))
         }
         PragmaName::DatabaseList => unreachable!("database_list cannot be set"),
+        PragmaName::DeferForeignKeys => {
+            connection.set_defer_foreign_keys(parse_pragma_bool(&value)?);
+            Ok((program, TransactionMode::None))
+        }
+        PragmaName::ForeignKeys => {
+            // Like SQLite, foreign keys cannot be enabled or disabled inside a transaction
+            if connection.get_auto_commit() {
+                connection.set_foreign_keys_enabled(parse_pragma_bool(&value)?);
+            }
+            Ok((program, TransactionMode::None))
+        }
+        PragmaName::ForeignKeyCheck | PragmaName::ForeignKeyList => {
+            unreachable!("{pragma} cannot be set")
+        }
         PragmaName::QueryOnly => query_pragma(
             PragmaName::QueryOnly,
             schema,
+            database_id,
             Some(value),
             pager,
             connection,
@@ -312,6 +450,7 @@ fn update_pragma(
         PragmaName::FreelistCount => query_pragma(
             PragmaName::FreelistCount,
             schema,
+            database_id,
             Some(value),
             pager,
             connection,
@@ -329,6 +468,30 @@ fn update_pragma(
             connection.set_encryption_cipher(cipher)?;
             Ok((program, TransactionMode::None))
         }
+        PragmaName::TempStore => {
+            use crate::TempStore;
+
+            // Like SQLite, unknown values select the default
+            let temp_store = match value {
+                Expr::Name(name) => {
+                    let name_bytes = name.as_str().as_bytes();
+                    match_ignore_ascii_case!(match name_bytes {
+                        b"FILE" | b"1" => TempStore::File,
+                        b"MEMORY" | b"2" => TempStore::Memory,
+                        _ => TempStore::Default,
+                    })
+                }
+                Expr::Literal(Literal::Numeric(n)) => match n.as_str() {
+                    "1" => TempStore::File,
+                    "2" => TempStore::Memory,
+                    _ => TempStore::Default,
+                },
+                _ => TempStore::Default,
+            };
+
+            connection.set_temp_store(temp_store)?;
+            Ok((program, TransactionMode::None))
+        }
         PragmaName::Synchronous => {
             use crate::SyncMode;
 
@@ -372,6 +535,7 @@ fn update_pragma(
 fn query_pragma(
     pragma: PragmaName,
     schema: &Schema,
+    database_id: usize,
     value: Option<ast::Expr>,
     pager: Rc<Pager>,
     connection: Arc<crate::Connection>,
@@ -379,9 +543,15 @@ fn query_pragma(
 ) -> crate::Result<(ProgramBuilder, TransactionMode)> {
     let register = program.alloc_register();
     match pragma {
+        PragmaName::AnalysisLimit => {
+            program.emit_int(connection.get_analysis_limit() as i64, register);
+            program.emit_result_row(register, 1);
+            program.add_pragma_result_column(pragma.to_string());
+            Ok((program, TransactionMode::None))
+        }
         PragmaName::ApplicationId => {
             program.emit_insn(Insn::ReadCookie {
-                db: 0,
+                db: database_id,
                 dest: register,
                 cookie: Cookie::ApplicationId,
             });
@@ -420,6 +590,12 @@ fn query_pragma(
             }
             Ok((program, TransactionMode::None))
         }
+        PragmaName::DeferForeignKeys => {
+            program.emit_int(connection.get_defer_foreign_keys() as i64, register);
+            program.emit_result_row(register, 1);
+            program.add_pragma_result_column(pragma.to_string());
+            Ok((program, TransactionMode::None))
+        }
         PragmaName::Encoding => {
             let encoding = pager
                 .io
@@ -431,6 +607,83 @@ fn query_pragma(
             program.add_pragma_result_column(pragma.to_string());
             Ok((program, TransactionMode::None))
         }
+        PragmaName::ForeignKeyCheck => {
+            let name = match value {
+                Some(ast::Expr::Name(name)) => Some(normalize_ident(name.as_str())),
+                _ => None,
+            };
+            let tables = match name {
+                Some(name) => match schema.get_btree_table(&name) {
+                    Some(table) => vec![table],
+                    None => bail_parse_error!("no such table: {}", name),
+                },
+                None => {
+                    let mut tables = schema
+                        .tables
+                        .values()
+                        .filter_map(|table| table.btree())
+                        .collect::<Vec<_>>();
+                    tables.sort_by(|a, b| a.name.cmp(&b.name));
+                    tables
+                }
+            };
+
+            program.alloc_registers(3);
+            for table in tables {
+                emit_foreign_key_check(&mut program, schema, database_id, &table, register)?;
+            }
+            let pragma = pragma_for(&pragma);
+            for col_name in pragma.columns.iter() {
+                program.add_pragma_result_column(col_name.to_string());
+            }
+            Ok((program, TransactionMode::Read))
+        }
+        PragmaName::ForeignKeyList => {
+            let name = match value {
+                Some(ast::Expr::Name(name)) => Some(normalize_ident(name.as_str())),
+                _ => None,
+            };
+
+            let base_reg = register;
+            program.alloc_registers(7);
+            if let Some(table) = name.and_then(|name| schema.get_btree_table(&name)) {
+                // Like SQLite, the last declared constraint comes first
+                for (id, foreign_key) in table.foreign_keys.iter().rev().enumerate() {
+                    for (seq, from) in foreign_key.child_columns.iter().enumerate() {
+                        program.emit_int(id as i64, base_reg);
+                        program.emit_int(seq as i64, base_reg + 1);
+                        program.emit_string8(foreign_key.parent_table.clone(), base_reg + 2);
+                        program.emit_string8(from.clone(), base_reg + 3);
+                        // The referenced columns are NULL if they are the primary key
+                        match foreign_key.parent_columns.get(seq) {
+                            Some(to) => program.emit_string8(to.clone(), base_reg + 4),
+                            None => program.emit_null(base_reg + 4, None),
+                        }
+                        program.emit_string8(
+                            ForeignKey::action_sql(foreign_key.on_update).to_string(),
+                            base_reg + 5,
+                        );
+                        program.emit_string8(
+                            ForeignKey::action_sql(foreign_key.on_delete).to_string(),
+                            base_reg + 6,
+                        );
+                        program.emit_string8("NONE".to_string(), base_reg + 7);
+                        program.emit_result_row(base_reg, 8);
+                    }
+                }
+            }
+            let pragma = pragma_for(&pragma);
+            for col_name in pragma.columns.iter() {
+                program.add_pragma_result_column(col_name.to_string());
+            }
+            Ok((program, TransactionMode::None))
+        }
+        PragmaName::ForeignKeys => {
+            program.emit_int(connection.foreign_keys_enabled() as i64, register);
+            program.emit_result_row(register, 1);
+            program.add_pragma_result_column(pragma.to_string());
+            Ok((program, TransactionMode::None))
+        }
         PragmaName::JournalMode => {
             // Use the JournalMode opcode to get the current journal mode
             program.emit_insn(Insn::JournalMode {
@@ -477,9 +730,16 @@ fn query_pragma(
             program.add_pragma_result_column(pragma.to_string());
             Ok((program, TransactionMode::None))
         }
+        PragmaName::MvccMemoryLimit => {
+            program.emit_int(connection.get_mvcc_memory_limit() as i64, register);
+            program.emit_result_row(register, 1);
+            program.add_pragma_result_column(pragma.to_string());
+            Ok((program, TransactionMode::None))
+        }
+        PragmaName::Optimize => unreachable!("optimize is translated by translate_pragma"),
         PragmaName::PageCount => {
             program.emit_insn(Insn::PageCount {
-                db: 0,
+                db: database_id,
                 dest: register,
             });
             program.emit_result_row(register, 1);
@@ -522,7 +782,7 @@ fn query_pragma(
         }
         PragmaName::UserVersion => {
             program.emit_insn(Insn::ReadCookie {
-                db: 0,
+                db: database_id,
                 dest: register,
                 cookie: Cookie::UserVersion,
             });
@@ -532,7 +792,7 @@ fn query_pragma(
         }
         PragmaName::SchemaVersion => {
             program.emit_insn(Insn::ReadCookie {
-                db: 0,
+                db: database_id,
                 dest: register,
                 cookie: Cookie::SchemaVersion,
             });
@@ -569,9 +829,8 @@ fn query_pragma(
             program.emit_result_row(register, 1);
             Ok((program, TransactionMode::None))
         }
-        PragmaName::IntegrityCheck => {
-            translate_integrity_check(schema, &mut program)?;
-            Ok((program, TransactionMode::Read))
+        PragmaName::IntegrityCheck | PragmaName::QuickCheck => {
+            unreachable!("{pragma} is translated by translate_pragma")
         }
         PragmaName::UnstableCaptureDataChangesConn => {
             let pragma = pragma_for(&pragma);
@@ -649,6 +908,13 @@ fn query_pragma(
             }
             Ok((program, TransactionMode::None))
         }
+        PragmaName::TempStore => {
+            let register = program.alloc_register();
+            program.emit_int(connection.get_temp_store() as i64, register);
+            program.emit_result_row(register, 1);
+            program.add_pragma_result_column(pragma.to_string());
+            Ok((program, TransactionMode::None))
+        }
         PragmaName::Synchronous => {
             let mode = connection.get_sync_mode();
             let register = program.alloc_register();
@@ -677,8 +943,13 @@ fn emit_columns_for_table_info(
 ) {
     // According to the SQLite documentation: "The 'cid' column should not be taken to
     // mean more than 'rank within the current result set'."
-    // Therefore, we enumerate only after filtering out hidden columns.
-    for (i, column) in columns.iter().filter(|col| !col.hidden).enumerate() {
+    // Therefore, we enumerate only after filtering out hidden columns. Like in SQLite, generated
+    // columns are hidden as well.
+    for (i, column) in columns
+        .iter()
+        .filter(|col| !col.hidden && !col.is_generated())
+        .enumerate()
+    {
         // cid
         program.emit_int(i as i64, base_reg);
         // name
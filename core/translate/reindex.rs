@@ -0,0 +1,120 @@
+//! REINDEX, see <https://www.sqlite.org/lang_reindex.html>.
+//!
+//! Every index that is rebuilt is emptied and then filled again from the rows of its table, the
+//! same way CREATE INDEX fills a new index.
+
+use std::sync::Arc;
+
+use turso_parser::ast;
+
+use crate::{
+    bail_parse_error,
+    schema::{BTreeTable, Index, Schema},
+    translate::{collate::CollationSeq, emitter::Resolver, index::emit_index_population},
+    util::normalize_ident,
+    vdbe::{builder::ProgramBuilder, insn::RegisterOrLiteral},
+    Connection, Result, SymbolTable,
+};
+
+pub fn translate_reindex(
+    target_opt: Option<ast::QualifiedName>,
+    schema: &Schema,
+    syms: &SymbolTable,
+    connection: &Arc<Connection>,
+    mut program: ProgramBuilder,
+) -> Result<ProgramBuilder> {
+    let database_id = match &target_opt {
+        // An unqualified name that is not a collation sequence names a table or an index
+        Some(target)
+            if target.db_name.is_some() || CollationSeq::new(target.name.as_str()).is_err() =>
+        {
+            connection.resolve_database_id_with(target, |schema, name| {
+                schema.get_btree_table(name).is_some() || find_index(schema, name).is_some()
+            })?
+        }
+        _ => 0,
+    };
+    let database_schema = connection.database_schema(database_id)?;
+    let schema = database_schema.as_deref().unwrap_or(schema);
+
+    let indexes = resolve_reindex_targets(target_opt, schema)?;
+    let resolver = Resolver::new(schema, syms);
+    for (table, index) in indexes {
+        emit_index_population(
+            &mut program,
+            &resolver,
+            database_id,
+            &table,
+            &index,
+            RegisterOrLiteral::Literal(index.root_page),
+            true,
+        )?;
+    }
+    Ok(program)
+}
+
+/// The indexes rebuilt by REINDEX: all of them, the ones using a collation sequence, the ones
+/// of a table, or a single one.
+fn resolve_reindex_targets(
+    target_opt: Option<ast::QualifiedName>,
+    schema: &Schema,
+) -> Result<Vec<(Arc<BTreeTable>, Arc<Index>)>> {
+    let Some(target) = target_opt else {
+        return Ok(reindexable_indexes(schema, |_| true));
+    };
+    let name = normalize_ident(target.name.as_str());
+    if target.db_name.is_none() {
+        if let Ok(collation) = CollationSeq::new(&name) {
+            return Ok(reindexable_indexes(schema, |index| {
+                index
+                    .columns
+                    .iter()
+                    .any(|column| column.collation.unwrap_or_default() == collation)
+            }));
+        }
+    }
+    if let Some(table) = schema.get_btree_table(&name) {
+        return Ok(reindexable_indexes(schema, |index| {
+            normalize_ident(&index.table_name) == normalize_ident(&table.name)
+        }));
+    }
+    if let Some(index) = find_index(schema, &name) {
+        let table = schema
+            .get_btree_table(&index.table_name)
+            .expect("index must belong to a btree table");
+        if index.is_without_rowid_primary_key(&table) {
+            return Ok(vec![]);
+        }
+        return Ok(vec![(table, index)]);
+    }
+    bail_parse_error!("unable to identify the object to be reindexed")
+}
+
+/// The indexes of the schema that `filter` accepts, along with their tables. The primary key
+/// index of a WITHOUT ROWID table is the table itself and is never rebuilt.
+fn reindexable_indexes(
+    schema: &Schema,
+    filter: impl Fn(&Index) -> bool,
+) -> Vec<(Arc<BTreeTable>, Arc<Index>)> {
+    let mut indexes = schema
+        .indexes
+        .values()
+        .flatten()
+        .filter(|index| !index.ephemeral && filter(index))
+        .filter_map(|index| {
+            let table = schema.get_btree_table(&index.table_name)?;
+            (!index.is_without_rowid_primary_key(&table)).then(|| (table, index.clone()))
+        })
+        .collect::<Vec<_>>();
+    indexes.sort_by(|(_, a), (_, b)| a.name.cmp(&b.name));
+    indexes
+}
+
+fn find_index(schema: &Schema, name: &str) -> Option<Arc<Index>> {
+    schema
+        .indexes
+        .values()
+        .flatten()
+        .find(|index| normalize_ident(&index.name) == name)
+        .cloned()
+}
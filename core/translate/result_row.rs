@@ -143,6 +143,7 @@ pub fn emit_result_row_and_limit(
                 end_offset: BranchOffset::Offset(0),
             });
         }
+        QueryDestination::Discard => {}
     }
 
     if plan.limit.is_some() {
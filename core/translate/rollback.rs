@@ -2,7 +2,11 @@ use turso_parser::ast::Name;
 
 use crate::{
     schema::Schema,
-    vdbe::{builder::ProgramBuilder, insn::Insn},
+    util::normalize_ident,
+    vdbe::{
+        builder::ProgramBuilder,
+        insn::{Insn, SavepointOp},
+    },
     Result, SymbolTable,
 };
 
@@ -10,13 +14,17 @@ pub fn translate_rollback(
     _schema: &Schema,
     _syms: &SymbolTable,
     mut program: ProgramBuilder,
-    txn_name: Option<Name>,
+    _txn_name: Option<Name>,
     savepoint_name: Option<Name>,
 ) -> Result<ProgramBuilder> {
-    assert!(
-        txn_name.is_none() && savepoint_name.is_none(),
-        "txn_name and savepoint not supported yet"
-    );
+    if let Some(savepoint_name) = savepoint_name {
+        // ROLLBACK TO only undoes the work done since the savepoint; the transaction stays open.
+        program.emit_insn(Insn::Savepoint {
+            op: SavepointOp::Rollback,
+            name: normalize_ident(savepoint_name.as_str()),
+        });
+        return Ok(program);
+    }
     program.emit_insn(Insn::AutoCommit {
         auto_commit: true,
         rollback: true,
@@ -1,4 +1,3 @@
-use std::ops::Range;
 use std::rc::Rc;
 use std::sync::Arc;
 
@@ -16,6 +15,7 @@ use crate::translate::emitter::emit_cdc_insns;
 use crate::translate::emitter::prepare_cdc_if_necessary;
 use crate::translate::emitter::OperationMode;
 use crate::translate::emitter::Resolver;
+use crate::translate::trigger::emit_drop_trigger;
 use crate::translate::ProgramBuilder;
 use crate::translate::ProgramBuilderOpts;
 use crate::util::normalize_ident;
@@ -39,9 +39,9 @@ pub fn translate_create_table(
     connection: &Arc<crate::Connection>,
     mut program: ProgramBuilder,
 ) -> Result<ProgramBuilder> {
-    if temporary {
-        bail_parse_error!("TEMPORARY table not supported yet");
-    }
+    let database_id = resolve_create_database_id(connection, &tbl_name, temporary)?;
+    let database_schema = connection.database_schema(database_id)?;
+    let schema = database_schema.as_deref().unwrap_or(schema);
 
     // Check for STRICT mode without experimental flag
     if let ast::CreateTableBody::ColumnsAndConstraints { options, .. } = &body {
@@ -67,6 +67,10 @@ pub fn translate_create_table(
     }
 
     let sql = create_table_body_to_str(&tbl_name, &body);
+    let table = create_table(tbl_name.name.as_str(), &body, 0)?; // the root page is not known yet
+    if !table.has_rowid && table.primary_key_columns.is_empty() {
+        bail_parse_error!("PRIMARY KEY missing on table {}", normalized_tbl_name);
+    }
 
     let parse_schema_label = program.allocate_label();
     // TODO: ReadCookie
@@ -74,12 +78,17 @@ pub fn translate_create_table(
     // TODO: SetCookie
     // TODO: SetCookie
 
-    // Create the table B-tree
+    // Create the table B-tree.
+    // A WITHOUT ROWID table is stored as an index b-tree keyed by its primary key.
     let table_root_reg = program.alloc_register();
     program.emit_insn(Insn::CreateBtree {
-        db: 0,
+        db: database_id,
         root: table_root_reg,
-        flags: CreateBTreeFlags::new_table(),
+        flags: if table.has_rowid {
+            CreateBTreeFlags::new_table()
+        } else {
+            CreateBTreeFlags::new_index()
+        },
     });
 
     // Create an automatic index B-tree if needed
@@ -106,19 +115,16 @@ pub fn translate_create_table(
     // https://github.com/sqlite/sqlite/blob/95f6df5b8d55e67d1e34d2bff217305a2f21b1fb/src/build.c#L2856-L2871
     // https://github.com/sqlite/sqlite/blob/95f6df5b8d55e67d1e34d2bff217305a2f21b1fb/src/build.c#L1334C5-L1336C65
 
-    let index_regs =
-        check_automatic_pk_index_required(&body, &mut program, tbl_name.name.as_str())?;
-    if let Some(index_regs) = index_regs.as_ref() {
-        if !schema.indexes_enabled() {
-            bail_parse_error!("Constraints UNIQUE and PRIMARY KEY (unless INTEGER PRIMARY KEY) on table are not supported without indexes");
-        }
-        for index_reg in index_regs.clone() {
-            program.emit_insn(Insn::CreateBtree {
-                db: 0,
-                root: index_reg,
-                flags: CreateBTreeFlags::new_index(),
-            });
-        }
+    if !table.unique_sets.is_empty() && !schema.indexes_enabled() {
+        bail_parse_error!("Constraints UNIQUE and PRIMARY KEY (unless INTEGER PRIMARY KEY) on table are not supported without indexes");
+    }
+    let index_regs = check_automatic_pk_index_required(&table, &mut program);
+    for (_, index_reg) in index_regs.iter() {
+        program.emit_insn(Insn::CreateBtree {
+            db: database_id,
+            root: *index_reg,
+            flags: CreateBTreeFlags::new_index(),
+        });
     }
 
     let table = schema.get_btree_table(SQLITE_TABLEID).unwrap();
@@ -126,10 +132,15 @@ pub fn translate_create_table(
     program.emit_insn(Insn::OpenWrite {
         cursor_id: sqlite_schema_cursor_id,
         root_page: 1usize.into(),
-        db: 0,
+        db: database_id,
     });
 
-    let cdc_table = prepare_cdc_if_necessary(&mut program, schema, SQLITE_TABLEID)?;
+    // changes to the temp database are not captured
+    let cdc_table = if database_id == 0 {
+        prepare_cdc_if_necessary(&mut program, schema, SQLITE_TABLEID)?
+    } else {
+        None
+    };
     let resolver = Resolver::new(schema, syms);
     // Add the table entry to sqlite_schema
     emit_schema_entry(
@@ -144,33 +155,31 @@ pub fn translate_create_table(
         Some(sql),
     )?;
 
-    // If we need an automatic index, add its entry to sqlite_schema
-    if let Some(index_regs) = index_regs {
-        for (idx, index_reg) in index_regs.into_iter().enumerate() {
-            let index_name = format!(
-                "{}{}_{}",
-                PRIMARY_KEY_AUTOMATIC_INDEX_NAME_PREFIX,
-                tbl_name.name.as_str(),
-                idx + 1
-            );
-            emit_schema_entry(
-                &mut program,
-                &resolver,
-                sqlite_schema_cursor_id,
-                None,
-                SchemaEntryType::Index,
-                &index_name,
-                &normalized_tbl_name,
-                index_reg,
-                None,
-            )?;
-        }
+    // If we need automatic indexes, add their entries to sqlite_schema
+    for (index_number, index_reg) in index_regs {
+        let index_name = format!(
+            "{}{}_{}",
+            PRIMARY_KEY_AUTOMATIC_INDEX_NAME_PREFIX,
+            tbl_name.name.as_str(),
+            index_number
+        );
+        emit_schema_entry(
+            &mut program,
+            &resolver,
+            sqlite_schema_cursor_id,
+            None,
+            SchemaEntryType::Index,
+            &index_name,
+            &normalized_tbl_name,
+            index_reg,
+            None,
+        )?;
     }
 
     program.resolve_label(parse_schema_label, program.offset());
     // TODO: SetCookie
     program.emit_insn(Insn::SetCookie {
-        db: 0,
+        db: database_id,
         cookie: Cookie::SchemaVersion,
         value: schema.schema_version as i32 + 1,
         p5: 0,
@@ -179,7 +188,7 @@ pub fn translate_create_table(
     let parse_schema_where_clause =
         format!("tbl_name = '{normalized_tbl_name}' AND type != 'trigger'");
     program.emit_insn(Insn::ParseSchema {
-        db: sqlite_schema_cursor_id,
+        db: database_id,
         where_clause: Some(parse_schema_where_clause),
     });
 
@@ -188,11 +197,30 @@ pub fn translate_create_table(
     Ok(program)
 }
 
+/// Resolves the database a CREATE statement creates its object in: the database the name is
+/// qualified with, otherwise the temp database for CREATE TEMP and the main database.
+pub(crate) fn resolve_create_database_id(
+    connection: &Arc<crate::Connection>,
+    name: &ast::QualifiedName,
+    temporary: bool,
+) -> Result<usize> {
+    let database_id = match &name.db_name {
+        Some(db_name) => connection.resolve_database_name(db_name.as_str())?,
+        None if temporary => 1,
+        None => 0,
+    };
+    if temporary && database_id != 1 {
+        bail_parse_error!("temporary table name must be unqualified");
+    }
+    Ok(database_id)
+}
+
 #[derive(Debug, Clone, Copy)]
 pub enum SchemaEntryType {
     Table,
     Index,
     View,
+    Trigger,
 }
 
 impl SchemaEntryType {
@@ -201,6 +229,7 @@ impl SchemaEntryType {
             SchemaEntryType::Table => "table",
             SchemaEntryType::Index => "index",
             SchemaEntryType::View => "view",
+            SchemaEntryType::Trigger => "trigger",
         }
     }
 }
@@ -288,26 +317,29 @@ pub fn emit_schema_entry(
     Ok(())
 }
 
-/// Check if an automatic PRIMARY KEY index is required for the table.
-/// If so, create a register for the index root page and return it.
+/// Check which automatic PRIMARY KEY and UNIQUE indexes are required for the table.
+/// For each of them, create a register for the index root page and return it along with
+/// the number of the automatic index, which is part of its name.
 ///
 /// An automatic PRIMARY KEY index is not required if:
 /// - The table has no PRIMARY KEY
 /// - The table has a single-column PRIMARY KEY whose typename is _exactly_ "INTEGER" e.g. not "INT".
 ///   In this case, the PRIMARY KEY column becomes an alias for the rowid.
+/// - The table is a WITHOUT ROWID table. The table itself is stored as an index on its PRIMARY KEY,
+///   which still uses up an automatic index number.
 ///
 /// Otherwise, an automatic PRIMARY KEY index is required.
 fn check_automatic_pk_index_required(
-    body: &ast::CreateTableBody,
+    table: &BTreeTable,
     program: &mut ProgramBuilder,
-    tbl_name: &str,
-) -> Result<Option<Range<usize>>> {
-    let table = create_table(tbl_name, body, 0)?; // abusing create_table() to avoid code duplication; we don't care about the root page here
-    if table.unique_sets.is_empty() {
-        return Ok(None);
-    }
-    let start_reg = program.alloc_registers(table.unique_sets.len());
-    Ok(Some(start_reg..start_reg + table.unique_sets.len()))
+) -> Vec<(usize, usize)> {
+    table
+        .unique_sets
+        .iter()
+        .enumerate()
+        .filter(|(_, unique_set)| table.has_rowid || !unique_set.is_primary_key)
+        .map(|(idx, _)| (idx + 1, program.alloc_register()))
+        .collect()
 }
 
 fn create_table_body_to_str(tbl_name: &ast::QualifiedName, body: &ast::CreateTableBody) -> String {
@@ -461,7 +493,7 @@ pub fn translate_create_virtual_table(
     });
     let parse_schema_where_clause = format!("tbl_name = '{table_name}' AND type != 'trigger'");
     program.emit_insn(Insn::ParseSchema {
-        db: sqlite_schema_cursor_id,
+        db: 0,
         where_clause: Some(parse_schema_where_clause),
     });
 
@@ -470,6 +502,7 @@ pub fn translate_create_virtual_table(
 
 pub fn translate_drop_table(
     tbl_name: ast::QualifiedName,
+    database_id: usize,
     if_exists: bool,
     schema: &Schema,
     syms: &SymbolTable,
@@ -503,7 +536,12 @@ pub fn translate_drop_table(
             tbl_name.name.as_str()
         );
     }
-    let cdc_table = prepare_cdc_if_necessary(&mut program, schema, SQLITE_TABLEID)?;
+    // changes to the temp database are not captured
+    let cdc_table = if database_id == 0 {
+        prepare_cdc_if_necessary(&mut program, schema, SQLITE_TABLEID)?
+    } else {
+        None
+    };
 
     let null_reg = program.alloc_register(); //  r1
     program.emit_null(null_reg, None);
@@ -522,7 +560,7 @@ pub fn translate_drop_table(
     program.emit_insn(Insn::OpenWrite {
         cursor_id: sqlite_schema_cursor_id_0,
         root_page: 1usize.into(),
-        db: 0,
+        db: database_id,
     });
 
     //  1. Remove all entries from the schema table related to the table we are dropping, except for triggers
@@ -620,10 +658,17 @@ pub fn translate_drop_table(
     //  2. Destroy the indices within a loop
     let indices = schema.get_indices(tbl_name.name.as_str());
     for index in indices {
+        // The primary key index of a WITHOUT ROWID table is destroyed along with the table.
+        if table
+            .btree()
+            .is_some_and(|btree| index.is_without_rowid_primary_key(&btree))
+        {
+            continue;
+        }
         program.emit_insn(Insn::Destroy {
             root: index.root_page,
             former_root_reg: 0, //  no autovacuum (https://www.sqlite.org/opcode.html#Destroy)
-            is_temp: 0,
+            is_temp: database_id,
         });
 
         //  3. TODO: Open an ephemeral table, and read over triggers from schema table into ephemeral table
@@ -639,7 +684,7 @@ pub fn translate_drop_table(
             program.emit_insn(Insn::Destroy {
                 root: table.root_page,
                 former_root_reg: table_name_and_root_page_register,
-                is_temp: 0,
+                is_temp: database_id,
             });
         }
         Table::Virtual(vtab) => {
@@ -687,9 +732,11 @@ pub fn translate_drop_table(
                 unique: false,
                 collation: None,
                 hidden: false,
+                generated: None,
             }],
             is_strict: false,
             unique_sets: vec![],
+            foreign_keys: vec![],
         });
         //  cursor id 2
         let ephemeral_cursor_id = program.alloc_cursor_id(CursorType::BTreeTable(simple_table_rc));
@@ -706,7 +753,7 @@ pub fn translate_drop_table(
         program.emit_insn(Insn::OpenRead {
             cursor_id: sqlite_schema_cursor_id_1,
             root_page: 1usize,
-            db: 0,
+            db: database_id,
         });
 
         let schema_column_0_register = program.alloc_register();
@@ -763,7 +810,7 @@ pub fn translate_drop_table(
         program.emit_insn(Insn::OpenWrite {
             cursor_id: sqlite_schema_cursor_id_1,
             root_page: 1usize.into(),
-            db: 0,
+            db: database_id,
         });
 
         //  Loop to copy over row id's from the ephemeral table and then re-insert into the schema table with the correct root page
@@ -826,16 +873,21 @@ pub fn translate_drop_table(
         //  End loop to copy over row id's from the ephemeral table and then re-insert into the schema table with the correct root page
     }
 
+    // Drop the triggers attached to the table
+    for trigger in schema.get_triggers_for_table(tbl_name.name.as_str()) {
+        emit_drop_trigger(&mut program, schema, database_id, &trigger.name);
+    }
+
     //  Drop the in-memory structures for the table
     program.emit_insn(Insn::DropTable {
-        db: 0,
+        db: database_id,
         _p2: 0,
         _p3: 0,
         table_name: tbl_name.name.as_str().to_string(),
     });
 
     program.emit_insn(Insn::SetCookie {
-        db: 0,
+        db: database_id,
         cookie: Cookie::SchemaVersion,
         value: schema.schema_version as i32 + 1,
         p5: 0,
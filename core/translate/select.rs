@@ -10,7 +10,9 @@ use crate::translate::planner::{
     bind_column_references, break_predicate_at_and_boundaries, parse_from, parse_limit,
     parse_where, resolve_aggregates,
 };
-use crate::util::normalize_ident;
+use crate::translate::subquery::plan_subqueries_from_select_plan;
+use crate::translate::window::{plan_windows, resolve_window_names};
+use crate::util::{exprs_are_equivalent, normalize_ident};
 use crate::vdbe::builder::{ProgramBuilderOpts, TableRefIdCounter};
 use crate::vdbe::insn::Insn;
 use crate::{schema::Schema, vdbe::builder::ProgramBuilder, Result};
@@ -41,6 +43,40 @@ pub fn translate_select(
         query_destination,
         connection,
     )?;
+    let mut param_idx = 1;
+    match &mut select_plan {
+        Plan::Select(plan) => plan_subqueries_from_select_plan(
+            &mut program,
+            plan,
+            schema,
+            syms,
+            connection,
+            &mut param_idx,
+        )?,
+        Plan::CompoundSelect {
+            left, right_most, ..
+        } => {
+            for (plan, _) in left.iter_mut() {
+                plan_subqueries_from_select_plan(
+                    &mut program,
+                    plan,
+                    schema,
+                    syms,
+                    connection,
+                    &mut param_idx,
+                )?;
+            }
+            plan_subqueries_from_select_plan(
+                &mut program,
+                right_most,
+                schema,
+                syms,
+                connection,
+                &mut param_idx,
+            )?;
+        }
+        other => panic!("plan is not a SelectPlan: {other:?}"),
+    }
     optimize_plan(&mut select_plan, schema)?;
     let num_result_cols;
     let opts = match &select_plan {
@@ -80,7 +116,7 @@ pub fn translate_select(
     };
 
     program.extend(&opts);
-    emit_program(&mut program, select_plan, schema, syms, |_| {})?;
+    emit_program(&mut program, select_plan, schema, syms, connection, |_| {})?;
     Ok(TranslateSelectResult {
         program,
         num_result_cols,
@@ -111,12 +147,13 @@ pub fn prepare_select_plan(
             connection,
         )?)),
         false => {
+            // The CTEs of a compound SELECT are visible to all of its SELECTs
             let mut last = prepare_one_select_plan(
                 schema,
                 select.body.select,
                 None,
                 vec![],
-                None,
+                select.with.clone(),
                 syms,
                 outer_query_refs,
                 table_ref_counter,
@@ -124,6 +161,7 @@ pub fn prepare_select_plan(
                 connection,
             )?;
 
+            let select_with = select.with;
             let mut left = Vec::with_capacity(compounds.len());
             for CompoundSelect { select, operator } in compounds {
                 left.push((last, operator));
@@ -132,7 +170,7 @@ pub fn prepare_select_plan(
                     select,
                     None,
                     vec![],
-                    None,
+                    select_with.clone(),
                     syms,
                     outer_query_refs,
                     table_ref_counter,
@@ -152,27 +190,31 @@ pub fn prepare_select_plan(
                 .limit
                 .map_or(Ok((None, None)), |mut l| parse_limit(&mut l, connection))?;
 
-            // FIXME: handle ORDER BY for compound selects
-            if !select.order_by.is_empty() {
-                crate::bail_parse_error!("ORDER BY is not supported for compound SELECTs yet");
-            }
-            // FIXME: handle WITH for compound selects
-            if select.with.is_some() {
-                crate::bail_parse_error!("WITH is not supported for compound SELECTs yet");
-            }
+            let order_by = if select.order_by.is_empty() {
+                None
+            } else {
+                let mut key = Vec::with_capacity(select.order_by.len());
+                for (i, o) in select.order_by.into_iter().enumerate() {
+                    let expr = resolve_compound_order_by_term(
+                        i, *o.expr, &mut left, &mut last, connection,
+                    )?;
+                    key.push((expr, o.order.unwrap_or(ast::SortOrder::Asc)));
+                }
+                Some(key)
+            };
             Ok(Plan::CompoundSelect {
                 left,
                 right_most: last,
                 limit,
                 offset,
-                order_by: None,
+                order_by,
             })
         }
     }
 }
 
 #[allow(clippy::too_many_arguments)]
-fn prepare_one_select_plan(
+pub(crate) fn prepare_one_select_plan(
     schema: &Schema,
     select: ast::OneSelect,
     limit: Option<ast::Limit>,
@@ -199,9 +241,6 @@ fn prepare_one_select_plan(
                     "SELECT with DISTINCT is not allowed without indexes enabled"
                 );
             }
-            if !window_clause.is_empty() {
-                crate::bail_parse_error!("WINDOW clause is not supported yet");
-            }
             let col_count = columns.len();
             if col_count == 0 {
                 crate::bail_parse_error!("SELECT without columns is not allowed");
@@ -280,6 +319,7 @@ fn prepare_one_select_plan(
                 query_destination,
                 distinctness: Distinctness::from_ast(distinctness.as_ref()),
                 values: vec![],
+                window: None,
             };
 
             let mut aggregate_expressions = Vec::new();
@@ -332,6 +372,7 @@ fn prepare_one_select_plan(
                         }
                     }
                     ResultColumn::Expr(ref mut expr, maybe_alias) => {
+                        resolve_window_names(expr, &window_clause)?;
                         bind_column_references(
                             expr,
                             &mut plan.table_references,
@@ -416,6 +457,7 @@ fn prepare_one_select_plan(
 
             for mut o in order_by {
                 replace_column_number_with_copy_of_column_expr(&mut o.expr, &plan.result_columns)?;
+                resolve_window_names(&mut o.expr, &window_clause)?;
 
                 bind_column_references(
                     &mut o.expr,
@@ -428,6 +470,7 @@ fn prepare_one_select_plan(
                 key.push((o.expr, o.order.unwrap_or(ast::SortOrder::Asc)));
             }
             plan.order_by = key;
+            let mut plan = plan_windows(plan, schema, syms, table_ref_counter)?;
 
             // Parse the LIMIT/OFFSET clause
             (plan.limit, plan.offset) =
@@ -463,6 +506,7 @@ fn prepare_one_select_plan(
                     .iter()
                     .map(|values| values.iter().map(|value| *value.clone()).collect())
                     .collect(),
+                window: None,
             };
 
             Ok(plan)
@@ -496,6 +540,81 @@ fn add_vtab_predicates_to_where_clause(
 /// Replaces a column number in an ORDER BY or GROUP BY expression with a copy of the column expression.
 /// For example, in SELECT u.first_name, count(1) FROM users u GROUP BY 1 ORDER BY 2,
 /// the column number 1 is replaced with u.first_name and the column number 2 is replaced with count(1).
+/// Resolves a term of the ORDER BY clause of a compound SELECT to the result column it sorts by.
+/// A term is either the number of a result column, the name of a result column of the left-most
+/// SELECT, or an expression identical to a result column of one of the SELECTs.
+/// The term is returned as the number of the result column, keeping its COLLATE clause if any.
+fn resolve_compound_order_by_term(
+    term_idx: usize,
+    term: ast::Expr,
+    left: &mut [(SelectPlan, ast::CompoundOperator)],
+    right_most: &mut SelectPlan,
+    connection: &Arc<crate::Connection>,
+) -> Result<ast::Expr> {
+    if let ast::Expr::Collate(inner, collation) = term {
+        let inner = resolve_compound_order_by_term(term_idx, *inner, left, right_most, connection)?;
+        return Ok(ast::Expr::Collate(Box::new(inner), collation));
+    }
+    let num_columns = right_most.result_columns.len();
+    let column_number =
+        |column_idx: usize| ast::Expr::Literal(ast::Literal::Numeric((column_idx + 1).to_string()));
+    if let ast::Expr::Literal(ast::Literal::Numeric(num)) = &term {
+        return match num.parse::<usize>() {
+            Ok(n) if (1..=num_columns).contains(&n) => Ok(column_number(n - 1)),
+            _ => crate::bail_parse_error!(
+                "{} ORDER BY term out of range - should be between 1 and {}",
+                ordinal(term_idx + 1),
+                num_columns
+            ),
+        };
+    }
+    if let ast::Expr::Id(name) | ast::Expr::Name(name) = &term {
+        let name = normalize_ident(name.as_str());
+        let left_most = left.first().map(|(plan, _)| plan).unwrap_or(&*right_most);
+        let found = left_most.result_columns.iter().position(|rc| {
+            rc.name(&left_most.table_references)
+                .is_some_and(|n| normalize_ident(n) == name)
+        });
+        if let Some(column_idx) = found {
+            return Ok(column_number(column_idx));
+        }
+    }
+    for plan in left
+        .iter_mut()
+        .map(|(plan, _)| plan)
+        .chain(std::iter::once(right_most))
+    {
+        let mut expr = term.clone();
+        if bind_column_references(&mut expr, &mut plan.table_references, None, connection).is_err()
+        {
+            continue;
+        }
+        if let Some(column_idx) = plan
+            .result_columns
+            .iter()
+            .position(|rc| exprs_are_equivalent(&rc.expr, &expr))
+        {
+            return Ok(column_number(column_idx));
+        }
+    }
+    crate::bail_parse_error!(
+        "{} ORDER BY term does not match any column in the result set",
+        ordinal(term_idx + 1)
+    )
+}
+
+/// Formats a number as an English ordinal, e.g. "1st" or "12th".
+fn ordinal(n: usize) -> String {
+    let suffix = match (n % 10, n % 100) {
+        (_, 11..=13) => "th",
+        (1, _) => "st",
+        (2, _) => "nd",
+        (3, _) => "rd",
+        _ => "th",
+    };
+    format!("{n}{suffix}")
+}
+
 fn replace_column_number_with_copy_of_column_expr(
     order_by_or_group_by_expr: &mut ast::Expr,
     columns: &[ResultSetColumn],
@@ -1,14 +1,34 @@
+use std::{collections::HashMap, sync::Arc};
+
+use turso_parser::ast::{self, Expr, SortOrder, SubqueryType, TableInternalId};
+
 use crate::{
     emit_explain,
-    schema::Table,
-    vdbe::{builder::ProgramBuilder, insn::Insn},
-    QueryMode, Result,
+    parameters::PARAM_PREFIX,
+    schema::{FromClauseSubquery, Index, IndexColumn, Schema, SubqueryKind, Table},
+    util::exprs_are_equivalent,
+    vdbe::{
+        builder::{CursorType, ProgramBuilder},
+        insn::{InsertFlags, Insn},
+        BranchOffset, CursorID,
+    },
+    Connection, LimboError, QueryMode, Result, SymbolTable,
 };
 
 use super::{
+    compound_select::emit_compound_select_rows,
     emitter::{emit_query, Resolver, TranslateCtx},
+    expr::{
+        compare_affinity, get_expr_affinity, translate_expr, walk_expr, walk_expr_mut, WalkControl,
+    },
+    insert::ephemeral_scratch_table,
     main_loop::LoopLabels,
-    plan::{Operation, QueryDestination, Search, SelectPlan, TableReferences},
+    optimizer::{optimize_plan, semi_join::rewrite_exists_as_semi_join},
+    plan::{
+        ColumnUsedMask, NonFromClauseSubquery, Operation, OuterQueryReference, Plan,
+        QueryDestination, Search, SelectPlan, TableReferences, WhereTerm,
+    },
+    select::prepare_select_plan,
 };
 
 /// Emit the subqueries contained in the FROM clause.
@@ -22,6 +42,7 @@ pub fn emit_subqueries(
         emit_explain!(program, false, "SCAN CONSTANT ROW".to_owned());
     }
 
+    let mut materialized_tables = HashMap::new();
     for table_reference in tables.joined_tables_mut() {
         emit_explain!(
             program,
@@ -59,8 +80,23 @@ pub fn emit_subqueries(
 
         if let Table::FromClauseSubquery(from_clause_subquery) = &mut table_reference.table {
             // Emit the subquery and get the start register of the result columns.
-            let result_columns_start =
-                emit_subquery(program, &mut from_clause_subquery.plan, t_ctx)?;
+            let result_columns_start = match from_clause_subquery.kind {
+                SubqueryKind::Coroutine => {
+                    emit_subquery(program, &mut from_clause_subquery.plan, &t_ctx.resolver)?
+                }
+                SubqueryKind::Materialized => emit_materialized_subquery(
+                    program,
+                    from_clause_subquery,
+                    t_ctx,
+                    &mut materialized_tables,
+                )?,
+                SubqueryKind::Recursive(_) => {
+                    emit_recursive_cte(program, from_clause_subquery, t_ctx)?
+                }
+                SubqueryKind::RecursiveReference => {
+                    emit_recursive_reference(program, from_clause_subquery)
+                }
+            };
             // Set the start register of the subquery's result columns.
             // This is done so that translate_expr() can read the result columns of the subquery,
             // as if it were reading from a regular table.
@@ -88,7 +124,7 @@ pub fn emit_subqueries(
 pub fn emit_subquery(
     program: &mut ProgramBuilder,
     plan: &mut SelectPlan,
-    t_ctx: &mut TranslateCtx,
+    resolver: &Resolver,
 ) -> Result<usize> {
     let yield_reg = program.alloc_register();
     let coroutine_implementation_start_offset = program.allocate_label();
@@ -112,14 +148,16 @@ pub fn emit_subquery(
         label_main_loop_end: None,
         meta_group_by: None,
         meta_left_joins: (0..plan.joined_tables().len()).map(|_| None).collect(),
+        meta_right_joins: (0..plan.joined_tables().len()).map(|_| None).collect(),
         meta_sort: None,
+        meta_window: None,
         reg_agg_start: None,
         reg_nonagg_emit_once_flag: None,
         reg_result_cols_start: None,
         limit_ctx: None,
         reg_offset: None,
         reg_limit_offset_sum: None,
-        resolver: Resolver::new(t_ctx.resolver.schema, t_ctx.resolver.symbol_table),
+        resolver: Resolver::new(resolver.schema, resolver.symbol_table),
         non_aggregate_expressions: Vec::new(),
         cdc_cursor_id: None,
     };
@@ -136,3 +174,1196 @@ pub fn emit_subquery(
     program.preassign_label_to_next_insn(subquery_body_end_label);
     Ok(result_column_start_reg)
 }
+
+/// Emit a compound SELECT as a coroutine, see [emit_subquery]. Returns the yield register of the
+/// coroutine and the start register of the result columns, which directly follow it.
+fn emit_compound_subquery(
+    program: &mut ProgramBuilder,
+    mut plan: Plan,
+    resolver: &Resolver,
+) -> Result<(usize, usize)> {
+    let num_columns = plan.select_plans()[0].result_columns.len();
+    let yield_reg = program.alloc_register();
+    let result_columns_start = program.alloc_registers(num_columns);
+    let coroutine_implementation_start = program.allocate_label();
+    for select_plan in plan.select_plans_mut() {
+        select_plan.query_destination = QueryDestination::CoroutineYield {
+            yield_reg,
+            coroutine_implementation_start,
+        };
+    }
+    let subquery_body_end_label = program.allocate_label();
+    program.emit_insn(Insn::InitCoroutine {
+        yield_reg,
+        jump_on_definition: subquery_body_end_label,
+        start_offset: coroutine_implementation_start,
+    });
+    program.preassign_label_to_next_insn(coroutine_implementation_start);
+    emit_compound_select_rows(
+        program,
+        plan,
+        resolver.schema,
+        resolver.symbol_table,
+        Some(result_columns_start),
+    )?;
+    program.emit_insn(Insn::EndCoroutine { yield_reg });
+    program.preassign_label_to_next_insn(subquery_body_end_label);
+    Ok((yield_reg, result_columns_start))
+}
+
+/// Emit the subquery that an [ast::Expr::SubqueryResult] refers to, so that its result can be
+/// read as described by its [SubqueryType]. An uncorrelated subquery only runs the first time
+/// this code is reached, whereas a correlated one runs every time, as the rows of the enclosing
+/// query that it refers to may have changed.
+pub fn emit_non_from_clause_subquery(
+    program: &mut ProgramBuilder,
+    subquery_id: TableInternalId,
+    referenced_tables: Option<&TableReferences>,
+    resolver: &Resolver,
+) -> Result<()> {
+    let Some(subquery) = program.non_from_clause_subquery(subquery_id) else {
+        return Err(LimboError::InternalError(format!(
+            "subquery {subquery_id} was not planned"
+        )));
+    };
+    let NonFromClauseSubquery {
+        mut plan,
+        query_type,
+        correlated,
+        ..
+    } = subquery.clone();
+
+    let label_done = program.allocate_label();
+    if !correlated {
+        program.emit_insn(Insn::Once {
+            target_pc_when_reentered: label_done,
+        });
+    }
+    // The tables of the enclosing query were copied when the subquery was planned; the copies
+    // of derived tables do not know yet where the rows of the derived table are read from.
+    if let Some(referenced_tables) = referenced_tables {
+        for select_plan in plan.select_plans_mut() {
+            for outer_ref in select_plan.table_references.outer_query_refs_mut() {
+                if let Some(table) =
+                    referenced_tables.find_table_by_internal_id(outer_ref.internal_id)
+                {
+                    outer_ref.table = table.clone();
+                }
+            }
+        }
+    }
+
+    emit_explain!(
+        program,
+        true,
+        format!(
+            "{}{} SUBQUERY {}",
+            if correlated { "CORRELATED " } else { "" },
+            if matches!(query_type, SubqueryType::In { .. }) {
+                "LIST"
+            } else {
+                "SCALAR"
+            },
+            subquery_id
+        )
+    );
+    let (yield_reg, result_columns_start) = match plan {
+        Plan::Select(mut plan) => {
+            let result_columns_start = emit_subquery(program, &mut plan, resolver)?;
+            let QueryDestination::CoroutineYield { yield_reg, .. } = plan.query_destination else {
+                unreachable!("emit_subquery always yields to a coroutine");
+            };
+            (yield_reg, result_columns_start)
+        }
+        plan => emit_compound_subquery(program, plan, resolver)?,
+    };
+    program.pop_current_parent_explain();
+    match query_type {
+        SubqueryType::Exists { result_reg } => {
+            program.emit_insn(Insn::Integer {
+                value: 0,
+                dest: result_reg,
+            });
+            program.emit_insn(Insn::Yield {
+                yield_reg,
+                end_offset: label_done,
+            });
+            program.emit_insn(Insn::Integer {
+                value: 1,
+                dest: result_reg,
+            });
+        }
+        SubqueryType::RowValue {
+            result_reg_start,
+            num_regs,
+        } => {
+            program.emit_insn(Insn::Null {
+                dest: result_reg_start,
+                dest_end: Some(result_reg_start + num_regs - 1),
+            });
+            program.emit_insn(Insn::Yield {
+                yield_reg,
+                end_offset: label_done,
+            });
+            program.emit_insn(Insn::Copy {
+                src_reg: result_columns_start,
+                dst_reg: result_reg_start,
+                extra_amount: num_regs - 1,
+            });
+        }
+        SubqueryType::In {
+            cursor_id,
+            affinity,
+        } => {
+            program.emit_insn(Insn::OpenEphemeral {
+                cursor_id,
+                is_table: false,
+            });
+            let label_loop_start = program.allocate_label();
+            program.preassign_label_to_next_insn(label_loop_start);
+            program.emit_insn(Insn::Yield {
+                yield_reg,
+                end_offset: label_done,
+            });
+            let record_reg = program.alloc_register();
+            program.emit_insn(Insn::MakeRecord {
+                start_reg: result_columns_start,
+                count: 1,
+                dest_reg: record_reg,
+                index_name: None,
+                affinity_str: Some(affinity.to_string()),
+            });
+            program.emit_insn(Insn::IdxInsert {
+                cursor_id,
+                record_reg,
+                unpacked_start: Some(result_columns_start),
+                unpacked_count: Some(1),
+                flags: Default::default(),
+            });
+            program.emit_insn(Insn::Goto {
+                target_pc: label_loop_start,
+            });
+        }
+    }
+    program.preassign_label_to_next_insn(label_done);
+    Ok(())
+}
+
+/// Emit a coroutine that yields the rows of `subquery` from an ephemeral table. Returns the start
+/// register of the result columns.
+///
+/// All references to the same CTE share one ephemeral table, which is filled with the rows of
+/// the plan of the first reference that is emitted, once, before any of the coroutines run.
+/// Every reference then scans the table through a cursor of its own, so that e.g. a self-join
+/// of the CTE reads the same rows on both sides.
+fn emit_materialized_subquery(
+    program: &mut ProgramBuilder,
+    subquery: &mut FromClauseSubquery,
+    t_ctx: &mut TranslateCtx,
+    materialized_tables: &mut HashMap<String, CursorID>,
+) -> Result<usize> {
+    let num_columns = subquery.columns.len();
+    let table_cursor_id = match materialized_tables.get(&subquery.name) {
+        Some(table_cursor_id) => *table_cursor_id,
+        None => {
+            let table_cursor_id = program
+                .alloc_cursor_id(CursorType::BTreeTable(ephemeral_scratch_table(num_columns)));
+            let label_materialized = program.allocate_label();
+            program.emit_insn(Insn::Once {
+                target_pc_when_reentered: label_materialized,
+            });
+            emit_explain!(program, true, format!("MATERIALIZE {}", subquery.name));
+            program.emit_insn(Insn::OpenEphemeral {
+                cursor_id: table_cursor_id,
+                is_table: true,
+            });
+            emit_rows_into_table(program, &mut subquery.plan, t_ctx, table_cursor_id, None)?;
+            program.pop_current_parent_explain();
+            program.preassign_label_to_next_insn(label_materialized);
+            materialized_tables.insert(subquery.name.clone(), table_cursor_id);
+            table_cursor_id
+        }
+    };
+
+    let yield_reg = program.alloc_register();
+    let coroutine_implementation_start = program.allocate_label();
+    let subquery_body_end_label = program.allocate_label();
+    program.emit_insn(Insn::InitCoroutine {
+        yield_reg,
+        jump_on_definition: subquery_body_end_label,
+        start_offset: coroutine_implementation_start,
+    });
+    program.preassign_label_to_next_insn(coroutine_implementation_start);
+
+    let cursor_id =
+        program.alloc_cursor_id(CursorType::BTreeTable(ephemeral_scratch_table(num_columns)));
+    program.emit_insn(Insn::OpenDup {
+        new_cursor_id: cursor_id,
+        original_cursor_id: table_cursor_id,
+    });
+    let label_done = program.allocate_label();
+    let label_loop_start = program.allocate_label();
+    let result_columns_start = program.alloc_registers(num_columns);
+    program.emit_insn(Insn::Rewind {
+        cursor_id,
+        pc_if_empty: label_done,
+    });
+    program.preassign_label_to_next_insn(label_loop_start);
+    for i in 0..num_columns {
+        program.emit_insn(Insn::Column {
+            cursor_id,
+            column: i,
+            dest: result_columns_start + i,
+            default: None,
+        });
+    }
+    program.emit_insn(Insn::Yield {
+        yield_reg,
+        end_offset: BranchOffset::Offset(0),
+    });
+    program.emit_insn(Insn::Next {
+        cursor_id,
+        pc_if_next: label_loop_start,
+    });
+    program.preassign_label_to_next_insn(label_done);
+    program.emit_insn(Insn::EndCoroutine { yield_reg });
+    program.preassign_label_to_next_insn(subquery_body_end_label);
+
+    // The plan, if emitted at all, filled the table with a coroutine of its own; the parent
+    // query reads from the coroutine of this reference instead.
+    subquery.plan.query_destination = QueryDestination::CoroutineYield {
+        yield_reg,
+        coroutine_implementation_start,
+    };
+    Ok(result_columns_start)
+}
+
+/// Emit a coroutine that yields the rows of a recursive CTE, see [crate::schema::RecursiveCte].
+/// Returns the start register of the result columns, which hold the row taken from the queue
+/// both for the parent query and for the recursive selects.
+fn emit_recursive_cte(
+    program: &mut ProgramBuilder,
+    subquery: &mut FromClauseSubquery,
+    t_ctx: &mut TranslateCtx,
+) -> Result<usize> {
+    let FromClauseSubquery {
+        name,
+        plan,
+        columns,
+        kind,
+        ..
+    } = subquery;
+    let SubqueryKind::Recursive(cte) = kind else {
+        unreachable!("emit_recursive_cte called on non-recursive subquery");
+    };
+    let num_columns = columns.len();
+
+    let yield_reg = program.alloc_register();
+    let coroutine_implementation_start = program.allocate_label();
+    let subquery_body_end_label = program.allocate_label();
+    program.emit_insn(Insn::InitCoroutine {
+        yield_reg,
+        jump_on_definition: subquery_body_end_label,
+        start_offset: coroutine_implementation_start,
+    });
+    program.preassign_label_to_next_insn(coroutine_implementation_start);
+    let label_done = program.allocate_label();
+
+    let reg_limit = match &cte.limit {
+        Some(limit) => {
+            let reg = program.alloc_register();
+            translate_expr(program, None, limit, reg, &t_ctx.resolver)?;
+            program.emit_insn(Insn::MustBeInt { reg });
+            program.emit_insn(Insn::IfNot {
+                reg,
+                target_pc: label_done,
+                jump_if_null: true,
+            });
+            Some(reg)
+        }
+        None => None,
+    };
+    let reg_offset = match &cte.offset {
+        Some(offset) => {
+            let reg = program.alloc_register();
+            translate_expr(program, None, offset, reg, &t_ctx.resolver)?;
+            program.emit_insn(Insn::MustBeInt { reg });
+            Some(reg)
+        }
+        None => None,
+    };
+
+    let queue_cursor_id =
+        program.alloc_cursor_id(CursorType::BTreeTable(ephemeral_scratch_table(num_columns)));
+    program.emit_insn(Insn::OpenEphemeral {
+        cursor_id: queue_cursor_id,
+        is_table: true,
+    });
+    let distinct_cursor_id = if cte.distinct {
+        let index = Arc::new(Index {
+            columns: columns
+                .iter()
+                .enumerate()
+                .map(|(i, column)| IndexColumn {
+                    name: column.name.clone().unwrap_or_default(),
+                    order: SortOrder::Asc,
+                    pos_in_table: i,
+                    default: None,
+                    collation: None, // FIXME: this should be inferred
+                    expr: None,
+                })
+                .collect(),
+            name: format!("{name}_distinct"),
+            root_page: 0,
+            ephemeral: true,
+            table_name: String::new(),
+            unique: false,
+            has_rowid: false,
+            num_key_columns: None,
+            where_clause: None,
+        });
+        let cursor_id = program.alloc_cursor_id(CursorType::BTreeIndex(index));
+        program.emit_insn(Insn::OpenEphemeral {
+            cursor_id,
+            is_table: false,
+        });
+        Some(cursor_id)
+    } else {
+        None
+    };
+
+    emit_explain!(program, true, "SETUP".to_owned());
+    emit_rows_into_table(program, plan, t_ctx, queue_cursor_id, distinct_cursor_id)?;
+    program.pop_current_parent_explain();
+
+    // Take the first row from the queue and hand it to the parent query.
+    let label_loop_start = program.allocate_label();
+    let label_recursive_step = program.allocate_label();
+    let current_row_start = program.alloc_registers(num_columns);
+    program.preassign_label_to_next_insn(label_loop_start);
+    program.emit_insn(Insn::Rewind {
+        cursor_id: queue_cursor_id,
+        pc_if_empty: label_done,
+    });
+    for i in 0..num_columns {
+        program.emit_insn(Insn::Column {
+            cursor_id: queue_cursor_id,
+            column: i,
+            dest: current_row_start + i,
+            default: None,
+        });
+    }
+    program.emit_insn(Insn::Delete {
+        cursor_id: queue_cursor_id,
+        table_name: String::new(),
+    });
+    if let Some(reg) = reg_offset {
+        program.emit_insn(Insn::IfPos {
+            reg,
+            target_pc: label_recursive_step,
+            decrement_by: 1,
+        });
+    }
+    program.emit_insn(Insn::Yield {
+        yield_reg,
+        end_offset: BranchOffset::Offset(0),
+    });
+    if let Some(reg) = reg_limit {
+        program.emit_insn(Insn::DecrJumpZero {
+            reg,
+            target_pc: label_done,
+        });
+    }
+
+    // Queue the rows of the recursive selects, which read the row taken from the queue.
+    program.preassign_label_to_next_insn(label_recursive_step);
+    emit_explain!(program, true, "RECURSIVE STEP".to_owned());
+    for recursive_plan in cte.recursive_plans.iter_mut() {
+        for table in recursive_plan.table_references.joined_tables_mut() {
+            if let Table::FromClauseSubquery(reference) = &mut table.table {
+                if matches!(reference.kind, SubqueryKind::RecursiveReference) {
+                    reference.result_columns_start_reg = Some(current_row_start);
+                }
+            }
+        }
+        emit_rows_into_table(
+            program,
+            recursive_plan,
+            t_ctx,
+            queue_cursor_id,
+            distinct_cursor_id,
+        )?;
+    }
+    program.pop_current_parent_explain();
+    program.emit_insn(Insn::Goto {
+        target_pc: label_loop_start,
+    });
+
+    program.preassign_label_to_next_insn(label_done);
+    program.emit_insn(Insn::EndCoroutine { yield_reg });
+    program.preassign_label_to_next_insn(subquery_body_end_label);
+
+    plan.query_destination = QueryDestination::CoroutineYield {
+        yield_reg,
+        coroutine_implementation_start,
+    };
+    Ok(current_row_start)
+}
+
+/// Emit the coroutine of a reference to a recursive CTE from one of its recursive selects,
+/// which yields the row currently processed by the CTE once. Returns the start register of
+/// that row, which the CTE sets before emitting its recursive selects.
+fn emit_recursive_reference(
+    program: &mut ProgramBuilder,
+    subquery: &mut FromClauseSubquery,
+) -> usize {
+    let yield_reg = program.alloc_register();
+    let coroutine_implementation_start = program.allocate_label();
+    let subquery_body_end_label = program.allocate_label();
+    program.emit_insn(Insn::InitCoroutine {
+        yield_reg,
+        jump_on_definition: subquery_body_end_label,
+        start_offset: coroutine_implementation_start,
+    });
+    program.preassign_label_to_next_insn(coroutine_implementation_start);
+    program.emit_insn(Insn::Yield {
+        yield_reg,
+        end_offset: BranchOffset::Offset(0),
+    });
+    program.emit_insn(Insn::EndCoroutine { yield_reg });
+    program.preassign_label_to_next_insn(subquery_body_end_label);
+
+    subquery.plan.query_destination = QueryDestination::CoroutineYield {
+        yield_reg,
+        coroutine_implementation_start,
+    };
+    subquery
+        .result_columns_start_reg
+        .expect("recursive reference must be bound to the row of its CTE")
+}
+
+/// Run `plan` as a coroutine and append every row it yields to the ephemeral table
+/// `table_cursor_id`. If `distinct_cursor_id` is set, rows already present in that ephemeral
+/// index are skipped, and appended rows are added to it.
+fn emit_rows_into_table(
+    program: &mut ProgramBuilder,
+    plan: &mut SelectPlan,
+    t_ctx: &mut TranslateCtx,
+    table_cursor_id: CursorID,
+    distinct_cursor_id: Option<CursorID>,
+) -> Result<()> {
+    let num_columns = plan.result_columns.len();
+    let result_columns_start = emit_subquery(program, plan, &t_ctx.resolver)?;
+    let QueryDestination::CoroutineYield {
+        yield_reg,
+        coroutine_implementation_start,
+    } = plan.query_destination
+    else {
+        unreachable!("emit_subquery always yields to a coroutine");
+    };
+    // The coroutine may already have run, e.g. for the previous row of a recursive CTE.
+    program.emit_insn(Insn::InitCoroutine {
+        yield_reg,
+        jump_on_definition: BranchOffset::Offset(0),
+        start_offset: coroutine_implementation_start,
+    });
+    let label_loop_start = program.allocate_label();
+    let label_done = program.allocate_label();
+    program.preassign_label_to_next_insn(label_loop_start);
+    program.emit_insn(Insn::Yield {
+        yield_reg,
+        end_offset: label_done,
+    });
+    let record_reg = program.alloc_register();
+    program.emit_insn(Insn::MakeRecord {
+        start_reg: result_columns_start,
+        count: num_columns,
+        dest_reg: record_reg,
+        index_name: None,
+        affinity_str: None,
+    });
+    if let Some(distinct_cursor_id) = distinct_cursor_id {
+        program.emit_insn(Insn::Found {
+            cursor_id: distinct_cursor_id,
+            target_pc: label_loop_start,
+            record_reg,
+            num_regs: 0,
+        });
+        program.emit_insn(Insn::IdxInsert {
+            cursor_id: distinct_cursor_id,
+            record_reg,
+            unpacked_start: Some(result_columns_start),
+            unpacked_count: Some(num_columns as u16),
+            flags: Default::default(),
+        });
+    }
+    let rowid_reg = program.alloc_register();
+    program.emit_insn(Insn::NewRowid {
+        cursor: table_cursor_id,
+        rowid_reg,
+        prev_largest_reg: 0,
+    });
+    program.emit_insn(Insn::Insert {
+        cursor: table_cursor_id,
+        key_reg: rowid_reg,
+        record_reg,
+        flag: InsertFlags::new(),
+        table_name: String::new(),
+    });
+    program.emit_insn(Insn::Goto {
+        target_pc: label_loop_start,
+    });
+    program.preassign_label_to_next_insn(label_done);
+    Ok(())
+}
+
+/// Plan the subqueries that appear in the expressions of `plan`, i.e. `EXISTS (SELECT ...)`,
+/// `x IN (SELECT ...)` and scalar `(SELECT ...)` expressions, and replace each of them with an
+/// [ast::Expr::SubqueryResult] that refers to the planned subquery.
+///
+/// The anonymous parameters of the statement are numbered along the way, starting at `param_idx`,
+/// so that the parameters of a subquery get numbers in the order they appear in the statement.
+/// The numbering matches the one done by the optimizer, which is then a no-op.
+pub fn plan_subqueries_from_select_plan(
+    program: &mut ProgramBuilder,
+    plan: &mut SelectPlan,
+    schema: &Schema,
+    syms: &SymbolTable,
+    connection: &Arc<Connection>,
+    param_idx: &mut usize,
+) -> Result<()> {
+    // Aggregates keep their own copies of the expressions they were collected from,
+    // so the subqueries in those copies are replaced with the ones planned for the originals.
+    let mut planned = vec![];
+
+    for row in plan.values.iter_mut() {
+        for expr in row.iter_mut() {
+            plan_subqueries_in_expr(
+                program,
+                expr,
+                &mut plan.table_references,
+                schema,
+                syms,
+                connection,
+                param_idx,
+                &mut planned,
+            )?;
+        }
+    }
+    for result_column in plan.result_columns.iter_mut() {
+        if result_column.alias.is_none() && expr_contains_subquery(&result_column.expr) {
+            // The name of the column is the text of the expression, which is lost once
+            // the subquery is planned.
+            result_column.alias = Some(result_column.expr.to_string());
+        }
+        plan_subqueries_in_expr(
+            program,
+            &mut result_column.expr,
+            &mut plan.table_references,
+            schema,
+            syms,
+            connection,
+            param_idx,
+            &mut planned,
+        )?;
+    }
+    for joined_table in plan.table_references.joined_tables_mut() {
+        if let Table::FromClauseSubquery(from_clause_subquery) = &mut joined_table.table {
+            // The plan of a recursive reference is a copy of the plan of its CTE, and never runs.
+            if matches!(from_clause_subquery.kind, SubqueryKind::RecursiveReference) {
+                continue;
+            }
+            plan_subqueries_from_select_plan(
+                program,
+                &mut from_clause_subquery.plan,
+                schema,
+                syms,
+                connection,
+                param_idx,
+            )?;
+            if let SubqueryKind::Recursive(cte) = &mut from_clause_subquery.kind {
+                for recursive_plan in cte.recursive_plans.iter_mut() {
+                    plan_subqueries_from_select_plan(
+                        program,
+                        recursive_plan,
+                        schema,
+                        syms,
+                        connection,
+                        param_idx,
+                    )?;
+                }
+            }
+        }
+    }
+    for term in plan.where_clause.iter_mut() {
+        plan_subqueries_in_where_term(
+            program,
+            term,
+            &mut plan.table_references,
+            schema,
+            syms,
+            connection,
+            param_idx,
+            &mut planned,
+        )?;
+    }
+    if let Some(group_by) = &mut plan.group_by {
+        for expr in group_by.exprs.iter_mut() {
+            plan_subqueries_in_expr(
+                program,
+                expr,
+                &mut plan.table_references,
+                schema,
+                syms,
+                connection,
+                param_idx,
+                &mut planned,
+            )?;
+        }
+        for expr in group_by.having.iter_mut().flatten() {
+            plan_subqueries_in_expr(
+                program,
+                expr,
+                &mut plan.table_references,
+                schema,
+                syms,
+                connection,
+                param_idx,
+                &mut planned,
+            )?;
+        }
+    }
+    for (expr, _) in plan.order_by.iter_mut() {
+        plan_subqueries_in_expr(
+            program,
+            expr,
+            &mut plan.table_references,
+            schema,
+            syms,
+            connection,
+            param_idx,
+            &mut planned,
+        )?;
+    }
+    for aggregate in plan.aggregates.iter_mut() {
+        for expr in std::iter::once(&mut aggregate.original_expr).chain(aggregate.inputs_mut()) {
+            walk_expr_mut(expr, &mut |expr: &mut Expr| -> Result<()> {
+                if let Some((_, subquery_result)) =
+                    planned.iter().find(|(original, _)| *original == *expr)
+                {
+                    *expr = subquery_result.clone();
+                }
+                Ok(())
+            })?;
+        }
+    }
+
+    check_correlated_subqueries_in_aggregation(plan)
+}
+
+/// Plan the subqueries of the WHERE clause of a DELETE or UPDATE; see [plan_subqueries_from_select_plan].
+pub fn plan_subqueries_from_where_clause(
+    program: &mut ProgramBuilder,
+    where_clause: &mut [WhereTerm],
+    table_references: &mut TableReferences,
+    schema: &Schema,
+    syms: &SymbolTable,
+    connection: &Arc<Connection>,
+    param_idx: &mut usize,
+) -> Result<()> {
+    for term in where_clause.iter_mut() {
+        plan_subqueries_in_where_term(
+            program,
+            term,
+            table_references,
+            schema,
+            syms,
+            connection,
+            param_idx,
+            &mut vec![],
+        )?;
+    }
+    Ok(())
+}
+
+/// Plan the subqueries of a single expression whose columns refer to `table_references`;
+/// see [plan_subqueries_from_select_plan].
+pub fn plan_subqueries_from_expr(
+    program: &mut ProgramBuilder,
+    expr: &mut Expr,
+    table_references: &mut TableReferences,
+    schema: &Schema,
+    syms: &SymbolTable,
+    connection: &Arc<Connection>,
+    param_idx: &mut usize,
+) -> Result<()> {
+    plan_subqueries_in_expr(
+        program,
+        expr,
+        table_references,
+        schema,
+        syms,
+        connection,
+        param_idx,
+        &mut vec![],
+    )
+}
+
+/// Number the anonymous parameters of an expression in which subqueries are not supported,
+/// e.g. a RETURNING clause, so that the numbering continues with the rest of the statement.
+pub fn number_anonymous_params(expr: &mut Expr, param_idx: &mut usize) -> Result<()> {
+    walk_expr_mut(expr, &mut |expr: &mut Expr| -> Result<()> {
+        if let Expr::Variable(var) = expr {
+            if var.is_empty() {
+                *var = format!("{PARAM_PREFIX}{param_idx}");
+                *param_idx += 1;
+            }
+        }
+        Ok(())
+    })
+}
+
+/// Whether the expression contains a subquery that has not been planned yet.
+pub fn expr_contains_subquery(expr: &Expr) -> bool {
+    let mut contains_subquery = false;
+    let _ = walk_expr(expr, &mut |expr: &Expr| -> Result<WalkControl> {
+        if matches!(
+            expr,
+            Expr::Exists(_) | Expr::Subquery(_) | Expr::InSelect { .. }
+        ) {
+            contains_subquery = true;
+        }
+        Ok(WalkControl::Continue)
+    });
+    contains_subquery
+}
+
+/// `planned` collects the planned subqueries, as pairs of the original expression and
+/// the [ast::Expr::SubqueryResult] that replaced it.
+#[allow(clippy::too_many_arguments)]
+fn plan_subqueries_in_expr(
+    program: &mut ProgramBuilder,
+    expr: &mut Expr,
+    table_references: &mut TableReferences,
+    schema: &Schema,
+    syms: &SymbolTable,
+    connection: &Arc<Connection>,
+    param_idx: &mut usize,
+    planned: &mut Vec<(Expr, Expr)>,
+) -> Result<()> {
+    walk_expr_mut(expr, &mut |expr: &mut Expr| -> Result<()> {
+        match expr {
+            Expr::Variable(var) if var.is_empty() => {
+                *var = format!("{PARAM_PREFIX}{param_idx}");
+                *param_idx += 1;
+            }
+            Expr::Exists(_) | Expr::Subquery(_) | Expr::InSelect { .. } => {
+                let original = expr.clone();
+                // The left hand side of IN comes before the subquery in the statement.
+                if let Expr::InSelect { lhs, .. } = expr {
+                    plan_subqueries_in_expr(
+                        program,
+                        lhs,
+                        table_references,
+                        schema,
+                        syms,
+                        connection,
+                        param_idx,
+                        planned,
+                    )?;
+                }
+                let subquery = std::mem::replace(expr, Expr::Literal(ast::Literal::Null));
+                *expr = plan_subquery(
+                    program,
+                    subquery,
+                    table_references,
+                    schema,
+                    syms,
+                    connection,
+                    param_idx,
+                )?;
+                planned.push((original, expr.clone()));
+            }
+            _ => {}
+        }
+        Ok(())
+    })
+}
+
+/// Plan the subqueries of a term of a WHERE clause, see [plan_subqueries_in_expr]. A term that is
+/// a correlated `EXISTS` or `NOT EXISTS` subquery is evaluated as a semi-join or an anti-join
+/// when that is cheaper, see [rewrite_exists_as_semi_join].
+#[allow(clippy::too_many_arguments)]
+fn plan_subqueries_in_where_term(
+    program: &mut ProgramBuilder,
+    term: &mut WhereTerm,
+    table_references: &mut TableReferences,
+    schema: &Schema,
+    syms: &SymbolTable,
+    connection: &Arc<Connection>,
+    param_idx: &mut usize,
+    planned: &mut Vec<(Expr, Expr)>,
+) -> Result<()> {
+    let exists = match &term.expr {
+        _ if term.from_outer_join.is_some() => None,
+        Expr::Exists(select) => Some((select, false)),
+        Expr::Unary(ast::UnaryOperator::Not, expr) => match expr.as_ref() {
+            Expr::Exists(select) => Some((select, true)),
+            _ => None,
+        },
+        _ => None,
+    };
+    let Some((select, anti)) = exists else {
+        return plan_subqueries_in_expr(
+            program,
+            &mut term.expr,
+            table_references,
+            schema,
+            syms,
+            connection,
+            param_idx,
+            planned,
+        );
+    };
+    let original = Expr::Exists(select.clone());
+    let mut plan = prepare_subquery_plan(
+        program,
+        select.clone(),
+        table_references,
+        schema,
+        syms,
+        connection,
+    )?;
+    let semi_join_lhs = match &mut plan {
+        Plan::Select(plan) => rewrite_exists_as_semi_join(plan, table_references, schema),
+        _ => None,
+    };
+    let Some(lhs) = semi_join_lhs else {
+        let result = register_subquery(
+            program,
+            plan,
+            None,
+            false,
+            true,
+            table_references,
+            schema,
+            syms,
+            connection,
+            param_idx,
+        )?;
+        planned.push((original, result.clone()));
+        term.expr = if anti {
+            Expr::Unary(ast::UnaryOperator::Not, Box::new(result))
+        } else {
+            result
+        };
+        return Ok(());
+    };
+    let result = register_subquery(
+        program,
+        plan,
+        Some(Box::new(lhs.clone())),
+        anti,
+        false,
+        table_references,
+        schema,
+        syms,
+        connection,
+        param_idx,
+    )?;
+    term.expr = if anti {
+        Expr::Binary(
+            Box::new(Expr::IsNull(Box::new(lhs))),
+            ast::Operator::Or,
+            Box::new(result),
+        )
+    } else {
+        result
+    };
+    Ok(())
+}
+
+/// Plan a single `EXISTS (SELECT ...)`, `x IN (SELECT ...)` or `(SELECT ...)` expression,
+/// register the plan in the program, and return the [ast::Expr::SubqueryResult] that replaces it.
+///
+/// The subquery may refer to the tables of the enclosing query, which makes it correlated.
+/// The columns it uses are marked as used in `table_references`, so that the enclosing query
+/// keeps them available, e.g. does not choose an index that does not cover them.
+fn plan_subquery(
+    program: &mut ProgramBuilder,
+    subquery: Expr,
+    table_references: &mut TableReferences,
+    schema: &Schema,
+    syms: &SymbolTable,
+    connection: &Arc<Connection>,
+    param_idx: &mut usize,
+) -> Result<Expr> {
+    let (select, lhs, not_in, is_exists) = match subquery {
+        Expr::Exists(select) => (select, None, false, true),
+        Expr::Subquery(select) => (select, None, false, false),
+        Expr::InSelect { lhs, not, rhs } => (rhs, Some(lhs), not, false),
+        _ => unreachable!("plan_subquery called on an expression that is not a subquery"),
+    };
+    let plan = prepare_subquery_plan(program, select, table_references, schema, syms, connection)?;
+    register_subquery(
+        program,
+        plan,
+        lhs,
+        not_in,
+        is_exists,
+        table_references,
+        schema,
+        syms,
+        connection,
+        param_idx,
+    )
+}
+
+/// Prepare the plan of a subquery whose enclosing query reads `table_references`.
+fn prepare_subquery_plan(
+    program: &mut ProgramBuilder,
+    select: ast::Select,
+    table_references: &TableReferences,
+    schema: &Schema,
+    syms: &SymbolTable,
+    connection: &Arc<Connection>,
+) -> Result<Plan> {
+    let outer_query_refs = table_references
+        .joined_tables()
+        .iter()
+        .map(|t| OuterQueryReference {
+            identifier: t.identifier.clone(),
+            internal_id: t.internal_id,
+            table: t.table.clone(),
+            col_used_mask: ColumnUsedMask::default(),
+        })
+        .chain(
+            table_references
+                .outer_query_refs()
+                .iter()
+                .map(|t| OuterQueryReference {
+                    col_used_mask: ColumnUsedMask::default(),
+                    ..t.clone()
+                }),
+        )
+        .collect::<Vec<_>>();
+    prepare_select_plan(
+        schema,
+        select,
+        syms,
+        &outer_query_refs,
+        &mut program.table_reference_counter,
+        QueryDestination::CoroutineYield {
+            yield_reg: usize::MAX, // will be set later in bytecode emission
+            coroutine_implementation_start: BranchOffset::Placeholder, // will be set later in bytecode emission
+        },
+        connection,
+    )
+}
+
+/// Plan the subqueries nested in the prepared `plan` of a subquery, optimize it, register it in
+/// the program and return the [ast::Expr::SubqueryResult] that refers to it.
+#[allow(clippy::too_many_arguments)]
+fn register_subquery(
+    program: &mut ProgramBuilder,
+    mut plan: Plan,
+    lhs: Option<Box<Expr>>,
+    not_in: bool,
+    is_exists: bool,
+    table_references: &mut TableReferences,
+    schema: &Schema,
+    syms: &SymbolTable,
+    connection: &Arc<Connection>,
+    param_idx: &mut usize,
+) -> Result<Expr> {
+    // The SELECTs of a compound SELECT all have the same number of result columns, and the
+    // left-most one determines the affinity of the result.
+    let num_result_columns = plan.select_plans()[0].result_columns.len();
+    if !is_exists && num_result_columns != 1 {
+        crate::bail_parse_error!("sub-select returns {num_result_columns} columns - expected 1");
+    }
+    if matches!(lhs.as_deref(), Some(Expr::Parenthesized(exprs)) if exprs.len() > 1) {
+        crate::bail_parse_error!("row values are not supported on the left hand side of IN");
+    }
+    for select_plan in plan.select_plans_mut() {
+        plan_subqueries_from_select_plan(
+            program,
+            select_plan,
+            schema,
+            syms,
+            connection,
+            param_idx,
+        )?;
+    }
+    optimize_plan(&mut plan, schema)?;
+
+    let mut correlated_tables = vec![];
+    for select_plan in plan.select_plans() {
+        for table in referenced_outer_tables(select_plan) {
+            if !correlated_tables.contains(&table) {
+                correlated_tables.push(table);
+            }
+        }
+        for outer_ref in select_plan.table_references.outer_query_refs() {
+            for column in 0..outer_ref.columns().len().min(128) {
+                if outer_ref.col_used_mask.get(column) {
+                    table_references.mark_column_used(outer_ref.internal_id, column);
+                }
+            }
+        }
+    }
+
+    let query_type = match &lhs {
+        None if is_exists => SubqueryType::Exists {
+            result_reg: program.alloc_register(),
+        },
+        None => SubqueryType::RowValue {
+            result_reg_start: program.alloc_register(),
+            num_regs: 1,
+        },
+        Some(lhs) => {
+            let left_most = plan.select_plans()[0];
+            let affinity = compare_affinity(
+                lhs,
+                get_expr_affinity(
+                    &left_most.result_columns[0].expr,
+                    Some(&left_most.table_references),
+                ),
+                Some(&*table_references),
+            );
+            let index = Arc::new(Index {
+                columns: vec![IndexColumn {
+                    name: String::new(),
+                    order: SortOrder::Asc,
+                    pos_in_table: 0,
+                    default: None,
+                    collation: None,
+                    expr: None,
+                }],
+                name: "in_subquery".to_string(),
+                root_page: 0,
+                ephemeral: true,
+                table_name: String::new(),
+                unique: false,
+                has_rowid: false,
+                num_key_columns: None,
+                where_clause: None,
+            });
+            SubqueryType::In {
+                cursor_id: program.alloc_cursor_id(CursorType::BTreeIndex(index)),
+                affinity: affinity.aff_mask(),
+            }
+        }
+    };
+
+    let subquery_id = program.table_reference_counter.next();
+    program.add_non_from_clause_subquery(NonFromClauseSubquery {
+        internal_id: subquery_id,
+        plan,
+        query_type: query_type.clone(),
+        correlated: !correlated_tables.is_empty(),
+    });
+    Ok(Expr::SubqueryResult {
+        subquery_id,
+        lhs,
+        not_in,
+        query_type,
+        correlated_tables,
+    })
+}
+
+/// Returns the tables of enclosing queries that the expressions of `plan` refer to,
+/// including through the subqueries nested in it.
+fn referenced_outer_tables(plan: &SelectPlan) -> Vec<TableInternalId> {
+    let mut tables = vec![];
+    let mut visit = |expr: &Expr| {
+        let _ = walk_expr(expr, &mut |expr: &Expr| -> Result<WalkControl> {
+            let referenced = match expr {
+                Expr::Column { table, .. } | Expr::RowId { table, .. } => vec![*table],
+                Expr::SubqueryResult {
+                    correlated_tables, ..
+                } => correlated_tables.clone(),
+                _ => vec![],
+            };
+            for table in referenced {
+                if plan
+                    .table_references
+                    .find_outer_query_ref_by_internal_id(table)
+                    .is_some()
+                    && !tables.contains(&table)
+                {
+                    tables.push(table);
+                }
+            }
+            Ok(WalkControl::Continue)
+        });
+    };
+    plan.result_columns.iter().for_each(|rc| visit(&rc.expr));
+    plan.where_clause.iter().for_each(|term| visit(&term.expr));
+    if let Some(group_by) = &plan.group_by {
+        group_by.exprs.iter().for_each(&mut visit);
+        group_by.having.iter().flatten().for_each(&mut visit);
+    }
+    plan.order_by
+        .iter()
+        .for_each(|(expr, _)| visit(expr.as_ref()));
+    plan.aggregates
+        .iter()
+        .for_each(|agg| agg.inputs().for_each(&mut visit));
+    plan.limit
+        .iter()
+        .chain(plan.offset.iter())
+        .for_each(|expr| visit(expr.as_ref()));
+    tables
+}
+
+/// With aggregation or window functions, the result columns, HAVING and ORDER BY are evaluated
+/// after the rows of the joined tables have been scanned, so a correlated subquery can only
+/// refer to those tables from within the arguments of an aggregate or a GROUP BY expression.
+fn check_correlated_subqueries_in_aggregation(plan: &SelectPlan) -> Result<()> {
+    if plan.aggregates.is_empty() && plan.group_by.is_none() && plan.window.is_none() {
+        return Ok(());
+    }
+    let group_by_exprs = plan.group_by.as_ref().map_or(&[][..], |g| &g.exprs[..]);
+    let having = plan
+        .group_by
+        .as_ref()
+        .and_then(|g| g.having.as_deref())
+        .unwrap_or_default();
+    for expr in plan
+        .result_columns
+        .iter()
+        .map(|rc| &rc.expr)
+        .chain(having.iter())
+        .chain(plan.order_by.iter().map(|(expr, _)| expr.as_ref()))
+    {
+        walk_expr(expr, &mut |expr: &Expr| -> Result<WalkControl> {
+            if plan
+                .aggregates
+                .iter()
+                .any(|agg| exprs_are_equivalent(&agg.original_expr, expr))
+                || group_by_exprs
+                    .iter()
+                    .any(|group_by_expr| exprs_are_equivalent(group_by_expr, expr))
+            {
+                return Ok(WalkControl::SkipChildren);
+            }
+            if let Expr::SubqueryResult {
+                correlated_tables, ..
+            } = expr
+            {
+                if correlated_tables.iter().any(|table| {
+                    plan.table_references
+                        .find_joined_table_by_internal_id(*table)
+                        .is_some()
+                }) {
+                    crate::bail_parse_error!(
+                        "correlated subqueries outside of aggregate functions are not supported in aggregate queries yet"
+                    );
+                }
+            }
+            Ok(WalkControl::Continue)
+        })?;
+    }
+    Ok(())
+}
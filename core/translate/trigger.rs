@@ -0,0 +1,1057 @@
+use std::sync::Arc;
+
+use turso_parser::ast::{self, Expr, InsertBody, TriggerEvent, TriggerTime};
+
+use crate::schema::{Column, Schema, Trigger, View};
+use crate::translate::emitter::Resolver;
+use crate::translate::expr::{translate_expr, walk_expr_mut};
+use crate::translate::generated::emit_table_column;
+use crate::translate::insert::ephemeral_scratch_table;
+use crate::translate::plan::{QueryDestination, TableReferences};
+use crate::translate::planner::{bind_column_references, ROWID};
+use crate::translate::schema::{emit_schema_entry, SchemaEntryType, SQLITE_TABLEID};
+use crate::translate::select::translate_select;
+use crate::util::normalize_ident;
+use crate::vdbe::builder::{
+    CursorType, ProgramBuilder, ProgramBuilderOpts, QueryMode, TriggerFrame,
+};
+use crate::vdbe::insn::{CmpInsFlags, Cookie, InsertFlags, Insn};
+use crate::vdbe::BranchOffset;
+use crate::{bail_parse_error, CaptureDataChangesMode, Connection, Result, SymbolTable};
+
+pub fn translate_create_trigger(
+    schema: &Schema,
+    stmt: ast::Stmt,
+    syms: &SymbolTable,
+    connection: &Arc<Connection>,
+    mut program: ProgramBuilder,
+) -> Result<ProgramBuilder> {
+    let ast::Stmt::CreateTrigger {
+        temporary,
+        if_not_exists,
+        trigger_name,
+        time,
+        event: _,
+        tbl_name,
+        ..
+    } = &stmt
+    else {
+        bail_parse_error!("expected CREATE TRIGGER statement");
+    };
+    // Like in SQLite, a trigger is stored in the database of its table, so the triggers of temp
+    // tables are always TEMP triggers.
+    let table_database_id = connection.resolve_database_id(tbl_name)?;
+    let database_id = match &trigger_name.db_name {
+        Some(_) if *temporary => {
+            bail_parse_error!("temporary trigger may not have qualified name")
+        }
+        Some(db_name) => connection.resolve_database_name(db_name.as_str())?,
+        None if *temporary => 1,
+        None => table_database_id,
+    };
+    if database_id != table_database_id {
+        if database_id == 1 {
+            bail_parse_error!("TEMP triggers on tables of other databases are not supported yet");
+        }
+        bail_parse_error!("cannot create triggers on tables in another database");
+    }
+    let database_schema = connection.database_schema(database_id)?;
+    let schema = database_schema.as_deref().unwrap_or(schema);
+    let normalized_trigger_name = normalize_ident(trigger_name.name.as_str());
+    let normalized_table_name = normalize_ident(tbl_name.name.as_str());
+
+    if schema.get_trigger(&normalized_trigger_name).is_some() {
+        if *if_not_exists {
+            return Ok(program);
+        }
+        bail_parse_error!("trigger {} already exists", normalized_trigger_name);
+    }
+
+    if crate::schema::is_system_table(&normalized_table_name) {
+        bail_parse_error!("cannot create trigger on system table");
+    }
+    if schema.is_materialized_view(&normalized_table_name) {
+        bail_parse_error!(
+            "cannot create trigger on materialized view: {}",
+            normalized_table_name
+        );
+    }
+    let is_view = schema.get_view(&normalized_table_name).is_some();
+    match schema.get_table(&normalized_table_name) {
+        Some(table) if table.virtual_table().is_some() => {
+            bail_parse_error!("cannot create triggers on virtual tables");
+        }
+        Some(_) => {
+            if matches!(time, Some(TriggerTime::InsteadOf)) {
+                bail_parse_error!(
+                    "cannot create INSTEAD OF trigger on table: {}",
+                    normalized_table_name
+                );
+            }
+        }
+        None if is_view => {
+            if !matches!(time, Some(TriggerTime::InsteadOf)) {
+                let time = if matches!(time, Some(TriggerTime::After)) {
+                    "AFTER"
+                } else {
+                    "BEFORE"
+                };
+                bail_parse_error!(
+                    "cannot create {} trigger on view: {}",
+                    time,
+                    normalized_table_name
+                );
+            }
+        }
+        None => bail_parse_error!("no such table: main.{}", normalized_table_name),
+    }
+
+    // The statement is stored without IF NOT EXISTS, TEMP and the database name, like SQLite
+    // does.
+    let mut stored_stmt = stmt.clone();
+    if let ast::Stmt::CreateTrigger {
+        temporary,
+        if_not_exists,
+        trigger_name,
+        ..
+    } = &mut stored_stmt
+    {
+        *temporary = false;
+        *if_not_exists = false;
+        trigger_name.db_name = None;
+    }
+    let sql = stored_stmt.to_string();
+
+    let schema_table = schema.get_btree_table(SQLITE_TABLEID).unwrap();
+    let sqlite_schema_cursor_id = program.alloc_cursor_id(CursorType::BTreeTable(schema_table));
+    program.emit_insn(Insn::OpenWrite {
+        cursor_id: sqlite_schema_cursor_id,
+        root_page: 1usize.into(),
+        db: database_id,
+    });
+
+    let resolver = Resolver::new(schema, syms);
+    emit_schema_entry(
+        &mut program,
+        &resolver,
+        sqlite_schema_cursor_id,
+        None, // cdc_table_cursor_id, no cdc for triggers
+        SchemaEntryType::Trigger,
+        &normalized_trigger_name,
+        &normalized_table_name,
+        0, // triggers don't have a btree
+        Some(sql),
+    )?;
+
+    program.emit_insn(Insn::ParseSchema {
+        db: database_id,
+        where_clause: Some(format!(
+            "type = 'trigger' AND name = '{normalized_trigger_name}'"
+        )),
+    });
+
+    program.emit_insn(Insn::SetCookie {
+        db: database_id,
+        cookie: Cookie::SchemaVersion,
+        value: (schema.schema_version + 1) as i32,
+        p5: 0,
+    });
+
+    Ok(program)
+}
+
+pub fn translate_drop_trigger(
+    schema: &Schema,
+    database_id: usize,
+    trigger_name: &str,
+    if_exists: bool,
+    mut program: ProgramBuilder,
+) -> Result<ProgramBuilder> {
+    let normalized_trigger_name = normalize_ident(trigger_name);
+    if schema.get_trigger(&normalized_trigger_name).is_none() {
+        if if_exists {
+            return Ok(program);
+        }
+        bail_parse_error!("no such trigger: {}", normalized_trigger_name);
+    }
+
+    emit_drop_trigger(&mut program, schema, database_id, &normalized_trigger_name);
+
+    program.emit_insn(Insn::SetCookie {
+        db: database_id,
+        cookie: Cookie::SchemaVersion,
+        value: (schema.schema_version + 1) as i32,
+        p5: 0,
+    });
+
+    Ok(program)
+}
+
+/// Emits the instructions that remove a trigger from sqlite_schema and from the in-memory schema.
+/// Used by DROP TRIGGER and when dropping the table or view the trigger is attached to.
+pub fn emit_drop_trigger(
+    program: &mut ProgramBuilder,
+    schema: &Schema,
+    database_id: usize,
+    trigger_name: &str,
+) {
+    let schema_table = schema.get_btree_table(SQLITE_TABLEID).unwrap();
+    let sqlite_schema_cursor_id = program.alloc_cursor_id(CursorType::BTreeTable(schema_table));
+    program.emit_insn(Insn::OpenWrite {
+        cursor_id: sqlite_schema_cursor_id,
+        root_page: 1usize.into(),
+        db: database_id,
+    });
+
+    let type_reg = program.emit_string8_new_reg("trigger".to_string());
+    let name_reg = program.emit_string8_new_reg(trigger_name.to_string());
+    let col_reg = program.alloc_register();
+
+    let end_loop_label = program.allocate_label();
+    let loop_start_label = program.allocate_label();
+    let next_label = program.allocate_label();
+    program.emit_insn(Insn::Rewind {
+        cursor_id: sqlite_schema_cursor_id,
+        pc_if_empty: end_loop_label,
+    });
+    program.preassign_label_to_next_insn(loop_start_label);
+
+    // Column 0 is type, column 1 is name
+    program.emit_column_or_rowid(sqlite_schema_cursor_id, 0, col_reg);
+    program.emit_insn(Insn::Ne {
+        lhs: col_reg,
+        rhs: type_reg,
+        target_pc: next_label,
+        flags: CmpInsFlags::default(),
+        collation: program.curr_collation(),
+    });
+    program.emit_column_or_rowid(sqlite_schema_cursor_id, 1, col_reg);
+    program.emit_insn(Insn::Ne {
+        lhs: col_reg,
+        rhs: name_reg,
+        target_pc: next_label,
+        flags: CmpInsFlags::default(),
+        collation: program.curr_collation(),
+    });
+    program.emit_insn(Insn::Delete {
+        cursor_id: sqlite_schema_cursor_id,
+        table_name: SQLITE_TABLEID.to_string(),
+    });
+
+    program.preassign_label_to_next_insn(next_label);
+    program.emit_insn(Insn::Next {
+        cursor_id: sqlite_schema_cursor_id,
+        pc_if_next: loop_start_label,
+    });
+    program.preassign_label_to_next_insn(end_loop_label);
+
+    program.emit_insn(Insn::DropTrigger {
+        db: database_id,
+        trigger_name: trigger_name.to_string(),
+    });
+}
+
+/// Registers holding a row that a trigger program can read through `OLD.<col>` or `NEW.<col>`.
+#[derive(Debug, Clone)]
+pub struct TriggerRow {
+    pub rowid_reg: usize,
+    /// One register per column, in the column order of the table or view.
+    pub column_regs: Vec<usize>,
+}
+
+impl TriggerRow {
+    /// Reads the row the table cursor currently points at into fresh registers.
+    pub fn load_from_cursor(
+        program: &mut ProgramBuilder,
+        resolver: &Resolver,
+        cursor_id: usize,
+        columns: &[Column],
+    ) -> Result<Self> {
+        let rowid_reg = program.alloc_register();
+        program.emit_rowid_or_null(cursor_id, rowid_reg);
+        let column_regs = columns
+            .iter()
+            .enumerate()
+            .map(|(idx, column)| {
+                if column.is_rowid_alias {
+                    return Ok(rowid_reg);
+                }
+                let reg = program.alloc_register();
+                emit_table_column(program, resolver, cursor_id, idx, reg)?;
+                Ok(reg)
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            rowid_reg,
+            column_regs,
+        })
+    }
+
+    /// A row whose column values are already in consecutive registers starting at
+    /// `columns_start_reg`. Rowid alias columns read `rowid_reg` instead, since the
+    /// record being built holds a NULL for them.
+    pub fn from_registers(rowid_reg: usize, columns_start_reg: usize, columns: &[Column]) -> Self {
+        let column_regs = columns
+            .iter()
+            .enumerate()
+            .map(|(idx, column)| {
+                if column.is_rowid_alias {
+                    rowid_reg
+                } else {
+                    columns_start_reg + idx
+                }
+            })
+            .collect();
+        Self {
+            rowid_reg,
+            column_regs,
+        }
+    }
+
+    fn column_reg(&self, name: &str, columns: &[Column]) -> Option<usize> {
+        if let Some(idx) = columns.iter().position(|c| {
+            c.name
+                .as_ref()
+                .is_some_and(|n| n.eq_ignore_ascii_case(name))
+        }) {
+            return Some(self.column_regs[idx]);
+        }
+        if name.eq_ignore_ascii_case(ROWID)
+            || name.eq_ignore_ascii_case("oid")
+            || name.eq_ignore_ascii_case("_rowid_")
+        {
+            return Some(self.rowid_reg);
+        }
+        None
+    }
+}
+
+/// The rows a trigger program is fired for.
+pub struct TriggerRows<'a> {
+    /// Columns of the table or view the trigger is attached to.
+    pub columns: &'a [Column],
+    pub old: Option<&'a TriggerRow>,
+    pub new: Option<&'a TriggerRow>,
+}
+
+/// Returns the triggers on `table_name` that fire for the given operation, in the order
+/// they must be run (most recently created first, like SQLite).
+/// Triggers that are already being run are skipped, as recursive triggers are not supported.
+pub fn get_relevant_triggers(
+    program: &ProgramBuilder,
+    schema: &Schema,
+    table_name: &str,
+    time: TriggerTime,
+    event: &TriggerEvent,
+    changed_columns: &[&str],
+) -> Vec<Arc<Trigger>> {
+    schema
+        .get_triggers_for_table(table_name)
+        .iter()
+        .rev()
+        .filter(|trigger| trigger.matches(time, event, changed_columns))
+        .filter(|trigger| !program.is_trigger_active(&trigger.name))
+        .cloned()
+        .collect()
+}
+
+/// Whether any trigger, at any time, fires on `event` for `table_name`.
+pub fn has_triggers(schema: &Schema, table_name: &str, event: &TriggerEvent) -> bool {
+    schema
+        .get_triggers_for_table(table_name)
+        .iter()
+        .any(|trigger| match (&trigger.event, event) {
+            (TriggerEvent::UpdateOf(_), TriggerEvent::Update) => true,
+            (a, b) => a == b,
+        })
+}
+
+/// Inlines the programs of `triggers` for the current row.
+///
+/// `ignore_label` is where RAISE(IGNORE) jumps to: the point after the processing
+/// of the row that fired the triggers.
+pub fn fire_triggers(
+    program: &mut ProgramBuilder,
+    schema: &Schema,
+    syms: &SymbolTable,
+    connection: &Arc<Connection>,
+    triggers: &[Arc<Trigger>],
+    rows: &TriggerRows,
+    ignore_label: BranchOffset,
+) -> Result<()> {
+    for trigger in triggers {
+        let skip_label = program.allocate_label();
+        if let Some(when_clause) = &trigger.when_clause {
+            let mut when_clause = when_clause.as_ref().clone();
+            rewrite_trigger_expr(&mut when_clause, rows)?;
+            let mut table_references = TableReferences::new(vec![], vec![]);
+            bind_column_references(&mut when_clause, &mut table_references, None, connection)?;
+            let reg = program.alloc_register();
+            let resolver = Resolver::new(schema, syms);
+            translate_expr(
+                program,
+                Some(&table_references),
+                &when_clause,
+                reg,
+                &resolver,
+            )?;
+            program.emit_insn(Insn::IfNot {
+                reg,
+                target_pc: skip_label,
+                jump_if_null: true,
+            });
+        }
+
+        program.push_trigger(TriggerFrame {
+            trigger_name: trigger.name.clone(),
+            ignore_label,
+            cascade_queue: None,
+        });
+        program.incr_nesting();
+        // The statements of the trigger program overwrite the result columns of the builder.
+        let result_columns = std::mem::take(&mut program.result_columns);
+        for command in &trigger.commands {
+            let mut command = command.clone();
+            rewrite_trigger_cmd(&mut command, rows)?;
+            translate_trigger_cmd(program, schema, syms, connection, command)?;
+        }
+        program.result_columns = result_columns;
+        program.decr_nesting();
+        program.pop_trigger();
+
+        program.preassign_label_to_next_insn(skip_label);
+    }
+    Ok(())
+}
+
+pub(crate) fn translate_trigger_cmd(
+    program: &mut ProgramBuilder,
+    schema: &Schema,
+    syms: &SymbolTable,
+    connection: &Arc<Connection>,
+    command: ast::TriggerCmd,
+) -> Result<()> {
+    // The statement translators take the builder by value, so swap in a placeholder
+    // while they run.
+    let placeholder = ProgramBuilder::new(
+        QueryMode::Normal,
+        CaptureDataChangesMode::Off,
+        ProgramBuilderOpts {
+            num_cursors: 0,
+            approx_num_insns: 0,
+            approx_num_labels: 0,
+        },
+    );
+    let owned_program = std::mem::replace(program, placeholder);
+    *program = match command {
+        ast::TriggerCmd::Update {
+            or_conflict,
+            tbl_name,
+            sets,
+            from,
+            where_clause,
+        } => {
+            let mut update = ast::Update {
+                with: None,
+                or_conflict,
+                tbl_name: ast::QualifiedName::single(tbl_name),
+                indexed: None,
+                sets,
+                from,
+                where_clause,
+                returning: vec![],
+                order_by: vec![],
+                limit: None,
+            };
+            super::update::translate_update(schema, &mut update, syms, owned_program, connection)?
+        }
+        ast::TriggerCmd::Insert {
+            or_conflict,
+            tbl_name,
+            col_names,
+            select,
+            upsert,
+            returning,
+        } => super::insert::translate_insert(
+            schema,
+            None,
+            or_conflict,
+            ast::QualifiedName::single(tbl_name),
+            col_names,
+            InsertBody::Select(select, upsert),
+            returning,
+            syms,
+            owned_program,
+            connection,
+        )?,
+        ast::TriggerCmd::Delete {
+            tbl_name,
+            where_clause,
+        } => super::delete::translate_delete(
+            schema,
+            &ast::QualifiedName::single(tbl_name),
+            None,
+            where_clause,
+            None,
+            vec![],
+            syms,
+            owned_program,
+            connection,
+        )?,
+        ast::TriggerCmd::Select(select) => {
+            if !select.body.compounds.is_empty() {
+                bail_parse_error!("compound SELECT in a trigger program is not supported yet");
+            }
+            translate_select(
+                schema,
+                select,
+                syms,
+                owned_program,
+                QueryDestination::Discard,
+                connection,
+            )?
+            .program
+        }
+    };
+    Ok(())
+}
+
+/// Replaces `OLD.<col>` and `NEW.<col>` references with the registers holding those values.
+fn rewrite_trigger_expr(expr: &mut Expr, rows: &TriggerRows) -> Result<()> {
+    walk_expr_mut(expr, &mut |expr: &mut Expr| -> Result<()> {
+        match expr {
+            Expr::Qualified(ns, col) => {
+                let ns_name = normalize_ident(ns.as_str());
+                let row = match ns_name.as_str() {
+                    "new" => rows.new,
+                    "old" => rows.old,
+                    _ => return Ok(()),
+                };
+                let col_name = normalize_ident(col.as_str());
+                let Some(reg) = row.and_then(|row| row.column_reg(&col_name, rows.columns)) else {
+                    bail_parse_error!("no such column: {}.{}", ns_name, col_name);
+                };
+                *expr = Expr::Register(reg);
+            }
+            Expr::Exists(select) | Expr::Subquery(select) => {
+                rewrite_trigger_select(select, rows)?;
+            }
+            Expr::InSelect { rhs, .. } => {
+                rewrite_trigger_select(rhs, rows)?;
+            }
+            _ => {}
+        }
+        Ok(())
+    })
+}
+
+fn rewrite_trigger_select(select: &mut ast::Select, rows: &TriggerRows) -> Result<()> {
+    if let Some(with) = &mut select.with {
+        for cte in &mut with.ctes {
+            rewrite_trigger_select(&mut cte.select, rows)?;
+        }
+    }
+    rewrite_trigger_one_select(&mut select.body.select, rows)?;
+    for compound in &mut select.body.compounds {
+        rewrite_trigger_one_select(&mut compound.select, rows)?;
+    }
+    for sorted_column in &mut select.order_by {
+        rewrite_trigger_expr(&mut sorted_column.expr, rows)?;
+    }
+    if let Some(limit) = &mut select.limit {
+        rewrite_trigger_expr(&mut limit.expr, rows)?;
+        if let Some(offset) = &mut limit.offset {
+            rewrite_trigger_expr(offset, rows)?;
+        }
+    }
+    Ok(())
+}
+
+fn rewrite_trigger_one_select(one_select: &mut ast::OneSelect, rows: &TriggerRows) -> Result<()> {
+    match one_select {
+        ast::OneSelect::Select {
+            columns,
+            from,
+            where_clause,
+            group_by,
+            ..
+        } => {
+            for column in columns {
+                if let ast::ResultColumn::Expr(expr, _) = column {
+                    rewrite_trigger_expr(expr, rows)?;
+                }
+            }
+            if let Some(from) = from {
+                rewrite_trigger_from(from, rows)?;
+            }
+            if let Some(where_clause) = where_clause {
+                rewrite_trigger_expr(where_clause, rows)?;
+            }
+            if let Some(group_by) = group_by {
+                for expr in &mut group_by.exprs {
+                    rewrite_trigger_expr(expr, rows)?;
+                }
+                if let Some(having) = &mut group_by.having {
+                    rewrite_trigger_expr(having, rows)?;
+                }
+            }
+        }
+        ast::OneSelect::Values(values) => {
+            for expr in values.iter_mut().flatten() {
+                rewrite_trigger_expr(expr, rows)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn rewrite_trigger_from(from: &mut ast::FromClause, rows: &TriggerRows) -> Result<()> {
+    rewrite_trigger_select_table(&mut from.select, rows)?;
+    for join in &mut from.joins {
+        rewrite_trigger_select_table(&mut join.table, rows)?;
+        if let Some(ast::JoinConstraint::On(expr)) = &mut join.constraint {
+            rewrite_trigger_expr(expr, rows)?;
+        }
+    }
+    Ok(())
+}
+
+fn rewrite_trigger_select_table(table: &mut ast::SelectTable, rows: &TriggerRows) -> Result<()> {
+    match table {
+        ast::SelectTable::Table(..) => {}
+        ast::SelectTable::TableCall(_, args, _) => {
+            for arg in args {
+                rewrite_trigger_expr(arg, rows)?;
+            }
+        }
+        ast::SelectTable::Select(select, _) => rewrite_trigger_select(select, rows)?,
+        ast::SelectTable::Sub(from, _) => rewrite_trigger_from(from, rows)?,
+    }
+    Ok(())
+}
+
+fn rewrite_trigger_cmd(command: &mut ast::TriggerCmd, rows: &TriggerRows) -> Result<()> {
+    match command {
+        ast::TriggerCmd::Update {
+            sets,
+            from,
+            where_clause,
+            ..
+        } => {
+            for set in sets {
+                rewrite_trigger_expr(&mut set.expr, rows)?;
+            }
+            if let Some(from) = from {
+                rewrite_trigger_from(from, rows)?;
+            }
+            if let Some(where_clause) = where_clause {
+                rewrite_trigger_expr(where_clause, rows)?;
+            }
+        }
+        ast::TriggerCmd::Insert { select, upsert, .. } => {
+            rewrite_trigger_select(select, rows)?;
+            let mut upsert = upsert.as_deref_mut();
+            while let Some(clause) = upsert {
+                if let ast::UpsertDo::Set { sets, where_clause } = &mut clause.do_clause {
+                    for set in sets {
+                        rewrite_trigger_expr(&mut set.expr, rows)?;
+                    }
+                    if let Some(where_clause) = where_clause {
+                        rewrite_trigger_expr(where_clause, rows)?;
+                    }
+                }
+                upsert = clause.next.as_deref_mut();
+            }
+        }
+        ast::TriggerCmd::Delete { where_clause, .. } => {
+            if let Some(where_clause) = where_clause {
+                rewrite_trigger_expr(where_clause, rows)?;
+            }
+        }
+        ast::TriggerCmd::Select(select) => rewrite_trigger_select(select, rows)?,
+    }
+    Ok(())
+}
+
+/// INSERT INTO a view: runs its INSTEAD OF INSERT triggers once per inserted row.
+pub fn translate_insert_into_view(
+    schema: &Schema,
+    view: &View,
+    columns: Vec<ast::Name>,
+    body: InsertBody,
+    syms: &SymbolTable,
+    program: ProgramBuilder,
+    connection: &Arc<Connection>,
+) -> Result<ProgramBuilder> {
+    let select = match body {
+        InsertBody::Select(_, Some(_)) => bail_parse_error!("cannot UPSERT a view"),
+        InsertBody::Select(select, None) => select,
+        // A single row of NULLs
+        InsertBody::DefaultValues => ast::Select {
+            with: None,
+            body: ast::SelectBody {
+                select: ast::OneSelect::Values(vec![vec![Box::new(Expr::Literal(
+                    ast::Literal::Null,
+                ))]]),
+                compounds: vec![],
+            },
+            order_by: vec![],
+            limit: None,
+        },
+    };
+    let is_default_values = columns.is_empty()
+        && matches!(&select.body.select, ast::OneSelect::Values(rows) if rows.len() == 1 && rows[0].len() == 1 && matches!(rows[0][0].as_ref(), Expr::Literal(ast::Literal::Null)))
+        && view.columns.len() != 1;
+
+    // For each view column, the position of its value in the inserted rows.
+    let new_mapping = if columns.is_empty() {
+        (0..view.columns.len()).map(Some).collect::<Vec<_>>()
+    } else {
+        let mut mapping = vec![None; view.columns.len()];
+        for (value_idx, name) in columns.iter().enumerate() {
+            let name = normalize_ident(name.as_str());
+            let Some(col_idx) = view_column_index(view, &name) else {
+                bail_parse_error!("table {} has no column named {}", view.name, name);
+            };
+            mapping[col_idx] = Some(value_idx);
+        }
+        mapping
+    };
+    let expected_values = if columns.is_empty() {
+        view.columns.len()
+    } else {
+        columns.len()
+    };
+
+    emit_instead_of_loop(
+        schema,
+        view,
+        select,
+        &TriggerEvent::Insert,
+        &[],
+        InsteadOfRows {
+            num_values: if is_default_values {
+                None
+            } else {
+                Some(expected_values)
+            },
+            has_old: false,
+            new_mapping: Some(if is_default_values {
+                vec![None; view.columns.len()]
+            } else {
+                new_mapping
+            }),
+        },
+        syms,
+        program,
+        connection,
+    )
+}
+
+/// UPDATE of a view: runs its INSTEAD OF UPDATE triggers once per matching view row.
+pub fn translate_update_of_view(
+    schema: &Schema,
+    view: &View,
+    body: &ast::Update,
+    syms: &SymbolTable,
+    program: ProgramBuilder,
+    connection: &Arc<Connection>,
+) -> Result<ProgramBuilder> {
+    if body.from.is_some() {
+        bail_parse_error!("FROM clause is not supported in UPDATE of a view");
+    }
+    // The loop reads the view row followed by the new value of every assigned column:
+    // SELECT *, <set exprs> FROM view WHERE ...
+    let mut result_columns = vec![ast::ResultColumn::Star];
+    let mut new_mapping = (0..view.columns.len()).map(Some).collect::<Vec<_>>();
+    let mut changed_columns = vec![];
+    for set in &body.sets {
+        let values = match set.expr.as_ref() {
+            Expr::Parenthesized(values) => values.clone(),
+            expr => vec![Box::new(expr.clone())],
+        };
+        if set.col_names.len() != values.len() {
+            bail_parse_error!(
+                "{} columns assigned {} values",
+                set.col_names.len(),
+                values.len()
+            );
+        }
+        for (name, value) in set.col_names.iter().zip(values) {
+            let name = normalize_ident(name.as_str());
+            let Some(col_idx) = view_column_index(view, &name) else {
+                bail_parse_error!("no such column: {}", name);
+            };
+            new_mapping[col_idx] = Some(view.columns.len() + result_columns.len() - 1);
+            result_columns.push(ast::ResultColumn::Expr(value, None));
+            changed_columns.push(name);
+        }
+    }
+    let select = select_from_view(
+        view,
+        result_columns,
+        body.where_clause.clone(),
+        body.order_by.clone(),
+        body.limit.clone(),
+    );
+    let changed_columns = changed_columns
+        .iter()
+        .map(|c| c.as_str())
+        .collect::<Vec<_>>();
+
+    emit_instead_of_loop(
+        schema,
+        view,
+        select,
+        &TriggerEvent::Update,
+        &changed_columns,
+        InsteadOfRows {
+            num_values: None,
+            has_old: true,
+            new_mapping: Some(new_mapping),
+        },
+        syms,
+        program,
+        connection,
+    )
+}
+
+/// DELETE FROM a view: runs its INSTEAD OF DELETE triggers once per matching view row.
+pub fn translate_delete_from_view(
+    schema: &Schema,
+    view: &View,
+    where_clause: Option<Box<Expr>>,
+    limit: Option<ast::Limit>,
+    syms: &SymbolTable,
+    program: ProgramBuilder,
+    connection: &Arc<Connection>,
+) -> Result<ProgramBuilder> {
+    let select = select_from_view(
+        view,
+        vec![ast::ResultColumn::Star],
+        where_clause,
+        vec![],
+        limit,
+    );
+    emit_instead_of_loop(
+        schema,
+        view,
+        select,
+        &TriggerEvent::Delete,
+        &[],
+        InsteadOfRows {
+            num_values: None,
+            has_old: true,
+            new_mapping: None,
+        },
+        syms,
+        program,
+        connection,
+    )
+}
+
+fn view_column_index(view: &View, name: &str) -> Option<usize> {
+    view.columns.iter().position(|c| {
+        c.name
+            .as_ref()
+            .is_some_and(|n| n.eq_ignore_ascii_case(name))
+    })
+}
+
+fn select_from_view(
+    view: &View,
+    columns: Vec<ast::ResultColumn>,
+    where_clause: Option<Box<Expr>>,
+    order_by: Vec<ast::SortedColumn>,
+    limit: Option<ast::Limit>,
+) -> ast::Select {
+    ast::Select {
+        with: None,
+        body: ast::SelectBody {
+            select: ast::OneSelect::Select {
+                distinctness: None,
+                columns,
+                from: Some(ast::FromClause {
+                    select: Box::new(ast::SelectTable::Table(
+                        ast::QualifiedName::single(ast::Name::new(&view.name)),
+                        None,
+                        None,
+                    )),
+                    joins: vec![],
+                }),
+                where_clause,
+                group_by: None,
+                window_clause: vec![],
+            },
+            compounds: vec![],
+        },
+        order_by,
+        limit,
+    }
+}
+
+/// How the values produced for each affected view row map to the OLD and NEW rows.
+struct InsteadOfRows {
+    /// The number of values each row must have, if it is not known up front.
+    num_values: Option<usize>,
+    /// The first values of each row are the view columns of the OLD row.
+    has_old: bool,
+    /// For each view column, the position of its NEW value. `None` means NULL.
+    new_mapping: Option<Vec<Option<usize>>>,
+}
+
+#[allow(clippy::too_many_arguments)]
+fn emit_instead_of_loop(
+    schema: &Schema,
+    view: &View,
+    select: ast::Select,
+    event: &TriggerEvent,
+    changed_columns: &[&str],
+    rows: InsteadOfRows,
+    syms: &SymbolTable,
+    mut program: ProgramBuilder,
+    connection: &Arc<Connection>,
+) -> Result<ProgramBuilder> {
+    let triggers = get_relevant_triggers(
+        &program,
+        schema,
+        &view.name,
+        TriggerTime::InsteadOf,
+        event,
+        changed_columns,
+    );
+    if triggers.is_empty() {
+        bail_parse_error!("cannot modify {} because it is a view", view.name);
+    }
+
+    // Run the SELECT as a coroutine and collect its rows into an ephemeral table first,
+    // as the trigger programs may modify the tables the view reads from.
+    let yield_reg = program.alloc_register();
+    let jump_on_definition_label = program.allocate_label();
+    let start_offset_label = program.allocate_label();
+    program.emit_insn(Insn::InitCoroutine {
+        yield_reg,
+        jump_on_definition: jump_on_definition_label,
+        start_offset: start_offset_label,
+    });
+    program.preassign_label_to_next_insn(start_offset_label);
+    program.incr_nesting();
+    let result = translate_select(
+        schema,
+        select,
+        syms,
+        program,
+        QueryDestination::CoroutineYield {
+            yield_reg,
+            coroutine_implementation_start: start_offset_label,
+        },
+        connection,
+    )?;
+    program = result.program;
+    program.decr_nesting();
+    program.emit_insn(Insn::EndCoroutine { yield_reg });
+    program.preassign_label_to_next_insn(jump_on_definition_label);
+
+    let num_values = result.num_result_cols;
+    if let Some(expected) = rows.num_values {
+        if expected != num_values {
+            bail_parse_error!(
+                "table {} has {} columns but {} values were supplied",
+                view.name,
+                expected,
+                num_values
+            );
+        }
+    }
+
+    let temp_cursor_id =
+        program.alloc_cursor_id(CursorType::BTreeTable(ephemeral_scratch_table(num_values)));
+    program.emit_insn(Insn::OpenEphemeral {
+        cursor_id: temp_cursor_id,
+        is_table: true,
+    });
+
+    let collect_loop_label = program.allocate_label();
+    let collect_done_label = program.allocate_label();
+    program.preassign_label_to_next_insn(collect_loop_label);
+    program.emit_insn(Insn::Yield {
+        yield_reg,
+        end_offset: collect_done_label,
+    });
+    let record_reg = program.alloc_register();
+    program.emit_insn(Insn::MakeRecord {
+        start_reg: yield_reg + 1,
+        count: num_values,
+        dest_reg: record_reg,
+        index_name: None,
+        affinity_str: None,
+    });
+    let rowid_reg = program.alloc_register();
+    program.emit_insn(Insn::NewRowid {
+        cursor: temp_cursor_id,
+        rowid_reg,
+        prev_largest_reg: 0,
+    });
+    program.emit_insn(Insn::Insert {
+        cursor: temp_cursor_id,
+        key_reg: rowid_reg,
+        record_reg,
+        flag: InsertFlags::new(),
+        table_name: String::new(),
+    });
+    program.emit_insn(Insn::Goto {
+        target_pc: collect_loop_label,
+    });
+    program.preassign_label_to_next_insn(collect_done_label);
+
+    // Fire the triggers for every collected row.
+    let loop_end_label = program.allocate_label();
+    let loop_start_label = program.allocate_label();
+    let row_done_label = program.allocate_label();
+    program.emit_insn(Insn::Rewind {
+        cursor_id: temp_cursor_id,
+        pc_if_empty: loop_end_label,
+    });
+    program.preassign_label_to_next_insn(loop_start_label);
+    let values_start_reg = program.alloc_registers(num_values);
+    for i in 0..num_values {
+        program.emit_column_or_rowid(temp_cursor_id, i, values_start_reg + i);
+    }
+    // Views have no rowid
+    let null_reg = program.alloc_register();
+    program.emit_null(null_reg, None);
+    let old = rows.has_old.then(|| TriggerRow {
+        rowid_reg: null_reg,
+        column_regs: (0..view.columns.len())
+            .map(|i| values_start_reg + i)
+            .collect(),
+    });
+    let new = rows.new_mapping.as_ref().map(|mapping| TriggerRow {
+        rowid_reg: null_reg,
+        column_regs: mapping
+            .iter()
+            .map(|value_idx| value_idx.map_or(null_reg, |i| values_start_reg + i))
+            .collect(),
+    });
+    fire_triggers(
+        &mut program,
+        schema,
+        syms,
+        connection,
+        &triggers,
+        &TriggerRows {
+            columns: &view.columns,
+            old: old.as_ref(),
+            new: new.as_ref(),
+        },
+        row_done_label,
+    )?;
+    program.preassign_label_to_next_insn(row_done_label);
+    program.emit_insn(Insn::Next {
+        cursor_id: temp_cursor_id,
+        pc_if_next: loop_start_label,
+    });
+    program.preassign_label_to_next_insn(loop_end_label);
+
+    Ok(program)
+}
@@ -1,7 +1,7 @@
 use std::collections::HashMap;
 use std::sync::Arc;
 
-use crate::schema::{BTreeTable, Column, Type};
+use crate::schema::{BTreeTable, Column, Index, Type};
 use crate::translate::optimizer::optimize_select_plan;
 use crate::translate::plan::{Operation, QueryDestination, Scan, Search, SelectPlan};
 use crate::translate::planner::parse_limit;
@@ -13,16 +13,23 @@ use crate::{
     vdbe::builder::{ProgramBuilder, ProgramBuilderOpts},
     SymbolTable,
 };
-use turso_parser::ast::{self, Expr, Indexed, SortOrder};
+use turso_parser::ast::{self, Expr, SortOrder, TriggerEvent};
 
 use super::emitter::emit_program;
 use super::expr::process_returning_clause;
+use super::fkeys::has_foreign_key_actions;
+use super::index::index_expr_refers_to_column;
 use super::optimizer::optimize_plan;
 use super::plan::{
-    ColumnUsedMask, IterationDirection, JoinedTable, Plan, ResultSetColumn, TableReferences,
-    UpdatePlan,
+    ColumnUsedMask, IndexedBy, IterationDirection, JoinedTable, Plan, ResultSetColumn,
+    TableReferences, UpdatePlan,
 };
-use super::planner::{bind_column_references, parse_where};
+use super::planner::{bind_column_references, parse_where, resolve_indexed_by};
+use super::subquery::{
+    expr_contains_subquery, number_anonymous_params, plan_subqueries_from_expr,
+    plan_subqueries_from_where_clause,
+};
+use super::trigger::{has_triggers, translate_update_of_view};
 /*
 * Update is simple. By default we scan the table, and for each row, we check the WHERE
 * clause. If it evaluates to true, we build the new record with the updated value and insert.
@@ -59,7 +66,19 @@ pub fn translate_update(
     mut program: ProgramBuilder,
     connection: &Arc<crate::Connection>,
 ) -> crate::Result<ProgramBuilder> {
-    let mut plan = prepare_update_plan(&mut program, schema, body, connection, false)?;
+    let database_id = connection.resolve_database_id(&body.tbl_name)?;
+    let database_schema = connection.database_schema(database_id)?;
+    let schema = database_schema.as_deref().unwrap_or(schema);
+    let table_name = body.tbl_name.name.as_str();
+    if schema.get_table(table_name).is_none() {
+        if let Some(view) = schema.get_view(table_name) {
+            if !body.returning.is_empty() {
+                bail_parse_error!("RETURNING is not supported for views");
+            }
+            return translate_update_of_view(schema, view, body, syms, program, connection);
+        }
+    }
+    let mut plan = prepare_update_plan(&mut program, schema, body, syms, connection, false)?;
     optimize_plan(&mut plan, schema)?;
     // TODO: freestyling these numbers
     let opts = ProgramBuilderOpts {
@@ -68,7 +87,7 @@ pub fn translate_update(
         approx_num_labels: 4,
     };
     program.extend(&opts);
-    emit_program(&mut program, plan, schema, syms, |_| {})?;
+    emit_program(&mut program, plan, schema, syms, connection, |_| {})?;
     Ok(program)
 }
 
@@ -81,7 +100,7 @@ pub fn translate_update_for_schema_change(
     ddl_query: &str,
     after: impl FnOnce(&mut ProgramBuilder),
 ) -> crate::Result<ProgramBuilder> {
-    let mut plan = prepare_update_plan(&mut program, schema, body, connection, true)?;
+    let mut plan = prepare_update_plan(&mut program, schema, body, syms, connection, true)?;
 
     if let Plan::Update(plan) = &mut plan {
         if program.capture_data_changes_mode().has_updates() {
@@ -97,7 +116,7 @@ pub fn translate_update_for_schema_change(
         approx_num_labels: 4,
     };
     program.extend(&opts);
-    emit_program(&mut program, plan, schema, syms, after)?;
+    emit_program(&mut program, plan, schema, syms, connection, after)?;
     Ok(program)
 }
 
@@ -105,6 +124,7 @@ pub fn prepare_update_plan(
     program: &mut ProgramBuilder,
     schema: &Schema,
     body: &mut ast::Update,
+    syms: &SymbolTable,
     connection: &Arc<crate::Connection>,
     is_internal_schema_change: bool,
 ) -> crate::Result<Plan> {
@@ -114,13 +134,7 @@ pub fn prepare_update_plan(
     if body.or_conflict.is_some() {
         bail_parse_error!("ON CONFLICT clause is not supported in UPDATE");
     }
-    if body
-        .indexed
-        .as_ref()
-        .is_some_and(|i| matches!(i, Indexed::IndexedBy(_)))
-    {
-        bail_parse_error!("INDEXED BY clause is not supported in UPDATE");
-    }
+    let database_id = connection.resolve_database_id(&body.tbl_name)?;
     let table_name = &body.tbl_name.name;
 
     // Check if this is a system table that should be protected from direct writes
@@ -158,6 +172,7 @@ pub fn prepare_update_plan(
         })
         .unwrap_or(IterationDirection::Forwards);
 
+    let indexed = resolve_indexed_by(body.indexed.as_ref(), schema.get_indices(table_name))?;
     let joined_tables = vec![JoinedTable {
         table: match table.as_ref() {
             Table::Virtual(vtab) => Table::Virtual(vtab.clone()),
@@ -169,7 +184,8 @@ pub fn prepare_update_plan(
         op: build_scan_op(&table, iter_dir),
         join_info: None,
         col_used_mask: ColumnUsedMask::default(),
-        database_id: 0,
+        database_id,
+        indexed: None,
     }];
     let mut table_references = TableReferences::new(joined_tables, vec![]);
 
@@ -207,6 +223,9 @@ pub fn prepare_update_plan(
                 Some(idx) => idx,
                 None => bail_parse_error!("no such column: {}", ident),
             };
+            if table.columns()[*col_index].is_generated() {
+                bail_parse_error!("cannot UPDATE generated column \"{}\"", ident);
+            }
 
             // Update existing entry or add new one
             match set_clauses.iter_mut().find(|(idx, _)| idx == col_index) {
@@ -216,7 +235,21 @@ pub fn prepare_update_plan(
         }
     }
 
-    let (result_columns, _table_references) = process_returning_clause(
+    // Subqueries are planned, and anonymous parameters numbered, in the order they appear in the statement.
+    let mut param_idx = 1;
+    for (_, expr) in set_clauses.iter_mut() {
+        plan_subqueries_from_expr(
+            program,
+            expr,
+            &mut table_references,
+            schema,
+            syms,
+            connection,
+            &mut param_idx,
+        )?;
+    }
+
+    let (mut result_columns, _table_references) = process_returning_clause(
         &mut body.returning,
         &table,
         body.tbl_name.name.as_str(),
@@ -224,7 +257,7 @@ pub fn prepare_update_plan(
         connection,
     )?;
 
-    let order_by = body
+    let mut order_by: Vec<(Box<Expr>, SortOrder)> = body
         .order_by
         .iter()
         .map(|o| (o.expr.clone(), o.order.unwrap_or(SortOrder::Asc)))
@@ -239,26 +272,48 @@ pub fn prepare_update_plan(
     let rowid_alias_used = set_clauses.iter().fold(false, |accum, (idx, _)| {
         accum || columns[*idx].is_rowid_alias
     });
+    // Changing the primary key of a WITHOUT ROWID table moves the row within the table b-tree,
+    // just like changing the rowid does.
+    let without_rowid = table
+        .btree()
+        .is_some_and(|btree_table| !btree_table.has_rowid);
+    let primary_key_used =
+        without_rowid && set_clauses.iter().any(|(idx, _)| columns[*idx].primary_key);
+
+    // Triggers and the actions of foreign key constraints may modify the table being updated,
+    // so the rowids to update are collected up front, like SQLite does.
+    let has_update_triggers = has_triggers(schema, table_name, &TriggerEvent::Update)
+        || has_foreign_key_actions(schema, connection, table_name, &TriggerEvent::Update);
+    // So are they when the WHERE clause has a subquery, as it may read the table being updated.
+    let where_has_subquery = body
+        .where_clause
+        .as_deref()
+        .is_some_and(expr_contains_subquery);
+
+    let ephemeral_plan =
+        if rowid_alias_used || primary_key_used || has_update_triggers || where_has_subquery {
+            build_ephemeral_rowid_plan(
+                program,
+                schema,
+                syms,
+                &table,
+                database_id,
+                iter_dir,
+                indexed.clone(),
+                body.where_clause.as_deref(),
+                &result_columns,
+                connection,
+                &mut param_idx,
+            )?
+        } else {
+            None
+        };
+    let mut where_clause = vec![];
 
-    let (ephemeral_plan, mut where_clause) = if rowid_alias_used {
-        let mut where_clause = vec![];
-        let internal_id = program.table_reference_counter.next();
-
-        let joined_tables = vec![JoinedTable {
-            table: match table.as_ref() {
-                Table::Virtual(vtab) => Table::Virtual(vtab.clone()),
-                Table::BTree(btree_table) => Table::BTree(btree_table.clone()),
-                _ => unreachable!(),
-            },
-            identifier: table_name.to_string(),
-            internal_id,
-            op: build_scan_op(&table, iter_dir),
-            join_info: None,
-            col_used_mask: ColumnUsedMask::default(),
-            database_id: 0,
-        }];
-        let mut table_references = TableReferences::new(joined_tables, vec![]);
-
+    if ephemeral_plan.is_none() {
+        // Otherwise the rows to update are found by the statement's own loop,
+        // which INDEXED BY and NOT INDEXED apply to.
+        table_references.joined_tables_mut()[0].indexed = indexed;
         // Parse the WHERE clause
         parse_where(
             body.where_clause.as_deref(),
@@ -267,82 +322,22 @@ pub fn prepare_update_plan(
             &mut where_clause,
             connection,
         )?;
-
-        let table = Arc::new(BTreeTable {
-            root_page: 0, // Not relevant for ephemeral table definition
-            name: "ephemeral_scratch".to_string(),
-            has_rowid: true,
-            primary_key_columns: vec![],
-            columns: vec![Column {
-                name: Some("rowid".to_string()),
-                ty: Type::Integer,
-                ty_str: "INTEGER".to_string(),
-                primary_key: true,
-                is_rowid_alias: false,
-                notnull: true,
-                default: None,
-                unique: false,
-                collation: None,
-                hidden: false,
-            }],
-            is_strict: false,
-            unique_sets: vec![],
-        });
-
-        let temp_cursor_id = program.alloc_cursor_id(CursorType::BTreeTable(table.clone()));
-
-        let mut ephemeral_plan = SelectPlan {
-            table_references,
-            result_columns: vec![ResultSetColumn {
-                expr: Expr::RowId {
-                    database: None,
-                    table: internal_id,
-                },
-                alias: None,
-                contains_aggregates: false,
-            }],
-            where_clause,       // original WHERE terms from the UPDATE clause
-            group_by: None,     // N/A
-            order_by: vec![],   // N/A
-            aggregates: vec![], // N/A
-            limit: None,        // N/A
-            query_destination: QueryDestination::EphemeralTable {
-                cursor_id: temp_cursor_id,
-                table,
-            },
-            join_order: vec![],
-            offset: None,
-            contains_constant_false_condition: false,
-            distinctness: super::plan::Distinctness::NonDistinct,
-            values: vec![],
-        };
-
-        optimize_select_plan(&mut ephemeral_plan, schema)?;
-        let table = ephemeral_plan
-            .table_references
-            .joined_tables()
-            .first()
-            .unwrap();
-        // We do not need to emit an ephemeral plan if we are not going to loop over the table values
-        if matches!(table.op, Operation::Search(Search::RowidEq { .. })) {
-            (None, vec![])
-        } else {
-            (Some(ephemeral_plan), vec![])
-        }
-    } else {
-        (None, vec![])
-    };
-
-    if ephemeral_plan.is_none() {
-        // Parse the WHERE clause
-        parse_where(
-            body.where_clause.as_deref(),
-            &mut table_references,
-            Some(&result_columns),
+        plan_subqueries_from_where_clause(
+            program,
             &mut where_clause,
+            &mut table_references,
+            schema,
+            syms,
             connection,
+            &mut param_idx,
         )?;
     };
+    for (expr, _) in order_by.iter_mut() {
+        number_anonymous_params(expr, &mut param_idx)?;
+    }
+    for result_column in result_columns.iter_mut() {
+        number_anonymous_params(&mut result_column.expr, &mut param_idx)?;
+    }
 
     // Parse the LIMIT/OFFSET clause
     let (limit, offset) = body
@@ -353,6 +348,19 @@ pub fn prepare_update_plan(
     // Check what indexes will need to be updated by checking set_clauses and see
     // if a column is contained in an index.
     let indexes = schema.get_indices(table_name);
+    // Generated columns change along with the columns they refer to
+    let mut changed_columns = set_clauses.iter().map(|(idx, _)| *idx).collect::<Vec<_>>();
+    if let Some(btree_table) = table.btree() {
+        for pos in btree_table.generated_columns_in_dependency_order()? {
+            if btree_table
+                .generated_column_dependencies(pos)?
+                .iter()
+                .any(|dependency| changed_columns.contains(dependency))
+            {
+                changed_columns.push(pos);
+            }
+        }
+    }
     let rowid_alias_used = set_clauses
         .iter()
         .any(|(idx, _)| columns[*idx].is_rowid_alias);
@@ -360,15 +368,27 @@ pub fn prepare_update_plan(
         // If the rowid alias is used in the SET clause, we need to update all indexes
         indexes.to_vec()
     } else {
-        // otherwise we need to update the indexes whose columns are set in the SET clause
+        // otherwise we need to update the indexes whose columns are set in the SET clause,
+        // and the indexes whose expressions or WHERE clause refer to a column in the SET clause.
+        // The indexes of a WITHOUT ROWID table contain its primary key, so they are updated when
+        // it changes. Its primary key index is the table itself, which is written separately.
         indexes
             .iter()
             .filter(|index| {
-                index.columns.iter().any(|index_column| {
-                    set_clauses
-                        .iter()
-                        .any(|(set_index_column, _)| index_column.pos_in_table == *set_index_column)
-                })
+                !table
+                    .btree()
+                    .is_some_and(|btree_table| index.is_without_rowid_primary_key(&btree_table))
+            })
+            .filter(|index| {
+                index
+                    .columns
+                    .iter()
+                    .any(|index_column| changed_columns.contains(&index_column.pos_in_table))
+                    || table.btree().is_some_and(|btree_table| {
+                        changed_columns.iter().any(|changed_column| {
+                            index_expr_refers_to_column(index, &btree_table, *changed_column)
+                        })
+                    })
             })
             .cloned()
             .collect()
@@ -393,6 +413,178 @@ pub fn prepare_update_plan(
     }))
 }
 
+/// Builds a plan that collects the rowids of the rows matching `where_clause` into an
+/// ephemeral table, so that the rows can be modified while looping over that table.
+/// The primary keys of a WITHOUT ROWID table are collected into an ephemeral index instead.
+/// Returns `None` when the WHERE clause is a rowid equality, as at most one row is visited.
+#[allow(clippy::too_many_arguments)]
+pub fn build_ephemeral_rowid_plan(
+    program: &mut ProgramBuilder,
+    schema: &Schema,
+    syms: &SymbolTable,
+    table: &Table,
+    database_id: usize,
+    iter_dir: IterationDirection,
+    indexed: Option<IndexedBy>,
+    where_clause: Option<&Expr>,
+    result_columns: &[ResultSetColumn],
+    connection: &Arc<crate::Connection>,
+    param_idx: &mut usize,
+) -> crate::Result<Option<SelectPlan>> {
+    let mut where_terms = vec![];
+    let internal_id = program.table_reference_counter.next();
+
+    let joined_tables = vec![JoinedTable {
+        table: match table {
+            Table::Virtual(vtab) => Table::Virtual(vtab.clone()),
+            Table::BTree(btree_table) => Table::BTree(btree_table.clone()),
+            _ => unreachable!(),
+        },
+        identifier: table.get_name().to_string(),
+        internal_id,
+        op: build_scan_op(table, iter_dir),
+        join_info: None,
+        col_used_mask: ColumnUsedMask::default(),
+        database_id,
+        indexed,
+    }];
+    let mut table_references = TableReferences::new(joined_tables, vec![]);
+
+    // Parse the WHERE clause
+    parse_where(
+        where_clause,
+        &mut table_references,
+        Some(result_columns),
+        &mut where_terms,
+        connection,
+    )?;
+    let param_idx_start = *param_idx;
+    plan_subqueries_from_where_clause(
+        program,
+        &mut where_terms,
+        &mut table_references,
+        schema,
+        syms,
+        connection,
+        param_idx,
+    )?;
+
+    let (result_column_exprs, query_destination) = match table {
+        // A WITHOUT ROWID table has no rowids, so its primary keys are collected into an
+        // ephemeral index instead.
+        Table::BTree(btree_table) if !btree_table.has_rowid => {
+            let mut index = Index::automatic_from_primary_key(
+                btree_table,
+                ("ephemeral_primary_key".to_string(), 0),
+                btree_table.primary_key_columns.len(),
+            )?;
+            index
+                .columns
+                .truncate(btree_table.primary_key_columns.len());
+            index.ephemeral = true;
+            index.num_key_columns = None;
+            let index = Arc::new(index);
+            let temp_cursor_id = program.alloc_cursor_id(CursorType::BTreeIndex(index.clone()));
+            let exprs = btree_table
+                .primary_key_column_positions()
+                .into_iter()
+                .map(|column| {
+                    table_references.joined_tables_mut()[0].mark_column_used(column);
+                    Expr::Column {
+                        database: None,
+                        table: internal_id,
+                        column,
+                        is_rowid_alias: false,
+                    }
+                })
+                .collect::<Vec<_>>();
+            (
+                exprs,
+                QueryDestination::EphemeralIndex {
+                    cursor_id: temp_cursor_id,
+                    index,
+                    is_delete: false,
+                },
+            )
+        }
+        _ => {
+            let table = Arc::new(BTreeTable {
+                root_page: 0, // Not relevant for ephemeral table definition
+                name: "ephemeral_scratch".to_string(),
+                has_rowid: true,
+                primary_key_columns: vec![],
+                columns: vec![Column {
+                    name: Some("rowid".to_string()),
+                    ty: Type::Integer,
+                    ty_str: "INTEGER".to_string(),
+                    primary_key: true,
+                    is_rowid_alias: false,
+                    notnull: true,
+                    default: None,
+                    unique: false,
+                    collation: None,
+                    hidden: false,
+                    generated: None,
+                }],
+                is_strict: false,
+                unique_sets: vec![],
+                foreign_keys: vec![],
+            });
+
+            let temp_cursor_id = program.alloc_cursor_id(CursorType::BTreeTable(table.clone()));
+            (
+                vec![Expr::RowId {
+                    database: None,
+                    table: internal_id,
+                }],
+                QueryDestination::EphemeralTable {
+                    cursor_id: temp_cursor_id,
+                    table,
+                },
+            )
+        }
+    };
+
+    let mut ephemeral_plan = SelectPlan {
+        table_references,
+        result_columns: result_column_exprs
+            .into_iter()
+            .map(|expr| ResultSetColumn {
+                expr,
+                alias: None,
+                contains_aggregates: false,
+            })
+            .collect(),
+        where_clause: where_terms, // original WHERE terms from the statement
+        group_by: None,            // N/A
+        order_by: vec![],          // N/A
+        aggregates: vec![],        // N/A
+        limit: None,               // N/A
+        query_destination,
+        join_order: vec![],
+        offset: None,
+        contains_constant_false_condition: false,
+        distinctness: super::plan::Distinctness::NonDistinct,
+        values: vec![],
+        window: None,
+    };
+
+    optimize_select_plan(&mut ephemeral_plan, schema)?;
+    let table = ephemeral_plan
+        .table_references
+        .joined_tables()
+        .first()
+        .unwrap();
+    // We do not need to emit an ephemeral plan if we are not going to loop over the table values
+    if matches!(table.op, Operation::Search(Search::RowidEq { .. })) {
+        // The WHERE clause is planned again as part of the statement's own plan.
+        *param_idx = param_idx_start;
+        Ok(None)
+    } else {
+        Ok(Some(ephemeral_plan))
+    }
+}
+
 fn build_scan_op(table: &Table, iter_dir: IterationDirection) -> Operation {
     match table {
         Table::BTree(_) => Operation::Scan(Scan::BTreeTable {
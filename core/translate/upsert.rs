@@ -4,7 +4,7 @@ use turso_parser::ast::{self, Upsert};
 
 use crate::{
     bail_parse_error,
-    error::SQLITE_CONSTRAINT_NOTNULL,
+    error::{SQLITE_CONSTRAINT_NOTNULL, SQLITE_CONSTRAINT_PRIMARYKEY},
     schema::{Index, IndexColumn, Schema, Table},
     translate::{
         emitter::{
@@ -14,15 +14,23 @@ use crate::{
             emit_returning_results, translate_expr, translate_expr_no_constant_opt, walk_expr_mut,
             NoConstantOptReason, ReturningValueRegisters,
         },
+        fkeys::{emit_fk_actions, emit_fk_checks, has_foreign_keys},
+        generated::{
+            emit_generated_columns, emit_generated_columns_not_null_checks, emit_table_column,
+        },
+        index::{emit_index_expr, emit_partial_index_check, terms_imply_partial_index_predicate},
         insert::{Insertion, ROWID_COLUMN},
         plan::ResultSetColumn,
+        planner::break_predicate_at_and_boundaries,
+        trigger::TriggerRow,
     },
-    util::normalize_ident,
+    util::{exprs_are_equivalent, normalize_ident},
     vdbe::{
         builder::ProgramBuilder,
-        insn::{IdxInsertFlags, InsertFlags, Insn},
+        insn::{CmpInsFlags, IdxInsertFlags, InsertFlags, Insn},
         BranchOffset,
     },
+    Connection,
 };
 
 /// A ConflictTarget is extracted from each ON CONFLICT target,
@@ -128,12 +136,30 @@ pub fn upsert_matches_index(upsert: &Upsert, index: &Index, table: &Table) -> bo
         return true;
     };
     // if not unique or column count differs, no match
-    if !index.unique || target.targets.len() != index.columns.len() {
+    if !index.unique || target.targets.len() != index.key_columns().len() {
         return false;
     }
+    // a partial index only matches if the target's WHERE clause implies the index predicate
+    if let Some(predicate) = &index.where_clause {
+        let Some(target_where) = &target.where_clause else {
+            return false;
+        };
+        let mut target_terms = Vec::new();
+        break_predicate_at_and_boundaries(target_where, &mut target_terms);
+        let target_terms = target_terms.iter().collect::<Vec<_>>();
+        if !terms_imply_partial_index_predicate(&target_terms, predicate) {
+            return false;
+        }
+    }
 
     let mut need: HashMap<KeySig, usize> = HashMap::new();
-    for ic in &index.columns {
+    // indexed expressions are matched by targets that are equivalent expressions
+    let mut need_exprs = index
+        .key_columns()
+        .iter()
+        .filter_map(|ic| ic.expr.as_deref())
+        .collect::<Vec<_>>();
+    for ic in index.key_columns().iter().filter(|ic| ic.expr.is_none()) {
         let sig = KeySig {
             name: normalize_ident(&ic.name).to_string(),
             coll: effective_collation_for_index_col(ic, table),
@@ -145,7 +171,19 @@ pub fn upsert_matches_index(upsert: &Upsert, index: &Index, table: &Table) -> bo
     for te in &target.targets {
         let tk = match extract_target_key(&te.expr) {
             Some(x) => x,
-            None => return false, // not a simple column ref
+            None => {
+                // not a simple column ref, so it must be one of the indexed expressions
+                match need_exprs
+                    .iter()
+                    .position(|expr| exprs_are_equivalent(expr, &te.expr))
+                {
+                    Some(pos) => {
+                        need_exprs.swap_remove(pos);
+                        continue;
+                    }
+                    None => return false,
+                }
+            }
         };
 
         // Candidate signatures for this target:
@@ -183,7 +221,7 @@ pub fn upsert_matches_index(upsert: &Upsert, index: &Index, table: &Table) -> bo
         }
     }
     // All targets matched exactly.
-    need.is_empty()
+    need.is_empty() && need_exprs.is_empty()
 }
 
 #[allow(clippy::too_many_arguments)]
@@ -194,15 +232,16 @@ pub fn upsert_matches_index(upsert: &Upsert, index: &Index, table: &Table) -> bo
 /// `ON CONFLICT ... DO UPDATE`.
 ///
 /// High-level flow:
-/// 1. Seek to the conflicting row by rowid and load the current row snapshot
-///    into a contiguous set of registers.
+/// 1. Seek to the conflicting row by rowid (or by primary key, for a WITHOUT ROWID
+///    table) and load the current row snapshot into a contiguous set of registers.
 /// 2. Optionally duplicate CURRENT into BEFORE* (for index rebuild and CDC).
 /// 3. Copy CURRENT into NEW, then evaluate SET expressions into NEW,
 ///    with all references to the target table columns rewritten to read from
 ///    the CURRENT registers (per SQLite semantics).
 /// 4. Enforce NOT NULL constraints and (if STRICT) type checks on NEW.
 /// 5. Rebuild indexes (delete keys using BEFORE, insert keys using NEW).
-/// 6. Rewrite the table row payload at the same rowid with NEW.
+/// 6. Rewrite the table row payload at the same rowid with NEW. The row of a WITHOUT ROWID
+///    table is deleted and inserted again, as its primary key may have changed.
 /// 7. Emit CDC rows and RETURNING output if requested.
 /// 8. Jump to `row_done_label`.
 ///
@@ -212,10 +251,11 @@ pub fn upsert_matches_index(upsert: &Upsert, index: &Index, table: &Table) -> bo
 pub fn emit_upsert(
     program: &mut ProgramBuilder,
     schema: &Schema,
+    database_id: usize,
     table: &Table,
     insertion: &Insertion,
     tbl_cursor_id: usize,
-    conflict_rowid_reg: usize,
+    conflict_key_reg: usize,
     set_pairs: &mut [(usize, Box<ast::Expr>)],
     where_clause: &mut Option<Box<ast::Expr>>,
     resolver: &Resolver,
@@ -223,29 +263,37 @@ pub fn emit_upsert(
     returning: &mut [ResultSetColumn],
     cdc_cursor_id: Option<usize>,
     row_done_label: BranchOffset,
+    connection: &Arc<Connection>,
 ) -> crate::Result<()> {
-    // Seek and snapshot current row
-    program.emit_insn(Insn::SeekRowid {
-        cursor_id: tbl_cursor_id,
-        src_reg: conflict_rowid_reg,
-        target_pc: row_done_label,
-    });
+    // Seek and snapshot current row.
+    // `conflict_key_reg` holds the rowid of the conflicting row, or the first of the
+    // registers holding its primary key for a WITHOUT ROWID table, whose rowid is NULL.
+    let without_rowid_pk = table
+        .btree()
+        .filter(|bt| !bt.has_rowid)
+        .map(|bt| bt.primary_key_column_positions());
+    let conflict_rowid_reg = if let Some(pk_positions) = &without_rowid_pk {
+        program.emit_insn(Insn::NotFound {
+            cursor_id: tbl_cursor_id,
+            target_pc: row_done_label,
+            record_reg: conflict_key_reg,
+            num_regs: pk_positions.len(),
+        });
+        let conflict_rowid_reg = program.alloc_register();
+        program.emit_null(conflict_rowid_reg, None);
+        conflict_rowid_reg
+    } else {
+        program.emit_insn(Insn::SeekRowid {
+            cursor_id: tbl_cursor_id,
+            src_reg: conflict_key_reg,
+            target_pc: row_done_label,
+        });
+        conflict_key_reg
+    };
     let num_cols = table.columns().len();
     let current_start = program.alloc_registers(num_cols);
-    for (i, col) in table.columns().iter().enumerate() {
-        if col.is_rowid_alias {
-            program.emit_insn(Insn::RowId {
-                cursor_id: tbl_cursor_id,
-                dest: current_start + i,
-            });
-        } else {
-            program.emit_insn(Insn::Column {
-                cursor_id: tbl_cursor_id,
-                column: i,
-                dest: current_start + i,
-                default: None,
-            });
-        }
+    for i in 0..num_cols {
+        emit_table_column(program, resolver, tbl_cursor_id, i, current_start + i)?;
     }
 
     // Keep BEFORE snapshot if needed
@@ -308,7 +356,8 @@ pub fn emit_upsert(
             NoConstantOptReason::RegisterReuse,
         )?;
         let col = &table.columns()[*col_idx];
-        if col.notnull && !col.is_rowid_alias {
+        // The primary key columns of a WITHOUT ROWID table are implicitly NOT NULL
+        if (col.notnull || (without_rowid_pk.is_some() && col.primary_key)) && !col.is_rowid_alias {
             program.emit_insn(Insn::HaltIfNull {
                 target_reg: new_start + *col_idx,
                 err_code: SQLITE_CONSTRAINT_NOTNULL,
@@ -317,6 +366,12 @@ pub fn emit_upsert(
         }
     }
 
+    // Generated columns are computed over the NEW image
+    if let Some(bt) = table.btree().filter(|bt| bt.has_generated_columns()) {
+        emit_generated_columns(program, resolver, &bt, new_start, conflict_rowid_reg)?;
+        emit_generated_columns_not_null_checks(program, &bt, new_start);
+    }
+
     // If STRICT, perform type checks on the NEW image
     if let Some(bt) = table.btree() {
         if bt.is_strict {
@@ -329,16 +384,123 @@ pub fn emit_upsert(
         }
     }
 
+    // A new primary key of a WITHOUT ROWID table must not collide with another row
+    if let Some(pk_positions) = &without_rowid_pk {
+        if set_pairs
+            .iter()
+            .any(|(col_idx, _)| pk_positions.contains(col_idx))
+        {
+            let ok_label = program.allocate_label();
+            let conflict_label = program.allocate_label();
+            let new_key_reg = program.alloc_registers(pk_positions.len());
+            for (i, pos) in pk_positions.iter().enumerate() {
+                program.emit_insn(Insn::Copy {
+                    src_reg: new_start + pos,
+                    dst_reg: new_key_reg + i,
+                    extra_amount: 0,
+                });
+                program.emit_insn(Insn::Ne {
+                    lhs: new_key_reg + i,
+                    rhs: conflict_key_reg + i,
+                    target_pc: conflict_label,
+                    flags: CmpInsFlags::default(),
+                    collation: program.curr_collation(),
+                });
+            }
+            program.emit_insn(Insn::Goto {
+                target_pc: ok_label,
+            });
+            program.preassign_label_to_next_insn(conflict_label);
+            program.emit_insn(Insn::NoConflict {
+                cursor_id: tbl_cursor_id,
+                target_pc: ok_label,
+                record_reg: new_key_reg,
+                num_regs: pk_positions.len(),
+            });
+            program.emit_insn(Insn::Halt {
+                err_code: SQLITE_CONSTRAINT_PRIMARYKEY,
+                description: pk_positions
+                    .iter()
+                    .map(|pos| {
+                        format!(
+                            "{}.{}",
+                            table.get_name(),
+                            table.columns()[*pos].name.as_deref().unwrap_or_default()
+                        )
+                    })
+                    .collect::<Vec<_>>()
+                    .join(", "),
+            });
+            program.preassign_label_to_next_insn(ok_label);
+            // The check moved the cursor off the conflicting row
+            program.emit_insn(Insn::NotFound {
+                cursor_id: tbl_cursor_id,
+                target_pc: row_done_label,
+                record_reg: conflict_key_reg,
+                num_regs: pk_positions.len(),
+            });
+        }
+    }
+
+    // The table whose foreign key constraints are checked, if any
+    let fk_table = table
+        .btree()
+        .filter(|bt| has_foreign_keys(schema, connection, bt));
+    let old_row = TriggerRow::from_registers(conflict_rowid_reg, current_start, table.columns());
+    let new_row = TriggerRow::from_registers(conflict_rowid_reg, new_start, table.columns());
+    let changed_columns = set_pairs
+        .iter()
+        .map(|(col_idx, _)| *col_idx)
+        .collect::<Vec<_>>();
+    if let Some(bt) = &fk_table {
+        emit_fk_checks(
+            program,
+            schema,
+            database_id,
+            connection,
+            bt,
+            Some(&old_row),
+            Some(&new_row),
+            &changed_columns,
+        )?;
+    }
+
     // Rebuild indexes: remove keys corresponding to BEFORE and insert keys for NEW.
+    // For a partial index, only the images that satisfy its WHERE clause have keys.
     if let Some(before) = before_start {
+        let btree_table = table.btree().expect("UPSERT target must be a btree table");
         for (idx_name, _root, idx_cid) in idx_cursors {
             let idx_meta = schema
                 .get_index(table.get_name(), idx_name)
                 .expect("index exists");
             let k = idx_meta.columns.len();
+            // The indexes of a WITHOUT ROWID table store its primary key instead of a rowid
+            let n = k + idx_meta.has_rowid as usize;
 
-            let del = program.alloc_registers(k + 1);
+            let skip_delete_label = program.allocate_label();
+            emit_partial_index_check(
+                program,
+                resolver,
+                idx_meta,
+                &btree_table,
+                |_, pos| Ok(before + pos),
+                conflict_rowid_reg,
+                skip_delete_label,
+            )?;
+            let del = program.alloc_registers(n);
             for (i, ic) in idx_meta.columns.iter().enumerate() {
+                if let Some(expr) = &ic.expr {
+                    emit_index_expr(
+                        program,
+                        resolver,
+                        &btree_table,
+                        expr,
+                        |_, pos| Ok(before + pos),
+                        conflict_rowid_reg,
+                        del + i,
+                    )?;
+                    continue;
+                }
                 let (ci, _) = table.get_column_by_name(&ic.name).unwrap();
                 program.emit_insn(Insn::Copy {
                     src_reg: before + ci,
@@ -346,20 +508,45 @@ pub fn emit_upsert(
                     extra_amount: 0,
                 });
             }
-            program.emit_insn(Insn::Copy {
-                src_reg: conflict_rowid_reg,
-                dst_reg: del + k,
-                extra_amount: 0,
-            });
+            if idx_meta.has_rowid {
+                program.emit_insn(Insn::Copy {
+                    src_reg: conflict_rowid_reg,
+                    dst_reg: del + k,
+                    extra_amount: 0,
+                });
+            }
             program.emit_insn(Insn::IdxDelete {
                 start_reg: del,
-                num_regs: k + 1,
+                num_regs: n,
                 cursor_id: *idx_cid,
                 raise_error_if_no_matching_entry: false,
             });
+            program.preassign_label_to_next_insn(skip_delete_label);
 
-            let ins = program.alloc_registers(k + 1);
+            let skip_insert_label = program.allocate_label();
+            emit_partial_index_check(
+                program,
+                resolver,
+                idx_meta,
+                &btree_table,
+                |_, pos| Ok(new_start + pos),
+                conflict_rowid_reg,
+                skip_insert_label,
+            )?;
+            let ins = program.alloc_registers(n);
             for (i, ic) in idx_meta.columns.iter().enumerate() {
+                if let Some(expr) = &ic.expr {
+                    emit_index_expr(
+                        program,
+                        resolver,
+                        &btree_table,
+                        expr,
+                        |_, pos| Ok(new_start + pos),
+                        conflict_rowid_reg,
+                        ins + i,
+                    )?;
+                    continue;
+                }
                 let (ci, _) = table.get_column_by_name(&ic.name).unwrap();
                 program.emit_insn(Insn::Copy {
                     src_reg: new_start + ci,
@@ -367,16 +554,18 @@ pub fn emit_upsert(
                     extra_amount: 0,
                 });
             }
-            program.emit_insn(Insn::Copy {
-                src_reg: conflict_rowid_reg,
-                dst_reg: ins + k,
-                extra_amount: 0,
-            });
+            if idx_meta.has_rowid {
+                program.emit_insn(Insn::Copy {
+                    src_reg: conflict_rowid_reg,
+                    dst_reg: ins + k,
+                    extra_amount: 0,
+                });
+            }
 
             let rec = program.alloc_register();
             program.emit_insn(Insn::MakeRecord {
                 start_reg: ins,
-                count: k + 1,
+                count: n,
                 dest_reg: rec,
                 index_name: Some((*idx_name).clone()),
                 affinity_str: None,
@@ -385,35 +574,82 @@ pub fn emit_upsert(
                 cursor_id: *idx_cid,
                 record_reg: rec,
                 unpacked_start: Some(ins),
-                unpacked_count: Some((k + 1) as u16),
+                unpacked_count: Some(n as u16),
                 flags: IdxInsertFlags::new().nchange(true),
             });
+            program.preassign_label_to_next_insn(skip_insert_label);
         }
     }
 
     // Write table row (same rowid, new payload)
     let rec = program.alloc_register();
 
-    let affinity_str = table
-        .columns()
+    // The records of a WITHOUT ROWID table hold the primary key columns first, and VIRTUAL
+    // generated columns are not stored at all
+    let (record_start, record_column_positions) = match table.btree() {
+        Some(bt) if !bt.stores_columns_in_table_order() => {
+            let record_column_positions = bt.record_column_positions();
+            let record_start = program.alloc_registers(record_column_positions.len());
+            for (i, pos) in record_column_positions.iter().enumerate() {
+                program.emit_insn(Insn::Copy {
+                    src_reg: new_start + pos,
+                    dst_reg: record_start + i,
+                    extra_amount: 0,
+                });
+            }
+            (record_start, record_column_positions)
+        }
+        _ => (new_start, (0..num_cols).collect::<Vec<_>>()),
+    };
+    let affinity_str = record_column_positions
         .iter()
-        .map(|col| col.affinity().aff_mask())
+        .map(|pos| table.columns()[*pos].affinity().aff_mask())
         .collect::<String>();
-
     program.emit_insn(Insn::MakeRecord {
-        start_reg: new_start,
-        count: num_cols,
+        start_reg: record_start,
+        count: record_column_positions.len(),
         dest_reg: rec,
         index_name: None,
         affinity_str: Some(affinity_str),
     });
-    program.emit_insn(Insn::Insert {
-        cursor: tbl_cursor_id,
-        key_reg: conflict_rowid_reg,
-        record_reg: rec,
-        flag: InsertFlags::new(),
-        table_name: table.get_name().to_string(),
-    });
+
+    if without_rowid_pk.is_some() {
+        program.emit_insn(Insn::Delete {
+            cursor_id: tbl_cursor_id,
+            table_name: table.get_name().to_string(),
+        });
+        program.emit_insn(Insn::IdxInsert {
+            cursor_id: tbl_cursor_id,
+            record_reg: rec,
+            unpacked_start: Some(record_start),
+            unpacked_count: Some(record_column_positions.len() as u16),
+            // The row was already counted as changed by the Delete above
+            flags: IdxInsertFlags::new(),
+        });
+    } else {
+        program.emit_insn(Insn::Insert {
+            cursor: tbl_cursor_id,
+            key_reg: conflict_rowid_reg,
+            record_reg: rec,
+            flag: InsertFlags::new(),
+            table_name: table.get_name().to_string(),
+        });
+    }
+
+    if let Some(bt) = &fk_table {
+        emit_fk_actions(
+            program,
+            schema,
+            database_id,
+            resolver.symbol_table,
+            connection,
+            bt,
+            &old_row,
+            Some(&new_row),
+            &changed_columns,
+            row_done_label,
+        )?;
+    }
 
     if let Some(cdc_id) = cdc_cursor_id {
         let after_rec = if program.capture_data_changes_mode().has_after() {
@@ -501,6 +737,9 @@ pub fn collect_set_clauses_for_upsert(
             let Some(idx) = lookup.get(&normalize_ident(cn.as_str())) else {
                 bail_parse_error!("no such column: {}", cn);
             };
+            if table.columns()[*idx].is_generated() {
+                bail_parse_error!("cannot UPDATE generated column \"{}\"", cn.as_str());
+            }
             if let Some(existing) = out.iter_mut().find(|(i, _)| *i == *idx) {
                 existing.1 = e;
             } else {
@@ -0,0 +1,48 @@
+use turso_parser::ast;
+
+use crate::{
+    bail_parse_error,
+    schema::Schema,
+    translate::{emitter::Resolver, expr::translate_expr},
+    util::normalize_ident,
+    vdbe::{builder::ProgramBuilder, insn::Insn},
+    Result, SymbolTable,
+};
+
+/// Translate `VACUUM [schema] [INTO filename]`.
+///
+/// `VACUUM` rebuilds the database into a fresh file and copies it back over the original
+/// within a write transaction. `VACUUM INTO` only needs a read transaction: it writes a compacted
+/// copy of the snapshot seen by the connection to a new file.
+pub fn translate_vacuum(
+    name: Option<ast::Name>,
+    into: Option<Box<ast::Expr>>,
+    schema: &Schema,
+    syms: &SymbolTable,
+    mut program: ProgramBuilder,
+) -> Result<ProgramBuilder> {
+    if let Some(name) = name {
+        let name = normalize_ident(name.as_str());
+        if name != "main" {
+            bail_parse_error!(
+                "VACUUM is only supported on the main database, not {}",
+                name
+            );
+        }
+    }
+    let into = match into {
+        Some(expr) => {
+            let reg = program.alloc_register();
+            let resolver = Resolver::new(schema, syms);
+            translate_expr(&mut program, None, &expr, reg, &resolver)?;
+            program.begin_read_operation();
+            Some(reg)
+        }
+        None => {
+            program.begin_write_operation();
+            None
+        }
+    };
+    program.emit_insn(Insn::Vacuum { db: 0, into });
+    Ok(program)
+}
@@ -22,7 +22,9 @@ pub fn emit_values(
         QueryDestination::CoroutineYield { yield_reg, .. } => {
             emit_values_in_subquery(program, plan, &t_ctx.resolver, yield_reg)?
         }
-        QueryDestination::EphemeralIndex { .. } => emit_toplevel_values(program, plan, t_ctx)?,
+        QueryDestination::EphemeralIndex { .. } | QueryDestination::Discard => {
+            emit_toplevel_values(program, plan, t_ctx)?
+        }
         QueryDestination::EphemeralTable { .. } => unreachable!(),
     };
     Ok(reg_result_cols_start)
@@ -167,6 +169,7 @@ fn emit_values_to_destination(
         QueryDestination::EphemeralIndex { .. } => {
             emit_values_to_index(program, plan, start_reg, row_len);
         }
+        QueryDestination::Discard => {}
         QueryDestination::EphemeralTable { .. } => unreachable!(),
     }
 }
@@ -2,6 +2,7 @@ use crate::schema::{Schema, DBSP_TABLE_PREFIX};
 use crate::storage::pager::CreateBTreeFlags;
 use crate::translate::emitter::Resolver;
 use crate::translate::schema::{emit_schema_entry, SchemaEntryType, SQLITE_TABLEID};
+use crate::translate::trigger::emit_drop_trigger;
 use crate::util::normalize_ident;
 use crate::vdbe::builder::{CursorType, ProgramBuilder};
 use crate::vdbe::insn::{CmpInsFlags, Cookie, Insn, RegisterOrLiteral};
@@ -76,6 +77,7 @@ pub fn translate_create_materialized_view(
         has_rowid: true,
         is_strict: false,
         unique_sets: vec![],
+        foreign_keys: vec![],
     });
 
     // Allocate a cursor for writing to the view's btree during population
@@ -157,7 +159,7 @@ pub fn translate_create_materialized_view(
 
     // Parse schema to load the new view and DBSP state table
     program.emit_insn(Insn::ParseSchema {
-        db: sqlite_schema_cursor_id,
+        db: 0,
         where_clause: Some(format!(
             "name = '{normalized_view_name}' OR name = '{dbsp_table_name}'"
         )),
@@ -184,8 +186,10 @@ fn create_materialized_view_to_str(view_name: &str, select_stmt: &ast::Select) -
     format!("CREATE MATERIALIZED VIEW {view_name} AS {select_stmt}")
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn translate_create_view(
     schema: &Schema,
+    database_id: usize,
     view_name: &str,
     select_stmt: &ast::Select,
     _columns: &[ast::IndexedColumn],
@@ -215,7 +219,7 @@ pub fn translate_create_view(
     program.emit_insn(Insn::OpenWrite {
         cursor_id: sqlite_schema_cursor_id,
         root_page: 1usize.into(),
-        db: 0,
+        db: database_id,
     });
 
     // Add the view entry to sqlite_schema
@@ -234,12 +238,12 @@ pub fn translate_create_view(
 
     // Parse schema to load the new view
     program.emit_insn(Insn::ParseSchema {
-        db: sqlite_schema_cursor_id,
+        db: database_id,
         where_clause: Some(format!("name = '{normalized_view_name}'")),
     });
 
     program.emit_insn(Insn::SetCookie {
-        db: 0,
+        db: database_id,
         cookie: Cookie::SchemaVersion,
         value: (schema.schema_version + 1) as i32,
         p5: 0,
@@ -254,6 +258,7 @@ fn create_view_to_str(view_name: &str, select_stmt: &ast::Select) -> String {
 
 pub fn translate_drop_view(
     schema: &Schema,
+    database_id: usize,
     view_name: &str,
     if_exists: bool,
     mut program: ProgramBuilder,
@@ -284,7 +289,7 @@ pub fn translate_drop_view(
                 program.emit_insn(Insn::Destroy {
                     root: btree_table.root_page,
                     former_root_reg: 0, // No autovacuum
-                    is_temp: 0,
+                    is_temp: database_id,
                 });
             }
         }
@@ -297,7 +302,7 @@ pub fn translate_drop_view(
     program.emit_insn(Insn::OpenWrite {
         cursor_id: sqlite_schema_cursor_id,
         root_page: 1usize.into(),
-        db: 0,
+        db: database_id,
     });
 
     // Allocate registers for searching
@@ -373,9 +378,14 @@ pub fn translate_drop_view(
 
     program.preassign_label_to_next_insn(end_loop_label);
 
+    // Drop the triggers attached to the view
+    for trigger in schema.get_triggers_for_table(&normalized_view_name) {
+        emit_drop_trigger(&mut program, schema, database_id, &trigger.name);
+    }
+
     // Remove the view from the in-memory schema
     program.emit_insn(Insn::DropView {
-        db: 0,
+        db: database_id,
         view_name: normalized_view_name.clone(),
     });
 
@@ -386,7 +396,7 @@ pub fn translate_drop_view(
         value: (schema.schema_version + 1) as i64,
     });
     program.emit_insn(Insn::SetCookie {
-        db: 0,
+        db: database_id,
         cookie: Cookie::SchemaVersion,
         value: (schema.schema_version + 1) as i32,
         p5: 1, // update version
@@ -0,0 +1,955 @@
+//! Window functions.
+//!
+//! A SELECT with window functions is evaluated in three steps:
+//! 1. The main loop inserts a row into a sorter for every row produced by the FROM clause.
+//!    The row is keyed by the PARTITION BY and ORDER BY terms of the window, followed by the
+//!    arguments of the window functions and the columns referenced by the result columns.
+//! 2. The sorted rows are fed into a window cursor, which buffers one partition at a time and
+//!    computes the built-in window functions (row_number(), lag(), ...) once it is complete.
+//! 3. For every row of a complete partition, the aggregate window functions are computed by
+//!    iterating over the frame of the row, and the result row is emitted (or inserted into the
+//!    ORDER BY sorter).
+//!
+//! Aggregate queries, and queries whose window functions use different PARTITION BY or ORDER
+//! BY clauses, are split into nested subqueries that each need a single pass, see [plan_windows].
+
+use turso_parser::ast::{
+    self, Expr, FrameBound, FrameClause, FrameExclude, FrameMode, SortOrder, TableInternalId,
+};
+
+use crate::{
+    function::{AggFunc, ExtFunc, Func, WindowFunc},
+    schema::{PseudoCursorType, Schema},
+    util::{exprs_are_equivalent, normalize_ident},
+    vdbe::{
+        builder::{CursorType, ProgramBuilder, TableRefIdCounter},
+        insn::Insn,
+        window::{WindowFrame, WindowFrameBound, WindowFunctionSpec},
+        BranchOffset, CursorID,
+    },
+    Result, SymbolTable,
+};
+
+use super::{
+    aggregation::{translate_aggregation_step, AggArgumentSource},
+    emitter::TranslateCtx,
+    expr::{translate_expr, walk_expr, walk_expr_mut, WalkControl},
+    order_by::{order_by_sorter_insert, sort_key_collation, sorter_insert},
+    plan::{
+        Aggregate, Distinctness, JoinOrderMember, JoinedTable, OuterQueryReference,
+        QueryDestination, ResultSetColumn, SelectPlan, TableReferences, Window, WindowFunction,
+        WindowFunctionKind,
+    },
+    planner::resolve_aggregates,
+    result_row::emit_select_result,
+};
+
+// Metadata for evaluating window functions
+#[derive(Debug)]
+pub struct WindowMetadata {
+    // cursor id for the Sorter table where the rows produced by the main loop are sorted
+    pub sort_cursor: CursorID,
+    // cursor id for the window cursor that buffers the rows of a partition
+    pub window_cursor: CursorID,
+    // register where the sorter data is inserted and later retrieved from
+    pub reg_sorter_data: usize,
+    // number of columns in the rows inserted into the sorter. The window cursor appends
+    // the values of the window functions after these columns.
+    pub row_width: usize,
+    // column of the first argument of each window function
+    pub arg_columns: Vec<usize>,
+    // column of the first passthrough expression
+    pub passthrough_start: usize,
+}
+
+/// Replaces the references to the windows of the WINDOW clause in `expr`,
+/// e.g. `OVER w` or `OVER (w ORDER BY x)`, with the window definitions they refer to.
+pub fn resolve_window_names(expr: &mut Expr, window_clause: &[ast::WindowDef]) -> Result<()> {
+    walk_expr_mut(expr, &mut |expr: &mut Expr| -> Result<()> {
+        if let Expr::FunctionCall { filter_over, .. } | Expr::FunctionCallStar { filter_over, .. } =
+            expr
+        {
+            if let Some(over) = &mut filter_over.over_clause {
+                let window = match over {
+                    ast::Over::Name(name) => find_window(name, window_clause, 0)?,
+                    ast::Over::Window(window) => resolve_window(window, window_clause, 0)?,
+                };
+                *over = ast::Over::Window(window);
+            }
+        }
+        Ok(())
+    })
+}
+
+fn find_window(
+    name: &ast::Name,
+    window_clause: &[ast::WindowDef],
+    depth: usize,
+) -> Result<ast::Window> {
+    let name = normalize_ident(name.as_str());
+    let def = window_clause
+        .iter()
+        .find(|def| normalize_ident(def.name.as_str()) == name);
+    match def {
+        // A window that is (indirectly) based on itself can never be resolved.
+        Some(def) if depth < window_clause.len() => {
+            resolve_window(&def.window, window_clause, depth + 1)
+        }
+        _ => crate::bail_parse_error!("no such window: {}", name),
+    }
+}
+
+fn resolve_window(
+    window: &ast::Window,
+    window_clause: &[ast::WindowDef],
+    depth: usize,
+) -> Result<ast::Window> {
+    let Some(base_name) = &window.base else {
+        return Ok(window.clone());
+    };
+    let base = find_window(base_name, window_clause, depth)?;
+    let base_name = base_name.as_str();
+    if !window.partition_by.is_empty() {
+        crate::bail_parse_error!("cannot override PARTITION clause of window: {}", base_name);
+    }
+    if !window.order_by.is_empty() && !base.order_by.is_empty() {
+        crate::bail_parse_error!("cannot override ORDER BY clause of window: {}", base_name);
+    }
+    if base.frame_clause.is_some() {
+        crate::bail_parse_error!(
+            "cannot override frame specification of window: {}",
+            base_name
+        );
+    }
+    Ok(ast::Window {
+        base: None,
+        partition_by: base.partition_by,
+        order_by: if window.order_by.is_empty() {
+            base.order_by
+        } else {
+            window.order_by.clone()
+        },
+        frame_clause: window.frame_clause.clone(),
+    })
+}
+
+/// Returns the window definition of `expr` if it is a window function call.
+fn window_definition(expr: &Expr) -> Option<&ast::Window> {
+    match expr {
+        Expr::FunctionCall { filter_over, .. } | Expr::FunctionCallStar { filter_over, .. } => {
+            match &filter_over.over_clause {
+                Some(ast::Over::Window(window)) => Some(window),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Returns the window function calls in the result columns and ORDER BY terms of a SELECT,
+/// without duplicates, in the order they appear in the query.
+fn window_function_calls<'a>(
+    result_columns: &'a [ResultSetColumn],
+    order_by: &'a [(Box<Expr>, SortOrder)],
+) -> Result<Vec<&'a Expr>> {
+    let mut calls: Vec<&'a Expr> = vec![];
+    let exprs = result_columns
+        .iter()
+        .map(|rc| &rc.expr)
+        .chain(order_by.iter().map(|(expr, _)| expr.as_ref()));
+    for expr in exprs {
+        walk_expr(expr, &mut |expr: &'a Expr| -> Result<WalkControl> {
+            if window_definition(expr).is_none() {
+                return Ok(WalkControl::Continue);
+            }
+            if !calls.iter().any(|call| exprs_are_equivalent(call, expr)) {
+                calls.push(expr);
+            }
+            Ok(WalkControl::SkipChildren)
+        })?;
+    }
+    Ok(calls)
+}
+
+/// Plans the window functions of a SELECT whose window names have already been resolved
+/// with [resolve_window_names].
+///
+/// A single pass over the sorted rows of the FROM clause can only compute window functions
+/// that share one PARTITION BY/ORDER BY, and only on rows that have not been aggregated. Any
+/// other SELECT is split into nested FROM clause subqueries: the innermost one evaluates the
+/// FROM, WHERE, GROUP BY and HAVING clauses and returns the values the window functions and
+/// result columns depend on, and every enclosing one computes the window functions of one
+/// PARTITION BY/ORDER BY, passing the columns of its subquery through.
+pub fn plan_windows(
+    mut plan: SelectPlan,
+    schema: &Schema,
+    syms: &SymbolTable,
+    table_ref_counter: &mut TableRefIdCounter,
+) -> Result<SelectPlan> {
+    let calls = window_function_calls(&plan.result_columns, &plan.order_by)?;
+    if calls.is_empty() {
+        return Ok(plan);
+    }
+    let mut specs: Vec<Vec<Expr>> = vec![];
+    for call in calls {
+        let window = window_definition(call).expect("window function must have a window");
+        let spec = specs.iter_mut().find(|spec| {
+            let definition =
+                window_definition(&spec[0]).expect("window function must have a window");
+            window.partition_by == definition.partition_by && window.order_by == definition.order_by
+        });
+        match spec {
+            Some(spec) => spec.push(call.clone()),
+            None => specs.push(vec![call.clone()]),
+        }
+    }
+
+    let mut leaves: Vec<Expr> = vec![];
+    let exprs = plan
+        .result_columns
+        .iter()
+        .map(|rc| &rc.expr)
+        .chain(plan.order_by.iter().map(|(expr, _)| expr.as_ref()));
+    for expr in exprs {
+        collect_window_leaves(expr, &mut leaves, schema, syms)?;
+    }
+    let mut aggregates = vec![];
+    let mut is_aggregate = plan.group_by.is_some() || !plan.aggregates.is_empty();
+    for leaf in &leaves {
+        is_aggregate |= resolve_aggregates(schema, syms, leaf, &mut aggregates)?;
+    }
+    if specs.len() == 1 && !is_aggregate {
+        plan.window = plan_window(&plan, syms)?;
+        return Ok(plan);
+    }
+
+    // The innermost subquery keeps the FROM, WHERE, GROUP BY and HAVING clauses of the SELECT.
+    // Its aggregates are collected again, since the ones in the arguments, PARTITION BY and
+    // ORDER BY of the window functions were skipped by the planner.
+    if leaves.is_empty() {
+        leaves.push(Expr::Literal(ast::Literal::Numeric("1".to_string())));
+    }
+    let outer_query_refs = plan.table_references.outer_query_refs().to_vec();
+    let result_columns = std::mem::take(&mut plan.result_columns)
+        .into_iter()
+        .map(|rc| {
+            let alias = rc
+                .name(&plan.table_references)
+                .map(String::from)
+                .unwrap_or_else(|| rc.expr.to_string());
+            (rc.expr, alias)
+        })
+        .collect::<Vec<_>>();
+    let order_by = std::mem::take(&mut plan.order_by);
+    let distinctness = std::mem::replace(&mut plan.distinctness, Distinctness::NonDistinct);
+    let coroutine = || QueryDestination::CoroutineYield {
+        yield_reg: usize::MAX, // will be set later in bytecode emission
+        coroutine_implementation_start: BranchOffset::Placeholder, // will be set later in bytecode emission
+    };
+    let query_destination = std::mem::replace(&mut plan.query_destination, coroutine());
+    plan.aggregates = vec![];
+    for leaf in &leaves {
+        let contains_aggregates = resolve_aggregates(schema, syms, leaf, &mut plan.aggregates)?;
+        plan.result_columns.push(ResultSetColumn {
+            expr: leaf.clone(),
+            alias: None,
+            contains_aggregates,
+        });
+    }
+    if let Some(having) = plan
+        .group_by
+        .as_ref()
+        .and_then(|group_by| group_by.having.as_ref())
+    {
+        for expr in having {
+            resolve_aggregates(schema, syms, expr, &mut plan.aggregates)?;
+        }
+    }
+
+    // Each enclosing subquery refers to the columns of the one it reads from by their position:
+    // the leaves first, followed by the window functions computed so far.
+    let mut columns = leaves;
+    for spec in &specs[..specs.len() - 1] {
+        let table_id = table_ref_counter.next();
+        let mut result_columns: Vec<ResultSetColumn> = (0..columns.len())
+            .map(|column| ResultSetColumn {
+                expr: Expr::Column {
+                    database: None,
+                    table: table_id,
+                    column,
+                    is_rowid_alias: false,
+                },
+                alias: None,
+                contains_aggregates: false,
+            })
+            .collect();
+        for call in spec {
+            let mut expr = call.clone();
+            replace_window_columns(&mut expr, table_id, &columns)?;
+            result_columns.push(ResultSetColumn {
+                expr,
+                alias: None,
+                contains_aggregates: false,
+            });
+        }
+        columns.extend(spec.iter().cloned());
+        plan = window_subquery_plan(
+            plan,
+            table_id,
+            outer_query_refs.clone(),
+            result_columns,
+            vec![],
+            Distinctness::NonDistinct,
+            coroutine(),
+            syms,
+        )?;
+    }
+
+    // The outermost SELECT computes the window functions of the last PARTITION BY/ORDER BY and
+    // keeps the names of the original result columns.
+    let table_id = table_ref_counter.next();
+    let mut final_columns = Vec::with_capacity(result_columns.len());
+    for (mut expr, alias) in result_columns {
+        replace_window_columns(&mut expr, table_id, &columns)?;
+        final_columns.push(ResultSetColumn {
+            expr,
+            alias: Some(alias),
+            contains_aggregates: false,
+        });
+    }
+    let mut final_order_by = Vec::with_capacity(order_by.len());
+    for (mut expr, order) in order_by {
+        replace_window_columns(&mut expr, table_id, &columns)?;
+        final_order_by.push((expr, order));
+    }
+    window_subquery_plan(
+        plan,
+        table_id,
+        outer_query_refs,
+        final_columns,
+        final_order_by,
+        distinctness,
+        query_destination,
+        syms,
+    )
+}
+
+/// Collects the largest subexpressions of `expr` that are not window functions but depend on
+/// the rows of the FROM clause, i.e. reference a column, contain an aggregate or a subquery.
+/// These are the values a SELECT split by [plan_windows] returns from its innermost subquery.
+fn collect_window_leaves(
+    expr: &Expr,
+    leaves: &mut Vec<Expr>,
+    schema: &Schema,
+    syms: &SymbolTable,
+) -> Result<()> {
+    walk_expr(expr, &mut |expr: &Expr| -> Result<WalkControl> {
+        if contains_window_function(expr)? {
+            return Ok(WalkControl::Continue);
+        }
+        let mut references_rows = false;
+        walk_expr(expr, &mut |expr: &Expr| -> Result<WalkControl> {
+            references_rows |= matches!(
+                expr,
+                Expr::Column { .. }
+                    | Expr::RowId { .. }
+                    | Expr::Exists(_)
+                    | Expr::Subquery(_)
+                    | Expr::InSelect { .. }
+                    | Expr::SubqueryResult { .. }
+            );
+            Ok(WalkControl::Continue)
+        })?;
+        references_rows |= resolve_aggregates(schema, syms, expr, &mut vec![])?;
+        if references_rows && !leaves.iter().any(|leaf| exprs_are_equivalent(leaf, expr)) {
+            leaves.push(expr.clone());
+        }
+        Ok(WalkControl::SkipChildren)
+    })?;
+    Ok(())
+}
+
+fn contains_window_function(expr: &Expr) -> Result<bool> {
+    let mut found = false;
+    walk_expr(expr, &mut |expr: &Expr| -> Result<WalkControl> {
+        found |= window_definition(expr).is_some();
+        Ok(WalkControl::Continue)
+    })?;
+    Ok(found)
+}
+
+/// Replaces the subexpressions of `expr` that are equivalent to one of `columns` with a
+/// reference to the column at the same position of the subquery `table`.
+fn replace_window_columns(expr: &mut Expr, table: TableInternalId, columns: &[Expr]) -> Result<()> {
+    walk_expr_mut(expr, &mut |expr: &mut Expr| -> Result<()> {
+        if let Some(column) = columns.iter().position(|c| exprs_are_equivalent(c, expr)) {
+            *expr = Expr::Column {
+                database: None,
+                table,
+                column,
+                is_rowid_alias: false,
+            };
+        }
+        Ok(())
+    })
+}
+
+/// Builds a SELECT that reads `subplan` as a FROM clause subquery and computes the window
+/// functions in `result_columns` and `order_by`.
+#[allow(clippy::too_many_arguments)]
+fn window_subquery_plan(
+    subplan: SelectPlan,
+    table_id: TableInternalId,
+    outer_query_refs: Vec<OuterQueryReference>,
+    result_columns: Vec<ResultSetColumn>,
+    order_by: Vec<(Box<Expr>, SortOrder)>,
+    distinctness: Distinctness,
+    query_destination: QueryDestination,
+    syms: &SymbolTable,
+) -> Result<SelectPlan> {
+    let mut table =
+        JoinedTable::new_subquery(format!("window_{table_id}"), subplan, None, table_id);
+    for column in 0..table.columns().len() {
+        table.mark_column_used(column);
+    }
+    let mut plan = SelectPlan {
+        join_order: vec![JoinOrderMember {
+            table_id,
+            original_idx: 0,
+            is_outer: false,
+        }],
+        table_references: TableReferences::new(vec![table], outer_query_refs),
+        result_columns,
+        where_clause: vec![],
+        group_by: None,
+        order_by,
+        aggregates: vec![],
+        limit: None,
+        offset: None,
+        contains_constant_false_condition: false,
+        query_destination,
+        distinctness,
+        values: vec![],
+        window: None,
+    };
+    plan.window = plan_window(&plan, syms)?;
+    Ok(plan)
+}
+
+/// Collects the window functions of a SELECT whose window functions all share the same
+/// PARTITION BY and ORDER BY, and which has no aggregates. See [plan_windows].
+fn plan_window(plan: &SelectPlan, syms: &SymbolTable) -> Result<Option<Window>> {
+    let calls = window_function_calls(&plan.result_columns, &plan.order_by)?;
+    if calls.is_empty() {
+        return Ok(None);
+    }
+    let mut functions = Vec::with_capacity(calls.len());
+    for call in calls {
+        let window = window_definition(call).expect("window function must have a window");
+        validate_frame(window)?;
+        functions.push(WindowFunction {
+            original_expr: call.clone(),
+            func: resolve_window_function(call, syms)?,
+        });
+    }
+
+    // Every column referenced by the result columns is carried through the sorter,
+    // since the result columns are evaluated after the main loop.
+    let mut passthrough: Vec<Expr> = vec![];
+    let exprs = plan
+        .result_columns
+        .iter()
+        .map(|rc| &rc.expr)
+        .chain(plan.order_by.iter().map(|(expr, _)| expr.as_ref()));
+    for expr in exprs {
+        walk_expr(expr, &mut |expr: &Expr| -> Result<WalkControl> {
+            if matches!(expr, Expr::Column { .. } | Expr::RowId { .. })
+                && !passthrough.iter().any(|e| exprs_are_equivalent(e, expr))
+            {
+                passthrough.push(expr.clone());
+            }
+            Ok(WalkControl::Continue)
+        })?;
+    }
+
+    Ok(Some(Window {
+        functions,
+        passthrough,
+    }))
+}
+
+/// Re-collects the window function calls of `plan` after the optimizer has rewritten the
+/// result columns and ORDER BY terms, so that the calls can still be matched against them.
+pub fn refresh_window_exprs(plan: &mut SelectPlan) -> Result<()> {
+    if plan.window.is_none() {
+        return Ok(());
+    }
+    let calls: Vec<Expr> = window_function_calls(&plan.result_columns, &plan.order_by)?
+        .into_iter()
+        .cloned()
+        .collect();
+    let window = plan.window.as_mut().unwrap();
+    assert_eq!(
+        calls.len(),
+        window.functions.len(),
+        "window functions changed during optimization"
+    );
+    for (function, call) in window.functions.iter_mut().zip(calls) {
+        function.original_expr = call;
+    }
+    Ok(())
+}
+
+fn resolve_window_function(call: &Expr, syms: &SymbolTable) -> Result<WindowFunctionKind> {
+    let (name, args, distinct, filter_over, order_by) = match call {
+        Expr::FunctionCall {
+            name,
+            args,
+            distinctness,
+            filter_over,
+            order_by,
+        } => (
+            name,
+            args.len(),
+            distinctness.is_some(),
+            filter_over,
+            order_by.len(),
+        ),
+        Expr::FunctionCallStar { name, filter_over } => (name, 0, false, filter_over, 0),
+        _ => unreachable!("window function is not a function call"),
+    };
+    if distinct {
+        crate::bail_parse_error!("DISTINCT is not supported for window functions");
+    }
+    if filter_over.filter_clause.is_some() {
+        crate::bail_parse_error!("FILTER clause is not supported yet in window functions");
+    }
+    if order_by > 0 {
+        crate::bail_parse_error!("ORDER BY is not supported yet in window function arguments");
+    }
+    if let Some(func) = WindowFunc::resolve_function(name.as_str(), args)? {
+        return Ok(WindowFunctionKind::Builtin(func));
+    }
+    match Func::resolve_function(name.as_str(), args) {
+        Ok(Func::Agg(func)) => Ok(WindowFunctionKind::Aggregate(func)),
+        Ok(_) => {
+            crate::bail_parse_error!("{}() may not be used as a window function", name.as_str())
+        }
+        Err(e) => match syms.resolve_function(name.as_str(), args) {
+            Some(f) if matches!(f.func, ExtFunc::Aggregate { .. }) => Ok(
+                WindowFunctionKind::Aggregate(AggFunc::External(f.func.clone().into())),
+            ),
+            Some(_) => {
+                crate::bail_parse_error!("{}() may not be used as a window function", name.as_str())
+            }
+            None => Err(e),
+        },
+    }
+}
+
+/// Returns the frame of `window`, with the default frame and end bound filled in.
+fn window_frame(window: &ast::Window) -> FrameClause {
+    match &window.frame_clause {
+        Some(frame) => FrameClause {
+            mode: frame.mode,
+            start: frame.start.clone(),
+            end: Some(frame.end.clone().unwrap_or(FrameBound::CurrentRow)),
+            exclude: frame.exclude.clone(),
+        },
+        None => FrameClause {
+            mode: FrameMode::Range,
+            start: FrameBound::UnboundedPreceding,
+            end: Some(FrameBound::CurrentRow),
+            exclude: None,
+        },
+    }
+}
+
+fn validate_frame(window: &ast::Window) -> Result<()> {
+    let frame = window_frame(window);
+    let end = frame.end.as_ref().unwrap();
+    let unsupported = match (&frame.start, end) {
+        (FrameBound::UnboundedFollowing, _) | (_, FrameBound::UnboundedPreceding) => true,
+        (FrameBound::Following(_), FrameBound::Preceding(_) | FrameBound::CurrentRow) => true,
+        (FrameBound::CurrentRow, FrameBound::Preceding(_)) => true,
+        _ => false,
+    };
+    if unsupported {
+        crate::bail_parse_error!("unsupported frame specification");
+    }
+    let has_offset =
+        |bound: &FrameBound| matches!(bound, FrameBound::Preceding(_) | FrameBound::Following(_));
+    if frame.mode == FrameMode::Range
+        && (has_offset(&frame.start) || has_offset(end))
+        && window.order_by.len() != 1
+    {
+        crate::bail_parse_error!(
+            "RANGE with offset PRECEDING/FOLLOWING requires one ORDER BY expression"
+        );
+    }
+    Ok(())
+}
+
+/// Initialize resources needed for evaluating window functions
+pub fn init_window(
+    program: &mut ProgramBuilder,
+    t_ctx: &mut TranslateCtx,
+    plan: &SelectPlan,
+) -> Result<()> {
+    let window = plan.window.as_ref().expect("window must exist");
+    let definition = window.definition();
+    let partition_keys = definition.partition_by.len();
+    let order = definition
+        .order_by
+        .iter()
+        .map(|col| col.order.unwrap_or(SortOrder::Asc))
+        .collect::<Vec<_>>();
+    let sort_keys = definition
+        .partition_by
+        .iter()
+        .map(|expr| expr.as_ref())
+        .chain(definition.order_by.iter().map(|col| col.expr.as_ref()));
+
+    let sort_cursor = program.alloc_cursor_id(CursorType::Sorter);
+    program.emit_insn(Insn::SorterOpen {
+        cursor_id: sort_cursor,
+        columns: partition_keys + order.len(),
+        order: std::iter::repeat_n(SortOrder::Asc, partition_keys)
+            .chain(order.iter().copied())
+            .collect(),
+        collations: sort_keys
+            .map(|expr| sort_key_collation(expr, &plan.table_references))
+            .collect::<Result<Vec<_>>>()?,
+    });
+
+    let mut arg_columns = Vec::with_capacity(window.functions.len());
+    let mut functions = Vec::with_capacity(window.functions.len());
+    let mut column = partition_keys + order.len();
+    for function in &window.functions {
+        let frame = window_frame(function.definition());
+        let frame = WindowFrame {
+            mode: frame.mode,
+            start: translate_frame_bound(program, t_ctx, plan, &frame.start)?,
+            end: translate_frame_bound(program, t_ctx, plan, frame.end.as_ref().unwrap())?,
+            exclude: frame.exclude.unwrap_or(FrameExclude::NoOthers),
+        };
+        functions.push(WindowFunctionSpec {
+            func: match function.func {
+                WindowFunctionKind::Builtin(func) => Some(func),
+                WindowFunctionKind::Aggregate(_) => None,
+            },
+            arg_start: column,
+            arg_count: function.args().len(),
+            frame,
+        });
+        arg_columns.push(column);
+        column += function.args().len();
+    }
+
+    let window_cursor = program.alloc_cursor_id(CursorType::Window);
+    program.emit_insn(Insn::WindowOpen {
+        cursor_id: window_cursor,
+        partition_keys,
+        order,
+        functions,
+    });
+
+    t_ctx.meta_window = Some(WindowMetadata {
+        sort_cursor,
+        window_cursor,
+        reg_sorter_data: program.alloc_register(),
+        row_width: column + window.passthrough.len(),
+        arg_columns,
+        passthrough_start: column,
+    });
+    Ok(())
+}
+
+/// Evaluates a frame offset (e.g. the `2` in `ROWS 2 PRECEDING`) into a register.
+fn translate_frame_bound(
+    program: &mut ProgramBuilder,
+    t_ctx: &TranslateCtx,
+    plan: &SelectPlan,
+    bound: &FrameBound,
+) -> Result<WindowFrameBound<usize>> {
+    let mut translate_offset = |expr: &Expr| -> Result<usize> {
+        let reg = program.alloc_register();
+        translate_expr(
+            program,
+            Some(&plan.table_references),
+            expr,
+            reg,
+            &t_ctx.resolver,
+        )?;
+        Ok(reg)
+    };
+    Ok(match bound {
+        FrameBound::UnboundedPreceding => WindowFrameBound::UnboundedPreceding,
+        FrameBound::Preceding(expr) => WindowFrameBound::Preceding(translate_offset(expr)?),
+        FrameBound::CurrentRow => WindowFrameBound::CurrentRow,
+        FrameBound::Following(expr) => WindowFrameBound::Following(translate_offset(expr)?),
+        FrameBound::UnboundedFollowing => WindowFrameBound::UnboundedFollowing,
+    })
+}
+
+/// Emits the bytecode for inserting a row produced by the main loop into the window sorter.
+pub fn window_sorter_insert(
+    program: &mut ProgramBuilder,
+    t_ctx: &TranslateCtx,
+    plan: &SelectPlan,
+) -> Result<()> {
+    let window = plan.window.as_ref().expect("window must exist");
+    let meta = t_ctx
+        .meta_window
+        .as_ref()
+        .expect("window metadata must exist");
+    let definition = window.definition();
+    let exprs = definition
+        .partition_by
+        .iter()
+        .map(|expr| expr.as_ref())
+        .chain(definition.order_by.iter().map(|col| col.expr.as_ref()))
+        .chain(
+            window
+                .functions
+                .iter()
+                .flat_map(|f| f.args().iter().map(|arg| arg.as_ref())),
+        )
+        .chain(window.passthrough.iter());
+
+    let start_reg = program.alloc_registers(meta.row_width);
+    for (i, expr) in exprs.enumerate() {
+        translate_expr(
+            program,
+            Some(&plan.table_references),
+            expr,
+            start_reg + i,
+            &t_ctx.resolver,
+        )?;
+    }
+    sorter_insert(
+        program,
+        start_reg,
+        meta.row_width,
+        meta.sort_cursor,
+        meta.reg_sorter_data,
+    );
+    Ok(())
+}
+
+/// Emits the bytecode for evaluating the window functions and outputting the result rows.
+/// This is called when the main query execution loop has finished processing,
+/// and all rows have been inserted into the window sorter.
+pub fn emit_window<'a>(
+    program: &mut ProgramBuilder,
+    t_ctx: &mut TranslateCtx<'a>,
+    plan: &'a SelectPlan,
+) -> Result<()> {
+    let window = plan.window.as_ref().expect("window must exist");
+    let WindowMetadata {
+        sort_cursor,
+        window_cursor,
+        reg_sorter_data,
+        row_width,
+        ref arg_columns,
+        passthrough_start,
+    } = *t_ctx
+        .meta_window
+        .as_ref()
+        .expect("window metadata must exist");
+    let arg_columns = arg_columns.clone();
+
+    let label_sort_loop_start = program.allocate_label();
+    let label_sort_loop_next = program.allocate_label();
+    let label_sort_loop_end = program.allocate_label();
+    let label_output_subroutine = program.allocate_label();
+    let label_done = program.allocate_label();
+    let reg_subroutine_return = program.alloc_register();
+
+    let pseudo_cursor = program.alloc_cursor_id(CursorType::Pseudo(PseudoCursorType {
+        column_count: row_width,
+    }));
+    program.emit_insn(Insn::OpenPseudo {
+        cursor_id: pseudo_cursor,
+        content_reg: reg_sorter_data,
+        num_fields: row_width,
+    });
+    program.emit_insn(Insn::SorterSort {
+        cursor_id: sort_cursor,
+        pc_if_empty: label_sort_loop_end,
+    });
+
+    // Feed the sorted rows into the window cursor. Whenever a partition is complete,
+    // its rows are output before the next partition is started.
+    program.preassign_label_to_next_insn(label_sort_loop_start);
+    program.emit_insn(Insn::SorterData {
+        cursor_id: sort_cursor,
+        dest_reg: reg_sorter_data,
+        pseudo_cursor,
+    });
+    let start_reg = program.alloc_registers(row_width);
+    for i in 0..row_width {
+        program.emit_column_or_rowid(pseudo_cursor, i, start_reg + i);
+    }
+    program.emit_insn(Insn::WindowAdd {
+        cursor_id: window_cursor,
+        start_reg,
+        num_regs: row_width,
+        target_pc: label_sort_loop_next,
+    });
+    program.emit_insn(Insn::Gosub {
+        target_pc: label_output_subroutine,
+        return_reg: reg_subroutine_return,
+    });
+    program.preassign_label_to_next_insn(label_sort_loop_next);
+    program.emit_insn(Insn::SorterNext {
+        cursor_id: sort_cursor,
+        pc_if_next: label_sort_loop_start,
+    });
+    program.preassign_label_to_next_insn(label_sort_loop_end);
+    program.emit_insn(Insn::WindowFlush {
+        cursor_id: window_cursor,
+        pc_if_empty: label_done,
+    });
+    program.emit_insn(Insn::Gosub {
+        target_pc: label_output_subroutine,
+        return_reg: reg_subroutine_return,
+    });
+    program.emit_insn(Insn::Goto {
+        target_pc: label_done,
+    });
+
+    // Subroutine that outputs the rows of the completed partition.
+    let label_row_loop_start = program.allocate_label();
+    let label_row_loop_next = program.allocate_label();
+    let label_row_loop_end = program.allocate_label();
+    program.preassign_label_to_next_insn(label_output_subroutine);
+    program.emit_insn(Insn::Rewind {
+        cursor_id: window_cursor,
+        pc_if_empty: label_row_loop_end,
+    });
+    program.preassign_label_to_next_insn(label_row_loop_start);
+
+    // The columns referenced by the result columns and the values of the window functions
+    // are loaded into registers, and the result columns are translated using these registers.
+    let reg_passthrough = program.alloc_registers(window.passthrough.len());
+    for (i, expr) in window.passthrough.iter().enumerate() {
+        program.emit_insn(Insn::Column {
+            cursor_id: window_cursor,
+            column: passthrough_start + i,
+            dest: reg_passthrough + i,
+            default: None,
+        });
+        t_ctx
+            .resolver
+            .expr_to_reg_cache
+            .push((expr, reg_passthrough + i));
+    }
+    let reg_functions = program.alloc_registers(window.functions.len());
+    for (i, function) in window.functions.iter().enumerate() {
+        let target_register = reg_functions + i;
+        t_ctx
+            .resolver
+            .expr_to_reg_cache
+            .push((&function.original_expr, target_register));
+        let agg_func = match &function.func {
+            WindowFunctionKind::Builtin(_) => {
+                program.emit_insn(Insn::Column {
+                    cursor_id: window_cursor,
+                    column: row_width + i,
+                    dest: target_register,
+                    default: None,
+                });
+                continue;
+            }
+            WindowFunctionKind::Aggregate(agg_func) => agg_func.clone(),
+        };
+
+        // Aggregate functions are computed from scratch over the frame of every row.
+        let aggregate = Aggregate::new(
+            agg_func.clone(),
+            function.args(),
+            &function.original_expr,
+            Distinctness::NonDistinct,
+        );
+        let label_frame_loop_start = program.allocate_label();
+        let label_frame_loop_end = program.allocate_label();
+        program.emit_insn(Insn::Null {
+            dest: target_register,
+            dest_end: None,
+        });
+        program.emit_insn(Insn::WindowFrameRewind {
+            cursor_id: window_cursor,
+            func: i,
+            pc_if_empty: label_frame_loop_end,
+        });
+        program.preassign_label_to_next_insn(label_frame_loop_start);
+        let reg_args = program.alloc_registers(aggregate.args.len());
+        for j in 0..function.args().len() {
+            program.emit_insn(Insn::WindowFrameColumn {
+                cursor_id: window_cursor,
+                column: arg_columns[i] + j,
+                dest: reg_args + j,
+            });
+        }
+        translate_aggregation_step(
+            program,
+            &plan.table_references,
+            AggArgumentSource::new_from_registers(reg_args, &aggregate),
+            target_register,
+            &t_ctx.resolver,
+        )?;
+        program.emit_insn(Insn::WindowFrameNext {
+            cursor_id: window_cursor,
+            pc_if_next: label_frame_loop_start,
+        });
+        program.preassign_label_to_next_insn(label_frame_loop_end);
+        program.emit_insn(Insn::AggFinal {
+            register: target_register,
+            func: agg_func,
+        });
+    }
+    t_ctx.resolver.enable_expr_to_reg_cache();
+
+    if !plan.order_by.is_empty() {
+        order_by_sorter_insert(
+            program,
+            &t_ctx.resolver,
+            t_ctx
+                .meta_sort
+                .as_ref()
+                .expect("sort metadata must exist for ORDER BY"),
+            plan,
+        )?;
+    } else {
+        emit_select_result(
+            program,
+            &t_ctx.resolver,
+            plan,
+            Some(label_done),
+            Some(label_row_loop_next),
+            None,
+            t_ctx.reg_offset,
+            t_ctx.reg_result_cols_start.unwrap(),
+            t_ctx.limit_ctx,
+        )?;
+    }
+    if let Distinctness::Distinct { ctx } = &plan.distinctness {
+        let distinct_ctx = ctx.as_ref().expect("distinct context must exist");
+        program.preassign_label_to_next_insn(distinct_ctx.label_on_conflict);
+    }
+
+    program.preassign_label_to_next_insn(label_row_loop_next);
+    program.emit_insn(Insn::Next {
+        cursor_id: window_cursor,
+        pc_if_next: label_row_loop_start,
+    });
+    program.preassign_label_to_next_insn(label_row_loop_end);
+    program.emit_insn(Insn::Return {
+        return_reg: reg_subroutine_return,
+        can_fallthrough: false,
+    });
+
+    program.preassign_label_to_next_insn(label_done);
+    Ok(())
+}
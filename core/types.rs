@@ -13,6 +13,7 @@ use crate::storage::sqlite3_ondisk::{read_integer, read_value, read_varint, writ
 use crate::translate::collate::CollationSeq;
 use crate::translate::plan::IterationDirection;
 use crate::vdbe::sorter::Sorter;
+use crate::vdbe::window::WindowCursor;
 use crate::vdbe::Register;
 use crate::vtab::VirtualTableCursor;
 use crate::{turso_assert, Completion, CompletionError, Result, IO};
@@ -2336,6 +2337,7 @@ pub enum Cursor {
     Sorter(Sorter),
     Virtual(VirtualTableCursor),
     MaterializedView(Box<crate::incremental::cursor::MaterializedViewCursor>),
+    Window(Box<WindowCursor>),
 }
 
 impl Debug for Cursor {
@@ -2346,6 +2348,7 @@ impl Debug for Cursor {
             Self::Sorter(..) => f.debug_tuple("Sorter").finish(),
             Self::Virtual(..) => f.debug_tuple("Virtual").finish(),
             Self::MaterializedView(..) => f.debug_tuple("MaterializedView").finish(),
+            Self::Window(..) => f.debug_tuple("Window").finish(),
         }
     }
 }
@@ -2363,6 +2366,10 @@ impl Cursor {
         Self::Sorter(cursor)
     }
 
+    pub fn new_window(cursor: WindowCursor) -> Self {
+        Self::Window(Box::new(cursor))
+    }
+
     pub fn new_materialized_view(
         cursor: crate::incremental::cursor::MaterializedViewCursor,
     ) -> Self {
@@ -2397,6 +2404,13 @@ impl Cursor {
         }
     }
 
+    pub fn as_window_mut(&mut self) -> &mut WindowCursor {
+        match self {
+            Self::Window(cursor) => cursor,
+            _ => panic!("Cursor is not a window cursor"),
+        }
+    }
+
     pub fn as_materialized_view_mut(
         &mut self,
     ) -> &mut crate::incremental::cursor::MaterializedViewCursor {
@@ -205,6 +205,40 @@ pub fn parse_schema_rows(
     Ok(())
 }
 
+/// Loads the rows of sqlite_stat1 returned by `rows` into the statistics of `schema`. The
+/// statistics are left unchanged if the rows cannot all be read.
+pub fn parse_stat1_rows(
+    mut rows: Statement,
+    schema: &mut Schema,
+    mv_tx: Option<(u64, TransactionMode)>,
+) -> Result<()> {
+    rows.set_mv_tx(mv_tx);
+    let mut stat1_rows = Vec::new();
+    loop {
+        match rows.step()? {
+            StepResult::Row => {
+                let row = rows.row().unwrap();
+                // Like SQLite, rows that are not well-formed are ignored.
+                let (Ok(table_name), Ok(stat)) = (row.get::<&str>(0), row.get::<&str>(2)) else {
+                    continue;
+                };
+                let index_name = row.get::<&str>(1).ok().map(str::to_string);
+                stat1_rows.push((table_name.to_string(), index_name, stat.to_string()));
+            }
+            StepResult::IO => {
+                rows.run_once()?;
+            }
+            StepResult::Done => break,
+            StepResult::Interrupt | StepResult::Busy => return Err(LimboError::Busy),
+        }
+    }
+    schema.table_stats.clear();
+    for (table_name, index_name, stat) in stat1_rows {
+        schema.add_stat1_row(&table_name, index_name.as_deref(), &stat);
+    }
+    Ok(())
+}
+
 fn cmp_numeric_strings(num_str: &str, other: &str) -> bool {
     match (num_str.parse::<f64>(), other.parse::<f64>()) {
         (Ok(num), Ok(other)) => num == other,
@@ -1033,7 +1067,6 @@ pub fn parse_string(expr: &Expr) -> Result<String> {
     }
 }
 
-#[allow(unused)]
 pub fn parse_pragma_bool(expr: &Expr) -> Result<bool> {
     const TRUE_VALUES: &[&str] = &["yes", "true", "on"];
     const FALSE_VALUES: &[&str] = &["no", "false", "off"];
@@ -1113,6 +1146,7 @@ pub fn extract_view_columns(select_stmt: &ast::Select, schema: &Schema) -> Vec<C
                         unique: false,
                         collation: None,
                         hidden: false,
+                        generated: None,
                     });
                 }
                 ast::ResultColumn::Star => {
@@ -1132,6 +1166,7 @@ pub fn extract_view_columns(select_stmt: &ast::Select, schema: &Schema) -> Vec<C
                                     unique: false,
                                     collation: table_column.collation,
                                     hidden: false,
+                                    generated: None,
                                 });
                             }
                         } else {
@@ -1147,6 +1182,7 @@ pub fn extract_view_columns(select_stmt: &ast::Select, schema: &Schema) -> Vec<C
                                 unique: false,
                                 collation: None,
                                 hidden: false,
+                                generated: None,
                             });
                         }
                     } else {
@@ -1162,6 +1198,7 @@ pub fn extract_view_columns(select_stmt: &ast::Select, schema: &Schema) -> Vec<C
                             unique: false,
                             collation: None,
                             hidden: false,
+                            generated: None,
                         });
                     }
                 }
@@ -1182,6 +1219,7 @@ pub fn extract_view_columns(select_stmt: &ast::Select, schema: &Schema) -> Vec<C
                                 unique: false,
                                 collation: table_column.collation,
                                 hidden: false,
+                                generated: None,
                             });
                         }
                     } else {
@@ -1197,6 +1235,7 @@ pub fn extract_view_columns(select_stmt: &ast::Select, schema: &Schema) -> Vec<C
                             unique: false,
                             collation: None,
                             hidden: false,
+                            generated: None,
                         });
                     }
                 }
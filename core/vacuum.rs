@@ -0,0 +1,484 @@
+//! VACUUM and VACUUM INTO.
+//!
+//! Both statements rebuild the main database into a fresh file by replaying its schema and
+//! copying every table row by row, so that b-trees end up densely packed and free pages are
+//! dropped. `VACUUM INTO` stops there and leaves the copy at the requested path. `VACUUM` then
+//! copies the pages of the rebuilt file back over the original database as part of the write
+//! transaction of the statement, which makes the swap atomic for other connections.
+//!
+//! The work is driven by [VacuumState], which the VACUUM instruction steps until it is done. The
+//! schema and rows are copied through nested statements, and whenever one of them or a page read
+//! waits for IO, the state yields its completions to the IO loop of the VACUUM statement. The
+//! copy is removed again if the statement fails or is dropped before it completes.
+
+use std::collections::VecDeque;
+use std::num::NonZero;
+use std::sync::Arc;
+
+use crate::result::LimboResult;
+use crate::storage::database::DatabaseFile;
+use crate::storage::pager::{AutoVacuumMode, PageRef};
+use crate::storage::sqlite3_ondisk::DatabaseHeader;
+use crate::types::{IOCompletions, IOResult};
+use crate::{
+    io_yield_one, return_if_io, Connection, Database, DatabaseOpts, LimboError, OpenFlags, Result,
+    Statement, StepResult, TransactionState, IO,
+};
+
+/// Progress of a VACUUM or VACUUM INTO statement between the steps of its instruction.
+pub(crate) struct VacuumState {
+    /// Where the copy is built: the target of VACUUM INTO, or a scratch file next to the
+    /// database for VACUUM.
+    path: String,
+    /// Whether the copy replaces the main database rather than being left at `path`.
+    in_place: bool,
+    /// Whether the file at `path` was created by this statement, so that it has to be removed
+    /// if the statement does not complete.
+    created: bool,
+    io: Arc<dyn IO>,
+    /// Connection to the copy.
+    dest: Option<Arc<Connection>>,
+    /// Header of the main database, whose user fields the copy keeps.
+    header: Option<DatabaseHeader>,
+    phase: VacuumPhase,
+}
+
+enum VacuumPhase {
+    /// Checks that the database can be vacuumed and creates the copy.
+    Start,
+    /// Reads the header of the main database.
+    ReadHeader,
+    /// Reads the type, name and sql of the schema objects to replay on the copy.
+    ReadSchema {
+        stmt: Statement,
+        objects: Vec<(String, String, String)>,
+    },
+    /// Fills the copy one step at a time.
+    Build {
+        steps: VecDeque<BuildStep>,
+        running: Option<RunningStep>,
+    },
+    /// Checkpoints the copy of VACUUM INTO into its database file.
+    Checkpoint,
+    /// Reads the header of the copy of VACUUM before its pages replace the main database.
+    ReadCopyHeader,
+    /// Copies the pages of the copy of VACUUM over the main database.
+    CopyPages {
+        page_count: usize,
+        next: usize,
+        reading: Option<PageRef>,
+    },
+    Done,
+}
+
+enum BuildStep {
+    /// Runs a statement on the copy.
+    Execute(String),
+    /// Copies the rows of a table into the copy.
+    CopyRows(String),
+    /// Sets the header fields that VACUUM keeps on the copy.
+    CopyHeader,
+}
+
+enum RunningStep {
+    Execute(Statement),
+    /// Rows are read by `select` on the main database and written by `insert` into the copy.
+    /// `inserting` is set while `insert` has not yet finished with the last row read.
+    CopyRows {
+        select: Statement,
+        insert: Statement,
+        inserting: bool,
+    },
+    CopyHeader,
+}
+
+impl VacuumState {
+    /// Prepares VACUUM INTO `into`, or VACUUM of the main database when `into` is `None`.
+    pub(crate) fn new(conn: &Connection, into: Option<String>) -> Self {
+        let (path, in_place) = match into {
+            Some(path) => (path, false),
+            None => (format!("{}-vacuum", conn._db.path), true),
+        };
+        Self {
+            path,
+            in_place,
+            created: false,
+            io: conn._db.io.clone(),
+            dest: None,
+            header: None,
+            phase: VacuumPhase::Start,
+        }
+    }
+
+    pub(crate) fn step(&mut self, conn: &Arc<Connection>) -> Result<IOResult<()>> {
+        loop {
+            match &mut self.phase {
+                VacuumPhase::Start => {
+                    self.start(conn)?;
+                    self.phase = VacuumPhase::ReadHeader;
+                }
+                VacuumPhase::ReadHeader => {
+                    let pager = conn.pager.borrow().clone();
+                    let header = return_if_io!(pager.with_header(|header| *header));
+                    self.dest().reset_page_size(header.page_size.get())?;
+                    self.header = Some(header);
+                    let stmt = conn.prepare_nested(
+                        "SELECT type, name, sql FROM sqlite_schema WHERE sql IS NOT NULL ORDER BY rowid",
+                    )?;
+                    self.phase = VacuumPhase::ReadSchema {
+                        stmt,
+                        objects: Vec::new(),
+                    };
+                }
+                VacuumPhase::ReadSchema { stmt, objects } => {
+                    while return_if_io!(conn.run_nested(|| step_statement(stmt))) {
+                        let row = stmt.row().expect("row must be present");
+                        let mut row = row.get_values().map(|value| value.to_string());
+                        objects.push((
+                            row.next().unwrap_or_default(),
+                            row.next().unwrap_or_default(),
+                            row.next().unwrap_or_default(),
+                        ));
+                    }
+                    let steps = build_steps(conn, std::mem::take(objects))?;
+                    self.phase = VacuumPhase::Build {
+                        steps,
+                        running: None,
+                    };
+                }
+                VacuumPhase::Build { steps, running } => {
+                    let dest = self.dest.clone().expect("copy must be open");
+                    loop {
+                        match running {
+                            None => {
+                                *running = match steps.pop_front() {
+                                    Some(BuildStep::Execute(sql)) => {
+                                        Some(RunningStep::Execute(dest.prepare(sql)?))
+                                    }
+                                    Some(BuildStep::CopyRows(name)) => {
+                                        prepare_copy_rows(conn, &dest, &name)?
+                                    }
+                                    Some(BuildStep::CopyHeader) => Some(RunningStep::CopyHeader),
+                                    None => break,
+                                };
+                            }
+                            Some(RunningStep::Execute(stmt)) => {
+                                while return_if_io!(step_statement(stmt)) {}
+                                *running = None;
+                            }
+                            Some(RunningStep::CopyRows {
+                                select,
+                                insert,
+                                inserting,
+                            }) => {
+                                loop {
+                                    if *inserting {
+                                        while return_if_io!(step_statement(insert)) {}
+                                        *inserting = false;
+                                    }
+                                    if !return_if_io!(conn.run_nested(|| step_statement(select))) {
+                                        break;
+                                    }
+                                    let row = select.row().expect("row must be present");
+                                    insert.reset();
+                                    for (i, value) in row.get_values().enumerate() {
+                                        insert.bind_at(
+                                            NonZero::new(i + 1).expect("index is not zero"),
+                                            value.clone(),
+                                        );
+                                    }
+                                    *inserting = true;
+                                }
+                                *running = None;
+                            }
+                            Some(RunningStep::CopyHeader) => {
+                                let header = self.header.expect("header must be read");
+                                let in_place = self.in_place;
+                                let dest_pager = dest.pager.borrow().clone();
+                                return_if_io!(dest_pager.with_header_mut(
+                                    |dest_header: &mut DatabaseHeader| {
+                                        dest_header.user_version = header.user_version;
+                                        dest_header.application_id = header.application_id;
+                                        dest_header.default_page_cache_size =
+                                            header.default_page_cache_size;
+                                        // The new pages of VACUUM are a schema change for
+                                        // other connections.
+                                        if in_place {
+                                            dest_header.schema_cookie =
+                                                (header.schema_cookie.get() + 1).into();
+                                        }
+                                    }
+                                ));
+                                *running = None;
+                            }
+                        }
+                    }
+                    self.phase = if self.in_place {
+                        VacuumPhase::ReadCopyHeader
+                    } else {
+                        VacuumPhase::Checkpoint
+                    };
+                }
+                VacuumPhase::Checkpoint => {
+                    let dest_pager = self.dest().pager.borrow().clone();
+                    let mut result = return_if_io!(dest_pager.checkpoint());
+                    result.release_guard();
+                    if !result.everything_backfilled() {
+                        return Err(LimboError::Busy);
+                    }
+                    // Everything is in the database file, the copy needs no WAL.
+                    self.dest = None;
+                    self.io.remove_file(&format!("{}-wal", self.path))?;
+                    self.phase = VacuumPhase::Done;
+                }
+                VacuumPhase::ReadCopyHeader => {
+                    let dest_pager = self.dest().pager.borrow().clone();
+                    let dest_header = return_if_io!(dest_pager.with_header(|header| *header));
+                    let reserved_space = self.header.expect("header must be read").reserved_space;
+                    if dest_header.reserved_space != reserved_space {
+                        return Err(LimboError::InvalidArgument(format!(
+                            "VACUUM is not supported on databases with {reserved_space} reserved bytes per page"
+                        )));
+                    }
+                    if let LimboResult::Busy = dest_pager.begin_read_tx()? {
+                        return Err(LimboError::Busy);
+                    }
+                    conn.pager.borrow().begin_replacing_pages();
+                    self.phase = VacuumPhase::CopyPages {
+                        page_count: dest_header.database_size.get() as usize,
+                        next: 1,
+                        reading: None,
+                    };
+                }
+                VacuumPhase::CopyPages {
+                    page_count,
+                    next,
+                    reading,
+                } => {
+                    let dest_pager = self
+                        .dest
+                        .as_ref()
+                        .expect("copy must be open")
+                        .pager
+                        .borrow()
+                        .clone();
+                    let pager = conn.pager.borrow().clone();
+                    while *next <= *page_count {
+                        let page = match reading.take() {
+                            Some(page) => page,
+                            None => {
+                                let (page, c) = dest_pager.read_page(*next)?;
+                                if let Some(c) = c {
+                                    *reading = Some(page);
+                                    io_yield_one!(c);
+                                }
+                                page
+                            }
+                        };
+                        pager.replace_page(*next, page.get_contents().as_ptr())?;
+                        *next += 1;
+                    }
+                    dest_pager.end_read_tx()?;
+                    self.dest = None;
+                    self.io.remove_file(&self.path)?;
+                    self.io.remove_file(&format!("{}-wal", self.path))?;
+                    self.phase = VacuumPhase::Done;
+
+                    // Root pages have moved, so the schema has to be loaded again from the new
+                    // pages.
+                    conn.transaction_state.replace(TransactionState::Write {
+                        schema_did_change: true,
+                    });
+                    conn.run_nested(|| conn.reparse_schema())?;
+                }
+                VacuumPhase::Done => return Ok(IOResult::Done(())),
+            }
+        }
+    }
+
+    /// Checks that the database can be vacuumed and opens a new database at `path` for the copy.
+    fn start(&mut self, conn: &Arc<Connection>) -> Result<()> {
+        if self.in_place {
+            if conn.mv_tx.get().is_some() {
+                return Err(LimboError::InvalidArgument(
+                    "VACUUM is not supported in MVCC mode".to_string(),
+                ));
+            }
+            let pager = conn.pager.borrow().clone();
+            if pager.is_encryption_ctx_set() {
+                return Err(LimboError::InvalidArgument(
+                    "VACUUM is not supported on encrypted databases".to_string(),
+                ));
+            }
+            if !matches!(pager.get_auto_vacuum_mode(), AutoVacuumMode::None) {
+                return Err(LimboError::InvalidArgument(
+                    "VACUUM is not supported on databases with auto_vacuum enabled".to_string(),
+                ));
+            }
+            // A copy left behind by an interrupted VACUUM is of no use.
+            let _ = self.io.remove_file(&self.path);
+            let _ = self.io.remove_file(&format!("{}-wal", self.path));
+        }
+
+        let schema = conn.schema.borrow().clone();
+        if !schema.materialized_view_names.is_empty() {
+            return Err(LimboError::InvalidArgument(
+                "VACUUM is not supported on databases with materialized views".to_string(),
+            ));
+        }
+
+        let file = self.io.open_file(&self.path, OpenFlags::Create, false)?;
+        if file.size()? > 0 {
+            return Err(LimboError::InvalidArgument(
+                "output file already exists".to_string(),
+            ));
+        }
+        self.created = true;
+        let opts = DatabaseOpts::new()
+            .with_indexes(schema.indexes_enabled())
+            .with_views(conn._db.experimental_views_enabled())
+            .with_strict(conn._db.experimental_strict_enabled());
+        // The copy is private to this statement, so its WAL is not shared through a wal-index.
+        let db = Database::open_with_flags_bypass_registry_internal(
+            self.io.clone(),
+            &self.path,
+            &format!("{}-wal", self.path),
+            None,
+            Arc::new(DatabaseFile::new(file)),
+            OpenFlags::Create,
+            opts,
+        )?;
+        self.dest = Some(db.connect()?);
+        Ok(())
+    }
+
+    fn dest(&self) -> &Arc<Connection> {
+        self.dest.as_ref().expect("copy must be open")
+    }
+}
+
+impl Drop for VacuumState {
+    fn drop(&mut self) {
+        if matches!(self.phase, VacuumPhase::Done) || !self.created {
+            return;
+        }
+        // The copy is incomplete, so it is closed and removed.
+        self.phase = VacuumPhase::Done;
+        self.dest = None;
+        let _ = self.io.remove_file(&self.path);
+        let _ = self.io.remove_file(&format!("{}-wal", self.path));
+    }
+}
+
+/// Lists the steps that replay the schema `objects` on the copy and fill it.
+fn build_steps(
+    conn: &Arc<Connection>,
+    objects: Vec<(String, String, String)>,
+) -> Result<VecDeque<BuildStep>> {
+    let schema = conn.schema.borrow().clone();
+    if let Some((_, name, _)) = objects.iter().find(|(ty, name, _)| {
+        ty == "table"
+            && schema
+                .get_table(name)
+                .is_some_and(|table| table.virtual_table().is_some())
+    }) {
+        return Err(LimboError::InvalidArgument(format!(
+            "VACUUM is not supported on databases with virtual tables: {name}"
+        )));
+    }
+
+    let mut steps = VecDeque::from([BuildStep::Execute("BEGIN".to_string())]);
+    for (_, name, sql) in objects.iter().filter(|(ty, _, _)| ty == "table") {
+        steps.push_back(BuildStep::Execute(sql.clone()));
+        steps.push_back(BuildStep::CopyRows(name.clone()));
+    }
+    // Indexes are built once the rows are in place; views and triggers come last so that
+    // the triggers do not fire while the rows are copied.
+    for (_, _, sql) in objects.iter().filter(|(ty, _, _)| ty == "index") {
+        steps.push_back(BuildStep::Execute(sql.clone()));
+    }
+    for (_, _, sql) in objects
+        .iter()
+        .filter(|(ty, _, _)| ty == "view" || ty == "trigger")
+    {
+        steps.push_back(BuildStep::Execute(sql.clone()));
+    }
+    steps.push_back(BuildStep::CopyHeader);
+    steps.push_back(BuildStep::Execute("COMMIT".to_string()));
+    Ok(steps)
+}
+
+/// Prepares the statements that copy every row of table `name`, keeping rowids, into the table
+/// of the same name in `dest`.
+fn prepare_copy_rows(
+    conn: &Arc<Connection>,
+    dest: &Arc<Connection>,
+    name: &str,
+) -> Result<Option<RunningStep>> {
+    let schema = conn.schema.borrow().clone();
+    let Some(table) = schema.get_btree_table(name) else {
+        return Ok(None);
+    };
+    // Generated columns cannot be inserted into, the copy computes them again.
+    let mut columns = table
+        .columns
+        .iter()
+        .filter(|column| !column.is_generated())
+        .map(|column| quote_ident(column.name.as_deref().unwrap_or_default()))
+        .collect::<Vec<_>>();
+    // Rowids are only preserved implicitly when they are aliased by a column.
+    if table.has_rowid && table.get_rowid_alias_column().is_none() {
+        columns.insert(0, "rowid".to_string());
+    }
+    let placeholders = (1..=columns.len())
+        .map(|i| format!("?{i}"))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let columns = columns.join(", ");
+    let table_name = quote_ident(name);
+    let insert = dest.prepare(format!(
+        "INSERT INTO {table_name} ({columns}) VALUES ({placeholders})"
+    ))?;
+    let select = conn.prepare_nested(format!("SELECT {columns} FROM {table_name}"))?;
+    Ok(Some(RunningStep::CopyRows {
+        select,
+        insert,
+        inserting: false,
+    }))
+}
+
+/// Steps `stmt` to its next row, returning whether there was one, or yields the IO it waits for.
+fn step_statement(stmt: &mut Statement) -> Result<IOResult<bool>> {
+    match stmt.step()? {
+        StepResult::Row => Ok(IOResult::Done(true)),
+        StepResult::Done => Ok(IOResult::Done(false)),
+        StepResult::IO => Ok(IOResult::IO(stmt.pending_io())),
+        StepResult::Interrupt | StepResult::Busy => Err(LimboError::Busy),
+    }
+}
+
+impl Connection {
+    /// Prepares a statement that runs inside the transaction of the statement being executed.
+    fn prepare_nested(self: &Arc<Connection>, sql: impl AsRef<str>) -> Result<Statement> {
+        let mut stmt = self.prepare(sql)?;
+        stmt.set_mv_tx(self.mv_tx.get());
+        Ok(stmt)
+    }
+
+    /// Runs `f`, which executes statements on this connection, as part of the statement being
+    /// executed: the nested statements neither commit nor roll back the transaction.
+    fn run_nested<T>(&self, f: impl FnOnce() -> Result<T>) -> Result<T> {
+        let previous_auto_commit = self.auto_commit.get();
+        let previous_nested = self.is_nested_stmt.get();
+        self.auto_commit.set(false);
+        self.is_nested_stmt.set(true);
+        let result = f();
+        self.is_nested_stmt.set(previous_nested);
+        self.auto_commit.set(previous_auto_commit);
+        result
+    }
+}
+
+fn quote_ident(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
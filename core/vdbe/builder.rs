@@ -1,4 +1,9 @@
-use std::{cell::Cell, cmp::Ordering, sync::Arc};
+use std::{
+    cell::Cell,
+    cmp::Ordering,
+    collections::{BTreeMap, HashMap},
+    sync::Arc,
+};
 
 use tracing::{instrument, Level};
 use turso_parser::ast::{self, TableInternalId};
@@ -10,7 +15,7 @@ use crate::{
     translate::{
         collate::CollationSeq,
         emitter::TransactionMode,
-        plan::{ResultSetColumn, TableReferences},
+        plan::{NonFromClauseSubquery, ResultSetColumn, TableReferences},
     },
     CaptureDataChangesMode, Connection, Value, VirtualTable,
 };
@@ -113,11 +118,35 @@ pub struct ProgramBuilder {
     capture_data_changes_mode: CaptureDataChangesMode,
     // TODO: when we support multiple dbs, this should be a write mask to track which DBs need to be written
     txn_mode: TransactionMode,
+    /// The transaction modes of the temp and attached databases, by database index, set by the
+    /// instructions that access them.
+    attached_txn_modes: BTreeMap<usize, TransactionMode>,
+    /// The schema cookies of the temp and attached databases the program is translated against.
+    attached_schema_cookies: HashMap<usize, u32>,
     rollback: bool,
     /// The mode in which the query is being executed.
     query_mode: QueryMode,
     /// Current parent explain address, if any.
     current_parent_explain_idx: Option<usize>,
+    /// Trigger programs currently being inlined, innermost last.
+    trigger_stack: Vec<TriggerFrame>,
+    /// Subqueries that appear in expressions rather than in a FROM clause.
+    /// They are planned before the statement is emitted, and emitted where the expression is evaluated.
+    non_from_clause_subqueries: Vec<NonFromClauseSubquery>,
+    /// Whether the statement can be undone on its own, as RAISE(ABORT) does.
+    uses_statement_savepoint: bool,
+}
+
+/// A trigger program that is being inlined into the statement that fired it.
+#[derive(Debug, Clone)]
+pub struct TriggerFrame {
+    pub trigger_name: String,
+    /// Target of RAISE(IGNORE): abandons the trigger program and the rest of the
+    /// processing of the row that fired it.
+    pub ignore_label: BranchOffset,
+    /// For the action of a foreign key constraint, the ephemeral table where the rows that fire
+    /// the action again queue their keys.
+    pub cascade_queue: Option<CursorID>,
 }
 
 #[derive(Debug, Clone)]
@@ -126,6 +155,7 @@ pub enum CursorType {
     BTreeIndex(Arc<Index>),
     Pseudo(PseudoCursorType),
     Sorter,
+    Window,
     VirtualTable(Arc<VirtualTable>),
     MaterializedView(
         Arc<BTreeTable>,
@@ -200,9 +230,14 @@ impl ProgramBuilder {
             start_offset: BranchOffset::Placeholder,
             capture_data_changes_mode,
             txn_mode: TransactionMode::None,
+            attached_txn_modes: BTreeMap::new(),
+            attached_schema_cookies: HashMap::new(),
             rollback: false,
             query_mode,
             current_parent_explain_idx: None,
+            trigger_stack: Vec::new(),
+            non_from_clause_subqueries: Vec::new(),
+            uses_statement_savepoint: false,
         }
     }
 
@@ -331,6 +366,26 @@ impl ProgramBuilder {
         let function = insn.to_function();
         // This seemingly empty trace here is needed so that a function span is emmited with it
         tracing::trace!("");
+        match &insn {
+            Insn::OpenRead { db, .. }
+            | Insn::ReadCookie { db, .. }
+            | Insn::PageCount { db, .. }
+                if *db > 0 =>
+            {
+                self.attached_txn_modes
+                    .entry(*db)
+                    .or_insert(TransactionMode::Read);
+            }
+            Insn::OpenWrite { db, .. }
+            | Insn::CreateBtree { db, .. }
+            | Insn::Destroy { is_temp: db, .. }
+            | Insn::SetCookie { db, .. }
+                if *db > 0 =>
+            {
+                self.attached_txn_modes.insert(*db, TransactionMode::Write);
+            }
+            _ => {}
+        }
         self.insns.push((insn, function, self.insns.len()));
     }
 
@@ -658,6 +713,9 @@ impl ProgramBuilder {
                 } => {
                     resolve(target_pc, "IfNot");
                 }
+                Insn::FkIfZero { target_pc, .. } => {
+                    resolve(target_pc, "FkIfZero");
+                }
                 Insn::Rewind { pc_if_empty, .. } => {
                     resolve(pc_if_empty, "Rewind");
                 }
@@ -682,6 +740,18 @@ impl ProgramBuilder {
                 Insn::SorterSort { pc_if_empty, .. } => {
                     resolve(pc_if_empty, "SorterSort");
                 }
+                Insn::WindowAdd { target_pc, .. } => {
+                    resolve(target_pc, "WindowAdd");
+                }
+                Insn::WindowFlush { pc_if_empty, .. } => {
+                    resolve(pc_if_empty, "WindowFlush");
+                }
+                Insn::WindowFrameRewind { pc_if_empty, .. } => {
+                    resolve(pc_if_empty, "WindowFrameRewind");
+                }
+                Insn::WindowFrameNext { pc_if_next, .. } => {
+                    resolve(pc_if_next, "WindowFrameNext");
+                }
                 Insn::NotNull {
                     reg: _reg,
                     target_pc,
@@ -794,6 +864,31 @@ impl ProgramBuilder {
             .position(|(k, _)| k.as_ref().is_some_and(|k| k.equals(key)))
     }
 
+    /// Returns the index of an index cursor opened for the table reference, if any.
+    pub fn resolve_index_for_table(
+        &self,
+        table_reference_id: TableInternalId,
+    ) -> Option<Arc<Index>> {
+        self.cursor_ref.iter().find_map(|(key, _)| {
+            key.as_ref()
+                .filter(|key| key.table_reference_id == table_reference_id)
+                .and_then(|key| key.index.clone())
+        })
+    }
+
+    pub fn add_non_from_clause_subquery(&mut self, subquery: NonFromClauseSubquery) {
+        self.non_from_clause_subqueries.push(subquery);
+    }
+
+    pub fn non_from_clause_subquery(
+        &self,
+        internal_id: TableInternalId,
+    ) -> Option<&NonFromClauseSubquery> {
+        self.non_from_clause_subqueries
+            .iter()
+            .find(|subquery| subquery.internal_id == internal_id)
+    }
+
     pub fn resolve_cursor_id(&self, key: &CursorKey) -> CursorID {
         self.resolve_cursor_id_safe(key)
             .unwrap_or_else(|| panic!("Cursor not found: {key:?}"))
@@ -815,6 +910,42 @@ impl ProgramBuilder {
         self.collation = None;
     }
 
+    pub fn push_trigger(&mut self, frame: TriggerFrame) {
+        self.trigger_stack.push(frame);
+    }
+
+    pub fn pop_trigger(&mut self) {
+        self.trigger_stack.pop();
+    }
+
+    /// Make the program capture the state of the transaction before the statement, so that the
+    /// statement can be undone without ending the transaction.
+    pub fn use_statement_savepoint(&mut self) {
+        self.uses_statement_savepoint = true;
+    }
+
+    /// The innermost trigger program being generated, if any.
+    pub fn current_trigger(&self) -> Option<&TriggerFrame> {
+        self.trigger_stack.last()
+    }
+
+    /// Whether the trigger is already on the trigger stack. Triggers are not fired
+    /// recursively, matching SQLite with `recursive_triggers` off.
+    pub fn is_trigger_active(&self, trigger_name: &str) -> bool {
+        self.trigger_stack
+            .iter()
+            .any(|frame| frame.trigger_name == trigger_name)
+    }
+
+    /// The queue of the foreign key action inlined as `trigger_name`, if it is on the trigger
+    /// stack.
+    pub fn cascade_queue(&self, trigger_name: &str) -> Option<CursorID> {
+        self.trigger_stack
+            .iter()
+            .find(|frame| frame.trigger_name == trigger_name)
+            .and_then(|frame| frame.cascade_queue)
+    }
+
     #[inline]
     pub fn incr_nesting(&mut self) {
         self.nested_level += 1;
@@ -856,6 +987,10 @@ impl ProgramBuilder {
         self.txn_mode = TransactionMode::Concurrent;
     }
 
+    pub fn set_attached_schema_cookies(&mut self, schema_cookies: HashMap<usize, u32>) {
+        self.attached_schema_cookies = schema_cookies;
+    }
+
     /// Indicates the rollback behvaiour for the halt instruction in epilogue
     pub fn rollback(&mut self) {
         self.rollback = true;
@@ -877,6 +1012,18 @@ impl ProgramBuilder {
                     schema_cookie: schema.schema_version,
                 });
             }
+            for (&db, &tx_mode) in self.attached_txn_modes.clone().iter() {
+                let schema_cookie = self
+                    .attached_schema_cookies
+                    .get(&db)
+                    .copied()
+                    .unwrap_or_default();
+                self.emit_insn(Insn::Transaction {
+                    db,
+                    tx_mode,
+                    schema_cookie,
+                });
+            }
 
             self.emit_constant_insns();
             self.emit_insn(Insn::Goto {
@@ -942,9 +1089,41 @@ impl ProgramBuilder {
         }
     }
 
+    /// Emits an instruction that reads the rowid of the row `cursor_id` points to into `out`.
+    /// The rows of a WITHOUT ROWID table have no rowid, so NULL is read instead.
+    pub fn emit_rowid_or_null(&mut self, cursor_id: CursorID, out: usize) {
+        let (_, cursor_type) = self.cursor_ref.get(cursor_id).unwrap();
+        if matches!(cursor_type, CursorType::BTreeTable(btree) if !btree.has_rowid) {
+            self.emit_null(out, None);
+        } else {
+            self.emit_insn(Insn::RowId {
+                cursor_id,
+                dest: out,
+            });
+        }
+    }
+
     fn emit_column(&mut self, cursor_id: CursorID, column: usize, out: usize) {
         let (_, cursor_type) = self.cursor_ref.get(cursor_id).unwrap();
 
+        // VIRTUAL generated columns are not stored, their values are computed by the caller
+        // with [crate::translate::generated::emit_table_column].
+        let is_virtual_generated = matches!(
+            cursor_type,
+            CursorType::BTreeTable(btree)
+                if btree.columns.get(column).is_some_and(|c| c.is_virtual_generated())
+        );
+        if is_virtual_generated {
+            self.emit_null(out, None);
+            return;
+        }
+
+        // The records of a WITHOUT ROWID table start with the primary key columns.
+        let column_in_record = match cursor_type {
+            CursorType::BTreeTable(btree) => btree.column_pos_in_record(column),
+            _ => column,
+        };
+
         use crate::translate::expr::sanitize_string;
 
         let default = 'value: {
@@ -985,7 +1164,7 @@ impl ProgramBuilder {
 
         self.emit_insn(Insn::Column {
             cursor_id,
-            column,
+            column: column_in_record,
             dest: out,
             default,
         });
@@ -1011,7 +1190,9 @@ impl ProgramBuilder {
             result_columns: self.result_columns,
             table_references: self.table_references,
             sql: sql.to_string(),
-            accesses_db: !matches!(self.txn_mode, TransactionMode::None),
+            accesses_db: !matches!(self.txn_mode, TransactionMode::None)
+                || !self.attached_txn_modes.is_empty(),
+            uses_statement_savepoint: self.uses_statement_savepoint,
         }
     }
 }
@@ -1,26 +1,29 @@
 #![allow(unused_variables)]
 use crate::function::AlterTableFunc;
 use crate::numeric::{NullableInteger, Numeric};
-use crate::schema::Table;
+use crate::schema::{Schema, Table};
 use crate::storage::btree::{
     integrity_check, IntegrityCheckError, IntegrityCheckState, PageCategory,
 };
 use crate::storage::database::DatabaseFile;
 use crate::storage::page_cache::PageCache;
-use crate::storage::pager::{AtomicDbState, CreateBTreeFlags, DbState};
+use crate::storage::pager::{AtomicDbState, AutoVacuumMode, CreateBTreeFlags, DbState};
 use crate::storage::sqlite3_ondisk::read_varint;
 use crate::translate::collate::CollationSeq;
+use crate::translate::index::rename_index_expr_column;
 use crate::types::{
     compare_immutable, compare_records_generic, Extendable, IOCompletions, ImmutableRecord,
     SeekResult, Text,
 };
 use crate::util::{normalize_ident, IOExt as _};
+use crate::vacuum::VacuumState;
 use crate::vdbe::insn::InsertFlags;
 use crate::vdbe::registers_to_ref_values;
 use crate::vector::{vector_concat, vector_slice};
 use crate::{
     error::{
-        LimboError, SQLITE_CONSTRAINT, SQLITE_CONSTRAINT_NOTNULL, SQLITE_CONSTRAINT_PRIMARYKEY,
+        LimboError, SQLITE_CONSTRAINT, SQLITE_CONSTRAINT_FOREIGNKEY, SQLITE_CONSTRAINT_NOTNULL,
+        SQLITE_CONSTRAINT_PRIMARYKEY,
     },
     ext::ExtValue,
     function::{AggFunc, ExtFunc, MathFunc, MathFuncArity, ScalarFunc, VectorFunc},
@@ -32,7 +35,7 @@ use crate::{
     },
     translate::emitter::TransactionMode,
 };
-use crate::{get_cursor, MvCursor};
+use crate::{get_cursor, MvCursor, MvIndexCursor};
 use std::env::temp_dir;
 use std::ops::DerefMut;
 use std::{
@@ -45,7 +48,7 @@ use turso_macros::match_ignore_ascii_case;
 use crate::{pseudo::PseudoCursor, result::LimboResult};
 
 use crate::{
-    schema::{affinity, Affinity},
+    schema::{affinity, Affinity, Index},
     storage::btree::{BTreeCursor, BTreeKey},
 };
 
@@ -61,7 +64,8 @@ use crate::{
     },
     vdbe::{
         builder::CursorType,
-        insn::{IdxInsertFlags, Insn},
+        insn::{IdxInsertFlags, Insn, SavepointOp},
+        window::WindowCursor,
     },
     vector::{vector32, vector64, vector_distance_cos, vector_distance_l2, vector_extract},
 };
@@ -260,10 +264,16 @@ pub fn op_drop_index(
     pager: &Rc<Pager>,
     mv_store: Option<&Arc<MvStore>>,
 ) -> Result<InsnFunctionStepResult> {
-    load_insn!(DropIndex { index, db: _ }, insn);
-    program
-        .connection
-        .with_schema_mut(|schema| schema.remove_index(index));
+    load_insn!(DropIndex { index, db }, insn);
+    if *db > 0 {
+        program
+            .connection
+            .with_attached_schema_mut(*db, |schema| schema.remove_index(index))?;
+    } else {
+        program
+            .connection
+            .with_schema_mut(|schema| schema.remove_index(index));
+    }
     state.pc += 1;
     Ok(InsnFunctionStepResult::Step)
 }
@@ -941,8 +951,14 @@ pub fn op_open_read(
     let pager = program.get_pager_from_database_index(db);
 
     let (_, cursor_type) = program.cursor_ref.get(*cursor_id).unwrap();
+    let is_index = match cursor_type {
+        CursorType::BTreeIndex(_) => true,
+        CursorType::BTreeTable(table) => !table.has_rowid,
+        _ => false,
+    };
     let mv_cursor = match program.connection.mv_tx.get() {
-        Some((tx_id, _)) => {
+        // Index b-trees are read through an MVCC index cursor
+        Some((tx_id, _)) if !is_index => {
             let table_id = *root_page as u64;
             let mv_store = mv_store.unwrap().clone();
             let mv_cursor = Rc::new(RefCell::new(
@@ -950,7 +966,7 @@ pub fn op_open_read(
             ));
             Some(mv_cursor)
         }
-        None => None,
+        _ => None,
     };
     let cursors = &mut state.cursors;
     let num_columns = match cursor_type {
@@ -991,6 +1007,26 @@ pub fn op_open_read(
                 .unwrap()
                 .replace(Cursor::new_materialized_view(mv_cursor));
         }
+        CursorType::BTreeTable(table) if !table.has_rowid => {
+            // A WITHOUT ROWID table is stored as an index on its primary key
+            let index = Index::automatic_from_primary_key(
+                table,
+                (table.name.clone(), *root_page),
+                table.primary_key_columns.len(),
+            )?;
+            let mv_index_cursor = open_mv_index_cursor(program, mv_store, &index, pager.clone())?;
+            let cursor = BTreeCursor::new_index(
+                mv_index_cursor,
+                pager.clone(),
+                *root_page,
+                &index,
+                num_columns,
+            );
+            cursors
+                .get_mut(*cursor_id)
+                .unwrap()
+                .replace(Cursor::new_btree(cursor));
+        }
         CursorType::BTreeTable(_) => {
             // Regular table
             let cursor = BTreeCursor::new_table(mv_cursor, pager.clone(), *root_page, num_columns);
@@ -1000,8 +1036,9 @@ pub fn op_open_read(
                 .replace(Cursor::new_btree(cursor));
         }
         CursorType::BTreeIndex(index) => {
+            let mv_index_cursor = open_mv_index_cursor(program, mv_store, index, pager.clone())?;
             let cursor = BTreeCursor::new_index(
-                mv_cursor,
+                mv_index_cursor,
                 pager.clone(),
                 *root_page,
                 index.as_ref(),
@@ -1018,6 +1055,9 @@ pub fn op_open_read(
         CursorType::Sorter => {
             panic!("OpenRead on sorter cursor");
         }
+        CursorType::Window => {
+            panic!("OpenRead on window cursor");
+        }
         CursorType::VirtualTable(_) => {
             panic!("OpenRead on virtual table cursor, use Insn:VOpen instead");
         }
@@ -1026,6 +1066,21 @@ pub fn op_open_read(
     Ok(InsnFunctionStepResult::Step)
 }
 
+/// Opens an MVCC cursor over the entries of `index`, if the connection is in an MVCC transaction.
+fn open_mv_index_cursor(
+    program: &Program,
+    mv_store: Option<&Arc<MvStore>>,
+    index: &Index,
+    pager: Rc<Pager>,
+) -> Result<Option<Rc<RefCell<MvIndexCursor>>>> {
+    let Some((tx_id, _)) = program.connection.mv_tx.get() else {
+        return Ok(None);
+    };
+    let mv_store = mv_store.unwrap().clone();
+    let mv_index_cursor = MvIndexCursor::new(mv_store, tx_id, index, pager)?;
+    Ok(Some(Rc::new(RefCell::new(mv_index_cursor))))
+}
+
 pub fn op_vopen(
     program: &Program,
     state: &mut ProgramState,
@@ -1319,6 +1374,7 @@ pub fn op_rewind(
         let cursor = state.get_cursor(*cursor_id);
         match cursor {
             Cursor::BTree(btree_cursor) => {
+                btree_cursor.set_null_flag(false);
                 return_if_io!(btree_cursor.rewind());
                 btree_cursor.is_empty()
             }
@@ -1326,6 +1382,7 @@ pub fn op_rewind(
                 return_if_io!(mv_cursor.rewind());
                 !mv_cursor.is_valid()?
             }
+            Cursor::Window(window_cursor) => !window_cursor.rewind(),
             _ => panic!("Rewind on non-btree/materialized-view cursor"),
         }
     };
@@ -1357,6 +1414,7 @@ pub fn op_last(
     let is_empty = {
         let cursor = must_be_btree_cursor!(*cursor_id, program.cursor_ref, state, "Last");
         let cursor = cursor.as_btree_mut();
+        cursor.set_null_flag(false);
         return_if_io!(cursor.last());
         cursor.is_empty()
     };
@@ -1782,6 +1840,13 @@ pub fn op_column(
                         };
                         state.registers[*dest] = Register::Value(value);
                     }
+                    CursorType::Window => {
+                        let value = {
+                            let cursor = state.get_cursor(*cursor_id);
+                            cursor.as_window_mut().column(*column)
+                        };
+                        state.registers[*dest] = Register::Value(value);
+                    }
                     CursorType::VirtualTable(_) => {
                         panic!("Insn:Column on virtual table cursor, use Insn:VColumn instead");
                     }
@@ -1939,6 +2004,7 @@ pub fn op_next(
                 let has_more = return_if_io!(mv_cursor.next());
                 !has_more
             }
+            Cursor::Window(window_cursor) => !window_cursor.next(),
             _ => panic!("Next on non-btree/materialized-view cursor"),
         }
     };
@@ -2027,6 +2093,11 @@ pub fn halt(
                 "NOT NULL constraint failed: {description} (19)"
             )));
         }
+        SQLITE_CONSTRAINT_FOREIGNKEY => {
+            return Err(LimboError::Constraint(
+                "FOREIGN KEY constraint failed (19)".to_string(),
+            ));
+        }
         _ => {
             return Err(LimboError::Constraint(format!(
                 "undocumented halt error code {description}"
@@ -2034,9 +2105,28 @@ pub fn halt(
         }
     }
 
+    if state.fk_violations > 0 {
+        return Err(LimboError::Constraint(
+            "FOREIGN KEY constraint failed (19)".to_string(),
+        ));
+    }
+
     let auto_commit = program.connection.auto_commit.get();
     tracing::trace!("halt(auto_commit={})", auto_commit);
     if auto_commit {
+        if !program.connection.is_nested_stmt.get() {
+            if program.connection.deferred_fk_violations.get() > 0 {
+                return Err(LimboError::Constraint(
+                    "FOREIGN KEY constraint failed (19)".to_string(),
+                ));
+            }
+            if matches!(
+                program.connection.transaction_state.get(),
+                TransactionState::Write { .. }
+            ) {
+                program.connection.end_foreign_key_transaction();
+            }
+        }
         program
             .commit_txn(pager.clone(), state, mv_store, false)
             .map(Into::into)
@@ -2062,6 +2152,102 @@ pub fn op_halt(
     halt(program, state, pager, mv_store, *err_code, description)
 }
 
+pub fn op_raise(
+    program: &Program,
+    state: &mut ProgramState,
+    insn: &Insn,
+    pager: &Rc<Pager>,
+    mv_store: Option<&Arc<MvStore>>,
+) -> Result<InsnFunctionStepResult> {
+    load_insn!(
+        Raise {
+            resolve_type,
+            description,
+        },
+        insn
+    );
+    let conn = program.connection.clone();
+    match resolve_type {
+        // Outside of an explicit transaction, the transaction of the statement is rolled back
+        // as a whole, the same as for RAISE(ROLLBACK).
+        ast::ResolveType::Abort if !conn.auto_commit.get() => {
+            // Only the changes made by the statement are undone
+            if let Some(savepoint) = state.statement_savepoint.take() {
+                conn.rollback_to_savepoint(&savepoint, pager, mv_store)?;
+                return Err(LimboError::StatementAborted(description.clone()));
+            }
+        }
+        ast::ResolveType::Fail => {
+            // The changes made before the failing row are kept, and committed when the
+            // statement runs outside of an explicit transaction
+            if conn.auto_commit.get() {
+                match halt(program, state, pager, mv_store, 0, "")? {
+                    InsnFunctionStepResult::Done => {}
+                    result => return Ok(result),
+                }
+            }
+            return Err(LimboError::StatementAborted(description.clone()));
+        }
+        _ => {}
+    }
+    pager.clear_page_cache();
+    Err(LimboError::Constraint(description.clone()))
+}
+
+pub fn op_fk_counter(
+    program: &Program,
+    state: &mut ProgramState,
+    insn: &Insn,
+    _pager: &Rc<Pager>,
+    _mv_store: Option<&Arc<MvStore>>,
+) -> Result<InsnFunctionStepResult> {
+    load_insn!(
+        FkCounter {
+            increment_value,
+            deferred,
+        },
+        insn
+    );
+    // With PRAGMA defer_foreign_keys, immediate constraints are checked at commit time too
+    if *deferred || program.connection.get_defer_foreign_keys() {
+        let conn = &program.connection;
+        conn.deferred_fk_violations
+            .set(conn.deferred_fk_violations.get() + increment_value);
+    } else {
+        state.fk_violations += increment_value;
+    }
+    state.pc += 1;
+    Ok(InsnFunctionStepResult::Step)
+}
+
+pub fn op_fk_if_zero(
+    program: &Program,
+    state: &mut ProgramState,
+    insn: &Insn,
+    _pager: &Rc<Pager>,
+    _mv_store: Option<&Arc<MvStore>>,
+) -> Result<InsnFunctionStepResult> {
+    load_insn!(
+        FkIfZero {
+            deferred,
+            target_pc,
+        },
+        insn
+    );
+    assert!(target_pc.is_offset());
+    let violations = if *deferred || program.connection.get_defer_foreign_keys() {
+        program.connection.deferred_fk_violations.get()
+    } else {
+        state.fk_violations
+    };
+    if violations == 0 {
+        state.pc = target_pc.as_offset_int();
+    } else {
+        state.pc += 1;
+    }
+    Ok(InsnFunctionStepResult::Step)
+}
+
 pub fn op_halt_if_null(
     program: &Program,
     state: &mut ProgramState,
@@ -2089,7 +2275,7 @@ pub fn op_transaction(
     program: &Program,
     state: &mut ProgramState,
     insn: &Insn,
-    _pager: &Rc<Pager>,
+    pager: &Rc<Pager>,
     mv_store: Option<&Arc<MvStore>>,
 ) -> Result<InsnFunctionStepResult> {
     load_insn!(
@@ -2101,7 +2287,42 @@ pub fn op_transaction(
         insn
     );
     let conn = program.connection.clone();
+    if program.uses_statement_savepoint
+        && !conn.auto_commit.get()
+        && state.statement_savepoint.is_none()
+    {
+        // Capture the state from before the statement, before any of its transactions begin, so
+        // that RAISE(ABORT) can undo the statement without ending the transaction
+        state.statement_savepoint = Some(conn.savepoint(String::new(), false, pager, mv_store)?);
+    }
     let write = matches!(tx_mode, TransactionMode::Write);
+    if *db > 1 && mv_store.is_some() && write {
+        return Err(LimboError::ParseError(
+            "writing to attached databases is not supported with MVCC yet".to_string(),
+        ));
+    }
+    if *db == 1 || (*db > 1 && mv_store.is_none()) {
+        // The transactions of the temp database and of attached databases are tracked apart
+        // from the one of the main database. The temp database is private to the connection,
+        // so its transaction never conflicts with other connections.
+        match conn.begin_attached_transaction(*db, write) {
+            Err(LimboError::Busy) => return Ok(InsnFunctionStepResult::Busy),
+            result => result?,
+        }
+        let pager = program.get_pager_from_database_index(db);
+        match pager
+            .io
+            .block(|| pager.with_header(|header| header.schema_cookie.get()))
+        {
+            Ok(header_schema_cookie) if header_schema_cookie != *schema_cookie => {
+                return Err(LimboError::SchemaUpdated);
+            }
+            Ok(_) | Err(LimboError::Page1NotAlloc) => {}
+            Err(err) => return Err(err),
+        }
+        state.pc += 1;
+        return Ok(InsnFunctionStepResult::Step);
+    }
     if write && conn._db.open_flags.contains(OpenFlags::ReadOnly) {
         return Err(LimboError::ReadOnly);
     }
@@ -2298,7 +2519,18 @@ pub fn op_auto_commit(
     }
 
     if *auto_commit != conn.auto_commit.get() {
+        if *auto_commit && !*rollback && conn.deferred_fk_violations.get() > 0 {
+            // Like in SQLite, the transaction stays open so that the violations can be fixed
+            return Err(LimboError::TxError(
+                "FOREIGN KEY constraint failed".to_string(),
+            ));
+        }
+        // COMMIT and ROLLBACK end the transaction along with all of its savepoints.
+        conn.savepoints.borrow_mut().clear();
+        conn.end_foreign_key_transaction();
         if *rollback {
+            // Like in handle_program_error, before the main database releases its locks
+            conn.end_attached_transactions(true)?;
             // TODO(pere): add rollback I/O logic once we implement rollback journal
             if let Some(mv_store) = mv_store {
                 if let Some((tx_id, _)) = conn.mv_tx.get() {
@@ -2336,6 +2568,101 @@ pub fn op_auto_commit(
         .map(Into::into)
 }
 
+pub fn op_savepoint(
+    program: &Program,
+    state: &mut ProgramState,
+    insn: &Insn,
+    pager: &Rc<Pager>,
+    mv_store: Option<&Arc<MvStore>>,
+) -> Result<InsnFunctionStepResult> {
+    load_insn!(Savepoint { op, name }, insn);
+    let conn = program.connection.clone();
+    if let SavepointOp::Begin = op {
+        // Outside of a transaction, SAVEPOINT behaves like BEGIN DEFERRED.
+        let starts_transaction = conn.auto_commit.get();
+        conn.auto_commit.replace(false);
+        let savepoint = conn.savepoint(name.clone(), starts_transaction, pager, mv_store)?;
+        conn.savepoints.borrow_mut().push(savepoint);
+        state.pc += 1;
+        return Ok(InsnFunctionStepResult::Step);
+    }
+
+    let mut savepoints = conn.savepoints.borrow_mut();
+    let Some(idx) = savepoints
+        .iter()
+        .rposition(|savepoint| savepoint.name.eq_ignore_ascii_case(name))
+    else {
+        return Err(LimboError::TxError(format!("no such savepoint: {name}")));
+    };
+    match op {
+        SavepointOp::Release => {
+            let commits = idx == 0 && savepoints[0].starts_transaction;
+            savepoints.truncate(idx);
+            if commits {
+                // Releasing the savepoint that opened the transaction commits it when the
+                // program halts, just like COMMIT.
+                conn.auto_commit.replace(true);
+            }
+        }
+        SavepointOp::Rollback => {
+            conn.rollback_to_savepoint(&savepoints[idx], pager, mv_store)?;
+            savepoints.truncate(idx + 1);
+        }
+        SavepointOp::Begin => unreachable!(),
+    }
+    state.pc += 1;
+    Ok(InsnFunctionStepResult::Step)
+}
+
+pub fn op_vacuum(
+    program: &Program,
+    state: &mut ProgramState,
+    insn: &Insn,
+    _pager: &Rc<Pager>,
+    _mv_store: Option<&Arc<MvStore>>,
+) -> Result<InsnFunctionStepResult> {
+    load_insn!(Vacuum { db, into }, insn);
+    if *db > 0 {
+        return Err(LimboError::InvalidArgument(
+            "VACUUM of temp and attached databases is not supported".to_string(),
+        ));
+    }
+    let conn = program.connection.clone();
+    if state.op_vacuum_state.is_none() {
+        if !conn.auto_commit.get() {
+            return Err(LimboError::TxError(
+                "cannot VACUUM from within a transaction".to_string(),
+            ));
+        }
+        let into = match into {
+            Some(reg) => {
+                let Value::Text(path) = state.registers[*reg].get_value() else {
+                    return Err(LimboError::InvalidArgument("non-text filename".to_string()));
+                };
+                Some(path.as_str().to_string())
+            }
+            None => None,
+        };
+        state.op_vacuum_state = Some(Box::new(VacuumState::new(&conn, into)));
+    }
+    let vacuum = state
+        .op_vacuum_state
+        .as_mut()
+        .expect("vacuum state must be set");
+    match vacuum.step(&conn) {
+        Ok(IOResult::Done(())) => {}
+        Ok(IOResult::IO(io)) => return Ok(InsnFunctionStepResult::IO(io)),
+        Err(err) => {
+            // Dropping the state removes the incomplete copy
+            state.op_vacuum_state = None;
+            return Err(err);
+        }
+    }
+    state.op_vacuum_state = None;
+    state.pc += 1;
+    Ok(InsnFunctionStepResult::Step)
+}
+
 pub fn op_goto(
     program: &Program,
     state: &mut ProgramState,
@@ -4055,6 +4382,176 @@ pub fn op_sorter_next(
     Ok(InsnFunctionStepResult::Step)
 }
 
+pub fn op_window_open(
+    program: &Program,
+    state: &mut ProgramState,
+    insn: &Insn,
+    pager: &Rc<Pager>,
+    mv_store: Option<&Arc<MvStore>>,
+) -> Result<InsnFunctionStepResult> {
+    load_insn!(
+        WindowOpen {
+            cursor_id,
+            partition_keys,
+            order,
+            functions,
+        },
+        insn
+    );
+    let cursor = WindowCursor::new(
+        *partition_keys,
+        order.clone(),
+        functions.clone(),
+        &state.registers,
+    )?;
+    state
+        .cursors
+        .get_mut(*cursor_id)
+        .unwrap()
+        .replace(Cursor::new_window(cursor));
+    state.pc += 1;
+    Ok(InsnFunctionStepResult::Step)
+}
+
+pub fn op_window_add(
+    program: &Program,
+    state: &mut ProgramState,
+    insn: &Insn,
+    pager: &Rc<Pager>,
+    mv_store: Option<&Arc<MvStore>>,
+) -> Result<InsnFunctionStepResult> {
+    load_insn!(
+        WindowAdd {
+            cursor_id,
+            start_reg,
+            num_regs,
+            target_pc,
+        },
+        insn
+    );
+    assert!(target_pc.is_offset());
+    let row = state.registers[*start_reg..*start_reg + *num_regs]
+        .iter()
+        .map(|reg| reg.get_value().clone())
+        .collect();
+    let partition_completed = {
+        let cursor = state.get_cursor(*cursor_id);
+        cursor.as_window_mut().add(row)?
+    };
+    if partition_completed {
+        state.pc += 1;
+    } else {
+        state.pc = target_pc.as_offset_int();
+    }
+    Ok(InsnFunctionStepResult::Step)
+}
+
+pub fn op_window_flush(
+    program: &Program,
+    state: &mut ProgramState,
+    insn: &Insn,
+    pager: &Rc<Pager>,
+    mv_store: Option<&Arc<MvStore>>,
+) -> Result<InsnFunctionStepResult> {
+    load_insn!(
+        WindowFlush {
+            cursor_id,
+            pc_if_empty,
+        },
+        insn
+    );
+    assert!(pc_if_empty.is_offset());
+    let partition_completed = {
+        let cursor = state.get_cursor(*cursor_id);
+        cursor.as_window_mut().flush()?
+    };
+    if partition_completed {
+        state.pc += 1;
+    } else {
+        state.pc = pc_if_empty.as_offset_int();
+    }
+    Ok(InsnFunctionStepResult::Step)
+}
+
+pub fn op_window_frame_rewind(
+    program: &Program,
+    state: &mut ProgramState,
+    insn: &Insn,
+    pager: &Rc<Pager>,
+    mv_store: Option<&Arc<MvStore>>,
+) -> Result<InsnFunctionStepResult> {
+    load_insn!(
+        WindowFrameRewind {
+            cursor_id,
+            func,
+            pc_if_empty,
+        },
+        insn
+    );
+    assert!(pc_if_empty.is_offset());
+    let has_rows = {
+        let cursor = state.get_cursor(*cursor_id);
+        cursor.as_window_mut().frame_rewind(*func)
+    };
+    if has_rows {
+        state.pc += 1;
+    } else {
+        state.pc = pc_if_empty.as_offset_int();
+    }
+    Ok(InsnFunctionStepResult::Step)
+}
+
+pub fn op_window_frame_next(
+    program: &Program,
+    state: &mut ProgramState,
+    insn: &Insn,
+    pager: &Rc<Pager>,
+    mv_store: Option<&Arc<MvStore>>,
+) -> Result<InsnFunctionStepResult> {
+    load_insn!(
+        WindowFrameNext {
+            cursor_id,
+            pc_if_next,
+        },
+        insn
+    );
+    assert!(pc_if_next.is_offset());
+    let has_more = {
+        let cursor = state.get_cursor(*cursor_id);
+        cursor.as_window_mut().frame_next()
+    };
+    if has_more {
+        state.pc = pc_if_next.as_offset_int();
+    } else {
+        state.pc += 1;
+    }
+    Ok(InsnFunctionStepResult::Step)
+}
+
+pub fn op_window_frame_column(
+    program: &Program,
+    state: &mut ProgramState,
+    insn: &Insn,
+    pager: &Rc<Pager>,
+    mv_store: Option<&Arc<MvStore>>,
+) -> Result<InsnFunctionStepResult> {
+    load_insn!(
+        WindowFrameColumn {
+            cursor_id,
+            column,
+            dest,
+        },
+        insn
+    );
+    let value = {
+        let cursor = state.get_cursor(*cursor_id);
+        cursor.as_window_mut().frame_column(*column)
+    };
+    state.registers[*dest] = Register::Value(value);
+    state.pc += 1;
+    Ok(InsnFunctionStepResult::Step)
+}
+
 pub fn op_function(
     program: &Program,
     state: &mut ProgramState,
@@ -5144,7 +5641,7 @@ pub fn op_function(
                                 unique,
                                 if_not_exists,
                                 idx_name,
-                                where_clause,
+                                mut where_clause,
                             } => {
                                 if table != normalize_ident(tbl_name.as_str()) {
                                     break 'sql None;
@@ -5157,9 +5654,21 @@ pub fn op_function(
                                         {
                                             *id = column_def.col_name.as_str().to_owned();
                                         }
-                                        _ => {}
+                                        ast::Expr::Id(_) | ast::Expr::Name(_) => {}
+                                        expr => rename_index_expr_column(
+                                            expr,
+                                            &rename_from,
+                                            column_def.col_name.as_str(),
+                                        ),
                                     }
                                 }
+                                if let Some(where_clause) = where_clause.as_mut() {
+                                    rename_index_expr_column(
+                                        where_clause,
+                                        &rename_from,
+                                        column_def.col_name.as_str(),
+                                    );
+                                }
 
                                 Some(
                                     ast::Stmt::CreateIndex {
@@ -5192,6 +5701,21 @@ pub fn op_function(
                                     todo!()
                                 };
 
+                                // Generated columns refer to the column by name
+                                for column in columns.iter_mut() {
+                                    for constraint in column.constraints.iter_mut() {
+                                        if let ast::ColumnConstraint::Generated { expr, .. } =
+                                            &mut constraint.constraint
+                                        {
+                                            rename_index_expr_column(
+                                                expr,
+                                                &rename_from,
+                                                column_def.col_name.as_str(),
+                                            );
+                                        }
+                                    }
+                                }
+
                                 let column = columns
                                     .iter_mut()
                                     .find(|column| column.col_name == ast::Name::new(&rename_from))
@@ -5874,13 +6398,16 @@ pub fn op_idx_insert(
     match state.op_idx_insert_state {
         OpIdxInsertState::MaybeSeek => {
             let (_, cursor_type) = program.cursor_ref.get(cursor_id).unwrap();
-            let CursorType::BTreeIndex(index_meta) = cursor_type else {
-                panic!("IdxInsert: not a BTreeIndex cursor");
+            let unique = match cursor_type {
+                CursorType::BTreeIndex(index_meta) => index_meta.unique,
+                // A WITHOUT ROWID table is an index on its primary key
+                CursorType::BTreeTable(table) if !table.has_rowid => true,
+                _ => panic!("IdxInsert: not a BTreeIndex cursor"),
             };
 
             // TODO: currently we never pass USE_SEEK, so this other check is a bit redundant and we always seek,
             // but I guess it's FutureProofed™®
-            if !index_meta.unique && flags.has(IdxInsertFlags::USE_SEEK) {
+            if !unique && flags.has(IdxInsertFlags::USE_SEEK) {
                 state.op_idx_insert_state = OpIdxInsertState::Insert;
                 return Ok(InsnFunctionStepResult::Step);
             }
@@ -5896,7 +6423,7 @@ pub fn op_idx_insert(
                 SeekOp::GE { eq_only: true },
             )? {
                 SeekInternalResult::Found => {
-                    state.op_idx_insert_state = if index_meta.unique {
+                    state.op_idx_insert_state = if unique {
                         OpIdxInsertState::UniqueConstraintCheck
                     } else {
                         OpIdxInsertState::Insert
@@ -5965,6 +6492,12 @@ pub fn op_idx_insert(
             // Increment metrics for index write
             if flags.has(IdxInsertFlags::NCHANGE) {
                 state.metrics.rows_written = state.metrics.rows_written.saturating_add(1);
+                // A row of a WITHOUT ROWID table is inserted into its primary key index
+                let (_, cursor_type) = program.cursor_ref.get(cursor_id).unwrap();
+                if matches!(cursor_type, CursorType::BTreeTable(_)) {
+                    let prev_changes = program.n_change.get();
+                    program.n_change.set(prev_changes + 1);
+                }
             }
             state.op_idx_insert_state = OpIdxInsertState::MaybeSeek;
             state.pc += 1;
@@ -6385,11 +6918,20 @@ pub fn op_open_write(
     let (_, cursor_type) = program.cursor_ref.get(*cursor_id).unwrap();
     let cursors = &mut state.cursors;
     let maybe_index = match cursor_type {
-        CursorType::BTreeIndex(index) => Some(index),
+        CursorType::BTreeIndex(index) => Some(index.clone()),
+        // A WITHOUT ROWID table is stored as an index on its primary key
+        CursorType::BTreeTable(table) if !table.has_rowid => {
+            Some(Arc::new(Index::automatic_from_primary_key(
+                table,
+                (table.name.clone(), root_page as usize),
+                table.primary_key_columns.len(),
+            )?))
+        }
         _ => None,
     };
     let mv_cursor = match program.connection.mv_tx.get() {
-        Some((tx_id, _)) => {
+        // Index b-trees are written through an MVCC index cursor
+        Some((tx_id, _)) if maybe_index.is_none() => {
             let table_id = root_page;
             let mv_store = mv_store.unwrap().clone();
             let mv_cursor = Rc::new(RefCell::new(
@@ -6397,7 +6939,7 @@ pub fn op_open_write(
             ));
             Some(mv_cursor)
         }
-        None => None,
+        _ => None,
     };
     if let Some(index) = maybe_index {
         let conn = program.connection.clone();
@@ -6407,8 +6949,9 @@ pub fn op_open_write(
             .and_then(|table| table.btree());
 
         let num_columns = index.columns.len();
+        let mv_index_cursor = open_mv_index_cursor(program, mv_store, &index, pager.clone())?;
         let cursor = BTreeCursor::new_index(
-            mv_cursor,
+            mv_index_cursor,
             pager.clone(),
             root_page as usize,
             index.as_ref(),
@@ -6469,15 +7012,10 @@ pub fn op_create_btree(
 ) -> Result<InsnFunctionStepResult> {
     load_insn!(CreateBtree { db, root, flags }, insn);
 
-    assert_eq!(*db, 0);
-
     if program.connection.is_readonly(*db) {
         return Err(LimboError::ReadOnly);
     }
-    if *db > 0 {
-        // TODO: implement temp databases
-        todo!("temp databases not implemented yet");
-    }
+    let pager = program.get_pager_from_database_index(db);
     // FIXME: handle page cache is full
     let root_page = return_if_io!(pager.btree_create(flags));
     state.registers[*root] = Register::Value(Value::Integer(root_page as i64));
@@ -6500,9 +7038,7 @@ pub fn op_destroy(
         },
         insn
     );
-    if *is_temp == 1 {
-        todo!("temp databases not implemented yet.");
-    }
+    let pager = program.get_pager_from_database_index(is_temp);
     // TODO not sure if should be BTreeCursor::new_table or BTreeCursor::new_index here or neither and just pass an emtpy vec
     let mut cursor = BTreeCursor::new(None, pager.clone(), *root, 0);
     let former_root_page_result = cursor.btree_destroy()?;
@@ -6522,15 +7058,15 @@ pub fn op_drop_table(
     mv_store: Option<&Arc<MvStore>>,
 ) -> Result<InsnFunctionStepResult> {
     load_insn!(DropTable { db, table_name, .. }, insn);
-    if *db > 0 {
-        todo!("temp databases not implemented yet");
-    }
     let conn = program.connection.clone();
-    {
-        conn.with_schema_mut(|schema| {
-            schema.remove_indices_for_table(table_name);
-            schema.remove_table(table_name);
-        });
+    let drop_table = |schema: &mut Schema| {
+        schema.remove_indices_for_table(table_name);
+        schema.remove_table(table_name);
+    };
+    if *db > 0 {
+        conn.with_attached_schema_mut(*db, drop_table)?;
+    } else {
+        conn.with_schema_mut(drop_table);
     }
     state.pc += 1;
     Ok(InsnFunctionStepResult::Step)
@@ -6544,14 +7080,33 @@ pub fn op_drop_view(
     _mv_store: Option<&Arc<MvStore>>,
 ) -> Result<InsnFunctionStepResult> {
     load_insn!(DropView { db, view_name }, insn);
+    let conn = program.connection.clone();
     if *db > 0 {
-        todo!("temp databases not implemented yet");
+        conn.with_attached_schema_mut(*db, |schema| schema.remove_view(view_name))??;
+    } else {
+        conn.with_schema_mut(|schema| {
+            schema.remove_view(view_name)?;
+            Ok::<(), crate::LimboError>(())
+        })?;
     }
+    state.pc += 1;
+    Ok(InsnFunctionStepResult::Step)
+}
+
+pub fn op_drop_trigger(
+    program: &Program,
+    state: &mut ProgramState,
+    insn: &Insn,
+    _pager: &Rc<Pager>,
+    _mv_store: Option<&Arc<MvStore>>,
+) -> Result<InsnFunctionStepResult> {
+    load_insn!(DropTrigger { db, trigger_name }, insn);
     let conn = program.connection.clone();
-    conn.with_schema_mut(|schema| {
-        schema.remove_view(view_name)?;
-        Ok::<(), crate::LimboError>(())
-    })?;
+    if *db > 0 {
+        conn.with_attached_schema_mut(*db, |schema| schema.remove_trigger(trigger_name))??;
+    } else {
+        conn.with_schema_mut(|schema| schema.remove_trigger(trigger_name))?;
+    }
     state.pc += 1;
     Ok(InsnFunctionStepResult::Step)
 }
@@ -6617,10 +7172,7 @@ pub fn op_page_count(
     mv_store: Option<&Arc<MvStore>>,
 ) -> Result<InsnFunctionStepResult> {
     load_insn!(PageCount { db, dest }, insn);
-    if *db > 0 {
-        // TODO: implement temp databases
-        todo!("temp databases not implemented yet");
-    }
+    let pager = program.get_pager_from_database_index(db);
     let count = match pager.with_header(|header| header.database_size.get()) {
         Err(_) => 0.into(),
         Ok(IOResult::Done(v)) => v.into(),
@@ -6638,15 +7190,12 @@ pub fn op_parse_schema(
     pager: &Rc<Pager>,
     mv_store: Option<&Arc<MvStore>>,
 ) -> Result<InsnFunctionStepResult> {
-    load_insn!(
-        ParseSchema {
-            db: _,
-            where_clause,
-        },
-        insn
-    );
+    load_insn!(ParseSchema { db, where_clause }, insn);
 
     let conn = program.connection.clone();
+    if *db > 0 {
+        return op_parse_attached_schema(program, state, *db, where_clause.as_deref());
+    }
     // set auto commit to false in order for parse schema to not commit changes as transaction state is stored in connection,
     // and we use the same connection for nested query.
     let previous_auto_commit = conn.auto_commit.get();
@@ -6690,6 +7239,70 @@ pub fn op_parse_schema(
     Ok(InsnFunctionStepResult::Step)
 }
 
+/// ParseSchema on the temp database or on an attached database: the rows of its schema table
+/// are parsed into the schema the connection holds for it rather than into the main one.
+fn op_parse_attached_schema(
+    program: &Program,
+    state: &mut ProgramState,
+    db: usize,
+    where_clause: Option<&str>,
+) -> Result<InsnFunctionStepResult> {
+    let conn = program.connection.clone();
+    let Some(db_name) = conn.database_name(db) else {
+        return Err(LimboError::InternalError(format!(
+            "no database with index {db}"
+        )));
+    };
+    let previous_auto_commit = conn.auto_commit.get();
+    conn.auto_commit.set(false);
+
+    let schema_table = format!("\"{}\".sqlite_schema", db_name.replace('"', "\"\""));
+    let sql = match where_clause {
+        Some(where_clause) => format!("SELECT * FROM {schema_table} WHERE {where_clause}"),
+        None => format!("SELECT * FROM {schema_table}"),
+    };
+    let maybe_nested_stmt_err = conn.prepare(sql).and_then(|stmt| {
+        conn.with_attached_schema_mut(db, |schema| {
+            let existing_views = schema.incremental_views.clone();
+            conn.is_nested_stmt.set(true);
+            parse_schema_rows(
+                stmt,
+                schema,
+                &conn.syms.borrow(),
+                program.connection.mv_tx.get(),
+                existing_views,
+            )
+        })?
+    });
+    conn.is_nested_stmt.set(false);
+    conn.auto_commit.set(previous_auto_commit);
+    maybe_nested_stmt_err?;
+    state.pc += 1;
+    Ok(InsnFunctionStepResult::Step)
+}
+
+pub fn op_load_analysis(
+    program: &Program,
+    state: &mut ProgramState,
+    insn: &Insn,
+    pager: &Rc<Pager>,
+    mv_store: Option<&Arc<MvStore>>,
+) -> Result<InsnFunctionStepResult> {
+    load_insn!(LoadAnalysis { db: _ }, insn);
+
+    let conn = program.connection.clone();
+    // as in ParseSchema, the nested query must not commit the transaction of the connection.
+    let previous_auto_commit = conn.auto_commit.get();
+    conn.auto_commit.set(false);
+    conn.is_nested_stmt.set(true);
+    let maybe_nested_stmt_err = conn.load_analysis(program.connection.mv_tx.get());
+    conn.is_nested_stmt.set(false);
+    conn.auto_commit.set(previous_auto_commit);
+    maybe_nested_stmt_err?;
+    state.pc += 1;
+    Ok(InsnFunctionStepResult::Step)
+}
+
 pub fn op_populate_materialized_views(
     program: &Program,
     state: &mut ProgramState,
@@ -6775,10 +7388,7 @@ pub fn op_read_cookie(
     mv_store: Option<&Arc<MvStore>>,
 ) -> Result<InsnFunctionStepResult> {
     load_insn!(ReadCookie { db, dest, cookie }, insn);
-    if *db > 0 {
-        // TODO: implement temp databases
-        todo!("temp databases not implemented yet");
-    }
+    let pager = program.get_pager_from_database_index(db);
 
     let cookie_value = match pager.with_header(|header| match cookie {
         Cookie::ApplicationId => header.application_id.get().into(),
@@ -6813,9 +7423,7 @@ pub fn op_set_cookie(
         },
         insn
     );
-    if *db > 0 {
-        todo!("temp databases not implemented yet");
-    }
+    let pager = program.get_pager_from_database_index(db);
 
     return_if_io!(pager.with_header_mut(|header| {
         match cookie {
@@ -6827,6 +7435,9 @@ pub fn op_set_cookie(
             Cookie::IncrementalVacuum => {
                 header.incremental_vacuum_enabled = (*value as u32).into()
             }
+            Cookie::SchemaVersion if *db > 0 => {
+                header.schema_cookie = (*value as u32).into();
+            }
             Cookie::SchemaVersion => {
                 // we update transaction state to indicate that the schema has changed
                 match program.connection.transaction_state.get() {
@@ -6845,6 +7456,12 @@ pub fn op_set_cookie(
             cookie => todo!("{cookie:?} is not yet implement for SetCookie"),
         };
     }));
+    if *db > 0 && matches!(cookie, Cookie::SchemaVersion) {
+        // the temp and attached databases track their transactions apart from the main one
+        program
+            .connection
+            .set_attached_schema_version(*db, *value as u32)?;
+    }
 
     state.pc += 1;
     Ok(InsnFunctionStepResult::Step)
@@ -7153,13 +7770,7 @@ pub fn op_open_ephemeral(
             };
 
             let cursor = if let CursorType::BTreeIndex(index) = cursor_type {
-                BTreeCursor::new_index(
-                    mv_cursor,
-                    pager.clone(),
-                    root_page as usize,
-                    index,
-                    num_columns,
-                )
+                BTreeCursor::new_index(None, pager.clone(), root_page as usize, index, num_columns)
             } else {
                 BTreeCursor::new_table(mv_cursor, pager.clone(), root_page as usize, num_columns)
             };
@@ -7200,6 +7811,9 @@ pub fn op_open_ephemeral(
                 CursorType::Sorter => {
                     panic!("OpenEphemeral on sorter cursor");
                 }
+                CursorType::Window => {
+                    panic!("OpenEphemeral on window cursor");
+                }
                 CursorType::VirtualTable(_) => {
                     panic!("OpenEphemeral on virtual table cursor, use Insn::VOpen instead");
                 }
@@ -7216,6 +7830,50 @@ pub fn op_open_ephemeral(
     Ok(InsnFunctionStepResult::Step)
 }
 
+pub fn op_open_dup(
+    program: &Program,
+    state: &mut ProgramState,
+    insn: &Insn,
+    pager: &Rc<Pager>,
+    mv_store: Option<&Arc<MvStore>>,
+) -> Result<InsnFunctionStepResult> {
+    load_insn!(
+        OpenDup {
+            new_cursor_id,
+            original_cursor_id,
+        },
+        insn
+    );
+    let (ephemeral_pager, root_page) = {
+        let original_cursor = state.get_cursor(*original_cursor_id).as_btree_mut();
+        (original_cursor.pager(), original_cursor.root_page())
+    };
+    let mv_cursor = match program.connection.mv_tx.get() {
+        Some((tx_id, _)) => {
+            let mv_store = mv_store.unwrap().clone();
+            Some(Rc::new(RefCell::new(MvCursor::new(
+                mv_store,
+                tx_id,
+                root_page as u64,
+                ephemeral_pager.clone(),
+            )?)))
+        }
+        None => None,
+    };
+    let (_, cursor_type) = program.cursor_ref.get(*new_cursor_id).unwrap();
+    let CursorType::BTreeTable(table) = cursor_type else {
+        unreachable!("OpenDup on non-table cursor");
+    };
+    let cursor = BTreeCursor::new_table(mv_cursor, ephemeral_pager, root_page, table.columns.len());
+    state
+        .cursors
+        .get_mut(*new_cursor_id)
+        .unwrap()
+        .replace(Cursor::new_btree(cursor));
+    state.pc += 1;
+    Ok(InsnFunctionStepResult::Step)
+}
+
 /// Execute the [Insn::Once] instruction.
 ///
 /// This instruction is used to execute a block of code only once.
@@ -7392,9 +8050,10 @@ pub fn op_integrity_check(
 ) -> Result<InsnFunctionStepResult> {
     load_insn!(
         IntegrityCk {
-            max_errors,
+            errors_left_register,
             roots,
             message_register,
+            check_page_usage,
         },
         insn
     );
@@ -7436,6 +8095,17 @@ pub fn op_integrity_check(
                 *current_root_idx += 1;
                 return Ok(InsnFunctionStepResult::Step);
             } else {
+                // Pointer map pages of auto-vacuum databases are not part of any b-tree
+                let check_page_usage = *check_page_usage
+                    && matches!(pager.get_auto_vacuum_mode(), AutoVacuumMode::None);
+                let (database_size, page_size) = if check_page_usage {
+                    return_if_io!(pager.with_header(|header| (
+                        header.database_size.get() as usize,
+                        header.page_size.get() as usize
+                    )))
+                } else {
+                    (0, 0)
+                };
                 if integrity_check_state.freelist_count.actual_count
                     != integrity_check_state.freelist_count.expected_count
                 {
@@ -7444,16 +8114,30 @@ pub fn op_integrity_check(
                         expected_count: integrity_check_state.freelist_count.expected_count,
                     });
                 }
-                let message = if errors.is_empty() {
-                    "ok".to_string()
+                if check_page_usage {
+                    integrity_check_state.check_page_usage(database_size, page_size, errors);
+                }
+                let errors_left = match state.registers[*errors_left_register].get_value() {
+                    Value::Integer(n) => (*n).max(0) as usize,
+                    _ => {
+                        return Err(LimboError::InternalError(
+                            "IntegrityCk: the number of errors left is not an integer".into(),
+                        ))
+                    }
+                };
+                let reported = errors.len().min(errors_left);
+                state.registers[*message_register] = if reported == 0 {
+                    Register::Value(Value::Null)
                 } else {
-                    errors
+                    let message = errors[..reported]
                         .iter()
                         .map(|e| e.to_string())
                         .collect::<Vec<String>>()
-                        .join("\n")
+                        .join("\n");
+                    Register::Value(Value::build_text(message))
                 };
-                state.registers[*message_register] = Register::Value(Value::build_text(message));
+                state.registers[*errors_left_register] =
+                    Register::Value(Value::Integer((errors_left - reported) as i64));
                 state.op_integrity_check_state = OpIntegrityCheckState::Start;
                 state.pc += 1;
             }
@@ -7633,6 +8317,20 @@ pub fn op_alter_column(
 
         let btree = Arc::make_mut(btree);
 
+        let old_name = btree.columns[*column_index]
+            .name
+            .clone()
+            .expect("btree column should be named");
+        for column in &mut btree.columns {
+            if let Some(generated) = column.generated.as_mut() {
+                rename_index_expr_column(
+                    &mut generated.expr,
+                    &old_name,
+                    definition.col_name.as_str(),
+                );
+            }
+        }
+
         let column = btree
             .columns
             .get_mut(*column_index)
@@ -7641,13 +8339,22 @@ pub fn op_alter_column(
         if let Some(indexes) = schema.indexes.get_mut(table_name) {
             for index in indexes {
                 let index = Arc::make_mut(index);
+                let column_name = column.name.as_ref().expect("btree column should be named");
                 for index_column in &mut index.columns {
-                    if index_column.name
-                        == *column.name.as_ref().expect("btree column should be named")
-                    {
+                    if let Some(expr) = index_column.expr.as_mut() {
+                        rename_index_expr_column(expr, column_name, definition.col_name.as_str());
+                        index_column.name = expr.to_string();
+                    } else if index_column.name == *column_name {
                         index_column.name = definition.col_name.as_str().to_owned();
                     }
                 }
+                if let Some(where_clause) = index.where_clause.as_mut() {
+                    rename_index_expr_column(
+                        where_clause,
+                        column_name,
+                        definition.col_name.as_str(),
+                    );
+                }
             }
         }
 
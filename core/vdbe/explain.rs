@@ -21,6 +21,7 @@ pub fn insn_to_row(
             CursorType::VirtualTable(virtual_table) => &virtual_table.name,
             CursorType::MaterializedView(table, _) => &table.name,
             CursorType::Sorter => "sorter",
+            CursorType::Window => "window",
         }
     };
     match insn {
@@ -547,6 +548,7 @@ pub fn insn_to_row(
                     }
                     CursorType::Pseudo(_) => None,
                     CursorType::Sorter => None,
+                    CursorType::Window => None,
                     CursorType::VirtualTable(v) => v.columns.get(*column).unwrap().name.as_ref(),
                 };
                 (
@@ -639,6 +641,18 @@ pub fn insn_to_row(
                 0,
                 "".to_string(),
             ),
+            Insn::Raise {
+                resolve_type,
+                description,
+            } => (
+                "Raise",
+                0,
+                0,
+                0,
+                Value::build_text(description),
+                0,
+                format!("{resolve_type:?}"),
+            ),
             Insn::HaltIfNull {
                 err_code,
                 target_reg,
@@ -652,6 +666,37 @@ pub fn insn_to_row(
                 0,
                 "".to_string(),
             ),
+            Insn::FkCounter {
+                increment_value,
+                deferred,
+            } => (
+                "FkCounter",
+                *deferred as i32,
+                *increment_value as i32,
+                0,
+                Value::build_text(""),
+                0,
+                format!(
+                    "{}fk_counter += {increment_value}",
+                    if *deferred { "deferred_" } else { "" }
+                ),
+            ),
+            Insn::FkIfZero {
+                deferred,
+                target_pc,
+            } => (
+                "FkIfZero",
+                *deferred as i32,
+                target_pc.as_debug_int(),
+                0,
+                Value::build_text(""),
+                0,
+                format!(
+                    "if {}fk_counter == 0 goto {}",
+                    if *deferred { "deferred_" } else { "" },
+                    target_pc.as_debug_int()
+                ),
+            ),
             Insn::Transaction { db, tx_mode, schema_cookie} => (
                 "Transaction",
                 *db as i32,
@@ -1033,6 +1078,97 @@ pub fn insn_to_row(
                 0,
                 "".to_string(),
             ),
+            Insn::WindowOpen {
+                cursor_id,
+                partition_keys,
+                order,
+                functions,
+            } => (
+                "WindowOpen",
+                *cursor_id as i32,
+                *partition_keys as i32,
+                order.len() as i32,
+                Value::build_text(
+                    functions
+                        .iter()
+                        .map(|f| match f.func {
+                            Some(func) => func.to_string(),
+                            None => "agg".to_string(),
+                        })
+                        .collect::<Vec<_>>()
+                        .join(","),
+                ),
+                0,
+                format!("cursor={cursor_id}"),
+            ),
+            Insn::WindowAdd {
+                cursor_id,
+                start_reg,
+                num_regs,
+                target_pc,
+            } => (
+                "WindowAdd",
+                *cursor_id as i32,
+                target_pc.as_debug_int(),
+                *start_reg as i32,
+                Value::build_text(""),
+                0,
+                format!(
+                    "window[{cursor_id}] add r[{}..{}]",
+                    start_reg,
+                    start_reg + num_regs
+                ),
+            ),
+            Insn::WindowFlush {
+                cursor_id,
+                pc_if_empty,
+            } => (
+                "WindowFlush",
+                *cursor_id as i32,
+                pc_if_empty.as_debug_int(),
+                0,
+                Value::build_text(""),
+                0,
+                "".to_string(),
+            ),
+            Insn::WindowFrameRewind {
+                cursor_id,
+                func,
+                pc_if_empty,
+            } => (
+                "WindowFrameRewind",
+                *cursor_id as i32,
+                pc_if_empty.as_debug_int(),
+                *func as i32,
+                Value::build_text(""),
+                0,
+                "".to_string(),
+            ),
+            Insn::WindowFrameNext {
+                cursor_id,
+                pc_if_next,
+            } => (
+                "WindowFrameNext",
+                *cursor_id as i32,
+                pc_if_next.as_debug_int(),
+                0,
+                Value::build_text(""),
+                0,
+                "".to_string(),
+            ),
+            Insn::WindowFrameColumn {
+                cursor_id,
+                column,
+                dest,
+            } => (
+                "WindowFrameColumn",
+                *cursor_id as i32,
+                *column as i32,
+                *dest as i32,
+                Value::build_text(""),
+                0,
+                format!("r[{dest}]=window[{cursor_id}].frame[{column}]"),
+            ),
             Insn::Function {
                 constant_mask,
                 start_reg,
@@ -1294,6 +1430,15 @@ pub fn insn_to_row(
                 0,
                 format!("DROP VIEW {view_name}"),
             ),
+            Insn::DropTrigger { db, trigger_name } => (
+                "DropTrigger",
+                *db as i32,
+                0,
+                0,
+                Value::build_text(trigger_name),
+                0,
+                format!("DROP TRIGGER {trigger_name}"),
+            ),
             Insn::DropIndex { db: _, index } => (
                 "DropIndex",
                 0,
@@ -1342,6 +1487,15 @@ pub fn insn_to_row(
                 0,
                 where_clause.clone().unwrap_or("NULL".to_string()),
             ),
+            Insn::LoadAnalysis { db } => (
+                "LoadAnalysis",
+                *db as i32,
+                0,
+                0,
+                Value::build_text(""),
+                0,
+                "".to_string(),
+            ),
             Insn::PopulateMaterializedViews { cursors } => (
                 "PopulateMaterializedViews",
                 0,
@@ -1491,6 +1645,27 @@ pub fn insn_to_row(
                 0,
                 format!("auto_commit={auto_commit}, rollback={rollback}"),
             ),
+            Insn::Vacuum { db, into } => (
+                "Vacuum",
+                *db as i32,
+                into.map_or(0, |reg| reg as i32),
+                0,
+                Value::build_text(""),
+                0,
+                match into {
+                    Some(reg) => format!("vacuum db={db} into r[{reg}]"),
+                    None => format!("vacuum db={db}"),
+                },
+            ),
+            Insn::Savepoint { op, name } => (
+                "Savepoint",
+                *op as i32,
+                0,
+                0,
+                Value::build_text(name.clone()),
+                0,
+                format!("{op:?} {name}"),
+            ),
             Insn::OpenEphemeral {
                 cursor_id,
                 is_table,
@@ -1516,6 +1691,18 @@ pub fn insn_to_row(
                 0,
                 format!("cursor={cursor_id}"),
             ),
+            Insn::OpenDup {
+                new_cursor_id,
+                original_cursor_id,
+            } => (
+                "OpenDup",
+                *new_cursor_id as i32,
+                *original_cursor_id as i32,
+                0,
+                Value::build_text(""),
+                0,
+                format!("cursor={new_cursor_id} original_cursor={original_cursor_id}"),
+            ),
             Insn::Once {
                 target_pc_when_reentered,
             } => (
@@ -1620,16 +1807,17 @@ pub fn insn_to_row(
                 format!("r[{}]={}", *out_reg, *value),
             ),
             Insn::IntegrityCk {
-                max_errors,
+                errors_left_register,
                 roots,
                 message_register,
+                check_page_usage,
             } => (
                 "IntegrityCk",
-                *max_errors as i32,
-                0,
+                *errors_left_register as i32,
+                *message_register as i32,
                 0,
                 Value::build_text(""),
-                0,
+                *check_page_usage as u16,
                 format!("roots={roots:?} message_register={message_register}"),
             ),
             Insn::RowData { cursor_id, dest } => (
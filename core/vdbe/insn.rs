@@ -3,7 +3,10 @@ use std::{
     sync::Arc,
 };
 
-use super::{execute, AggFunc, BranchOffset, CursorID, FuncCtx, InsnFunction, PageIdx};
+use super::{
+    execute, window::WindowFunctionSpec, AggFunc, BranchOffset, CursorID, FuncCtx, InsnFunction,
+    PageIdx,
+};
 use crate::{
     schema::{Affinity, BTreeTable, Column, Index},
     storage::{pager::CreateBTreeFlags, wal::CheckpointMode},
@@ -11,7 +14,7 @@ use crate::{
     Value,
 };
 use turso_macros::Description;
-use turso_parser::ast::SortOrder;
+use turso_parser::ast::{ResolveType, SortOrder};
 
 /// Flags provided to comparison instructions (e.g. Eq, Ne) which determine behavior related to NULL values.
 #[derive(Clone, Copy, Debug, Default)]
@@ -456,6 +459,13 @@ pub enum Insn {
         description: String,
     },
 
+    /// Halt the program with the error raised by RAISE(ROLLBACK|ABORT|FAIL, description) in a
+    /// trigger, undoing the transaction, the statement or nothing, respectively.
+    Raise {
+        resolve_type: ResolveType,
+        description: String,
+    },
+
     /// Halt the program if P3 is null.
     HaltIfNull {
         target_reg: usize,   // P3
@@ -463,6 +473,20 @@ pub enum Insn {
         err_code: usize,     // p1
     },
 
+    /// Add increment_value to the counter of foreign key constraint violations. The counter is
+    /// the one of the connection if deferred is set, or the one of the statement otherwise.
+    FkCounter {
+        increment_value: i64, // P2
+        deferred: bool,       // P1
+    },
+
+    /// Jump to target_pc if the counter of foreign key constraint violations is zero. The counter
+    /// is the one of the connection if deferred is set, or the one of the statement otherwise.
+    FkIfZero {
+        deferred: bool,          // P1
+        target_pc: BranchOffset, // P2
+    },
+
     /// Start a transaction.
     Transaction {
         db: usize,                // p1
@@ -476,6 +500,19 @@ pub enum Insn {
         rollback: bool,
     },
 
+    /// Rebuild database P1 into a fresh file and swap it in, or, if `into` is set, write the
+    /// compacted copy to the file named by that register instead.
+    Vacuum {
+        db: usize,
+        into: Option<usize>,
+    },
+
+    /// Open, release or roll back to the savepoint with the given name.
+    Savepoint {
+        op: SavepointOp,
+        name: String,
+    },
+
     /// Branch to the given PC.
     Goto {
         target_pc: BranchOffset,
@@ -705,6 +742,53 @@ pub enum Insn {
         pc_if_next: BranchOffset,
     },
 
+    /// Open a window cursor, which buffers the rows of one window partition at a time.
+    /// Rows must be added in PARTITION BY, ORDER BY order.
+    WindowOpen {
+        cursor_id: CursorID,
+        partition_keys: usize,
+        order: Vec<SortOrder>,
+        functions: Vec<WindowFunctionSpec>,
+    },
+
+    /// Add the row in registers start_reg..start_reg+num_regs to the window cursor.
+    /// If the row starts a new partition, the previous partition becomes readable
+    /// and execution falls through. Otherwise, jump to target_pc.
+    WindowAdd {
+        cursor_id: CursorID,
+        start_reg: usize,
+        num_regs: usize,
+        target_pc: BranchOffset,
+    },
+
+    /// Make the last partition of the window cursor readable.
+    /// Jump to pc_if_empty if no rows were added since the last partition was completed.
+    WindowFlush {
+        cursor_id: CursorID,
+        pc_if_empty: BranchOffset,
+    },
+
+    /// Position the frame iterator of the window cursor on the first row of the frame of
+    /// window function `func` for the current row. Jump to pc_if_empty if the frame is empty.
+    WindowFrameRewind {
+        cursor_id: CursorID,
+        func: usize,
+        pc_if_empty: BranchOffset,
+    },
+
+    /// Advance the frame iterator of the window cursor, jumping to pc_if_next if there are more rows in the frame.
+    WindowFrameNext {
+        cursor_id: CursorID,
+        pc_if_next: BranchOffset,
+    },
+
+    /// Read a column of the frame row the frame iterator of the window cursor is positioned on.
+    WindowFrameColumn {
+        cursor_id: CursorID,
+        column: usize,
+        dest: usize,
+    },
+
     /// Function
     Function {
         constant_mask: i32, // P1
@@ -836,7 +920,7 @@ pub enum Insn {
         root: usize,
         /// Register to store the former value of any moved root page (for AUTOVACUUM)
         former_root_reg: usize,
-        /// Whether this is a temporary table (1) or main database table (0)
+        /// The database of the table: 0 for main, 1 for temp and above for attached databases
         is_temp: usize,
     },
 
@@ -857,6 +941,12 @@ pub enum Insn {
         /// The name of the view being dropped
         view_name: String,
     },
+    DropTrigger {
+        /// The database within which this trigger needs to be dropped
+        db: usize,
+        /// The name of the trigger being dropped
+        trigger_name: String,
+    },
     DropIndex {
         ///  The database within which this index needs to be dropped (P1).
         db: usize,
@@ -898,6 +988,12 @@ pub enum Insn {
         where_clause: Option<String>,
     },
 
+    /// Reload the statistics in sqlite_stat1 into the schema, so that the query optimizer
+    /// uses the statistics collected by ANALYZE.
+    LoadAnalysis {
+        db: usize,
+    },
+
     /// Populate all materialized views after schema parsing
     /// The cursors parameter contains a mapping of view names to cursor IDs that have been
     /// opened to the view's btree for writing the materialized data
@@ -993,6 +1089,12 @@ pub enum Insn {
     OpenAutoindex {
         cursor_id: usize,
     },
+    /// Open a new cursor P1 to the same transient table as cursor P2, which must have been
+    /// opened with OpenEphemeral. Both cursors see the same rows but move independently.
+    OpenDup {
+        new_cursor_id: CursorID,
+        original_cursor_id: CursorID,
+    },
     /// Fall through to the next instruction on the first invocation, otherwise jump to target_pc
     Once {
         target_pc_when_reentered: BranchOffset,
@@ -1036,16 +1138,17 @@ pub enum Insn {
         exact: bool,
     },
 
-    /// Do an analysis of the currently open database. Store in register (P1+1) the text of an error message describing any problems.
-    /// If no problems are found, store a NULL in register (P1+1).
-    /// The register (P1) contains one less than the maximum number of allowed errors.
-    /// At most reg(P1) errors will be reported. In other words, the analysis stops as soon as reg(P1) errors are seen.
-    /// Reg(P1) is updated with the number of errors remaining. The root page numbers of all tables in the database are integers
-    /// stored in P4_INTARRAY argument. If P5 is not zero, the check is done on the auxiliary database file, not the main database file. This opcode is used to implement the integrity_check pragma.
+    /// Do an analysis of the currently open database. Store in register (message_register) the text of an error message describing any problems.
+    /// If no problems are found, store a NULL in register (message_register).
+    /// The register (errors_left_register) contains the maximum number of errors to report. At most that many errors are reported,
+    /// and the register is updated with the number of errors that can still be reported. The root page numbers of all b-trees to check are in roots.
+    /// If check_page_usage is set, every page of the database must belong to one of the b-trees or to the freelist. This opcode is used to implement
+    /// the integrity_check and quick_check pragmas.
     IntegrityCk {
-        max_errors: usize,
+        errors_left_register: usize,
         roots: Vec<usize>,
         message_register: usize,
+        check_page_usage: bool,
     },
     RenameTable {
         from: String,
@@ -1144,9 +1247,14 @@ impl Insn {
             Insn::Next { .. } => execute::op_next,
             Insn::Prev { .. } => execute::op_prev,
             Insn::Halt { .. } => execute::op_halt,
+            Insn::Raise { .. } => execute::op_raise,
             Insn::HaltIfNull { .. } => execute::op_halt_if_null,
+            Insn::FkCounter { .. } => execute::op_fk_counter,
+            Insn::FkIfZero { .. } => execute::op_fk_if_zero,
             Insn::Transaction { .. } => execute::op_transaction,
             Insn::AutoCommit { .. } => execute::op_auto_commit,
+            Insn::Savepoint { .. } => execute::op_savepoint,
+            Insn::Vacuum { .. } => execute::op_vacuum,
             Insn::Goto { .. } => execute::op_goto,
             Insn::Gosub { .. } => execute::op_gosub,
             Insn::Return { .. } => execute::op_return,
@@ -1177,6 +1285,12 @@ impl Insn {
             Insn::SorterSort { .. } => execute::op_sorter_sort,
             Insn::SorterData { .. } => execute::op_sorter_data,
             Insn::SorterNext { .. } => execute::op_sorter_next,
+            Insn::WindowOpen { .. } => execute::op_window_open,
+            Insn::WindowAdd { .. } => execute::op_window_add,
+            Insn::WindowFlush { .. } => execute::op_window_flush,
+            Insn::WindowFrameRewind { .. } => execute::op_window_frame_rewind,
+            Insn::WindowFrameNext { .. } => execute::op_window_frame_next,
+            Insn::WindowFrameColumn { .. } => execute::op_window_frame_column,
             Insn::Function { .. } => execute::op_function,
             Insn::Cast { .. } => execute::op_cast,
             Insn::InitCoroutine { .. } => execute::op_init_coroutine,
@@ -1199,10 +1313,12 @@ impl Insn {
 
             Insn::DropTable { .. } => execute::op_drop_table,
             Insn::DropView { .. } => execute::op_drop_view,
+            Insn::DropTrigger { .. } => execute::op_drop_trigger,
             Insn::Close { .. } => execute::op_close,
             Insn::IsNull { .. } => execute::op_is_null,
             Insn::CollSeq { .. } => execute::op_coll_seq,
             Insn::ParseSchema { .. } => execute::op_parse_schema,
+            Insn::LoadAnalysis { .. } => execute::op_load_analysis,
             Insn::PopulateMaterializedViews { .. } => execute::op_populate_materialized_views,
             Insn::ShiftRight { .. } => execute::op_shift_right,
             Insn::ShiftLeft { .. } => execute::op_shift_left,
@@ -1218,6 +1334,7 @@ impl Insn {
             Insn::ReadCookie { .. } => execute::op_read_cookie,
             Insn::SetCookie { .. } => execute::op_set_cookie,
             Insn::OpenEphemeral { .. } | Insn::OpenAutoindex { .. } => execute::op_open_ephemeral,
+            Insn::OpenDup { .. } => execute::op_open_dup,
             Insn::Once { .. } => execute::op_once,
             Insn::Found { .. } | Insn::NotFound { .. } => execute::op_found,
             Insn::Affinity { .. } => execute::op_affinity,
@@ -1256,3 +1373,14 @@ pub enum Cookie {
     /// The application ID as set by the application_id pragma.
     ApplicationId = 8,
 }
+
+/// The operation performed by [Insn::Savepoint].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SavepointOp {
+    /// Open a new savepoint.
+    Begin = 0,
+    /// Release a savepoint along with every savepoint opened after it.
+    Release = 1,
+    /// Roll back to a savepoint, which stays open.
+    Rollback = 2,
+}
@@ -24,6 +24,7 @@ pub mod insn;
 pub mod likeop;
 pub mod metrics;
 pub mod sorter;
+pub mod window;
 
 use crate::{
     error::LimboError,
@@ -52,7 +53,8 @@ use crate::{
 
 #[cfg(feature = "json")]
 use crate::json::JsonCacheCell;
-use crate::{Connection, MvStore, Result, TransactionState};
+use crate::vacuum::VacuumState;
+use crate::{Connection, MvStore, Result, Savepoint, TransactionState};
 use builder::{CursorKey, QueryMode};
 use execute::{
     InsnFunction, InsnFunctionStepResult, OpIdxDeleteState, OpIntegrityCheckState,
@@ -292,6 +294,12 @@ pub struct ProgramState {
     op_row_id_state: OpRowIdState,
     /// State machine for committing view deltas with I/O handling
     view_delta_state: ViewDeltaCommitState,
+    /// The number of immediate foreign key constraint violations caused by the statement
+    fk_violations: i64,
+    /// The state of the transaction before the statement, if the program uses it
+    statement_savepoint: Option<Savepoint>,
+    /// Progress of a VACUUM that waits for IO
+    op_vacuum_state: Option<Box<VacuumState>>,
 }
 
 impl ProgramState {
@@ -334,6 +342,9 @@ impl ProgramState {
             op_column_state: OpColumnState::Start,
             op_row_id_state: OpRowIdState::Start,
             view_delta_state: ViewDeltaCommitState::NotStarted,
+            fk_violations: 0,
+            statement_savepoint: None,
+            op_vacuum_state: None,
         }
     }
 
@@ -417,6 +428,9 @@ impl ProgramState {
         self.current_collation = None;
         self.op_column_state = OpColumnState::Start;
         self.op_row_id_state = OpRowIdState::Start;
+        self.fk_violations = 0;
+        self.statement_savepoint = None;
+        self.op_vacuum_state = None;
         self.view_delta_state = ViewDeltaCommitState::NotStarted;
     }
 
@@ -489,6 +503,9 @@ pub struct Program {
     /// Used to determine whether we need to check for schema changes when
     /// starting a transaction.
     pub accesses_db: bool,
+    /// Whether the state of the transaction before the statement is captured, so that the
+    /// statement can be undone without ending the transaction.
+    pub uses_statement_savepoint: bool,
 }
 
 impl Program {
@@ -806,7 +823,18 @@ impl Program {
         // Reset state for next use
         program_state.view_delta_state = ViewDeltaCommitState::NotStarted;
 
+        if self.connection.auto_commit.get() && !self.connection.is_nested_stmt.get() {
+            // The transactions of the temp and attached databases end right away, before the
+            // one of the main database. A commit writing to several database files is made
+            // atomic by a super-journal, deleted once the main database is committed: until
+            // then, the databases committed before keep their locks and can be rolled back.
+            if !rollback {
+                self.connection.write_super_journal()?;
+            }
+            self.connection.end_attached_transactions(rollback)?;
+        }
         if self.connection.transaction_state.get() == TransactionState::None {
+            self.connection.delete_super_journal()?;
             // No need to do any work here if not in tx. Current MVCC logic doesn't work with this assumption,
             // hence the mv_store.is_none() check.
             return Ok(IOResult::Done(()));
@@ -907,6 +935,7 @@ impl Program {
                     self.connection.set_changes(self.n_change.get());
                 }
                 connection.transaction_state.replace(TransactionState::None);
+                connection.delete_super_journal()?;
                 *commit_state = CommitState::Ready;
             }
             IOResult::IO(io) => {
@@ -1077,7 +1106,12 @@ pub fn handle_program_error(
         LimboError::TableLocked => {}
         // Busy errors do not cause a rollback.
         LimboError::Busy => {}
+        // RAISE(ABORT) and RAISE(FAIL) already dealt with the changes of the statement.
+        LimboError::StatementAborted(_) => {}
         _ => {
+            // A commit under a super-journal is rolled back while the main database still holds
+            // its locks, see Connection::rollback_super_journal
+            let attached_result = connection.end_attached_transactions(true);
             if let Some(mv_store) = mv_store {
                 if let Some((tx_id, _)) = connection.mv_tx.get() {
                     connection.transaction_state.replace(TransactionState::None);
@@ -1092,7 +1126,10 @@ pub fn handle_program_error(
                         tracing::error!("end_tx failed: {e}");
                     })?;
             }
+            attached_result?;
             connection.transaction_state.replace(TransactionState::None);
+            connection.savepoints.borrow_mut().clear();
+            connection.end_foreign_key_transaction();
         }
     }
     Ok(())
@@ -0,0 +1,479 @@
+//! Runtime support for window functions.
+//!
+//! Rows reach a [WindowCursor] already sorted by the PARTITION BY and ORDER BY terms of the
+//! window. The cursor buffers the rows of one partition at a time. Once a partition is complete,
+//! the built-in window functions (row_number(), lag(), nth_value(), ...) are evaluated for every
+//! row of the partition, and their results are appended to each row as extra columns, so that the
+//! partition can be read back with the regular Rewind/Column/Next instructions.
+//!
+//! Aggregate window functions are not evaluated here. Instead, the program iterates over the frame
+//! of the current row with WindowFrameRewind/WindowFrameNext and feeds the frame rows to AggStep,
+//! so that every aggregate function supported in a GROUP BY can also be used over a window.
+
+use std::{cmp::Ordering, ops::Range};
+
+use turso_parser::ast::{FrameExclude, FrameMode, SortOrder};
+
+use super::Register;
+use crate::{
+    function::WindowFunc, types::Value, util::checked_cast_text_to_numeric, LimboError, Result,
+};
+
+/// A bound of a window frame. At translation time the PRECEDING/FOLLOWING offsets are the
+/// registers the offset expressions were evaluated into; at runtime they are the offset values.
+#[derive(Debug, Clone, PartialEq)]
+pub enum WindowFrameBound<T> {
+    UnboundedPreceding,
+    Preceding(T),
+    CurrentRow,
+    Following(T),
+    UnboundedFollowing,
+}
+
+/// The frame of a window function, e.g. `ROWS BETWEEN 1 PRECEDING AND CURRENT ROW`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WindowFrame<T> {
+    pub mode: FrameMode,
+    pub start: WindowFrameBound<T>,
+    pub end: WindowFrameBound<T>,
+    pub exclude: FrameExclude,
+}
+
+impl WindowFrame<usize> {
+    /// Reads the frame offsets from the registers they were evaluated into.
+    fn resolve(&self, registers: &[Register]) -> Result<WindowFrame<Value>> {
+        Ok(WindowFrame {
+            mode: self.mode,
+            start: resolve_frame_bound(&self.start, self.mode, registers, "starting")?,
+            end: resolve_frame_bound(&self.end, self.mode, registers, "ending")?,
+            exclude: self.exclude.clone(),
+        })
+    }
+}
+
+fn resolve_frame_bound(
+    bound: &WindowFrameBound<usize>,
+    mode: FrameMode,
+    registers: &[Register],
+    which: &str,
+) -> Result<WindowFrameBound<Value>> {
+    let resolve_offset = |reg: usize| -> Result<Value> {
+        let value = match registers[reg].get_value() {
+            Value::Text(text) => checked_cast_text_to_numeric(text.as_str()).unwrap_or(Value::Null),
+            value => value.clone(),
+        };
+        match (mode, value) {
+            (FrameMode::Range, Value::Integer(i)) if i >= 0 => Ok(Value::Integer(i)),
+            (FrameMode::Range, Value::Float(f)) if f >= 0.0 => Ok(Value::Float(f)),
+            (FrameMode::Range, _) => Err(LimboError::Constraint(format!(
+                "frame {which} offset must be a non-negative number"
+            ))),
+            (_, Value::Integer(i)) if i >= 0 => Ok(Value::Integer(i)),
+            (_, Value::Float(f)) if f >= 0.0 && f.fract() == 0.0 => Ok(Value::Integer(f as i64)),
+            _ => Err(LimboError::Constraint(format!(
+                "frame {which} offset must be a non-negative integer"
+            ))),
+        }
+    };
+    Ok(match bound {
+        WindowFrameBound::UnboundedPreceding => WindowFrameBound::UnboundedPreceding,
+        WindowFrameBound::Preceding(reg) => WindowFrameBound::Preceding(resolve_offset(*reg)?),
+        WindowFrameBound::CurrentRow => WindowFrameBound::CurrentRow,
+        WindowFrameBound::Following(reg) => WindowFrameBound::Following(resolve_offset(*reg)?),
+        WindowFrameBound::UnboundedFollowing => WindowFrameBound::UnboundedFollowing,
+    })
+}
+
+/// A window function evaluated over the rows of a [WindowCursor].
+#[derive(Debug, Clone)]
+pub struct WindowFunctionSpec {
+    /// The built-in window function, or `None` for an aggregate window function.
+    /// Aggregate window functions are evaluated by the program itself.
+    pub func: Option<WindowFunc>,
+    /// Column of the first argument of the function in the buffered rows.
+    pub arg_start: usize,
+    /// Number of arguments of the function.
+    pub arg_count: usize,
+    pub frame: WindowFrame<usize>,
+}
+
+pub struct WindowCursor {
+    /// Number of PARTITION BY columns at the start of every row.
+    partition_keys: usize,
+    /// Sort order of the ORDER BY columns, which follow the PARTITION BY columns.
+    order: Vec<SortOrder>,
+    functions: Vec<WindowFunctionSpec>,
+    frames: Vec<WindowFrame<Value>>,
+    /// Rows of the partition that is still being accumulated.
+    pending: Vec<Vec<Value>>,
+    /// Rows of the complete partition that is being read.
+    rows: Vec<Vec<Value>>,
+    /// Ranges of peer rows (rows with equal ORDER BY values) in `rows`.
+    peer_groups: Vec<Range<usize>>,
+    /// Index into `peer_groups` for every row in `rows`.
+    row_peer_group: Vec<usize>,
+    current: Option<usize>,
+    /// Rows of the frame being iterated for the current row.
+    frame: Vec<usize>,
+    frame_pos: usize,
+}
+
+impl WindowCursor {
+    pub fn new(
+        partition_keys: usize,
+        order: Vec<SortOrder>,
+        functions: Vec<WindowFunctionSpec>,
+        registers: &[Register],
+    ) -> Result<Self> {
+        let frames = functions
+            .iter()
+            .map(|f| f.frame.resolve(registers))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self {
+            partition_keys,
+            order,
+            functions,
+            frames,
+            pending: Vec::new(),
+            rows: Vec::new(),
+            peer_groups: Vec::new(),
+            row_peer_group: Vec::new(),
+            current: None,
+            frame: Vec::new(),
+            frame_pos: 0,
+        })
+    }
+
+    /// Adds a row to the partition being accumulated.
+    /// If the row starts a new partition, the previous partition is completed and becomes
+    /// readable, and `true` is returned.
+    pub fn add(&mut self, row: Vec<Value>) -> Result<bool> {
+        let starts_partition = self
+            .pending
+            .first()
+            .is_some_and(|first| first[..self.partition_keys] != row[..self.partition_keys]);
+        let completed = if starts_partition {
+            self.complete_partition()?;
+            true
+        } else {
+            false
+        };
+        self.pending.push(row);
+        Ok(completed)
+    }
+
+    /// Completes the partition being accumulated, if any.
+    /// Returns `false` if there were no rows left to complete.
+    pub fn flush(&mut self) -> Result<bool> {
+        if self.pending.is_empty() {
+            return Ok(false);
+        }
+        self.complete_partition()?;
+        Ok(true)
+    }
+
+    pub fn rewind(&mut self) -> bool {
+        self.current = if self.rows.is_empty() { None } else { Some(0) };
+        self.current.is_some()
+    }
+
+    pub fn next(&mut self) -> bool {
+        self.current = self.current.map(|i| i + 1).filter(|i| *i < self.rows.len());
+        self.current.is_some()
+    }
+
+    pub fn column(&self, idx: usize) -> Value {
+        self.current
+            .and_then(|i| self.rows[i].get(idx))
+            .cloned()
+            .unwrap_or(Value::Null)
+    }
+
+    /// Positions the frame iterator on the first row of the frame of window function `func`
+    /// for the current row. Returns `false` if the frame is empty.
+    pub fn frame_rewind(&mut self, func: usize) -> bool {
+        self.frame = match self.current {
+            Some(row) => self.frame_rows(func, row),
+            None => Vec::new(),
+        };
+        self.frame_pos = 0;
+        !self.frame.is_empty()
+    }
+
+    pub fn frame_next(&mut self) -> bool {
+        self.frame_pos += 1;
+        self.frame_pos < self.frame.len()
+    }
+
+    pub fn frame_column(&self, idx: usize) -> Value {
+        self.frame
+            .get(self.frame_pos)
+            .and_then(|row| self.rows[*row].get(idx))
+            .cloned()
+            .unwrap_or(Value::Null)
+    }
+
+    fn complete_partition(&mut self) -> Result<()> {
+        self.rows = std::mem::take(&mut self.pending);
+        self.current = None;
+        self.compute_peer_groups();
+
+        let mut values = Vec::with_capacity(self.functions.len());
+        for (idx, spec) in self.functions.iter().enumerate() {
+            let column = match spec.func {
+                Some(func) => (0..self.rows.len())
+                    .map(|row| self.evaluate(func, idx, row))
+                    .collect::<Result<Vec<_>>>()?,
+                None => vec![Value::Null; self.rows.len()],
+            };
+            values.push(column);
+        }
+        for column in values {
+            for (row, value) in self.rows.iter_mut().zip(column) {
+                row.push(value);
+            }
+        }
+        Ok(())
+    }
+
+    fn compute_peer_groups(&mut self) {
+        let order_keys = self.partition_keys..self.partition_keys + self.order.len();
+        self.peer_groups.clear();
+        self.row_peer_group.clear();
+        let mut start = 0;
+        for i in 1..=self.rows.len() {
+            if i == self.rows.len()
+                || self.rows[i][order_keys.clone()] != self.rows[start][order_keys.clone()]
+            {
+                self.peer_groups.push(start..i);
+                start = i;
+            }
+        }
+        for (group, range) in self.peer_groups.iter().enumerate() {
+            self.row_peer_group
+                .extend(std::iter::repeat_n(group, range.len()));
+        }
+    }
+
+    fn evaluate(&self, func: WindowFunc, idx: usize, row: usize) -> Result<Value> {
+        let spec = &self.functions[idx];
+        let arg = |n: usize| &self.rows[row][spec.arg_start + n];
+        let num_rows = self.rows.len();
+        let peer_group = &self.peer_groups[self.row_peer_group[row]];
+        Ok(match func {
+            WindowFunc::RowNumber => Value::Integer(row as i64 + 1),
+            WindowFunc::Rank => Value::Integer(peer_group.start as i64 + 1),
+            WindowFunc::DenseRank => Value::Integer(self.row_peer_group[row] as i64 + 1),
+            WindowFunc::PercentRank => {
+                if num_rows > 1 {
+                    Value::Float(peer_group.start as f64 / (num_rows - 1) as f64)
+                } else {
+                    Value::Float(0.0)
+                }
+            }
+            WindowFunc::CumeDist => Value::Float(peer_group.end as f64 / num_rows as f64),
+            WindowFunc::Ntile => {
+                let Some(buckets) = value_to_integer(arg(0), true).filter(|n| *n > 0) else {
+                    return Err(LimboError::Constraint(
+                        "argument of ntile must be a positive integer".to_string(),
+                    ));
+                };
+                // The first `num_rows % buckets` buckets get one extra row.
+                let buckets = buckets as usize;
+                let size = num_rows / buckets;
+                let larger = num_rows % buckets;
+                let bucket = if row < larger * (size + 1) {
+                    row / (size + 1)
+                } else {
+                    larger + (row - larger * (size + 1)) / size
+                };
+                Value::Integer(bucket as i64 + 1)
+            }
+            WindowFunc::Lag | WindowFunc::Lead => {
+                let default = if spec.arg_count > 2 {
+                    arg(2).clone()
+                } else {
+                    Value::Null
+                };
+                let offset = if spec.arg_count > 1 {
+                    match value_to_integer(arg(1), false) {
+                        Some(offset) => offset,
+                        None => return Ok(default),
+                    }
+                } else {
+                    1
+                };
+                let target = if func == WindowFunc::Lag {
+                    (row as i64).checked_sub(offset)
+                } else {
+                    (row as i64).checked_add(offset)
+                };
+                match target {
+                    Some(target) if (0..num_rows as i64).contains(&target) => {
+                        self.rows[target as usize][spec.arg_start].clone()
+                    }
+                    _ => default,
+                }
+            }
+            WindowFunc::FirstValue => self
+                .frame_rows(idx, row)
+                .first()
+                .map_or(Value::Null, |r| self.rows[*r][spec.arg_start].clone()),
+            WindowFunc::LastValue => self
+                .frame_rows(idx, row)
+                .last()
+                .map_or(Value::Null, |r| self.rows[*r][spec.arg_start].clone()),
+            WindowFunc::NthValue => {
+                let Some(n) = value_to_integer(arg(1), false).filter(|n| *n > 0) else {
+                    return Err(LimboError::Constraint(
+                        "second argument to nth_value must be a positive integer".to_string(),
+                    ));
+                };
+                self.frame_rows(idx, row)
+                    .get(n as usize - 1)
+                    .map_or(Value::Null, |r| self.rows[*r][spec.arg_start].clone())
+            }
+        })
+    }
+
+    /// Returns the rows in the frame of window function `func` for `row`,
+    /// with the rows removed by the EXCLUDE clause already filtered out.
+    fn frame_rows(&self, func: usize, row: usize) -> Vec<usize> {
+        let frame = &self.frames[func];
+        let group_idx = self.row_peer_group[row];
+        let peer_group = self.peer_groups[group_idx].clone();
+        let (start, end) = match frame.mode {
+            FrameMode::Rows => (
+                self.rows_bound(&frame.start, row, false),
+                self.rows_bound(&frame.end, row, true),
+            ),
+            FrameMode::Groups => (
+                self.groups_bound(&frame.start, group_idx, false),
+                self.groups_bound(&frame.end, group_idx, true),
+            ),
+            FrameMode::Range => (
+                self.range_bound(&frame.start, row, false),
+                self.range_bound(&frame.end, row, true),
+            ),
+        };
+        let excluded = match frame.exclude {
+            FrameExclude::NoOthers => 0..0,
+            FrameExclude::CurrentRow => row..row + 1,
+            FrameExclude::Group | FrameExclude::Ties => peer_group,
+        };
+        (start..end.max(start))
+            .filter(|r| !excluded.contains(r) || (frame.exclude == FrameExclude::Ties && *r == row))
+            .collect()
+    }
+
+    /// Frame boundary for ROWS frames. The end boundary is exclusive.
+    fn rows_bound(&self, bound: &WindowFrameBound<Value>, row: usize, is_end: bool) -> usize {
+        let num_rows = self.rows.len() as i64;
+        let row = row as i64;
+        let pos = match bound {
+            WindowFrameBound::UnboundedPreceding => 0,
+            WindowFrameBound::Preceding(offset) => row.saturating_sub(offset_value(offset)),
+            WindowFrameBound::CurrentRow => row,
+            WindowFrameBound::Following(offset) => row.saturating_add(offset_value(offset)),
+            WindowFrameBound::UnboundedFollowing => num_rows,
+        };
+        let pos = if is_end && !matches!(bound, WindowFrameBound::UnboundedFollowing) {
+            pos.saturating_add(1)
+        } else {
+            pos
+        };
+        pos.clamp(0, num_rows) as usize
+    }
+
+    /// Frame boundary for GROUPS frames. The end boundary is exclusive.
+    fn groups_bound(&self, bound: &WindowFrameBound<Value>, group: usize, is_end: bool) -> usize {
+        let num_groups = self.peer_groups.len() as i64;
+        let group = group as i64;
+        let target = match bound {
+            WindowFrameBound::UnboundedPreceding => return 0,
+            WindowFrameBound::Preceding(offset) => group.saturating_sub(offset_value(offset)),
+            WindowFrameBound::CurrentRow => group,
+            WindowFrameBound::Following(offset) => group.saturating_add(offset_value(offset)),
+            WindowFrameBound::UnboundedFollowing => return self.rows.len(),
+        };
+        if target < 0 {
+            0
+        } else if target >= num_groups {
+            self.rows.len()
+        } else if is_end {
+            self.peer_groups[target as usize].end
+        } else {
+            self.peer_groups[target as usize].start
+        }
+    }
+
+    /// Frame boundary for RANGE frames. The end boundary is exclusive.
+    fn range_bound(&self, bound: &WindowFrameBound<Value>, row: usize, is_end: bool) -> usize {
+        let peer_group = &self.peer_groups[self.row_peer_group[row]];
+        let key_column = self.partition_keys;
+        let key = &self.rows[row][key_column];
+        let descending = self.order.first() == Some(&SortOrder::Desc);
+        let (offset, towards_end) = match bound {
+            WindowFrameBound::UnboundedPreceding => return 0,
+            WindowFrameBound::UnboundedFollowing => return self.rows.len(),
+            WindowFrameBound::CurrentRow => {
+                return if is_end {
+                    peer_group.end
+                } else {
+                    peer_group.start
+                };
+            }
+            WindowFrameBound::Preceding(offset) => (offset, false),
+            WindowFrameBound::Following(offset) => (offset, true),
+        };
+        // Offsets only apply to numeric ORDER BY values. Any other value
+        // (including NULL) only has its peers within range.
+        if !matches!(key, Value::Integer(_) | Value::Float(_)) {
+            return if is_end {
+                peer_group.end
+            } else {
+                peer_group.start
+            };
+        }
+        let target = if towards_end != descending {
+            key.exec_add(offset)
+        } else {
+            key.exec_subtract(offset)
+        };
+        // Position of a row relative to the target value, in the direction of the sort order.
+        let cmp = |r: &Vec<Value>| {
+            let ord = r[key_column]
+                .partial_cmp(&target)
+                .unwrap_or(Ordering::Equal);
+            if descending {
+                ord.reverse()
+            } else {
+                ord
+            }
+        };
+        if is_end {
+            self.rows.partition_point(|r| cmp(r) != Ordering::Greater)
+        } else {
+            self.rows.partition_point(|r| cmp(r) == Ordering::Less)
+        }
+    }
+}
+
+fn offset_value(offset: &Value) -> i64 {
+    offset.as_int().unwrap_or(0)
+}
+
+/// Converts a function argument to an integer. Text is converted to a number first.
+/// Reals are truncated if `truncate` is set, otherwise only reals without
+/// a fractional part are accepted.
+fn value_to_integer(value: &Value, truncate: bool) -> Option<i64> {
+    let value = match value {
+        Value::Text(text) => checked_cast_text_to_numeric(text.as_str()).ok()?,
+        value => value.clone(),
+    };
+    match value {
+        Value::Integer(i) => Some(i),
+        Value::Float(f) if truncate || f.fract() == 0.0 => Some(f as i64),
+        _ => None,
+    }
+}
@@ -468,12 +468,52 @@ pub enum Expr {
     Raise(ResolveType, Option<Box<Expr>>),
     /// Subquery expression
     Subquery(Select),
+    /// The result of a subquery that was planned separately from the expression it appears in,
+    /// i.e. a `(SELECT ...)`, `EXISTS (SELECT ...)` or `x IN (SELECT ...)` expression.
+    SubqueryResult {
+        /// the subquery's identifier, used to refer to its plan
+        subquery_id: TableInternalId,
+        /// left hand side of an `IN` subquery
+        lhs: Option<Box<Expr>>,
+        /// `NOT IN`
+        not_in: bool,
+        /// how the result of the subquery is read
+        query_type: SubqueryType,
+        /// the tables of the enclosing queries that the subquery refers to;
+        /// empty if the subquery is not correlated
+        correlated_tables: Vec<TableInternalId>,
+    },
     /// Unary expression
     Unary(UnaryOperator, Box<Expr>),
     /// Parameters
     Variable(String),
 }
 
+/// How the result of a [Expr::SubqueryResult] is made available to the enclosing expression.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum SubqueryType {
+    /// `EXISTS (SELECT ...)`: the register holds 1 if the subquery returned a row, 0 otherwise.
+    Exists {
+        /// register holding the result
+        result_reg: usize,
+    },
+    /// `(SELECT ...)`: the registers hold the first row of the subquery, or NULLs.
+    RowValue {
+        /// first register of the row
+        result_reg_start: usize,
+        /// number of registers in the row
+        num_regs: usize,
+    },
+    /// `x IN (SELECT ...)`: the rows of the subquery are stored in an ephemeral index.
+    In {
+        /// cursor of the ephemeral index
+        cursor_id: usize,
+        /// affinity character applied to both the rows of the subquery and the left hand side
+        affinity: char,
+    },
+}
+
 impl Expr {
     pub fn into_boxed(self) -> Box<Expr> {
         Box::new(self)
@@ -1308,6 +1348,8 @@ pub type PragmaValue = Box<Expr>; // TODO
 #[strum(serialize_all = "snake_case")]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum PragmaName {
+    /// Limit the number of index entries scanned by ANALYZE
+    AnalysisLimit,
     /// Returns the application ID of the database file.
     ApplicationId,
     /// set the autovacuum mode
@@ -1322,8 +1364,16 @@ pub enum PragmaName {
     DataSyncRetry,
     /// List databases
     DatabaseList,
+    /// Defer the enforcement of all foreign key constraints to the commit of the transaction
+    DeferForeignKeys,
     /// Encoding - only support utf8
     Encoding,
+    /// Check the foreign key constraints of the tables
+    ForeignKeyCheck,
+    /// returns information about the foreign key constraints of a table
+    ForeignKeyList,
+    /// Enable or disable the enforcement of foreign key constraints
+    ForeignKeys,
     /// Current free page count.
     FreelistCount,
     /// Run integrity check on the database file
@@ -1341,18 +1391,26 @@ pub enum PragmaName {
     /// `module_list` pragma
     /// `module_list` lists modules used by virtual tables.
     ModuleList,
+    /// Limit the memory that MVCC row versions take, in bytes (0 = no limit)
+    MvccMemoryLimit,
+    /// Refresh the query planner statistics of tables that need it
+    Optimize,
     /// Return the total number of pages in the database file.
     PageCount,
     /// Return the page size of the database in bytes.
     PageSize,
     /// make connection query only
     QueryOnly,
+    /// Run integrity check on the database file, without checking the content of the indexes
+    QuickCheck,
     /// Returns schema version of the database file.
     SchemaVersion,
     /// Control database synchronization mode (OFF | FULL | NORMAL | EXTRA)
     Synchronous,
     /// returns information about the columns of a table
     TableInfo,
+    /// Where the temp database is stored (DEFAULT | FILE | MEMORY)
+    TempStore,
     /// enable capture-changes logic for the connection
     UnstableCaptureDataChangesConn,
     /// Returns the user version of the database file.
@@ -895,6 +895,7 @@ impl ToTokens for Expr {
                 query.to_tokens(s, context)?;
                 s.append(TK_RP, None)
             }
+            Self::SubqueryResult { .. } => Ok(()),
             Self::Unary(op, sub_expr) => {
                 op.to_tokens(s, context)?;
                 sub_expr.to_tokens(s, context)
@@ -137,6 +137,23 @@ impl File for SimulatorFile {
         self.inner.unlock_file()
     }
 
+    fn lock_range(&self, offset: u64, len: u64, exclusive: bool) -> Result<bool> {
+        if self.fault.get() {
+            return Err(turso_core::LimboError::InternalError(
+                FAULT_ERROR_MSG.into(),
+            ));
+        }
+        self.inner.lock_range(offset, len, exclusive)
+    }
+
+    fn unlock_range(&self, offset: u64, len: u64) -> Result<()> {
+        self.inner.unlock_range(offset, len)
+    }
+
+    fn map_shared(&self, offset: u64, len: usize) -> Result<Option<turso_core::SharedMemory>> {
+        self.inner.map_shared(offset, len)
+    }
+
     fn pread(&self, pos: u64, c: turso_core::Completion) -> Result<turso_core::Completion> {
         self.nr_pread_calls.set(self.nr_pread_calls.get() + 1);
         if self.fault.get() {
@@ -1,4 +1,4 @@
-use crate::common::TempDatabase;
+use crate::common::{limbo_exec_rows, TempDatabase};
 use turso_core::{StepResult, Value};
 
 #[test]
@@ -703,3 +703,45 @@ fn test_bind_parameters_delete_rowid_alias_seek_out_of_order() -> anyhow::Result
     assert_eq!(ins.parameters().count(), 4);
     Ok(())
 }
+
+#[test]
+fn test_compound_select_without_indexes() -> anyhow::Result<()> {
+    let tmp_db = TempDatabase::new_empty(false);
+    let conn = tmp_db.connect_limbo();
+    conn.execute("CREATE TABLE t (x, y)")?;
+    conn.execute("CREATE TABLE u (x, y)")?;
+    conn.execute("INSERT INTO t VALUES (1, 'a'), (2, 'b'), (2, 'b'), (3, NULL)")?;
+    conn.execute("INSERT INTO u VALUES (2, 'b'), (3, NULL), (4, 'd'), (4, 'd')")?;
+
+    let int = rusqlite::types::Value::Integer;
+    let text = |s: &str| rusqlite::types::Value::Text(s.to_string());
+    let null = rusqlite::types::Value::Null;
+    let query = |sql: &str| limbo_exec_rows(&tmp_db, &conn, sql);
+
+    assert_eq!(
+        query("SELECT x, y FROM t UNION SELECT x, y FROM u"),
+        vec![
+            vec![int(1), text("a")],
+            vec![int(2), text("b")],
+            vec![int(3), null.clone()],
+            vec![int(4), text("d")],
+        ]
+    );
+    assert_eq!(
+        query("SELECT x, y FROM t INTERSECT SELECT x, y FROM u"),
+        vec![vec![int(2), text("b")], vec![int(3), null]]
+    );
+    assert_eq!(
+        query("SELECT x, y FROM t EXCEPT SELECT x, y FROM u"),
+        vec![vec![int(1), text("a")]]
+    );
+    assert_eq!(
+        query("SELECT x FROM t UNION ALL SELECT x FROM u EXCEPT SELECT 4 ORDER BY 1 DESC LIMIT 2"),
+        vec![vec![int(3)], vec![int(2)]]
+    );
+    assert_eq!(
+        query("SELECT x FROM t UNION SELECT x FROM u LIMIT 2 OFFSET 1"),
+        vec![vec![int(2)], vec![int(3)]]
+    );
+    Ok(())
+}
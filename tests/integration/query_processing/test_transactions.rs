@@ -458,6 +458,53 @@ fn test_mvcc_concurrent_conflicting_update_2() {
     assert!(matches!(err, LimboError::WriteWriteConflict));
 }
 
+#[test]
+fn test_mvcc_serializable_write_skew() {
+    let tmp_db = TempDatabase::new_with_opts(
+        "test_mvcc_serializable_write_skew.db",
+        turso_core::DatabaseOpts::new()
+            .with_mvcc(true)
+            .with_mvcc_serializable(true),
+    );
+    let conn1 = tmp_db.connect_limbo();
+    let conn2 = tmp_db.connect_limbo();
+
+    conn1
+        .execute("CREATE TABLE doctors (id INTEGER, on_call INTEGER)")
+        .unwrap();
+    conn1
+        .execute("INSERT INTO doctors (id, on_call) VALUES (1, 1), (2, 1)")
+        .unwrap();
+
+    conn1.execute("BEGIN CONCURRENT").unwrap();
+    conn2.execute("BEGIN CONCURRENT").unwrap();
+
+    // Each transaction sees two doctors on call, so each lets one of them go off call
+    for conn in [&conn1, &conn2] {
+        let stmt = conn
+            .query("SELECT count(*) FROM doctors WHERE on_call = 1")
+            .unwrap()
+            .unwrap();
+        assert_eq!(helper_read_single_row(stmt), vec![Value::Integer(2)]);
+    }
+    conn1
+        .execute("UPDATE doctors SET on_call = 0 WHERE id = 1")
+        .unwrap();
+    conn2
+        .execute("UPDATE doctors SET on_call = 0 WHERE id = 2")
+        .unwrap();
+
+    conn1.execute("COMMIT").unwrap();
+    let err = conn2.execute("COMMIT").expect_err("expected error");
+    assert!(matches!(err, LimboError::SerializationFailure));
+
+    let stmt = conn2
+        .query("SELECT count(*) FROM doctors WHERE on_call = 1")
+        .unwrap()
+        .unwrap();
+    assert_eq!(helper_read_single_row(stmt), vec![Value::Integer(1)]);
+}
+
 fn helper_read_all_rows(mut stmt: turso_core::Statement) -> Vec<Vec<Value>> {
     let mut ret = Vec::new();
     loop {
@@ -789,3 +789,145 @@ fn test_insert_with_column_names() -> anyhow::Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_vacuum_shrinks_database_file() -> anyhow::Result<()> {
+    let tmp_db = TempDatabase::new_empty(true);
+    let conn = tmp_db.connect_limbo();
+    conn.execute("CREATE TABLE t (x INTEGER PRIMARY KEY, y)")?;
+    conn.execute("CREATE INDEX t_y ON t (y)")?;
+    conn.execute("INSERT INTO t SELECT value, randomblob(500) FROM generate_series(1, 2000)")?;
+    conn.execute("DELETE FROM t WHERE x > 10")?;
+    conn.checkpoint(CheckpointMode::Truncate {
+        upper_bound_inclusive: None,
+    })?;
+    let size_before = std::fs::metadata(&tmp_db.path)?.len();
+
+    conn.execute("VACUUM")?;
+    conn.checkpoint(CheckpointMode::Truncate {
+        upper_bound_inclusive: None,
+    })?;
+    let size_after = std::fs::metadata(&tmp_db.path)?.len();
+    assert!(
+        size_after < size_before / 10,
+        "expected {size_after} to be much smaller than {size_before}"
+    );
+
+    let rows = common::limbo_exec_rows(&tmp_db, &conn, "SELECT count(*), sum(x) FROM t");
+    assert_eq!(
+        rows,
+        vec![vec![
+            rusqlite::types::Value::Integer(10),
+            rusqlite::types::Value::Integer(55)
+        ]]
+    );
+    conn.close()?;
+
+    let sqlite_conn = rusqlite::Connection::open(&tmp_db.path)?;
+    let rows = common::sqlite_exec_rows(&sqlite_conn, "PRAGMA integrity_check");
+    assert_eq!(rows, vec![vec![rusqlite::types::Value::Text("ok".into())]]);
+    Ok(())
+}
+
+#[test]
+fn test_vacuum_into_with_concurrent_writer() -> anyhow::Result<()> {
+    let tmp_db = TempDatabase::new_empty(true);
+    let conn = tmp_db.connect_limbo();
+    conn.execute("CREATE TABLE t (x)")?;
+    conn.execute("INSERT INTO t VALUES (1), (2)")?;
+
+    // VACUUM INTO only reads, so it must not be blocked by a pending write transaction and
+    // must not see its changes.
+    let writer = tmp_db.connect_limbo();
+    writer.execute("BEGIN")?;
+    writer.execute("INSERT INTO t VALUES (3)")?;
+
+    let out_path = tmp_db.path.with_file_name("vacuum-into.db");
+    conn.execute(format!("VACUUM INTO '{}'", out_path.display()))?;
+    writer.execute("COMMIT")?;
+
+    let sqlite_conn = rusqlite::Connection::open(&out_path)?;
+    let rows = common::sqlite_exec_rows(&sqlite_conn, "SELECT x FROM t");
+    assert_eq!(
+        rows,
+        vec![
+            vec![rusqlite::types::Value::Integer(1)],
+            vec![rusqlite::types::Value::Integer(2)]
+        ]
+    );
+
+    let result = conn.execute(format!("VACUUM INTO '{}'", out_path.display()));
+    assert!(matches!(result, Err(LimboError::InvalidArgument(_))));
+    Ok(())
+}
+
+#[test]
+fn test_vacuum_into_removes_copy_on_failure() -> anyhow::Result<()> {
+    let tmp_db = TempDatabase::new_empty(true);
+    let conn = tmp_db.connect_limbo();
+    conn.execute("CREATE TABLE t (x)")?;
+    conn.execute("INSERT INTO t VALUES (1), (2)")?;
+
+    // The WAL of the copy cannot be created, so VACUUM INTO fails after creating the copy.
+    let out_path = tmp_db.path.with_file_name("vacuum-into.db");
+    let wal_path = tmp_db.path.with_file_name("vacuum-into.db-wal");
+    std::fs::create_dir(&wal_path)?;
+    let result = conn.execute(format!("VACUUM INTO '{}'", out_path.display()));
+    assert!(result.is_err());
+    assert!(!out_path.exists());
+    std::fs::remove_dir(&wal_path)?;
+
+    // The database is still usable and VACUUM INTO succeeds once the path is free.
+    conn.execute(format!("VACUUM INTO '{}'", out_path.display()))?;
+    let sqlite_conn = rusqlite::Connection::open(&out_path)?;
+    let rows = common::sqlite_exec_rows(&sqlite_conn, "SELECT x FROM t");
+    assert_eq!(
+        rows,
+        vec![
+            vec![rusqlite::types::Value::Integer(1)],
+            vec![rusqlite::types::Value::Integer(2)]
+        ]
+    );
+    Ok(())
+}
+
+#[test]
+fn test_rollback_to_savepoint_after_cacheflush() -> anyhow::Result<()> {
+    let tmp_db = TempDatabase::new_empty(false);
+    let conn = tmp_db.connect_limbo();
+    // The transaction does not fit in the cache, so its pages are spilled to the WAL.
+    conn.execute("PRAGMA cache_size = 10")?;
+    conn.execute("CREATE TABLE t (x INTEGER PRIMARY KEY, y BLOB)")?;
+    conn.execute("BEGIN")?;
+    for i in 0..21 {
+        conn.execute(format!("INSERT INTO t VALUES ({i}, zeroblob(2000))"))?;
+        if i % 4 == 3 {
+            do_flush(&conn, &tmp_db)?;
+        }
+    }
+
+    conn.execute("SAVEPOINT sp")?;
+    for i in 0..21 {
+        conn.execute(format!("UPDATE t SET y = zeroblob(1000) WHERE x = {i}"))?;
+        conn.execute(format!(
+            "INSERT INTO t VALUES ({}, zeroblob(2000))",
+            100 + i
+        ))?;
+        if i % 2 == 1 {
+            do_flush(&conn, &tmp_db)?;
+        }
+    }
+    do_flush(&conn, &tmp_db)?;
+    conn.execute("ROLLBACK TO sp")?;
+    conn.execute("COMMIT")?;
+
+    let expected = vec![vec![
+        rusqlite::types::Value::Integer(21),
+        rusqlite::types::Value::Integer(42000),
+    ]];
+    let query = "SELECT count(*), sum(length(y)) FROM t";
+    assert_eq!(common::limbo_exec_rows(&tmp_db, &conn, query), expected);
+    let conn = tmp_db.connect_limbo();
+    assert_eq!(common::limbo_exec_rows(&tmp_db, &conn, query), expected);
+    Ok(())
+}
@@ -1,2 +1,3 @@
 #[cfg(feature = "checksum")]
 mod checksum;
+mod super_journal;
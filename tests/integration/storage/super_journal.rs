@@ -0,0 +1,185 @@
+use crate::common::{limbo_exec_rows, limbo_exec_rows_error, TempDatabase};
+use rusqlite::types::Value;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use turso_core::{
+    Buffer, Clock, Completion, Database, DatabaseOpts, File, Instant, LimboError, OpenFlags,
+    PlatformIO, Result, SharedMemory, IO,
+};
+
+/// Fails the syncs of the files whose path ends with `fail_suffix` while `armed` is set.
+struct FailingSyncIO {
+    inner: Arc<dyn IO>,
+    fail_suffix: String,
+    armed: Arc<AtomicBool>,
+}
+
+struct FailingSyncFile {
+    inner: Arc<dyn File>,
+    fail: bool,
+    armed: Arc<AtomicBool>,
+}
+
+impl Clock for FailingSyncIO {
+    fn now(&self) -> Instant {
+        self.inner.now()
+    }
+}
+
+impl IO for FailingSyncIO {
+    fn open_file(&self, path: &str, flags: OpenFlags, direct: bool) -> Result<Arc<dyn File>> {
+        Ok(Arc::new(FailingSyncFile {
+            inner: self.inner.open_file(path, flags, direct)?,
+            fail: path.ends_with(&self.fail_suffix),
+            armed: self.armed.clone(),
+        }))
+    }
+
+    fn remove_file(&self, path: &str) -> Result<()> {
+        self.inner.remove_file(path)
+    }
+
+    fn step(&self) -> Result<()> {
+        self.inner.step()
+    }
+
+    fn drain(&self) -> Result<()> {
+        self.inner.drain()
+    }
+}
+
+impl File for FailingSyncFile {
+    fn lock_file(&self, exclusive: bool) -> Result<()> {
+        self.inner.lock_file(exclusive)
+    }
+
+    fn unlock_file(&self) -> Result<()> {
+        self.inner.unlock_file()
+    }
+
+    fn lock_range(&self, offset: u64, len: u64, exclusive: bool) -> Result<bool> {
+        self.inner.lock_range(offset, len, exclusive)
+    }
+
+    fn unlock_range(&self, offset: u64, len: u64) -> Result<()> {
+        self.inner.unlock_range(offset, len)
+    }
+
+    fn map_shared(&self, offset: u64, len: usize) -> Result<Option<SharedMemory>> {
+        self.inner.map_shared(offset, len)
+    }
+
+    fn pread(&self, pos: u64, c: Completion) -> Result<Completion> {
+        self.inner.pread(pos, c)
+    }
+
+    fn pwrite(&self, pos: u64, buffer: Arc<Buffer>, c: Completion) -> Result<Completion> {
+        self.inner.pwrite(pos, buffer, c)
+    }
+
+    fn pwritev(&self, pos: u64, buffers: Vec<Arc<Buffer>>, c: Completion) -> Result<Completion> {
+        self.inner.pwritev(pos, buffers, c)
+    }
+
+    fn sync(&self, c: Completion) -> Result<Completion> {
+        if self.fail && self.armed.load(Ordering::SeqCst) {
+            return Err(LimboError::InternalError(
+                "injected sync failure".to_string(),
+            ));
+        }
+        self.inner.sync(c)
+    }
+
+    fn size(&self) -> Result<u64> {
+        self.inner.size()
+    }
+
+    fn truncate(&self, len: u64, c: Completion) -> Result<Completion> {
+        self.inner.truncate(len, c)
+    }
+}
+
+#[test]
+fn test_super_journal_rollback_when_second_commit_fails() {
+    let _ = env_logger::try_init();
+    let dir = tempfile::TempDir::new().unwrap();
+    let main_path = dir.path().join("main.db");
+    let aux_path = dir.path().join("aux.db");
+    let armed = Arc::new(AtomicBool::new(false));
+    // The attached database is committed first, the main one second
+    let io: Arc<dyn IO + Send> = Arc::new(FailingSyncIO {
+        inner: Arc::new(PlatformIO::new().unwrap()),
+        fail_suffix: "main.db-wal".to_string(),
+        armed: armed.clone(),
+    });
+    let db = Database::open_file_with_flags(
+        io.clone(),
+        main_path.to_str().unwrap(),
+        OpenFlags::default(),
+        DatabaseOpts::new().with_indexes(true),
+    )
+    .unwrap();
+    let tmp_db = TempDatabase {
+        path: main_path.clone(),
+        io,
+        db,
+    };
+    let conn = tmp_db.connect_limbo();
+    let attach = format!("ATTACH '{}' AS aux", aux_path.to_str().unwrap());
+    limbo_exec_rows_error(&tmp_db, &conn, &attach).unwrap();
+    for sql in [
+        "CREATE TABLE t(x)",
+        "CREATE TABLE aux.t(x)",
+        "INSERT INTO t VALUES (1)",
+        "INSERT INTO aux.t VALUES (1)",
+    ] {
+        limbo_exec_rows_error(&tmp_db, &conn, sql).unwrap();
+    }
+    let aux_wal_path = dir.path().join("aux.db-wal");
+    let aux_wal_size = std::fs::metadata(&aux_wal_path).unwrap().len();
+
+    armed.store(true, Ordering::SeqCst);
+    for sql in [
+        "BEGIN",
+        "INSERT INTO t VALUES (2)",
+        "INSERT INTO aux.t VALUES (2)",
+    ] {
+        limbo_exec_rows_error(&tmp_db, &conn, sql).unwrap();
+    }
+    assert!(limbo_exec_rows_error(&tmp_db, &conn, "COMMIT").is_err());
+    armed.store(false, Ordering::SeqCst);
+
+    // The attached database was committed before the main one failed, and is rolled back
+    assert_eq!(
+        std::fs::metadata(&aux_wal_path).unwrap().len(),
+        aux_wal_size
+    );
+    for table in ["t", "aux.t"] {
+        let rows = limbo_exec_rows(&tmp_db, &conn, &format!("SELECT x FROM {table}"));
+        assert_eq!(rows, vec![vec![Value::Integer(1)]], "{table}");
+    }
+    let leftovers = std::fs::read_dir(dir.path())
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+        .filter(|name| name.contains("-mj") || name.ends_with("-sjptr"))
+        .collect::<Vec<_>>();
+    assert!(leftovers.is_empty(), "{leftovers:?}");
+
+    // No lock was left behind
+    for sql in [
+        "BEGIN",
+        "INSERT INTO t VALUES (3)",
+        "INSERT INTO aux.t VALUES (3)",
+        "COMMIT",
+    ] {
+        limbo_exec_rows_error(&tmp_db, &conn, sql).unwrap();
+    }
+    for table in ["t", "aux.t"] {
+        let rows = limbo_exec_rows(&tmp_db, &conn, &format!("SELECT x FROM {table}"));
+        assert_eq!(
+            rows,
+            vec![vec![Value::Integer(1)], vec![Value::Integer(3)]],
+            "{table}"
+        );
+    }
+}
@@ -109,6 +109,137 @@ fn test_wal_1_writer_1_reader() -> Result<()> {
     Ok(())
 }
 
+/// Checks that Turso and SQLite read and write the WAL and the wal-index in the same format.
+/// Both run in this process, which holds the POSIX locks of the files for both of them, so the
+/// locking between processes is not covered here but by
+/// [test_wal_locks_shared_between_processes].
+#[test]
+fn test_wal_shared_with_sqlite() -> Result<()> {
+    maybe_setup_tracing();
+    let tmp_db = TempDatabase::new_with_rusqlite("CREATE TABLE t (x INTEGER);", false);
+    let conn = tmp_db.connect_limbo();
+    let sqlite_conn = rusqlite::Connection::open(&tmp_db.path).unwrap();
+
+    // Both see the commits of the other through the wal-index
+    conn.execute("INSERT INTO t VALUES (1), (2)")?;
+    let count: i64 = sqlite_conn
+        .query_row("SELECT count(*) FROM t", (), |row| row.get(0))
+        .unwrap();
+    assert_eq!(count, 2);
+
+    sqlite_conn.execute("INSERT INTO t VALUES (3)", ()).unwrap();
+    let res = execute_and_get_ints(&conn, "SELECT sum(x) FROM t")?;
+    assert_eq!(res, vec![6]);
+
+    // A checkpoint restarting the WAL is seen by the other side as well
+    let res = execute_and_get_ints(&conn, "PRAGMA wal_checkpoint(RESTART)")?;
+    assert_eq!(res[0], 0);
+    sqlite_conn.execute("INSERT INTO t VALUES (4)", ()).unwrap();
+    let res = execute_and_get_ints(&conn, "SELECT sum(x) FROM t")?;
+    assert_eq!(res, vec![10]);
+    conn.execute("INSERT INTO t VALUES (5)")?;
+    let sum: i64 = sqlite_conn
+        .query_row("SELECT sum(x) FROM t", (), |row| row.get(0))
+        .unwrap();
+    assert_eq!(sum, 15);
+
+    Ok(())
+}
+
+/// Names the database the child process of [test_wal_locks_shared_between_processes] opens.
+#[cfg(unix)]
+const LOCKS_CHILD_DB_ENV: &str = "TURSO_TEST_WAL_LOCKS_CHILD_DB";
+
+#[cfg(unix)]
+#[test]
+fn test_wal_locks_shared_between_processes() -> Result<()> {
+    use std::io::{BufRead, BufReader, Write};
+    use std::process::{Command, Stdio};
+
+    maybe_setup_tracing();
+    let tmp_db = TempDatabase::new_empty(false);
+    let conn = tmp_db.connect_limbo();
+    conn.execute("CREATE TABLE t (x INTEGER)")?;
+    conn.execute("INSERT INTO t VALUES (1)")?;
+
+    // The locks are per process, so the other connection has to live in another process
+    let mut child = Command::new(std::env::current_exe().unwrap())
+        .args([
+            "wal::test_wal::wal_locks_child_process",
+            "--exact",
+            "--nocapture",
+        ])
+        .env(LOCKS_CHILD_DB_ENV, &tmp_db.path)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut child_stdin = child.stdin.take().unwrap();
+    let mut child_stdout = BufReader::new(child.stdout.take().unwrap()).lines();
+    let mut wait_for_child = |state: &str| {
+        let reached = child_stdout.any(|line| line.unwrap() == state);
+        assert!(reached, "child process exited before reaching '{state}'");
+    };
+
+    // The child holds the write lock: writers are excluded, readers see the last commit
+    wait_for_child("writing");
+    assert!(matches!(
+        conn.execute("INSERT INTO t VALUES (3)"),
+        Err(LimboError::Busy)
+    ));
+    let res = execute_and_get_ints(&conn, "SELECT count(*) FROM t")?;
+    assert_eq!(res, vec![1]);
+    writeln!(child_stdin).unwrap();
+
+    // The child committed and holds a read lock: the WAL cannot be restarted under it
+    wait_for_child("reading");
+    let res = execute_and_get_ints(&conn, "SELECT count(*) FROM t")?;
+    assert_eq!(res, vec![2]);
+    // The other columns are NULL when the checkpoint is busy
+    let res = execute_and_get_strings(&conn, "PRAGMA wal_checkpoint(TRUNCATE)")?;
+    assert_eq!(res[0], "1");
+    writeln!(child_stdin).unwrap();
+    assert!(child.wait().unwrap().success());
+
+    // The locks went away with the child
+    let res = execute_and_get_ints(&conn, "PRAGMA wal_checkpoint(TRUNCATE)")?;
+    assert_eq!(res[0], 0);
+    conn.execute("INSERT INTO t VALUES (3)")?;
+    let res = execute_and_get_ints(&conn, "SELECT sum(x) FROM t")?;
+    assert_eq!(res, vec![6]);
+
+    Ok(())
+}
+
+/// The other process of [test_wal_locks_shared_between_processes], which runs this test binary
+/// again with only this test selected. Does nothing when run as part of the test suite.
+#[cfg(unix)]
+#[test]
+fn wal_locks_child_process() -> Result<()> {
+    let Ok(path) = std::env::var(LOCKS_CHILD_DB_ENV) else {
+        return Ok(());
+    };
+    let io = Arc::new(turso_core::PlatformIO::new()?);
+    let db = turso_core::Database::open_file(io, &path, false, false)?;
+    let conn = db.connect()?;
+    let mut parent = std::io::stdin().lines();
+
+    conn.execute("BEGIN IMMEDIATE")?;
+    conn.execute("INSERT INTO t VALUES (2)")?;
+    println!("writing");
+    parent.next();
+    conn.execute("COMMIT")?;
+
+    conn.execute("BEGIN")?;
+    let res = execute_and_get_ints(&conn, "SELECT count(*) FROM t")?;
+    assert_eq!(res, vec![2]);
+    println!("reading");
+    parent.next();
+    conn.execute("COMMIT")?;
+
+    Ok(())
+}
+
 /// Execute a statement and get strings result
 pub(crate) fn execute_and_get_strings(conn: &Arc<Connection>, sql: &str) -> Result<Vec<String>> {
     let statement = conn.prepare(sql)?;